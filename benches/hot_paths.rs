@@ -0,0 +1,120 @@
+//! Benchmarks of the scan and media listing hot paths.
+//!
+//! Run with `cargo bench`. Only parts that don't need a database are covered:
+//! folder tree construction, hashing of media files and serialization of media responses.
+
+use chrono::NaiveDateTime;
+use chrono_tz::Tz;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use galera::models::Media;
+use galera::routes::{MediaLikes, MediaResponse};
+use galera::scan::ScanOptions;
+use galera::scan::filesystem::{Filesystem, LocalFilesystem};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Creates an empty directory for the benchmark in the system temporary directory.
+fn bench_directory(name: &str) -> PathBuf {
+  let path = std::env::temp_dir().join(format!("galera-bench-{}-{}", name, std::process::id()));
+  fs::remove_dir_all(&path).ok();
+  fs::create_dir_all(&path).unwrap();
+
+  path
+}
+
+/// Creates `width` folders in every level up to `depth`, each containing one file.
+fn create_tree(path: &Path, depth: u32, width: u32) {
+  fs::write(path.join("media.jpg"), b"").unwrap();
+
+  if depth == 0 { return }
+
+  for i in 0..width {
+    let child = path.join(format!("folder_{}", i));
+    fs::create_dir(&child).unwrap();
+    create_tree(&child, depth - 1, width);
+  }
+}
+
+fn folder_tree(c: &mut Criterion) {
+  let directory = bench_directory("tree");
+  let username = String::from("bench");
+
+  // 1 + 6 + 36 + 216 + 1296 folders
+  create_tree(&directory.join(&username), 4, 6);
+
+  let root = directory.join(&username);
+  let options = ScanOptions::default();
+
+  c.bench_function("scan_folders_1555", |b| b.iter(|| LocalFilesystem.folders(&root, &options)));
+
+  fs::remove_dir_all(&directory).ok();
+}
+
+fn hash_throughput(c: &mut Criterion) {
+  let directory = bench_directory("hash");
+  let mut group = c.benchmark_group("hash_file_sha2_512");
+
+  for size in [64 * 1024, 4 * 1024 * 1024, 32 * 1024 * 1024] {
+    let path = directory.join(format!("{}.bin", size));
+    fs::write(&path, vec![0x5a; size]).unwrap();
+
+    group.throughput(Throughput::Bytes(size as u64));
+    group.bench_with_input(BenchmarkId::from_parameter(size), &path, |b, path| {
+      b.iter(|| checksums::hash_file(path, checksums::Algorithm::SHA2512))
+    });
+  }
+
+  group.finish();
+  fs::remove_dir_all(&directory).ok();
+}
+
+fn media_response_serialization(c: &mut Criterion) {
+  let media: Vec<Media> = (0..100_000).map(|i| Media {
+    id: i,
+    filename: format!("IMG_{:06}.jpg", i),
+    folder_id: 1,
+    owner_id: 1,
+    width: 4032,
+    height: 3024,
+    description: None,
+    date_taken: NaiveDateTime::from_timestamp(1_600_000_000 + i as i64 * 60, 0),
+    // every other media needs the timezone of the user
+    date_taken_offset: if i % 2 == 0 { Some(3600) } else { None },
+    uuid: format!("00000000-0000-4000-8000-{:012}", i),
+    sha2_512: String::new(),
+    version: 0,
+    camera_make: None,
+    camera_model: None,
+    latitude: None,
+    longitude: None,
+    iso: None,
+    focal_length: None,
+    orientation: None,
+    filename_sort_key: None,
+    file_size: None,
+    file_modified_at: None,
+    missing_since: None,
+  }).collect();
+
+  let timezone = Tz::Europe__Prague;
+  let likes = MediaLikes::default();
+
+  let mut group = c.benchmark_group("media_response_100k");
+  group.sample_size(10);
+
+  group.bench_function("new", |b| {
+    b.iter(|| media.iter().map(|media| MediaResponse::new(media, timezone, &likes)).collect::<Vec<_>>())
+  });
+
+  group.bench_function("new_and_serialize", |b| {
+    b.iter(|| {
+      let responses: Vec<MediaResponse> = media.iter().map(|media| MediaResponse::new(media, timezone, &likes)).collect();
+      serde_json::to_vec(&responses).unwrap()
+    })
+  });
+
+  group.finish();
+}
+
+criterion_group!(benches, folder_tree, hash_throughput, media_response_serialization);
+criterion_main!(benches);
@@ -0,0 +1,37 @@
+//! Embeds the SQL of migrations, so pending migrations can be inspected before they are applied
+//! and the applied ones can be reverted without the migrations directory (see `src/migrations.rs`).
+//! Diesel's embedded migrations only expose their versions.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+fn main() {
+  println!("cargo:rerun-if-changed=migrations");
+
+  let mut directories: Vec<_> = fs::read_dir("migrations")
+    .expect("migrations directory")
+    .filter_map(|entry| entry.ok())
+    .map(|entry| entry.path())
+    .filter(|path| path.join("up.sql").is_file() && path.join("down.sql").is_file())
+    .collect();
+
+  directories.sort();
+
+  let mut output = String::from("/// Versions, SQL applying and SQL reverting of all migrations in the order they are applied.\n");
+  output.push_str("pub const MIGRATIONS: &[(&str, &str, &str)] = &[\n");
+
+  for directory in directories {
+    let name = directory.file_name().unwrap().to_string_lossy().to_string();
+    // the same version as diesel uses: the part of the name before the first underscore, without dashes
+    let version = name.split('_').next().unwrap().replace('-', "");
+    let up = fs::canonicalize(directory.join("up.sql")).unwrap();
+    let down = fs::canonicalize(directory.join("down.sql")).unwrap();
+
+    output.push_str(&format!("  ({:?}, include_str!({:?}), include_str!({:?})),\n", version, up, down));
+  }
+
+  output.push_str("];\n");
+
+  fs::write(Path::new(&env::var("OUT_DIR").unwrap()).join("migrations.rs"), output).unwrap();
+}
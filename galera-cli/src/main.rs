@@ -1,6 +1,8 @@
 #![forbid(unsafe_code)]
 // https://github.com/clap-rs/clap
 use clap::{App, AppSettings, Arg, ArgGroup, ArgSettings};
+use galera::banned_passwords::BannedPasswords;
+use std::path::Path;
 
 pub fn main() {
   // This example shows how to create an application with several arguments using usage strings, which can be
@@ -68,6 +70,23 @@ pub fn main() {
             .multiple(true),
         ),
     )
+    .subcommand(
+      App::new("banned-passwords")
+        .about("compiles a list of banned passwords (one per line) for the password_policy.banned_passwords setting")
+        .setting(AppSettings::ArgRequiredElseHelp)
+        .arg(
+          Arg::new("list")
+            .about("text file with one password per line")
+            .required(true)
+            .index(1),
+        )
+        .arg(
+          Arg::new("output")
+            .about("where the compiled file is written")
+            .required(true)
+            .index(2),
+        ),
+    )
     .get_matches();
 
   // You can check the value provided by positional arguments, or option arguments
@@ -102,6 +121,19 @@ pub fn main() {
     }
   }
 
+  if let Some(matches) = matches.subcommand_matches("banned-passwords") {
+    let list = Path::new(matches.value_of("list").unwrap());
+    let output = Path::new(matches.value_of("output").unwrap());
+
+    match BannedPasswords::compile(list, output) {
+      Ok(count) => println!("Compiled {} banned passwords into {:?}.", count, output),
+      Err(err) => {
+        eprintln!("Banned passwords couldn't be compiled: {}", err);
+        std::process::exit(1);
+      }
+    }
+  }
+
   // You can check for the existence of subcommands, and if found use their
   // matches just as you would the top level app
   if let Some(matches) = matches.subcommand_matches("secret") {
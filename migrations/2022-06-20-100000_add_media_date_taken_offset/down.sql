@@ -0,0 +1 @@
+ALTER TABLE `media` DROP COLUMN `date_taken_offset`;
@@ -0,0 +1 @@
+ALTER TABLE `media` ADD COLUMN `date_taken_offset` INT AFTER `date_taken`;
@@ -0,0 +1,5 @@
+CREATE TABLE `user_setting` (
+  `user_id` INT NOT NULL PRIMARY KEY,
+  `timezone` VARCHAR(64),
+  CONSTRAINT `user_setting_fk0` FOREIGN KEY (`user_id`) REFERENCES `user`(`id`) ON DELETE CASCADE
+);
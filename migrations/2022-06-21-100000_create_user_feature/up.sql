@@ -0,0 +1,7 @@
+CREATE TABLE `user_feature` (
+  `id` INT NOT NULL PRIMARY KEY AUTO_INCREMENT,
+  `user_id` INT NOT NULL,
+  `feature` VARCHAR(64) NOT NULL,
+  CONSTRAINT `user_feature_fk0` FOREIGN KEY (`user_id`) REFERENCES `user`(`id`) ON DELETE CASCADE,
+  CONSTRAINT `user_feature_un0` UNIQUE (`user_id`, `feature`)
+);
@@ -0,0 +1,7 @@
+CREATE TABLE `user_scan_ignore` (
+  `id` INT NOT NULL PRIMARY KEY AUTO_INCREMENT,
+  `user_id` INT NOT NULL,
+  `pattern` VARCHAR(255) NOT NULL,
+  CONSTRAINT `user_scan_ignore_fk0` FOREIGN KEY (`user_id`) REFERENCES `user`(`id`) ON DELETE CASCADE,
+  CONSTRAINT `user_scan_ignore_un0` UNIQUE (`user_id`, `pattern`)
+);
@@ -0,0 +1 @@
+DROP TABLE media_grant
\ No newline at end of file
@@ -0,0 +1,9 @@
+CREATE TABLE `media_grant` (
+  `id` INT NOT NULL PRIMARY KEY AUTO_INCREMENT,
+  `media_id` INT NOT NULL,
+  `user_id` INT NOT NULL,
+  `created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  CONSTRAINT `media_grant_fk0` FOREIGN KEY (`media_id`) REFERENCES `media`(`id`) ON DELETE CASCADE,
+  CONSTRAINT `media_grant_fk1` FOREIGN KEY (`user_id`) REFERENCES `user`(`id`) ON DELETE CASCADE,
+  CONSTRAINT `media_grant_un0` UNIQUE (`media_id`, `user_id`)
+);
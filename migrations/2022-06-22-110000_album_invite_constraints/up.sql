@@ -0,0 +1,8 @@
+ALTER TABLE `album_invite`
+  DROP FOREIGN KEY `album_invite_fk0`,
+  DROP FOREIGN KEY `album_invite_fk1`;
+
+ALTER TABLE `album_invite`
+  ADD CONSTRAINT `album_invite_fk0` FOREIGN KEY (`album_id`) REFERENCES `album`(`id`) ON DELETE CASCADE,
+  ADD CONSTRAINT `album_invite_fk1` FOREIGN KEY (`invited_user_id`) REFERENCES `user`(`id`) ON DELETE CASCADE,
+  ADD CONSTRAINT `album_invite_un0` UNIQUE (`album_id`, `invited_user_id`);
@@ -0,0 +1 @@
+ALTER TABLE `album` ADD COLUMN `locked` BOOLEAN NOT NULL DEFAULT FALSE AFTER `password`;
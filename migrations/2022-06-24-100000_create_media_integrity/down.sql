@@ -0,0 +1 @@
+DROP TABLE media_integrity
\ No newline at end of file
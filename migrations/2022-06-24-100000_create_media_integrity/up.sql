@@ -0,0 +1,7 @@
+CREATE TABLE `media_integrity` (
+  `media_id` INT NOT NULL PRIMARY KEY,
+  `verified_at` DATETIME NOT NULL,
+  `status` VARCHAR(16) NOT NULL,
+  `actual_sha2_512` VARCHAR(128),
+  CONSTRAINT `media_integrity_fk0` FOREIGN KEY (`media_id`) REFERENCES `media`(`id`) ON DELETE CASCADE
+);
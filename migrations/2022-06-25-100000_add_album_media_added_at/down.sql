@@ -0,0 +1 @@
+ALTER TABLE `album_media` DROP COLUMN `added_at`;
@@ -0,0 +1 @@
+ALTER TABLE `album_media` ADD COLUMN `added_at` DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP;
@@ -0,0 +1 @@
+DROP TABLE album_visit
\ No newline at end of file
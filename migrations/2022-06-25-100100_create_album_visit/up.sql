@@ -0,0 +1,8 @@
+CREATE TABLE `album_visit` (
+  `user_id` INT NOT NULL,
+  `album_id` INT NOT NULL,
+  `visited_at` DATETIME NOT NULL,
+  PRIMARY KEY (`user_id`, `album_id`),
+  CONSTRAINT `album_visit_fk0` FOREIGN KEY (`user_id`) REFERENCES `user`(`id`) ON DELETE CASCADE,
+  CONSTRAINT `album_visit_fk1` FOREIGN KEY (`album_id`) REFERENCES `album`(`id`) ON DELETE CASCADE
+);
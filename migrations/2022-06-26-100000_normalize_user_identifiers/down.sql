@@ -0,0 +1,3 @@
+ALTER TABLE `user`
+  MODIFY `username` VARCHAR(60) NOT NULL,
+  MODIFY `email` VARCHAR(254) NOT NULL;
@@ -0,0 +1,8 @@
+-- NFC normalization isn't available in MySQL, so existing rows are only trimmed and lowercased.
+-- Users whose names or emails differ only in case must be merged manually before running this migration.
+UPDATE `user` SET `username` = LOWER(TRIM(`username`)), `email` = LOWER(TRIM(`email`));
+
+-- the case-insensitive collation makes the existing unique constraints case-insensitive too
+ALTER TABLE `user`
+  MODIFY `username` VARCHAR(60) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NOT NULL,
+  MODIFY `email` VARCHAR(254) CHARACTER SET utf8mb4 COLLATE utf8mb4_unicode_ci NOT NULL;
@@ -0,0 +1,4 @@
+ALTER TABLE `album_share_link`
+  DROP COLUMN `title`,
+  DROP COLUMN `welcome_message`,
+  DROP COLUMN `accent_color`;
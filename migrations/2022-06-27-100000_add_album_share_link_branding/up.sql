@@ -0,0 +1,4 @@
+ALTER TABLE `album_share_link`
+  ADD COLUMN `title` VARCHAR(255) AFTER `expiration`,
+  ADD COLUMN `welcome_message` TEXT AFTER `title`,
+  ADD COLUMN `accent_color` CHAR(7) AFTER `welcome_message`;
@@ -0,0 +1,3 @@
+DROP TABLE `album_share_link_download`;
+
+ALTER TABLE `album_share_link` DROP COLUMN `allow_zip_download`;
@@ -0,0 +1,8 @@
+ALTER TABLE `album_share_link` ADD COLUMN `allow_zip_download` BOOLEAN NOT NULL DEFAULT FALSE AFTER `accent_color`;
+
+CREATE TABLE `album_share_link_download` (
+  `id` INT NOT NULL PRIMARY KEY AUTO_INCREMENT,
+  `album_share_link_id` INT NOT NULL,
+  `downloaded_at` DATETIME NOT NULL,
+  CONSTRAINT `album_share_link_download_fk0` FOREIGN KEY (`album_share_link_id`) REFERENCES `album_share_link`(`id`) ON DELETE CASCADE
+);
@@ -0,0 +1 @@
+ALTER TABLE `album` ADD COLUMN `smart` VARCHAR(32) AFTER `locked`;
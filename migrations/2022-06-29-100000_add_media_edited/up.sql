@@ -0,0 +1 @@
+ALTER TABLE `media` ADD COLUMN `edited` BOOLEAN NOT NULL DEFAULT FALSE AFTER `sha2_512`;
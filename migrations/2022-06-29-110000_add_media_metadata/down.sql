@@ -0,0 +1,8 @@
+ALTER TABLE `media`
+  DROP COLUMN `camera_make`,
+  DROP COLUMN `camera_model`,
+  DROP COLUMN `latitude`,
+  DROP COLUMN `longitude`,
+  DROP COLUMN `iso`,
+  DROP COLUMN `focal_length`,
+  DROP COLUMN `orientation`;
@@ -0,0 +1,8 @@
+ALTER TABLE `media`
+  ADD COLUMN `camera_make` VARCHAR(255) AFTER `edited`,
+  ADD COLUMN `camera_model` VARCHAR(255) AFTER `camera_make`,
+  ADD COLUMN `latitude` DOUBLE AFTER `camera_model`,
+  ADD COLUMN `longitude` DOUBLE AFTER `latitude`,
+  ADD COLUMN `iso` INT UNSIGNED AFTER `longitude`,
+  ADD COLUMN `focal_length` DOUBLE AFTER `iso`,
+  ADD COLUMN `orientation` SMALLINT UNSIGNED AFTER `focal_length`;
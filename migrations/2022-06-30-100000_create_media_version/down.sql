@@ -0,0 +1,4 @@
+DROP TABLE `media_version`;
+
+ALTER TABLE `media` DROP COLUMN `version`;
+ALTER TABLE `media` ADD COLUMN `edited` BOOLEAN NOT NULL DEFAULT FALSE AFTER `sha2_512`;
@@ -0,0 +1,16 @@
+ALTER TABLE `media` DROP COLUMN `edited`;
+ALTER TABLE `media` ADD COLUMN `version` INT NOT NULL DEFAULT 0 AFTER `sha2_512`;
+
+CREATE TABLE `media_version` (
+  `id` INT NOT NULL PRIMARY KEY AUTO_INCREMENT,
+  `media_id` INT NOT NULL,
+  `version` INT NOT NULL,
+  `operations` TEXT,
+  `width` INT UNSIGNED NOT NULL,
+  `height` INT UNSIGNED NOT NULL,
+  `sha2_512` VARCHAR(128) NOT NULL,
+  `orientation` SMALLINT UNSIGNED,
+  `created_at` DATETIME NOT NULL,
+  UNIQUE (`media_id`, `version`),
+  CONSTRAINT `media_version_fk0` FOREIGN KEY (`media_id`) REFERENCES `media`(`id`) ON DELETE CASCADE
+);
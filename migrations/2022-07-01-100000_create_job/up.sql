@@ -0,0 +1,16 @@
+CREATE TABLE `job` (
+  `id` INT NOT NULL PRIMARY KEY AUTO_INCREMENT,
+  `uuid` VARCHAR(21) NOT NULL UNIQUE,
+  `user_id` INT NOT NULL,
+  `kind` VARCHAR(32) NOT NULL,
+  `state` VARCHAR(16) NOT NULL,
+  `total` INT UNSIGNED,
+  `processed` INT UNSIGNED NOT NULL DEFAULT 0,
+  `error` TEXT,
+  `resumed_from` VARCHAR(21),
+  `created_at` DATETIME NOT NULL,
+  `finished_at` DATETIME,
+  INDEX (`state`),
+  CONSTRAINT `job_fk0` FOREIGN KEY (`user_id`) REFERENCES `user`(`id`) ON DELETE CASCADE,
+  CONSTRAINT `job_fk1` FOREIGN KEY (`resumed_from`) REFERENCES `job`(`uuid`) ON DELETE SET NULL
+);
@@ -0,0 +1,6 @@
+DROP TABLE `organization_admin`;
+
+ALTER TABLE `user` DROP FOREIGN KEY `user_fk0`;
+ALTER TABLE `user` DROP COLUMN `organization_id`;
+
+DROP TABLE `organization`;
@@ -0,0 +1,25 @@
+CREATE TABLE `organization` (
+  `id` INT NOT NULL PRIMARY KEY AUTO_INCREMENT,
+  `uuid` VARCHAR(21) NOT NULL UNIQUE,
+  `name` VARCHAR(255) NOT NULL,
+  `created_at` DATETIME NOT NULL
+);
+
+-- existing users form the default organization
+INSERT INTO `organization` (`id`, `uuid`, `name`, `created_at`) VALUES (1, SUBSTRING(REPLACE(UUID(), '-', ''), 1, 21), 'Default', NOW());
+
+ALTER TABLE `user`
+  ADD COLUMN `organization_id` INT NOT NULL DEFAULT 1 AFTER `password`,
+  ADD CONSTRAINT `user_fk0` FOREIGN KEY (`organization_id`) REFERENCES `organization`(`id`);
+
+ALTER TABLE `user` ALTER COLUMN `organization_id` DROP DEFAULT;
+
+CREATE TABLE `organization_admin` (
+  `user_id` INT NOT NULL PRIMARY KEY,
+  `organization_id` INT NOT NULL,
+  CONSTRAINT `organization_admin_fk0` FOREIGN KEY (`user_id`) REFERENCES `user`(`id`) ON DELETE CASCADE,
+  CONSTRAINT `organization_admin_fk1` FOREIGN KEY (`organization_id`) REFERENCES `organization`(`id`) ON DELETE CASCADE
+);
+
+-- the first user administers the default organization
+INSERT INTO `organization_admin` (`user_id`, `organization_id`) SELECT MIN(`id`), 1 FROM `user` HAVING MIN(`id`) IS NOT NULL;
@@ -0,0 +1 @@
+ALTER TABLE `album_share_link` DROP COLUMN `bandwidth_limit`;
@@ -0,0 +1,2 @@
+-- kibibytes per second shared by all visitors of the link; NULL means no limit
+ALTER TABLE `album_share_link` ADD COLUMN `bandwidth_limit` INT NULL AFTER `allow_zip_download`;
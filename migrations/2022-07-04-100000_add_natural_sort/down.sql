@@ -0,0 +1,7 @@
+ALTER TABLE `album`
+  DROP COLUMN `sort_order`,
+  DROP COLUMN `sort`;
+
+ALTER TABLE `media`
+  DROP INDEX `media_filename_sort_key`,
+  DROP COLUMN `filename_sort_key`;
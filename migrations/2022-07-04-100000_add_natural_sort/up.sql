@@ -0,0 +1,9 @@
+-- filenames with zero-padded numbers, so they sort naturally (IMG_2 before IMG_10); filled in by the server
+ALTER TABLE `media`
+  ADD COLUMN `filename_sort_key` VARCHAR(512) NULL,
+  ADD INDEX `media_filename_sort_key` (`owner_id`, `filename_sort_key`);
+
+-- default sorting of the album's media
+ALTER TABLE `album`
+  ADD COLUMN `sort` VARCHAR(20) NULL,
+  ADD COLUMN `sort_order` VARCHAR(4) NULL;
@@ -0,0 +1,2 @@
+ALTER TABLE `media`
+  DROP INDEX `media_owner_hash`;
@@ -0,0 +1,3 @@
+-- duplicates are looked up by hash among media of one user
+ALTER TABLE `media`
+  ADD INDEX `media_owner_hash` (`owner_id`, `sha2_512`);
@@ -0,0 +1,3 @@
+ALTER TABLE `album`
+  DROP COLUMN `date_to`,
+  DROP COLUMN `date_from`;
@@ -0,0 +1,4 @@
+-- local time when the earliest and the latest media of the album were taken; filled in by the server
+ALTER TABLE `album`
+  ADD COLUMN `date_from` DATETIME NULL,
+  ADD COLUMN `date_to` DATETIME NULL;
@@ -0,0 +1,5 @@
+DROP TABLE `folder_scan`;
+
+ALTER TABLE `media`
+  DROP COLUMN `file_modified_at`,
+  DROP COLUMN `file_size`;
@@ -0,0 +1,11 @@
+-- size and modification time of the file at the last scan, so unchanged files aren't read again
+ALTER TABLE `media`
+  ADD COLUMN `file_size` BIGINT UNSIGNED NULL,
+  ADD COLUMN `file_modified_at` DATETIME(6) NULL;
+
+-- modification time of folders at the last scan; folders which haven't changed aren't listed again
+CREATE TABLE `folder_scan` (
+  `folder_id` INT NOT NULL PRIMARY KEY,
+  `modified_at` DATETIME(6) NOT NULL,
+  CONSTRAINT `folder_scan_fk0` FOREIGN KEY (`folder_id`) REFERENCES `folder`(`id`) ON DELETE CASCADE
+);
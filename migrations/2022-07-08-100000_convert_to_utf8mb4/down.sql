@@ -0,0 +1,2 @@
+-- the previous character sets aren't known and converting back could lose emoji, so tables stay in utf8mb4
+SELECT 1;
@@ -0,0 +1,13 @@
+-- one-time links for signing up into an organization
+CREATE TABLE `user_invite` (
+  `id` INT NOT NULL PRIMARY KEY AUTO_INCREMENT,
+  `token` VARCHAR(21) NOT NULL UNIQUE,
+  `organization_id` INT NOT NULL,
+  `created_by` INT NULL,
+  `created_at` DATETIME NOT NULL,
+  `expiration` DATETIME NOT NULL,
+  `admin` BOOLEAN NOT NULL DEFAULT FALSE,
+  `used_at` DATETIME NULL,
+  CONSTRAINT `user_invite_fk0` FOREIGN KEY (`organization_id`) REFERENCES `organization`(`id`) ON DELETE CASCADE,
+  CONSTRAINT `user_invite_fk1` FOREIGN KEY (`created_by`) REFERENCES `user`(`id`) ON DELETE SET NULL
+);
@@ -0,0 +1,3 @@
+-- role on the whole instance; the server promotes the oldest user when there's no administrator
+ALTER TABLE `user`
+  ADD COLUMN `role` VARCHAR(20) NOT NULL DEFAULT 'user';
@@ -0,0 +1,3 @@
+-- disabled users can't sign in; administrators can enable them again
+ALTER TABLE `user`
+  ADD COLUMN `disabled` BOOLEAN NOT NULL DEFAULT FALSE;
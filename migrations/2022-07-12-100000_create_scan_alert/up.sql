@@ -0,0 +1,14 @@
+-- scans which found an unusual share of the user's media modified or missing
+CREATE TABLE `scan_alert` (
+  `id` INT NOT NULL PRIMARY KEY AUTO_INCREMENT,
+  `user_id` INT NOT NULL,
+  `job_id` INT NULL,
+  `created_at` DATETIME NOT NULL,
+  `media` INT UNSIGNED NOT NULL,
+  `modified` INT UNSIGNED NOT NULL,
+  `missing` INT UNSIGNED NOT NULL,
+  `paused` BOOLEAN NOT NULL,
+  `confirmed_at` DATETIME NULL,
+  CONSTRAINT `scan_alert_fk0` FOREIGN KEY (`user_id`) REFERENCES `user`(`id`) ON DELETE CASCADE,
+  CONSTRAINT `scan_alert_fk1` FOREIGN KEY (`job_id`) REFERENCES `job`(`id`) ON DELETE SET NULL
+);
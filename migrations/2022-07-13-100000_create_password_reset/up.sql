@@ -0,0 +1,12 @@
+-- one-time tokens for setting a new password, issued by administrators of the instance
+CREATE TABLE `password_reset` (
+  `id` INT NOT NULL PRIMARY KEY AUTO_INCREMENT,
+  `token` VARCHAR(21) NOT NULL UNIQUE,
+  `user_id` INT NOT NULL,
+  `created_by` INT NULL,
+  `created_at` DATETIME NOT NULL,
+  `expiration` DATETIME NOT NULL,
+  `used_at` DATETIME NULL,
+  CONSTRAINT `password_reset_fk0` FOREIGN KEY (`user_id`) REFERENCES `user`(`id`) ON DELETE CASCADE,
+  CONSTRAINT `password_reset_fk1` FOREIGN KEY (`created_by`) REFERENCES `user`(`id`) ON DELETE SET NULL
+);
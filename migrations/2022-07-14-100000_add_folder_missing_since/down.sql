@@ -0,0 +1,2 @@
+ALTER TABLE `folder`
+  DROP COLUMN `missing_since`;
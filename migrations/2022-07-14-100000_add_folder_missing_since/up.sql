@@ -0,0 +1,3 @@
+-- folders with media whose directories disappeared; cleared when the directory appears again
+ALTER TABLE `folder`
+  ADD COLUMN `missing_since` DATETIME NULL;
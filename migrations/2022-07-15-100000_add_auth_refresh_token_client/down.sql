@@ -0,0 +1,5 @@
+ALTER TABLE `auth_refresh_token`
+  DROP COLUMN `uuid`,
+  DROP COLUMN `created_at`,
+  DROP COLUMN `user_agent`,
+  DROP COLUMN `ip_address`;
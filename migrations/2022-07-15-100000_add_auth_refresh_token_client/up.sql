@@ -0,0 +1,6 @@
+-- sessions listed to their users; tokens issued before have no UUID and expire within an hour
+ALTER TABLE `auth_refresh_token`
+  ADD COLUMN `uuid` VARCHAR(21) UNIQUE AFTER `user_id`,
+  ADD COLUMN `created_at` DATETIME AFTER `expiration_time`,
+  ADD COLUMN `user_agent` VARCHAR(255) AFTER `created_at`,
+  ADD COLUMN `ip_address` VARCHAR(45) AFTER `user_agent`;
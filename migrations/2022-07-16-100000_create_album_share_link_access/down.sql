@@ -0,0 +1 @@
+DROP TABLE `album_share_link_access`;
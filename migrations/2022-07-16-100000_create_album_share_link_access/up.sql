@@ -0,0 +1,10 @@
+-- accesses to share links shown to album owners, including wrong passwords
+CREATE TABLE `album_share_link_access` (
+  `id` INT NOT NULL PRIMARY KEY AUTO_INCREMENT,
+  `album_share_link_id` INT NOT NULL,
+  `accessed_at` DATETIME NOT NULL,
+  `ip_address` VARCHAR(45) NULL,
+  `user_agent` VARCHAR(255) NULL,
+  `succeeded` BOOLEAN NOT NULL,
+  CONSTRAINT `album_share_link_access_fk0` FOREIGN KEY (`album_share_link_id`) REFERENCES `album_share_link`(`id`) ON DELETE CASCADE
+);
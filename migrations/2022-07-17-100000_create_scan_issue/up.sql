@@ -0,0 +1,11 @@
+-- files which scans couldn't add, so users can fix them
+CREATE TABLE `scan_issue` (
+  `id` INT NOT NULL PRIMARY KEY AUTO_INCREMENT,
+  `job_id` INT NOT NULL,
+  `path` TEXT NOT NULL,
+  `kind` VARCHAR(32) NOT NULL,
+  `severity` VARCHAR(16) NOT NULL,
+  `message` TEXT NULL,
+  `created_at` DATETIME NOT NULL,
+  CONSTRAINT `scan_issue_fk0` FOREIGN KEY (`job_id`) REFERENCES `job`(`id`) ON DELETE CASCADE
+);
@@ -0,0 +1,8 @@
+ALTER TABLE `auth_refresh_token`
+  DROP INDEX `auth_refresh_token_user_expiration`;
+
+ALTER TABLE `media`
+  DROP INDEX `media_owner_date_taken`;
+
+ALTER TABLE `folder`
+  DROP INDEX `folder_owner_parent_name`;
@@ -0,0 +1,17 @@
+-- indexes of the hot queries; media.uuid, the token strings and album_media.album_id are already covered
+-- by their unique keys, media.owner_id by `media_filename_sort_key` and `media_owner_hash`
+
+-- folders are looked up by their name in the parent folder on every scan;
+-- expected plan: ref on `folder_owner_parent_name` with all three columns instead of the owner's foreign key
+ALTER TABLE `folder`
+  ADD INDEX `folder_owner_parent_name` (`owner_id`, `parent`, `name`);
+
+-- the default listing of media (by date taken) and the cursors of its pages;
+-- expected plan: range on `media_owner_date_taken` without a filesort
+ALTER TABLE `media`
+  ADD INDEX `media_owner_date_taken` (`owner_id`, `date_taken`);
+
+-- sessions of a user are listed without the expired ones;
+-- expected plan: range on `auth_refresh_token_user_expiration`
+ALTER TABLE `auth_refresh_token`
+  ADD INDEX `auth_refresh_token_user_expiration` (`user_id`, `expiration_time`);
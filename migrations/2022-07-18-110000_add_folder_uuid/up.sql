@@ -0,0 +1,3 @@
+-- folders are exposed by the API; existing folders get their UUIDs from the server at the next start
+ALTER TABLE `folder`
+  ADD COLUMN `uuid` VARCHAR(21) NULL UNIQUE;
@@ -0,0 +1,3 @@
+-- media of a user are selected by a bounding box of the map
+ALTER TABLE `media`
+  ADD INDEX `media_owner_location` (`owner_id`, `latitude`, `longitude`);
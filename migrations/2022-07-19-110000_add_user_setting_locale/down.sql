@@ -0,0 +1,3 @@
+ALTER TABLE `user_setting`
+  DROP COLUMN `date_format`,
+  DROP COLUMN `locale`;
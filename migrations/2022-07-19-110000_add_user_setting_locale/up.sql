@@ -0,0 +1,4 @@
+-- language and date format preferences, see `locale`
+ALTER TABLE `user_setting`
+  ADD COLUMN `locale` VARCHAR(35) NULL,
+  ADD COLUMN `date_format` VARCHAR(20) NULL;
@@ -0,0 +1,3 @@
+ALTER TABLE `album_media`
+  DROP FOREIGN KEY `album_media_fk2`,
+  DROP COLUMN `added_by`;
@@ -0,0 +1,5 @@
+-- who added the media to the album, so owners of shared albums can see the contributions;
+-- NULL for media added before it was recorded
+ALTER TABLE `album_media`
+  ADD COLUMN `added_by` INT NULL,
+  ADD CONSTRAINT `album_media_fk2` FOREIGN KEY (`added_by`) REFERENCES `user`(`id`) ON DELETE SET NULL;
@@ -0,0 +1,2 @@
+DROP TABLE `media_tag`;
+DROP TABLE `tag`;
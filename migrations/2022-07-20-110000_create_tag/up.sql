@@ -0,0 +1,18 @@
+-- tags of media; each user has their own tags, names are unique per user regardless of the letter case
+CREATE TABLE `tag` (
+  `id` INT NOT NULL PRIMARY KEY AUTO_INCREMENT,
+  `owner_id` INT NOT NULL,
+  `name` VARCHAR(64) NOT NULL,
+  `created_at` DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  CONSTRAINT `tag_fk0` FOREIGN KEY (`owner_id`) REFERENCES `user`(`id`) ON DELETE CASCADE,
+  CONSTRAINT `tag_un0` UNIQUE (`owner_id`, `name`)
+);
+
+CREATE TABLE `media_tag` (
+  `id` INT NOT NULL PRIMARY KEY AUTO_INCREMENT,
+  `media_id` INT NOT NULL,
+  `tag_id` INT NOT NULL,
+  CONSTRAINT `media_tag_fk0` FOREIGN KEY (`media_id`) REFERENCES `media`(`id`) ON DELETE CASCADE,
+  CONSTRAINT `media_tag_fk1` FOREIGN KEY (`tag_id`) REFERENCES `tag`(`id`) ON DELETE CASCADE,
+  CONSTRAINT `media_tag_un0` UNIQUE (`tag_id`, `media_id`)
+);
@@ -0,0 +1,2 @@
+DROP TABLE `media_face`;
+DROP TABLE `person`;
@@ -0,0 +1,25 @@
+-- people in media of a user; faces are assigned manually for now, so faces found by a later recognition
+-- can be stored without a person
+CREATE TABLE `person` (
+  `id` INT NOT NULL PRIMARY KEY AUTO_INCREMENT,
+  `uuid` VARCHAR(21) NOT NULL UNIQUE,
+  `owner_id` INT NOT NULL,
+  `name` VARCHAR(255) NOT NULL,
+  `created_at` DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  CONSTRAINT `person_fk0` FOREIGN KEY (`owner_id`) REFERENCES `user`(`id`) ON DELETE CASCADE
+);
+
+-- bounding boxes are relative to the width and height of the original media, from its top left corner
+CREATE TABLE `media_face` (
+  `id` INT NOT NULL PRIMARY KEY AUTO_INCREMENT,
+  `uuid` VARCHAR(21) NOT NULL UNIQUE,
+  `media_id` INT NOT NULL,
+  `person_id` INT NULL,
+  `x` DOUBLE NOT NULL,
+  `y` DOUBLE NOT NULL,
+  `width` DOUBLE NOT NULL,
+  `height` DOUBLE NOT NULL,
+  `created_at` DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  CONSTRAINT `media_face_fk0` FOREIGN KEY (`media_id`) REFERENCES `media`(`id`) ON DELETE CASCADE,
+  CONSTRAINT `media_face_fk1` FOREIGN KEY (`person_id`) REFERENCES `person`(`id`) ON DELETE SET NULL
+);
@@ -0,0 +1,2 @@
+DROP TABLE `tag_suggestion`;
+DROP TABLE `media_detection`;
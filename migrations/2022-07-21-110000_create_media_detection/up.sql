@@ -0,0 +1,16 @@
+-- media processed by the detection of faces and objects (the `detection` feature), so they aren't processed again
+CREATE TABLE `media_detection` (
+  `media_id` INT NOT NULL PRIMARY KEY,
+  `detected_at` DATETIME NOT NULL,
+  CONSTRAINT `media_detection_fk0` FOREIGN KEY (`media_id`) REFERENCES `media`(`id`) ON DELETE CASCADE
+);
+
+-- tags suggested for media by the detection of objects; detected faces are stored in `media_face` without a person
+CREATE TABLE `tag_suggestion` (
+  `id` INT NOT NULL PRIMARY KEY AUTO_INCREMENT,
+  `media_id` INT NOT NULL,
+  `name` VARCHAR(64) NOT NULL,
+  `confidence` DOUBLE NOT NULL,
+  CONSTRAINT `tag_suggestion_fk0` FOREIGN KEY (`media_id`) REFERENCES `media`(`id`) ON DELETE CASCADE,
+  CONSTRAINT `tag_suggestion_un0` UNIQUE (`media_id`, `name`)
+);
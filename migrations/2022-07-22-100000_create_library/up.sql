@@ -0,0 +1,12 @@
+-- directories with media of a user outside of the gallery, e.g. on external drives;
+-- a library without a name replaces the user's directory in the gallery, the other ones appear as its subfolders
+CREATE TABLE `library` (
+  `id` INT NOT NULL PRIMARY KEY AUTO_INCREMENT,
+  `uuid` VARCHAR(21) NOT NULL UNIQUE,
+  `owner_id` INT NOT NULL,
+  `name` VARCHAR(255) NULL,
+  `path` VARCHAR(4096) NOT NULL,
+  `created_at` DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  CONSTRAINT `library_fk0` FOREIGN KEY (`owner_id`) REFERENCES `user`(`id`) ON DELETE CASCADE,
+  CONSTRAINT `library_un0` UNIQUE (`owner_id`, `name`)
+);
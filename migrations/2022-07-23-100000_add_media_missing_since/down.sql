@@ -0,0 +1,2 @@
+ALTER TABLE `media`
+  DROP COLUMN `missing_since`;
@@ -0,0 +1,3 @@
+-- media whose files weren't found by a scan; cleared when the file appears again or is relinked
+ALTER TABLE `media`
+  ADD COLUMN `missing_since` DATETIME NULL;
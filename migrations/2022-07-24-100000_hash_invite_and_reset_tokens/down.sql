@@ -0,0 +1,9 @@
+-- hashed tokens don't fit the original columns and can't be turned back into plaintext tokens
+DELETE FROM `user_invite` WHERE CHAR_LENGTH(`token`) > 21;
+DELETE FROM `password_reset` WHERE CHAR_LENGTH(`token`) > 21;
+
+ALTER TABLE `user_invite`
+  MODIFY `token` VARCHAR(21) NOT NULL;
+
+ALTER TABLE `password_reset`
+  MODIFY `token` VARCHAR(21) NOT NULL;
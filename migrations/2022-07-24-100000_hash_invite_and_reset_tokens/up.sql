@@ -0,0 +1,7 @@
+-- tokens of invites and password resets are stored as SHA-256 hashes (64 hexadecimal characters);
+-- tokens stored in plaintext before are hashed when the server starts
+ALTER TABLE `user_invite`
+  MODIFY `token` VARCHAR(64) NOT NULL;
+
+ALTER TABLE `password_reset`
+  MODIFY `token` VARCHAR(64) NOT NULL;
@@ -0,0 +1,86 @@
+//! Versions of the API.
+//!
+//! Routes are mounted under the prefix of their version (e.g. `/v1/media`), so a breaking change can be made
+//! in a new version while existing clients keep using the old one.\
+//! For clients written before versioning, the current routes are also mounted without a prefix.
+//! These legacy routes are deprecated: their responses have the `Deprecation` header, a `Link` to the versioned route
+//! and, once `legacy_api_sunset` is configured, the `Sunset` header with the time they stop working.\
+//! Operation IDs of the `OpenApi` document are the names of the route functions (see `api_routes()`),
+//! so generated clients keep their method names.
+
+use crate::config::Config;
+use okapi::openapi3::{OpenApi, Server};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket::{Request, Response};
+
+/// Version of the API served by this server.
+pub const CURRENT: &str = "v1";
+
+/// Returns the path prefix of routes of the current version.
+pub fn prefix() -> String {
+  format!("/{}", CURRENT)
+}
+
+/// Sets the server of the `OpenApi` document to the prefix, so clients generated from it call the versioned routes.
+pub fn with_server_prefix(mut spec: OpenApi, prefix: &str) -> OpenApi {
+  spec.servers = vec![Server { url: prefix.to_owned(), ..Default::default() }];
+
+  spec
+}
+
+/// Marks responses of the legacy routes, which are mounted without a version prefix, as deprecated.
+pub struct LegacyRoutes;
+
+#[rocket::async_trait]
+impl Fairing for LegacyRoutes {
+  fn info(&self) -> Info {
+    Info { name: "Legacy API routes", kind: Kind::Response }
+  }
+
+  async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+    // requests which didn't match any route and routes of other mounts (e.g. the Swagger UI) aren't API routes
+    let legacy = matches!(request.route(), Some(route) if route.uri.base() == "/");
+    if !legacy { return }
+
+    response.set_header(Header::new("Deprecation", "true"));
+    response.set_header(Header::new("Link", format!("<{}{}>; rel=\"successor-version\"", prefix(), request.uri().path())));
+
+    let sunset = request.rocket().state::<Config>().and_then(|config| config.legacy_api_sunset);
+    if let Some(sunset) = sunset {
+      response.set_header(Header::new("Sunset", sunset.format("%a, %d %b %Y %H:%M:%S GMT").to_string()));
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use okapi::merge::merge_specs;
+  use rocket_okapi::settings::OpenApiSettings;
+  use std::collections::HashSet;
+
+  #[test]
+  fn operation_ids_are_unique() {
+    let settings = OpenApiSettings::default();
+    let (api_routes, mut spec) = crate::api_routes(&settings);
+    let (management_routes, management_spec) = crate::management_routes(&settings);
+
+    // without the management listener, all routes are in one document
+    merge_specs(&mut spec, &"", &management_spec).unwrap();
+
+    let mut operation_ids = HashSet::new();
+    for path in spec.paths.values() {
+      let operations = [&path.get, &path.put, &path.post, &path.delete, &path.options, &path.head, &path.patch, &path.trace];
+
+      for operation in operations.into_iter().flatten() {
+        let operation_id = operation.operation_id.clone().expect("operation ID");
+
+        // generated from the path of a function, e.g. `routes_media_structure`, when it isn't imported by its name
+        assert!(!operation_id.starts_with("routes_"), "Operation ID {} isn't the name of its function.", operation_id);
+        assert!(operation_ids.insert(operation_id.clone()), "Operation ID {} isn't unique.", operation_id);
+      }
+    }
+
+    assert_eq!(operation_ids.len(), api_routes.len() + management_routes.len());
+  }
+}
@@ -1,6 +1,8 @@
-use crate::{DbConn, db::users::{check_user_login_email, check_user_login_username}, models::User};
+use crate::{DbConn, db::users::{is_user_admin, select_user_login_email, select_user_login_username, update_user_password_hash}, models::User, validation::normalize_identifier};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket_okapi::{gen::OpenApiGenerator, request::{OpenApiFromRequest, RequestHeaderInput}};
 use serde::{Serialize, Deserialize};
-use sha2::Digest;
+use super::password::{self, PasswordCheck};
 use super::token::{Claims, ClaimsEncoded};
 
 /// Used for receiving login data.
@@ -12,45 +14,81 @@ pub struct UserLogin {
 }
 
 impl UserLogin {
+  pub fn username_or_email(&self) -> &str {
+    &self.username_or_email
+  }
+
   /// Checks whether the `username_or_email` field is an email or not.
   fn is_email(&self) -> bool {
     self.username_or_email.contains('@')
   }
 
-  /// Checks the credentials.
+  /// Checks the credentials; legacy password hashes are replaced by Argon2 hashes.
   async fn check(&self, conn: &DbConn) -> Option<i32> {
-    if self.is_email() {
-      return check_user_login_email(conn, self.username_or_email.clone(), self.password.clone()).await;
+    let (user_id, hash) = if self.is_email() {
+      select_user_login_email(conn, self.username_or_email.clone()).await.ok()??
     } else {
-      return check_user_login_username(conn, self.username_or_email.clone(), self.password.clone()).await;
+      select_user_login_username(conn, self.username_or_email.clone()).await.ok()??
+    };
+
+    let check = password::verify_password(&self.password, &hash).await;
+    if !check.is_valid() { return None }
+
+    if check == PasswordCheck::ValidLegacy && update_user_password_hash(conn, user_id, password::hash_password(&self.password).await).await.is_err() {
+      error!("Password of user {} couldn't be rehashed.", user_id);
     }
+
+    Some(user_id)
   }
 
-  /// Tries to log the user in.
-  pub async fn login(&self, conn: &DbConn) -> Option<Claims> {
+  /// Tries to log the user in; the client is shown in the list of the user's sessions.
+  pub async fn login(&self, conn: &DbConn, client: LoginClient) -> Option<Claims> {
     let user_id = self.check(conn).await?;
+    let is_admin = is_user_admin(conn, user_id).await.ok()?;
 
-    let token = Claims::new(user_id);
+    let token = Claims::new(user_id, is_admin);
 
     // add refresh and access tokens to db
-    let refresh_token_id = token.add_refresh_token_to_db(conn).await?;
+    let refresh_token_id = token.add_refresh_token_to_db(conn, client).await?;
     token.add_access_token_to_db(conn, refresh_token_id).await?;
 
     Some(token)
   }
 
-  /// Encrypts the password.
-  // TODO: deduplicate later
-  pub fn hash_password(mut self) -> Self {
-    let mut hasher = sha2::Sha512::new();
-    hasher.update(self.password);
-    // {:x} means format as hexadecimal
-    self.password = format!("{:X}", hasher.finalize());
+  /// Normalizes the username or email the same way as during the signup.
+  pub fn normalize(mut self) -> Self {
+    self.username_or_email = normalize_identifier(&self.username_or_email);
 
     self
   }
 }
 
+/// Client logging in, recorded with its session; the request guard never fails.
+#[derive(Debug, Default)]
+pub struct LoginClient {
+  pub user_agent: Option<String>,
+  /// Address of the client, as seen by Rocket (see its `ip_header` setting behind a proxy).
+  pub ip_address: Option<String>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for LoginClient {
+  type Error = ();
+
+  async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+    Outcome::Success(LoginClient {
+      user_agent: request.headers().get_one("User-Agent").map(str::to_owned),
+      ip_address: request.client_ip().map(|address| address.to_string()),
+    })
+  }
+}
+
+impl<'a> OpenApiFromRequest<'a> for LoginClient {
+  fn from_request_input(_gen: &mut OpenApiGenerator, _name: String, _required: bool) -> rocket_okapi::Result<RequestHeaderInput> {
+    Ok(RequestHeaderInput::None)
+  }
+}
+
 /// Used for sending information about user.
 #[derive(Serialize, JsonSchema)]
 pub struct UserInfo {
@@ -66,7 +104,9 @@ impl From<User> for UserInfo {
   ///   id: 0,
   ///   username: "John".to_string(),
   ///   email: "john@email.com".to_string(),
-  ///   password: "secret".to_string()
+  ///   password: "secret".to_string(),
+  ///   organization_id: 1,
+  ///   role: "user".to_string()
   /// };
   ///
   /// let user_info = UserInfo::from(user);
@@ -1,4 +1,7 @@
 pub mod login;
+pub mod password;
+pub mod permissions;
 pub mod secret;
 pub mod shared_album_link;
+pub mod signed_url;
 pub mod token;
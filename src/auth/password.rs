@@ -0,0 +1,120 @@
+//! Hashing of passwords of users and share links.
+//!
+//! Passwords are hashed with Argon2id and a random salt; the hash is stored as a PHC string
+//! (e.g. `$argon2id$v=19$m=4096,t=3,p=1$...`), so its parameters can change without breaking stored hashes.
+//!
+//! Hashing and verification take tens of milliseconds, so they run on the blocking thread pool of Tokio
+//! instead of stalling the async workers which handle other requests.
+//!
+//! Older versions stored unsalted SHA-512 hashes. They are still accepted and are replaced by Argon2 hashes
+//! once the password is used successfully.
+
+use argon2::password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use rocket::tokio::task;
+use sha2::Digest;
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+/// Result of checking a password against a stored hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordCheck {
+  Valid,
+  /// The password is valid, but its hash should be replaced by `hash_password()`.
+  ValidLegacy,
+  Invalid,
+}
+
+impl PasswordCheck {
+  pub fn is_valid(&self) -> bool {
+    *self != PasswordCheck::Invalid
+  }
+}
+
+/// Hashes the password with a random salt.
+pub async fn hash_password(password: &str) -> String {
+  let password = password.to_owned();
+
+  task::spawn_blocking(move || hash_password_blocking(&password)).await
+    .expect("Argon2 hashing doesn't panic")
+}
+
+/// Checks the password against a stored hash.
+pub async fn verify_password(password: &str, hash: &str) -> PasswordCheck {
+  let (password, hash) = (password.to_owned(), hash.to_owned());
+
+  task::spawn_blocking(move || verify_password_blocking(&password, &hash)).await
+    .expect("Argon2 verification doesn't panic")
+}
+
+/// Hashes the password like `hash_password()` on the current thread.
+fn hash_password_blocking(password: &str) -> String {
+  let salt = SaltString::generate(&mut OsRng);
+
+  Argon2::default()
+    .hash_password(password.as_bytes(), &salt)
+    .expect("Argon2 hashing with default parameters")
+    .to_string()
+}
+
+/// Checks the password like `verify_password()` on the current thread.
+fn verify_password_blocking(password: &str, hash: &str) -> PasswordCheck {
+  if !hash.starts_with('$') {
+    return match legacy_hash(password) == hash {
+      true => PasswordCheck::ValidLegacy,
+      false => PasswordCheck::Invalid,
+    };
+  }
+
+  let parsed_hash = match PasswordHash::new(hash) {
+    Ok(parsed_hash) => parsed_hash,
+    Err(err) => {
+      error!("Stored password hash is malformed: {}", err);
+      return PasswordCheck::Invalid;
+    },
+  };
+
+  match Argon2::default().verify_password(password.as_bytes(), &parsed_hash) {
+    Ok(()) => PasswordCheck::Valid,
+    Err(_) => PasswordCheck::Invalid,
+  }
+}
+
+/// Unsalted SHA-512 hash used by older versions.
+fn legacy_hash(password: &str) -> String {
+  let mut hasher = sha2::Sha512::new();
+  hasher.update(password);
+  // {:X} means format as uppercase hexadecimal
+  format!("{:X}", hasher.finalize())
+}
+
+/// Maximum number of remembered verifications; all are forgotten when it's reached.
+const MAX_VERIFIED: usize = 10_000;
+
+/// Passwords verified recently; managed by Rocket.\
+/// Every media of a shared album is requested with the password of the share link,
+/// so it would be hashed by Argon2 hundreds of times per page otherwise.
+#[derive(Debug, Default)]
+pub struct VerifiedPasswords {
+  verified: Mutex<HashSet<String>>,
+}
+
+impl VerifiedPasswords {
+  /// Checks the password against a stored hash like `verify_password()`, but valid passwords are checked only once.
+  pub async fn verify(&self, password: &str, hash: &str) -> PasswordCheck {
+    // the stored hash is a part of the key, so a changed password is verified again
+    let key = legacy_hash(&format!("{}\0{}", hash, password));
+    if self.verified.lock().unwrap().contains(&key) { return PasswordCheck::Valid }
+
+    let check = verify_password(password, hash).await;
+
+    if check == PasswordCheck::Valid {
+      let mut verified = self.verified.lock().unwrap();
+      if verified.len() >= MAX_VERIFIED { verified.clear(); }
+
+      verified.insert(key);
+    }
+
+    check
+  }
+}
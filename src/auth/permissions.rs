@@ -0,0 +1,194 @@
+use crate::auth::shared_album_link::SharedAlbumLinkSecurity;
+use crate::config::AccessDeniedPolicy;
+use crate::db;
+use crate::errors;
+use crate::models::SmartAlbum;
+use crate::DbConn;
+use rocket::http::Status;
+
+/// Role of a user in an album.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlbumRole {
+  /// Creator of the album.
+  Owner,
+  /// Invited user with write access.
+  Editor,
+  /// Invited user without write access.
+  Viewer,
+}
+
+/// Actions which can be performed on an album.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlbumAction {
+  /// Listing media of the album.
+  View,
+  /// Adding own media to the album.
+  AddMedia,
+  /// Removing media from the album; editors can remove only their own media.
+  RemoveMedia,
+  /// Changing name and default sorting of the album.
+  Update,
+  /// Changing description of the album.
+  UpdateDescription,
+  /// Choosing media of the album shown as its thumbnail.
+  SetThumbnail,
+  Delete,
+  ManageShareLinks,
+  ManageInvites,
+  /// Locking and unlocking the album.
+  Lock,
+}
+
+impl AlbumRole {
+  /// Checks whether the role allows the action.
+  /// # Permissions
+  /// | Action             | Owner | Editor | Viewer |
+  /// |--------------------|-------|--------|--------|
+  /// | `View`             | yes   | yes    | yes    |
+  /// | `AddMedia`         | yes   | yes    | no     |
+  /// | `RemoveMedia`      | yes   | own    | no     |
+  /// | `Update`           | yes   | no     | no     |
+  /// | `UpdateDescription`| yes   | yes    | no     |
+  /// | `SetThumbnail`     | yes   | yes    | no     |
+  /// | `Delete`           | yes   | no     | no     |
+  /// | `ManageShareLinks` | yes   | no     | no     |
+  /// | `ManageInvites`    | yes   | no     | no     |
+  /// | `Lock`             | yes   | no     | no     |
+  pub fn can(&self, action: AlbumAction) -> bool {
+    match self {
+      AlbumRole::Owner => true,
+      AlbumRole::Editor => matches!(
+        action,
+        AlbumAction::View | AlbumAction::AddMedia | AlbumAction::RemoveMedia | AlbumAction::UpdateDescription | AlbumAction::SetThumbnail
+      ),
+      AlbumRole::Viewer => action == AlbumAction::View,
+    }
+  }
+}
+
+/// Actions which can be performed on a media.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaAction {
+  /// Viewing, downloading and liking the media; allowed to everyone who has access to the media.
+  View,
+  /// Changing the media, sharing it and adding it to albums; allowed only to the owner.
+  Manage,
+}
+
+/// Checks whether the user can perform the action on the album and returns the user's role.\
+/// Users who can't see the album get the status of the `AccessDeniedPolicy`,
+/// users who can see it but aren't allowed to perform the action get 403.
+/// # Example
+/// ```
+/// let role: AlbumRole = authorize_album(&conn, config.access_denied, claims.user_id, album_id, AlbumAction::Delete).await?;
+/// ```
+pub async fn authorize_album(conn: &DbConn, policy: AccessDeniedPolicy, user_id: i32, album_id: i32, action: AlbumAction) -> Result<AlbumRole, Status> {
+  let role = db::albums::user_has_album_access(conn, user_id, album_id).await;
+  if role.is_err() { return Err(Status::InternalServerError) }
+
+  match role.unwrap() {
+    Some(role) if role.can(action) => Ok(role),
+    Some(_) => Err(Status::Forbidden),
+    None => Err(policy.status()),
+  }
+}
+
+/// Checks whether the user can perform the action on the media.\
+/// Users who can't see the media get the status of the `AccessDeniedPolicy`,
+/// users who can see it but aren't allowed to perform the action get 403.
+pub async fn authorize_media(conn: &DbConn, policy: AccessDeniedPolicy, user_id: i32, media_uuid: String, action: MediaAction) -> Result<(), Status> {
+  let access = db::media::media_user_has_access(conn, media_uuid.clone(), user_id).await;
+  if access.is_err() { return Err(Status::InternalServerError) }
+
+  if !access.unwrap() { return Err(policy.status()) }
+
+  if action == MediaAction::View { return Ok(()) }
+
+  let owner = db::media::media_user_is_owner(conn, media_uuid, user_id).await;
+  if owner.is_err() { return Err(Status::InternalServerError) }
+
+  if !owner.unwrap() { return Err(Status::Forbidden) }
+
+  Ok(())
+}
+
+/// Checks whether the share link grants access to the album.\
+/// Share links grant access only to their own album; links of other albums get 401.
+pub fn authorize_share_link_album(shared_album_link_security: &SharedAlbumLinkSecurity, album_id: i32) -> Result<(), Status> {
+  if shared_album_link_security.album_id() != album_id { return Err(Status::Unauthorized) }
+
+  Ok(())
+}
+
+/// Checks whether the media belongs to the shared album, so visitors of its share links can see it.\
+/// Media of smart albums are checked the same way as they're listed; other media get 404, like media which don't exist.
+/// # Example
+/// ```
+/// authorize_share_link_media(&conn, shared_album_link_security.album_id(), media.id).await?;
+/// ```
+pub async fn authorize_share_link_media(conn: &DbConn, album_id: i32, media_id: i32) -> Result<(), Status> {
+  let album = db::albums::select_album(conn, album_id).await.map_err(errors::internal)?;
+  if album.is_none() { return Err(Status::NotFound) }
+
+  let album = album.unwrap();
+
+  let contains = match album.smart() {
+    Some(SmartAlbum::Favorites) => db::media::media_is_liked(conn, media_id, album.owner_id).await,
+    None => db::albums::album_already_has_media(conn, album.id, media_id).await,
+  };
+
+  if contains.is_err() { return Err(Status::InternalServerError) }
+
+  if !contains.unwrap() { return Err(Status::NotFound) }
+
+  Ok(())
+}
+
+/// Checks whether the user administers an organization and returns its ID.\
+/// Other users get 403.
+/// # Example
+/// ```
+/// let organization_id: i32 = authorize_organization_admin(&conn, claims.user_id).await?;
+/// ```
+pub async fn authorize_organization_admin(conn: &DbConn, user_id: i32) -> Result<i32, Status> {
+  let is_admin = db::organizations::is_organization_admin(conn, user_id).await;
+  if is_admin.is_err() { return Err(Status::InternalServerError) }
+
+  if !is_admin.unwrap() { return Err(Status::Forbidden) }
+
+  let user = db::users::get_user_by_id(conn, user_id).await.map_err(errors::internal)?;
+  if user.is_none() { return Err(Status::InternalServerError) }
+
+  Ok(user.unwrap().organization_id)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{AlbumAction, AlbumRole};
+
+  /// Expected permissions of owners, editors and viewers; the same matrix as the documentation of `AlbumRole::can()`.
+  const MATRIX: [(AlbumAction, [bool; 3]); 10] = [
+    (AlbumAction::View, [true, true, true]),
+    (AlbumAction::AddMedia, [true, true, false]),
+    // editors can remove only their own media, which is checked by the route
+    (AlbumAction::RemoveMedia, [true, true, false]),
+    (AlbumAction::Update, [true, false, false]),
+    (AlbumAction::UpdateDescription, [true, true, false]),
+    (AlbumAction::SetThumbnail, [true, true, false]),
+    (AlbumAction::Delete, [true, false, false]),
+    (AlbumAction::ManageShareLinks, [true, false, false]),
+    (AlbumAction::ManageInvites, [true, false, false]),
+    (AlbumAction::Lock, [true, false, false]),
+  ];
+
+  const ROLES: [AlbumRole; 3] = [AlbumRole::Owner, AlbumRole::Editor, AlbumRole::Viewer];
+
+  #[test]
+  fn roles_allow_actions_of_the_matrix() {
+    for (action, allowed) in MATRIX {
+      for (role, allowed) in ROLES.iter().zip(allowed) {
+        assert_eq!(role.can(action), allowed, "{:?} can {:?}", role, action);
+      }
+    }
+  }
+}
@@ -1,6 +1,29 @@
+use crate::directories::Directories;
 use rand::{Rng, distributions::Alphanumeric, thread_rng};
-use std::fs;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
 
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+/// Environment variable containing the secret itself.
+pub const SECRET_ENV: &str = "GALERA_SECRET";
+
+/// Environment variable containing a path to a file with the secret, e.g. a mounted container secret.
+pub const SECRET_FILE_ENV: &str = "GALERA_SECRET_FILE";
+
+/// Secret used for signing bearer tokens.\
+/// It's read once at startup and then managed by Rocket, so it can be accessed as a request guard.
+/// # Example
+/// ```
+/// #[get("/data")]
+/// pub async fn get_data(secret: &State<Secret>) -> String {
+///   Claims::new(1).encode(secret).unwrap().encoded_claims()
+/// }
+/// ```
+#[derive(Clone)]
 pub struct Secret {
   key: String,
 }
@@ -25,14 +48,82 @@ impl Secret {
     .unwrap()
   }
 
-  /// Reads content of a secret.key file.
-  // TODO: check for write and read permissions
-  pub fn read() -> Result<String, std::io::Error> {
-    let path = "secret.key";
-    fs::read_to_string(path)
+  /// Returns the path of the secret.key file in the config directory.
+  pub fn path() -> io::Result<PathBuf> {
+    let directories = Directories::new()
+      .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "Config directory is unknown."))?;
+
+    Ok(directories.config().join("secret.key"))
+  }
+
+  /// Checks whether the secret is provided using environment variables instead of the secret.key file.
+  pub fn is_external() -> bool {
+    env::var_os(SECRET_ENV).is_some() || env::var_os(SECRET_FILE_ENV).is_some()
+  }
+
+  /// Reads the secret.\
+  /// The `GALERA_SECRET` and `GALERA_SECRET_FILE` environment variables take precedence over the secret.key file
+  /// in the config directory. The secret.key file must not be readable by other users.
+  pub fn read() -> Result<Secret, io::Error> {
+    if let Ok(key) = env::var(SECRET_ENV) {
+      return Secret::from_key(key);
+    }
+
+    if let Ok(path) = env::var(SECRET_FILE_ENV) {
+      return Secret::from_key(fs::read_to_string(path)?);
+    }
+
+    let path = Secret::path()?;
+    Secret::check_permissions(&path)?;
+
+    Secret::from_key(fs::read_to_string(path)?)
+  }
+
+  /// Reads the secret.key file from the current working directory, where it was stored by older versions.
+  pub fn read_legacy() -> Result<Secret, io::Error> {
+    Secret::from_key(fs::read_to_string("secret.key")?)
+  }
+
+  /// Removes the secret.key file from the current working directory once it was moved to the config directory.
+  pub fn remove_legacy() -> io::Result<()> {
+    fs::remove_file("secret.key")
   }
 
-  /// Writes a secret to the secret.key file.
+  fn from_key(key: String) -> io::Result<Secret> {
+    // mounted secrets often end with a newline
+    let key = key.trim_end().to_string();
+
+    if key.is_empty() {
+      return Err(io::Error::new(ErrorKind::InvalidData, "Secret is empty."));
+    }
+
+    Ok(Secret { key })
+  }
+
+  /// Checks that the file isn't readable by other users.
+  #[cfg(unix)]
+  fn check_permissions(path: &Path) -> io::Result<()> {
+    let mode = fs::metadata(path)?.permissions().mode();
+
+    if mode & 0o004 != 0 {
+      return Err(io::Error::new(ErrorKind::PermissionDenied, format!("{:?} is readable by other users, change its permissions to 600.", path)));
+    }
+
+    Ok(())
+  }
+
+  #[cfg(not(unix))]
+  fn check_permissions(_path: &Path) -> io::Result<()> {
+    Ok(())
+  }
+
+  /// Returns the key.
+  pub fn key(&self) -> &[u8] {
+    self.key.as_bytes()
+  }
+
+  /// Writes a secret to the secret.key file in the config directory.\
+  /// The file is readable and writable only by its owner.
   /// # Example
   /// ```
   /// // creates a new secret
@@ -41,9 +132,14 @@ impl Secret {
   /// // writes it to the disk
   /// my_secret.write();
   /// ```
-  pub fn write(self) -> std::io::Result<()> {
-    let path = "secret.key";
-    fs::write(path, self.key)
+  pub fn write(self) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    options.open(Secret::path()?)?.write_all(self.key.as_bytes())
   }
 
   /// Creates a new secret
@@ -14,24 +14,35 @@ use rocket_okapi::{
   response::OpenApiResponder,
 };
 use serde::{Serialize, Deserialize};
-use sha2::Digest;
-use crate::db::{albums::{select_album, select_album_share_link_by_uuid}};
+use crate::background::Background;
+use crate::db::{albums::{delete_album_share_link_accesses_before, insert_album_share_link_access, select_album, select_album_share_link_by_uuid, update_album_share_link_password}};
+use crate::login_limit::LoginLimiter;
+use crate::models::NewAlbumShareLinkAccess;
 use crate::DbConn;
+use super::password::{self, PasswordCheck, VerifiedPasswords};
+use chrono::{Duration, Utc};
+use rocket::tokio::time;
 use std::str;
 
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct SharedAlbumLinkSecurity {
   album_share_link_uuid: String,
-  password: Option<String>,
+  share_link_uuid: String,
+  /// ID of the shared album.
+  #[serde(skip)]
+  album_id: i32,
 }
 
-/// Encrypts the password.
-// TODO: deduplicate later
-pub fn hash_password(password: String) -> String {
-  let mut hasher = sha2::Sha512::new();
-  hasher.update(password);
-  // {:x} means format as hexadecimal
-  format!("{:X}", hasher.finalize())
+impl SharedAlbumLinkSecurity {
+  /// Returns the UUID of the share link used for authorization.
+  pub fn share_link_uuid(&self) -> &str {
+    &self.share_link_uuid
+  }
+
+  /// Returns the ID of the album the share link grants access to.
+  pub fn album_id(&self) -> i32 {
+    self.album_id
+  }
 }
 
 #[rocket::async_trait]
@@ -67,10 +78,14 @@ impl<'r> FromRequest<'r> for SharedAlbumLinkSecurity {
 
     let split: Vec<&str> = decoded_str.unwrap().split(':').collect();
     let album_share_link_uuid = split[0].to_string();
-    let password_unfiltered = split[1].to_string();
-    let hashed_password =  match password_unfiltered.len() {
-      0 => None,
-      _ => Some(hash_password(password_unfiltered))
+    let password = split[1];
+
+    let verified_passwords = match request.rocket().state::<VerifiedPasswords>() {
+      Some(verified_passwords) => verified_passwords,
+      None => {
+        error!("VerifiedPasswords are not managed by Rocket.");
+        return Outcome::Failure((Status::InternalServerError, ()));
+      }
     };
 
     let album_share_link_result = select_album_share_link_by_uuid(&conn, album_share_link_uuid).await;
@@ -81,15 +96,49 @@ impl<'r> FromRequest<'r> for SharedAlbumLinkSecurity {
 
     let album_share_link = album_share_link_option.unwrap();
 
-    // TODO: change select_album() to return Result<Option<Album>>; change status when this happens
     let album = select_album(&conn, album_share_link.album_id).await;
+    if album.is_err() { return Outcome::Failure((Status::InternalServerError, ())) }
+
+    let album = album.unwrap();
     if album.is_none() { return Outcome::Failure((Status::Unauthorized, ())) }
 
-    let album_share_link_security = SharedAlbumLinkSecurity { album_share_link_uuid: album.unwrap().link, password: hashed_password };
+    let login_limiter = match request.rocket().state::<LoginLimiter>() {
+      Some(login_limiter) => login_limiter,
+      None => {
+        error!("LoginLimiter is not managed by Rocket.");
+        return Outcome::Failure((Status::InternalServerError, ()));
+      }
+    };
 
-    if album_share_link_security.password != album_share_link.password { return Outcome::Failure((Status::Unauthorized, ())) }
+    let address = request.client_ip();
+    if login_limiter.share_link_locked(address, &album_share_link.uuid).is_some() {
+      return Outcome::Failure((Status::TooManyRequests, ()));
+    }
 
-    Outcome::Success(album_share_link_security)
+    let check = match &album_share_link.password {
+      Some(hash) => verified_passwords.verify(password, hash).await,
+      None if password.is_empty() => PasswordCheck::Valid,
+      None => PasswordCheck::Invalid,
+    };
+
+    let user_agent = request.headers().get_one("User-Agent").map(str::to_owned);
+    let access = NewAlbumShareLinkAccess::new(album_share_link.id, address.map(|address| address.to_string()), user_agent, check.is_valid());
+    if insert_album_share_link_access(&conn, access).await.is_err() {
+      error!("Access to share link {} couldn't be recorded.", album_share_link.uuid);
+    }
+
+    if !check.is_valid() {
+      login_limiter.share_link_failed(address, &album_share_link.uuid);
+      return Outcome::Failure((Status::Unauthorized, ()));
+    }
+
+    if check == PasswordCheck::ValidLegacy && update_album_share_link_password(&conn, album_share_link.id, password::hash_password(password).await).await.is_err() {
+      error!("Password of share link {} couldn't be rehashed.", album_share_link.uuid);
+    }
+
+    let album = album.unwrap();
+
+    Outcome::Success(SharedAlbumLinkSecurity { album_share_link_uuid: album.link, share_link_uuid: album_share_link.uuid, album_id: album.id })
   }
 }
 
@@ -128,3 +177,23 @@ impl<'a, 'r> OpenApiFromRequest<'a> for SharedAlbumLinkSecurity {
     ))
   }
 }
+
+/// Deletes accesses through share links older than `retention_days` once a day.
+pub async fn prune_accesses(background: Background, retention_days: u64) {
+  loop {
+    match background.conn().await {
+      Some(conn) => {
+        let before = (Utc::now() - Duration::days(retention_days as i64)).naive_utc();
+
+        match delete_album_share_link_accesses_before(&conn, before).await {
+          Ok(0) => {},
+          Ok(deleted) => info!("Deleted {} accesses through share links older than {} days.", deleted, retention_days),
+          Err(err) => error!("Old accesses through share links couldn't be deleted: {}", err),
+        }
+      },
+      None => error!("Deletion of old accesses through share links was skipped as no database connection is available."),
+    }
+
+    time::sleep(std::time::Duration::from_secs(24 * 3600)).await;
+  }
+}
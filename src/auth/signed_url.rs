@@ -0,0 +1,70 @@
+use chrono::{Duration, Utc};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use crate::auth::secret::Secret;
+use crate::auth::token::hash_token;
+
+/// Token granting access to a single path of a shared album without the authorization header.\
+/// Used by clients which can only request plain URLs (TVs, kiosks, photo frames).
+///
+/// The token is bound to the password of the share link, so it stops working when the password is changed
+/// (see `SignedUrl::matches_password()`).
+/// # Example
+/// ```
+/// let token = SignedUrl::new(share_link_uuid, share_link_password, format!("/media/{}", media_uuid), Duration::hours(6)).encode(&secret)?;
+///
+/// let signed_url = SignedUrl::verify(&token, &format!("/media/{}", media_uuid), &secret);
+/// ```
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignedUrl {
+  /// expiration time
+  exp: i64,
+  /// UUID of the share link the access is granted by
+  share_link_uuid: String,
+  /// path the token is valid for
+  path: String,
+  /// fingerprint of the password hash of the share link, see `SignedUrl::password_fingerprint()`
+  password: String,
+}
+
+impl SignedUrl {
+  /// Creates a token for the share link with the given password hash.
+  pub fn new(share_link_uuid: String, password_hash: Option<&str>, path: String, expires_in: Duration) -> Self {
+    Self {
+      exp: (Utc::now() + expires_in).timestamp(),
+      share_link_uuid,
+      path,
+      password: SignedUrl::password_fingerprint(password_hash),
+    }
+  }
+
+  /// Returns the hash of the password hash, so the token, which anyone can decode, doesn't contain the hash itself;
+  /// empty for share links without a password.
+  fn password_fingerprint(password_hash: Option<&str>) -> String {
+    password_hash.map(hash_token).unwrap_or_default()
+  }
+
+  pub fn share_link_uuid(&self) -> &str {
+    &self.share_link_uuid
+  }
+
+  /// Checks that the password of the share link wasn't changed since the token was created.
+  pub fn matches_password(&self, password_hash: Option<&str>) -> bool {
+    self.password == SignedUrl::password_fingerprint(password_hash)
+  }
+
+  /// Encodes the token, so it can be used as the `token` query parameter.
+  pub fn encode(&self, secret: &Secret) -> Result<String, jsonwebtoken::errors::Error> {
+    jsonwebtoken::encode(&Header::new(Algorithm::HS512), self, &EncodingKey::from_secret(secret.key()))
+  }
+
+  /// Checks the token for the requested path.\
+  /// Returns the token when it's valid and not expired; its password must still be checked against the share link.
+  pub fn verify(token: &str, path: &str, secret: &Secret) -> Option<SignedUrl> {
+    let decoded = jsonwebtoken::decode::<SignedUrl>(token, &DecodingKey::from_secret(secret.key()), &Validation::new(Algorithm::HS512)).ok()?;
+
+    if decoded.claims.path != path { return None }
+
+    Some(decoded.claims)
+  }
+}
@@ -1,4 +1,3 @@
-use std::convert::TryFrom;
 use okapi::openapi3::{
   Object, Responses, SecurityRequirement,
   SecurityScheme, SecuritySchemeData,
@@ -9,11 +8,13 @@ use rocket::{
   request::{FromRequest, Request, Outcome},
 };
 use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
 use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation};
 use uuid::Uuid;
 use rocket::http::Status;
 use crate::db::{self, tokens::{insert_access_token, insert_refresh_token, select_refresh_token_expiration}, users};
 use crate::DbConn;
+use crate::auth::login::LoginClient;
 use crate::auth::secret::Secret;
 
 use rocket_okapi::{
@@ -21,7 +22,6 @@ use rocket_okapi::{
   request::{OpenApiFromRequest, RequestHeaderInput},
   response::OpenApiResponder,
 };
-use anyhow::{self, Context};
 
 /// Bearer token\
 /// used as a Request guard
@@ -42,6 +42,9 @@ pub struct Claims {
   iat: i64,
   /// ID of a user
   pub user_id: i32,
+  /// The user is an administrator of the instance; the `Admin` guard checks the role again.
+  #[serde(default)]
+  pub is_admin: bool,
   /// Refresh token - used to refresh access token
   refresh_token: String,
   /// Access token - used to access data
@@ -52,9 +55,9 @@ pub struct Claims {
 /// # Example
 /// decode an encoded bearer token
 /// ```
-/// let encoded_token = Claims::new(1).encode().unwrap();
+/// let encoded_token = Claims::new(1, false).encode(&secret).unwrap();
 ///
-/// let decoded_token = encoded_token.decode();
+/// let decoded_token = encoded_token.decode(&secret);
 /// ```
 #[derive(Serialize, Deserialize, JsonSchema, Clone)]
 pub struct ClaimsEncoded {
@@ -68,10 +71,8 @@ impl ClaimsEncoded {
   }
 
   /// Decodes a bearer token.
-  pub fn decode(self) -> Result<TokenData<Claims>, jsonwebtoken::errors::Error> {
-    let secret = Secret::read().context("Secret couldn't be read.").unwrap();
-
-    let decoded = jsonwebtoken::decode::<Claims>(self.encoded_claims.as_str(), &DecodingKey::from_secret(secret.as_ref()), &Validation::new(Algorithm::HS512));
+  pub fn decode(self, secret: &Secret) -> Result<TokenData<Claims>, jsonwebtoken::errors::Error> {
+    let decoded = jsonwebtoken::decode::<Claims>(self.encoded_claims.as_str(), &DecodingKey::from_secret(secret.key()), &Validation::new(Algorithm::HS512));
 
     Ok(decoded?)
   }
@@ -81,27 +82,23 @@ impl ClaimsEncoded {
   }
 }
 
-impl TryFrom<&str> for Claims {
-  type Error = jsonwebtoken::errors::Error;
-
+impl Claims {
   /// Tries to convert encoded bearer token presented as a string to a Claims struct.\
   /// Will return error if token can't be decoded.
   /// # Example
   /// ```
   /// let my_bearer_string = "<encoded_bearer>";
   ///
-  /// let result = Claims::try_from(my_bearer_string)?;
+  /// let result = Claims::from_encoded(my_bearer_string, &secret)?;
   /// ```
-  fn try_from(token: &str) -> Result<Claims, Self::Error> {
+  pub fn from_encoded(token: &str, secret: &Secret) -> Result<Claims, jsonwebtoken::errors::Error> {
     let encoded = ClaimsEncoded {
       encoded_claims: token.to_owned(),
     };
 
-    Ok(encoded.decode()?.claims)
+    Ok(encoded.decode(secret)?.claims)
   }
-}
 
-impl Claims {
   /// Checks the exp field of bearer token for its expiration.
   fn is_expired(&self) -> bool {
     let current_time = Utc::now().timestamp();
@@ -111,7 +108,7 @@ impl Claims {
 
   /// Checks whether the refresh token is expired or not.
   pub async fn is_refresh_token_expired(&self, conn: &DbConn) -> bool {
-    let refresh_token_exp = select_refresh_token_expiration(conn, self.refresh_token.clone()).await;
+    let refresh_token_exp = select_refresh_token_expiration(conn, self.refresh_token.clone()).await.ok().flatten();
     if refresh_token_exp.is_none() {
       return true;
     }
@@ -125,23 +122,21 @@ impl Claims {
   pub async fn is_valid(&self, conn: DbConn) -> bool {
     // expiration
     !self.is_expired()
-    // valid user
-    && users::get_user_username(&conn, self.user_id).await.is_some()
-    // TODO: other checks
-    // valid refresh_token
-    // && db::users::(&conn, user_id,)
+    // valid user which isn't disabled
+    && users::is_user_active(&conn, self.user_id).await.unwrap_or(false)
+    // the session wasn't revoked
+    && !self.is_refresh_token_expired(&conn).await
   }
 
   /// Encodes a bearer token.
   /// # Example
   /// ```
-  /// let token = Claims::new(1).encode();
+  /// let token = Claims::new(1, false).encode(&secret);
   /// ```
-  pub fn encode(self) -> anyhow::Result<ClaimsEncoded> {
+  pub fn encode(self, secret: &Secret) -> anyhow::Result<ClaimsEncoded> {
     let header = Header::new(Algorithm::HS512);
-    let secret = Secret::read()?;
 
-    let encoded_claims = jsonwebtoken::encode(&header, &self, &EncodingKey::from_secret(secret.as_bytes()));
+    let encoded_claims = jsonwebtoken::encode(&header, &self, &EncodingKey::from_secret(secret.key()));
 
     // TODO: better error messages
     if let Err(err) = encoded_claims {
@@ -153,11 +148,11 @@ impl Claims {
 
   /// Generates a new bearer token.
   /// # Example
-  /// This will generate a new bearer token for user with ID 1.
+  /// This will generate a new bearer token for user with ID 1, who isn't an administrator.
   /// ```
-  /// let new_bearer_token = Claims::new(1);
+  /// let new_bearer_token = Claims::new(1, false);
   /// ```
-  pub fn new(user_id: i32) -> Claims {
+  pub fn new(user_id: i32, is_admin: bool) -> Claims {
     let current_time = Utc::now().timestamp();
 
     // 15 mins in seconds
@@ -167,22 +162,23 @@ impl Claims {
       exp: current_time + expiraton_time,
       iat: current_time,
       user_id,
+      is_admin,
       refresh_token: Claims::generate_random_string(),
       access_token: Claims::generate_random_string()
     }
   }
 
-  /// Makes a new token from an old one.
+  /// Makes a new token from an old one; `is_admin` is the current role of the user, as it could have changed.
   /// # Example
   /// This will recreate a bearer token for user with ID 1.
   /// ```
-  /// let bearer_token = Claims::new(1);
+  /// let bearer_token = Claims::new(1, false);
   ///
-  /// let new_token = Claims::from_existing(&bearer_token);
+  /// let new_token = Claims::from_existing(&bearer_token, false);
   /// ```
 
-  pub fn from_existing(token: &Claims) -> Claims {
-    let mut new_token = Claims::new(token.user_id);
+  pub fn from_existing(token: &Claims, is_admin: bool) -> Claims {
+    let mut new_token = Claims::new(token.user_id, is_admin);
     new_token.refresh_token = token.refresh_token.clone();
 
     new_token
@@ -202,43 +198,43 @@ impl Claims {
   /// # Example
   /// Adds the `refresh_token` of a bearer token for user with ID 1 to the database.
   /// ```
-  /// let bearer_token = Claims::new(1);
+  /// let bearer_token = Claims::new(1, false);
   ///
-  /// bearer_token.add_refresh_token_to_db(conn)
+  /// bearer_token.add_refresh_token_to_db(conn, LoginClient::default())
   /// ```
-  pub async fn add_refresh_token_to_db(&self, conn: &DbConn) -> Option<i32> {
-    insert_refresh_token(conn, self.user_id, self.refresh_token()).await;
+  pub async fn add_refresh_token_to_db(&self, conn: &DbConn, client: LoginClient) -> Option<i32> {
+    insert_refresh_token(conn, self.user_id, self.refresh_token(), client).await.ok()?;
 
-    Some(db::general::get_last_insert_id(conn).await?)
+    db::general::get_last_insert_id(conn).await.ok()
   }
 
   /// Adds a new access token to the database.
   /// # Example
   /// Adds the `access_token` of a bearer token for user with ID 1 to the database.
   /// ```
-  /// let bearer_token = Claims::new(1);
+  /// let bearer_token = Claims::new(1, false);
   ///
-  /// let refresh_token_id = bearer_token.add_refresh_token_to_db(conn).await?;
+  /// let refresh_token_id = bearer_token.add_refresh_token_to_db(conn, LoginClient::default()).await?;
   /// bearer_token.add_access_token_to_db(conn, refresh_token_id).await?;
   /// ```
   pub async fn add_access_token_to_db(&self, conn: &DbConn, refresh_token_id: i32) -> Option<i32> {
-    insert_access_token(conn, refresh_token_id, self.access_token()).await;
+    insert_access_token(conn, refresh_token_id, self.access_token()).await.ok()?;
 
-    Some(db::general::get_last_insert_id(conn).await?)
+    db::general::get_last_insert_id(conn).await.ok()
   }
 
   /// Deletes obsolete access tokens for a given refresh token ID from the database.
   /// # Example
   /// This will create a bearer token and refresh it.
   /// ```
-  /// let bearer_token = Claims::new(1);
+  /// let bearer_token = Claims::new(1, false);
   ///
   /// // add refresh and access tokens to db
-  /// let refresh_token_id = bearer_token.add_refresh_token_to_db(conn).await?;
+  /// let refresh_token_id = bearer_token.add_refresh_token_to_db(conn, LoginClient::default()).await?;
   /// bearer_token.add_access_token_to_db(conn, refresh_token_id).await?;
   ///
   /// // create a new token from the previous one; only the refresh_token will be the same
-  /// let new_token = Claims::from_existing(&bearer_token);
+  /// let new_token = Claims::from_existing(&bearer_token, false);
   ///
   /// // remove obsolete access tokens
   /// Claims::delete_obsolete_access_tokens(&conn, refresh_token_id).await;
@@ -258,6 +254,15 @@ impl Claims {
   }
 }
 
+/// Hashes a refresh or access token; only hashes are stored, so a leaked database can't be used to sign in.\
+/// Tokens are random UUIDs, so they can't be guessed from their hashes and don't need a salt or the secret
+/// (which would sign everyone out when it changes).
+pub fn hash_token(token: &str) -> String {
+  let mut hasher = Sha256::new();
+  hasher.update(token);
+  format!("{:x}", hasher.finalize())
+}
+
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for Claims {
   type Error = ();
@@ -283,7 +288,15 @@ impl<'r> FromRequest<'r> for Claims {
     }
 
     let bearer_token_encoded: &str = authorization_header[0][6..authorization_header[0].len()].trim();
-    let bearer_token_decoded = Claims::try_from(bearer_token_encoded);
+    let secret = match request.rocket().state::<Secret>() {
+      Some(secret) => secret,
+      None => {
+        error!("Secret is not managed by Rocket.");
+        return Outcome::Failure((Status::InternalServerError, ()));
+      }
+    };
+
+    let bearer_token_decoded = Claims::from_encoded(bearer_token_encoded, secret);
 
     if let Ok(claims) = bearer_token_decoded {
       if claims.is_valid(conn).await { return Outcome::Success(claims) };
@@ -339,6 +352,58 @@ impl<'a, 'r> OpenApiFromRequest<'a> for Claims {
   }
 }
 
+/// Bearer token of an administrator of the instance\
+/// used as a Request guard; other users get 403.
+/// # Example
+/// Only administrators will be able to access data on this endpoint.
+/// ```
+/// #[get("/admin/data")]
+/// pub async fn get_data(admin: Admin, conn: DbConn) -> Json<Vec<Data>> {
+///   Json(db::request_data(&conn).await)
+/// }
+/// ```
+pub struct Admin {
+  pub claims: Claims,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+  type Error = ();
+
+  /// Implements Request guard for Admin.\
+  /// The role is checked in the database too, so demoted administrators lose access before their token expires.
+  async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+    let claims = match request.guard::<Claims>().await {
+      Outcome::Success(claims) => claims,
+      Outcome::Failure(failure) => return Outcome::Failure(failure),
+      Outcome::Forward(forward) => return Outcome::Forward(forward),
+    };
+
+    if !claims.is_admin { return Outcome::Failure((Status::Forbidden, ())) }
+
+    let conn = request.guard::<DbConn>().await.unwrap();
+
+    match users::is_user_admin(&conn, claims.user_id).await {
+      Ok(true) => Outcome::Success(Admin { claims }),
+      Ok(false) => Outcome::Failure((Status::Forbidden, ())),
+      Err(err) => {
+        error!("Role of user {} couldn't be checked: {}", claims.user_id, err);
+        Outcome::Failure((Status::InternalServerError, ()))
+      },
+    }
+  }
+}
+
+impl<'a, 'r> OpenApiFromRequest<'a> for Admin {
+  fn from_request_input(
+    gen: &mut OpenApiGenerator,
+    name: String,
+    required: bool,
+  ) -> rocket_okapi::Result<RequestHeaderInput> {
+    Claims::from_request_input(gen, name, required)
+  }
+}
+
 impl<'a, 'r> OpenApiFromRequest<'a> for DbConn {
   fn from_request_input(
     _gen: &mut OpenApiGenerator,
@@ -0,0 +1,34 @@
+use crate::DbConn;
+use diesel::MysqlConnection;
+use rocket::{Build, Orbit, Rocket};
+use rocket_sync_db_pools::ConnectionPool;
+
+/// Provides database connections to background tasks, which can't use request guards.
+/// # Example
+/// ```
+/// let background = Background::new(rocket).await.expect("database pool");
+///
+/// rocket::tokio::spawn(async move {
+///   let conn: Option<DbConn> = background.conn().await;
+/// });
+/// ```
+pub struct Background {
+  // never launched, it only manages the connection pool shared with the server
+  rocket: Rocket<Build>,
+}
+
+impl Background {
+  /// Creates a new instance sharing the connection pool of the running server.
+  pub async fn new(rocket: &Rocket<Orbit>) -> Option<Self> {
+    let pool = ConnectionPool::<DbConn, MysqlConnection>::get_pool(rocket).await?;
+
+    Some(Self {
+      rocket: rocket::custom(rocket.figment().clone()).manage(pool),
+    })
+  }
+
+  /// Gets a connection from the pool.
+  pub async fn conn(&self) -> Option<DbConn> {
+    DbConn::get_one(&self.rocket).await
+  }
+}
@@ -0,0 +1,254 @@
+//! Bandwidth limits of share links.
+//!
+//! Public share links can be hot-linked, so their downloads can drain the bandwidth of the server.
+//! Owners can limit the bandwidth of each link (`bandwidth_limit`), which is shared by all its visitors,
+//! and the administrator can limit the bandwidth of all share links together (`share_link_bandwidth_limit`).
+//!
+//! Limits are token buckets around the response body; a bucket is refilled at the limit and holds
+//! at most one second of data, so short bursts aren't throttled. Bytes which had to wait for a bucket
+//! are counted as throttled.
+
+use rocket::tokio::io::{self, AsyncRead, AsyncSeek, ReadBuf, SeekFrom};
+use rocket::tokio::time::{self, Sleep};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// Number of bytes a throttled stream waits for, so it doesn't wake up for every few bytes.
+const MIN_CHUNK: f64 = 16.0 * 1024.0;
+
+#[derive(Debug)]
+struct TokenBucket {
+  /// Bytes per second.
+  rate: f64,
+  /// Available bytes; negative when more was read than allowed.
+  tokens: f64,
+  updated: Instant,
+}
+
+impl TokenBucket {
+  fn new(kibibytes_per_second: u64) -> Self {
+    let rate = (kibibytes_per_second.max(1) * 1024) as f64;
+
+    Self { rate, tokens: rate, updated: Instant::now() }
+  }
+
+  fn set_limit(&mut self, kibibytes_per_second: u64) {
+    self.rate = (kibibytes_per_second.max(1) * 1024) as f64;
+  }
+
+  fn refill(&mut self) {
+    let now = Instant::now();
+    self.tokens = (self.tokens + now.duration_since(self.updated).as_secs_f64() * self.rate).min(self.rate);
+    self.updated = now;
+  }
+
+  /// Returns the number of bytes which can be read now, or the time to wait for them.
+  fn available(&mut self) -> Result<usize, Duration> {
+    self.refill();
+
+    let wanted = MIN_CHUNK.min(self.rate);
+    if self.tokens >= wanted { return Ok(self.tokens as usize) }
+
+    Err(Duration::from_secs_f64((wanted - self.tokens) / self.rate))
+  }
+
+  fn consume(&mut self, bytes: usize) {
+    self.tokens -= bytes as f64;
+  }
+}
+
+type SharedBucket = Arc<Mutex<TokenBucket>>;
+
+/// Buckets and counters of share links by their UUIDs.
+type LinkBuckets = HashMap<String, (Option<SharedBucket>, Arc<Counters>)>;
+
+/// Bandwidth statistics since the server started.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Default, Clone, Copy)]
+pub struct BandwidthStats {
+  /// Bytes sent through share links.
+  pub sent_bytes: u64,
+  /// Bytes which were delayed by a bandwidth limit.
+  pub throttled_bytes: u64,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+  sent_bytes: AtomicU64,
+  throttled_bytes: AtomicU64,
+}
+
+impl Counters {
+  fn add(&self, bytes: usize, throttled: bool) {
+    self.sent_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    if throttled { self.throttled_bytes.fetch_add(bytes as u64, Ordering::Relaxed); }
+  }
+
+  fn stats(&self) -> BandwidthStats {
+    BandwidthStats {
+      sent_bytes: self.sent_bytes.load(Ordering::Relaxed),
+      throttled_bytes: self.throttled_bytes.load(Ordering::Relaxed),
+    }
+  }
+}
+
+/// Buckets and statistics of share links; managed by Rocket.\
+/// Clones share the buckets and statistics, e.g. with the management listener (see `management`).
+#[derive(Debug, Clone)]
+pub struct BandwidthLimiter {
+  global: Option<SharedBucket>,
+  links: Arc<Mutex<LinkBuckets>>,
+  total: Arc<Counters>,
+}
+
+impl BandwidthLimiter {
+  /// Creates a limiter with the limit of all share links in KiB/s; zero means no limit.
+  pub fn new(global_limit: u64) -> Self {
+    Self {
+      global: (global_limit != 0).then(|| Arc::new(Mutex::new(TokenBucket::new(global_limit)))),
+      links: Arc::new(Mutex::new(HashMap::new())),
+      total: Arc::new(Counters::default()),
+    }
+  }
+
+  /// Returns the bandwidth of a stream of the share link.\
+  /// `limit` is the current limit of the link in KiB/s.
+  pub fn share_link(&self, share_link_uuid: &str, limit: Option<i32>) -> Bandwidth {
+    let limit = limit.filter(|limit| *limit > 0).map(|limit| limit as u64);
+
+    let mut links = self.links.lock().unwrap();
+    let (bucket, counters) = links.entry(share_link_uuid.to_owned()).or_insert_with(|| (None, Arc::new(Counters::default())));
+
+    // the link could have been updated since its last stream
+    match (limit, bucket.as_ref()) {
+      (Some(limit), Some(existing)) => existing.lock().unwrap().set_limit(limit),
+      (Some(limit), None) => *bucket = Some(Arc::new(Mutex::new(TokenBucket::new(limit)))),
+      (None, _) => *bucket = None,
+    }
+
+    Bandwidth {
+      buckets: self.global.iter().chain(bucket.iter()).cloned().collect(),
+      counters: vec![self.total.clone(), counters.clone()],
+    }
+  }
+
+  /// Returns statistics of all share links together.
+  pub fn stats(&self) -> BandwidthStats {
+    self.total.stats()
+  }
+
+  /// Returns statistics of the share link.
+  pub fn link_stats(&self, share_link_uuid: &str) -> BandwidthStats {
+    self.links.lock().unwrap()
+      .get(share_link_uuid)
+      .map(|(_, counters)| counters.stats())
+      .unwrap_or_default()
+  }
+}
+
+/// Limits and statistics applied to one response.
+#[derive(Debug, Default)]
+pub struct Bandwidth {
+  buckets: Vec<SharedBucket>,
+  counters: Vec<Arc<Counters>>,
+}
+
+impl Bandwidth {
+  /// Bandwidth of responses which aren't limited nor counted.
+  pub fn unlimited() -> Self {
+    Self::default()
+  }
+
+  fn available(&self) -> Result<usize, Duration> {
+    let mut available = usize::MAX;
+    let mut wait = Duration::ZERO;
+
+    for bucket in &self.buckets {
+      match bucket.lock().unwrap().available() {
+        Ok(bytes) => available = available.min(bytes),
+        Err(duration) => wait = wait.max(duration),
+      }
+    }
+
+    if wait > Duration::ZERO { return Err(wait) }
+
+    Ok(available)
+  }
+
+  fn consume(&self, bytes: usize, throttled: bool) {
+    for bucket in &self.buckets {
+      bucket.lock().unwrap().consume(bytes);
+    }
+
+    for counters in &self.counters {
+      counters.add(bytes, throttled);
+    }
+  }
+}
+
+/// Reader sending data only as fast as its bandwidth allows.
+pub struct Throttled<R> {
+  inner: R,
+  bandwidth: Bandwidth,
+  sleep: Option<Pin<Box<Sleep>>>,
+  /// The stream waited for a bucket since the last read.
+  throttled: bool,
+}
+
+impl<R> Throttled<R> {
+  pub fn new(inner: R, bandwidth: Bandwidth) -> Self {
+    Self { inner, bandwidth, sleep: None, throttled: false }
+  }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Throttled<R> {
+  fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+    loop {
+      if let Some(sleep) = self.sleep.as_mut() {
+        if sleep.as_mut().poll(cx).is_pending() { return Poll::Pending }
+
+        self.sleep = None;
+      }
+
+      match self.bandwidth.available() {
+        Ok(available) => {
+          let mut limited = buf.take(available.max(1));
+          let result = Pin::new(&mut self.inner).poll_read(cx, &mut limited);
+          let read = limited.filled().len();
+
+          // the bytes were written to the unfilled part of `buf`
+          unsafe { buf.assume_init(read); }
+          buf.advance(read);
+
+          if read > 0 {
+            let throttled = self.throttled;
+            self.bandwidth.consume(read, throttled);
+            self.throttled = false;
+          }
+
+          return result;
+        },
+        Err(wait) => {
+          self.throttled = true;
+          self.sleep = Some(Box::pin(time::sleep(wait)));
+        },
+      }
+    }
+  }
+}
+
+impl<R: AsyncSeek + Unpin> AsyncSeek for Throttled<R> {
+  fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+    Pin::new(&mut self.inner).start_seek(position)
+  }
+
+  fn poll_complete(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+    Pin::new(&mut self.inner).poll_complete(cx)
+  }
+}
@@ -0,0 +1,129 @@
+//! List of banned (common or breached) passwords.
+//!
+//! The list is stored as a bloom filter, so even lists with hundreds of millions of passwords
+//! take only a fraction of their size in memory. Only plaintext lists are supported; hash lists
+//! (e.g. SHA-1 hashes from Have I Been Pwned) can't be compiled.
+//! False positives are possible (about 1 in 1000 allowed passwords is rejected), false negatives aren't.
+//!
+//! The filter is compiled from a text file with one password per line using `galera-cli banned-passwords`.
+//!
+//! # File format
+//! 1. magic bytes `GLRBLOOM`
+//! 2. number of hash functions (u32, little endian)
+//! 3. number of bits (u64, little endian)
+//! 4. the bits
+
+use sha2::{Digest, Sha512};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"GLRBLOOM";
+
+/// Probability of false positives the filter is compiled for.
+const FALSE_POSITIVE_RATE: f64 = 0.001;
+
+/// Bloom filter of banned passwords.
+#[derive(Debug, Default)]
+pub struct BannedPasswords {
+  hashes: u32,
+  bits: Vec<u8>,
+}
+
+impl BannedPasswords {
+  /// Creates an empty list, nothing is banned.
+  pub fn empty() -> Self {
+    Self::default()
+  }
+
+  /// Creates a filter sized for `count` passwords.
+  fn with_capacity(count: usize) -> Self {
+    let count = count.max(1) as f64;
+    let bit_count = (-count * FALSE_POSITIVE_RATE.ln() / 2f64.ln().powi(2)).ceil() as usize;
+    let hashes = ((bit_count as f64 / count) * 2f64.ln()).round().max(1.0) as u32;
+
+    Self { hashes, bits: vec![0; (bit_count + 7) / 8] }
+  }
+
+  /// Returns positions of the password's bits using double hashing.
+  fn positions(&self, password: &str) -> impl Iterator<Item = usize> {
+    let digest = Sha512::digest(password.as_bytes());
+    let h1 = u64::from_le_bytes(digest[0..8].try_into().unwrap());
+    let h2 = u64::from_le_bytes(digest[8..16].try_into().unwrap());
+    let bit_count = self.bits.len() as u64 * 8;
+
+    (0..self.hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % bit_count) as usize)
+  }
+
+  fn insert(&mut self, password: &str) {
+    for position in self.positions(password).collect::<Vec<usize>>() {
+      self.bits[position / 8] |= 1 << (position % 8);
+    }
+  }
+
+  /// Checks whether the password is banned.
+  pub fn contains(&self, password: &str) -> bool {
+    if self.bits.is_empty() { return false }
+
+    self.positions(password).all(|position| self.bits[position / 8] & (1 << (position % 8)) != 0)
+  }
+
+  /// Compiles a text file with one password per line into a filter file.\
+  /// Returns the number of compiled passwords.
+  /// # Example
+  /// ```
+  /// let count = BannedPasswords::compile(Path::new("passwords.txt"), Path::new("banned_passwords.bloom"))?;
+  /// ```
+  pub fn compile(list: &Path, output: &Path) -> io::Result<usize> {
+    // the file is read twice, so huge lists don't have to fit into memory
+    let count = BufReader::new(File::open(list)?).lines().count();
+
+    let mut filter = Self::with_capacity(count);
+    for line in BufReader::new(File::open(list)?).lines() {
+      let line = line?;
+      if !line.is_empty() { filter.insert(&line) }
+    }
+
+    let mut writer = BufWriter::new(File::create(output)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&filter.hashes.to_le_bytes())?;
+    writer.write_all(&(filter.bits.len() as u64 * 8).to_le_bytes())?;
+    writer.write_all(&filter.bits)?;
+    writer.flush()?;
+
+    Ok(count)
+  }
+
+  /// Reads a filter compiled by `BannedPasswords::compile()`.
+  pub fn read(path: &Path) -> io::Result<Self> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+      return Err(io::Error::new(io::ErrorKind::InvalidData, "not a banned passwords file"));
+    }
+
+    let mut hashes = [0; 4];
+    reader.read_exact(&mut hashes)?;
+    let hashes = u32::from_le_bytes(hashes);
+
+    // without hash functions every password would be rejected
+    if hashes == 0 {
+      return Err(io::Error::new(io::ErrorKind::InvalidData, "banned passwords file has no hash functions"));
+    }
+
+    let mut bit_count = [0; 8];
+    reader.read_exact(&mut bit_count)?;
+
+    let byte_count = (u64::from_le_bytes(bit_count) / 8) as usize;
+    let mut bits = Vec::with_capacity(byte_count);
+    reader.read_to_end(&mut bits)?;
+
+    if bits.len() != byte_count {
+      return Err(io::Error::new(io::ErrorKind::InvalidData, "banned passwords file is truncated"));
+    }
+
+    Ok(Self { hashes, bits })
+  }
+}
@@ -0,0 +1,28 @@
+//! Error bodies of requests which failed with a bare status: routes returning `Status`, failed request guards,
+//! panics and timeouts. They have the same body as `errors::ApiError`.
+
+use crate::errors::ErrorBody;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::{Catcher, Request};
+
+/// Returns all catchers, registered on both listeners.
+pub fn all() -> Vec<Catcher> {
+  catchers![internal_error, default_error]
+}
+
+/// Responds to requests which failed with 500, including panics of their handlers; the panics themselves
+/// are logged by the hook in `panics`.
+#[catch(500)]
+pub fn internal_error(request: &Request) -> Json<ErrorBody> {
+  error!("Request {} {} failed with an internal error", request.method(), request.uri());
+
+  Json(ErrorBody::new(Status::InternalServerError))
+}
+
+/// Responds to requests which failed with any other status, e.g. 404 or 503 when they weren't handled in time
+/// (see `timeouts`).
+#[catch(default)]
+pub fn default_error(status: Status, _request: &Request) -> (Status, Json<ErrorBody>) {
+  (status, Json(ErrorBody::new(status)))
+}
@@ -0,0 +1,143 @@
+//! Compression of API responses, see `CompressionPolicy`.
+//!
+//! Responses are compressed while they're sent, so streamed responses (e.g. NDJSON exports) don't have to fit in memory.
+//! Only responses whose content types compress well are compressed; media, thumbnails, zip archives
+//! and server-sent events (which must reach clients right away) are sent as they are.
+
+use crate::config::Config;
+use flate2::{write::GzEncoder, Compression as Level};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{ContentType, Header, Status};
+use rocket::tokio::io::{self, AsyncRead, ReadBuf};
+use rocket::{Request, Response};
+use std::io::Write;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Bytes read from the response at once.
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Compresses responses for clients which accept gzip.
+pub struct Compression;
+
+#[rocket::async_trait]
+impl Fairing for Compression {
+  fn info(&self) -> Info {
+    Info { name: "Response compression", kind: Kind::Response }
+  }
+
+  async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+    let policy = match request.rocket().state::<Config>() {
+      Some(config) if config.compression.enabled => config.compression,
+      _ => return,
+    };
+
+    // partial and empty responses can't be compressed; the body of an encoded response is already encoded
+    if response.status() != Status::Ok || response.headers().contains("Content-Encoding") { return }
+    if !response.content_type().map_or(false, |content_type| is_compressible(&content_type)) { return }
+
+    // the representation depends on the header even when this client doesn't accept gzip
+    response.adjoin_header(Header::new("Vary", "Accept-Encoding"));
+
+    if !accepts_gzip(request.headers().get("Accept-Encoding")) { return }
+
+    if let Some(size) = response.body_mut().size().await {
+      if size < policy.min_size { return }
+    }
+
+    let body = response.body_mut().take();
+    response.set_streamed_body(Gzipped::new(body, Level::new(policy.level.clamp(1, 9))));
+    response.set_header(Header::new("Content-Encoding", "gzip"));
+    // byte ranges would refer to the compressed body
+    response.remove_header("Accept-Ranges");
+  }
+}
+
+fn is_compressible(content_type: &ContentType) -> bool {
+  if content_type.is_event_stream() { return false }
+
+  content_type.top() == "text"
+    || content_type.is_json()
+    || content_type.is_javascript()
+    || content_type.is_xml()
+    || content_type.sub() == "x-ndjson"
+}
+
+/// Checks whether the `Accept-Encoding` header accepts gzip, either by its name or by `*`, with a nonzero quality.
+fn accepts_gzip<'a>(accept_encoding: impl Iterator<Item = &'a str>) -> bool {
+  accept_encoding
+    .flat_map(|header| header.split(','))
+    .any(|encoding| {
+      let mut parts = encoding.split(';').map(str::trim);
+      let name = parts.next().unwrap_or_default();
+
+      let quality = parts
+        .find_map(|parameter| parameter.strip_prefix("q="))
+        .and_then(|quality| quality.parse::<f32>().ok())
+        .unwrap_or(1.0);
+
+      (name.eq_ignore_ascii_case("gzip") || name == "*") && quality > 0.0
+    })
+}
+
+/// Reader compressing the body of a response with gzip.
+pub struct Gzipped<R> {
+  inner: R,
+  /// `None` once the whole body was compressed.
+  encoder: Option<GzEncoder<Vec<u8>>>,
+  input: Vec<u8>,
+  /// Compressed data which wasn't read yet, starting at `position`.
+  output: Vec<u8>,
+  position: usize,
+}
+
+impl<R> Gzipped<R> {
+  pub fn new(inner: R, level: Level) -> Self {
+    Self {
+      inner,
+      encoder: Some(GzEncoder::new(vec![], level)),
+      input: vec![0; CHUNK_SIZE],
+      output: vec![],
+      position: 0,
+    }
+  }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Gzipped<R> {
+  fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+    let this = &mut *self;
+
+    loop {
+      if this.position < this.output.len() {
+        let read = buf.remaining().min(this.output.len() - this.position);
+        buf.put_slice(&this.output[this.position..this.position + read]);
+        this.position += read;
+
+        return Poll::Ready(Ok(()));
+      }
+
+      let encoder = match this.encoder.as_mut() {
+        Some(encoder) => encoder,
+        None => return Poll::Ready(Ok(())),
+      };
+
+      let mut input = ReadBuf::new(&mut this.input);
+      match Pin::new(&mut this.inner).poll_read(cx, &mut input) {
+        Poll::Ready(Ok(())) => (),
+        Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+        Poll::Pending => return Poll::Pending,
+      }
+
+      this.output.clear();
+      this.position = 0;
+
+      if input.filled().is_empty() {
+        // the end of the body; the encoder writes the rest of the data and the gzip trailer
+        this.output = this.encoder.take().unwrap().finish()?;
+      } else {
+        encoder.write_all(input.filled())?;
+        this.output.append(encoder.get_mut());
+      }
+    }
+  }
+}
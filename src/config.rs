@@ -0,0 +1,565 @@
+use chrono::{DateTime, Utc};
+use crate::metadata::DEFAULT_FILENAME_DATE_PATTERNS;
+use rocket::figment::{Figment, Profile, providers::{Env, Format, Serialized, Toml}};
+use rand::distributions::Alphanumeric;
+use rand::seq::SliceRandom;
+use rand::{thread_rng, Rng};
+use rocket::http::Status;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+/// Seconds idle HTTP connections are kept open by default.\
+/// Rocket closes them after 5 seconds, which makes browsers reconnect repeatedly
+/// while loading hundreds of thumbnails of a large gallery.
+pub const DEFAULT_KEEP_ALIVE: u32 = 75;
+
+/// Returns the Rocket figment with Galera's defaults of the HTTP server.\
+/// They can be changed in `Rocket.toml` or using environment variables like any other Rocket setting
+/// (e.g. `ROCKET_KEEP_ALIVE=30`).
+pub fn figment() -> Figment {
+  Figment::from(rocket::Config::default())
+    .merge(Serialized::defaults(("keep_alive", DEFAULT_KEEP_ALIVE)))
+    .merge(Toml::file(Env::var_or("ROCKET_CONFIG", "Rocket.toml")).nested())
+    .merge(Env::prefixed("ROCKET_").ignore(&["PROFILE"]).global())
+    .select(Profile::from_env_or("ROCKET_PROFILE", rocket::Config::DEFAULT_PROFILE))
+}
+
+/// HTTP server settings in effect.
+/// # Example
+/// ```toml
+/// [default]
+/// keep_alive = 75
+/// workers = 16
+///
+/// [default.shutdown]
+/// grace = 5
+/// mercy = 5
+/// ```
+#[derive(Serialize, JsonSchema, Debug, Clone)]
+pub struct HttpSettings {
+  /// HTTP/2 is negotiated with TLS clients and used by clients with prior knowledge (e.g. reverse proxies).
+  pub http2: bool,
+  /// Seconds idle connections are kept open; zero disables keep-alive.
+  pub keep_alive: u32,
+  /// Number of threads processing requests; further requests wait until a thread is free.
+  pub workers: usize,
+  /// Seconds running requests may finish after a shutdown is requested.
+  pub shutdown_grace: u32,
+  /// Seconds connections may finish sending responses after the grace period, before they are closed.
+  pub shutdown_mercy: u32,
+}
+
+impl HttpSettings {
+  pub fn new(config: &rocket::Config) -> Self {
+    HttpSettings {
+      // Rocket is always built with HTTP/2 support
+      http2: true,
+      keep_alive: config.keep_alive,
+      workers: config.workers,
+      shutdown_grace: config.shutdown.grace,
+      shutdown_mercy: config.shutdown.mercy,
+    }
+  }
+}
+
+/// Configuration of Galera.\
+/// It's read from the Rocket configuration, so it can be set in `Rocket.toml`
+/// or using environment variables prefixed with `ROCKET_` (e.g. `ROCKET_SCAN_SYMLINKS=follow`).
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Config {
+  /// How the scanner handles symbolic links.
+  pub scan_symlinks: SymlinkPolicy,
+  /// How the scanner handles files whose content is already in the user's library.
+  pub scan_duplicates: DuplicatePolicy,
+  /// Alerts about scans which find an unusual share of the user's media modified or missing.
+  pub scan_alerts: ScanAlertPolicy,
+  /// Checks of the gallery directory before scanning, for galleries on network mounts which may not be ready.
+  pub gallery_check: GalleryCheckPolicy,
+  /// Response to requests for resources of other users the caller can't see.
+  pub access_denied: AccessDeniedPolicy,
+  /// Number of media verified against their stored hashes every night; zero disables the check.
+  pub integrity_check_files: i64,
+  /// Hour (UTC) when the nightly integrity check starts.
+  pub integrity_check_hour: u32,
+  /// Requirements on passwords of new users and changed passwords.
+  pub password_policy: PasswordPolicy,
+  /// Lockout after repeated failed logins.
+  pub login_limits: LoginLimitPolicy,
+  /// Compression of API responses.
+  pub compression: CompressionPolicy,
+  /// Users can sign up only with an invite created by an organization administrator;
+  /// until the default organization has an administrator, anyone can sign up.
+  pub disable_local_signups: bool,
+  /// Image copied to the gallery of new users during onboarding when they ask for it.
+  pub sample_media: Option<PathBuf>,
+  /// Number of videos and files larger than 32 MiB one user, or one visitor of a share link, can stream at once;
+  /// further requests get 429. Zero disables the limit.
+  pub max_streams_per_user: usize,
+  /// Path of `ffmpeg` used for transcoding videos browsers can't play; found in `PATH` by default.
+  pub ffmpeg: PathBuf,
+  /// Number of videos transcoded at once.
+  pub max_transcodes: usize,
+  /// Applies migrations which can lose data at startup; back up the database before enabling it.
+  pub allow_destructive_migrations: bool,
+  /// Periodically sends anonymous usage statistics to `telemetry_endpoint`; disabled by default.
+  pub telemetry: bool,
+  /// URL receiving the statistics as a JSON `POST`; nothing is sent when it's not set.
+  pub telemetry_endpoint: Option<String>,
+  /// Hours between two reports.
+  pub telemetry_interval_hours: u64,
+  /// Bandwidth of all share links together in KiB/s; zero disables the limit.
+  pub share_link_bandwidth_limit: u64,
+  /// Days after which recorded accesses through share links are deleted; zero keeps them forever.
+  pub share_link_access_retention_days: u64,
+  /// Number of directories (named after the first bytes of the media hash) above derivatives directories;
+  /// at most 4. Run `galera --migrate-derivatives` after changing it.
+  pub derivative_shard_depth: usize,
+  /// Hours between removals of derivatives whose media no longer exist; zero removes them only at startup.
+  pub derivative_cleanup_interval_hours: u64,
+  /// Time when routes without the version prefix (e.g. `/media` instead of `/v1/media`) stop working,
+  /// announced to their clients in the `Sunset` header (e.g. `2023-06-30T00:00:00Z`); see `api_version`.
+  pub legacy_api_sunset: Option<DateTime<Utc>>,
+  /// Detection of faces and objects in scanned media; requires the `detection` feature.
+  pub detection: DetectionPolicy,
+  /// Regular expressions of dates in filenames, used for media without a date in EXIF (see `metadata`);
+  /// named groups `year`, `month` and `day` are required, `hour`, `minute` and `second` are optional.
+  /// An empty list disables them.
+  pub filename_date_patterns: Vec<String>,
+  /// Directories with media of users outside of the gallery, added or updated at startup; see `libraries`.
+  pub libraries: Vec<LibraryConfig>,
+  /// Address of the listener serving administration routes, `/health` and `/metrics` instead of the public one
+  /// (e.g. `127.0.0.1:9000`); see `management`.
+  pub management_address: Option<SocketAddr>,
+  /// Automatic scanning of changed files for users with the `watcher` feature.
+  pub watch: WatchPolicy,
+  /// Timeouts of handlers and of reading request bodies; see `timeouts`.
+  pub timeouts: TimeoutPolicy,
+}
+
+impl Default for Config {
+  fn default() -> Self {
+    Config {
+      scan_symlinks: SymlinkPolicy::default(),
+      scan_duplicates: DuplicatePolicy::default(),
+      scan_alerts: ScanAlertPolicy::default(),
+      gallery_check: GalleryCheckPolicy::default(),
+      access_denied: AccessDeniedPolicy::default(),
+      integrity_check_files: 0,
+      integrity_check_hour: 3,
+      password_policy: PasswordPolicy::default(),
+      login_limits: LoginLimitPolicy::default(),
+      compression: CompressionPolicy::default(),
+      disable_local_signups: false,
+      sample_media: None,
+      max_streams_per_user: 8,
+      ffmpeg: PathBuf::from("ffmpeg"),
+      max_transcodes: 1,
+      allow_destructive_migrations: false,
+      telemetry: false,
+      telemetry_endpoint: None,
+      telemetry_interval_hours: 24,
+      share_link_bandwidth_limit: 0,
+      share_link_access_retention_days: 90,
+      derivative_shard_depth: 2,
+      derivative_cleanup_interval_hours: 24,
+      legacy_api_sunset: None,
+      detection: DetectionPolicy::default(),
+      filename_date_patterns: DEFAULT_FILENAME_DATE_PATTERNS.iter().map(|pattern| pattern.to_string()).collect(),
+      libraries: vec![],
+      management_address: None,
+      watch: WatchPolicy::default(),
+      timeouts: TimeoutPolicy::default(),
+    }
+  }
+}
+
+/// Requirements on passwords.
+/// # Example
+/// ```toml
+/// [default.password_policy]
+/// min_length = 12
+/// require_digit = true
+/// banned_passwords = "/etc/galera/banned_passwords.bloom"
+/// ```
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PasswordPolicy {
+  /// Minimum number of characters; passwords can't be shorter than 8 characters regardless of this setting.
+  pub min_length: usize,
+  pub require_lowercase: bool,
+  pub require_uppercase: bool,
+  pub require_digit: bool,
+  /// Requires a character which is neither a letter nor a digit.
+  pub require_symbol: bool,
+  /// Filter of banned passwords compiled by `galera-cli banned-passwords`.
+  pub banned_passwords: Option<PathBuf>,
+}
+
+impl Default for PasswordPolicy {
+  fn default() -> Self {
+    PasswordPolicy {
+      min_length: 8,
+      require_lowercase: false,
+      require_uppercase: false,
+      require_digit: false,
+      require_symbol: false,
+      banned_passwords: None,
+    }
+  }
+}
+
+impl PasswordPolicy {
+  /// Generates a random password satisfying the policy; it contains a character of every class.
+  pub fn generate_password(&self) -> String {
+    let mut rng = thread_rng();
+    let len = self.min_length.clamp(16, 128);
+
+    let mut password: Vec<char> = (&mut rng).sample_iter(Alphanumeric).take(len - 4).map(char::from).collect();
+    for class in [PASSWORD_LOWERCASE, PASSWORD_UPPERCASE, PASSWORD_DIGITS, PASSWORD_SYMBOLS] {
+      password.push(*class.choose(&mut rng).unwrap() as char);
+    }
+
+    password.shuffle(&mut rng);
+    password.into_iter().collect()
+  }
+}
+
+const PASSWORD_LOWERCASE: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+const PASSWORD_UPPERCASE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const PASSWORD_DIGITS: &[u8] = b"0123456789";
+const PASSWORD_SYMBOLS: &[u8] = b"!#$%&*+-=?@^_~";
+
+/// Limits of failed logins and wrong passwords of share links; they're rejected with 429 once a limit is reached.
+/// # Example
+/// ```toml
+/// [default.login_limits]
+/// max_failures_per_account = 10
+/// lockout_minutes = 30
+/// ```
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct LoginLimitPolicy {
+  /// Failed logins from one client address before it's locked out; zero disables the limit.
+  pub max_failures_per_address: u32,
+  /// Failed logins into one account before it's locked out; zero disables the limit.
+  pub max_failures_per_account: u32,
+  /// Wrong passwords of one share link before it's locked out; zero disables the limit.
+  pub max_failures_per_share_link: u32,
+  /// Minutes since the last failed login until the lockout ends and the failures are forgotten.
+  pub lockout_minutes: u64,
+}
+
+impl Default for LoginLimitPolicy {
+  fn default() -> Self {
+    LoginLimitPolicy {
+      max_failures_per_address: 20,
+      max_failures_per_account: 5,
+      max_failures_per_share_link: 10,
+      lockout_minutes: 15,
+    }
+  }
+}
+
+/// Gzip compression of JSON, NDJSON and text responses for clients which accept it.\
+/// Media, thumbnails and archives are already compressed, so they're always sent as they are.
+/// # Example
+/// ```toml
+/// [default.compression]
+/// min_size = 4096
+/// level = 9
+/// ```
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct CompressionPolicy {
+  pub enabled: bool,
+  /// Responses smaller than this number of bytes aren't compressed; responses of unknown size always are.
+  pub min_size: usize,
+  /// From 1 (fastest) to 9 (smallest).
+  pub level: u32,
+}
+
+impl Default for CompressionPolicy {
+  fn default() -> Self {
+    CompressionPolicy {
+      enabled: true,
+      min_size: 1024,
+      level: 6,
+    }
+  }
+}
+
+/// Detection of faces and objects in scanned media using an ONNX model, see `detection`.
+/// # Example
+/// ```toml
+/// [default.detection]
+/// model = "/etc/galera/detector.onnx"
+/// labels = "/etc/galera/detector.labels"
+/// min_confidence = 0.6
+/// ```
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DetectionPolicy {
+  /// Path of the model; the detection is disabled when it's not set.
+  pub model: Option<PathBuf>,
+  /// Text file with names of the classes of the model, one per line in the order of their indexes.
+  pub labels: Option<PathBuf>,
+  /// Class whose detections are stored as faces instead of suggested tags.
+  pub face_label: String,
+  /// Width and height of the input image of the model in pixels.
+  pub input_size: u32,
+  /// Detections with a lower confidence (between 0 and 1) are ignored.
+  pub min_confidence: f32,
+}
+
+impl Default for DetectionPolicy {
+  fn default() -> Self {
+    DetectionPolicy {
+      model: None,
+      labels: None,
+      face_label: String::from("face"),
+      input_size: 640,
+      min_confidence: 0.5,
+    }
+  }
+}
+
+/// Library of a user, see `libraries`.
+/// # Example
+/// ```toml
+/// [[default.libraries]]
+/// user = "john"
+/// path = "/mnt/john"
+/// ```
+#[derive(Deserialize, Debug, Clone)]
+pub struct LibraryConfig {
+  /// Username of the owner.
+  pub user: String,
+  /// Name of the subfolder of the user's root folder; without it, the library replaces the user's directory in the gallery.
+  pub name: Option<String>,
+  /// Absolute path of the directory.
+  pub path: PathBuf,
+}
+
+/// Automatic scanning of changed files, see `watcher`.
+/// # Example
+/// ```toml
+/// [default.watch]
+/// interval_seconds = 60
+/// debounce_seconds = 30
+/// ```
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct WatchPolicy {
+  /// Seconds between checks of the users' directories, at least 10; zero disables the watcher.
+  pub interval_seconds: u64,
+  /// Seconds the directories have to stay unchanged before they are scanned, so copying many files results in one scan.
+  pub debounce_seconds: u64,
+}
+
+impl Default for WatchPolicy {
+  fn default() -> Self {
+    WatchPolicy {
+      interval_seconds: 30,
+      debounce_seconds: 10,
+    }
+  }
+}
+
+/// Timeouts of requests, see `timeouts`.
+/// # Example
+/// ```toml
+/// [default.timeouts]
+/// request_seconds = 30
+/// body_seconds = 120
+///
+/// [default.timeouts.routes]
+/// upload_media = 7200
+/// ```
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TimeoutPolicy {
+  /// Seconds a handler may take to respond, not counting responses streamed afterwards; zero disables the timeout.
+  /// Handlers of routes with a body (`POST`, `PUT`, `PATCH`) may take `body_seconds` longer, as they read it first.
+  pub request_seconds: u64,
+  /// Seconds reading the body of a request may take; zero disables the timeout.
+  pub body_seconds: u64,
+  /// Timeouts of routes given by their names (e.g. `upload_media`), used for both the handler and its body
+  /// instead of the ones above. They're added to the built-in ones, see `DEFAULT_ROUTE_TIMEOUTS`.
+  pub routes: HashMap<String, u64>,
+}
+
+/// Seconds of routes which take longer than others: uploads of large videos, imports which may hash all media
+/// of the user, and scans, which aren't limited as a dropped scan would leave its job running until a restart.
+pub const DEFAULT_ROUTE_TIMEOUTS: &[(&str, u64)] = &[
+  ("upload_media", 3600),
+  ("import_metadata", 3600),
+  ("scan_media", 0),
+  ("admin_scan_user", 0),
+  ("admin_confirm_scan_alert", 0),
+];
+
+impl TimeoutPolicy {
+  /// Returns the timeouts of the route's handler and body in seconds.
+  pub fn route_seconds(&self, name: &str) -> (u64, u64) {
+    let configured = self.routes.get(name).copied()
+      .or_else(|| DEFAULT_ROUTE_TIMEOUTS.iter().find(|(route, _)| *route == name).map(|(_, seconds)| *seconds));
+
+    match configured {
+      Some(seconds) => (seconds, seconds),
+      None => (self.request_seconds, self.body_seconds),
+    }
+  }
+}
+
+impl Default for TimeoutPolicy {
+  fn default() -> Self {
+    TimeoutPolicy {
+      request_seconds: 60,
+      body_seconds: 60,
+      routes: HashMap::new(),
+    }
+  }
+}
+
+/// Alerts about scans which find many media modified or missing at once, e.g. after ransomware
+/// encrypted the gallery or a folder was deleted by accident.
+/// # Example
+/// ```toml
+/// [default.scan_alerts]
+/// threshold_percent = 10
+/// pause = true
+/// ```
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct ScanAlertPolicy {
+  /// Share of the user's media which has to be modified or missing to raise an alert; zero disables alerts.
+  pub threshold_percent: u32,
+  /// Changes of modified media (their new hashes) aren't stored until an administrator confirms the alert.
+  pub pause: bool,
+}
+
+impl Default for ScanAlertPolicy {
+  fn default() -> Self {
+    ScanAlertPolicy {
+      threshold_percent: 20,
+      pause: false,
+    }
+  }
+}
+
+/// Number of modified or missing media which never raises an alert, so small libraries aren't alerted about every edit.
+const SCAN_ALERT_MIN_MEDIA: usize = 10;
+
+impl ScanAlertPolicy {
+  /// Policy of scans confirmed by an administrator, which never raise an alert.
+  pub fn confirmed() -> Self {
+    ScanAlertPolicy { threshold_percent: 0, pause: false }
+  }
+
+  /// Checks whether the number of modified and missing media out of the user's media is unusual.
+  pub fn is_unusual(&self, media: usize, changed: usize) -> bool {
+    self.threshold_percent != 0
+      && changed > SCAN_ALERT_MIN_MEDIA
+      && changed * 100 >= media * self.threshold_percent as usize
+  }
+}
+
+/// Checks whether the gallery directory is available before it's scanned.
+///
+/// A network mount which isn't ready looks like an empty directory, so a scan would find all media missing.
+/// When the gallery doesn't pass the checks, scans don't reconcile folders (see `Scanner::reconcile_folders()`),
+/// or in the strict mode they fail. In the strict mode, scans interrupted by a restart also wait for the gallery.
+/// # Example
+/// ```toml
+/// [default.gallery_check]
+/// require_marker = true
+/// strict = true
+/// ```
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct GalleryCheckPolicy {
+  /// Requires the file `.galera` in the gallery directory; create it on the mounted filesystem, not in the mount point.
+  pub require_marker: bool,
+  /// Minimum number of entries in the gallery directory.
+  pub min_entries: usize,
+  /// Scans fail when the gallery doesn't pass the checks.
+  pub strict: bool,
+  /// Seconds resumed scans wait for the gallery after a restart in the strict mode.
+  pub wait_seconds: u64,
+}
+
+impl Default for GalleryCheckPolicy {
+  fn default() -> Self {
+    GalleryCheckPolicy {
+      require_marker: false,
+      min_entries: 0,
+      strict: false,
+      wait_seconds: 300,
+    }
+  }
+}
+
+/// Policy for symbolic links found while scanning.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SymlinkPolicy {
+  /// Symlinked files and directories are scanned as if they were regular ones.\
+  /// Symlink loops are detected and skipped.
+  Follow,
+  /// Symlinks are skipped.
+  Ignore,
+  /// Symlinked files are scanned, symlinked directories are logged but not entered.
+  Record,
+}
+
+impl Default for SymlinkPolicy {
+  fn default() -> Self {
+    SymlinkPolicy::Record
+  }
+}
+
+/// Policy for scanned files with the same hash as media the user already has.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicatePolicy {
+  /// Duplicates are added like other media; they're listed by `/media/duplicates`.
+  Import,
+  /// Duplicates are logged and skipped.
+  Skip,
+}
+
+impl Default for DuplicatePolicy {
+  fn default() -> Self {
+    DuplicatePolicy::Import
+  }
+}
+
+/// Response to requests for media and albums the caller can't see.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessDeniedPolicy {
+  /// Responds with 404, so it's not possible to find out whether the resource exists.
+  NotFound,
+  /// Responds with 403.
+  Forbidden,
+}
+
+impl AccessDeniedPolicy {
+  /// Returns the status used for denied requests.
+  pub fn status(&self) -> Status {
+    match self {
+      AccessDeniedPolicy::NotFound => Status::NotFound,
+      AccessDeniedPolicy::Forbidden => Status::Forbidden,
+    }
+  }
+}
+
+impl Default for AccessDeniedPolicy {
+  fn default() -> Self {
+    AccessDeniedPolicy::NotFound
+  }
+}
@@ -0,0 +1,133 @@
+//! Co-view sessions: slideshows of a shared album watched together.
+//!
+//! The album owner creates a session and sends its token to the visitors of a share link of the album.
+//! The owner then sets the position of the slideshow (shown media, offset in videos, pausing)
+//! and every visitor receives it as a server-sent event, so all clients show the same media.\
+//! Sessions are kept in memory only; they end when the owner ends them, when they expire or when the server restarts.
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use futures::stream::{self, BoxStream, StreamExt};
+use nanoid::nanoid;
+use rocket::http::Status;
+use rocket::response::stream::{Event, EventStream};
+use rocket::tokio::sync::broadcast::{self, error::RecvError, Receiver, Sender};
+use rocket::Shutdown;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// How long a session lasts.
+pub const SESSION_HOURS: i64 = 12;
+
+/// Number of positions kept for slow visitors; older positions are skipped, only the latest matters.
+const CHANNEL_CAPACITY: usize = 16;
+
+/// Position of the slideshow set by the owner of the session.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+pub struct CoViewPosition {
+  /// Shown media; `None` before the slideshow starts.
+  pub media_uuid: Option<String>,
+  /// Seconds from the start of a video or audio.
+  #[serde(default)]
+  pub offset: f64,
+  #[serde(default)]
+  pub paused: bool,
+}
+
+#[derive(Debug)]
+struct CoViewSession {
+  album_id: i32,
+  owner_id: i32,
+  position: CoViewPosition,
+  sender: Sender<CoViewPosition>,
+  expires_at: NaiveDateTime,
+}
+
+/// Positions sent to a visitor: the current one right away, then every change until the session ends.
+pub type CoViewEvents = EventStream<BoxStream<'static, Event>>;
+
+/// Active sessions by their tokens; managed by Rocket.
+#[derive(Debug, Default)]
+pub struct CoViewSessions {
+  sessions: Mutex<HashMap<String, CoViewSession>>,
+}
+
+impl CoViewSessions {
+  /// Starts a session of the album; returns its token and expiration.
+  pub fn create(&self, album_id: i32, owner_id: i32) -> (String, NaiveDateTime) {
+    let token = nanoid!();
+    let expires_at = Utc::now().naive_utc() + Duration::hours(SESSION_HOURS);
+    let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+
+    let mut sessions = self.sessions.lock().unwrap();
+    // dropping the sender of an expired session ends the streams of its visitors
+    sessions.retain(|_, session| session.expires_at > Utc::now().naive_utc());
+    sessions.insert(token.clone(), CoViewSession { album_id, owner_id, position: CoViewPosition::default(), sender, expires_at });
+
+    (token, expires_at)
+  }
+
+  /// Sends the position to all visitors; returns the number of connected visitors.\
+  /// Only the owner of the session can change it.
+  pub fn update(&self, token: &str, owner_id: i32, position: CoViewPosition) -> Result<usize, Status> {
+    let mut sessions = self.sessions.lock().unwrap();
+
+    let session = match sessions.get_mut(token) {
+      Some(session) if session.expires_at > Utc::now().naive_utc() => session,
+      _ => return Err(Status::NotFound),
+    };
+
+    if session.owner_id != owner_id { return Err(Status::Forbidden) }
+
+    session.position = position.clone();
+
+    // sending fails only when nobody is watching
+    Ok(session.sender.send(position).unwrap_or_default())
+  }
+
+  /// Ends the session; streams of all visitors are closed.
+  pub fn end(&self, token: &str, owner_id: i32) -> Result<(), Status> {
+    let mut sessions = self.sessions.lock().unwrap();
+
+    match sessions.get(token) {
+      None => return Err(Status::NotFound),
+      Some(session) if session.owner_id != owner_id => return Err(Status::Forbidden),
+      Some(_) => (),
+    }
+
+    sessions.remove(token);
+
+    Ok(())
+  }
+
+  /// Joins the session of the album; `None` when there is no such session.
+  pub fn join(&self, token: &str, album_id: i32, shutdown: Shutdown) -> Option<CoViewEvents> {
+    let sessions = self.sessions.lock().unwrap();
+
+    let session = sessions.get(token)
+      .filter(|session| session.album_id == album_id && session.expires_at > Utc::now().naive_utc())?;
+
+    let position = session.position.clone();
+    let current = stream::once(async move { Event::json(&position) });
+    let updates = stream::unfold(session.sender.subscribe(), next_position);
+
+    let events = current
+      .chain(updates)
+      // streams are infinite, so they have to end when the server shuts down
+      .take_until(shutdown)
+      .boxed();
+
+    Some(EventStream::from(events))
+  }
+}
+
+async fn next_position(mut receiver: Receiver<CoViewPosition>) -> Option<(Event, Receiver<CoViewPosition>)> {
+  loop {
+    match receiver.recv().await {
+      Ok(position) => return Some((Event::json(&position), receiver)),
+      Err(RecvError::Lagged(_)) => continue,
+      Err(RecvError::Closed) => return None,
+    }
+  }
+}
@@ -1,16 +1,30 @@
-use crate::models::{Album, AlbumShareLink, Media, NewAlbum, NewAlbumMedia, NewAlbumShareLink};
+use crate::auth::permissions::AlbumRole;
+use crate::models::{Album, Album_invite, AlbumShareLink, AlbumShareLinkAccess, AlbumVisit, Media, NewAlbum, NewAlbumInvite, NewAlbumMedia, NewAlbumShareLink, NewAlbumShareLinkAccess, NewAlbumShareLinkDownload, SmartAlbum};
 use crate::routes::{AlbumInsertData, AlbumShareLinkInsert, AlbumUpdateData};
-use crate::schema::{album, album_media, album_share_link, media};
+use crate::routes::pagination::MediaPagination;
+use crate::db::media::paginate;
+use crate::schema::{album, album_invite, album_media, album_share_link, album_share_link_access, album_share_link_download, album_visit, favorite_media, media, user};
+use crate::db::DbError;
 use crate::DbConn;
+use chrono::{Duration, NaiveDateTime};
 use diesel::BoolExpressionMethods;
+use diesel::Connection;
 use diesel::ExpressionMethods;
 use diesel::OptionalExtension;
+use diesel::GroupByDsl;
+use diesel::JoinOnDsl;
 use diesel::QueryDsl;
 use diesel::RunQueryDsl;
 use diesel::Table;
+use diesel::dsl::sql;
+use diesel::expression::BoxableExpression;
+use diesel::mysql::Mysql;
+use diesel::sql_types::{BigInt, Bool};
+use std::collections::HashMap;
 
-// Checks whether the user has access to the album.
-pub async fn user_has_album_access(conn: &DbConn, user_id: i32, album_id: i32) -> Result<bool, diesel::result::Error> {
+/// Checks whether the user has access to the album and returns the user's role in it.\
+/// Returns `None` when the user is neither the owner nor an invited user who accepted the invite.
+pub async fn user_has_album_access(conn: &DbConn, user_id: i32, album_id: i32) -> Result<Option<AlbumRole>, DbError> {
   let id: Option<i32> = conn.run(move |c| {
     album::table
       .select(album::dsl::id)
@@ -19,143 +33,303 @@ pub async fn user_has_album_access(conn: &DbConn, user_id: i32, album_id: i32) -
       .optional()
   }).await?;
 
-  if id.is_none() {
-    return Ok(false);
+  if id.is_some() {
+    return Ok(Some(AlbumRole::Owner));
   }
 
-  Ok(true)
+  let write_access: Option<bool> = conn.run(move |c| {
+    album_invite::table
+      .select(album_invite::write_access)
+      .filter(album_invite::album_id.eq(album_id).and(album_invite::invited_user_id.eq(user_id)).and(album_invite::accepted.eq(true)))
+      .first::<bool>(c)
+      .optional()
+  }).await?;
+
+  Ok(write_access.map(|write_access| if write_access { AlbumRole::Editor } else { AlbumRole::Viewer }))
 }
 
-pub async fn select_album(conn: &DbConn, album_id: i32) -> Option<Album> {
+pub async fn select_album(conn: &DbConn, album_id: i32) -> Result<Option<Album>, DbError> {
   conn.run(move |c| {
     album::table
       .select(album::table::all_columns())
       .filter(album::dsl::id.eq(album_id))
       .first::<Album>(c)
       .optional()
-      .unwrap()
   }).await
 }
 
-pub async fn select_album_id(conn: &DbConn, album_uuid: String) -> Option<i32> {
+pub async fn select_album_id(conn: &DbConn, album_uuid: String) -> Result<Option<i32>, DbError> {
   conn.run(move |c| {
     album::table
       .select(album::id)
       .filter(album::dsl::link.eq(album_uuid))
       .first::<i32>(c)
       .optional()
-      .unwrap()
   }).await
 }
 
-pub async fn insert_album(conn: &DbConn, user_id: i32, album_insert_data: AlbumInsertData) {
+/// Inserts an album and returns it.
+pub async fn insert_album(conn: &DbConn, user_id: i32, album_insert_data: AlbumInsertData) -> Result<Album, DbError> {
   let new_album = NewAlbum::new(user_id, album_insert_data.name, album_insert_data.description, None);
+  conn.run(move |c| {
+    c.transaction(|| {
+      diesel::insert_into(album::table)
+        .values(&new_album)
+        .execute(c)?;
+
+      // links are unique, so the album doesn't have to be found by the last insert ID
+      album::table
+        .filter(album::link.eq(&new_album.link))
+        .first::<Album>(c)
+    })
+  }).await
+}
+
+/// Inserts an album together with its media; returns the ID of the album.
+pub async fn insert_album_with_media(conn: &DbConn, new_album: NewAlbum, media_ids: Vec<i32>) -> Result<i32, DbError> {
+  conn.run(move |c| {
+    c.transaction(|| {
+      diesel::insert_into(album::table)
+        .values(&new_album)
+        .execute(c)?;
+
+      // links are unique, so the album doesn't have to be found by the last insert ID
+      let album_id = album::table
+        .select(album::id)
+        .filter(album::link.eq(&new_album.link))
+        .first::<i32>(c)?;
+
+      let album_media: Vec<NewAlbumMedia> = media_ids.into_iter()
+        .map(|media_id| NewAlbumMedia::new(album_id, media_id, Some(new_album.owner_id)))
+        .collect();
+
+      if !album_media.is_empty() {
+        diesel::insert_into(album_media::table)
+          .values(album_media)
+          .execute(c)?;
+      }
+
+      Ok(album_id)
+    })
+  }).await
+}
+
+/// Selects IDs of albums containing any of the media.
+pub async fn select_media_album_ids(conn: &DbConn, media_ids: Vec<i32>) -> Result<Vec<i32>, DbError> {
+  conn.run(move |c| {
+    album_media::table
+      .select(album_media::album_id)
+      .filter(album_media::media_id.eq_any(media_ids))
+      .distinct()
+      .get_results::<i32>(c)
+  }).await
+}
+
+/// Inserts a smart album of the user.
+pub async fn insert_smart_album(conn: &DbConn, user_id: i32, smart: SmartAlbum) -> Result<usize, DbError> {
+  let new_album = NewAlbum::new_smart(user_id, smart);
   conn.run(move |c| {
     diesel::insert_into(album::table)
-      .values(new_album.clone())
+      .values(new_album)
       .execute(c)
-      .unwrap_or_else(|_| panic!("Could not add a new album for user with ID {}", new_album.owner_id));
-  }).await;
+  }).await
+}
+
+/// Selects a smart album of the given kind owned by the user.
+pub async fn select_smart_album(conn: &DbConn, user_id: i32, smart: SmartAlbum) -> Result<Option<Album>, DbError> {
+  conn.run(move |c| {
+    album::table
+      .filter(album::owner_id.eq(user_id).and(album::smart.eq(smart.as_str())))
+      .first::<Album>(c)
+      .optional()
+  }).await
 }
 
-pub async fn get_album_list(conn: &DbConn, user_id: i32) -> Vec<Album> {
+/// Selects an album of the user by its name; smart albums are left out.
+pub async fn select_user_album_by_name(conn: &DbConn, user_id: i32, name: String) -> Result<Option<Album>, DbError> {
+  conn.run(move |c| {
+    album::table
+      .filter(album::owner_id.eq(user_id).and(album::name.eq(name)).and(album::smart.is_null()))
+      .order(album::id.asc())
+      .first::<Album>(c)
+      .optional()
+  }).await
+}
+
+/// Gets albums of the user, including albums the user was invited to and accepted the invite.
+pub async fn get_album_list(conn: &DbConn, user_id: i32) -> Result<Vec<Album>, DbError> {
   conn.run(move |c| {
     album::table
       .select(album::table::all_columns())
-      .filter(album::dsl::owner_id.eq(user_id))
+      .filter(album::dsl::owner_id.eq(user_id).or(album::dsl::id.eq_any(
+        album_invite::table
+          .select(album_invite::album_id)
+          .filter(album_invite::invited_user_id.eq(user_id).and(album_invite::accepted.eq(true)))
+      )))
       .get_results::<Album>(c)
-      .optional()
-      .unwrap()
-      .unwrap()
   }).await
 }
 
-pub async fn album_add_media(conn: &DbConn, list_of_media: Vec<NewAlbumMedia>) -> Option<()> {
-  let r: Result<usize, diesel::result::Error> = conn.run(move |c| {
-    diesel::insert_into(album_media::table)
+/// Adds media to albums; media already present in an album are skipped by the unique key of `album_media`,
+/// so concurrent requests can't add them twice.
+pub async fn album_add_media(conn: &DbConn, list_of_media: Vec<NewAlbumMedia>) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::insert_or_ignore_into(album_media::table)
       .values(list_of_media)
       .execute(c)
-  }).await;
-
-  if r.is_err() {
-    return None;
-  }
-
-  Some(())
+  }).await
 }
 
-pub async fn album_already_has_media(conn: &DbConn, album_id: i32, media_id: i32) -> Result<bool, diesel::result::Error> {
-  let id: Result<Option<i32>, diesel::result::Error> = conn.run(move |c| {
+pub async fn album_already_has_media(conn: &DbConn, album_id: i32, media_id: i32) -> Result<bool, DbError> {
+  let id: Option<i32> = conn.run(move |c| {
     album_media::table
     .select(album_media::id)
     .filter(album_media::dsl::album_id.eq(album_id).and(album_media::dsl::media_id.eq(media_id)))
     .first::<i32>(c)
     .optional()
-  }).await;
-
-  if id.is_err() {
-    return Err(id.unwrap_err())
-  } else {
-    let id_unwrapped = id.unwrap();
-    if id_unwrapped.is_some() {
-      Ok(true)
-    } else {
-      Ok(false)
-    }
-  }
+  }).await?;
+
+  Ok(id.is_some())
 }
 
-pub async fn update_album(conn: &DbConn, album_id: i32, album_update_data: AlbumUpdateData) -> Option<usize> {
-  let mut name_result: Result<usize, diesel::result::Error> = Ok(0);
-  let mut description_result: Result<usize, diesel::result::Error> = Ok(0);
+/// Removes media from the album.
+pub async fn album_remove_media(conn: &DbConn, album_id: i32, media_id: i32) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::delete(
+      album_media::table
+        .filter(album_media::album_id.eq(album_id).and(album_media::media_id.eq(media_id)))
+    )
+      .execute(c)
+  }).await
+}
+
+pub async fn update_album(conn: &DbConn, album_id: i32, album_update_data: AlbumUpdateData) -> Result<usize, DbError> {
+  let mut updated = 0;
 
-  let name = album_update_data.name;
-  let description = album_update_data.description;
+  let sort_order = album_update_data.sort_order;
 
-  if name.is_some() {
-    name_result = conn.run(move |c| {
+  if let Some(name) = album_update_data.name {
+    updated += conn.run(move |c| {
       diesel::update(album::table.filter(album::id.eq(album_id)))
-        .set(album::dsl::name.eq(name.unwrap()))
+        .set(album::dsl::name.eq(name))
         .execute(c)
-    }).await;
+    }).await?;
   }
 
-  if description.is_some() {
-    description_result = conn.run(move |c| {
+  if let Some(description) = album_update_data.description {
+    updated += conn.run(move |c| {
       diesel::update(album::table.filter(album::id.eq(album_id)))
-        .set(album::dsl::description.eq(description.unwrap()))
+        .set(album::dsl::description.eq(description))
         .execute(c)
-    }).await;
+    }).await?;
   }
 
-  if name_result.is_err() || description_result.is_err() {
-    return None;
+  if let Some(sort) = album_update_data.sort {
+    updated += conn.run(move |c| {
+      diesel::update(album::table.filter(album::id.eq(album_id)))
+        .set((album::dsl::sort.eq(sort.as_str()), album::dsl::sort_order.eq(sort_order.map(|order| order.as_str()))))
+        .execute(c)
+    }).await?;
   }
 
-  Some(name_result.unwrap() + description_result.unwrap())
+  Ok(updated)
 }
 
-pub async fn delete_album(conn: &DbConn, album_id: i32) -> Result<usize, diesel::result::Error> {
+/// Locks or unlocks the album.
+pub async fn set_album_locked(conn: &DbConn, album_id: i32, locked: bool) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::update(album::table.filter(album::id.eq(album_id)))
+      .set(album::dsl::locked.eq(locked))
+      .execute(c)
+  }).await
+}
+
+pub async fn delete_album(conn: &DbConn, album_id: i32) -> Result<usize, DbError> {
   conn.run(move |c| {
     diesel::delete(album::table.filter(album::id.eq(album_id)))
       .execute(c)
   }).await
 }
 
-pub async fn get_album_media(conn: &DbConn, album_id: i32) -> Result<Vec<Media>, diesel::result::Error> {
+/// Records that the user has just visited the album.
+pub async fn upsert_album_visit(conn: &DbConn, user_id: i32, album_id: i32) -> Result<usize, DbError> {
+  let visit = AlbumVisit::new(user_id, album_id);
   conn.run(move |c| {
-    media::table
-      .select(media::table::all_columns())
+    diesel::replace_into(album_visit::table)
+      .values(visit)
+      .execute(c)
+  }).await
+}
+
+/// Counts media added to each of the albums since the last visit of the user; all media are new when the user never visited it.\
+/// Albums without new media are left out.
+pub async fn count_new_album_media(conn: &DbConn, user_id: i32, album_ids: Vec<i32>) -> Result<Vec<(i32, i64)>, DbError> {
+  conn.run(move |c| {
+    album_media::table
+      .left_join(album_visit::table.on(album_visit::album_id.eq(album_media::album_id).and(album_visit::user_id.eq(user_id))))
+      .filter(album_media::album_id.eq_any(album_ids))
+      .filter(album_visit::visited_at.is_null().or(album_media::added_at.gt(album_visit::visited_at)))
+      .group_by(album_media::album_id)
+      // diesel 1.4 doesn't allow aggregate functions next to grouped columns
+      .select((album_media::album_id, sql::<BigInt>("COUNT(*)")))
+      .get_results(c)
+  }).await
+}
+
+/// Gets a page of media in the album.
+pub async fn get_album_media(conn: &DbConn, album_id: i32, pagination: MediaPagination) -> Result<Vec<Media>, DbError> {
+  conn.run(move |c| {
+    let query = media::table
       .filter(media::id.eq_any(
         album_media::table
           .select(album_media::media_id)
           .filter(album_media::album_id.eq(album_id))
       ))
+      .filter(media::missing_since.is_null())
+      .into_boxed();
+
+    paginate(query, &pagination)
       .get_results::<Media>(c)
   }).await
 }
 
-pub async fn select_album_share_links(conn: &DbConn, album_id: i32) -> Result<Vec<AlbumShareLink>, diesel::result::Error> {
+/// Selects who added each media of the album, when, and the media UUID; the newest additions first.\
+/// With `media_ids`, only additions of these media are selected.\
+/// Media added before it was recorded are attributed to their owners, as only owners could add them.
+pub async fn select_album_contributions(conn: &DbConn, album_id: i32, media_ids: Option<Vec<i32>>) -> Result<Vec<(String, NaiveDateTime, String)>, DbError> {
+  conn.run(move |c| {
+    let mut query = album_media::table
+      .inner_join(media::table)
+      .select((album_media::added_by, media::owner_id, album_media::added_at, media::uuid))
+      .filter(album_media::album_id.eq(album_id))
+      .order(album_media::added_at.desc())
+      .into_boxed();
+
+    if let Some(media_ids) = media_ids {
+      query = query.filter(album_media::media_id.eq_any(media_ids));
+    }
+
+    let contributions = query.get_results::<(Option<i32>, i32, NaiveDateTime, String)>(c)?;
+
+    let user_ids: Vec<i32> = contributions.iter().map(|(added_by, owner_id, _, _)| added_by.unwrap_or(*owner_id)).collect();
+    let usernames: HashMap<i32, String> = user::table
+      .select((user::id, user::username))
+      .filter(user::id.eq_any(user_ids))
+      .get_results::<(i32, String)>(c)?
+      .into_iter()
+      .collect();
+
+    Ok(contributions.into_iter()
+      .filter_map(|(added_by, owner_id, added_at, media_uuid)| {
+        let username = usernames.get(&added_by.unwrap_or(owner_id))?;
+
+        Some((username.clone(), added_at, media_uuid))
+      })
+      .collect())
+  }).await
+}
+
+pub async fn select_album_share_links(conn: &DbConn, album_id: i32) -> Result<Vec<AlbumShareLink>, DbError> {
   conn.run(move |c| {
     album_share_link::table
       .select(album_share_link::table::all_columns())
@@ -164,7 +338,7 @@ pub async fn select_album_share_links(conn: &DbConn, album_id: i32) -> Result<Ve
   }).await
 }
 
-pub async fn select_album_share_link_by_uuid(conn: &DbConn, album_share_link_uuid: String) -> Result<Option<AlbumShareLink>, diesel::result::Error> {
+pub async fn select_album_share_link_by_uuid(conn: &DbConn, album_share_link_uuid: String) -> Result<Option<AlbumShareLink>, DbError> {
   conn.run(move |c| {
     album_share_link::table
       .select(album_share_link::table::all_columns())
@@ -174,7 +348,7 @@ pub async fn select_album_share_link_by_uuid(conn: &DbConn, album_share_link_uui
   }).await
 }
 
-pub async fn insert_album_share_link(conn: &DbConn, album_share_link: NewAlbumShareLink) -> Result<usize, diesel::result::Error> {
+pub async fn insert_album_share_link(conn: &DbConn, album_share_link: NewAlbumShareLink) -> Result<usize, DbError> {
   conn.run(move |c| {
     diesel::insert_into(album_share_link::table)
       .values(album_share_link)
@@ -183,18 +357,188 @@ pub async fn insert_album_share_link(conn: &DbConn, album_share_link: NewAlbumSh
 }
 
 /// Updates album share link.
-pub async fn update_album_share_link(conn: &DbConn, album_share_link_id: i32, album_share_link_insert: AlbumShareLinkInsert) -> Result<usize, diesel::result::Error> {
+/// Replaces the password of the share link with an already hashed one.
+pub async fn update_album_share_link_password(conn: &DbConn, album_share_link_id: i32, password: String) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::update(album_share_link::table.filter(album_share_link::id.eq(album_share_link_id)))
+      .set(album_share_link::password.eq(password))
+      .execute(c)
+  }).await
+}
+
+pub async fn update_album_share_link(conn: &DbConn, album_share_link_id: i32, album_share_link_insert: AlbumShareLinkInsert) -> Result<usize, DbError> {
   conn.run(move |c| {
     diesel::update(album_share_link::table.filter(album_share_link::id.eq(album_share_link_id)))
       .set(
         (album_share_link::dsl::expiration.eq(album_share_link_insert.expiration),
-        album_share_link::dsl::password.eq(album_share_link_insert.password)))
+        album_share_link::dsl::password.eq(album_share_link_insert.password),
+        album_share_link::dsl::title.eq(album_share_link_insert.branding.title),
+        album_share_link::dsl::welcome_message.eq(album_share_link_insert.branding.welcome_message),
+        album_share_link::dsl::accent_color.eq(album_share_link_insert.branding.accent_color),
+        album_share_link::dsl::allow_zip_download.eq(album_share_link_insert.allow_zip_download),
+        album_share_link::dsl::bandwidth_limit.eq(album_share_link_insert.bandwidth_limit)))
+      .execute(c)
+  }).await
+}
+
+/// Records a zip download of the album through the share link.
+pub async fn insert_album_share_link_download(conn: &DbConn, album_share_link_id: i32) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::insert_into(album_share_link_download::table)
+      .values(NewAlbumShareLinkDownload::new(album_share_link_id))
       .execute(c)
   }).await
 }
 
+/// Selects zip downloads of all share links of the album, newest first.
+/// # Example
+/// ```
+/// // [(album_share_link_id, downloaded_at)]
+/// let downloads: Vec<(i32, NaiveDateTime)> = select_album_share_link_downloads(&conn, album_id).await?;
+/// ```
+pub async fn select_album_share_link_downloads(conn: &DbConn, album_id: i32) -> Result<Vec<(i32, NaiveDateTime)>, DbError> {
+  conn.run(move |c| {
+    album_share_link_download::table
+      .inner_join(album_share_link::table)
+      .filter(album_share_link::album_id.eq(album_id))
+      .select((album_share_link_download::album_share_link_id, album_share_link_download::downloaded_at))
+      .order(album_share_link_download::downloaded_at.desc())
+      .get_results::<(i32, NaiveDateTime)>(c)
+  }).await
+}
+
+/// Seconds within which successful accesses of one client through a share link are recorded once.
+const SHARE_LINK_ACCESS_INTERVAL: i64 = 3600;
+
+/// Records an access through the share link.\
+/// Visitors are authorized for every media they view, so their successful accesses are recorded at most once an hour;
+/// wrong passwords are always recorded.
+pub async fn insert_album_share_link_access(conn: &DbConn, access: NewAlbumShareLinkAccess) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    if access.succeeded {
+      let mut recent = album_share_link_access::table
+        .filter(album_share_link_access::album_share_link_id.eq(access.album_share_link_id))
+        .filter(album_share_link_access::succeeded.eq(true))
+        .filter(album_share_link_access::accessed_at.gt(access.accessed_at - Duration::seconds(SHARE_LINK_ACCESS_INTERVAL)))
+        .into_boxed();
+
+      // comparing with NULL never matches, so clients without a known address need `IS NULL`
+      recent = match &access.ip_address {
+        Some(ip_address) => recent.filter(album_share_link_access::ip_address.eq(ip_address.clone())),
+        None => recent.filter(album_share_link_access::ip_address.is_null()),
+      };
+
+      let recorded = recent
+        .select(album_share_link_access::id)
+        .first::<i32>(c)
+        .optional()?
+        .is_some();
+
+      if recorded { return Ok(0) }
+    }
+
+    diesel::insert_into(album_share_link_access::table)
+      .values(access)
+      .execute(c)
+  }).await
+}
+
+/// Deletes accesses through share links recorded before the given time.
+pub async fn delete_album_share_link_accesses_before(conn: &DbConn, before: NaiveDateTime) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::delete(album_share_link_access::table.filter(album_share_link_access::accessed_at.lt(before)))
+      .execute(c)
+  }).await
+}
+
+/// Selects the latest accesses through the share link, newest first.
+pub async fn select_album_share_link_accesses(conn: &DbConn, album_share_link_id: i32, limit: i64) -> Result<Vec<AlbumShareLinkAccess>, DbError> {
+  conn.run(move |c| {
+    album_share_link_access::table
+      .filter(album_share_link_access::album_share_link_id.eq(album_share_link_id))
+      .order(album_share_link_access::id.desc())
+      .limit(limit)
+      .get_results::<AlbumShareLinkAccess>(c)
+  }).await
+}
+
+/// Sets or clears the media shown as the thumbnail of the album.
+pub async fn update_album_thumbnail(conn: &DbConn, album_id: i32, media_uuid: Option<String>) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::update(album::table.filter(album::id.eq(album_id)))
+      .set(album::thumbnail_link.eq(media_uuid))
+      .execute(c)
+  }).await
+}
+
+/// Shows the first added media of the album as its thumbnail, unless the thumbnail is media of the album already.\
+/// Must be called whenever media of the album change, so thumbnails of removed media are replaced.
+/// Smart albums don't have thumbnails.
+pub async fn update_album_thumbnail_fallback(conn: &DbConn, album_id: i32) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    let album = album::table
+      .filter(album::id.eq(album_id))
+      .first::<Album>(c)?;
+
+    if album.smart.is_some() { return Ok(0) }
+
+    if let Some(thumbnail_link) = album.thumbnail_link {
+      let in_album = diesel::select(diesel::dsl::exists(
+        album_media::table
+          .inner_join(media::table)
+          .filter(album_media::album_id.eq(album_id).and(media::uuid.eq(thumbnail_link)))
+      ))
+        .get_result::<bool>(c)?;
+
+      if in_album { return Ok(0) }
+    }
+
+    let first = first_album_media_uuid(c, album_id, &[])?;
+
+    diesel::update(album::table.filter(album::id.eq(album_id)))
+      .set(album::thumbnail_link.eq(first))
+      .execute(c)
+  }).await
+}
+
+/// Replaces thumbnails showing any of the media by the first other media of their albums, or clears them.\
+/// Must be called in the transaction deleting the media, before they're deleted, as thumbnails reference them.
+pub fn replace_album_thumbnails_of_media(c: &diesel::MysqlConnection, media_ids: &[i32]) -> Result<usize, DbError> {
+  let media_uuids = media::table
+    .select(media::uuid)
+    .filter(media::id.eq_any(media_ids))
+    .get_results::<String>(c)?;
+
+  let album_ids = album::table
+    .select(album::id)
+    .filter(album::thumbnail_link.eq_any(media_uuids))
+    .get_results::<i32>(c)?;
+
+  let mut updated = 0;
+  for album_id in album_ids {
+    let fallback = first_album_media_uuid(c, album_id, media_ids)?;
+
+    updated += diesel::update(album::table.filter(album::id.eq(album_id)))
+      .set(album::thumbnail_link.eq(fallback))
+      .execute(c)?;
+  }
+
+  Ok(updated)
+}
+
+/// Selects UUID of the first added media of the album, except the excluded ones.
+fn first_album_media_uuid(c: &diesel::MysqlConnection, album_id: i32, excluded_media_ids: &[i32]) -> Result<Option<String>, DbError> {
+  album_media::table
+    .inner_join(media::table)
+    .select(media::uuid)
+    .filter(album_media::album_id.eq(album_id).and(album_media::media_id.ne_all(excluded_media_ids)))
+    .order(album_media::id.asc())
+    .first::<String>(c)
+    .optional()
+}
+
 /// Removes album share link.
-pub async fn delete_album_share_link(conn: &DbConn, album_share_link_uuid: String) -> Result<usize, diesel::result::Error> {
+pub async fn delete_album_share_link(conn: &DbConn, album_share_link_uuid: String) -> Result<usize, DbError> {
   conn.run(move |c| {
     diesel::delete(
       album_share_link::table
@@ -203,3 +547,126 @@ pub async fn delete_album_share_link(conn: &DbConn, album_share_link_uuid: Strin
       .execute(c)
   }).await
 }
+
+/// Invites a user to the album.
+pub async fn insert_album_invite(conn: &DbConn, album_invite: NewAlbumInvite) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::insert_into(album_invite::table)
+      .values(album_invite)
+      .execute(c)
+  }).await
+}
+
+/// Selects invites of the album together with usernames of the invited users.
+pub async fn select_album_invites(conn: &DbConn, album_id: i32) -> Result<Vec<(Album_invite, String)>, DbError> {
+  conn.run(move |c| {
+    album_invite::table
+      .inner_join(user::table)
+      .select((album_invite::table::all_columns(), user::username))
+      .filter(album_invite::album_id.eq(album_id))
+      .get_results(c)
+  }).await
+}
+
+/// Selects invites of the user which weren't accepted yet, together with the albums.
+pub async fn select_pending_album_invites(conn: &DbConn, user_id: i32) -> Result<Vec<(Album_invite, Album)>, DbError> {
+  conn.run(move |c| {
+    album_invite::table
+      .inner_join(album::table)
+      .select((album_invite::table::all_columns(), album::table::all_columns()))
+      .filter(album_invite::invited_user_id.eq(user_id).and(album_invite::accepted.eq(false)))
+      .get_results(c)
+  }).await
+}
+
+/// Accepts the invite of the user to the album.
+pub async fn accept_album_invite(conn: &DbConn, album_id: i32, user_id: i32) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::update(
+      album_invite::table
+        .filter(album_invite::album_id.eq(album_id).and(album_invite::invited_user_id.eq(user_id)))
+    )
+      .set(album_invite::accepted.eq(true))
+      .execute(c)
+  }).await
+}
+
+/// Removes the invite of the user to the album.
+pub async fn delete_album_invite(conn: &DbConn, album_id: i32, user_id: i32) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::delete(
+      album_invite::table
+        .filter(album_invite::album_id.eq(album_id).and(album_invite::invited_user_id.eq(user_id)))
+    )
+      .execute(c)
+  }).await
+}
+
+/// Updates the cached range of dates when media of the album were taken.\
+/// Must be called whenever media of the album change; media of smart albums are the owner's liked media.
+pub async fn update_album_date_range(conn: &DbConn, album_id: i32) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    let album = album::table
+      .filter(album::id.eq(album_id))
+      .first::<Album>(c)?;
+
+    let album_media = || -> Box<dyn BoxableExpression<media::table, Mysql, SqlType = Bool>> {
+      match album.smart() {
+        Some(SmartAlbum::Favorites) => Box::new(media::id.eq_any(
+          favorite_media::table
+            .select(favorite_media::media_id)
+            .filter(favorite_media::user_id.eq(album.owner_id))
+        )),
+        None => Box::new(media::id.eq_any(
+          album_media::table
+            .select(album_media::media_id)
+            .filter(album_media::album_id.eq(album_id))
+        )),
+      }
+    };
+
+    // diesel can't select more aggregates at once
+    let date_from = media::table
+      .select(diesel::dsl::min(media::date_taken))
+      .filter(album_media())
+      .get_result::<Option<NaiveDateTime>>(c)?;
+
+    let date_to = media::table
+      .select(diesel::dsl::max(media::date_taken))
+      .filter(album_media())
+      .get_result::<Option<NaiveDateTime>>(c)?;
+
+    diesel::update(album::table.filter(album::id.eq(album_id)))
+      .set((album::date_from.eq(date_from), album::date_to.eq(date_to)))
+      .execute(c)
+  }).await
+}
+
+/// Selects IDs of albums without a date range; they're either empty or were created before date ranges existed.
+pub async fn select_albums_without_date_range(conn: &DbConn) -> Result<Vec<i32>, DbError> {
+  conn.run(move |c| {
+    album::table
+      .select(album::id)
+      .filter(album::date_from.is_null())
+      .get_results::<i32>(c)
+  }).await
+}
+
+/// Selects IDs of albums without a thumbnail, except smart albums; they're either empty or were created before thumbnails were chosen.
+pub async fn select_albums_without_thumbnail(conn: &DbConn) -> Result<Vec<i32>, DbError> {
+  conn.run(move |c| {
+    album::table
+      .select(album::id)
+      .filter(album::thumbnail_link.is_null().and(album::smart.is_null()))
+      .get_results::<i32>(c)
+  }).await
+}
+
+/// Counts albums of all users, including smart albums.
+pub async fn count_albums(conn: &DbConn) -> Result<i64, DbError> {
+  conn.run(move |c| {
+    album::table
+      .count()
+      .get_result::<i64>(c)
+  }).await
+}
@@ -0,0 +1,58 @@
+use crate::models::{Media, MediaDetection, NewMediaFace, NewTagSuggestion, TagSuggestion};
+use crate::schema::{media, media_detection, media_face, tag_suggestion};
+use crate::db::DbError;
+use crate::DbConn;
+use chrono::Utc;
+use diesel::Connection;
+use diesel::ExpressionMethods;
+use diesel::QueryDsl;
+use diesel::RunQueryDsl;
+use diesel::Table;
+
+/// Selects media which weren't processed by the detection yet, the oldest first.
+pub async fn select_media_to_detect(conn: &DbConn, limit: i64) -> Result<Vec<Media>, DbError> {
+  conn.run(move |c| {
+    media::table
+      .left_join(media_detection::table)
+      .select(media::table::all_columns())
+      .filter(media_detection::media_id.is_null())
+      .order(media::id.asc())
+      .limit(limit)
+      .get_results::<Media>(c)
+  }).await
+}
+
+/// Stores faces and tag suggestions detected in the media and marks it as processed.
+pub async fn insert_detections(conn: &DbConn, media_id: i32, faces: Vec<NewMediaFace>, suggestions: Vec<NewTagSuggestion>) -> Result<(), DbError> {
+  conn.run(move |c| {
+    c.transaction(|| {
+      if !faces.is_empty() {
+        diesel::insert_into(media_face::table)
+          .values(faces)
+          .execute(c)?;
+      }
+
+      if !suggestions.is_empty() {
+        diesel::insert_or_ignore_into(tag_suggestion::table)
+          .values(suggestions)
+          .execute(c)?;
+      }
+
+      diesel::replace_into(media_detection::table)
+        .values(MediaDetection { media_id, detected_at: Utc::now().naive_utc() })
+        .execute(c)?;
+
+      Ok(())
+    })
+  }).await
+}
+
+/// Selects tags suggested for the media, the most confident first.
+pub async fn select_tag_suggestions(conn: &DbConn, media_id: i32) -> Result<Vec<TagSuggestion>, DbError> {
+  conn.run(move |c| {
+    tag_suggestion::table
+      .filter(tag_suggestion::media_id.eq(media_id))
+      .order(tag_suggestion::confidence.desc())
+      .get_results::<TagSuggestion>(c)
+  }).await
+}
@@ -1,24 +1,33 @@
 use crate::models::{Folder, NewFolder};
-use crate::schema::folder;
+use crate::schema::{folder, folder_scan};
+use crate::db::DbError;
 use crate::DbConn;
+use chrono::NaiveDateTime;
 use diesel::BoolExpressionMethods;
+use diesel::Connection;
 use diesel::ExpressionMethods;
 use diesel::OptionalExtension;
 use diesel::QueryDsl;
 use diesel::RunQueryDsl;
 use diesel::Table;
-use std::path::PathBuf;
 
-pub async fn insert_folder(conn: &DbConn, new_folder: NewFolder, name: String, path: PathBuf) {
+/// Inserts a folder and returns it.
+pub async fn insert_folder(conn: &DbConn, new_folder: NewFolder) -> Result<Folder, DbError> {
   conn.run(move |c| {
-    diesel::insert_into(folder::table)
-      .values(new_folder)
-      .execute(c)
-      .unwrap_or_else(|_| panic!("Error scanning folder {} in {}", name, path.display().to_string()));
-  }).await;
+    c.transaction(|| {
+      diesel::insert_into(folder::table)
+        .values(&new_folder)
+        .execute(c)?;
+
+      // UUIDs are unique, so the folder doesn't have to be found by the last insert ID
+      folder::table
+        .filter(folder::uuid.eq(&new_folder.uuid))
+        .first::<Folder>(c)
+    })
+  }).await
 }
 
-pub async fn select_child_folder_id(conn: &DbConn, name: String, parent: Option<i32>, user_id: i32) -> Option<i32> {
+pub async fn select_child_folder_id(conn: &DbConn, name: String, parent: Option<i32>, user_id: i32) -> Result<Option<i32>, DbError> {
   if parent.is_none() {
     conn.run(move |c| {
       folder::table
@@ -26,7 +35,6 @@ pub async fn select_child_folder_id(conn: &DbConn, name: String, parent: Option<
         .filter(folder::dsl::parent.is_null().and(folder::dsl::name.eq(name).and(folder::owner_id.eq(user_id))))
         .first::<i32>(c)
         .optional()
-        .unwrap()
     }).await
 
   } else {
@@ -36,12 +44,20 @@ pub async fn select_child_folder_id(conn: &DbConn, name: String, parent: Option<
         .filter(folder::dsl::parent.eq(parent).and(folder::dsl::name.eq(name).and(folder::owner_id.eq(user_id))))
         .first::<i32>(c)
         .optional()
-        .unwrap()
     }).await
   }
 }
 
-pub async fn select_root_folder(conn: &DbConn, user_id: i32) -> Result<Option<Folder>, diesel::result::Error> {
+/// Selects all folders of the user.
+pub async fn select_user_folders(conn: &DbConn, user_id: i32) -> Result<Vec<Folder>, DbError> {
+  conn.run(move |c| {
+    folder::table
+      .filter(folder::owner_id.eq(user_id))
+      .get_results::<Folder>(c)
+  }).await
+}
+
+pub async fn select_root_folder(conn: &DbConn, user_id: i32) -> Result<Option<Folder>, DbError> {
   conn.run(move |c| {
     folder::table
       .select(folder::table::all_columns())
@@ -51,15 +67,12 @@ pub async fn select_root_folder(conn: &DbConn, user_id: i32) -> Result<Option<Fo
   }).await
 }
 
-pub async fn select_subfolders(conn: &DbConn, parent_folder: Folder, user_id: i32) -> Vec<Folder> {
+pub async fn select_subfolders(conn: &DbConn, parent_folder: Folder, user_id: i32) -> Result<Vec<Folder>, DbError> {
   conn.run(move |c| {
     folder::table
       .select(folder::table::all_columns())
       .filter(folder::dsl::parent.eq(parent_folder.id).and(folder::owner_id.eq(user_id)))
       .get_results::<Folder>(c)
-      .optional()
-      .unwrap()
-      .unwrap()
   }).await
 }
 
@@ -67,16 +80,15 @@ pub async fn select_subfolders(conn: &DbConn, parent_folder: Folder, user_id: i3
 /// # Example
 /// We're selecting folder with id 10.
 /// ```
-/// let folder: Folder = select_folder(&conn, 10);
+/// let folder: Option<Folder> = select_folder(&conn, 10).await?;
 /// ```
-pub async fn select_folder(conn: &DbConn, folder_id: i32) -> Option<Folder> {
+pub async fn select_folder(conn: &DbConn, folder_id: i32) -> Result<Option<Folder>, DbError> {
   conn.run(move |c| {
     folder::table
       .select(folder::table::all_columns())
       .filter(folder::dsl::id.eq(folder_id))
       .first::<Folder>(c)
       .optional()
-      .unwrap()
   }).await
 }
 
@@ -84,15 +96,75 @@ pub async fn select_folder(conn: &DbConn, folder_id: i32) -> Option<Folder> {
 /// # Example
 /// We're selecting parent folder of a folder with id 10, where user id is 1.
 /// ```
-/// let current_folder: Folder = select_folder(&conn, 10);
-/// let parent_folder: Option<Folder> = select_parent_folder(&conn, current_folder, 1);
+/// let current_folder: Folder = select_folder(&conn, 10).await?.unwrap();
+/// let parent_folder: Option<Folder> = select_parent_folder(&conn, current_folder, 1).await?;
 /// ```
-pub async fn select_parent_folder(conn: &DbConn, current_folder: Folder, user_id: i32) -> Option<Folder> {
+pub async fn select_parent_folder(conn: &DbConn, current_folder: Folder, user_id: i32) -> Result<Option<Folder>, DbError> {
+  let parent = match current_folder.parent {
+    Some(parent) => parent,
+    None => return Ok(None),
+  };
+
   conn.run(move |c| {
     folder::table
       .select(folder::table::all_columns())
-      .filter(folder::dsl::id.eq(current_folder.parent?).and(folder::owner_id.eq(user_id)))
+      .filter(folder::dsl::id.eq(parent).and(folder::owner_id.eq(user_id)))
       .first::<Folder>(c)
-      .ok()
+      .optional()
+  }).await
+}
+
+/// Deletes folders together with their scans; subfolders must be ordered before their parents.
+pub async fn delete_folders(conn: &DbConn, folder_ids: Vec<i32>) -> Result<(), DbError> {
+  conn.run(move |c| {
+    c.transaction(|| {
+      diesel::delete(folder_scan::table.filter(folder_scan::folder_id.eq_any(&folder_ids)))
+        .execute(c)?;
+
+      // folders reference their parents, so they're deleted one by one
+      for folder_id in folder_ids {
+        diesel::delete(folder::table.filter(folder::id.eq(folder_id)))
+          .execute(c)?;
+      }
+
+      Ok(())
+    })
+  }).await
+}
+
+/// Sets or clears the time since which directories of the folders are missing.
+pub async fn update_folders_missing_since(conn: &DbConn, folder_ids: Vec<i32>, missing_since: Option<NaiveDateTime>) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::update(folder::table.filter(folder::id.eq_any(folder_ids)))
+      .set(folder::missing_since.eq(missing_since))
+      .execute(c)
+  }).await
+}
+
+/// Selects a folder of the user by its UUID.
+pub async fn select_folder_by_uuid(conn: &DbConn, folder_uuid: String, user_id: i32) -> Result<Option<Folder>, DbError> {
+  conn.run(move |c| {
+    folder::table
+      .filter(folder::uuid.eq(folder_uuid).and(folder::owner_id.eq(user_id)))
+      .first::<Folder>(c)
+      .optional()
+  }).await
+}
+
+/// Selects IDs of folders created before folders had UUIDs.
+pub async fn select_folders_without_uuid(conn: &DbConn) -> Result<Vec<i32>, DbError> {
+  conn.run(move |c| {
+    folder::table
+      .select(folder::id)
+      .filter(folder::uuid.is_null())
+      .get_results::<i32>(c)
+  }).await
+}
+
+pub async fn update_folder_uuid(conn: &DbConn, folder_id: i32, folder_uuid: String) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::update(folder::table.filter(folder::id.eq(folder_id)))
+      .set(folder::uuid.eq(folder_uuid))
+      .execute(c)
   }).await
 }
@@ -1,24 +1,22 @@
+use crate::db::DbError;
 use crate::DbConn;
 use diesel::select;
 use diesel::sql_types::Integer;
-use diesel::OptionalExtension;
 use diesel::RunQueryDsl;
 
 /// Returns last inserted id.
 /// # Example
-/// We inserted a new folder and we need its ID.
+/// We inserted a new refresh token and we need its ID.
 /// ```
-/// insert_folder(conn, new_folder, name, path).await;
+/// insert_refresh_token(&conn, 1, refresh_token, LoginClient::default()).await?;
 ///
-/// let folder_id: Option<i32> = get_last_insert_id(&conn);
+/// let refresh_token_id: i32 = get_last_insert_id(&conn).await?;
 /// ```
-pub async fn get_last_insert_id(conn: &DbConn) -> Option<i32> {
+pub async fn get_last_insert_id(conn: &DbConn) -> Result<i32, DbError> {
   conn.run(|c| {
     no_arg_sql_function!(last_insert_id, Integer);
 
     select(last_insert_id)
       .first(c)
-      .optional()
-      .unwrap()
   }).await
 }
@@ -0,0 +1,65 @@
+use crate::models::{Media, MediaIntegrity, IntegrityStatus};
+use crate::schema::{media, media_integrity, user};
+use crate::db::DbError;
+use crate::DbConn;
+use diesel::BoolExpressionMethods;
+use diesel::ExpressionMethods;
+use diesel::NullableExpressionMethods;
+use diesel::QueryDsl;
+use diesel::RunQueryDsl;
+use diesel::Table;
+
+/// Selects media which weren't verified for the longest time; never verified media go first.
+pub async fn select_media_to_verify(conn: &DbConn, limit: i64) -> Result<Vec<Media>, DbError> {
+  conn.run(move |c| {
+    media::table
+      .left_join(media_integrity::table)
+      .select(media::table::all_columns())
+      .order(media_integrity::verified_at.nullable().asc())
+      .limit(limit)
+      .get_results::<Media>(c)
+  }).await
+}
+
+/// Inserts or replaces the result of an integrity check.
+pub async fn upsert_media_integrity(conn: &DbConn, integrity: MediaIntegrity) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::replace_into(media_integrity::table)
+      .values(integrity)
+      .execute(c)
+  }).await
+}
+
+/// Selects failed integrity checks of media of the user together with the media UUIDs.
+pub async fn select_integrity_failures(conn: &DbConn, user_id: i32) -> Result<Vec<(MediaIntegrity, String)>, DbError> {
+  conn.run(move |c| {
+    media_integrity::table
+      .inner_join(media::table)
+      .select((media_integrity::table::all_columns(), media::uuid))
+      .filter(media::owner_id.eq(user_id).and(media_integrity::status.ne(IntegrityStatus::Ok.as_str())))
+      .order(media_integrity::verified_at.desc())
+      .get_results(c)
+  }).await
+}
+
+/// Selects failed integrity checks of media of all users together with the media UUIDs and the usernames of their owners.
+pub async fn select_all_integrity_failures(conn: &DbConn) -> Result<Vec<(MediaIntegrity, String, String)>, DbError> {
+  conn.run(move |c| {
+    media_integrity::table
+      .inner_join(media::table.inner_join(user::table))
+      .select((media_integrity::table::all_columns(), media::uuid, user::username))
+      .filter(media_integrity::status.ne(IntegrityStatus::Ok.as_str()))
+      .order(media_integrity::verified_at.desc())
+      .get_results(c)
+  }).await
+}
+
+/// Counts media of all users which failed their last integrity check.
+pub async fn count_integrity_failures(conn: &DbConn) -> Result<i64, DbError> {
+  conn.run(move |c| {
+    media_integrity::table
+      .filter(media_integrity::status.ne(IntegrityStatus::Ok.as_str()))
+      .count()
+      .get_result::<i64>(c)
+  }).await
+}
@@ -0,0 +1,87 @@
+use crate::models::{Job, JobKind, JobState, NewJob};
+use crate::schema::job;
+use crate::db::DbError;
+use crate::DbConn;
+use chrono::Utc;
+use diesel::ExpressionMethods;
+use diesel::OptionalExtension;
+use diesel::QueryDsl;
+use diesel::RunQueryDsl;
+
+/// Inserts a new job and returns it.
+pub async fn insert_job(conn: &DbConn, new_job: NewJob) -> Result<Job, DbError> {
+  conn.run(move |c| {
+    diesel::insert_into(job::table)
+      .values(&new_job)
+      .execute(c)?;
+
+    job::table
+      .filter(job::uuid.eq(&new_job.uuid))
+      .first::<Job>(c)
+  }).await
+}
+
+pub async fn update_job_progress(conn: &DbConn, job_id: i32, processed: u32, total: Option<u32>) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::update(job::table.filter(job::id.eq(job_id)))
+      .set((job::processed.eq(processed), job::total.eq(total)))
+      .execute(c)
+  }).await
+}
+
+/// Moves a running job into its final state.\
+/// Jobs which are no longer running are left untouched, so a state is never changed twice.
+pub async fn finish_job(conn: &DbConn, job_id: i32, state: JobState, error: Option<String>) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::update(job::table.filter(job::id.eq(job_id)).filter(job::state.eq(JobState::Running.as_str())))
+      .set((
+        job::state.eq(state.as_str()),
+        job::error.eq(error),
+        job::finished_at.eq(Utc::now().naive_utc()),
+      ))
+      .execute(c)
+  }).await
+}
+
+/// Returns jobs which were running; after a restart, these are the interrupted jobs.
+pub async fn select_running_jobs(conn: &DbConn) -> Result<Vec<Job>, DbError> {
+  conn.run(move |c| {
+    job::table
+      .filter(job::state.eq(JobState::Running.as_str()))
+      .order(job::id.asc())
+      .get_results::<Job>(c)
+  }).await
+}
+
+/// Checks whether a job of the kind is running for the user.
+pub async fn is_user_job_running(conn: &DbConn, user_id: i32, kind: JobKind) -> Result<bool, DbError> {
+  conn.run(move |c| {
+    diesel::select(diesel::dsl::exists(
+      job::table
+        .filter(job::user_id.eq(user_id))
+        .filter(job::kind.eq(kind.as_str()))
+        .filter(job::state.eq(JobState::Running.as_str()))
+    ))
+      .get_result::<bool>(c)
+  }).await
+}
+
+/// Returns the latest jobs of the user, newest first.
+pub async fn select_user_jobs(conn: &DbConn, user_id: i32, limit: i64) -> Result<Vec<Job>, DbError> {
+  conn.run(move |c| {
+    job::table
+      .filter(job::user_id.eq(user_id))
+      .order(job::id.desc())
+      .limit(limit)
+      .get_results::<Job>(c)
+  }).await
+}
+
+pub async fn select_job_by_uuid(conn: &DbConn, uuid: String) -> Result<Option<Job>, DbError> {
+  conn.run(move |c| {
+    job::table
+      .filter(job::uuid.eq(uuid))
+      .first::<Job>(c)
+      .optional()
+  }).await
+}
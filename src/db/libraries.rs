@@ -0,0 +1,61 @@
+use crate::models::{Library, NewLibrary};
+use crate::schema::library;
+use crate::db::DbError;
+use crate::DbConn;
+use diesel::BoolExpressionMethods;
+use diesel::ExpressionMethods;
+use diesel::OptionalExtension;
+use diesel::QueryDsl;
+use diesel::RunQueryDsl;
+
+/// Selects libraries of the user ordered by their names; the one replacing the user's directory is the first.
+pub async fn select_libraries(conn: &DbConn, user_id: i32) -> Result<Vec<Library>, DbError> {
+  conn.run(move |c| {
+    library::table
+      .filter(library::owner_id.eq(user_id))
+      .order((library::name.asc(), library::id.asc()))
+      .load::<Library>(c)
+  }).await
+}
+
+/// Selects the user's library with the given name; `None` name selects the one replacing the user's directory.
+pub async fn select_library_by_name(conn: &DbConn, user_id: i32, name: Option<String>) -> Result<Option<Library>, DbError> {
+  conn.run(move |c| {
+    let query = library::table
+      .filter(library::owner_id.eq(user_id))
+      .into_boxed();
+
+    let query = match name {
+      Some(name) => query.filter(library::name.eq(name)),
+      None => query.filter(library::name.is_null()),
+    };
+
+    query
+      .first::<Library>(c)
+      .optional()
+  }).await
+}
+
+pub async fn insert_library(conn: &DbConn, new_library: NewLibrary) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::insert_into(library::table)
+      .values(new_library)
+      .execute(c)
+  }).await
+}
+
+pub async fn update_library_path(conn: &DbConn, library_id: i32, path: String) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::update(library::table.filter(library::id.eq(library_id)))
+      .set(library::path.eq(path))
+      .execute(c)
+  }).await
+}
+
+/// Deletes the user's library with the given UUID; folders and media found in it stay until the next scan.
+pub async fn delete_library(conn: &DbConn, user_id: i32, library_uuid: String) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::delete(library::table.filter(library::uuid.eq(library_uuid).and(library::owner_id.eq(user_id))))
+      .execute(c)
+  }).await
+}
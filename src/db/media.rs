@@ -1,15 +1,22 @@
+use crate::geo::GeoBounds;
+use crate::metadata::MediaMetadata;
 use crate::models::*;
-use crate::schema::{favorite_media, media};
-use crate::routes::MediaResponse;
+use crate::schema::{album, album_invite, album_media, favorite_media, media, media_grant, media_tag, media_version, tag, user};
+use crate::scan::filesystem::FileStat;
+use crate::routes::pagination::{natural_sort_key, CursorKey, MediaPagination, MediaSort, SortOrder};
+use crate::db::albums::replace_album_thumbnails_of_media;
+use crate::db::DbError;
 use crate::DbConn;
 use checksums::{hash_file, Algorithm::SHA2512};
-use chrono::NaiveDateTime;
+use chrono::{DateTime, FixedOffset, NaiveDateTime};
+use diesel::mysql::Mysql;
 use diesel::BoolExpressionMethods;
+use diesel::Connection;
 use diesel::ExpressionMethods;
 use diesel::OptionalExtension;
 use diesel::QueryDsl;
 use diesel::RunQueryDsl;
-use diesel::Table;
+use std::collections::HashMap;
 use std::path::PathBuf;
 use uuid::Uuid;
 
@@ -17,73 +24,282 @@ use uuid::Uuid;
 /// # Example
 /// We have a picture named cat.jpg and we need to check if it's already in a database.
 /// ```
-/// let media: Option<i32> = check_if_media_present(&conn, name, parent_folder, user_id).await;
+/// let media: Option<i32> = check_if_media_present(&conn, name, parent_folder, user_id).await?;
 /// ```
-pub async fn check_if_media_present(conn: &DbConn, name: String, parent_folder: Folder, user_id: i32) -> Option<i32> {
+pub async fn check_if_media_present(conn: &DbConn, name: String, parent_folder: Folder, user_id: i32) -> Result<Option<i32>, DbError> {
   conn.run(move |c| {
     media::table
       .select(media::id)
       .filter(media::dsl::filename.eq(name).and(media::owner_id.eq(user_id).and(media::folder_id.eq(parent_folder.id))))
       .first::<i32>(c)
       .optional()
-      .unwrap()
   }).await
 }
 
-/// Inserts new media.
-pub async fn insert_media(conn: &DbConn, name: String, parent_folder: Folder, user_id: i32, image_dimensions: (u32, u32), description: Option<String>, media_scanned: PathBuf) {
+/// Inserts new media and returns its UUID.
+pub async fn insert_media(conn: &DbConn, name: String, parent_folder: Folder, user_id: i32, image_dimensions: (u32, u32), description: Option<String>, media_scanned: PathBuf) -> Result<String, DbError> {
   conn.run(move |c| {
     let uuid = Uuid::new_v4().to_string();
-    let new_media = NewMedia::new(name.clone(), parent_folder.id, user_id, image_dimensions.0, image_dimensions.1, description, NaiveDateTime::from_timestamp(10, 10), uuid, hash_file(&media_scanned, SHA2512));
+    let new_media = NewMedia::new(name, parent_folder.id, user_id, image_dimensions.0, image_dimensions.1, description, NaiveDateTime::from_timestamp(10, 10), None, uuid.clone(), hash_file(&media_scanned, SHA2512))
+      .with_metadata(MediaMetadata::read_or_modified(&media_scanned));
 
+    diesel::insert_into(media::table)
+      .values(new_media)
+      .execute(c)?;
+
+    Ok(uuid)
+  }).await
+}
+
+/// Inserts many media in one statement.
+/// # Example
+/// ```
+/// let inserted = insert_media_batch(&conn, vec![new_media_1, new_media_2]).await;
+/// ```
+pub async fn insert_media_batch(conn: &DbConn, new_media: Vec<NewMedia>) -> Result<usize, DbError> {
+  conn.run(move |c| {
     diesel::insert_into(media::table)
       .values(new_media)
       .execute(c)
-      .unwrap_or_else(|_| panic!("Error inserting file {:?}", name))
-  }).await;
+  }).await
 }
 
-/// Returns a skeleton media list.
-pub async fn get_media_structure(conn: &DbConn, user_id: i32) -> Vec<MediaResponse> {
-  let structure: Vec<Media> = conn.run(move |c| {
-    media::table
-      .select(media::table::all_columns())
+/// Orders and filters the media query and applies the pagination to it.\
+/// See `routes::pagination` for the ordering guarantees.
+/// # Example
+/// Selects the first 10 media of a user with ID 1.
+/// ```
+/// let pagination = MediaPagination { limit: Some(10), ..Default::default() };
+/// let query = paginate(media::table.filter(media::owner_id.eq(1)).into_boxed(), &pagination);
+/// ```
+/// Malformed cursors and dates are ignored, routes should reject them beforehand.
+pub fn paginate<'a>(query: media::BoxedQuery<'a, Mysql>, pagination: &MediaPagination) -> media::BoxedQuery<'a, Mysql> {
+  let descending = pagination.order() == SortOrder::Desc;
+
+  let mut query = match (pagination.sort(), descending) {
+    (MediaSort::DateTaken, true) => query.order((media::date_taken.desc(), media::id.desc())),
+    (MediaSort::DateTaken, false) => query.order((media::date_taken.asc(), media::id.asc())),
+    (MediaSort::Filename, true) => query.order((media::filename.desc(), media::id.desc())),
+    (MediaSort::Filename, false) => query.order((media::filename.asc(), media::id.asc())),
+    (MediaSort::NaturalFilename, true) => query.order((media::filename_sort_key.desc(), media::id.desc())),
+    (MediaSort::NaturalFilename, false) => query.order((media::filename_sort_key.asc(), media::id.asc())),
+    (MediaSort::Created, true) => query.order(media::id.desc()),
+    (MediaSort::Created, false) => query.order(media::id.asc()),
+  };
+
+  if let Ok((from, to)) = pagination.date_range() {
+    if let Some(from) = from {
+      query = query.filter(media::date_taken.ge(from));
+    }
+
+    if let Some(to) = to {
+      query = query.filter(media::date_taken.lt(to));
+    }
+  }
+
+  if let Some(name) = pagination.tag.clone() {
+    // media are tagged only by their owners, so the name is enough
+    query = query.filter(media::id.eq_any(
+      media_tag::table
+        .inner_join(tag::table)
+        .select(media_tag::media_id)
+        .filter(tag::name.eq(name))
+    ));
+  }
+
+  if let Ok(Some(cursor)) = pagination.decoded_cursor() {
+    let id = cursor.id;
+
+    query = match (cursor.key, descending) {
+      (CursorKey::DateTaken(date_taken), true) => query.filter(
+        media::date_taken.lt(date_taken).or(media::date_taken.eq(date_taken).and(media::id.lt(id)))
+      ),
+      (CursorKey::DateTaken(date_taken), false) => query.filter(
+        media::date_taken.gt(date_taken).or(media::date_taken.eq(date_taken).and(media::id.gt(id)))
+      ),
+      (CursorKey::Filename(filename), true) => query.filter(
+        media::filename.lt(filename.clone()).or(media::filename.eq(filename).and(media::id.lt(id)))
+      ),
+      (CursorKey::Filename(filename), false) => query.filter(
+        media::filename.gt(filename.clone()).or(media::filename.eq(filename).and(media::id.gt(id)))
+      ),
+      (CursorKey::NaturalFilename(key), true) => query.filter(
+        media::filename_sort_key.lt(key.clone()).or(media::filename_sort_key.eq(key).and(media::id.lt(id)))
+      ),
+      (CursorKey::NaturalFilename(key), false) => query.filter(
+        media::filename_sort_key.gt(key.clone()).or(media::filename_sort_key.eq(key).and(media::id.gt(id)))
+      ),
+      (CursorKey::Created, true) => query.filter(media::id.lt(id)),
+      (CursorKey::Created, false) => query.filter(media::id.gt(id)),
+    };
+  }
+
+  if let Some(limit) = pagination.limit() {
+    query = query.limit(limit);
+  }
+
+  if let Some(offset) = pagination.offset() {
+    // MySQL doesn't support OFFSET without LIMIT
+    if pagination.limit().is_none() {
+      query = query.limit(i64::MAX);
+    }
+
+    query = query.offset(offset);
+  }
+
+  query
+}
+
+/// Returns a page of user's media, optionally only from one folder (without its subfolders).\
+/// Media whose files are missing are left out, like in other listings.
+pub async fn get_media_structure(conn: &DbConn, user_id: i32, folder_id: Option<i32>, pagination: MediaPagination) -> Result<Vec<Media>, DbError> {
+  conn.run(move |c| {
+    let mut query = media::table
       .filter(media::owner_id.eq(user_id))
+      .filter(media::missing_since.is_null())
+      .into_boxed();
+
+    if let Some(folder_id) = folder_id {
+      query = query.filter(media::folder_id.eq(folder_id));
+    }
+
+    paginate(query, &pagination)
       .load::<Media>(c)
-      .unwrap()
-  }).await;
+  }).await
+}
 
-  let mut vec: Vec<MediaResponse> = vec!();
+/// Counts media in each folder of the user (without subfolders); folders without media are left out.
+pub async fn count_folder_media(conn: &DbConn, user_id: i32) -> Result<HashMap<i32, i64>, DbError> {
+  // diesel 1.4 can't group queries, so only folder IDs are selected and counted here
+  let folder_ids: Vec<i32> = conn.run(move |c| {
+    media::table
+      .select(media::folder_id)
+      .filter(media::owner_id.eq(user_id).and(media::missing_since.is_null()))
+      .load::<i32>(c)
+  }).await?;
 
-  for response in structure {
-    vec.push(
-      MediaResponse::from(response)
-    )
+  let mut counts = HashMap::new();
+  for folder_id in folder_ids {
+    *counts.entry(folder_id).or_insert(0) += 1;
   }
 
-  vec
+  Ok(counts)
+}
+
+/// Selects IDs of the user's media in the folders, ordered by the time they were taken.
+pub async fn select_folder_media_ids(conn: &DbConn, user_id: i32, folder_ids: Vec<i32>) -> Result<Vec<i32>, DbError> {
+  conn.run(move |c| {
+    media::table
+      .select(media::id)
+      .filter(media::owner_id.eq(user_id).and(media::folder_id.eq_any(folder_ids)))
+      .order((media::date_taken.asc(), media::id.asc()))
+      .load::<i32>(c)
+  }).await
+}
+
+/// Selects UUIDs and coordinates of the user's media within the bounds, the newest first.
+pub async fn select_geo_media(conn: &DbConn, user_id: i32, bounds: GeoBounds) -> Result<Vec<(String, f64, f64)>, DbError> {
+  let media: Vec<(String, Option<f64>, Option<f64>)> = conn.run(move |c| {
+    let mut query = media::table
+      .select((media::uuid, media::latitude, media::longitude))
+      .filter(media::owner_id.eq(user_id))
+      .filter(media::latitude.between(bounds.south, bounds.north))
+      .filter(media::missing_since.is_null())
+      .into_boxed();
+
+    query = if bounds.crosses_antimeridian() {
+      query.filter(media::longitude.ge(bounds.west).or(media::longitude.le(bounds.east)))
+    } else {
+      query.filter(media::longitude.between(bounds.west, bounds.east))
+    };
+
+    query
+      .order((media::date_taken.desc(), media::id.desc()))
+      .load::<(String, Option<f64>, Option<f64>)>(c)
+  }).await?;
+
+  // the filters exclude media without coordinates
+  Ok(media.into_iter().filter_map(|(uuid, latitude, longitude)| Some((uuid, latitude?, longitude?))).collect())
 }
 
 /// Tries to select a media ID from its UUID.
-pub async fn select_media_id(conn: &DbConn, media_uuid: String) -> Option<i32> {
+pub async fn select_media_id(conn: &DbConn, media_uuid: String) -> Result<Option<i32>, DbError> {
   conn.run(move |c| {
     media::table
       .select(media::id)
       .filter(media::dsl::uuid.eq(media_uuid))
       .first::<i32>(c)
       .optional()
-      .unwrap()
   }).await
 }
 
-/// Checks whether a user has access to the media.
+/// Tries to select a media from its UUID.
+pub async fn select_media_by_uuid(conn: &DbConn, media_uuid: String) -> Result<Option<Media>, DbError> {
+  conn.run(move |c| {
+    media::table
+      .filter(media::dsl::uuid.eq(media_uuid))
+      .first::<Media>(c)
+      .optional()
+  }).await
+}
+
+/// Selects hashes of current versions of media from the given list.\
+/// Hashes are compared case-insensitively by the column's collation.
+pub async fn select_existing_media_hashes(conn: &DbConn, hashes: Vec<String>) -> Result<Vec<String>, DbError> {
+  conn.run(move |c| {
+    media::table
+      .select(media::sha2_512)
+      .distinct()
+      .filter(media::sha2_512.eq_any(hashes))
+      .get_results::<String>(c)
+  }).await
+}
+
+/// Checks whether a user has access to the media.\
+/// Users have access to their own media, to media that were shared with them
+/// and to media in albums they own or are members of.
 // TODO: check more places for permissions
-pub async fn media_user_has_access(conn: &DbConn, media_uuid: String, owner_id: i32) -> Result<bool, diesel::result::Error> {
+pub async fn media_user_has_access(conn: &DbConn, media_uuid: String, user_id: i32) -> Result<bool, DbError> {
+  conn.run(move |c| {
+    let album_ids = album::table
+      .select(album::id)
+      .filter(album::owner_id.eq(user_id).or(album::id.eq_any(
+        album_invite::table
+          .select(album_invite::album_id)
+          .filter(album_invite::invited_user_id.eq(user_id).and(album_invite::accepted.eq(true)))
+      )));
+
+    diesel::dsl::select(
+        diesel::dsl::exists(
+          media::table.filter(
+            media::dsl::uuid.eq(media_uuid).and(
+              media::dsl::owner_id.eq(user_id)
+                .or(media::dsl::id.eq_any(
+                  media_grant::table
+                    .select(media_grant::media_id)
+                    .filter(media_grant::user_id.eq(user_id))
+                ))
+                .or(media::dsl::id.eq_any(
+                  album_media::table
+                    .select(album_media::media_id)
+                    .filter(album_media::album_id.eq_any(album_ids))
+                ))
+            )
+          )
+        )
+      )
+      .get_result(c)
+  }).await
+}
+
+/// Checks whether a user owns the media.\
+/// Unlike `media_user_has_access()`, this doesn't include media shared with the user.
+pub async fn media_user_is_owner(conn: &DbConn, media_uuid: String, user_id: i32) -> Result<bool, DbError> {
   conn.run(move |c| {
     diesel::dsl::select(
         diesel::dsl::exists(
           media::table.filter(
-            media::dsl::uuid.eq(media_uuid).and(media::dsl::owner_id.eq(owner_id))
+            media::dsl::uuid.eq(media_uuid).and(media::dsl::owner_id.eq(user_id))
           )
         )
       )
@@ -91,18 +307,72 @@ pub async fn media_user_has_access(conn: &DbConn, media_uuid: String, owner_id:
   }).await
 }
 
-/// Likes the media.
-pub async fn media_like(conn: &DbConn, media_id: i32, user_id: i32) -> Result<usize, diesel::result::Error> {
+/// Shares the media with a user.
+pub async fn insert_media_grant(conn: &DbConn, media_id: i32, user_id: i32) -> Result<usize, DbError> {
+  let new_grant = NewMediaGrant::new(media_id, user_id);
+  conn.run(move |c| {
+    diesel::insert_into(media_grant::table)
+      .values(new_grant)
+      .execute(c)
+  }).await
+}
+
+/// Stops sharing the media with a user.
+pub async fn delete_media_grant(conn: &DbConn, media_id: i32, user_id: i32) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::delete(
+      media_grant::table
+        .filter(media_grant::media_id.eq(media_id).and(media_grant::user_id.eq(user_id)))
+    )
+      .execute(c)
+  }).await
+}
+
+/// Selects usernames of users the media is shared with, together with the time it was shared.
+pub async fn select_media_grants(conn: &DbConn, media_id: i32) -> Result<Vec<(String, NaiveDateTime)>, DbError> {
+  conn.run(move |c| {
+    media_grant::table
+      .inner_join(user::table)
+      .select((user::username, media_grant::created_at))
+      .filter(media_grant::media_id.eq(media_id))
+      .order(media_grant::created_at.asc())
+      .get_results(c)
+  }).await
+}
+
+/// Likes the media; returns 0 when the user already likes it (the unique key of `favorite_media` ignores the like).
+pub async fn media_like(conn: &DbConn, media_id: i32, user_id: i32) -> Result<usize, DbError> {
   let new_like = NewFavoriteMedia::new(media_id, user_id);
   conn.run(move |c| {
-    diesel::insert_into(favorite_media::table)
+    diesel::insert_or_ignore_into(favorite_media::table)
       .values(new_like)
       .execute(c)
   }).await
 }
 
+/// Checks whether the user likes the media.
+pub async fn media_is_liked(conn: &DbConn, media_id: i32, user_id: i32) -> Result<bool, DbError> {
+  conn.run(move |c| {
+    diesel::select(diesel::dsl::exists(
+      favorite_media::table
+        .filter(favorite_media::media_id.eq(media_id).and(favorite_media::user_id.eq(user_id)))
+    ))
+      .get_result::<bool>(c)
+  }).await
+}
+
+/// Selects likes of the media as pairs of media and user IDs.
+pub async fn select_media_likes(conn: &DbConn, media_ids: Vec<i32>) -> Result<Vec<(i32, i32)>, DbError> {
+  conn.run(move |c| {
+    favorite_media::table
+      .select((favorite_media::media_id, favorite_media::user_id))
+      .filter(favorite_media::media_id.eq_any(media_ids))
+      .get_results::<(i32, i32)>(c)
+  }).await
+}
+
 /// Unlikes the media.
-pub async fn media_unlike(conn: &DbConn, media_id: i32, user_id: i32) -> Result<usize, diesel::result::Error> {
+pub async fn media_unlike(conn: &DbConn, media_id: i32, user_id: i32) -> Result<usize, DbError> {
   conn.run(move |c| {
     diesel::delete(
       favorite_media::table
@@ -112,25 +382,409 @@ pub async fn media_unlike(conn: &DbConn, media_id: i32, user_id: i32) -> Result<
   }).await
 }
 
-/// Gets a list of liked media.
-pub async fn get_liked_media(conn: &DbConn, user_id: i32) -> Result<Vec<Media>, diesel::result::Error> {
+/// Gets a page of liked media.
+pub async fn get_liked_media(conn: &DbConn, user_id: i32, pagination: MediaPagination) -> Result<Vec<Media>, DbError> {
   conn.run(move |c| {
-    media::table
-      .select(media::table::all_columns())
+    let query = media::table
       .filter(media::id.eq_any(
         favorite_media::table
           .select(favorite_media::media_id)
           .filter(favorite_media::user_id.eq(user_id))
       ))
+      .filter(media::missing_since.is_null())
+      .into_boxed();
+
+    paginate(query, &pagination)
       .get_results::<Media>(c)
   }).await
 }
 
+/// Gets a page of media shared with the user by other users.
+pub async fn get_shared_media(conn: &DbConn, user_id: i32, pagination: MediaPagination) -> Result<Vec<Media>, DbError> {
+  conn.run(move |c| {
+    let query = media::table
+      .filter(media::id.eq_any(
+        media_grant::table
+          .select(media_grant::media_id)
+          .filter(media_grant::user_id.eq(user_id))
+      ))
+      .filter(media::missing_since.is_null())
+      .into_boxed();
+
+    paginate(query, &pagination)
+      .get_results::<Media>(c)
+  }).await
+}
+
+/// Returns all versions of the media ordered from the original.
+pub async fn select_media_versions(conn: &DbConn, media_id: i32) -> Result<Vec<MediaVersion>, DbError> {
+  conn.run(move |c| {
+    media_version::table
+      .filter(media_version::media_id.eq(media_id))
+      .order(media_version::version.asc())
+      .get_results::<MediaVersion>(c)
+  }).await
+}
+
+pub async fn select_media_version(conn: &DbConn, media_id: i32, version: i32) -> Result<Option<MediaVersion>, DbError> {
+  conn.run(move |c| {
+    media_version::table
+      .filter(media_version::media_id.eq(media_id).and(media_version::version.eq(version)))
+      .first::<MediaVersion>(c)
+      .optional()
+  }).await
+}
+
+/// Returns the number the next version of the media will have.
+pub async fn select_next_media_version(conn: &DbConn, media_id: i32) -> Result<i32, DbError> {
+  conn.run(move |c| {
+    let last = media_version::table
+      .select(media_version::version)
+      .filter(media_version::media_id.eq(media_id))
+      .order(media_version::version.desc())
+      .first::<i32>(c)
+      .optional()?;
+
+    Ok(last.unwrap_or(0) + 1)
+  }).await
+}
+
+/// Inserts an edited version and makes it the current version of the media.\
+/// The original is recorded as the version 0 on the first edit.
+pub async fn insert_media_version(conn: &DbConn, original: NewMediaVersion, edited: NewMediaVersion) -> Result<(), DbError> {
+  conn.run(move |c| {
+    c.transaction(|| {
+      let original_exists = diesel::select(diesel::dsl::exists(
+        media_version::table.filter(media_version::media_id.eq(original.media_id).and(media_version::version.eq(0)))
+      )).get_result::<bool>(c)?;
+
+      if !original_exists {
+        diesel::insert_into(media_version::table).values(&original).execute(c)?;
+      }
+
+      diesel::insert_into(media_version::table).values(&edited).execute(c)?;
+
+      set_current_version(c, edited.media_id, edited.version, edited.width, edited.height, edited.sha2_512.clone(), edited.orientation)
+    })
+  }).await
+}
+
+/// Makes an existing version the current version of the media.
+pub async fn revert_media_version(conn: &DbConn, version: MediaVersion) -> Result<(), DbError> {
+  conn.run(move |c| {
+    set_current_version(c, version.media_id, version.version, version.width, version.height, version.sha2_512, version.orientation)
+  }).await
+}
+
+fn set_current_version(c: &diesel::MysqlConnection, media_id: i32, version: i32, width: u32, height: u32, sha2_512: String, orientation: Option<u16>) -> Result<(), DbError> {
+  diesel::update(media::table.filter(media::id.eq(media_id)))
+    .set((
+      media::dsl::version.eq(version),
+      media::dsl::width.eq(width),
+      media::dsl::height.eq(height),
+      media::dsl::sha2_512.eq(sha2_512),
+      media::dsl::orientation.eq(orientation),
+    ))
+    .execute(c)?;
+
+  Ok(())
+}
+
 /// Updates media description.
-pub async fn update_description(conn: &DbConn, media_id: i32, description: Option<String>) -> Result<usize, diesel::result::Error> {
+pub async fn update_description(conn: &DbConn, media_id: i32, description: Option<String>) -> Result<usize, DbError> {
   conn.run(move |c| {
     diesel::update(media::table.filter(media::id.eq(media_id)))
       .set(media::dsl::description.eq(description))
       .execute(c)
   }).await
 }
+
+/// Applies one change to all the media in a transaction, so either all of them are changed or none.\
+/// `description` is set when it's `Some` (`Some(None)` removes it); media already present in an album aren't added twice.
+pub async fn update_media_batch(conn: &DbConn, user_id: i32, media_ids: Vec<i32>, description: Option<Option<String>>, date_taken: Option<DateTime<FixedOffset>>, add_album_ids: Vec<i32>, remove_album_ids: Vec<i32>) -> Result<(), DbError> {
+  conn.run(move |c| {
+    c.transaction(|| {
+      if let Some(description) = description {
+        diesel::update(media::table.filter(media::id.eq_any(&media_ids)))
+          .set(media::description.eq(description))
+          .execute(c)?;
+      }
+
+      if let Some(date_taken) = date_taken {
+        diesel::update(media::table.filter(media::id.eq_any(&media_ids)))
+          .set((media::date_taken.eq(date_taken.naive_local()), media::date_taken_offset.eq(date_taken.offset().local_minus_utc())))
+          .execute(c)?;
+      }
+
+      let new_album_media: Vec<NewAlbumMedia> = add_album_ids.iter()
+        .flat_map(|album_id| media_ids.iter().map(move |media_id| NewAlbumMedia::new(*album_id, *media_id, Some(user_id))))
+        .collect();
+
+      if !new_album_media.is_empty() {
+        diesel::insert_or_ignore_into(album_media::table)
+          .values(new_album_media)
+          .execute(c)?;
+      }
+
+      if !remove_album_ids.is_empty() {
+        diesel::delete(album_media::table.filter(album_media::album_id.eq_any(&remove_album_ids).and(album_media::media_id.eq_any(&media_ids))))
+          .execute(c)?;
+      }
+
+      Ok(())
+    })
+  }).await
+}
+
+/// Returns all media of the user which have a description.
+pub async fn select_media_with_description(conn: &DbConn, user_id: i32) -> Result<Vec<Media>, DbError> {
+  conn.run(move |c| {
+    media::table
+      .filter(media::owner_id.eq(user_id).and(media::description.is_not_null()))
+      .order(media::id.asc())
+      .get_results::<Media>(c)
+  }).await
+}
+
+/// Updates the hash of the original file after it was changed.\
+/// Edited media keep the original as the version 0, so its hash is stored there.
+pub async fn update_original_hash(conn: &DbConn, media_id: i32, current_version: i32, sha2_512: String) -> Result<usize, DbError> {
+  conn.run(move |c| set_original_hash(c, media_id, current_version, sha2_512)).await
+}
+
+fn set_original_hash(c: &diesel::MysqlConnection, media_id: i32, current_version: i32, sha2_512: String) -> Result<usize, DbError> {
+  if current_version == 0 {
+    return diesel::update(media::table.filter(media::id.eq(media_id)))
+      .set(media::dsl::sha2_512.eq(sha2_512))
+      .execute(c);
+  }
+
+  diesel::update(media_version::table.filter(media_version::media_id.eq(media_id).and(media_version::version.eq(0))))
+    .set(media_version::dsl::sha2_512.eq(sha2_512))
+    .execute(c)
+}
+
+/// Counts media of all users.
+pub async fn count_media(conn: &DbConn) -> Result<i64, DbError> {
+  conn.run(move |c| {
+    media::table
+      .count()
+      .get_result::<i64>(c)
+  }).await
+}
+
+/// Counts media of the user.
+pub async fn count_user_media(conn: &DbConn, user_id: i32) -> Result<i64, DbError> {
+  conn.run(move |c| {
+    media::table
+      .filter(media::owner_id.eq(user_id))
+      .count()
+      .get_result::<i64>(c)
+  }).await
+}
+
+/// Selects media without a natural sort key; they were added before natural sorting existed.
+pub async fn select_media_without_sort_key(conn: &DbConn, limit: i64) -> Result<Vec<(i32, String)>, DbError> {
+  conn.run(move |c| {
+    media::table
+      .select((media::id, media::filename))
+      .filter(media::filename_sort_key.is_null())
+      .limit(limit)
+      .get_results::<(i32, String)>(c)
+  }).await
+}
+
+/// Stores natural sort keys of media.
+pub async fn update_media_sort_keys(conn: &DbConn, keys: Vec<(i32, String)>) -> Result<(), DbError> {
+  conn.run(move |c| {
+    c.transaction(|| {
+      for (media_id, key) in keys {
+        diesel::update(media::table.filter(media::id.eq(media_id)))
+          .set(media::filename_sort_key.eq(key))
+          .execute(c)?;
+      }
+
+      Ok(())
+    })
+  }).await
+}
+
+/// Selects media which may be dated by the modification time of their files (their date is in UTC),
+/// with IDs greater than `after_id`, ordered by their IDs.\
+/// Returns their IDs, filenames, dates taken and modification times.
+pub async fn select_media_dated_by_modification(conn: &DbConn, after_id: i32, limit: i64) -> Result<Vec<(i32, String, NaiveDateTime, NaiveDateTime)>, DbError> {
+  let media: Vec<(i32, String, NaiveDateTime, Option<NaiveDateTime>)> = conn.run(move |c| {
+    media::table
+      .select((media::id, media::filename, media::date_taken, media::file_modified_at))
+      .filter(media::id.gt(after_id).and(media::date_taken_offset.eq(0)).and(media::file_modified_at.is_not_null()))
+      .order(media::id.asc())
+      .limit(limit)
+      .get_results(c)
+  }).await?;
+
+  Ok(media.into_iter()
+    .filter_map(|(id, filename, date_taken, modified)| Some((id, filename, date_taken, modified?)))
+    .collect())
+}
+
+/// Sets dates when the media were taken, without UTC offsets.
+pub async fn update_media_dates(conn: &DbConn, dates: Vec<(i32, NaiveDateTime)>) -> Result<(), DbError> {
+  conn.run(move |c| {
+    c.transaction(|| {
+      for (media_id, date_taken) in dates {
+        diesel::update(media::table.filter(media::id.eq(media_id)))
+          .set((media::date_taken.eq(date_taken), media::date_taken_offset.eq(None::<i32>)))
+          .execute(c)?;
+      }
+
+      Ok(())
+    })
+  }).await
+}
+
+/// Checks whether the user has media with the given hash.
+pub async fn media_hash_exists(conn: &DbConn, user_id: i32, sha2_512: String) -> Result<bool, DbError> {
+  conn.run(move |c| {
+    diesel::select(diesel::dsl::exists(
+      media::table.filter(media::owner_id.eq(user_id).and(media::sha2_512.eq(sha2_512)))
+    ))
+      .get_result::<bool>(c)
+  }).await
+}
+
+/// Selects media of the user sharing their hash with other media of the user, ordered by hash.
+pub async fn select_duplicate_media(conn: &DbConn, user_id: i32) -> Result<Vec<Media>, DbError> {
+  conn.run(move |c| {
+    let hashes = media::table
+      .select(media::sha2_512)
+      .filter(media::owner_id.eq(user_id))
+      .get_results::<String>(c)?;
+
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for hash in hashes {
+      *counts.entry(hash).or_default() += 1;
+    }
+
+    let duplicated: Vec<String> = counts.into_iter()
+      .filter(|(_, count)| *count > 1)
+      .map(|(hash, _)| hash)
+      .collect();
+
+    if duplicated.is_empty() { return Ok(vec![]) }
+
+    media::table
+      .filter(media::owner_id.eq(user_id).and(media::sha2_512.eq_any(duplicated)))
+      .order((media::sha2_512.asc(), media::id.asc()))
+      .get_results::<Media>(c)
+  }).await
+}
+
+/// Selects files of media in the folder as recorded by the last scan.
+pub async fn select_scanned_files(conn: &DbConn, folder_id: i32) -> Result<Vec<ScannedFile>, DbError> {
+  conn.run(move |c| {
+    media::table
+      .select((media::id, media::filename, media::version, media::file_size, media::file_modified_at, media::missing_since))
+      .filter(media::folder_id.eq(folder_id))
+      .get_results::<ScannedFile>(c)
+  }).await
+}
+
+/// Sets or clears the time since which files of the media are missing.
+pub async fn update_media_missing_since(conn: &DbConn, media_ids: Vec<i32>, missing_since: Option<NaiveDateTime>) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::update(media::table.filter(media::id.eq_any(media_ids)))
+      .set(media::missing_since.eq(missing_since))
+      .execute(c)
+  }).await
+}
+
+/// Selects media of the user whose files are missing, the longest missing first.
+pub async fn select_missing_media(conn: &DbConn, user_id: i32) -> Result<Vec<Media>, DbError> {
+  conn.run(move |c| {
+    media::table
+      .filter(media::owner_id.eq(user_id).and(media::missing_since.is_not_null()))
+      .order((media::missing_since.asc(), media::id.asc()))
+      .get_results::<Media>(c)
+  }).await
+}
+
+/// Deletes media of the user whose files are missing, optionally only the one with the UUID, and returns their hashes.\
+/// Thumbnails of albums showing them are replaced; rows referencing them (album media, grants, tags...)
+/// are deleted by the database.
+pub async fn delete_missing_media(conn: &DbConn, user_id: i32, media_uuid: Option<String>) -> Result<Vec<String>, DbError> {
+  conn.run(move |c| {
+    c.transaction(|| {
+      let mut query = media::table
+        .select((media::id, media::sha2_512))
+        .filter(media::owner_id.eq(user_id).and(media::missing_since.is_not_null()))
+        .into_boxed();
+
+      if let Some(media_uuid) = media_uuid {
+        query = query.filter(media::uuid.eq(media_uuid));
+      }
+
+      let (media_ids, hashes): (Vec<i32>, Vec<String>) = query.get_results::<(i32, String)>(c)?.into_iter().unzip();
+
+      replace_album_thumbnails_of_media(c, &media_ids)?;
+
+      diesel::delete(favorite_media::table.filter(favorite_media::media_id.eq_any(&media_ids)))
+        .execute(c)?;
+
+      diesel::delete(media::table.filter(media::id.eq_any(&media_ids)))
+        .execute(c)?;
+
+      Ok(hashes)
+    })
+  }).await
+}
+
+/// Checks whether a media with the filename is in the folder.
+pub async fn media_file_exists(conn: &DbConn, folder_id: i32, filename: String) -> Result<bool, DbError> {
+  conn.run(move |c| {
+    diesel::select(diesel::dsl::exists(
+      media::table.filter(media::folder_id.eq(folder_id).and(media::filename.eq(filename)))
+    ))
+      .get_result::<bool>(c)
+  }).await
+}
+
+/// Points missing media to another file and clears the mark; the file's hash becomes the hash of the original.
+pub async fn relink_media(conn: &DbConn, media: Media, folder_id: i32, filename: String, stat: FileStat, sha2_512: String) -> Result<(), DbError> {
+  conn.run(move |c| {
+    c.transaction(|| {
+      diesel::update(media::table.filter(media::id.eq(media.id)))
+        .set((
+          media::folder_id.eq(folder_id),
+          media::filename_sort_key.eq(natural_sort_key(&filename)),
+          media::filename.eq(filename),
+          media::file_size.eq(stat.size),
+          media::file_modified_at.eq(stat.modified),
+          media::missing_since.eq(None::<NaiveDateTime>),
+        ))
+        .execute(c)?;
+
+      set_original_hash(c, media.id, media.version, sha2_512)?;
+
+      Ok(())
+    })
+  }).await
+}
+
+/// Stores the current size and modification time of scanned files, and the new hash of changed originals.
+pub async fn update_scanned_files(conn: &DbConn, changes: Vec<ScannedFileChange>) -> Result<(), DbError> {
+  conn.run(move |c| {
+    c.transaction(|| {
+      for change in changes {
+        diesel::update(media::table.filter(media::id.eq(change.media_id)))
+          .set((media::file_size.eq(change.stat.size), media::file_modified_at.eq(change.stat.modified)))
+          .execute(c)?;
+
+        if let Some(sha2_512) = change.sha2_512 {
+          set_original_hash(c, change.media_id, change.version, sha2_512)?;
+        }
+      }
+
+      Ok(())
+    })
+  }).await
+}
@@ -1,6 +1,21 @@
+//! Database queries.\
+//! Queries never panic; failures are returned as `DbError` and turned into 500 by routes (see `errors::ApiError`),
+//! so a lost connection fails only the requests which were using it.
+
 pub mod albums;
+pub mod detection;
 pub mod folders;
 pub mod general;
+pub mod integrity;
+pub mod jobs;
+pub mod libraries;
 pub mod media;
+pub mod organizations;
+pub mod people;
+pub mod scan;
+pub mod tags;
 pub mod tokens;
 pub mod users;
+
+/// Error of a failed query.
+pub type DbError = diesel::result::Error;
@@ -0,0 +1,223 @@
+use crate::auth::token::hash_token;
+use crate::models::{NewOrganization, NewUser, NewUserInvite, Organization, OrganizationAdmin, User, UserInvite};
+use crate::schema::{organization, organization_admin, user, user_invite};
+use crate::db::DbError;
+use crate::DbConn;
+use chrono::{NaiveDateTime, Utc};
+use diesel::BoolExpressionMethods;
+use diesel::Connection;
+use diesel::ExpressionMethods;
+use diesel::OptionalExtension;
+use diesel::QueryDsl;
+use diesel::RunQueryDsl;
+use diesel::Table;
+
+/// Returns the ID of the default organization (the oldest one), which users registered through `/user` join.
+pub async fn select_default_organization_id(conn: &DbConn) -> Result<Option<i32>, DbError> {
+  conn.run(move |c| {
+    organization::table
+      .select(organization::id)
+      .order(organization::id.asc())
+      .first::<i32>(c)
+      .optional()
+  }).await
+}
+
+pub async fn select_organization(conn: &DbConn, organization_id: i32) -> Result<Organization, DbError> {
+  conn.run(move |c| {
+    organization::table
+      .filter(organization::id.eq(organization_id))
+      .first::<Organization>(c)
+  }).await
+}
+
+/// Selects the organization of the user.
+pub async fn select_user_organization(conn: &DbConn, user_id: i32) -> Result<Organization, DbError> {
+  conn.run(move |c| {
+    organization::table
+      .inner_join(user::table)
+      .select(organization::table::all_columns())
+      .filter(user::id.eq(user_id))
+      .first::<Organization>(c)
+  }).await
+}
+
+/// Inserts a new organization and returns it.
+pub async fn insert_organization(conn: &DbConn, new_organization: NewOrganization) -> Result<Organization, DbError> {
+  conn.run(move |c| {
+    c.transaction(|| {
+      diesel::insert_into(organization::table)
+        .values(&new_organization)
+        .execute(c)?;
+
+      organization::table
+        .filter(organization::uuid.eq(&new_organization.uuid))
+        .first::<Organization>(c)
+    })
+  }).await
+}
+
+/// Counts all organizations.
+pub async fn count_organizations(conn: &DbConn) -> Result<i64, DbError> {
+  conn.run(move |c| {
+    organization::table
+      .count()
+      .get_result::<i64>(c)
+  }).await
+}
+
+pub async fn update_organization_name(conn: &DbConn, organization_id: i32, name: String) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::update(organization::table.filter(organization::id.eq(organization_id)))
+      .set(organization::name.eq(name))
+      .execute(c)
+  }).await
+}
+
+/// Returns users of the organization and whether they are its administrators, ordered by username.
+/// Inserts users into the organization; either all of them are inserted or none.
+pub async fn insert_organization_users(conn: &DbConn, users: Vec<NewUser>, organization_id: i32) -> Result<(), DbError> {
+  conn.run(move |c| {
+    c.transaction(|| {
+      for new_user in users {
+        diesel::insert_into(user::table)
+          .values((new_user, user::organization_id.eq(organization_id)))
+          .execute(c)?;
+      }
+
+      Ok(())
+    })
+  }).await
+}
+
+pub async fn select_organization_users(conn: &DbConn, organization_id: i32) -> Result<Vec<(User, bool)>, DbError> {
+  conn.run(move |c| {
+    let users = user::table
+      .filter(user::organization_id.eq(organization_id))
+      .order(user::username.asc())
+      .get_results::<User>(c)?;
+
+    let admins = organization_admin::table
+      .select(organization_admin::user_id)
+      .filter(organization_admin::organization_id.eq(organization_id))
+      .get_results::<i32>(c)?;
+
+    Ok(users.into_iter().map(|user| { let admin = admins.contains(&user.id); (user, admin) }).collect())
+  }).await
+}
+
+pub async fn is_organization_admin(conn: &DbConn, user_id: i32) -> Result<bool, DbError> {
+  conn.run(move |c| {
+    diesel::select(diesel::dsl::exists(
+      organization_admin::table.filter(organization_admin::user_id.eq(user_id))
+    )).get_result::<bool>(c)
+  }).await
+}
+
+pub async fn count_organization_admins(conn: &DbConn, organization_id: i32) -> Result<i64, DbError> {
+  conn.run(move |c| {
+    organization_admin::table
+      .filter(organization_admin::organization_id.eq(organization_id))
+      .count()
+      .get_result::<i64>(c)
+  }).await
+}
+
+/// Makes the user an administrator of the organization; does nothing when the user already is one.
+pub async fn insert_organization_admin(conn: &DbConn, admin: OrganizationAdmin) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::replace_into(organization_admin::table)
+      .values(admin)
+      .execute(c)
+  }).await
+}
+
+pub async fn delete_organization_admin(conn: &DbConn, user_id: i32) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::delete(organization_admin::table.filter(organization_admin::user_id.eq(user_id)))
+      .execute(c)
+  }).await
+}
+
+/// Checks whether both users belong to the same organization.
+pub async fn users_share_organization(conn: &DbConn, user_id: i32, other_user_id: i32) -> Result<bool, DbError> {
+  conn.run(move |c| {
+    let organization_ids = user::table
+      .select(user::organization_id)
+      .filter(user::id.eq(user_id).or(user::id.eq(other_user_id)))
+      .get_results::<i32>(c)?;
+
+    Ok(user_id == other_user_id || organization_ids.len() == 2 && organization_ids[0] == organization_ids[1])
+  }).await
+}
+
+/// Inserts an invite with the given token, of which only the hash is stored, and returns it.
+pub async fn insert_user_invite(conn: &DbConn, token: &str, organization_id: i32, created_by: i32, expiration: NaiveDateTime, admin: bool) -> Result<UserInvite, DbError> {
+  let new_invite = NewUserInvite::new(hash_token(token), organization_id, created_by, expiration, admin);
+
+  conn.run(move |c| {
+    c.transaction(|| {
+      diesel::insert_into(user_invite::table)
+        .values(&new_invite)
+        .execute(c)?;
+
+      user_invite::table
+        .filter(user_invite::token.eq(&new_invite.token))
+        .first::<UserInvite>(c)
+    })
+  }).await
+}
+
+/// Selects invites of the organization which can still be used, newest first.
+pub async fn select_organization_invites(conn: &DbConn, organization_id: i32) -> Result<Vec<UserInvite>, DbError> {
+  conn.run(move |c| {
+    user_invite::table
+      .filter(user_invite::organization_id.eq(organization_id))
+      .filter(user_invite::used_at.is_null().and(user_invite::expiration.gt(Utc::now().naive_utc())))
+      .order(user_invite::id.desc())
+      .get_results::<UserInvite>(c)
+  }).await
+}
+
+pub async fn delete_user_invite(conn: &DbConn, organization_id: i32, invite_id: i32) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::delete(user_invite::table.filter(user_invite::organization_id.eq(organization_id).and(user_invite::id.eq(invite_id))))
+      .execute(c)
+  }).await
+}
+
+/// Marks the invite as used and returns it; `None` when it doesn't exist, expired or was already used.\
+/// Only one of concurrent requests with the same token gets the invite.
+pub async fn claim_user_invite(conn: &DbConn, token: String) -> Result<Option<UserInvite>, DbError> {
+  let token = hash_token(&token);
+
+  conn.run(move |c| {
+    c.transaction(|| {
+      let now = Utc::now().naive_utc();
+
+      let claimed = diesel::update(
+        user_invite::table
+          .filter(user_invite::token.eq(&token))
+          .filter(user_invite::used_at.is_null().and(user_invite::expiration.gt(now)))
+      )
+        .set(user_invite::used_at.eq(now))
+        .execute(c)?;
+
+      if claimed == 0 { return Ok(None) }
+
+      user_invite::table
+        .filter(user_invite::token.eq(&token))
+        .first::<UserInvite>(c)
+        .optional()
+    })
+  }).await
+}
+
+/// Makes a claimed invite usable again, e.g. when the user couldn't be created.
+pub async fn release_user_invite(conn: &DbConn, invite_id: i32) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::update(user_invite::table.filter(user_invite::id.eq(invite_id)))
+      .set(user_invite::used_at.eq(None::<NaiveDateTime>))
+      .execute(c)
+  }).await
+}
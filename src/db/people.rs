@@ -0,0 +1,109 @@
+use crate::models::{Media, MediaFace, NewMediaFace, NewPerson, Person};
+use crate::routes::pagination::MediaPagination;
+use crate::db::media::paginate;
+use crate::schema::{media, media_face, person};
+use crate::db::DbError;
+use crate::DbConn;
+use diesel::BoolExpressionMethods;
+use diesel::ExpressionMethods;
+use diesel::NullableExpressionMethods;
+use diesel::OptionalExtension;
+use diesel::QueryDsl;
+use diesel::RunQueryDsl;
+use diesel::Table;
+use std::collections::HashMap;
+
+pub async fn insert_person(conn: &DbConn, new_person: NewPerson) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::insert_into(person::table)
+      .values(new_person)
+      .execute(c)
+  }).await
+}
+
+/// Selects the user's person by its UUID.
+pub async fn select_person_by_uuid(conn: &DbConn, person_uuid: String, user_id: i32) -> Result<Option<Person>, DbError> {
+  conn.run(move |c| {
+    person::table
+      .filter(person::uuid.eq(person_uuid).and(person::owner_id.eq(user_id)))
+      .first::<Person>(c)
+      .optional()
+  }).await
+}
+
+/// Selects people of the user ordered by their names.
+pub async fn select_people(conn: &DbConn, user_id: i32) -> Result<Vec<Person>, DbError> {
+  conn.run(move |c| {
+    person::table
+      .filter(person::owner_id.eq(user_id))
+      .order((person::name.asc(), person::id.asc()))
+      .load::<Person>(c)
+  }).await
+}
+
+/// Counts media of each person of the user; people without media are left out.
+pub async fn count_people_media(conn: &DbConn, user_id: i32) -> Result<HashMap<i32, i64>, DbError> {
+  // diesel 1.4 can't group queries, so the faces are counted here; a person can be in a media more than once
+  let mut faces: Vec<(i32, i32)> = conn.run(move |c| {
+    media_face::table
+      .inner_join(person::table)
+      .select((person::id, media_face::media_id))
+      .filter(person::owner_id.eq(user_id))
+      .load::<(i32, i32)>(c)
+  }).await?;
+
+  faces.sort_unstable();
+  faces.dedup();
+
+  let mut counts = HashMap::new();
+  for (person_id, _) in faces {
+    *counts.entry(person_id).or_insert(0) += 1;
+  }
+
+  Ok(counts)
+}
+
+/// Gets a page of media with faces of the person.
+pub async fn get_person_media(conn: &DbConn, person_id: i32, pagination: MediaPagination) -> Result<Vec<Media>, DbError> {
+  conn.run(move |c| {
+    let query = media::table
+      .filter(media::id.eq_any(
+        media_face::table
+          .select(media_face::media_id)
+          .filter(media_face::person_id.eq(person_id))
+      ))
+      .filter(media::missing_since.is_null())
+      .into_boxed();
+
+    paginate(query, &pagination)
+      .get_results::<Media>(c)
+  }).await
+}
+
+pub async fn insert_media_face(conn: &DbConn, new_face: NewMediaFace) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::insert_into(media_face::table)
+      .values(new_face)
+      .execute(c)
+  }).await
+}
+
+/// Selects faces of the media together with UUIDs and names of their people.
+pub async fn select_media_faces(conn: &DbConn, media_id: i32) -> Result<Vec<(MediaFace, Option<(String, String)>)>, DbError> {
+  conn.run(move |c| {
+    media_face::table
+      .left_join(person::table)
+      .select((media_face::table::all_columns(), (person::uuid, person::name).nullable()))
+      .filter(media_face::media_id.eq(media_id))
+      .order(media_face::id.asc())
+      .load(c)
+  }).await
+}
+
+/// Removes the face from the media.
+pub async fn delete_media_face(conn: &DbConn, media_id: i32, face_uuid: String) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::delete(media_face::table.filter(media_face::media_id.eq(media_id).and(media_face::uuid.eq(face_uuid))))
+      .execute(c)
+  }).await
+}
@@ -0,0 +1,155 @@
+use crate::models::{FolderScan, NewScanAlert, NewScanIssue, NewUserScanIgnore, ScanAlert, ScanIssue, ScanIssueKind, ScanIssueSeverity};
+use crate::schema::{folder, folder_scan, scan_alert, scan_issue, user, user_scan_ignore};
+use crate::db::DbError;
+use crate::DbConn;
+use chrono::Utc;
+use diesel::BoolExpressionMethods;
+use diesel::Connection;
+use diesel::ExpressionMethods;
+use diesel::OptionalExtension;
+use diesel::QueryDsl;
+use diesel::RunQueryDsl;
+use diesel::Table;
+use diesel::TextExpressionMethods;
+
+/// Selects scan ignore patterns of a user.
+pub async fn select_scan_ignore_patterns(conn: &DbConn, user_id: i32) -> Result<Vec<String>, DbError> {
+  conn.run(move |c| {
+    user_scan_ignore::table
+      .select(user_scan_ignore::pattern)
+      .filter(user_scan_ignore::user_id.eq(user_id))
+      .order(user_scan_ignore::id)
+      .get_results::<String>(c)
+  }).await
+}
+
+/// Replaces all scan ignore patterns of a user.\
+/// Folders of the user are scanned completely next time, so files which are no longer ignored are found.
+pub async fn replace_scan_ignore_patterns(conn: &DbConn, user_id: i32, patterns: Vec<String>) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    c.transaction(|| {
+      diesel::delete(user_scan_ignore::table.filter(user_scan_ignore::user_id.eq(user_id)))
+        .execute(c)?;
+
+      diesel::delete(folder_scan::table.filter(folder_scan::folder_id.eq_any(
+        folder::table
+          .select(folder::id)
+          .filter(folder::owner_id.eq(user_id))
+      )))
+        .execute(c)?;
+
+      let new_patterns = patterns.into_iter()
+        .map(|pattern| NewUserScanIgnore::new(user_id, pattern))
+        .collect::<Vec<NewUserScanIgnore>>();
+
+      diesel::insert_into(user_scan_ignore::table)
+        .values(new_patterns)
+        .execute(c)
+    })
+  }).await
+}
+
+/// Selects modification times of the user's folders at their last complete scan.
+pub async fn select_folder_scans(conn: &DbConn, user_id: i32) -> Result<Vec<FolderScan>, DbError> {
+  conn.run(move |c| {
+    folder_scan::table
+      .filter(folder_scan::folder_id.eq_any(
+        folder::table
+          .select(folder::id)
+          .filter(folder::owner_id.eq(user_id))
+      ))
+      .get_results::<FolderScan>(c)
+  }).await
+}
+
+/// Stores the modification time of a completely scanned folder.
+pub async fn replace_folder_scan(conn: &DbConn, folder_scan: FolderScan) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::replace_into(folder_scan::table)
+      .values(folder_scan)
+      .execute(c)
+  }).await
+}
+
+pub async fn insert_scan_alert(conn: &DbConn, new_scan_alert: NewScanAlert) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::insert_into(scan_alert::table)
+      .values(new_scan_alert)
+      .execute(c)
+  }).await
+}
+
+/// Selects alerts which weren't confirmed yet together with usernames, from the newest.
+pub async fn select_unconfirmed_scan_alerts(conn: &DbConn) -> Result<Vec<(ScanAlert, String)>, DbError> {
+  conn.run(move |c| {
+    scan_alert::table
+      .inner_join(user::table)
+      .select((scan_alert::table::all_columns(), user::username))
+      .filter(scan_alert::confirmed_at.is_null())
+      .order(scan_alert::id.desc())
+      .get_results::<(ScanAlert, String)>(c)
+  }).await
+}
+
+pub async fn select_scan_alert(conn: &DbConn, scan_alert_id: i32) -> Result<Option<ScanAlert>, DbError> {
+  conn.run(move |c| {
+    scan_alert::table
+      .filter(scan_alert::id.eq(scan_alert_id))
+      .first::<ScanAlert>(c)
+      .optional()
+  }).await
+}
+
+/// Confirms all alerts of the user, as later alerts usually repeat the same changes.
+pub async fn confirm_scan_alerts(conn: &DbConn, user_id: i32) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::update(scan_alert::table.filter(scan_alert::user_id.eq(user_id).and(scan_alert::confirmed_at.is_null())))
+      .set(scan_alert::confirmed_at.eq(Utc::now().naive_utc()))
+      .execute(c)
+  }).await
+}
+
+/// Counts alerts which weren't confirmed yet.
+pub async fn count_unconfirmed_scan_alerts(conn: &DbConn) -> Result<i64, DbError> {
+  conn.run(move |c| {
+    scan_alert::table
+      .filter(scan_alert::confirmed_at.is_null())
+      .count()
+      .get_result::<i64>(c)
+  }).await
+}
+
+pub async fn insert_scan_issues(conn: &DbConn, new_scan_issues: Vec<NewScanIssue>) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::insert_into(scan_issue::table)
+      .values(new_scan_issues)
+      .execute(c)
+  }).await
+}
+
+/// Selects issues of the scan job, optionally only of one kind or severity, or only in a folder.\
+/// `path` is a prefix of paths relative to the gallery directory, e.g. `john/Holiday/`.
+pub async fn select_scan_issues(conn: &DbConn, job_id: i32, kind: Option<ScanIssueKind>, severity: Option<ScanIssueSeverity>, path: Option<String>) -> Result<Vec<ScanIssue>, DbError> {
+  conn.run(move |c| {
+    let mut query = scan_issue::table
+      .filter(scan_issue::job_id.eq(job_id))
+      .into_boxed();
+
+    if let Some(kind) = kind {
+      query = query.filter(scan_issue::kind.eq(kind.as_str()));
+    }
+
+    if let Some(severity) = severity {
+      query = query.filter(scan_issue::severity.eq(severity.as_str()));
+    }
+
+    if let Some(path) = path {
+      let escaped = path.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+      query = query.filter(scan_issue::path.like(format!("{}%", escaped)));
+    }
+
+    query
+      .order(scan_issue::path.asc())
+      .get_results::<ScanIssue>(c)
+  }).await
+}
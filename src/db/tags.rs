@@ -0,0 +1,106 @@
+use crate::models::{NewMediaTag, NewTag};
+use crate::schema::{media_tag, tag};
+use crate::db::DbError;
+use crate::DbConn;
+use diesel::BoolExpressionMethods;
+use diesel::Connection;
+use diesel::ExpressionMethods;
+use diesel::QueryDsl;
+use diesel::RunQueryDsl;
+use std::collections::HashMap;
+
+/// Tags the media, creating the user's tags which don't exist yet; tags the media already has are skipped.
+pub async fn insert_media_tags(conn: &DbConn, user_id: i32, media_id: i32, names: Vec<String>) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    c.transaction(|| {
+      let new_tags: Vec<NewTag> = names.iter()
+        .map(|name| NewTag { owner_id: user_id, name: name.clone() })
+        .collect();
+
+      diesel::insert_or_ignore_into(tag::table)
+        .values(new_tags)
+        .execute(c)?;
+
+      let tag_ids = tag::table
+        .select(tag::id)
+        .filter(tag::owner_id.eq(user_id).and(tag::name.eq_any(&names)))
+        .load::<i32>(c)?;
+
+      let new_media_tags: Vec<NewMediaTag> = tag_ids.into_iter()
+        .map(|tag_id| NewMediaTag { media_id, tag_id })
+        .collect();
+
+      diesel::insert_or_ignore_into(media_tag::table)
+        .values(new_media_tags)
+        .execute(c)
+    })
+  }).await
+}
+
+/// Removes the tag from the media; the tag is deleted when no other media have it.
+pub async fn delete_media_tag(conn: &DbConn, user_id: i32, media_id: i32, name: String) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    c.transaction(|| {
+      let tag_ids = tag::table
+        .select(tag::id)
+        .filter(tag::owner_id.eq(user_id).and(tag::name.eq(name)))
+        .load::<i32>(c)?;
+
+      let removed = diesel::delete(media_tag::table.filter(media_tag::media_id.eq(media_id).and(media_tag::tag_id.eq_any(&tag_ids))))
+        .execute(c)?;
+
+      let used_tag_ids = media_tag::table
+        .select(media_tag::tag_id)
+        .filter(media_tag::tag_id.eq_any(&tag_ids))
+        .load::<i32>(c)?;
+
+      if used_tag_ids.is_empty() {
+        diesel::delete(tag::table.filter(tag::id.eq_any(&tag_ids)))
+          .execute(c)?;
+      }
+
+      Ok(removed)
+    })
+  }).await
+}
+
+/// Selects names of the media's tags in alphabetical order.
+pub async fn select_media_tags(conn: &DbConn, media_id: i32) -> Result<Vec<String>, DbError> {
+  conn.run(move |c| {
+    media_tag::table
+      .inner_join(tag::table)
+      .select(tag::name)
+      .filter(media_tag::media_id.eq(media_id))
+      .order(tag::name.asc())
+      .load::<String>(c)
+  }).await
+}
+
+/// Selects names of the user's tags in alphabetical order together with the number of media having them.
+pub async fn select_tag_counts(conn: &DbConn, user_id: i32) -> Result<Vec<(String, i64)>, DbError> {
+  // diesel 1.4 can't group queries, so only tag IDs of the media are selected and counted here
+  let (tags, media_tag_ids) = conn.run(move |c| -> Result<_, DbError> {
+    let tags = tag::table
+      .select((tag::id, tag::name))
+      .filter(tag::owner_id.eq(user_id))
+      .order(tag::name.asc())
+      .load::<(i32, String)>(c)?;
+
+    let media_tag_ids = media_tag::table
+      .inner_join(tag::table)
+      .select(media_tag::tag_id)
+      .filter(tag::owner_id.eq(user_id))
+      .load::<i32>(c)?;
+
+    Ok((tags, media_tag_ids))
+  }).await?;
+
+  let mut counts: HashMap<i32, i64> = HashMap::new();
+  for tag_id in media_tag_ids {
+    *counts.entry(tag_id).or_insert(0) += 1;
+  }
+
+  Ok(tags.into_iter()
+    .map(|(tag_id, name)| (name, counts.get(&tag_id).copied().unwrap_or(0)))
+    .collect())
+}
@@ -1,78 +1,73 @@
-use crate::models::{NewAuthAccessToken, NewAuthRefreshToken};
-use crate::{DbConn};
-use crate::schema::{auth_access_token, auth_refresh_token};
-use chrono::NaiveDateTime;
+use crate::auth::login::LoginClient;
+use crate::auth::token::hash_token;
+use crate::models::{AuthRefreshToken, NewAuthAccessToken, NewAuthRefreshToken};
+use crate::db::DbError;
+use crate::DbConn;
+use crate::schema::{auth_access_token, auth_refresh_token, password_reset, user_invite};
+use chrono::{NaiveDateTime, Utc};
+use diesel::{Connection, MysqlConnection};
+use diesel::BoolExpressionMethods;
 use diesel::RunQueryDsl;
 use diesel::QueryDsl;
 use diesel::OptionalExtension;
 use diesel::ExpressionMethods;
+use diesel::TextExpressionMethods;
 
-/// Inserts a new refresh token.
+/// Length of hashes created by `hash_token()`.
+const HASH_LENGTH: usize = 64;
+
+/// Inserts a new refresh token together with the client which logged in; only its hash is stored.
 /// # Example
 /// This will insert a new refresh token for a user with ID 1.
 /// ```
-/// insert_access_token(&conn, 1, "<my_access_token>".to_string());
+/// insert_refresh_token(&conn, 1, "<my_refresh_token>".to_string(), LoginClient::default()).await?;
 /// ```
-pub async fn insert_refresh_token(conn: &DbConn, user_id: i32, refresh_token: String) -> Option<()> {
-  let r: Result<usize, diesel::result::Error> = conn.run(move |c| {
+pub async fn insert_refresh_token(conn: &DbConn, user_id: i32, refresh_token: String, client: LoginClient) -> Result<usize, DbError> {
+  conn.run(move |c| {
     diesel::insert_into(auth_refresh_token::table)
-      .values(NewAuthRefreshToken::new(user_id, refresh_token))
+      .values(NewAuthRefreshToken::new(user_id, hash_token(&refresh_token)).with_client(client.user_agent, client.ip_address))
       .execute(c)
-  }).await;
-
-  if r.is_err() {
-    return None;
-  }
-
-  Some(())
+  }).await
 }
 
 /// Selects refresh token ID from a given refresh token.
-pub async fn select_refresh_token_id(conn: &DbConn, refresh_token: String) -> Option<i32> {
+pub async fn select_refresh_token_id(conn: &DbConn, refresh_token: String) -> Result<Option<i32>, DbError> {
   conn.run(move |c| {
     auth_refresh_token::table
       .select(auth_refresh_token::id)
-      .filter(auth_refresh_token::refresh_token.eq(refresh_token))
+      .filter(auth_refresh_token::refresh_token.eq(hash_token(&refresh_token)))
       .first(c)
       .optional()
-      .unwrap()
   }).await
 }
 
 /// Selects expiration time from a given refresh token.
-pub async fn select_refresh_token_expiration(conn: &DbConn, refresh_token: String) -> Option<NaiveDateTime> {
+pub async fn select_refresh_token_expiration(conn: &DbConn, refresh_token: String) -> Result<Option<NaiveDateTime>, DbError> {
   conn.run(move |c| {
     auth_refresh_token::table
       .select(auth_refresh_token::expiration_time)
-      .filter(auth_refresh_token::refresh_token.eq(refresh_token))
+      .filter(auth_refresh_token::refresh_token.eq(hash_token(&refresh_token)))
       .first(c)
       .optional()
-      .unwrap()
   }).await
 }
 
-/// Inserts a new token.
+/// Inserts a new access token; only its hash is stored.
 /// # Example
 /// This will insert a new access token with refresh token ID 20.
 /// ```
-/// insert_access_token(&conn, 20, "<my_access_token>".to_string());
+/// insert_access_token(&conn, 20, "<my_access_token>".to_string()).await?;
 /// ```
-pub async fn insert_access_token(conn: &DbConn, refresh_token_id: i32, access_token: String) -> Option<()> {
-  let r: Result<usize, diesel::result::Error> = conn.run(move |c| {
+pub async fn insert_access_token(conn: &DbConn, refresh_token_id: i32, access_token: String) -> Result<usize, DbError> {
+  conn.run(move |c| {
     diesel::insert_into(auth_access_token::table)
-      .values(NewAuthAccessToken::new(refresh_token_id, access_token))
+      .values(NewAuthAccessToken::new(refresh_token_id, hash_token(&access_token)))
       .execute(c)
-  }).await;
-
-  if r.is_err() {
-    return None;
-  }
-
-  Some(())
+  }).await
 }
 
 /// Deletes obsolete access tokens for a given refresh token.
-pub async fn delete_obsolete_access_tokens(conn: &DbConn, refresh_token_id: i32) -> Result<usize, diesel::result::Error> {
+pub async fn delete_obsolete_access_tokens(conn: &DbConn, refresh_token_id: i32) -> Result<usize, DbError> {
   conn.run(move |c| {
     diesel::delete(
       auth_access_token::table
@@ -81,3 +76,124 @@ pub async fn delete_obsolete_access_tokens(conn: &DbConn, refresh_token_id: i32)
       .execute(c)
   }).await
 }
+
+/// Selects unexpired refresh tokens of the user, the newest first.
+pub async fn select_user_sessions(conn: &DbConn, user_id: i32) -> Result<Vec<AuthRefreshToken>, DbError> {
+  conn.run(move |c| {
+    auth_refresh_token::table
+      .filter(auth_refresh_token::user_id.eq(user_id))
+      .filter(auth_refresh_token::expiration_time.gt(Utc::now().naive_utc()))
+      .order(auth_refresh_token::id.desc())
+      .get_results::<AuthRefreshToken>(c)
+  }).await
+}
+
+/// Deletes the refresh token of the user with the given UUID together with its access tokens.\
+/// Returns the number of deleted refresh tokens.
+pub async fn delete_user_session(conn: &DbConn, user_id: i32, uuid: String) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    c.transaction(|| {
+      let refresh_token_id = auth_refresh_token::table
+        .select(auth_refresh_token::id)
+        .filter(auth_refresh_token::user_id.eq(user_id))
+        .filter(auth_refresh_token::uuid.eq(uuid))
+        .first::<i32>(c)
+        .optional()?;
+
+      let refresh_token_id = match refresh_token_id {
+        Some(refresh_token_id) => refresh_token_id,
+        None => return Ok(0),
+      };
+
+      diesel::delete(auth_access_token::table.filter(auth_access_token::refresh_token_id.eq(refresh_token_id)))
+        .execute(c)?;
+
+      diesel::delete(auth_refresh_token::table.filter(auth_refresh_token::id.eq(refresh_token_id)))
+        .execute(c)
+    })
+  }).await
+}
+
+/// Replaces refresh, access, invite and password reset tokens stored in plaintext by older versions with their hashes;
+/// plaintext refresh and access tokens are UUIDs, which contain dashes unlike hashes, and plaintext invite
+/// and password reset tokens are shorter than hashes.\
+/// Returns the number of hashed tokens.
+pub async fn hash_plaintext_tokens(conn: &DbConn) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    c.transaction(|| {
+      let refresh_tokens = auth_refresh_token::table
+        .select((auth_refresh_token::id, auth_refresh_token::refresh_token))
+        .filter(auth_refresh_token::refresh_token.like("%-%"))
+        .get_results::<(i32, String)>(c)?;
+
+      let access_tokens = auth_access_token::table
+        .select((auth_access_token::id, auth_access_token::access_token))
+        .filter(auth_access_token::access_token.like("%-%"))
+        .get_results::<(i32, String)>(c)?;
+
+      for (id, refresh_token) in &refresh_tokens {
+        diesel::update(auth_refresh_token::table.filter(auth_refresh_token::id.eq(id)))
+          .set(auth_refresh_token::refresh_token.eq(hash_token(refresh_token)))
+          .execute(c)?;
+      }
+
+      for (id, access_token) in &access_tokens {
+        diesel::update(auth_access_token::table.filter(auth_access_token::id.eq(id)))
+          .set(auth_access_token::access_token.eq(hash_token(access_token)))
+          .execute(c)?;
+      }
+
+      let invite_tokens: Vec<(i32, String)> = user_invite::table
+        .select((user_invite::id, user_invite::token))
+        .get_results::<(i32, String)>(c)?
+        .into_iter()
+        .filter(|(_, token)| token.len() < HASH_LENGTH)
+        .collect();
+
+      let reset_tokens: Vec<(i32, String)> = password_reset::table
+        .select((password_reset::id, password_reset::token))
+        .get_results::<(i32, String)>(c)?
+        .into_iter()
+        .filter(|(_, token)| token.len() < HASH_LENGTH)
+        .collect();
+
+      for (id, token) in &invite_tokens {
+        diesel::update(user_invite::table.filter(user_invite::id.eq(id)))
+          .set(user_invite::token.eq(hash_token(token)))
+          .execute(c)?;
+      }
+
+      for (id, token) in &reset_tokens {
+        diesel::update(password_reset::table.filter(password_reset::id.eq(id)))
+          .set(password_reset::token.eq(hash_token(token)))
+          .execute(c)?;
+      }
+
+      Ok(refresh_tokens.len() + access_tokens.len() + invite_tokens.len() + reset_tokens.len())
+    })
+  }).await
+}
+
+/// Deletes expired access tokens and expired refresh tokens together with all their access tokens.\
+/// Returns the numbers of deleted refresh and access tokens.
+pub fn delete_expired_tokens(c: &MysqlConnection) -> Result<(usize, usize), DbError> {
+  let now = Utc::now().naive_utc();
+
+  c.transaction(|| {
+    let expired_refresh_tokens = auth_refresh_token::table
+      .select(auth_refresh_token::id)
+      .filter(auth_refresh_token::expiration_time.le(now))
+      .get_results::<i32>(c)?;
+
+    let access_tokens = diesel::delete(
+      auth_access_token::table
+        .filter(auth_access_token::expiration_time.le(now).or(auth_access_token::refresh_token_id.eq_any(&expired_refresh_tokens)))
+    )
+      .execute(c)?;
+
+    let refresh_tokens = diesel::delete(auth_refresh_token::table.filter(auth_refresh_token::id.eq_any(&expired_refresh_tokens)))
+      .execute(c)?;
+
+    Ok((refresh_tokens, access_tokens))
+  })
+}
@@ -1,14 +1,21 @@
-use crate::models::{NewUser, User};
-use crate::schema::user;
+use crate::auth::token::hash_token;
+use crate::features::Feature;
+use crate::models::{NewPasswordReset, NewUser, NewUserFeature, PasswordReset, User, UserRole, UserSetting};
+use crate::schema::{album, auth_access_token, auth_refresh_token, favorite_media, folder, media, organization, password_reset, user, user_feature, user_setting};
+use chrono::{NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use crate::db::albums::replace_album_thumbnails_of_media;
+use crate::db::DbError;
 use crate::DbConn;
 use diesel::BoolExpressionMethods;
+use diesel::Connection;
 use diesel::ExpressionMethods;
 use diesel::OptionalExtension;
 use diesel::QueryDsl;
 use diesel::RunQueryDsl;
 use diesel::Table;
 
-/// Inserts a new user.
+/// Inserts a new user into the organization.
 /// # Example
 /// ```
 /// let user = NewUser {
@@ -16,14 +23,13 @@ use diesel::Table;
 ///   email: String::from("foo@bar.foo"),
 ///   password: String::from("bar")
 /// };
-/// insert_user(&conn, user);
+/// insert_user(&conn, user, organization_id).await?;
 /// ```
-pub async fn insert_user(conn: &DbConn, user: NewUser) -> usize {
+pub async fn insert_user(conn: &DbConn, user: NewUser, organization_id: i32) -> Result<usize, DbError> {
   conn.run(move |c| {
     diesel::insert_into(user::table)
-      .values(user.clone())
+      .values((user, user::organization_id.eq(organization_id)))
       .execute(c)
-      .unwrap_or_else(|_| panic!("Error creating user {}", user.username))
   }).await
 }
 
@@ -36,23 +42,20 @@ pub async fn insert_user(conn: &DbConn, user: NewUser) -> usize {
 ///   email: String::from("foo@bar.foo"),
 ///   password: String::from("bar")
 /// };
-/// if is_user_unique(&conn, user) {
-///   insert_user(&conn, user);
+/// if is_user_unique(&conn, user).await? {
+///   insert_user(&conn, user, organization_id).await?;
 /// }
 /// ```
-pub async fn is_user_unique(conn: &DbConn, user: NewUser) -> bool {
+pub async fn is_user_unique(conn: &DbConn, user: NewUser) -> Result<bool, DbError> {
   conn.run(move |c| {
     let user_id: Option<i32> = user::table
       .select(user::id)
       .filter(user::username.eq(user.username))
       .or_filter(user::email.eq(user.email))
       .first(c)
-      .optional()
-      .unwrap();
-
-    if user_id.is_some() { return false }
+      .optional()?;
 
-    true
+    Ok(user_id.is_none())
   }).await
 }
 
@@ -60,16 +63,15 @@ pub async fn is_user_unique(conn: &DbConn, user: NewUser) -> bool {
 /// # Example
 /// We're selecting user with username michael.
 /// ```
-/// let user: Option<i32> = get_user_id(&conn, String::from("michael"));
+/// let user: Option<i32> = get_user_id(&conn, String::from("michael")).await?;
 /// ```
-pub async fn get_user_id(conn: &DbConn, username: String) -> Option<i32> {
+pub async fn get_user_id(conn: &DbConn, username: String) -> Result<Option<i32>, DbError> {
   conn.run(move |c| {
     user::table
       .select(user::id)
       .filter(user::username.eq(username))
       .first(c)
       .optional()
-      .unwrap()
   }).await
 }
 
@@ -77,63 +79,388 @@ pub async fn get_user_id(conn: &DbConn, username: String) -> Option<i32> {
 /// # Example
 /// We're selecting the username of a user with ID 1.
 /// ```
-/// let username: Option<String> = get_user_username(&conn, 1);
+/// let username: Option<String> = get_user_username(&conn, 1).await?;
 /// ```
-pub async fn get_user_username(conn: &DbConn, user_id: i32) -> Option<String> {
+pub async fn get_user_username(conn: &DbConn, user_id: i32) -> Result<Option<String>, DbError> {
   conn.run(move |c| {
     user::table
       .select(user::username)
       .filter(user::id.eq(user_id))
       .first(c)
       .optional()
-      .unwrap()
   }).await
 }
 
 /// Tries to select a user by its ID.
-pub async fn get_user_by_id(conn: &DbConn, user_id: i32) -> Option<User> {
+pub async fn get_user_by_id(conn: &DbConn, user_id: i32) -> Result<Option<User>, DbError> {
   conn.run(move |c| {
     user::table
       .select(user::table::all_columns())
       .filter(user::id.eq(user_id))
       .first::<User>(c)
       .optional()
-      .unwrap()
   }).await
 }
 
 /// Tries to select a user ID from a given email.
-pub async fn get_user_id_email(conn: &DbConn, email: String) -> Option<i32> {
+pub async fn get_user_id_email(conn: &DbConn, email: String) -> Result<Option<i32>, DbError> {
   conn.run(move |c| {
     user::table
       .select(user::id)
       .filter(user::email.eq(email))
       .first(c)
       .optional()
-      .unwrap()
   }).await
 }
 
-/// Checks the database for a combination of a specified username and password.
-pub async fn check_user_login_username(conn: &DbConn, username: String, password: String) -> Option<i32> {
+/// Selects the ID and the password hash of a user with the username.\
+/// Disabled users are never found.
+pub async fn select_user_login_username(conn: &DbConn, username: String) -> Result<Option<(i32, String)>, DbError> {
   conn.run(move |c| {
     user::table
-      .select(user::id)
-      .filter(user::username.eq(username).and(user::password.eq(password)))
+      .select((user::id, user::password))
+      .filter(user::username.eq(username).and(user::disabled.eq(false)))
       .first(c)
       .optional()
-      .unwrap()
   }).await
 }
 
-/// Checks the database for a combination of a specified email and password.
-pub async fn check_user_login_email(conn: &DbConn, email: String, password: String) -> Option<i32> {
+/// Selects the ID and the password hash of a user with the email.\
+/// Disabled users are never found.
+pub async fn select_user_login_email(conn: &DbConn, email: String) -> Result<Option<(i32, String)>, DbError> {
   conn.run(move |c| {
     user::table
-      .select(user::id)
-      .filter(user::email.eq(email).and(user::password.eq(password)))
+      .select((user::id, user::password))
+      .filter(user::email.eq(email).and(user::disabled.eq(false)))
       .first(c)
       .optional()
-      .unwrap()
+  }).await
+}
+
+/// Selects settings of a user; returns the default settings when the user hasn't changed them yet.
+pub async fn select_user_setting(conn: &DbConn, user_id: i32) -> Result<UserSetting, DbError> {
+  let setting = conn.run(move |c| {
+    user_setting::table
+      .select(user_setting::table::all_columns())
+      .filter(user_setting::user_id.eq(user_id))
+      .first::<UserSetting>(c)
+      .optional()
+  }).await?;
+
+  Ok(setting.unwrap_or_else(|| UserSetting::new(user_id)))
+}
+
+/// Replaces the password of a user with an already hashed one and signs the user out of all devices,
+/// except the one using the `kept` refresh token.
+pub async fn update_user_password(conn: &DbConn, user_id: i32, password: String, kept: Option<String>) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    c.transaction(|| {
+      let changed_rows = diesel::update(user::table.filter(user::id.eq(user_id)))
+        .set(user::password.eq(password))
+        .execute(c)?;
+
+      delete_user_tokens(c, user_id, kept)?;
+
+      Ok(changed_rows)
+    })
+  }).await
+}
+
+/// Replaces the password hash of a user without signing them out, e.g. when the hash is upgraded.
+pub async fn update_user_password_hash(conn: &DbConn, user_id: i32, password: String) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::update(user::table.filter(user::id.eq(user_id)))
+      .set(user::password.eq(password))
+      .execute(c)
+  }).await
+}
+
+/// Inserts a password reset with the given token, of which only the hash is stored, and returns it.
+pub async fn insert_password_reset(conn: &DbConn, token: &str, user_id: i32, created_by: i32, expiration: NaiveDateTime) -> Result<PasswordReset, DbError> {
+  let new_reset = NewPasswordReset::new(hash_token(token), user_id, created_by, expiration);
+
+  conn.run(move |c| {
+    c.transaction(|| {
+      diesel::insert_into(password_reset::table)
+        .values(&new_reset)
+        .execute(c)?;
+
+      password_reset::table
+        .filter(password_reset::token.eq(&new_reset.token))
+        .first::<PasswordReset>(c)
+    })
+  }).await
+}
+
+/// Uses the password reset to replace the password of its user with an already hashed one and signs the user out
+/// of all devices. Returns the ID of the user; `None` when the reset doesn't exist, expired or was already used.
+pub async fn reset_user_password(conn: &DbConn, token: String, password: String) -> Result<Option<i32>, DbError> {
+  let token = hash_token(&token);
+
+  conn.run(move |c| {
+    c.transaction(|| {
+      let now = Utc::now().naive_utc();
+
+      let claimed = diesel::update(
+        password_reset::table
+          .filter(password_reset::token.eq(&token))
+          .filter(password_reset::used_at.is_null().and(password_reset::expiration.gt(now)))
+      )
+        .set(password_reset::used_at.eq(now))
+        .execute(c)?;
+
+      if claimed == 0 { return Ok(None) }
+
+      let user_id = password_reset::table
+        .select(password_reset::user_id)
+        .filter(password_reset::token.eq(&token))
+        .first::<i32>(c)?;
+
+      diesel::update(user::table.filter(user::id.eq(user_id)))
+        .set(user::password.eq(password))
+        .execute(c)?;
+
+      delete_user_tokens(c, user_id, None)?;
+
+      Ok(Some(user_id))
+    })
+  }).await
+}
+
+/// Inserts or replaces settings of a user.
+pub async fn upsert_user_setting(conn: &DbConn, setting: UserSetting) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::replace_into(user_setting::table)
+      .values(setting)
+      .execute(c)
+  }).await
+}
+
+/// Gets user's default time zone, used for media without a known UTC offset.
+/// # Example
+/// ```
+/// let timezone: Tz = get_user_timezone(&conn, 1).await;
+/// ```
+pub async fn get_user_timezone(conn: &DbConn, user_id: i32) -> Tz {
+  match select_user_setting(conn, user_id).await {
+    Ok(setting) => setting.timezone(),
+    Err(_) => Tz::UTC,
+  }
+}
+
+/// Selects features enabled for a user.
+pub async fn select_user_features(conn: &DbConn, user_id: i32) -> Result<Vec<Feature>, DbError> {
+  let features: Vec<String> = conn.run(move |c| {
+    user_feature::table
+      .select(user_feature::feature)
+      .filter(user_feature::user_id.eq(user_id))
+      .get_results::<String>(c)
+  }).await?;
+
+  // unknown features may be left in the database by newer versions
+  Ok(features.iter().filter_map(|feature| feature.parse().ok()).collect())
+}
+
+/// Enables or disables a feature for a user.
+pub async fn set_user_feature(conn: &DbConn, user_id: i32, feature: Feature, enabled: bool) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    if enabled {
+      diesel::replace_into(user_feature::table)
+        .values(NewUserFeature::new(user_id, feature))
+        .execute(c)
+    } else {
+      diesel::delete(user_feature::table.filter(user_feature::user_id.eq(user_id).and(user_feature::feature.eq(feature.as_str()))))
+        .execute(c)
+    }
+  }).await
+}
+
+/// Selects IDs of users with the feature enabled who aren't disabled.
+pub async fn select_feature_user_ids(conn: &DbConn, feature: Feature) -> Result<Vec<i32>, DbError> {
+  conn.run(move |c| {
+    user_feature::table
+      .inner_join(user::table)
+      .select(user_feature::user_id)
+      .filter(user_feature::feature.eq(feature.as_str()).and(user::disabled.eq(false)))
+      .order(user_feature::user_id.asc())
+      .get_results::<i32>(c)
+  }).await
+}
+
+/// Selects features enabled for at least one user.
+pub async fn select_enabled_features(conn: &DbConn) -> Result<Vec<Feature>, DbError> {
+  let features: Vec<String> = conn.run(move |c| {
+    user_feature::table
+      .select(user_feature::feature)
+      .distinct()
+      .order(user_feature::feature.asc())
+      .get_results::<String>(c)
+  }).await?;
+
+  Ok(features.iter().filter_map(|feature| feature.parse().ok()).collect())
+}
+
+/// Checks whether the user is an administrator of the instance.
+pub async fn is_user_admin(conn: &DbConn, user_id: i32) -> Result<bool, DbError> {
+  conn.run(move |c| {
+    diesel::select(diesel::dsl::exists(
+      user::table.filter(user::id.eq(user_id).and(user::role.eq(UserRole::Admin.as_str())))
+    ))
+      .get_result::<bool>(c)
+  }).await
+}
+
+/// Selects all users together with the names of their organizations.
+pub async fn select_users(conn: &DbConn) -> Result<Vec<(User, String)>, DbError> {
+  conn.run(move |c| {
+    user::table
+      .inner_join(organization::table)
+      .select((user::table::all_columns(), organization::name))
+      .order(user::username.asc())
+      .get_results::<(User, String)>(c)
+  }).await
+}
+
+pub async fn update_user_role(conn: &DbConn, user_id: i32, role: UserRole) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    diesel::update(user::table.filter(user::id.eq(user_id)))
+      .set(user::role.eq(role.as_str()))
+      .execute(c)
+  }).await
+}
+
+/// Counts administrators of the instance who aren't disabled.
+pub async fn count_admins(conn: &DbConn) -> Result<i64, DbError> {
+  conn.run(move |c| {
+    user::table
+      .filter(user::role.eq(UserRole::Admin.as_str()).and(user::disabled.eq(false)))
+      .count()
+      .get_result::<i64>(c)
+  }).await
+}
+
+/// Counts users of all organizations.
+pub async fn count_users(conn: &DbConn) -> Result<i64, DbError> {
+  conn.run(move |c| {
+    user::table
+      .count()
+      .get_result::<i64>(c)
+  }).await
+}
+
+/// Makes the oldest user an administrator when the instance has none, so it can always be managed.\
+/// Returns the ID of the promoted user.
+pub async fn promote_first_admin(conn: &DbConn) -> Result<Option<i32>, DbError> {
+  conn.run(move |c| {
+    c.transaction(|| {
+      let admins = user::table
+        .filter(user::role.eq(UserRole::Admin.as_str()))
+        .count()
+        .get_result::<i64>(c)?;
+
+      if admins > 0 { return Ok(None) }
+
+      let oldest = user::table
+        .select(user::id)
+        .order(user::id.asc())
+        .first::<i32>(c)
+        .optional()?;
+
+      if let Some(user_id) = oldest {
+        diesel::update(user::table.filter(user::id.eq(user_id)))
+          .set(user::role.eq(UserRole::Admin.as_str()))
+          .execute(c)?;
+      }
+
+      Ok(oldest)
+    })
+  }).await
+}
+
+/// Checks whether the user exists and isn't disabled.
+pub async fn is_user_active(conn: &DbConn, user_id: i32) -> Result<bool, DbError> {
+  conn.run(move |c| {
+    diesel::select(diesel::dsl::exists(
+      user::table.filter(user::id.eq(user_id).and(user::disabled.eq(false)))
+    ))
+      .get_result::<bool>(c)
+  }).await
+}
+
+/// Disables or enables the user; disabling also signs the user out of all devices.
+pub async fn update_user_disabled(conn: &DbConn, user_id: i32, disabled: bool) -> Result<usize, DbError> {
+  conn.run(move |c| {
+    c.transaction(|| {
+      let changed_rows = diesel::update(user::table.filter(user::id.eq(user_id)))
+        .set(user::disabled.eq(disabled))
+        .execute(c)?;
+
+      if disabled { delete_user_tokens(c, user_id, None)?; }
+
+      Ok(changed_rows)
+    })
+  }).await
+}
+
+/// Signs the user out of all devices, except the one using the `kept` refresh token.
+fn delete_user_tokens(c: &diesel::MysqlConnection, user_id: i32, kept: Option<String>) -> Result<usize, DbError> {
+  let mut query = auth_refresh_token::table
+    .select(auth_refresh_token::id)
+    .filter(auth_refresh_token::user_id.eq(user_id))
+    .into_boxed();
+
+  if let Some(kept) = kept {
+    query = query.filter(auth_refresh_token::refresh_token.ne(hash_token(&kept)));
+  }
+
+  let refresh_token_ids = query.get_results::<i32>(c)?;
+
+  diesel::delete(auth_access_token::table.filter(auth_access_token::refresh_token_id.eq_any(&refresh_token_ids)))
+    .execute(c)?;
+
+  diesel::delete(auth_refresh_token::table.filter(auth_refresh_token::id.eq_any(&refresh_token_ids)))
+    .execute(c)
+}
+
+/// Deletes the user with their tokens, albums, folders and media and returns hashes of the media;
+/// files in the gallery are kept.\
+/// Thumbnails of other users' albums showing the media are replaced; rows referencing them (settings, invites,
+/// grants, share links...) are deleted by the database.
+pub async fn delete_user(conn: &DbConn, user_id: i32) -> Result<Vec<String>, DbError> {
+  conn.run(move |c| {
+    c.transaction(|| {
+      delete_user_tokens(c, user_id, None)?;
+
+      let (media_ids, hashes): (Vec<i32>, Vec<String>) = media::table
+        .select((media::id, media::sha2_512))
+        .filter(media::owner_id.eq(user_id))
+        .get_results::<(i32, String)>(c)?
+        .into_iter()
+        .unzip();
+
+      replace_album_thumbnails_of_media(c, &media_ids)?;
+
+      diesel::delete(favorite_media::table.filter(favorite_media::user_id.eq(user_id).or(favorite_media::media_id.eq_any(&media_ids))))
+        .execute(c)?;
+
+      diesel::delete(album::table.filter(album::owner_id.eq(user_id)))
+        .execute(c)?;
+
+      diesel::delete(media::table.filter(media::owner_id.eq(user_id)))
+        .execute(c)?;
+
+      // folders reference their parents
+      diesel::update(folder::table.filter(folder::owner_id.eq(user_id)))
+        .set(folder::parent.eq(None::<i32>))
+        .execute(c)?;
+
+      diesel::delete(folder::table.filter(folder::owner_id.eq(user_id)))
+        .execute(c)?;
+
+      diesel::delete(user::table.filter(user::id.eq(user_id)))
+        .execute(c)?;
+
+      Ok(hashes)
+    })
   }).await
 }
@@ -0,0 +1,343 @@
+//! Derivatives (thumbnails, transcodes, ...) generated from media.
+//!
+//! Derivatives are content-addressed: all derivatives of a media are stored in one directory named after
+//! the SHA-512 of its current version, so they are never served for another version and identical media
+//! share them. Directories are sharded by the first bytes of the hash to avoid millions of entries
+//! in one directory, e.g. `<data>/derivatives/ab/cd/abcd.../` with the default `derivative_shard_depth` of 2.
+//!
+//! `galera --migrate-derivatives` moves derivatives stored in another layout (older versions stored them
+//! in directories named after the media UUID) to the configured one.
+
+use crate::background::Background;
+use crate::config;
+use crate::db;
+use crate::directories::Directories;
+use crate::orientation;
+use crate::schema::media;
+use crate::DbConn;
+use diesel::{Connection, ExpressionMethods, MysqlConnection, QueryDsl, RunQueryDsl};
+use rocket::tokio::{fs, task, time};
+use std::collections::HashSet;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use uuid::Uuid;
+use walkdir::WalkDir;
+
+/// Maximum number of shard directories above a derivatives directory.
+pub const MAX_SHARD_DEPTH: usize = 4;
+
+/// Length of hex encoded SHA-512 hashes.
+const HASH_LENGTH: usize = 128;
+
+/// Number of shard directories above a derivatives directory, set from the configuration at startup.
+static SHARD_DEPTH: AtomicUsize = AtomicUsize::new(2);
+
+/// Sets the number of shard directories; at most `MAX_SHARD_DEPTH`.
+pub fn set_shard_depth(depth: usize) {
+  SHARD_DEPTH.store(depth.min(MAX_SHARD_DEPTH), Ordering::Relaxed);
+}
+
+fn is_hash(name: &str) -> bool {
+  name.len() == HASH_LENGTH && name.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+/// Returns the path of the derivatives directory of the hash inside `root`.
+fn content_dir(root: &Path, sha2_512: &str, depth: usize) -> PathBuf {
+  let hash = sha2_512.to_lowercase();
+  let mut path = root.to_path_buf();
+
+  for shard in 0..depth.min(MAX_SHARD_DEPTH) {
+    path.push(&hash[shard * 2..shard * 2 + 2]);
+  }
+
+  path.join(hash)
+}
+
+/// Returns the directory with derivatives of the media with the hash (its `sha2_512`).
+pub fn media_derivatives_dir(sha2_512: &str) -> Option<PathBuf> {
+  if !is_hash(sha2_512) { return None }
+
+  Some(content_dir(&Directories::new()?.derivatives()?, sha2_512, SHARD_DEPTH.load(Ordering::Relaxed)))
+}
+
+/// Returns the media with its EXIF orientation applied to the pixels.\
+/// The re-encoded image is cached in the derivatives directory, so it's created only once.\
+/// Returns the original path when the media doesn't need to be rotated.
+pub async fn oriented_media(sha2_512: &str, original: PathBuf) -> io::Result<PathBuf> {
+  let source = original.clone();
+  let orientation = task::spawn_blocking(move || orientation::read_orientation(&source)).await
+    .map_err(|err| io::Error::new(ErrorKind::Other, err))??;
+
+  let orientation = match orientation {
+    Some(orientation) if orientation != 1 => orientation,
+    _ => return Ok(original),
+  };
+
+  let directory = media_derivatives_dir(sha2_512)
+    .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "Derivatives directory is unknown."))?;
+
+  let extension = original.extension().and_then(|extension| extension.to_str()).unwrap_or("jpg").to_lowercase();
+  let path = directory.join(format!("oriented.{}", extension));
+
+  if fs::metadata(&path).await.is_ok() { return Ok(path) }
+
+  fs::create_dir_all(&directory).await?;
+
+  let target = path.clone();
+  task::spawn_blocking(move || write_oriented(&original, &target, orientation)).await
+    .map_err(|err| io::Error::new(ErrorKind::Other, err))??;
+
+  Ok(path)
+}
+
+/// Re-encodes the image with the orientation applied.\
+/// It's written to a temporary file first, so a concurrent request never serves a partial image.
+fn write_oriented(original: &Path, target: &Path, orientation: u16) -> io::Result<()> {
+  let image = image::open(original).map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+  let format = image::ImageFormat::from_path(target).map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))?;
+
+  let temporary = target.with_extension(format!("{}.tmp", std::process::id()));
+  orientation::apply_orientation(image, orientation)
+    .save_with_format(&temporary, format)
+    .map_err(|err| io::Error::new(ErrorKind::Other, err))?;
+
+  std::fs::rename(temporary, target)
+}
+
+/// Removes derivatives of the hash unless a media still has it.\
+/// Must be called whenever a media is deleted or its current version changes.
+pub async fn remove_unused_derivatives(conn: &DbConn, sha2_512: &str) -> io::Result<()> {
+  let in_use = db::media::select_existing_media_hashes(conn, vec![sha2_512.to_owned()]).await
+    .map_err(|err| io::Error::new(ErrorKind::Other, err))?;
+
+  if !in_use.is_empty() { return Ok(()) }
+
+  let path = media_derivatives_dir(sha2_512)
+    .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "Derivatives directory is unknown."))?;
+
+  remove_dir(&path).await
+}
+
+/// Removes derivatives of the hashes of deleted media unless other media still have them; failures are logged.
+pub async fn remove_derivatives_of_deleted(conn: &DbConn, mut hashes: Vec<String>) {
+  hashes.sort_unstable();
+  hashes.dedup();
+
+  for sha2_512 in hashes {
+    if let Err(err) = remove_unused_derivatives(conn, &sha2_512).await {
+      error!("Derivatives of {} couldn't be removed: {}", sha2_512, err);
+    }
+  }
+}
+
+async fn remove_dir(path: &Path) -> io::Result<()> {
+  match fs::remove_dir_all(path).await {
+    Err(err) if err.kind() != ErrorKind::NotFound => Err(err),
+    _ => Ok(()),
+  }
+}
+
+/// Returns derivatives directories in the layout with the given depth.
+fn content_dirs(root: &Path, depth: usize) -> Vec<(String, PathBuf)> {
+  WalkDir::new(root)
+    .min_depth(depth + 1)
+    .max_depth(depth + 1)
+    .into_iter()
+    .filter_map(|entry| entry.ok())
+    .filter(|entry| entry.file_type().is_dir())
+    .filter_map(|entry| {
+      let name = entry.file_name().to_str()?.to_owned();
+      (is_hash(&name) && entry.path() == content_dir(root, &name, depth)).then(|| (name, entry.into_path()))
+    })
+    .collect()
+}
+
+/// Removes derivatives whose content no longer belongs to any media.\
+/// Directories in other layouts are left to `galera --migrate-derivatives`.\
+/// Returns the number of removed directories.
+pub async fn remove_orphaned_derivatives(conn: &DbConn) -> io::Result<usize> {
+  let derivatives = Directories::new()
+    .and_then(|directories| directories.derivatives())
+    .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "Derivatives directory is unknown."))?;
+
+  let depth = SHARD_DEPTH.load(Ordering::Relaxed);
+  let directories = task::spawn_blocking(move || content_dirs(&derivatives, depth)).await
+    .map_err(|err| io::Error::new(ErrorKind::Other, err))?;
+
+  if directories.is_empty() { return Ok(0) }
+
+  let mut existing = HashSet::new();
+  for chunk in directories.chunks(ORPHAN_CHECK_BATCH_SIZE) {
+    let hashes = chunk.iter().map(|(hash, _)| hash.clone()).collect();
+    let found = db::media::select_existing_media_hashes(conn, hashes).await
+      .map_err(|err| io::Error::new(ErrorKind::Other, err))?;
+
+    existing.extend(found.into_iter().map(|hash| hash.to_lowercase()));
+  }
+
+  let mut removed = 0;
+  for (_, path) in directories.iter().filter(|(hash, _)| !existing.contains(hash)) {
+    remove_dir(path).await?;
+    removed += 1;
+  }
+
+  Ok(removed)
+}
+
+/// Number of hashes checked by one query when looking for orphaned derivatives.
+const ORPHAN_CHECK_BATCH_SIZE: usize = 1000;
+
+/// Removes orphaned derivatives every `interval_hours`, e.g. those whose removal failed when their media was deleted.
+pub async fn run_cleanup(background: Background, interval_hours: u64) {
+  loop {
+    time::sleep(Duration::from_secs(interval_hours.max(1) * 3600)).await;
+
+    let conn = match background.conn().await {
+      Some(conn) => conn,
+      None => {
+        error!("Removal of orphaned derivatives was skipped as no database connection is available.");
+        continue;
+      },
+    };
+
+    match remove_orphaned_derivatives(&conn).await {
+      Ok(0) => {},
+      Ok(removed) => info!("Removed {} orphaned derivatives directories.", removed),
+      Err(err) => error!("Orphaned derivatives couldn't be removed: {}", err),
+    }
+  }
+}
+
+/// Result of moving derivatives to the configured layout.
+#[derive(Debug, Default)]
+pub struct LayoutMigration {
+  pub moved: usize,
+  /// Directories of media which no longer exist.
+  pub removed: usize,
+  /// Directories which couldn't be moved because the target already exists; they are removed.
+  pub duplicates: usize,
+}
+
+/// Moves derivatives stored in other layouts to the layout with `depth` shard directories.\
+/// Directories named after media UUIDs (the flat layout of older versions) are moved to the hash of the media.
+pub fn migrate_layout(c: &MysqlConnection, root: &Path, depth: usize) -> io::Result<LayoutMigration> {
+  let mut migration = LayoutMigration::default();
+  let current: HashSet<PathBuf> = content_dirs(root, depth).into_iter().map(|(_, path)| path).collect();
+
+  // derivatives directories are never nested, so they aren't walked into
+  let mut walker = WalkDir::new(root).min_depth(1).max_depth(MAX_SHARD_DEPTH + 1).into_iter();
+  let mut legacy = vec![];
+  let mut misplaced = vec![];
+
+  while let Some(entry) = walker.next() {
+    let entry = entry.map_err(|err| io::Error::new(ErrorKind::Other, err))?;
+    if !entry.file_type().is_dir() { continue }
+
+    let name = entry.file_name().to_string_lossy().to_string();
+
+    if is_hash(&name) {
+      if !current.contains(entry.path()) { misplaced.push((name, entry.path().to_path_buf())) }
+      walker.skip_current_dir();
+    } else if entry.depth() == 1 && Uuid::parse_str(&name).is_ok() {
+      legacy.push((name, entry.path().to_path_buf()));
+      walker.skip_current_dir();
+    }
+  }
+
+  if !legacy.is_empty() {
+    let uuids: Vec<String> = legacy.iter().map(|(uuid, _)| uuid.clone()).collect();
+    let hashes: Vec<(String, String)> = media::table
+      .select((media::uuid, media::sha2_512))
+      .filter(media::uuid.eq_any(uuids))
+      .get_results(c)
+      .map_err(|err| io::Error::new(ErrorKind::Other, err))?;
+
+    for (uuid, path) in legacy {
+      match hashes.iter().find(|(media_uuid, _)| *media_uuid == uuid) {
+        Some((_, hash)) => misplaced.push((hash.to_lowercase(), path)),
+        None => {
+          std::fs::remove_dir_all(&path)?;
+          migration.removed += 1;
+        },
+      }
+    }
+  }
+
+  for (hash, path) in misplaced {
+    let target = content_dir(root, &hash, depth);
+
+    if target.exists() {
+      std::fs::remove_dir_all(&path)?;
+      migration.duplicates += 1;
+      continue;
+    }
+
+    std::fs::create_dir_all(target.parent().unwrap_or(root))?;
+    std::fs::rename(&path, &target)?;
+    migration.moved += 1;
+  }
+
+  remove_empty_shards(root, root)?;
+
+  Ok(migration)
+}
+
+/// Removes shard directories left empty after the migration.
+fn remove_empty_shards(root: &Path, directory: &Path) -> io::Result<()> {
+  for entry in std::fs::read_dir(directory)? {
+    let path = entry?.path();
+    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+
+    if path.is_dir() && name.len() == 2 {
+      remove_empty_shards(root, &path)?;
+    }
+  }
+
+  if directory != root && std::fs::read_dir(directory)?.next().is_none() {
+    std::fs::remove_dir(directory)?;
+  }
+
+  Ok(())
+}
+
+/// Moves derivatives of the configured database to the configured layout for `--migrate-derivatives`.\
+/// Returns the exit code: 0 on success, 1 on errors.
+pub fn migrate_layout_cli() -> i32 {
+  dotenv::dotenv().ok();
+
+  let figment = config::figment();
+  let url: String = match figment.extract_inner("databases.galera.url") {
+    Ok(url) => url,
+    Err(err) => {
+      eprintln!("Database URL isn't configured: {}", err);
+      return 1;
+    },
+  };
+
+  let depth = figment.extract_inner("derivative_shard_depth").unwrap_or_else(|_| config::Config::default().derivative_shard_depth);
+
+  let root = match Directories::new().and_then(|directories| directories.derivatives()) {
+    Some(root) => root,
+    None => {
+      eprintln!("Derivatives directory is unknown.");
+      return 1;
+    },
+  };
+
+  let migration = MysqlConnection::establish(&url)
+    .map_err(|err| io::Error::new(ErrorKind::Other, err))
+    .and_then(|c| migrate_layout(&c, &root, depth.min(MAX_SHARD_DEPTH)));
+
+  match migration {
+    Ok(migration) => {
+      println!("Moved {} derivatives directories, removed {} orphaned and {} duplicate ones.", migration.moved, migration.removed, migration.duplicates);
+      0
+    },
+    Err(err) => {
+      eprintln!("Derivatives couldn't be migrated: {}", err);
+      1
+    },
+  }
+}
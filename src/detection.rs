@@ -0,0 +1,210 @@
+//! Detection of faces and objects in scanned media (the `detection` feature).
+//!
+//! A background task processes media which weren't processed yet, a batch at a time, so scans aren't slowed down;
+//! media added by a scan are processed within a minute after it. Processed media are recorded in `media_detection`,
+//! including the ones which couldn't be processed (e.g. videos), so they aren't selected again.\
+//! The model is an ONNX detector configured in `detection` (see `config::DetectionPolicy`). Its input is an RGB image
+//! `[1, 3, input_size, input_size]` with values between 0 and 1, its output is `[1, N, 6]` with rows
+//! `x1, y1, x2, y2, confidence, class` in pixels of the input (e.g. YOLO models exported with NMS).\
+//! Detections of `face_label` are stored as faces without a person, which users assign to people;
+//! other detections are stored as tags suggested for the media. Without labels, all detections are faces.
+
+use crate::background::Background;
+use crate::config::DetectionPolicy;
+use crate::db;
+use crate::models::{NewMediaFace, NewTagSuggestion};
+use crate::scan;
+use crate::DbConn;
+use image::imageops::FilterType;
+use nanoid::nanoid;
+use rocket::tokio::{task, time};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tract_onnx::prelude::*;
+
+/// Number of media selected at once.
+const BATCH_SIZE: i64 = 32;
+
+/// Seconds waited for new media once all media are processed.
+const IDLE_SECONDS: u64 = 60;
+
+/// Maximum length of names of suggested tags, the same as of tags.
+const MAX_LABEL_LENGTH: usize = 64;
+
+/// Face or object found in an image; the bounding box is relative to the width and height of the image.
+#[derive(Debug, Clone)]
+pub struct Detection {
+  /// `None` for faces.
+  pub label: Option<String>,
+  pub confidence: f32,
+  pub x: f64,
+  pub y: f64,
+  pub width: f64,
+  pub height: f64,
+}
+
+/// Loaded model with its labels.
+pub struct Detector {
+  plan: TypedSimplePlan<TypedModel>,
+  labels: Vec<String>,
+  policy: DetectionPolicy,
+}
+
+impl Detector {
+  /// Loads and optimizes the model; it takes a while, so it should be run on a blocking thread.
+  pub fn load(policy: DetectionPolicy) -> TractResult<Self> {
+    let model = policy.model.as_ref().ok_or_else(|| anyhow::anyhow!("no model is configured"))?;
+    let size = policy.input_size as usize;
+
+    let plan = onnx()
+      .model_for_path(model)?
+      .with_input_fact(0, f32::fact([1, 3, size, size]).into())?
+      .into_optimized()?
+      .into_runnable()?;
+
+    let labels = match &policy.labels {
+      Some(path) => fs::read_to_string(path)?.lines().map(|label| label.trim().to_owned()).collect(),
+      None => vec![],
+    };
+
+    Ok(Self { plan, labels, policy })
+  }
+
+  /// Detects faces and objects in the image; fails for files which aren't images.
+  pub fn detect(&self, path: &Path) -> TractResult<Vec<Detection>> {
+    let size = self.policy.input_size;
+    let image = image::open(path)?.resize_exact(size, size, FilterType::Triangle).to_rgb8();
+
+    let input: Tensor = tract_ndarray::Array4::from_shape_fn((1, 3, size as usize, size as usize), |(_, channel, y, x)| {
+      image.get_pixel(x as u32, y as u32)[channel] as f32 / 255.0
+    }).into();
+
+    let outputs = self.plan.run(tvec!(input.into()))?;
+    let values: Vec<f32> = outputs[0].to_array_view::<f32>()?.iter().copied().collect();
+
+    let size = size as f32;
+    let detections = values.chunks_exact(6)
+      .filter(|row| row[4] >= self.policy.min_confidence)
+      .filter_map(|row| {
+        let (x1, y1) = (row[0].clamp(0.0, size), row[1].clamp(0.0, size));
+        let (x2, y2) = (row[2].clamp(0.0, size), row[3].clamp(0.0, size));
+        if x2 <= x1 || y2 <= y1 { return None }
+
+        Some(Detection {
+          label: self.label(row[5]),
+          confidence: row[4],
+          x: (x1 / size) as f64,
+          y: (y1 / size) as f64,
+          width: ((x2 - x1) / size) as f64,
+          height: ((y2 - y1) / size) as f64,
+        })
+      })
+      .collect();
+
+    Ok(detections)
+  }
+
+  /// Returns the label of the class; `None` for faces.
+  fn label(&self, class: f32) -> Option<String> {
+    if self.labels.is_empty() { return None }
+
+    let label = match self.labels.get(class as usize) {
+      Some(label) => label.clone(),
+      None => format!("class {}", class as usize),
+    };
+
+    if label == self.policy.face_label { return None }
+
+    Some(label.chars().take(MAX_LABEL_LENGTH).collect())
+  }
+}
+
+/// Splits the detections into faces and tag suggestions; each label is suggested once with its highest confidence.
+fn into_rows(media_id: i32, detections: Vec<Detection>) -> (Vec<NewMediaFace>, Vec<NewTagSuggestion>) {
+  let mut faces = vec![];
+  let mut suggestions: HashMap<String, f32> = HashMap::new();
+
+  for detection in detections {
+    match detection.label {
+      Some(label) => {
+        let confidence = suggestions.entry(label).or_insert(0.0);
+        *confidence = confidence.max(detection.confidence);
+      },
+      None => faces.push(NewMediaFace {
+        uuid: nanoid!(),
+        media_id,
+        person_id: None,
+        x: detection.x,
+        y: detection.y,
+        width: detection.width,
+        height: detection.height,
+      }),
+    }
+  }
+
+  let suggestions = suggestions.into_iter()
+    .map(|(name, confidence)| NewTagSuggestion { media_id, name, confidence: confidence as f64 })
+    .collect();
+
+  (faces, suggestions)
+}
+
+/// Processes one batch of media and returns their number.
+async fn detect_media(conn: &DbConn, detector: &Arc<Detector>) -> Result<usize, diesel::result::Error> {
+  let media_list = db::detection::select_media_to_detect(conn, BATCH_SIZE).await?;
+
+  for media in &media_list {
+    let path = scan::get_media_path(conn, media).await;
+
+    let detections = match path {
+      Some(path) if path.is_file() => {
+        let detector = detector.clone();
+        match task::spawn_blocking(move || detector.detect(&path)).await {
+          Ok(Ok(detections)) => detections,
+          Ok(Err(err)) => {
+            debug!("Media {} couldn't be processed by the detection: {}", media.uuid, err);
+            vec![]
+          },
+          Err(err) => {
+            error!("Detection in media {} panicked: {}", media.uuid, err);
+            vec![]
+          },
+        }
+      },
+      _ => vec![],
+    };
+
+    let (faces, suggestions) = into_rows(media.id, detections);
+    db::detection::insert_detections(conn, media.id, faces, suggestions).await?;
+  }
+
+  Ok(media_list.len())
+}
+
+/// Processes media which weren't processed yet until the server stops.
+pub async fn run(background: Background, detector: Detector) {
+  let detector = Arc::new(detector);
+
+  loop {
+    let conn = background.conn().await;
+    let processed = match conn {
+      Some(conn) => detect_media(&conn, &detector).await,
+      None => {
+        error!("Detection was paused as no database connection is available.");
+        Ok(0)
+      },
+    };
+
+    match processed {
+      Ok(0) => time::sleep(Duration::from_secs(IDLE_SECONDS)).await,
+      Ok(processed) => info!("Detection processed {} media.", processed),
+      Err(err) => {
+        error!("Detection failed: {}", err);
+        time::sleep(Duration::from_secs(IDLE_SECONDS)).await;
+      },
+    }
+  }
+}
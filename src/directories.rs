@@ -28,6 +28,42 @@ impl Directories {
     Directories::check(path)
   }
 
+  /// Returns the directory with derivatives (thumbnails, transcodes, ...) of media.
+  pub fn derivatives(&self) -> Option<PathBuf> {
+    let path = &self.data.join("derivatives");
+
+    Directories::check(path)
+  }
+
+  /// Returns the directory with edited versions of media; originals in the gallery are never changed.\
+  /// It's hidden in the gallery, which contains only folders of users otherwise, so it's not scanned.
+  pub fn versions(&self) -> Option<PathBuf> {
+    let path = &self.data.join("gallery").join(".versions");
+
+    Directories::check(path)
+  }
+
+  /// Returns the directory for unfinished uploads.
+  pub fn uploads(&self) -> Option<PathBuf> {
+    let path = &self.data.join("uploads");
+
+    Directories::check(path)
+  }
+
+  /// Returns the directory with archives of exports, see `export`.
+  pub fn exports(&self) -> Option<PathBuf> {
+    let path = &self.data.join("exports");
+
+    Directories::check(path)
+  }
+
+  /// Returns the directory with rejected uploads which looked corrupt or disguised.
+  pub fn quarantine(&self) -> Option<PathBuf> {
+    let path = &self.data.join("quarantine");
+
+    Directories::check(path)
+  }
+
   pub fn new() -> Option<Directories> {
     let dirs_option = Directories::get_dirs();
     if dirs_option.is_none() {
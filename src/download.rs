@@ -0,0 +1,116 @@
+use crate::bandwidth::{Bandwidth, Throttled};
+use async_zip::{Compression, ZipEntryBuilder};
+use async_zip::tokio::write::ZipFileWriter;
+use okapi::openapi3::Responses;
+use rocket::http::ContentType;
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use rocket::tokio::{self, fs::File, io::DuplexStream};
+use rocket_okapi::{gen::OpenApiGenerator, response::OpenApiResponderInner, util::set_content_type};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use tokio_util::compat::TokioAsyncReadCompatExt;
+
+/// Size of the buffer between the zip writer and the response.
+const ZIP_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Zip archive which is generated while it's being sent to the client.
+pub struct ZipDownload {
+  reader: Throttled<DuplexStream>,
+  filename: String,
+}
+
+impl ZipDownload {
+  /// Starts writing an archive with the given files.\
+  /// Each file is a pair of a name inside the archive and a path on the disk.
+  /// Duplicate names are made unique by appending a number.
+  /// # Example
+  /// ```
+  /// let files = vec![(String::from("cat.jpg"), PathBuf::from("/gallery/john/cat.jpg"))];
+  ///
+  /// let zip = ZipDownload::new(files, String::from("galera.zip"));
+  /// ```
+  pub fn new(files: Vec<(String, PathBuf)>, filename: String) -> Self {
+    ZipDownload::throttled(files, filename, Bandwidth::unlimited())
+  }
+
+  /// Checks that all files can be opened, so the archive isn't cut off in the middle of the response
+  /// after its status was already sent; returns the path of the first file which can't be opened.
+  pub async fn check_readable(files: &[(String, PathBuf)]) -> Result<(), PathBuf> {
+    for (_, path) in files {
+      if File::open(path).await.is_err() { return Err(path.clone()) }
+    }
+
+    Ok(())
+  }
+
+  /// Starts writing an archive which is sent only as fast as the bandwidth allows.
+  pub fn throttled(files: Vec<(String, PathBuf)>, filename: String, bandwidth: Bandwidth) -> Self {
+    let (reader, writer) = tokio::io::duplex(ZIP_BUFFER_SIZE);
+
+    tokio::spawn(async move {
+      if let Err(err) = ZipDownload::write(files, writer).await {
+        error!("Writing a zip archive failed: {}", err);
+      }
+    });
+
+    Self { reader: Throttled::new(reader, bandwidth), filename }
+  }
+
+  async fn write(files: Vec<(String, PathBuf)>, writer: DuplexStream) -> anyhow::Result<()> {
+    let mut zip = ZipFileWriter::with_tokio(writer);
+    let mut used_names = HashSet::new();
+
+    for (name, path) in files {
+      let mut file = File::open(&path).await?.compat();
+
+      // media are already compressed, so there is no point in compressing them again
+      let entry = ZipEntryBuilder::new(ZipDownload::unique_name(&mut used_names, name).into(), Compression::Stored);
+      let mut entry_writer = zip.write_entry_stream(entry).await?;
+
+      futures::io::copy(&mut file, &mut entry_writer).await?;
+      entry_writer.close().await?;
+    }
+
+    // dropping the writer closes the response stream
+    zip.close().await?;
+
+    Ok(())
+  }
+
+  /// Returns a name which wasn't used yet, e.g. `cat (1).jpg` when `cat.jpg` was already used.
+  fn unique_name(used_names: &mut HashSet<String>, name: String) -> String {
+    let mut unique = name.clone();
+    let mut counter = 1;
+
+    while used_names.contains(&unique) {
+      unique = match name.rsplit_once('.') {
+        Some((stem, extension)) => format!("{} ({}).{}", stem, counter, extension),
+        None => format!("{} ({})", name, counter),
+      };
+      counter += 1;
+    }
+
+    used_names.insert(unique.clone());
+    unique
+  }
+}
+
+impl<'r> Responder<'r, 'static> for ZipDownload {
+  fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+    Response::build()
+      .header(ContentType::ZIP)
+      .raw_header("Content-Disposition", format!("attachment; filename=\"{}\"", self.filename))
+      .streamed_body(self.reader)
+      .ok()
+  }
+}
+
+impl OpenApiResponderInner for ZipDownload {
+  fn responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+    let mut responses = <Vec<u8>>::responses(gen)?;
+    set_content_type(&mut responses, ContentType::ZIP)?;
+
+    Ok(responses)
+  }
+}
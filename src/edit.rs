@@ -0,0 +1,167 @@
+//! Editing of images (rotating, flipping and cropping).
+//!
+//! Rotating and flipping of JPEGs is lossless when `jpegtran` is installed and the image dimensions allow it,
+//! otherwise the image is decoded, edited and encoded again.\
+//! Edits are never written to the original in the gallery, every edit creates a new version
+//! in `Directories::versions()` instead.
+
+use crate::directories::Directories;
+use crate::orientation;
+use crate::validation::{InvalidReason, ValidationErrors};
+use image::{DynamicImage, ImageFormat};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Direction of flipping.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FlipDirection {
+  Horizontal,
+  Vertical,
+}
+
+/// Single operation of an edit; operations are applied in the given order.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "operation", rename_all = "snake_case")]
+pub enum EditOperation {
+  /// Rotates clockwise by 90, 180 or 270 degrees.
+  Rotate { degrees: u32 },
+  Flip { direction: FlipDirection },
+  /// Crops to the rectangle; coordinates are in pixels of the image after the previous operations.
+  Crop { x: u32, y: u32, width: u32, height: u32 },
+}
+
+impl EditOperation {
+  /// Returns the dimensions of an image with the given dimensions after the operation.
+  fn dimensions(&self, (width, height): (u32, u32)) -> (u32, u32) {
+    match self {
+      EditOperation::Rotate { degrees: 90 | 270 } => (height, width),
+      EditOperation::Crop { width, height, .. } => (*width, *height),
+      _ => (width, height),
+    }
+  }
+
+  /// Returns the arguments of `jpegtran` performing the operation, `None` when it can't be done losslessly.
+  fn jpegtran_args(&self) -> Option<Vec<String>> {
+    match self {
+      EditOperation::Rotate { degrees } => Some(vec![String::from("-rotate"), degrees.to_string()]),
+      EditOperation::Flip { direction: FlipDirection::Horizontal } => Some(vec![String::from("-flip"), String::from("horizontal")]),
+      EditOperation::Flip { direction: FlipDirection::Vertical } => Some(vec![String::from("-flip"), String::from("vertical")]),
+      EditOperation::Crop { .. } => None,
+    }
+  }
+
+  fn apply(&self, image: DynamicImage) -> DynamicImage {
+    match self {
+      EditOperation::Rotate { degrees: 90 } => image.rotate90(),
+      EditOperation::Rotate { degrees: 180 } => image.rotate180(),
+      EditOperation::Rotate { degrees: 270 } => image.rotate270(),
+      EditOperation::Rotate { .. } => image,
+      EditOperation::Flip { direction: FlipDirection::Horizontal } => image.fliph(),
+      EditOperation::Flip { direction: FlipDirection::Vertical } => image.flipv(),
+      EditOperation::Crop { x, y, width, height } => image.crop_imm(*x, *y, *width, *height),
+    }
+  }
+}
+
+/// Returns the path of an edited version of a media (`<versions>/<media uuid>/<version>.<extension>`).\
+/// It keeps the extension of the original, so the same format is used.
+pub fn media_version_path(media_uuid: &str, filename: &str, version: i32) -> Option<PathBuf> {
+  let extension = Path::new(filename).extension().and_then(|extension| extension.to_str()).unwrap_or("jpg").to_lowercase();
+
+  Some(Directories::new()?.versions()?.join(media_uuid).join(format!("{}.{}", version, extension)))
+}
+
+/// Returns the dimensions of the image as it's displayed, i.e. with the EXIF orientation applied.
+pub fn displayed_dimensions(path: &Path, (width, height): (u32, u32)) -> io::Result<(u32, u32)> {
+  match orientation::read_orientation(path)? {
+    Some(5..=8) => Ok((height, width)),
+    _ => Ok((width, height)),
+  }
+}
+
+/// Checks the operations against an image with the given dimensions.
+pub fn validate_operations(operations: &[EditOperation], dimensions: (u32, u32), errors: &mut ValidationErrors) {
+  if operations.is_empty() {
+    errors.add("operations", InvalidReason::TooShort { min: 1 });
+    return;
+  }
+
+  let mut dimensions = dimensions;
+  for (i, operation) in operations.iter().enumerate() {
+    let valid = match operation {
+      EditOperation::Rotate { degrees } => [90, 180, 270].contains(degrees),
+      EditOperation::Flip { .. } => true,
+      EditOperation::Crop { x, y, width, height } => {
+        *width > 0 && *height > 0
+          && x.checked_add(*width).map_or(false, |right| right <= dimensions.0)
+          && y.checked_add(*height).map_or(false, |bottom| bottom <= dimensions.1)
+      },
+    };
+
+    if !valid {
+      errors.add(&format!("operations[{}]", i), InvalidReason::InvalidFormat);
+      return;
+    }
+
+    dimensions = operation.dimensions(dimensions);
+  }
+}
+
+/// Writes the edited `source` into `target`.\
+/// The format of `target` is given by its extension.
+pub fn edit_image(source: &Path, target: &Path, operations: &[EditOperation]) -> io::Result<()> {
+  let format = ImageFormat::from_path(target).map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))?;
+
+  // EXIF orientation is lost when encoding, so only images without it can be edited losslessly
+  if format == ImageFormat::Jpeg && orientation::read_orientation(source)?.unwrap_or(1) == 1 && edit_jpeg_lossless(source, target, operations) {
+    return Ok(());
+  }
+
+  let mut image = image::open(source).map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+  if let Some(orientation) = orientation::read_orientation(source)? {
+    image = orientation::apply_orientation(image, orientation);
+  }
+
+  for operation in operations {
+    image = operation.apply(image);
+  }
+
+  image.save_with_format(target, format).map_err(|err| io::Error::new(ErrorKind::Other, err))
+}
+
+/// Tries to edit the JPEG using `jpegtran`; returns `false` when it's not possible.
+fn edit_jpeg_lossless(source: &Path, target: &Path, operations: &[EditOperation]) -> bool {
+  let args: Option<Vec<Vec<String>>> = operations.iter().map(EditOperation::jpegtran_args).collect();
+  let args = match args {
+    Some(args) => args,
+    None => return false,
+  };
+
+  let mut current = source.to_path_buf();
+  for (i, args) in args.iter().enumerate() {
+    let output = target.with_extension(format!("lossless{}.jpg", i));
+
+    // -perfect fails instead of dropping the edge blocks which can't be transformed losslessly
+    let status = Command::new("jpegtran")
+      .args(args)
+      .args(["-perfect", "-copy", "all", "-outfile"])
+      .arg(&output)
+      .arg(&current)
+      .status();
+
+    if current != source { std::fs::remove_file(&current).ok(); }
+
+    if !matches!(status, Ok(status) if status.success()) {
+      std::fs::remove_file(&output).ok();
+      return false;
+    }
+
+    current = output;
+  }
+
+  std::fs::rename(&current, target).is_ok()
+}
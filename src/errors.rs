@@ -0,0 +1,122 @@
+//! Error responses of the API.
+//!
+//! Every error response has the same JSON body, `ErrorBody`: a machine-readable `code` derived from the status
+//! (e.g. `not_found`), a human-readable `message` and optional `details`, e.g. the invalid fields of 422 responses.
+//! Routes returning `ApiError` build the body themselves; routes failing with a bare `Status` get it from
+//! the default catcher (see `catchers`).
+
+use crate::validation::ValidationErrors;
+use okapi::openapi3::Responses;
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use rocket::serde::json::Json;
+use rocket_okapi::{gen::OpenApiGenerator, response::OpenApiResponderInner, util::change_all_responses_to_default};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::fmt::Display;
+
+/// Body of error responses.
+#[derive(Serialize, JsonSchema, Debug)]
+pub struct ErrorBody {
+  /// Machine-readable code derived from the status, e.g. `not_found` or `unprocessable_entity`.
+  pub code: String,
+  /// Human-readable description, not meant to be shown to users.
+  pub message: String,
+  /// Additional information, e.g. `{"errors": [...]}` with the invalid fields of 422 responses.
+  pub details: Option<serde_json::Value>,
+}
+
+impl ErrorBody {
+  pub fn new(status: Status) -> Self {
+    let reason = status.reason_lossy();
+
+    let code = reason.to_lowercase()
+      .split(|c: char| !c.is_ascii_alphanumeric())
+      .filter(|word| !word.is_empty())
+      .collect::<Vec<&str>>()
+      .join("_");
+
+    Self { code, message: reason.to_owned(), details: None }
+  }
+}
+
+/// Error of a route, responding with an `ErrorBody`.
+/// # Example
+/// ```
+/// let user = get_user_by_id(&conn, claims.user_id).await?.ok_or(Status::NotFound)?;
+/// ```
+#[derive(Debug)]
+pub enum ApiError {
+  /// Responds with the status.
+  Status(Status),
+  /// Responds with 422 and the invalid fields in `details`.
+  Invalid(ValidationErrors),
+  /// Failed database query; it's logged and the response is 500 without details.
+  Database(diesel::result::Error),
+}
+
+impl ApiError {
+  pub fn status(&self) -> Status {
+    match self {
+      ApiError::Status(status) => *status,
+      ApiError::Invalid(_) => Status::UnprocessableEntity,
+      ApiError::Database(_) => Status::InternalServerError,
+    }
+  }
+}
+
+impl From<Status> for ApiError {
+  fn from(status: Status) -> Self {
+    ApiError::Status(status)
+  }
+}
+
+impl From<ValidationErrors> for ApiError {
+  fn from(errors: ValidationErrors) -> Self {
+    ApiError::Invalid(errors)
+  }
+}
+
+impl From<diesel::result::Error> for ApiError {
+  fn from(err: diesel::result::Error) -> Self {
+    ApiError::Database(err)
+  }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+  fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+    let status = self.status();
+    let mut body = ErrorBody::new(status);
+
+    match self {
+      ApiError::Status(_) => {},
+      ApiError::Invalid(errors) => body.details = serde_json::to_value(errors).ok(),
+      ApiError::Database(err) => error!("Request {} {} failed: {}", request.method(), request.uri(), err),
+    }
+
+    Response::build_from(Json(body).respond_to(request)?)
+      .status(status)
+      .ok()
+  }
+}
+
+impl OpenApiResponderInner for ApiError {
+  fn responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+    let mut responses = <Json<ErrorBody>>::responses(gen)?;
+    change_all_responses_to_default(&mut responses);
+
+    Ok(responses)
+  }
+}
+
+/// Logs a failed query and turns it into 500, for code which responds with a bare status.
+/// # Example
+/// ```
+/// let album_id = db::albums::select_album_id(&conn, album_uuid).await.map_err(errors::internal)?;
+/// ```
+pub fn internal(err: impl Display) -> Status {
+  error!("Database query failed: {}", err);
+
+  Status::InternalServerError
+}
@@ -0,0 +1,306 @@
+//! Export of favorites, albums and descriptions of a user to formats other software understands.
+//!
+//! An export is a job (see `/jobs`) writing a ZIP archive into the `exports` directory; it can be downloaded
+//! once the job finished. Each user keeps only their latest archive, older ones are removed when a new export starts.
+//! Only media owned by the user are exported, media of other users in shared albums are skipped.
+//!
+//! Formats:
+//! - `folders`: a folder per album (`Albums/<name>/`) and `Favorites/` with copies of the original files,
+//!   for software which organizes media by folders.
+//! - `digikam`: XMP sidecars (`IMG_0001.jpg.xmp`) next to the paths of the originals, with albums and favorites
+//!   as `Galera/Albums/<name>` and `Galera/Favorites` tags and descriptions; digiKam reads them when the library
+//!   is scanned.
+//! - `photoprism`: YAML sidecars (`sidecar/IMG_0001.yml`) with favorites and descriptions; album names are written
+//!   as keywords, as PhotoPrism's album backups refer to its own IDs.
+//!
+//! Paths in sidecar formats are relative to the user's folder, so the archive is extracted into the root
+//! of the library in the other software.
+
+use crate::db;
+use crate::directories::Directories;
+use crate::models::{Job, JobKind, JobState, Media, NewJob};
+use crate::routes::pagination::MediaPagination;
+use crate::scan;
+use crate::write_back::escape_xml;
+use crate::DbConn;
+use async_zip::tokio::write::ZipFileWriter;
+use async_zip::{Compression, ZipEntryBuilder};
+use okapi::openapi3::Responses;
+use rocket::form::FromFormField;
+use rocket::fs::NamedFile;
+use rocket::http::ContentType;
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use rocket::tokio::{self, fs::File};
+use rocket_okapi::{gen::OpenApiGenerator, response::OpenApiResponderInner, util::set_content_type};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tokio_util::compat::TokioAsyncReadCompatExt;
+
+#[derive(FromFormField, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+  /// Folder per album with copies of the media.
+  Folders,
+  /// XMP sidecars with tags.
+  Digikam,
+  /// YAML sidecars.
+  Photoprism,
+}
+
+/// Media of the export with its curation.
+struct ExportedMedia {
+  media: Media,
+  albums: Vec<String>,
+  favorite: bool,
+}
+
+/// Returns the path of the user's archive of the job.
+fn archive_path(exports: &Path, job: &Job) -> PathBuf {
+  exports.join(format!("{}-{}.zip", job.user_id, job.uuid))
+}
+
+/// Starts an export of the user's media; returns `None` when an export of the user is already running.
+pub async fn start(conn: DbConn, user_id: i32, format: ExportFormat) -> Result<Option<Job>, diesel::result::Error> {
+  if db::jobs::is_user_job_running(&conn, user_id, JobKind::Export).await? { return Ok(None) }
+
+  let job = db::jobs::insert_job(&conn, NewJob::new(user_id, JobKind::Export)).await?;
+
+  let exported = job.clone();
+  tokio::spawn(async move {
+    let (state, error) = match run(&conn, &exported, format).await {
+      Ok(()) => (JobState::Finished, None),
+      Err(err) => {
+        error!("Export {} of user {} failed: {}", exported.uuid, user_id, err);
+        (JobState::Failed, Some(err.to_string()))
+      },
+    };
+
+    if db::jobs::finish_job(&conn, exported.id, state, error).await.is_err() {
+      error!("State of job {} couldn't be saved.", exported.uuid);
+    }
+  });
+
+  Ok(Some(job))
+}
+
+async fn run(conn: &DbConn, job: &Job, format: ExportFormat) -> anyhow::Result<()> {
+  let exports = Directories::new().and_then(|directories| directories.exports())
+    .ok_or_else(|| anyhow::anyhow!("Exports directory is unknown."))?;
+
+  remove_archives(&exports, job.user_id);
+
+  let media = select_exported_media(conn, job.user_id).await?;
+  let total = media.len() as u32;
+
+  // the archive is renamed when it's complete, so an interrupted export never leaves a broken archive
+  let path = archive_path(&exports, job);
+  let partial = path.with_extension("zip.partial");
+
+  let result = write_archive(conn, job, format, media, total, &partial).await;
+  if result.is_err() {
+    fs::remove_file(&partial).ok();
+    return result;
+  }
+
+  fs::rename(&partial, &path)?;
+
+  Ok(())
+}
+
+/// Removes archives of the user's previous exports.
+fn remove_archives(exports: &Path, user_id: i32) {
+  let prefix = format!("{}-", user_id);
+
+  for entry in fs::read_dir(exports).into_iter().flatten().filter_map(Result::ok) {
+    if entry.file_name().to_string_lossy().starts_with(&prefix) && fs::remove_file(entry.path()).is_err() {
+      warn!("Export archive {:?} couldn't be removed.", entry.path());
+    }
+  }
+}
+
+/// Selects media of the user which are liked, in albums or described, ordered by their IDs.
+async fn select_exported_media(conn: &DbConn, user_id: i32) -> Result<Vec<ExportedMedia>, diesel::result::Error> {
+  let mut exported: BTreeMap<i32, ExportedMedia> = BTreeMap::new();
+
+  for media in db::media::get_liked_media(conn, user_id, MediaPagination::default()).await? {
+    exported.entry(media.id).or_insert(ExportedMedia { media, albums: vec![], favorite: false }).favorite = true;
+  }
+
+  // media of smart albums are selected automatically, favorites are exported on their own
+  for album in db::albums::get_album_list(conn, user_id).await?.into_iter().filter(|album| album.smart().is_none()) {
+    for media in db::albums::get_album_media(conn, album.id, MediaPagination::default()).await? {
+      exported.entry(media.id).or_insert(ExportedMedia { media, albums: vec![], favorite: false }).albums.push(album.name.clone());
+    }
+  }
+
+  for media in db::media::select_media_with_description(conn, user_id).await? {
+    exported.entry(media.id).or_insert(ExportedMedia { media, albums: vec![], favorite: false });
+  }
+
+  Ok(exported.into_values().filter(|exported| exported.media.owner_id == user_id && exported.media.missing_since.is_none()).collect())
+}
+
+async fn write_archive(conn: &DbConn, job: &Job, format: ExportFormat, media: Vec<ExportedMedia>, total: u32, path: &Path) -> anyhow::Result<()> {
+  let mut zip = ZipFileWriter::with_tokio(File::create(path).await?);
+  let mut used_names = HashSet::new();
+
+  for (processed, exported) in media.into_iter().enumerate() {
+    let relative = scan::get_relative_media_path(conn, &exported.media).await;
+
+    match (format, relative) {
+      (ExportFormat::Folders, _) => {
+        let original = match scan::get_original_media_path(conn, &exported.media).await {
+          Some(original) if original.is_file() => original,
+          _ => {
+            warn!("Media {} wasn't exported as its file is missing.", exported.media.uuid);
+            continue;
+          },
+        };
+
+        let mut directories: Vec<String> = exported.albums.iter().map(|album| format!("Albums/{}", directory_name(album))).collect();
+        if exported.favorite { directories.push(String::from("Favorites")) }
+
+        for directory in directories {
+          let name = unique_name(&mut used_names, format!("{}/{}", directory, exported.media.filename));
+          let mut file = File::open(&original).await?.compat();
+
+          // media are already compressed, so there is no point in compressing them again
+          let mut entry_writer = zip.write_entry_stream(ZipEntryBuilder::new(name.into(), Compression::Stored)).await?;
+          futures::io::copy(&mut file, &mut entry_writer).await?;
+          entry_writer.close().await?;
+        }
+      },
+      (ExportFormat::Digikam, Some(relative)) => {
+        let name = format!("{}.xmp", archive_name(&relative));
+        zip.write_entry_whole(ZipEntryBuilder::new(name.into(), Compression::Stored), digikam_sidecar(&exported).as_bytes()).await?;
+      },
+      (ExportFormat::Photoprism, Some(relative)) => {
+        let name = format!("sidecar/{}.yml", archive_name(&relative.with_extension("")));
+        zip.write_entry_whole(ZipEntryBuilder::new(name.into(), Compression::Stored), photoprism_sidecar(&exported).as_bytes()).await?;
+      },
+      (_, None) => warn!("Media {} wasn't exported as its folder is unknown.", exported.media.uuid),
+    }
+
+    db::jobs::update_job_progress(conn, job.id, processed as u32 + 1, Some(total)).await?;
+  }
+
+  zip.close().await?;
+
+  Ok(())
+}
+
+/// Returns the path with `/` separators used in archives.
+fn archive_name(path: &Path) -> String {
+  path.iter().map(|component| component.to_string_lossy()).collect::<Vec<_>>().join("/")
+}
+
+/// Returns the album name usable as a name of a directory.
+fn directory_name(album: &str) -> String {
+  let name = album.replace(['/', '\\'], "_");
+
+  match name.trim() {
+    "" | "." | ".." => String::from("_"),
+    _ => name,
+  }
+}
+
+/// Returns a name which wasn't used yet, e.g. `Albums/Holiday/IMG_0001 (1).jpg` for media of different folders.
+fn unique_name(used_names: &mut HashSet<String>, name: String) -> String {
+  let mut unique = name.clone();
+  let mut counter = 1;
+
+  while used_names.contains(&unique) {
+    unique = match name.rsplit_once('.') {
+      Some((stem, extension)) if !extension.contains('/') => format!("{} ({}).{}", stem, counter, extension),
+      _ => format!("{} ({})", name, counter),
+    };
+    counter += 1;
+  }
+
+  used_names.insert(unique.clone());
+  unique
+}
+
+fn digikam_sidecar(exported: &ExportedMedia) -> String {
+  let mut tags: Vec<String> = exported.albums.iter().map(|album| format!("Galera/Albums/{}", album.replace('/', "_"))).collect();
+  if exported.favorite { tags.push(String::from("Galera/Favorites")) }
+
+  let tags = tags.iter()
+    .map(|tag| format!("<rdf:li>{}</rdf:li>", escape_xml(tag)))
+    .collect::<String>();
+
+  let description = exported.media.description.as_deref()
+    .map(|description| format!("<dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:description>", escape_xml(description)))
+    .unwrap_or_default();
+
+  format!(
+    concat!(
+      "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n",
+      "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n",
+      "<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n",
+      "<rdf:Description rdf:about=\"\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\" xmlns:digiKam=\"http://www.digikam.org/ns/1.0/\">\n",
+      "<digiKam:TagsList><rdf:Seq>{}</rdf:Seq></digiKam:TagsList>\n",
+      "{}\n",
+      "</rdf:Description>\n",
+      "</rdf:RDF>\n",
+      "</x:xmpmeta>\n",
+      "<?xpacket end=\"w\"?>\n",
+    ),
+    tags, description
+  )
+}
+
+fn photoprism_sidecar(exported: &ExportedMedia) -> String {
+  // JSON strings are valid YAML scalars, so they don't need any other escaping
+  let quote = |text: &str| serde_json::to_string(text).unwrap_or_default();
+
+  let mut sidecar = format!("Favorite: {}\n", exported.favorite);
+
+  if let Some(description) = exported.media.description.as_deref() {
+    sidecar.push_str(&format!("Description: {}\nDescriptionSrc: manual\n", quote(description)));
+  }
+
+  if !exported.albums.is_empty() {
+    sidecar.push_str(&format!("Details:\n  Keywords: {}\n  KeywordsSrc: manual\n", quote(&exported.albums.join(", "))));
+  }
+
+  sidecar
+}
+
+/// Archive of a finished export.
+pub struct ExportDownload {
+  file: NamedFile,
+  filename: String,
+}
+
+impl ExportDownload {
+  /// Opens the archive of the finished job; returns `None` when it was removed by a newer export.
+  pub async fn open(job: &Job) -> Option<Self> {
+    let exports = Directories::new()?.exports()?;
+    let file = NamedFile::open(archive_path(&exports, job)).await.ok()?;
+
+    Some(Self { file, filename: format!("galera-export-{}.zip", job.created_at.format("%Y-%m-%d")) })
+  }
+}
+
+impl<'r> Responder<'r, 'static> for ExportDownload {
+  fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+    Response::build_from(self.file.respond_to(request)?)
+      .header(ContentType::ZIP)
+      .raw_header("Content-Disposition", format!("attachment; filename=\"{}\"", self.filename))
+      .ok()
+  }
+}
+
+impl OpenApiResponderInner for ExportDownload {
+  fn responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+    let mut responses = <Vec<u8>>::responses(gen)?;
+    set_content_type(&mut responses, ContentType::ZIP)?;
+
+    Ok(responses)
+  }
+}
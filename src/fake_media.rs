@@ -0,0 +1,139 @@
+//! Generator of synthetic media for load testing.
+//!
+//! Only compiled with the `fake-media` feature, e.g. `cargo run --features fake-media`.\
+//! Media are generated from a seed, so the same `count` and `seed` always produce the same
+//! filenames, dates and dimensions. Every media gets a tiny (1x1) placeholder PNG,
+//! while the stored dimensions are those of common camera and phone resolutions.
+
+use crate::auth::token::Claims;
+use crate::directories::Directories;
+use crate::models::NewMedia;
+use crate::{db, errors, scan, DbConn};
+use checksums::{hash_file, Algorithm::SHA2512};
+use chrono::NaiveDateTime;
+use image::{Rgb, RgbImage};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use uuid::Builder;
+
+/// Maximum number of media generated by one request.
+pub const MAX_FAKE_MEDIA: u32 = 100_000;
+
+/// Number of media inserted in one statement.
+const BATCH_SIZE: usize = 1000;
+
+/// Common resolutions of cameras, phones and screenshots.
+const DIMENSIONS: [(u32, u32); 8] = [
+  (4032, 3024),
+  (3024, 4032),
+  (6000, 4000),
+  (4000, 6000),
+  (1920, 1080),
+  (1080, 1920),
+  (2560, 1440),
+  (1280, 960),
+];
+
+/// UTC offsets (in seconds) of generated media; `None` means unknown offset.
+const OFFSETS: [Option<i32>; 5] = [None, Some(0), Some(3600), Some(7200), Some(-18000)];
+
+/// Media are taken between 2005-01-01 and 2022-01-01.
+const DATE_TAKEN_FROM: i64 = 1_104_537_600;
+const DATE_TAKEN_TO: i64 = 1_640_995_200;
+
+#[derive(Serialize)]
+pub struct FakeMediaResponse {
+  pub folder: String,
+  pub inserted: usize,
+}
+
+/// Generates `count` synthetic media owned by the authenticated user.
+///
+/// Media are placed in the `fake-media-<seed>` folder of the user, so generating them again
+/// with the same seed is rejected with 409 instead of creating duplicates.
+#[post("/fake_media?<count>&<seed>")]
+pub async fn generate_fake_media(claims: Claims, conn: DbConn, count: u32, seed: u64) -> Result<Json<FakeMediaResponse>, Status> {
+  if count == 0 || count > MAX_FAKE_MEDIA { return Err(Status::UnprocessableEntity) }
+
+  let username = db::users::get_user_username(&conn, claims.user_id).await.map_err(errors::internal)?;
+  if username.is_none() { return Err(Status::InternalServerError) }
+
+  let username = username.unwrap();
+
+  let directories = Directories::new();
+  if directories.is_none() { return Err(Status::InternalServerError) }
+
+  let gallery = directories.unwrap().gallery();
+  if gallery.is_none() { return Err(Status::InternalServerError) }
+
+  let folder_name = format!("fake-media-{}", seed);
+  let directory = gallery.unwrap().join(&username).join(&folder_name);
+  if directory.exists() { return Err(Status::Conflict) }
+
+  if rocket::tokio::fs::create_dir_all(&directory).await.is_err() { return Err(Status::InternalServerError) }
+
+  scan::add_folders_to_db(&conn, vec![PathBuf::from(&username).join(&folder_name)], claims.user_id).await;
+
+  let root_folder = db::folders::select_root_folder(&conn, claims.user_id).await;
+  if root_folder.is_err() { return Err(Status::InternalServerError) }
+
+  let root_folder = root_folder.unwrap();
+  if root_folder.is_none() { return Err(Status::InternalServerError) }
+
+  let folder_id = db::folders::select_child_folder_id(&conn, folder_name.clone(), Some(root_folder.unwrap().id), claims.user_id).await.map_err(errors::internal)?;
+  if folder_id.is_none() { return Err(Status::InternalServerError) }
+
+  let folder_id = folder_id.unwrap();
+  let user_id = claims.user_id;
+
+  // writing and hashing the files is blocking
+  let new_media = rocket::tokio::task::spawn_blocking(move || generate(&directory, folder_id, user_id, count, seed)).await;
+  if new_media.is_err() { return Err(Status::InternalServerError) }
+
+  let new_media = new_media.unwrap();
+  if new_media.is_err() { return Err(Status::InternalServerError) }
+
+  let mut new_media = new_media.unwrap();
+  let mut inserted = 0;
+  while !new_media.is_empty() {
+    let batch: Vec<NewMedia> = new_media.drain(..new_media.len().min(BATCH_SIZE)).collect();
+
+    let result = db::media::insert_media_batch(&conn, batch).await;
+    if result.is_err() { return Err(Status::InternalServerError) }
+
+    inserted += result.unwrap();
+  }
+
+  info!("Generated {} fake media for user {} (seed {}).", inserted, user_id, seed);
+
+  Ok(Json(FakeMediaResponse { folder: folder_name, inserted }))
+}
+
+/// Writes the placeholder files and returns media ready to be inserted.
+fn generate(directory: &Path, folder_id: i32, owner_id: i32, count: u32, seed: u64) -> Result<Vec<NewMedia>, image::ImageError> {
+  let mut rng = StdRng::seed_from_u64(seed);
+  let mut new_media = Vec::with_capacity(count as usize);
+
+  for i in 0..count {
+    let filename = format!("fake_{:06}.png", i);
+    let path = directory.join(&filename);
+
+    // every file has a different color (count is below 2^24), so their hashes differ too
+    let [_, r, g, b] = i.to_be_bytes();
+    let color = Rgb([r, g, b]);
+    RgbImage::from_pixel(1, 1, color).save(&path)?;
+
+    let (width, height) = DIMENSIONS[rng.gen_range(0..DIMENSIONS.len())];
+    let date_taken = NaiveDateTime::from_timestamp(rng.gen_range(DATE_TAKEN_FROM..DATE_TAKEN_TO), 0);
+    let date_taken_offset = OFFSETS[rng.gen_range(0..OFFSETS.len())];
+    let uuid = Builder::from_random_bytes(rng.gen()).into_uuid().to_string();
+
+    new_media.push(NewMedia::new(filename, folder_id, owner_id, width, height, None, date_taken, date_taken_offset, uuid, hash_file(&path, SHA2512)));
+  }
+
+  Ok(new_media)
+}
@@ -0,0 +1,37 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Experimental subsystems which can be enabled for individual users.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+  /// Automatic tagging of media using machine learning.
+  MlTagging,
+  /// Automatic scanning of changed files.
+  Watcher,
+}
+
+impl Feature {
+  /// All existing features.
+  pub const ALL: [Feature; 2] = [Feature::MlTagging, Feature::Watcher];
+
+  /// Returns the name used in the database.
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Feature::MlTagging => "ml_tagging",
+      Feature::Watcher => "watcher",
+    }
+  }
+}
+
+impl FromStr for Feature {
+  type Err = ();
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    Feature::ALL.iter()
+      .find(|feature| feature.as_str() == s)
+      .copied()
+      .ok_or(())
+  }
+}
@@ -0,0 +1,81 @@
+//! Map of media with GPS coordinates.
+//!
+//! Media in the shown part of the map are grouped into clusters on a grid, so a map doesn't have to draw
+//! a marker for each of thousands of media. Cells of the grid are a quarter of a 256 pixels wide map tile
+//! (about 64 pixels), so they get smaller as the map zooms in; the projection is ignored, cells are squares in degrees.
+
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Maximum zoom level of common web maps.
+pub const MAX_ZOOM: u8 = 22;
+
+/// Number of grid cells per map tile in each direction.
+const CELLS_PER_TILE: f64 = 4.0;
+
+/// Shown part of the map in degrees; `west` is greater than `east` when it crosses the antimeridian.
+#[derive(Debug, Clone, Copy)]
+pub struct GeoBounds {
+  pub north: f64,
+  pub south: f64,
+  pub east: f64,
+  pub west: f64,
+}
+
+impl GeoBounds {
+  pub fn is_valid(&self) -> bool {
+    let latitudes = -90.0..=90.0;
+    let longitudes = -180.0..=180.0;
+
+    latitudes.contains(&self.north) && latitudes.contains(&self.south) && self.south <= self.north
+      && longitudes.contains(&self.east) && longitudes.contains(&self.west)
+  }
+
+  pub fn crosses_antimeridian(&self) -> bool {
+    self.west > self.east
+  }
+}
+
+/// Media close to each other at the zoom level.
+#[derive(Serialize, JsonSchema, Debug)]
+pub struct GeoCluster {
+  /// Average latitude of the media.
+  pub latitude: f64,
+  /// Average longitude of the media.
+  pub longitude: f64,
+  pub count: usize,
+  /// The newest media of the cluster, e.g. for its thumbnail; the only media when `count` is 1.
+  pub media_uuid: String,
+}
+
+/// Groups media into clusters, the biggest first.\
+/// `media` are tuples of a UUID, latitude and longitude, ordered by preference for the media shown by a cluster.
+pub fn cluster(media: Vec<(String, f64, f64)>, zoom: u8) -> Vec<GeoCluster> {
+  // cells divide 180 degrees evenly, so none of them crosses the antimeridian
+  let cell_size = 360.0 / (2f64.powi(i32::from(zoom.min(MAX_ZOOM))) * CELLS_PER_TILE);
+
+  let mut cells: HashMap<(i64, i64), GeoCluster> = HashMap::new();
+  for (media_uuid, latitude, longitude) in media {
+    let cell = ((latitude / cell_size).floor() as i64, (longitude / cell_size).floor() as i64);
+
+    // coordinates are summed first and averaged below
+    let cluster = cells.entry(cell).or_insert_with(|| GeoCluster { latitude: 0.0, longitude: 0.0, count: 0, media_uuid });
+    cluster.latitude += latitude;
+    cluster.longitude += longitude;
+    cluster.count += 1;
+  }
+
+  let mut clusters: Vec<GeoCluster> = cells.into_values()
+    .map(|mut cluster| {
+      cluster.latitude /= cluster.count as f64;
+      cluster.longitude /= cluster.count as f64;
+
+      cluster
+    })
+    .collect();
+
+  clusters.sort_by(|a, b| b.count.cmp(&a.count));
+
+  clusters
+}
@@ -0,0 +1,419 @@
+//! Import of favorites, albums and descriptions from PhotoPrism and Immich.
+//!
+//! Users switching to Galera keep their curation: the media are scanned from the same files as before, and
+//! the metadata exported from the other software are mapped onto them. Media are matched by their paths first;
+//! the longest trailing part of the exported path which matches a path in the user's folder wins, so it doesn't
+//! matter where the other software kept its originals. Media without a unique match are matched by their SHA-1 hashes,
+//! which both PhotoPrism and Immich store.
+//!
+//! Imports never remove anything: media are liked, descriptions are set only for media without one, and media are added
+//! to the user's albums with the same name, which are created when they don't exist.
+//!
+//! Exports are JSON objects with lists returned by the APIs of the other software:
+//! - PhotoPrism: `{"photos": [...], "albums": [...]}` with photos from `/api/v1/photos` (`UID`, `FileName`, `Hash`,
+//!   `Favorite`, `Description`) and albums from `/api/v1/albums` with their `Photos` as in album backups
+//!   (`[{"UID": "..."}]`).
+//! - Immich: `{"assets": [...], "albums": [...]}` with assets from `/api/assets` (`id`, `originalPath`, `checksum`,
+//!   `isFavorite`, `exifInfo.description`) and albums from `/api/albums/<id>` with their `assets`.
+
+use crate::db;
+use crate::directories::Directories;
+use crate::libraries::UserLibraries;
+use crate::models::{Folder, Media, NewAlbum, NewAlbumMedia};
+use crate::routes::pagination::MediaPagination;
+use crate::DbConn;
+use checksums::{hash_reader, Algorithm::SHA1};
+use rocket::form::FromFormField;
+use rocket::tokio::task;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// Maximum number of exported paths listed as unmatched in the report.
+const MAX_UNMATCHED: usize = 1000;
+
+#[derive(FromFormField, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportSource {
+  Photoprism,
+  Immich,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PhotoPrismPhoto {
+  #[serde(rename = "UID")]
+  uid: String,
+  /// Path relative to the originals of PhotoPrism.
+  file_name: String,
+  /// SHA-1 of the file.
+  #[serde(default)]
+  hash: Option<String>,
+  #[serde(default)]
+  favorite: bool,
+  #[serde(default)]
+  description: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PhotoPrismAlbumPhoto {
+  #[serde(rename = "UID")]
+  uid: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PhotoPrismAlbum {
+  title: String,
+  #[serde(default)]
+  description: Option<String>,
+  #[serde(default)]
+  photos: Vec<PhotoPrismAlbumPhoto>,
+}
+
+#[derive(Deserialize)]
+struct PhotoPrismExport {
+  photos: Vec<PhotoPrismPhoto>,
+  #[serde(default)]
+  albums: Vec<PhotoPrismAlbum>,
+}
+
+#[derive(Deserialize)]
+struct ImmichExif {
+  #[serde(default)]
+  description: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ImmichAsset {
+  id: String,
+  /// Absolute path on the Immich server.
+  original_path: String,
+  /// Base64 of the SHA-1 of the file.
+  #[serde(default)]
+  checksum: Option<String>,
+  #[serde(default)]
+  is_favorite: bool,
+  #[serde(default)]
+  exif_info: Option<ImmichExif>,
+}
+
+#[derive(Deserialize)]
+struct ImmichAlbumAsset {
+  id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ImmichAlbum {
+  album_name: String,
+  #[serde(default)]
+  description: Option<String>,
+  #[serde(default)]
+  assets: Vec<ImmichAlbumAsset>,
+}
+
+#[derive(Deserialize)]
+struct ImmichExport {
+  assets: Vec<ImmichAsset>,
+  #[serde(default)]
+  albums: Vec<ImmichAlbum>,
+}
+
+/// Media of the other software.
+struct ForeignMedia {
+  id: String,
+  path: String,
+  /// Uppercase hex of the SHA-1.
+  sha1: Option<String>,
+  favorite: bool,
+  description: Option<String>,
+}
+
+/// Album of the other software with IDs of its media.
+struct ForeignAlbum {
+  name: String,
+  description: Option<String>,
+  media_ids: Vec<String>,
+}
+
+/// Parsed export of the other software.
+pub struct ForeignLibrary {
+  media: Vec<ForeignMedia>,
+  albums: Vec<ForeignAlbum>,
+}
+
+impl ForeignLibrary {
+  /// Parses the export; returns `None` when it isn't an export of the source.
+  pub fn parse(data: &str, source: ImportSource) -> Option<Self> {
+    match source {
+      ImportSource::Photoprism => {
+        let export: PhotoPrismExport = serde_json::from_str(data).ok()?;
+
+        Some(ForeignLibrary {
+          media: export.photos.into_iter()
+            .map(|photo| ForeignMedia {
+              id: photo.uid,
+              path: photo.file_name,
+              sha1: photo.hash.map(|hash| hash.to_uppercase()),
+              favorite: photo.favorite,
+              description: photo.description,
+            })
+            .collect(),
+          albums: export.albums.into_iter()
+            .map(|album| ForeignAlbum { name: album.title, description: album.description, media_ids: album.photos.into_iter().map(|photo| photo.uid).collect() })
+            .collect(),
+        })
+      },
+      ImportSource::Immich => {
+        let export: ImmichExport = serde_json::from_str(data).ok()?;
+
+        Some(ForeignLibrary {
+          media: export.assets.into_iter()
+            .map(|asset| ForeignMedia {
+              id: asset.id,
+              path: asset.original_path,
+              sha1: asset.checksum.and_then(|checksum| base64::decode(checksum).ok()).map(|hash| hex(&hash)),
+              favorite: asset.is_favorite,
+              description: asset.exif_info.and_then(|exif| exif.description),
+            })
+            .collect(),
+          albums: export.albums.into_iter()
+            .map(|album| ForeignAlbum { name: album.album_name, description: album.description, media_ids: album.assets.into_iter().map(|asset| asset.id).collect() })
+            .collect(),
+        })
+      },
+    }
+  }
+}
+
+fn hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+/// Result of an import.
+#[derive(Serialize, JsonSchema, Debug, Default)]
+pub struct ImportReport {
+  /// Nothing was changed, the report shows what would be imported.
+  pub dry_run: bool,
+  /// Number of exported media found in the user's folder.
+  pub matched: usize,
+  /// Number of matched media which were found by their hashes, as their paths weren't unique or didn't match.
+  pub matched_by_hash: usize,
+  /// Paths of exported media which weren't found (at most 1000).
+  pub unmatched: Vec<String>,
+  /// Number of media which were liked; media the user already liked aren't counted.
+  pub liked: usize,
+  /// Number of media which got a description.
+  pub described: usize,
+  /// Names of albums which were created.
+  pub albums_created: Vec<String>,
+  /// Number of media added to albums.
+  pub album_media_added: usize,
+  /// IDs of the albums whose media changed.
+  #[serde(skip)]
+  pub album_ids: Vec<i32>,
+}
+
+/// Media of the user by their paths relative to the user's folder.
+struct MediaIndex {
+  media: Vec<Media>,
+  paths: Vec<PathBuf>,
+  /// Indexes of media by their relative paths.
+  by_path: HashMap<PathBuf, usize>,
+  /// Indexes of media by their filenames.
+  by_filename: HashMap<String, Vec<usize>>,
+  /// Indexes of media by their SHA-1 hashes; computed when a hash is needed the first time.
+  by_sha1: Option<HashMap<String, usize>>,
+  username: String,
+  libraries: UserLibraries,
+}
+
+impl MediaIndex {
+  async fn load(conn: &DbConn, user_id: i32) -> Result<Option<Self>, diesel::result::Error> {
+    let folders: HashMap<i32, Folder> = db::folders::select_user_folders(conn, user_id).await?
+      .into_iter()
+      .map(|folder| (folder.id, folder))
+      .collect();
+
+    let media = db::media::get_media_structure(conn, user_id, None, MediaPagination::default()).await?;
+
+    let gallery = Directories::new().and_then(|directories| directories.gallery());
+    let username = db::users::get_user_username(conn, user_id).await?;
+
+    let (gallery, username) = match (gallery, username) {
+      (Some(gallery), Some(username)) => (gallery, username),
+      _ => return Ok(None),
+    };
+
+    let libraries = match UserLibraries::load(conn, &gallery, user_id, &username).await {
+      Ok(libraries) => libraries,
+      Err(_) => return Ok(None),
+    };
+
+    let mut index = MediaIndex {
+      media: vec![],
+      paths: vec![],
+      by_path: HashMap::new(),
+      by_filename: HashMap::new(),
+      by_sha1: None,
+      username,
+      libraries,
+    };
+
+    for media in media {
+      let path = match relative_path(&folders, media.folder_id) {
+        Some(folder_path) => folder_path.join(&media.filename),
+        None => continue,
+      };
+
+      let position = index.media.len();
+      index.by_path.insert(path.clone(), position);
+      index.by_filename.entry(media.filename.clone()).or_default().push(position);
+      index.paths.push(path);
+      index.media.push(media);
+    }
+
+    Ok(Some(index))
+  }
+
+  /// Returns the absolute path of the media file.
+  fn absolute_path(&self, position: usize) -> PathBuf {
+    self.libraries.resolve(&Path::new(&self.username).join(&self.paths[position]))
+  }
+
+  /// Finds the media by the longest trailing part of the path; returns `None` when it's missing or not unique.
+  fn find_by_path(&self, path: &str) -> Option<usize> {
+    let components: Vec<&str> = path.split(['/', '\\']).filter(|component| !component.is_empty()).collect();
+
+    // the filename alone is checked separately, as it's often not unique
+    for start in 0..components.len().saturating_sub(1) {
+      let suffix: PathBuf = components[start..].iter().collect();
+      if let Some(position) = self.by_path.get(&suffix) { return Some(*position) }
+    }
+
+    match self.by_filename.get(*components.last()?).map(Vec::as_slice) {
+      Some([position]) => Some(*position),
+      _ => None,
+    }
+  }
+
+  /// Finds the media by its hash; hashes of all media are computed on the first call.
+  async fn find_by_sha1(&mut self, sha1: &str) -> Option<usize> {
+    if self.by_sha1.is_none() {
+      let files: Vec<PathBuf> = (0..self.media.len()).map(|position| self.absolute_path(position)).collect();
+
+      let hashes = task::spawn_blocking(move || {
+        files.iter()
+          .enumerate()
+          .filter_map(|(position, path)| Some((sha1_file(path)?, position)))
+          .collect::<HashMap<String, usize>>()
+      }).await.unwrap_or_default();
+
+      self.by_sha1 = Some(hashes);
+    }
+
+    self.by_sha1.as_ref()?.get(sha1).copied()
+  }
+}
+
+/// Returns the path of the folder relative to the user's folder; the root folder is named after the user.
+fn relative_path(folders: &HashMap<i32, Folder>, folder_id: i32) -> Option<PathBuf> {
+  let mut names = vec![];
+  let mut folder = folders.get(&folder_id)?;
+
+  while let Some(parent) = folder.parent {
+    names.push(folder.name.as_str());
+    folder = folders.get(&parent)?;
+  }
+
+  Some(names.into_iter().rev().collect())
+}
+
+fn sha1_file(path: &Path) -> Option<String> {
+  let mut file = File::open(path).ok()?;
+
+  Some(hash_reader(&mut file, SHA1).to_uppercase())
+}
+
+/// Maps the exported metadata onto the user's media; with `dry_run`, nothing is changed.
+pub async fn import(conn: &DbConn, user_id: i32, library: ForeignLibrary, dry_run: bool) -> Result<Option<ImportReport>, diesel::result::Error> {
+  let mut index = match MediaIndex::load(conn, user_id).await? {
+    Some(index) => index,
+    None => return Ok(None),
+  };
+
+  let mut report = ImportReport { dry_run, ..Default::default() };
+
+  // IDs of the other software mapped to the IDs of matched media
+  let mut matched: HashMap<String, i32> = HashMap::new();
+
+  for foreign in library.media {
+    let mut position = index.find_by_path(&foreign.path);
+
+    if position.is_none() {
+      if let Some(sha1) = foreign.sha1.as_deref() {
+        position = index.find_by_sha1(sha1).await;
+        if position.is_some() { report.matched_by_hash += 1 }
+      }
+    }
+
+    let media = match position {
+      Some(position) => &index.media[position],
+      None => {
+        if report.unmatched.len() < MAX_UNMATCHED { report.unmatched.push(foreign.path) }
+        continue;
+      },
+    };
+
+    report.matched += 1;
+    matched.insert(foreign.id, media.id);
+
+    if foreign.favorite {
+      let liked = if dry_run { !db::media::media_is_liked(conn, media.id, user_id).await? } else { db::media::media_like(conn, media.id, user_id).await? > 0 };
+      if liked { report.liked += 1 }
+    }
+
+    let description = foreign.description.filter(|description| !description.trim().is_empty());
+    let has_description = !media.description.as_deref().unwrap_or_default().is_empty();
+
+    if let (Some(description), false) = (description, has_description) {
+      if !dry_run { db::media::update_description(conn, media.id, Some(description)).await?; }
+      report.described += 1;
+    }
+  }
+
+  for album in library.albums {
+    let mut media_ids: Vec<i32> = album.media_ids.iter().filter_map(|id| matched.get(id).copied()).collect();
+    media_ids.sort_unstable();
+    media_ids.dedup();
+
+    if media_ids.is_empty() { continue }
+
+    match db::albums::select_user_album_by_name(conn, user_id, album.name.clone()).await? {
+      Some(existing) => {
+        let album_media = media_ids.iter()
+          .map(|media_id| NewAlbumMedia::new(existing.id, *media_id, Some(user_id)))
+          .collect::<Vec<NewAlbumMedia>>();
+
+        report.album_media_added += if dry_run { media_ids.len() } else { db::albums::album_add_media(conn, album_media).await? };
+        report.album_ids.push(existing.id);
+      },
+      None => {
+        report.album_media_added += media_ids.len();
+
+        if !dry_run {
+          let album_id = db::albums::insert_album_with_media(conn, NewAlbum::new(user_id, album.name.clone(), album.description, None), media_ids).await?;
+          report.album_ids.push(album_id);
+        }
+
+        report.albums_created.push(album.name);
+      },
+    }
+  }
+
+  Ok(Some(report))
+}
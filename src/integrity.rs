@@ -0,0 +1,92 @@
+//! Periodic verification of media files against their stored hashes, used to detect bit rot.
+//!
+//! Every night at `integrity_check_hour` (UTC), up to `integrity_check_files` media which weren't verified
+//! for the longest time are hashed again. Files are verified one by one, so the check doesn't saturate disk IO.
+//! Results are stored in the `media_integrity` table; administrators see the failures in `GET /admin/integrity`
+//! and their number in `GET /admin/stats`.
+
+use crate::background::Background;
+use crate::db;
+use crate::models::{IntegrityStatus, MediaIntegrity};
+use crate::scan;
+use crate::DbConn;
+use checksums::{hash_file, Algorithm::SHA2512};
+use chrono::{Duration, Timelike, Utc};
+use rocket::tokio::{task, time};
+
+/// Media which failed the check.
+#[derive(Debug, Clone)]
+pub struct IntegrityFailure {
+  pub media_uuid: String,
+  pub status: IntegrityStatus,
+}
+
+/// Report of a check.
+#[derive(Debug, Clone)]
+pub struct IntegrityReport {
+  /// Number of verified media.
+  pub verified: usize,
+  pub failures: Vec<IntegrityFailure>,
+}
+
+/// Verifies up to `limit` media and returns the report of the check.
+pub async fn verify_media(conn: &DbConn, limit: i64) -> Result<IntegrityReport, diesel::result::Error> {
+  let media_list = db::integrity::select_media_to_verify(conn, limit).await?;
+  let mut report = IntegrityReport { verified: 0, failures: vec![] };
+
+  for media in media_list {
+    let path = scan::get_media_path(conn, &media).await;
+
+    let (status, actual_sha2_512) = match path {
+      Some(path) if path.is_file() => {
+        let hash = task::spawn_blocking(move || hash_file(&path, SHA2512)).await;
+
+        match hash {
+          Ok(hash) if hash.eq_ignore_ascii_case(&media.sha2_512) => (IntegrityStatus::Ok, None),
+          Ok(hash) => (IntegrityStatus::Mismatch, Some(hash)),
+          Err(_) => continue,
+        }
+      },
+      _ => (IntegrityStatus::Missing, None),
+    };
+
+    db::integrity::upsert_media_integrity(conn, MediaIntegrity::new(media.id, status, actual_sha2_512)).await?;
+    report.verified += 1;
+
+    if status != IntegrityStatus::Ok {
+      warn!("Integrity check of media {} failed: {}.", media.uuid, status.as_str());
+      report.failures.push(IntegrityFailure { media_uuid: media.uuid, status });
+    }
+  }
+
+  Ok(report)
+}
+
+/// Runs the integrity check every night at the given hour (UTC).
+pub async fn run(background: Background, files_per_night: i64, hour: u32) {
+  loop {
+    let now = Utc::now();
+    let mut next = now.date().and_hms(hour % 24, 0, 0);
+    if next <= now { next = next + Duration::days(1) }
+
+    time::sleep((next - now).to_std().unwrap_or_default()).await;
+
+    let conn = background.conn().await;
+    if conn.is_none() {
+      error!("Integrity check was skipped as no database connection is available.");
+      continue;
+    }
+
+    info!("Integrity check started at {}:00.", Utc::now().hour());
+
+    let report = match verify_media(&conn.unwrap(), files_per_night).await {
+      Ok(report) => report,
+      Err(err) => {
+        error!("Integrity check failed: {}", err);
+        continue;
+      },
+    };
+
+    info!("Integrity check is done, {} of {} media failed.", report.failures.len(), report.verified);
+  }
+}
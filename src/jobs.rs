@@ -0,0 +1,117 @@
+//! Long-running jobs persisted in the `job` table.
+//!
+//! A job is stored as running when it starts and moves to finished or failed when it ends.
+//! Jobs which are still running when the server starts were interrupted by a restart (or a crash),
+//! so they are marked as failed. Interrupted scans are resumed by a new job, as the scanner skips media
+//! which are already in the database; other jobs must be started again by the user.
+
+use crate::config::{DuplicatePolicy, GalleryCheckPolicy, ScanAlertPolicy, SymlinkPolicy};
+use crate::db;
+use crate::derivatives;
+use crate::directories::Directories;
+use crate::models::{Job, JobKind, JobState, NewJob, NewScanAlert, NewScanIssue};
+use crate::scan;
+use crate::DbConn;
+
+/// Error of jobs interrupted by a restart.
+pub const INTERRUPTED: &str = "Interrupted by a server restart.";
+
+/// Maximum number of file issues stored for one scan, e.g. when the gallery contains a whole tree of other files.
+const MAX_SCAN_ISSUES: usize = 1000;
+
+/// Scans the gallery of the job's user and stores the result in the job.\
+/// An alert is stored when the scan finds an unusual share of media modified or missing,
+/// and issues are stored for files which the scan couldn't add.
+pub async fn run_scan(conn: &DbConn, job: &Job, symlinks: SymlinkPolicy, duplicates: DuplicatePolicy, alerts: ScanAlertPolicy, gallery_check: GalleryCheckPolicy) -> JobState {
+  let result = match Directories::new().and_then(|directories| directories.gallery()) {
+    Some(gallery) => scan::scan_root(conn, gallery, job.user_id, symlinks, duplicates, alerts, gallery_check).await,
+    None => Err("Gallery directory is unknown."),
+  };
+
+  if let Ok(summary) = result.as_ref() {
+    if summary.alert && db::scan::insert_scan_alert(conn, NewScanAlert::new(job, summary)).await.is_err() {
+      error!("Alert of scan {} couldn't be saved.", job.uuid);
+    }
+
+    if summary.issues.len() > MAX_SCAN_ISSUES {
+      warn!("Scan {} found {} file issues; only the first {} are saved.", job.uuid, summary.issues.len(), MAX_SCAN_ISSUES);
+    }
+
+    let issues = summary.issues.iter()
+      .take(MAX_SCAN_ISSUES)
+      .map(|issue| NewScanIssue::new(job, issue))
+      .collect::<Vec<NewScanIssue>>();
+
+    if !issues.is_empty() && db::scan::insert_scan_issues(conn, issues).await.is_err() {
+      error!("Issues of scan {} couldn't be saved.", job.uuid);
+    }
+  }
+
+  if let Err(err) = derivatives::remove_orphaned_derivatives(conn).await {
+    error!("Orphaned derivatives couldn't be removed: {}", err);
+  }
+
+  let (state, error) = match result {
+    Ok(_) => (JobState::Finished, None),
+    Err(err) => (JobState::Failed, Some(err.to_string())),
+  };
+
+  if db::jobs::finish_job(conn, job.id, state, error).await.is_err() {
+    error!("State of job {} couldn't be saved.", job.uuid);
+  }
+
+  state
+}
+
+/// Marks interrupted jobs as failed and returns new jobs resuming the interrupted scans.\
+/// Must be called before the server starts accepting requests, otherwise new jobs would be marked too.
+pub async fn recover_interrupted(conn: &DbConn) -> Result<Vec<Job>, diesel::result::Error> {
+  let interrupted = db::jobs::select_running_jobs(conn).await?;
+  let mut resumed = vec![];
+
+  for job in interrupted {
+    warn!("Job {} ({}) was interrupted by a restart.", job.uuid, job.kind);
+
+    db::jobs::finish_job(conn, job.id, JobState::Failed, Some(INTERRUPTED.to_string())).await?;
+
+    if job.kind() == Some(JobKind::Scan) {
+      resumed.push(db::jobs::insert_job(conn, NewJob::resumed(&job, JobKind::Scan)).await?);
+    }
+  }
+
+  Ok(resumed)
+}
+
+/// Runs resumed scans one by one.
+pub async fn run_resumed_scans(conn: DbConn, jobs: Vec<Job>, symlinks: SymlinkPolicy, duplicates: DuplicatePolicy, alerts: ScanAlertPolicy, gallery_check: GalleryCheckPolicy) {
+  if gallery_check.strict { wait_for_gallery(gallery_check).await }
+
+  for job in jobs {
+    info!("Resuming interrupted scan {} as {}.", job.resumed_from.as_deref().unwrap_or_default(), job.uuid);
+
+    run_scan(&conn, &job, symlinks, duplicates, alerts, gallery_check).await;
+  }
+}
+
+/// Seconds between checks of the gallery while waiting for it.
+const GALLERY_CHECK_INTERVAL: u64 = 10;
+
+/// Waits up to `wait_seconds` of the policy until the gallery passes its checks, e.g. until a network mount is ready.
+async fn wait_for_gallery(gallery_check: GalleryCheckPolicy) {
+  let gallery = match Directories::new().and_then(|directories| directories.gallery()) {
+    Some(gallery) => gallery,
+    None => return,
+  };
+
+  let mut waited = 0;
+  while let Err(reason) = scan::check_gallery(&gallery, gallery_check) {
+    if waited >= gallery_check.wait_seconds {
+      warn!("{} Resumed scans won't wait any longer.", reason);
+      return;
+    }
+
+    info!("{} Resumed scans wait for it.", reason);
+    rocket::tokio::time::sleep(std::time::Duration::from_secs(GALLERY_CHECK_INTERVAL)).await;
+    waited += GALLERY_CHECK_INTERVAL;
+  }
+}
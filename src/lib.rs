@@ -0,0 +1,801 @@
+#![warn(
+  clippy::doc_markdown,
+  clippy::unused_self,
+  unused_extern_crates,
+  unused_qualifications
+)]
+
+#![allow(
+  clippy::manual_range_contains,
+  clippy::too_many_arguments
+)]
+
+#[macro_use]
+extern crate diesel;
+
+#[macro_use]
+extern crate rocket;
+
+#[macro_use]
+extern crate rocket_okapi;
+
+#[macro_use]
+extern crate log;
+
+#[macro_use]
+extern crate diesel_migrations;
+
+use okapi::merge::merge_specs;
+use okapi::openapi3::OpenApi;
+use rocket_okapi::get_openapi_route;
+use rocket_okapi::settings::OpenApiSettings;
+use rocket_okapi::swagger_ui::{ make_swagger_ui, SwaggerUIConfig };
+use rocket_sync_db_pools::database;
+use chrono::NaiveDateTime;
+use futures::future::BoxFuture;
+use nanoid::nanoid;
+use rocket::{Rocket, Build, Orbit, Route};
+use rocket::fairing::AdHoc;
+use crate::api_version::{with_server_prefix, LegacyRoutes};
+use crate::auth::password::VerifiedPasswords;
+use crate::auth::secret::Secret;
+use crate::background::Background;
+use crate::bandwidth::BandwidthLimiter;
+use crate::banned_passwords::BannedPasswords;
+use crate::compression::Compression;
+use crate::config::{Config, HttpSettings};
+use crate::coview::CoViewSessions;
+use crate::directories::Directories;
+use crate::login_limit::LoginLimiter;
+use crate::migrations::MigrationReport;
+use crate::routes::pagination::natural_sort_key;
+use crate::stream_limit::StreamLimiter;
+use crate::transcode::Transcoder;
+use crate::write_back::WriteBackJobs;
+
+// mod media;
+// mod errors;
+pub mod db;
+pub mod routes;
+pub mod models;
+pub mod scan;
+pub mod schema;
+pub mod api_version;
+pub mod auth;
+pub mod background;
+pub mod bandwidth;
+pub mod banned_passwords;
+pub mod catchers;
+pub mod compression;
+pub mod config;
+pub mod coview;
+pub mod derivatives;
+#[cfg(feature = "detection")]
+pub mod detection;
+pub mod directories;
+pub mod download;
+pub mod edit;
+pub mod errors;
+pub mod export;
+#[cfg(feature = "fake-media")]
+pub mod fake_media;
+pub mod features;
+pub mod geo;
+pub mod import;
+pub mod integrity;
+pub mod jobs;
+pub mod libraries;
+pub mod locale;
+pub mod login_limit;
+pub mod maintenance;
+pub mod management;
+pub mod metadata;
+pub mod migrations;
+pub mod orientation;
+pub mod panics;
+pub mod stream_limit;
+pub mod telemetry;
+pub mod timeouts;
+pub mod transcode;
+pub mod upload;
+pub mod validation;
+pub mod watcher;
+pub mod write_back;
+
+/// Number of media whose natural sort keys are created in one transaction.
+const SORT_KEY_BATCH_SIZE: i64 = 1000;
+
+/// Number of media checked at once when dating media by their filenames.
+const FILENAME_DATE_BATCH_SIZE: i64 = 1000;
+
+/// Connection to the database.\
+/// Diesel sets the utf8mb4 character set on every connection, so text with emoji (e.g. descriptions) isn't mangled.
+#[database("galera")]
+pub struct DbConn(diesel::MysqlConnection);
+
+/// Builds the Rocket instance with all fairings and routes.
+pub fn rocket() -> Rocket<Build> {
+  env_logger::init();
+  panics::install_hook();
+
+  dotenv::dotenv().ok();
+
+  let dir = Directories::new();
+  if dir.is_none() { panic!("Directories check failed."); }
+
+  let secret = match check_secret_startup() {
+    Ok(secret) => secret,
+    Err(err) => panic!("Secret couldn't be read and/or created: {}", err),
+  };
+
+  // the OpenAPI document is served next to the routes of each mount
+  let openapi_settings = OpenApiSettings::default();
+  let (api_routes, mut api_spec) = api_routes(&openapi_settings);
+
+  // served by the management listener when it's configured, see `management`
+  let (management_routes, management_spec) = management_routes(&openapi_settings);
+
+  // handlers of both listeners are limited by the timeouts of their routes
+  let timeout_policy = timeouts::policy();
+  let api_routes = timeouts::wrap(api_routes, &timeout_policy);
+  let management_routes = timeouts::wrap(management_routes, &timeout_policy);
+
+  let management_address = management::address();
+
+  let api_routes = match management_address {
+    Some(_) => api_routes,
+    None => {
+      merge_specs(&mut api_spec, &"", &management_spec).expect("management routes can be documented with the API");
+      api_routes.into_iter().chain(management_routes.clone()).collect()
+    },
+  };
+
+  let api_prefix = api_version::prefix();
+
+  let rocket = rocket::custom(config::figment())
+    .attach(DbConn::fairing())
+    .attach(AdHoc::config::<Config>())
+    .manage(secret)
+    .manage(StreamLimiter::default())
+    .manage(WriteBackJobs::default())
+    .manage(VerifiedPasswords::default())
+    .manage(CoViewSessions::default())
+    .attach(AdHoc::try_on_ignite("Database migration", run_migrations))
+    .attach(AdHoc::on_ignite("Libraries", sync_libraries))
+    .attach(AdHoc::on_ignite("Job recovery", recover_jobs))
+    .attach(AdHoc::on_ignite("HTTP settings", manage_http_settings))
+    .attach(AdHoc::on_ignite("Derivative layout", set_derivative_layout))
+    .attach(AdHoc::on_ignite("Transcoder", manage_transcoder))
+    .attach(AdHoc::on_ignite("Bandwidth limiter", manage_bandwidth_limiter))
+    .attach(AdHoc::on_ignite("Login limiter", manage_login_limiter))
+    .attach(AdHoc::try_on_ignite("Banned passwords", load_banned_passwords))
+    .attach(AdHoc::try_on_ignite("Filename dates", set_filename_date_patterns))
+    .attach(AdHoc::on_liftoff("Derivative cleanup", cleanup_derivatives))
+    .attach(AdHoc::on_liftoff("Instance administrator", promote_first_admin))
+    .attach(AdHoc::on_liftoff("Natural sort keys", fill_sort_keys))
+    .attach(AdHoc::on_liftoff("Media dates from filenames", fill_filename_dates))
+    .attach(AdHoc::on_liftoff("Album date ranges", fill_album_date_ranges))
+    .attach(AdHoc::on_liftoff("Album thumbnails", fill_album_thumbnails))
+    .attach(AdHoc::on_liftoff("Folder UUIDs", fill_folder_uuids))
+    .attach(AdHoc::on_liftoff("Token hashes", hash_plaintext_tokens))
+    .attach(AdHoc::on_liftoff("Integrity check", start_integrity_check))
+    .attach(AdHoc::on_liftoff("Share link access retention", start_share_link_access_retention))
+    .attach(AdHoc::on_liftoff("Telemetry", start_telemetry))
+    .attach(AdHoc::on_liftoff("Detection", start_detection))
+    .attach(AdHoc::on_liftoff("Watcher", start_watcher))
+    .attach(AdHoc::on_liftoff("Management listener", move |rocket| {
+      let routes = management_routes.clone();
+      let spec = management_spec.clone();
+
+      Box::pin(async move {
+        if let Some(address) = management_address {
+          management::launch(rocket, address, routes, spec).await;
+        }
+      })
+    }))
+    .attach(LegacyRoutes)
+    .attach(Compression)
+    .register("/", catchers::all())
+    .mount(&api_prefix, api_routes.clone())
+    .mount(&api_prefix, vec![get_openapi_route(with_server_prefix(api_spec.clone(), &api_prefix), &openapi_settings)])
+    // legacy routes of clients written before versioning, see `api_version`
+    .mount("/", api_routes)
+    .mount("/", vec![get_openapi_route(api_spec, &openapi_settings)])
+    .mount(
+      "/swagger-ui/",
+      make_swagger_ui(&SwaggerUIConfig {
+        url: format!("..{}/openapi.json", api_prefix),
+        ..Default::default()
+      }),
+    );
+
+  // development tools are intentionally left out of the OpenAPI document
+  #[cfg(feature = "fake-media")]
+  let rocket = rocket.mount("/dev", routes![fake_media::generate_fake_media]);
+
+  rocket
+}
+
+/// Returns routes of the API with their `OpenApi` document.\
+/// Routes are listed by the names of their functions, which become operation IDs of the document
+/// (e.g. `media_structure`), so clients generated from it get clean method names.
+/// Operation IDs are a part of the API: renaming a route function renames the methods of generated clients.
+pub fn api_routes(openapi_settings: &OpenApiSettings) -> (Vec<Route>, OpenApi) {
+  use crate::routes::*;
+
+  openapi_get_routes_spec![openapi_settings:
+    index,
+    media_structure,
+    get_folders,
+    get_folder_media,
+    create_album_from_folder,
+    scan_media,
+    get_scan_ignore_patterns,
+    update_scan_ignore_patterns,
+    get_orphaned_folders,
+    get_missing_media,
+    purge_missing_media,
+    relink_missing_media,
+    get_media_by_uuid,
+    get_media_playback,
+    get_media_preview_strip,
+    get_media_preview_strip_vtt,
+    download_media,
+    upload_media,
+    get_media_integrity_failures,
+    create_user,
+    change_password,
+    reset_password,
+    get_user_sessions,
+    delete_user_session,
+    get_organization,
+    update_organization,
+    create_organization,
+    get_organization_users,
+    create_organization_user,
+    import_organization_users,
+    create_user_invite,
+    get_user_invites,
+    delete_user_invite,
+    add_organization_admin,
+    delete_organization_admin,
+    onboard_user,
+    get_user_settings,
+    update_user_settings,
+    get_album_list,
+    create_album,
+    update_album,
+    update_album_thumbnail,
+    delete_album,
+    album_add_media,
+    album_remove_media,
+    lock_album,
+    unlock_album,
+    create_album_invite,
+    get_album_invites,
+    get_album_stats,
+    get_pending_album_invites,
+    accept_album_invite,
+    delete_album_invite,
+    login,
+    refresh_token,
+    get_media_geo,
+    get_media_liked_list,
+    get_duplicate_media,
+    get_album_structure,
+    media_like,
+    media_unlike,
+    get_media_shared_list,
+    get_tags,
+    get_media_tags,
+    get_media_tag_suggestions,
+    add_media_tags,
+    remove_media_tag,
+    create_person,
+    get_people,
+    get_person_media,
+    get_media_faces,
+    create_media_face,
+    delete_media_face,
+    get_media_grants,
+    create_media_grant,
+    delete_media_grant,
+    system_info_public,
+    system_features,
+    media_update_description,
+    edit_media,
+    get_media_versions,
+    revert_media_version,
+    start_metadata_write_back,
+    get_metadata_write_back,
+    get_jobs,
+    start_export,
+    download_export,
+    import_metadata,
+    get_scan_issues,
+    media_delete_description,
+    update_media_batch,
+    create_album_share_link,
+    get_album_share_links,
+    get_album_share_link,
+    update_album_share_link,
+    delete_album_share_link,
+    get_album_share_link_activity,
+    download_shared_album,
+    get_shared_album_slideshow,
+    create_co_view_session,
+    update_co_view_session,
+    delete_co_view_session,
+    join_co_view_session
+  ]
+}
+
+/// Returns administration routes, `/health` and `/metrics` with their `OpenApi` document, see `api_routes()`.\
+/// They're served by the management listener when it's configured, see `management`.
+pub fn management_routes(openapi_settings: &OpenApiSettings) -> (Vec<Route>, OpenApi) {
+  use crate::routes::*;
+  use crate::routes::admin::*;
+
+  openapi_get_routes_spec![openapi_settings:
+    health,
+    metrics,
+    system_bandwidth,
+    system_logins,
+    system_migrations,
+    system_telemetry,
+    admin_get_users,
+    admin_update_user_role,
+    admin_disable_user,
+    admin_delete_user,
+    admin_create_password_reset,
+    admin_scan_user,
+    admin_get_scan_alerts,
+    admin_confirm_scan_alert,
+    admin_get_stats,
+    admin_get_integrity_failures,
+    admin_get_libraries,
+    admin_create_library,
+    admin_delete_library,
+    admin_get_user_features,
+    admin_update_user_feature
+  ]
+}
+
+/// Runs migrations.\
+/// Rocket doesn't start when there are destructive migrations and `allow_destructive_migrations` isn't enabled,
+/// see `migrations` for details.
+pub async fn run_migrations(rocket: Rocket<Build>) -> Result<Rocket<Build>, Rocket<Build>> {
+
+  let allow_destructive = rocket.state::<Config>().map_or(false, |config| config.allow_destructive_migrations);
+
+  let conn = DbConn::get_one(&rocket).await.expect("database connection");
+  let report = conn.run(|c| MigrationReport::new(c)).await.expect("can check migrations");
+
+  if report.is_destructive() && !allow_destructive {
+    for migration in report.pending.iter().filter(|migration| migration.is_destructive()) {
+      error!("Migration {} can lose data: {}", migration.version, migration.destructive_statements.join("; "));
+    }
+
+    error!("Back up the database and enable allow_destructive_migrations (ROCKET_ALLOW_DESTRUCTIVE_MIGRATIONS=true) to apply the migrations.");
+    return Err(rocket);
+  }
+
+  conn.run(|c| migrations::run_pending(c, &mut std::io::sink())).await.expect("can run migrations");
+
+  Ok(rocket)
+}
+
+/// Adds libraries from the configuration to the database before resumed scans start.
+pub async fn sync_libraries(rocket: Rocket<Build>) -> Rocket<Build> {
+  let configured = rocket.state::<Config>().map(|config| config.libraries.clone()).unwrap_or_default();
+  if configured.is_empty() { return rocket }
+
+  let conn = DbConn::get_one(&rocket).await.expect("database connection");
+  libraries::sync_configured(&conn, &configured).await;
+
+  rocket
+}
+
+/// Marks jobs interrupted by the last shutdown as failed and resumes interrupted scans.\
+/// It runs before the server starts, so jobs started by requests are never mistaken for interrupted ones.
+pub async fn recover_jobs(rocket: Rocket<Build>) -> Rocket<Build> {
+  let conn = DbConn::get_one(&rocket).await.expect("database connection");
+
+  let resumed = match jobs::recover_interrupted(&conn).await {
+    Ok(resumed) => resumed,
+    Err(err) => {
+      error!("Interrupted jobs couldn't be recovered: {}", err);
+      return rocket;
+    },
+  };
+
+  if !resumed.is_empty() {
+    let symlinks = rocket.state::<Config>().map(|config| config.scan_symlinks).unwrap_or_default();
+    let duplicates = rocket.state::<Config>().map(|config| config.scan_duplicates).unwrap_or_default();
+    let alerts = rocket.state::<Config>().map(|config| config.scan_alerts).unwrap_or_default();
+    let gallery_check = rocket.state::<Config>().map(|config| config.gallery_check).unwrap_or_default();
+    rocket::tokio::spawn(jobs::run_resumed_scans(conn, resumed, symlinks, duplicates, alerts, gallery_check));
+  }
+
+  rocket
+}
+
+/// Manages the HTTP server settings in effect, so they can be shown in `/system/features`.
+pub async fn manage_http_settings(rocket: Rocket<Build>) -> Rocket<Build> {
+  // Rocket validates its configuration itself while igniting, so the defaults are fine here
+  let http_settings = HttpSettings::new(&rocket.figment().extract().unwrap_or_default());
+
+  rocket.manage(http_settings)
+}
+
+/// Sets the configured layout of derivatives.
+pub async fn set_derivative_layout(rocket: Rocket<Build>) -> Rocket<Build> {
+  let depth = rocket.state::<Config>().map(|config| config.derivative_shard_depth).unwrap_or_default();
+  derivatives::set_shard_depth(depth);
+
+  rocket
+}
+
+/// Manages the transcoder of videos with the configured `ffmpeg`.
+pub async fn manage_transcoder(rocket: Rocket<Build>) -> Rocket<Build> {
+  let config = rocket.state::<Config>().cloned().unwrap_or_default();
+
+  rocket.manage(Transcoder::new(config.ffmpeg, config.max_transcodes))
+}
+
+/// Manages the bandwidth limiter of share links with the configured global limit.
+pub async fn manage_bandwidth_limiter(rocket: Rocket<Build>) -> Rocket<Build> {
+  let limit = rocket.state::<Config>().map(|config| config.share_link_bandwidth_limit).unwrap_or_default();
+
+  rocket.manage(BandwidthLimiter::new(limit))
+}
+
+/// Manages the counters of failed logins with the configured limits.
+pub async fn manage_login_limiter(rocket: Rocket<Build>) -> Rocket<Build> {
+  let policy = rocket.state::<Config>().map(|config| config.login_limits).unwrap_or_default();
+
+  rocket.manage(LoginLimiter::new(policy))
+}
+
+/// Reads the filter of banned passwords set in the password policy.\
+/// Rocket doesn't start when the filter can't be read.
+pub async fn load_banned_passwords(rocket: Rocket<Build>) -> Result<Rocket<Build>, Rocket<Build>> {
+  let path = rocket.state::<Config>().and_then(|config| config.password_policy.banned_passwords.clone());
+
+  let banned_passwords = match path {
+    Some(path) => match BannedPasswords::read(&path) {
+      Ok(banned_passwords) => banned_passwords,
+      Err(err) => {
+        error!("Banned passwords couldn't be read from {:?}: {}", path, err);
+        return Err(rocket);
+      },
+    },
+    None => BannedPasswords::empty(),
+  };
+
+  Ok(rocket.manage(banned_passwords))
+}
+
+/// Sets the configured patterns of dates in filenames.\
+/// Rocket doesn't start when a pattern is invalid.
+pub async fn set_filename_date_patterns(rocket: Rocket<Build>) -> Result<Rocket<Build>, Rocket<Build>> {
+  let patterns = rocket.state::<Config>().cloned().unwrap_or_default().filename_date_patterns;
+
+  if let Err(pattern) = metadata::set_filename_date_patterns(&patterns) {
+    error!("Filename date pattern {:?} isn't a regular expression with year, month and day groups.", pattern);
+    return Err(rocket);
+  }
+
+  Ok(rocket)
+}
+
+/// Removes derivatives of media that were deleted while the server wasn't running
+/// and starts removing them every `derivative_cleanup_interval_hours`.
+pub fn cleanup_derivatives(rocket: &Rocket<Orbit>) -> BoxFuture<'_, ()> {
+  Box::pin(async move {
+    let conn = DbConn::get_one(rocket).await.expect("database connection");
+
+    match derivatives::remove_orphaned_derivatives(&conn).await {
+      Ok(removed) => info!("Removed {} orphaned derivatives directories.", removed),
+      Err(err) => error!("Orphaned derivatives couldn't be removed: {}", err),
+    }
+
+    let interval_hours = rocket.state::<Config>().map(|config| config.derivative_cleanup_interval_hours).unwrap_or_default();
+    if interval_hours == 0 { return }
+
+    let background = Background::new(rocket).await.expect("database pool");
+
+    rocket::tokio::spawn(derivatives::run_cleanup(background, interval_hours));
+  })
+}
+
+/// Makes the oldest user an administrator of the instance when there's none, e.g. after roles were added.
+pub fn promote_first_admin(rocket: &Rocket<Orbit>) -> BoxFuture<'_, ()> {
+  Box::pin(async move {
+    let conn = DbConn::get_one(rocket).await.expect("database connection");
+
+    match db::users::promote_first_admin(&conn).await {
+      Ok(Some(user_id)) => info!("User {} was made an administrator of the instance.", user_id),
+      Ok(None) => {},
+      Err(err) => error!("Administrator of the instance couldn't be promoted: {}", err),
+    }
+  })
+}
+
+/// Creates natural sort keys of media added before natural sorting existed.
+pub fn fill_sort_keys(rocket: &Rocket<Orbit>) -> BoxFuture<'_, ()> {
+  Box::pin(async move {
+    let conn = DbConn::get_one(rocket).await.expect("database connection");
+    let mut filled = 0;
+
+    loop {
+      let media = match db::media::select_media_without_sort_key(&conn, SORT_KEY_BATCH_SIZE).await {
+        Ok(media) if media.is_empty() => break,
+        Ok(media) => media,
+        Err(err) => {
+          error!("Natural sort keys couldn't be selected: {}", err);
+          return;
+        },
+      };
+
+      let keys: Vec<(i32, String)> = media.iter().map(|(id, filename)| (*id, natural_sort_key(filename))).collect();
+      filled += keys.len();
+
+      if let Err(err) = db::media::update_media_sort_keys(&conn, keys).await {
+        error!("Natural sort keys couldn't be saved: {}", err);
+        return;
+      }
+    }
+
+    if filled > 0 { info!("Created natural sort keys of {} media.", filled) }
+  })
+}
+
+/// Dates media by their filenames when they were dated by the modification times of their files,
+/// e.g. because they were scanned before their filename patterns were configured; see `metadata`.
+pub fn fill_filename_dates(rocket: &Rocket<Orbit>) -> BoxFuture<'_, ()> {
+  Box::pin(async move {
+    let conn = DbConn::get_one(rocket).await.expect("database connection");
+    let mut dated_ids = vec![];
+    let mut after_id = 0;
+
+    loop {
+      let media = match db::media::select_media_dated_by_modification(&conn, after_id, FILENAME_DATE_BATCH_SIZE).await {
+        Ok(media) if media.is_empty() => break,
+        Ok(media) => media,
+        Err(err) => {
+          error!("Media dated by modification times couldn't be selected: {}", err);
+          return;
+        },
+      };
+
+      after_id = media.last().map(|(id, _, _, _)| *id).unwrap_or(after_id);
+
+      let dates: Vec<(i32, NaiveDateTime)> = media.iter()
+        // dates taken are stored in whole seconds, modification times in microseconds
+        .filter(|(_, _, date_taken, modified)| (*date_taken - *modified).num_seconds().abs() <= 1)
+        .filter_map(|(id, filename, _, _)| Some((*id, metadata::filename_date(filename)?)))
+        .collect();
+
+      if dates.is_empty() { continue }
+
+      dated_ids.extend(dates.iter().map(|(id, _)| *id));
+
+      if let Err(err) = db::media::update_media_dates(&conn, dates).await {
+        error!("Media dates from filenames couldn't be saved: {}", err);
+        return;
+      }
+    }
+
+    if dated_ids.is_empty() { return }
+
+    info!("Dated {} media by their filenames.", dated_ids.len());
+
+    match db::albums::select_media_album_ids(&conn, dated_ids).await {
+      Ok(album_ids) => for album_id in album_ids {
+        if let Err(err) = db::albums::update_album_date_range(&conn, album_id).await {
+          error!("Date range of album {} couldn't be updated: {}", album_id, err);
+        }
+      },
+      Err(err) => error!("Albums of media dated by their filenames couldn't be selected: {}", err),
+    }
+  })
+}
+
+/// Computes date ranges of albums created before date ranges existed.\
+/// Empty albums have no range, so they're checked again at every start.
+pub fn fill_album_date_ranges(rocket: &Rocket<Orbit>) -> BoxFuture<'_, ()> {
+  Box::pin(async move {
+    let conn = DbConn::get_one(rocket).await.expect("database connection");
+
+    let albums = match db::albums::select_albums_without_date_range(&conn).await {
+      Ok(albums) => albums,
+      Err(err) => {
+        error!("Albums without a date range couldn't be selected: {}", err);
+        return;
+      },
+    };
+
+    for album_id in albums {
+      if let Err(err) = db::albums::update_album_date_range(&conn, album_id).await {
+        error!("Date range of album {} couldn't be updated: {}", album_id, err);
+      }
+    }
+  })
+}
+
+/// Chooses thumbnails of albums created before thumbnails were chosen.\
+/// Empty albums have no thumbnail, so they're checked again at every start.
+pub fn fill_album_thumbnails(rocket: &Rocket<Orbit>) -> BoxFuture<'_, ()> {
+  Box::pin(async move {
+    let conn = DbConn::get_one(rocket).await.expect("database connection");
+
+    let albums = match db::albums::select_albums_without_thumbnail(&conn).await {
+      Ok(albums) => albums,
+      Err(err) => {
+        error!("Albums without a thumbnail couldn't be selected: {}", err);
+        return;
+      },
+    };
+
+    for album_id in albums {
+      if let Err(err) = db::albums::update_album_thumbnail_fallback(&conn, album_id).await {
+        error!("Thumbnail of album {} couldn't be updated: {}", album_id, err);
+      }
+    }
+  })
+}
+
+/// Gives UUIDs to folders created before folders had UUIDs.
+pub fn fill_folder_uuids(rocket: &Rocket<Orbit>) -> BoxFuture<'_, ()> {
+  Box::pin(async move {
+    let conn = DbConn::get_one(rocket).await.expect("database connection");
+
+    let folders = match db::folders::select_folders_without_uuid(&conn).await {
+      Ok(folders) => folders,
+      Err(err) => {
+        error!("Folders without a UUID couldn't be selected: {}", err);
+        return;
+      },
+    };
+
+    for folder_id in folders {
+      if let Err(err) = db::folders::update_folder_uuid(&conn, folder_id, nanoid!()).await {
+        error!("UUID of folder {} couldn't be saved: {}", folder_id, err);
+      }
+    }
+  })
+}
+
+/// Hashes refresh, access, invite and password reset tokens stored in plaintext by older versions, see `auth::token::hash_token`.
+pub fn hash_plaintext_tokens(rocket: &Rocket<Orbit>) -> BoxFuture<'_, ()> {
+  Box::pin(async move {
+    let conn = DbConn::get_one(rocket).await.expect("database connection");
+
+    match db::tokens::hash_plaintext_tokens(&conn).await {
+      Ok(0) => (),
+      Ok(hashed) => info!("Hashed {} tokens stored in plaintext.", hashed),
+      Err(err) => error!("Tokens stored in plaintext couldn't be hashed: {}", err),
+    }
+  })
+}
+
+/// Starts the nightly integrity check when it's enabled.
+pub fn start_integrity_check(rocket: &Rocket<Orbit>) -> BoxFuture<'_, ()> {
+  Box::pin(async move {
+    let config = rocket.state::<Config>().expect("configuration");
+    if config.integrity_check_files <= 0 { return }
+
+    let background = Background::new(rocket).await.expect("database pool");
+
+    rocket::tokio::spawn(integrity::run(background, config.integrity_check_files, config.integrity_check_hour));
+  })
+}
+
+/// Starts the daily deletion of old accesses through share links, unless they're kept forever.
+pub fn start_share_link_access_retention(rocket: &Rocket<Orbit>) -> BoxFuture<'_, ()> {
+  Box::pin(async move {
+    let config = rocket.state::<Config>().expect("configuration");
+    if config.share_link_access_retention_days == 0 { return }
+
+    let background = Background::new(rocket).await.expect("database pool");
+
+    rocket::tokio::spawn(auth::shared_album_link::prune_accesses(background, config.share_link_access_retention_days));
+  })
+}
+
+/// Starts the watcher scanning changed files of users with the `watcher` feature, unless it's disabled.
+pub fn start_watcher(rocket: &Rocket<Orbit>) -> BoxFuture<'_, ()> {
+  Box::pin(async move {
+    let config = rocket.state::<Config>().expect("configuration");
+    if config.watch.interval_seconds == 0 { return }
+
+    let background = Background::new(rocket).await.expect("database pool");
+
+    rocket::tokio::spawn(watcher::run(background, config.clone()));
+  })
+}
+
+/// Starts sending anonymous usage statistics when they are enabled.
+pub fn start_telemetry(rocket: &Rocket<Orbit>) -> BoxFuture<'_, ()> {
+  Box::pin(async move {
+    let config = rocket.state::<Config>().expect("configuration");
+    if !config.telemetry { return }
+
+    let endpoint = match &config.telemetry_endpoint {
+      Some(endpoint) => endpoint.clone(),
+      None => {
+        warn!("Telemetry is enabled, but no telemetry_endpoint is set; nothing will be sent.");
+        return;
+      },
+    };
+
+    let background = Background::new(rocket).await.expect("database pool");
+
+    rocket::tokio::spawn(telemetry::run(background, endpoint, config.telemetry_interval_hours));
+  })
+}
+
+/// Starts the detection of faces and objects when a model is configured.
+pub fn start_detection(rocket: &Rocket<Orbit>) -> BoxFuture<'_, ()> {
+  Box::pin(async move {
+    let config = rocket.state::<Config>().expect("configuration");
+    if config.detection.model.is_none() { return }
+
+    #[cfg(not(feature = "detection"))]
+    warn!("A detection model is configured, but Galera was built without the detection feature; media won't be processed.");
+
+    #[cfg(feature = "detection")]
+    {
+      let policy = config.detection.clone();
+      let detector = match rocket::tokio::task::spawn_blocking(move || detection::Detector::load(policy)).await {
+        Ok(Ok(detector)) => detector,
+        Ok(Err(err)) => return error!("Detection model couldn't be loaded: {}", err),
+        Err(err) => return error!("Loading of the detection model panicked: {}", err),
+      };
+
+      let background = Background::new(rocket).await.expect("database pool");
+
+      rocket::tokio::spawn(detection::run(background, detector));
+    }
+  })
+}
+
+/// Reads the secret and creates the secret.key file in the config directory if it's missing.\
+/// This is meant to be run before starting Rocket; the returned secret is then managed by Rocket.
+pub fn check_secret_startup() -> Result<Secret, std::io::Error> {
+  if Secret::is_external() {
+    let secret = Secret::read()?;
+    info!("The secret was read from the environment.");
+    return Ok(secret);
+  }
+
+  match Secret::read() {
+    Ok(secret) => {
+      info!("The secret.key file was successfully read.");
+      return Ok(secret);
+    },
+    // other errors (e.g. insecure permissions) must stop the startup
+    Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err),
+    Err(_) => {},
+  }
+
+  match Secret::read_legacy() {
+    Ok(legacy) => {
+      warn!("Moving secret.key from the working directory to the config directory.");
+      legacy.write()?;
+
+      // a copy left in the working directory could be readable by other users
+      if let Err(err) = Secret::remove_legacy() {
+        warn!("The old secret.key in the working directory couldn't be removed, remove it manually: {}", err);
+      }
+    },
+    Err(_) => {
+      Secret::new().write()?;
+      warn!("Created missing secret.key file.");
+    },
+  }
+
+  // It is also possible to have write-only access, so we must check reading too.
+  Secret::read()
+}
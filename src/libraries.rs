@@ -0,0 +1,185 @@
+//! Libraries: directories with media of a user outside of the gallery, e.g. on external drives.
+//!
+//! Media of a user are in their directory in the gallery (`<gallery>/<username>`) by default. A library without a name
+//! replaces this directory, named libraries appear as subfolders of the user's root folder; e.g. the folder
+//! `john/external/2022` is the directory `2022` of john's library `external`. A library hides a directory
+//! of the same name in the user's directory.
+//!
+//! Libraries are set up by administrators, either in the configuration (added or updated at startup)
+//! or using `/admin/users/<username>/libraries`.
+//! # Example
+//! ```toml
+//! [[default.libraries]]
+//! user = "john"
+//! name = "external"
+//! path = "/mnt/external/photos"
+//! ```
+
+use crate::config::LibraryConfig;
+use crate::db;
+use crate::models::{Library, NewLibrary};
+use crate::validation;
+use crate::DbConn;
+use std::path::{Path, PathBuf};
+
+/// Directories with media of one user, used to find directories of the user's folders.
+#[derive(Clone, Debug, Default)]
+pub struct UserLibraries {
+  username: String,
+  /// Directory of the user's root folder.
+  root: PathBuf,
+  /// Names and directories of named libraries.
+  libraries: Vec<(String, PathBuf)>,
+}
+
+impl UserLibraries {
+  pub fn new(gallery: &Path, username: &str, libraries: &[Library]) -> Self {
+    let root = libraries.iter()
+      .find(|library| library.name.is_none())
+      .map_or_else(|| gallery.join(username), |library| PathBuf::from(&library.path));
+
+    let libraries = libraries.iter()
+      .filter_map(|library| Some((library.name.clone()?, PathBuf::from(&library.path))))
+      .collect();
+
+    Self { username: username.to_owned(), root, libraries }
+  }
+
+  /// Loads libraries of the user from the database.
+  pub async fn load(conn: &DbConn, gallery: &Path, user_id: i32, username: &str) -> Result<Self, diesel::result::Error> {
+    let libraries = db::libraries::select_libraries(conn, user_id).await?;
+
+    Ok(Self::new(gallery, username, &libraries))
+  }
+
+  pub fn username(&self) -> &str {
+    &self.username
+  }
+
+  /// Returns the directory of the user's root folder.
+  pub fn root(&self) -> &Path {
+    &self.root
+  }
+
+  /// Returns directories of the root folder and of the libraries with their paths relative to the gallery
+  /// (e.g. `john` and `john/external`).
+  pub fn directories(&self) -> Vec<(PathBuf, &Path)> {
+    let mut directories = vec![(PathBuf::from(&self.username), self.root.as_path())];
+
+    for (name, directory) in &self.libraries {
+      directories.push((Path::new(&self.username).join(name), directory.as_path()));
+    }
+
+    directories
+  }
+
+  /// Returns the directory of a folder given by its path relative to the gallery, e.g. `john/external/2022`.\
+  /// The first component is the user's root folder.
+  pub fn resolve(&self, relative: &Path) -> PathBuf {
+    let mut components = relative.iter().skip(1);
+
+    let library = components.next()
+      .and_then(|name| self.libraries.iter().find(|(library, _)| name == library.as_str()));
+
+    let mut path = match library {
+      Some((_, directory)) => directory.clone(),
+      None => {
+        components = relative.iter().skip(1);
+        self.root.clone()
+      },
+    };
+
+    path.extend(components);
+
+    path
+  }
+
+  /// Returns the path of a file or directory relative to the gallery, like the paths of folders;
+  /// `None` when it's outside of the user's directories.
+  pub fn relative(&self, path: &Path) -> Option<PathBuf> {
+    // the longest match wins when a library is inside of another directory
+    let library = self.libraries.iter()
+      .filter_map(|(name, directory)| Some((name, directory, path.strip_prefix(directory).ok()?)))
+      .max_by_key(|(_, directory, _)| directory.components().count());
+
+    if let Some((name, _, rest)) = library {
+      return Some(Path::new(&self.username).join(name).join(rest));
+    }
+
+    path.strip_prefix(&self.root).ok().map(|rest| Path::new(&self.username).join(rest))
+  }
+}
+
+/// Returns the directory of the user's root folder, e.g. for uploads.\
+/// The user's directory in the gallery is created when it's missing; other directories must exist,
+/// so nothing is written to the mount point of an unavailable drive.
+pub async fn user_directory(conn: &DbConn, gallery: &Path, user_id: i32, username: &str) -> Option<PathBuf> {
+  let libraries = UserLibraries::load(conn, gallery, user_id, username).await.ok()?;
+  let root = libraries.root().to_path_buf();
+
+  if root.starts_with(gallery) {
+    rocket::tokio::fs::create_dir_all(&root).await.ok()?;
+  } else if !root.is_dir() {
+    error!("Library {:?} of user {} isn't available.", root, username);
+    return None;
+  }
+
+  Some(root)
+}
+
+/// Checks whether the name of a library can be a folder name.
+pub fn is_name_valid(name: &str) -> bool {
+  !name.trim().is_empty()
+    && name.chars().count() <= 255
+    && name != "."
+    && name != ".."
+    && !name.starts_with('.')
+    && !name.contains(|c| c == '/' || c == '\\' || c == '\0')
+}
+
+/// Checks whether the path of a library is an absolute path of a directory.
+pub fn is_path_valid(path: &Path) -> bool {
+  path.is_absolute() && path.is_dir() && path.to_str().map_or(false, |path| path.len() <= 4096)
+}
+
+/// Adds libraries from the configuration to the database, or updates their paths.\
+/// Invalid libraries and libraries of unknown users are skipped.
+pub async fn sync_configured(conn: &DbConn, libraries: &[LibraryConfig]) {
+  for configured in libraries {
+    let name = configured.name.as_deref().map(str::trim).filter(|name| !name.is_empty()).map(str::to_owned);
+
+    if name.as_deref().map_or(false, |name| !is_name_valid(name)) || !configured.path.is_absolute() {
+      warn!("Library {:?} of user {} is invalid and was skipped.", configured.path, configured.user);
+      continue;
+    }
+
+    if !configured.path.is_dir() {
+      warn!("Library {:?} of user {} isn't available.", configured.path, configured.user);
+    }
+
+    let user_id = match db::users::get_user_id(conn, validation::normalize_identifier(&configured.user)).await {
+      Ok(Some(user_id)) => user_id,
+      Ok(None) => {
+        warn!("User {} of library {:?} doesn't exist.", configured.user, configured.path);
+        continue;
+      },
+      Err(err) => {
+        error!("User {} of library {:?} couldn't be loaded: {}", configured.user, configured.path, err);
+        continue;
+      },
+    };
+
+    let path = configured.path.to_string_lossy().into_owned();
+
+    let result = match db::libraries::select_library_by_name(conn, user_id, name.clone()).await {
+      Ok(Some(library)) if library.path == path => Ok(0),
+      Ok(Some(library)) => db::libraries::update_library_path(conn, library.id, path).await,
+      Ok(None) => db::libraries::insert_library(conn, NewLibrary::new(user_id, name, path)).await,
+      Err(err) => Err(err),
+    };
+
+    if let Err(err) = result {
+      error!("Library {:?} of user {} couldn't be saved: {}", configured.path, configured.user, err);
+    }
+  }
+}
@@ -0,0 +1,123 @@
+//! Language and date format preferences of users.
+//!
+//! The server doesn't translate anything itself; clients use the preferences of the user, which are kept
+//! with other user settings. The locale of a new user is taken from the `Accept-Language` header of the signup request.
+
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket_okapi::{gen::OpenApiGenerator, request::{OpenApiFromRequest, RequestHeaderInput}};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Maximum length of a locale, as stored in the database.
+const LOCALE_MAX_LENGTH: usize = 35;
+
+/// Order of the day, month and year in dates.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DateFormat {
+  /// 2022-07-18
+  Iso,
+  /// 18.07.2022
+  DayMonthYear,
+  /// 07/18/2022
+  MonthDayYear,
+}
+
+impl DateFormat {
+  /// Returns the name used in the database.
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      DateFormat::Iso => "iso",
+      DateFormat::DayMonthYear => "day_month_year",
+      DateFormat::MonthDayYear => "month_day_year",
+    }
+  }
+}
+
+impl FromStr for DateFormat {
+  type Err = ();
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    [DateFormat::Iso, DateFormat::DayMonthYear, DateFormat::MonthDayYear].iter()
+      .find(|format| format.as_str() == s)
+      .copied()
+      .ok_or(())
+  }
+}
+
+/// Normalizes a BCP 47 language tag (e.g. `cs-cz` to `cs-CZ`); returns `None` when it isn't one.\
+/// Only the shape of the tag is checked, not whether the language exists.
+pub fn normalize_locale(locale: &str) -> Option<String> {
+  let locale = locale.trim().replace('_', "-");
+  if locale.is_empty() || locale.len() > LOCALE_MAX_LENGTH { return None }
+
+  let mut subtags = locale.split('-');
+
+  let language = subtags.next()?;
+  if !(2..=3).contains(&language.len()) || !language.chars().all(|c| c.is_ascii_alphabetic()) { return None }
+
+  let mut normalized = language.to_ascii_lowercase();
+  for subtag in subtags {
+    if !(1..=8).contains(&subtag.len()) || !subtag.chars().all(|c| c.is_ascii_alphanumeric()) { return None }
+
+    normalized.push('-');
+    // regions are uppercase, other subtags (scripts, variants) are kept as they are
+    if subtag.len() == 2 && subtag.chars().all(|c| c.is_ascii_alphabetic()) {
+      normalized.push_str(&subtag.to_ascii_uppercase());
+    } else {
+      normalized.push_str(subtag);
+    }
+  }
+
+  Some(normalized)
+}
+
+/// Locale preferred by the client according to its `Accept-Language` header; `None` when it doesn't have one.
+#[derive(Debug, Clone)]
+pub struct AcceptLanguage(pub Option<String>);
+
+impl AcceptLanguage {
+  /// Returns the valid language with the highest quality; the first one wins ties.
+  fn parse(header: &str) -> Option<String> {
+    let mut preferred: Option<(f32, String)> = None;
+
+    for language in header.split(',') {
+      let mut parts = language.split(';');
+      let tag = parts.next().unwrap_or_default().trim();
+
+      let quality = parts
+        .find_map(|parameter| parameter.trim().strip_prefix("q="))
+        .map_or(Some(1.0), |quality| quality.trim().parse::<f32>().ok())
+        .unwrap_or(0.0);
+
+      if tag == "*" || quality <= 0.0 { continue }
+
+      let locale = match normalize_locale(tag) {
+        Some(locale) => locale,
+        None => continue,
+      };
+
+      if preferred.as_ref().map_or(true, |(best, _)| quality > *best) {
+        preferred = Some((quality, locale));
+      }
+    }
+
+    preferred.map(|(_, locale)| locale)
+  }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AcceptLanguage {
+  type Error = ();
+
+  async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+    Outcome::Success(AcceptLanguage(request.headers().get_one("Accept-Language").and_then(AcceptLanguage::parse)))
+  }
+}
+
+impl<'a> OpenApiFromRequest<'a> for AcceptLanguage {
+  fn from_request_input(_gen: &mut OpenApiGenerator, _name: String, _required: bool) -> rocket_okapi::Result<RequestHeaderInput> {
+    Ok(RequestHeaderInput::None)
+  }
+}
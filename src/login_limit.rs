@@ -0,0 +1,239 @@
+//! Limits of failed logins against guessing passwords.
+//!
+//! Failed logins are counted per client address and per account (the submitted username or email,
+//! so accounts which don't exist are limited the same way and can't be told apart by the response).
+//! Once a counter reaches its limit, logins are rejected with 429 and the `Retry-After` header
+//! until `lockout_minutes` pass since the last failure; a successful login clears the counter of the account.\
+//! Wrong passwords of share links are counted per client address (together with logins) and per share link;
+//! share link counters aren't cleared by visitors who know the password, otherwise they would never lock.\
+//! Counters are kept in memory, so they're cleared by a restart, and there are at most `MAX_COUNTERS` of them,
+//! so failed logins into many made-up accounts can't exhaust the memory.
+
+use crate::config::LoginLimitPolicy;
+use okapi::openapi3::Responses;
+use rocket::http::{Header, Status};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use rocket_okapi::{gen::OpenApiGenerator, response::OpenApiResponderInner, util::ensure_status_code_exists};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::iter;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// What the failed logins are counted for.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum LoginKey {
+  Address(IpAddr),
+  /// Normalized username or email.
+  Account(String),
+  /// UUID of a share link.
+  ShareLink(String),
+}
+
+impl fmt::Display for LoginKey {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      LoginKey::Address(address) => write!(f, "from address {}", address),
+      LoginKey::Account(account) => write!(f, "into account {}", account),
+      LoginKey::ShareLink(share_link_uuid) => write!(f, "into share link {}", share_link_uuid),
+    }
+  }
+}
+
+fn keys(address: Option<IpAddr>, key: LoginKey) -> impl Iterator<Item = LoginKey> {
+  address.map(LoginKey::Address).into_iter().chain(iter::once(key))
+}
+
+/// Maximum number of counters; counters which aren't locked are dropped first when it's reached,
+/// then the ones which expire soonest.
+const MAX_COUNTERS: usize = 100_000;
+
+#[derive(Debug)]
+struct Failures {
+  count: u32,
+  expires: Instant,
+}
+
+/// Failed login statistics since the server started.
+#[derive(Serialize, JsonSchema, Debug, Clone, Copy)]
+pub struct LoginStats {
+  /// Logins with wrong credentials, including wrong passwords of share links.
+  pub failed_logins: u64,
+  /// Logins rejected without checking the credentials, because the address or the account was locked out.
+  pub rejected_logins: u64,
+  /// Addresses locked out now.
+  pub locked_addresses: usize,
+  /// Accounts locked out now.
+  pub locked_accounts: usize,
+  /// Share links locked out now.
+  pub locked_share_links: usize,
+}
+
+/// Counters of failed logins; managed by Rocket.\
+/// Clones share the counters, e.g. with the management listener (see `management`).
+#[derive(Debug, Clone)]
+pub struct LoginLimiter {
+  policy: LoginLimitPolicy,
+  failures: Arc<Mutex<HashMap<LoginKey, Failures>>>,
+  failed_logins: Arc<AtomicU64>,
+  rejected_logins: Arc<AtomicU64>,
+}
+
+impl LoginLimiter {
+  pub fn new(policy: LoginLimitPolicy) -> Self {
+    Self {
+      policy,
+      failures: Arc::new(Mutex::new(HashMap::new())),
+      failed_logins: Arc::new(AtomicU64::new(0)),
+      rejected_logins: Arc::new(AtomicU64::new(0)),
+    }
+  }
+
+  /// Returns the limit of the counter; zero means no limit.
+  fn limit(&self, key: &LoginKey) -> u32 {
+    match key {
+      LoginKey::Address(_) => self.policy.max_failures_per_address,
+      LoginKey::Account(_) => self.policy.max_failures_per_account,
+      LoginKey::ShareLink(_) => self.policy.max_failures_per_share_link,
+    }
+  }
+
+  fn is_locked(&self, key: &LoginKey, failures: &Failures, now: Instant) -> bool {
+    let limit = self.limit(key);
+
+    limit != 0 && failures.count >= limit && failures.expires > now
+  }
+
+  /// Returns the number of seconds until logins from the address into the account are allowed again,
+  /// or `None` when they're allowed now.
+  pub fn locked(&self, address: Option<IpAddr>, account: &str) -> Option<u64> {
+    self.locked_keys(keys(address, LoginKey::Account(account.to_owned())))
+  }
+
+  /// Returns the number of seconds until passwords of the share link from the address are checked again,
+  /// or `None` when they're checked now.
+  pub fn share_link_locked(&self, address: Option<IpAddr>, share_link_uuid: &str) -> Option<u64> {
+    self.locked_keys(keys(address, LoginKey::ShareLink(share_link_uuid.to_owned())))
+  }
+
+  fn locked_keys(&self, keys: impl Iterator<Item = LoginKey>) -> Option<u64> {
+    let now = Instant::now();
+    let failures = self.failures.lock().unwrap();
+
+    let retry_after = keys
+      .filter_map(|key| {
+        let entry = failures.get(&key)?;
+
+        self.is_locked(&key, entry, now).then(|| entry.expires.duration_since(now).as_secs() + 1)
+      })
+      .max();
+
+    if retry_after.is_some() { self.rejected_logins.fetch_add(1, Ordering::Relaxed); }
+
+    retry_after
+  }
+
+  /// Counts a login with wrong credentials.
+  pub fn failed(&self, address: Option<IpAddr>, account: &str) {
+    self.failed_keys(keys(address, LoginKey::Account(account.to_owned())));
+  }
+
+  /// Counts a wrong password of the share link.
+  pub fn share_link_failed(&self, address: Option<IpAddr>, share_link_uuid: &str) {
+    self.failed_keys(keys(address, LoginKey::ShareLink(share_link_uuid.to_owned())));
+  }
+
+  fn failed_keys(&self, keys: impl Iterator<Item = LoginKey>) {
+    self.failed_logins.fetch_add(1, Ordering::Relaxed);
+
+    let now = Instant::now();
+    let expires = now + Duration::from_secs(self.policy.lockout_minutes * 60);
+
+    let mut failures = self.failures.lock().unwrap();
+    failures.retain(|_, entry| entry.expires > now);
+
+    for key in keys {
+      if failures.len() >= MAX_COUNTERS && !failures.contains_key(&key) { self.evict(&mut failures, now); }
+
+      let limit = self.limit(&key);
+      let entry = failures.entry(key.clone()).or_insert(Failures { count: 0, expires });
+      entry.count += 1;
+      entry.expires = expires;
+
+      if entry.count == limit {
+        warn!("Logins {} are locked out for {} minutes after {} failed attempts.", key, self.policy.lockout_minutes, limit);
+      }
+    }
+  }
+
+  /// Makes room for a new counter, see `MAX_COUNTERS`.
+  fn evict(&self, failures: &mut HashMap<LoginKey, Failures>, now: Instant) {
+    failures.retain(|key, entry| self.is_locked(key, entry, now));
+
+    while failures.len() >= MAX_COUNTERS {
+      let soonest = failures.iter().min_by_key(|(_, entry)| entry.expires).map(|(key, _)| key.clone());
+
+      match soonest {
+        Some(key) => failures.remove(&key),
+        None => break,
+      };
+    }
+
+    warn!("Failed logins reached {} counters; {} locked ones were kept.", MAX_COUNTERS, failures.len());
+  }
+
+  /// Clears the failed logins into the account.
+  pub fn succeeded(&self, account: &str) {
+    self.failures.lock().unwrap().remove(&LoginKey::Account(account.to_owned()));
+  }
+
+  pub fn stats(&self) -> LoginStats {
+    let now = Instant::now();
+    let failures = self.failures.lock().unwrap();
+
+    let mut stats = LoginStats {
+      failed_logins: self.failed_logins.load(Ordering::Relaxed),
+      rejected_logins: self.rejected_logins.load(Ordering::Relaxed),
+      locked_addresses: 0,
+      locked_accounts: 0,
+      locked_share_links: 0,
+    };
+
+    for (key, _) in failures.iter().filter(|(key, entry)| self.is_locked(key, entry, now)) {
+      match key {
+        LoginKey::Address(_) => stats.locked_addresses += 1,
+        LoginKey::Account(_) => stats.locked_accounts += 1,
+        LoginKey::ShareLink(_) => stats.locked_share_links += 1,
+      }
+    }
+
+    stats
+  }
+}
+
+/// Responds with 429 and the `Retry-After` header (in seconds) when logins are locked out.
+#[derive(Debug)]
+pub struct TooManyLogins(pub u64);
+
+impl<'r> Responder<'r, 'static> for TooManyLogins {
+  fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+    Response::build()
+      .status(Status::TooManyRequests)
+      .header(Header::new("Retry-After", self.0.to_string()))
+      .ok()
+  }
+}
+
+impl OpenApiResponderInner for TooManyLogins {
+  fn responses(_: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+    let mut responses = Responses::default();
+    ensure_status_code_exists(&mut responses, 429);
+
+    Ok(responses)
+  }
+}
@@ -0,0 +1,187 @@
+//! Database maintenance commands, so operators can manage the database without installing `diesel_cli`:
+//!
+//! - `galera db migrate` applies pending migrations; destructive ones only with `allow_destructive_migrations` enabled,
+//! - `galera db rollback [--allow-destructive]` reverts the last applied migration,
+//! - `galera db status` lists applied and pending migrations,
+//! - `galera db vacuum-tokens` deletes expired refresh and access tokens,
+//! - `galera db reindex-check` reports indexes created by the migrations which are missing in the database.
+//!
+//! Migrations are the ones embedded in the binary, the same as the server applies (see `src/migrations.rs`).
+
+use crate::config;
+use crate::db::tokens::delete_expired_tokens;
+use crate::migrations::{self, MigrationReport, MIGRATIONS};
+use diesel::sql_types::Text;
+use diesel::{MysqlConnection, RunQueryDsl};
+use diesel_migrations::MigrationConnection;
+use std::collections::HashSet;
+use std::io;
+
+const USAGE: &str = "Usage: galera db <migrate|rollback [--allow-destructive]|status|vacuum-tokens|reindex-check>";
+
+/// Index of a table in the database.
+#[derive(QueryableByName, Debug)]
+struct TableIndex {
+  #[sql_type = "Text"]
+  table_name: String,
+  #[sql_type = "Text"]
+  index_name: String,
+}
+
+/// Runs the `galera db` command with the given arguments (following `db`).\
+/// Returns the exit code: 0 on success, 2 when the database needs attention (destructive migrations, missing indexes), 1 on errors.
+pub fn db_cli(args: &[String]) -> i32 {
+  let command = match args.first() {
+    Some(command) => command.as_str(),
+    None => {
+      eprintln!("{}", USAGE);
+      return 1;
+    },
+  };
+
+  if !["migrate", "rollback", "status", "vacuum-tokens", "reindex-check"].contains(&command) {
+    eprintln!("Unknown command {}.\n{}", command, USAGE);
+    return 1;
+  }
+
+  let c = match migrations::connect() {
+    Some(c) => c,
+    None => return 1,
+  };
+
+  match command {
+    "migrate" => migrate(&c),
+    "rollback" => rollback(&c, args.iter().any(|arg| arg == "--allow-destructive")),
+    "status" => status(&c),
+    "vacuum-tokens" => vacuum_tokens(&c),
+    _ => reindex_check(&c),
+  }
+}
+
+fn migrate(c: &MysqlConnection) -> i32 {
+  let report = match MigrationReport::new(c) {
+    Ok(report) => report,
+    Err(err) => {
+      eprintln!("Migrations couldn't be checked: {}", err);
+      return 1;
+    },
+  };
+
+  let allow_destructive = config::figment().extract_inner("allow_destructive_migrations").unwrap_or(false);
+
+  if report.is_destructive() && !allow_destructive {
+    for migration in report.pending.iter().filter(|migration| migration.is_destructive()) {
+      eprintln!("Migration {} can lose data: {}", migration.version, migration.destructive_statements.join("; "));
+    }
+
+    eprintln!("Back up the database and enable allow_destructive_migrations (ROCKET_ALLOW_DESTRUCTIVE_MIGRATIONS=true) to apply the migrations.");
+    return 2;
+  }
+
+  if let Err(err) = migrations::run_pending(c, &mut io::stdout()) {
+    eprintln!("Migrations couldn't be applied: {}", err);
+    return 1;
+  }
+
+  println!("Applied {} migrations.", report.pending.len());
+  0
+}
+
+fn rollback(c: &MysqlConnection, allow_destructive: bool) -> i32 {
+  match migrations::revert_latest(c, allow_destructive) {
+    Ok(Some(version)) => {
+      println!("Reverted migration {}.", version);
+      0
+    },
+    Ok(None) => {
+      println!("No migration is applied.");
+      0
+    },
+    Err(err) => {
+      eprintln!("Migration couldn't be reverted: {}", err);
+      eprintln!("Back up the database and pass --allow-destructive to revert migrations which can lose data.");
+      1
+    },
+  }
+}
+
+fn status(c: &MysqlConnection) -> i32 {
+  let report = MigrationReport::new(c).map_err(|err| err.to_string());
+  let applied = c.previously_run_migration_versions().map_err(|err| err.to_string());
+
+  let (report, applied): (MigrationReport, HashSet<String>) = match report.and_then(|report| applied.map(|applied| (report, applied))) {
+    Ok(status) => status,
+    Err(err) => {
+      eprintln!("Migrations couldn't be checked: {}", err);
+      return 1;
+    },
+  };
+
+  for (version, _, _) in MIGRATIONS {
+    if applied.contains(*version) {
+      println!("applied  {}", version);
+    } else if report.pending.iter().any(|migration| migration.version == *version && migration.is_destructive()) {
+      println!("pending  {} (destructive)", version);
+    } else {
+      println!("pending  {}", version);
+    }
+  }
+
+  // e.g. the database was migrated by a newer version of galera
+  let mut unknown: Vec<&String> = applied.iter().filter(|version| !MIGRATIONS.iter().any(|(v, _, _)| v == version)).collect();
+  unknown.sort();
+
+  for version in unknown {
+    println!("unknown  {}", version);
+  }
+
+  println!("{} migrations are applied, {} are pending.", report.applied, report.pending.len());
+
+  if report.is_destructive() {
+    return 2;
+  }
+
+  0
+}
+
+fn vacuum_tokens(c: &MysqlConnection) -> i32 {
+  match delete_expired_tokens(c) {
+    Ok((refresh_tokens, access_tokens)) => {
+      println!("Deleted {} expired refresh tokens and {} access tokens.", refresh_tokens, access_tokens);
+      0
+    },
+    Err(err) => {
+      eprintln!("Expired tokens couldn't be deleted: {}", err);
+      1
+    },
+  }
+}
+
+fn reindex_check(c: &MysqlConnection) -> i32 {
+  let existing = diesel::sql_query("SELECT `TABLE_NAME` AS `table_name`, `INDEX_NAME` AS `index_name` FROM `information_schema`.`STATISTICS` WHERE `TABLE_SCHEMA` = DATABASE()")
+    .load::<TableIndex>(c);
+
+  let existing: HashSet<(String, String)> = match existing {
+    Ok(existing) => existing.into_iter().map(|index| (index.table_name, index.index_name)).collect(),
+    Err(err) => {
+      eprintln!("Indexes couldn't be listed: {}", err);
+      return 1;
+    },
+  };
+
+  let expected = migrations::expected_indexes();
+  let missing: Vec<&(String, String)> = expected.iter().filter(|index| !existing.contains(index)).collect();
+
+  for (table, index) in &missing {
+    println!("{}.{} is missing", table, index);
+  }
+
+  println!("{} of {} indexes are missing.", missing.len(), expected.len());
+
+  if !missing.is_empty() {
+    println!("Missing indexes slow down queries; apply pending migrations or recreate the indexes from the migrations.");
+    return 2;
+  }
+
+  0
+}
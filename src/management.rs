@@ -0,0 +1,137 @@
+//! Listener serving management routes separately from user traffic.
+//!
+//! Management routes are the administration routes (`/admin/...` and `/system/...` allowed only to administrators),
+//! `/health` and `/metrics`. When `management_address` is configured (e.g. `ROCKET_MANAGEMENT_ADDRESS=127.0.0.1:9000`),
+//! a second Rocket instance serves them on that address and the public listener doesn't serve them at all,
+//! so they can be firewalled off on instances exposed to the internet.\
+//! Administration routes require the token of an administrator on both listeners; `/metrics` is open
+//! on the management listener, so it can be scraped, and allowed only to administrators on the public one.
+//!
+//! The management instance has its own pool of database connections. Counters of share links and logins are shared
+//! with the public listener, so their statistics are complete; background tasks run only in the public instance.
+
+use crate::api_version::{self, with_server_prefix, LegacyRoutes};
+use crate::auth::secret::Secret;
+use crate::auth::token::Admin;
+use crate::bandwidth::BandwidthLimiter;
+use crate::catchers;
+use crate::config::{self, Config};
+use crate::login_limit::LoginLimiter;
+use crate::DbConn;
+use okapi::openapi3::OpenApi;
+use rocket::fairing::AdHoc;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::{Orbit, Rocket, Route};
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::get_openapi_route;
+use rocket_okapi::request::{OpenApiFromRequest, RequestHeaderInput};
+use rocket_okapi::settings::OpenApiSettings;
+use std::fmt::{Display, Write};
+use std::net::SocketAddr;
+
+/// Marks the management instance; managed only by it.
+pub struct ManagementListener;
+
+/// Returns the configured address of the management listener.\
+/// Invalid addresses are reported when the configuration is extracted, so they are ignored here.
+pub fn address() -> Option<SocketAddr> {
+  config::figment().extract_inner("management_address").ok()
+}
+
+/// Launches the management instance serving the routes on the address next to the public instance.\
+/// The public instance keeps running when the address can't be bound; the management routes are unavailable then.
+pub async fn launch(public: &Rocket<Orbit>, address: SocketAddr, routes: Vec<Route>, spec: OpenApi) {
+  let secret = public.state::<Secret>().cloned();
+  let bandwidth_limiter = public.state::<BandwidthLimiter>().cloned();
+  let login_limiter = public.state::<LoginLimiter>().cloned();
+
+  let (secret, bandwidth_limiter, login_limiter) = match (secret, bandwidth_limiter, login_limiter) {
+    (Some(secret), Some(bandwidth_limiter), Some(login_limiter)) => (secret, bandwidth_limiter, login_limiter),
+    _ => {
+      error!("Management listener couldn't be started, the public instance isn't set up.");
+      return;
+    },
+  };
+
+  let figment = config::figment()
+    .merge(("address", address.ip()))
+    .merge(("port", address.port()));
+
+  let openapi_settings = OpenApiSettings::default();
+  let api_prefix = api_version::prefix();
+
+  let rocket = rocket::custom(figment)
+    .attach(DbConn::fairing())
+    .attach(AdHoc::config::<Config>())
+    .manage(ManagementListener)
+    .manage(secret)
+    .manage(bandwidth_limiter)
+    .manage(login_limiter)
+    .attach(LegacyRoutes)
+    .register("/", catchers::all())
+    .mount(&api_prefix, routes.clone())
+    .mount(&api_prefix, vec![get_openapi_route(with_server_prefix(spec.clone(), &api_prefix), &openapi_settings)])
+    .mount("/", routes)
+    .mount("/", vec![get_openapi_route(spec, &openapi_settings)]);
+
+  rocket::tokio::spawn(async move {
+    if let Err(err) = rocket.launch().await {
+      error!("Management listener on {} failed: {}", address, err);
+    }
+  });
+}
+
+/// Request guard of `/metrics`: anyone on the management listener, only administrators on the public one.
+pub struct MetricsAccess;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for MetricsAccess {
+  type Error = ();
+
+  async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+    if request.rocket().state::<ManagementListener>().is_some() {
+      return Outcome::Success(MetricsAccess);
+    }
+
+    request.guard::<Admin>().await.map(|_| MetricsAccess)
+  }
+}
+
+impl<'a> OpenApiFromRequest<'a> for MetricsAccess {
+  fn from_request_input(
+    gen: &mut OpenApiGenerator,
+    name: String,
+    required: bool,
+  ) -> rocket_okapi::Result<RequestHeaderInput> {
+    Admin::from_request_input(gen, name, required)
+  }
+}
+
+/// Metrics in the Prometheus text format.
+#[derive(Debug, Default)]
+pub struct Metrics {
+  text: String,
+}
+
+impl Metrics {
+  /// Adds a value which can go up and down.
+  pub fn gauge(&mut self, name: &str, help: &str, value: impl Display) {
+    self.add(name, help, "gauge", value);
+  }
+
+  /// Adds a value which only goes up, since the server started.
+  pub fn counter(&mut self, name: &str, help: &str, value: impl Display) {
+    self.add(name, help, "counter", value);
+  }
+
+  fn add(&mut self, name: &str, help: &str, kind: &str, value: impl Display) {
+    // writing to a string can't fail
+    let _ = writeln!(self.text, "# HELP galera_{} {}", name, help);
+    let _ = writeln!(self.text, "# TYPE galera_{} {}", name, kind);
+    let _ = writeln!(self.text, "galera_{} {}", name, value);
+  }
+
+  pub fn into_text(self) -> String {
+    self.text
+  }
+}
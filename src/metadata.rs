@@ -0,0 +1,351 @@
+//! Metadata of media read from EXIF.
+//!
+//! EXIF is read from JPEGs (the APP1 segment) and from TIFF based formats (TIFF, CR2),
+//! other formats have no metadata.
+//!
+//! # Date taken
+//!
+//! The date when the media was taken is the first one known of:
+//! 1. `DateTimeOriginal` of EXIF, with the UTC offset from `OffsetTimeOriginal` when it's present,
+//! 2. `DateTime` of EXIF,
+//! 3. a date in the filename matching one of `filename_date_patterns` (e.g. `IMG-20240131-WA0001.jpg`),
+//!    tried in their order; the time is midnight when the pattern has none,
+//! 4. the modification time of the file (UTC).
+//!
+//! Dates from EXIF and filenames have no UTC offset unless EXIF has one, so they're shown in the owner's time zone.\
+//! Media dated by the modification time before the patterns were configured are dated by their filenames at startup.
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, Utc};
+use lazy_regex::{Lazy, Regex};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::RwLock;
+
+/// Metadata must be within the beginning of the file.
+const HEADER_SIZE: u64 = 128 * 1024;
+
+/// Maximum length of text values, they are stored as `VARCHAR(255)`.
+const MAX_TEXT_LENGTH: usize = 255;
+
+const TAG_MAKE: u16 = 0x010F;
+const TAG_MODEL: u16 = 0x0110;
+const TAG_ORIENTATION: u16 = 0x0112;
+const TAG_DATE_TIME: u16 = 0x0132;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_GPS_IFD: u16 = 0x8825;
+const TAG_ISO: u16 = 0x8827;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TAG_OFFSET_TIME_ORIGINAL: u16 = 0x9011;
+const TAG_FOCAL_LENGTH: u16 = 0x920A;
+const TAG_GPS_LATITUDE_REF: u16 = 0x0001;
+const TAG_GPS_LATITUDE: u16 = 0x0002;
+const TAG_GPS_LONGITUDE_REF: u16 = 0x0003;
+const TAG_GPS_LONGITUDE: u16 = 0x0004;
+
+/// Patterns of dates in filenames used by default; named groups `year`, `month` and `day` are required,
+/// `hour`, `minute` and `second` are optional.
+pub const DEFAULT_FILENAME_DATE_PATTERNS: [&str; 3] = [
+  // IMG_20240131_153012.jpg, PXL_20240131_153012345.jpg, Screenshot_20240131-153012.png
+  r"(?P<year>(?:19|20)\d{2})(?P<month>\d{2})(?P<day>\d{2})[_-](?P<hour>\d{2})(?P<minute>\d{2})(?P<second>\d{2})",
+  // IMG-20240131-WA0001.jpg (WhatsApp)
+  r"-(?P<year>(?:19|20)\d{2})(?P<month>\d{2})(?P<day>\d{2})-WA\d+",
+  // Screenshot 2024-01-31 at 15.30.12.png, Screenshot_2024-01-31-15-30-12.png, 2024-01-31.jpg
+  r"(?P<year>(?:19|20)\d{2})-(?P<month>\d{2})-(?P<day>\d{2})(?:[ _-](?:at )?(?P<hour>\d{2})[.:-](?P<minute>\d{2})[.:-](?P<second>\d{2}))?",
+];
+
+/// Patterns of dates in filenames, set from the configuration at startup.
+static FILENAME_DATE_PATTERNS: Lazy<RwLock<Vec<Regex>>> = Lazy::new(|| {
+  RwLock::new(DEFAULT_FILENAME_DATE_PATTERNS.iter().map(|pattern| Regex::new(pattern).unwrap()).collect())
+});
+
+/// Sets the patterns of dates in filenames; an empty list disables dating media by their filenames.
+/// # Errors
+/// Returns the first pattern which isn't a valid regular expression or lacks the `year`, `month` or `day` group.
+pub fn set_filename_date_patterns(patterns: &[String]) -> Result<(), String> {
+  let mut compiled = vec![];
+  for pattern in patterns {
+    let regex = Regex::new(pattern).map_err(|_| pattern.clone())?;
+
+    let names: Vec<&str> = regex.capture_names().flatten().collect();
+    if !["year", "month", "day"].iter().all(|name| names.contains(name)) { return Err(pattern.clone()) }
+
+    compiled.push(regex);
+  }
+
+  *FILENAME_DATE_PATTERNS.write().unwrap() = compiled;
+
+  Ok(())
+}
+
+/// Returns the date in the filename matching the first possible pattern, see the module documentation.
+pub fn filename_date(filename: &str) -> Option<NaiveDateTime> {
+  let patterns = FILENAME_DATE_PATTERNS.read().unwrap();
+
+  patterns.iter().find_map(|pattern| {
+    let captures = pattern.captures(filename)?;
+    let number = |name: &str| captures.name(name).and_then(|value| value.as_str().parse::<u32>().ok());
+
+    let date = NaiveDate::from_ymd_opt(number("year")? as i32, number("month")?, number("day")?)?;
+
+    date.and_hms_opt(number("hour").unwrap_or(0), number("minute").unwrap_or(0), number("second").unwrap_or(0))
+  })
+}
+
+/// Metadata of a media; all values are optional as cameras write only some of them.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MediaMetadata {
+  /// Local time when the media was taken.
+  pub date_taken: Option<NaiveDateTime>,
+  /// UTC offset of `date_taken` in seconds.
+  pub date_taken_offset: Option<i32>,
+  pub camera_make: Option<String>,
+  pub camera_model: Option<String>,
+  pub latitude: Option<f64>,
+  pub longitude: Option<f64>,
+  pub iso: Option<u32>,
+  /// Focal length in millimeters.
+  pub focal_length: Option<f64>,
+  /// EXIF orientation (1-8).
+  pub orientation: Option<u16>,
+}
+
+impl MediaMetadata {
+  /// Reads metadata of the media.\
+  /// Media without EXIF have empty metadata.
+  /// # Example
+  /// ```
+  /// let metadata = MediaMetadata::read(Path::new("IMG_0001.jpg"))?;
+  /// ```
+  pub fn read(path: &Path) -> io::Result<Self> {
+    let mut header = vec![];
+    File::open(path)?.take(HEADER_SIZE).read_to_end(&mut header)?;
+
+    let tiff = match jpeg_exif(&header).or_else(|| Some(&header[..])).and_then(Tiff::new) {
+      Some(tiff) => tiff,
+      None => return Ok(Self::default()),
+    };
+
+    Ok(Self::from_tiff(&tiff))
+  }
+
+  /// Reads metadata of the media, errors result in empty metadata.\
+  /// When the date taken is unknown, the date in the filename or the modification time of the file is used instead.
+  pub fn read_or_modified(path: &Path) -> Self {
+    let mut metadata = Self::read(path).unwrap_or_default();
+
+    if metadata.date_taken.is_none() {
+      metadata.date_taken = path.file_name().and_then(|filename| filename_date(&filename.to_string_lossy()));
+    }
+
+    if metadata.date_taken.is_none() {
+      metadata.date_taken = std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .map(|modified| DateTime::<Utc>::from(modified).naive_utc());
+      // modification times are in UTC
+      metadata.date_taken_offset = metadata.date_taken.map(|_| 0);
+    }
+
+    metadata
+  }
+
+  fn from_tiff(tiff: &Tiff) -> Self {
+    let mut metadata = Self::default();
+
+    let ifd0 = match tiff.first_ifd() {
+      Some(ifd0) => ifd0,
+      None => return metadata,
+    };
+
+    metadata.camera_make = tiff.ascii(&ifd0, TAG_MAKE);
+    metadata.camera_model = tiff.ascii(&ifd0, TAG_MODEL);
+    metadata.orientation = tiff.short(&ifd0, TAG_ORIENTATION).filter(|orientation| (1..=8).contains(orientation));
+    metadata.date_taken = tiff.ascii(&ifd0, TAG_DATE_TIME).and_then(|date| parse_date(&date));
+
+    if let Some(exif) = tiff.sub_ifd(&ifd0, TAG_EXIF_IFD) {
+      if let Some(date_taken) = tiff.ascii(&exif, TAG_DATE_TIME_ORIGINAL).and_then(|date| parse_date(&date)) {
+        metadata.date_taken = Some(date_taken);
+        metadata.date_taken_offset = tiff.ascii(&exif, TAG_OFFSET_TIME_ORIGINAL).and_then(|offset| parse_offset(&offset));
+      }
+
+      metadata.iso = tiff.short(&exif, TAG_ISO).map(u32::from);
+      metadata.focal_length = tiff.rationals(&exif, TAG_FOCAL_LENGTH).and_then(|values| values.first().copied());
+    }
+
+    if let Some(gps) = tiff.sub_ifd(&ifd0, TAG_GPS_IFD) {
+      metadata.latitude = gps_coordinate(tiff, &gps, TAG_GPS_LATITUDE, TAG_GPS_LATITUDE_REF, 'S', 90.0);
+      metadata.longitude = gps_coordinate(tiff, &gps, TAG_GPS_LONGITUDE, TAG_GPS_LONGITUDE_REF, 'W', 180.0);
+
+      // a single coordinate is useless
+      if metadata.latitude.is_none() || metadata.longitude.is_none() {
+        metadata.latitude = None;
+        metadata.longitude = None;
+      }
+    }
+
+    metadata
+  }
+}
+
+/// Returns the TIFF structure of the JPEG's EXIF segment.
+fn jpeg_exif(data: &[u8]) -> Option<&[u8]> {
+  if !data.starts_with(&[0xFF, 0xD8]) { return None }
+
+  let mut position = 2;
+  while position + 4 <= data.len() {
+    if data[position] != 0xFF { return None }
+
+    let marker = data[position + 1];
+    // start of scan, no more metadata after it
+    if marker == 0xDA { return None }
+
+    let length = u16::from_be_bytes([data[position + 2], data[position + 3]]) as usize;
+    let segment = data.get(position + 4..position + 2 + length)?;
+
+    if marker == 0xE1 && segment.starts_with(b"Exif\0\0") {
+      return Some(&segment[6..]);
+    }
+
+    position += 2 + length;
+  }
+
+  None
+}
+
+/// Parses EXIF dates like `2022:06:28 14:05:00`.
+fn parse_date(date: &str) -> Option<NaiveDateTime> {
+  NaiveDateTime::parse_from_str(date, "%Y:%m:%d %H:%M:%S").ok()
+}
+
+/// Parses EXIF UTC offsets like `+02:00` into seconds.
+fn parse_offset(offset: &str) -> Option<i32> {
+  let sign = match offset.get(0..1)? {
+    "+" => 1,
+    "-" => -1,
+    _ => return None,
+  };
+
+  let hours: i32 = offset.get(1..3)?.parse().ok()?;
+  let minutes: i32 = offset.get(4..6)?.parse().ok()?;
+  let seconds = sign * (hours * 3600 + minutes * 60);
+
+  FixedOffset::east_opt(seconds).map(|_| seconds)
+}
+
+/// Converts degrees, minutes and seconds into signed decimal degrees.
+fn gps_coordinate(tiff: &Tiff, gps: &[Entry], tag: u16, reference_tag: u16, negative_reference: char, max: f64) -> Option<f64> {
+  let values = tiff.rationals(gps, tag)?;
+  if values.len() < 3 { return None }
+
+  let degrees = values[0] + values[1] / 60.0 + values[2] / 3600.0;
+  let degrees = match tiff.ascii(gps, reference_tag) {
+    Some(reference) if reference.starts_with(negative_reference) => -degrees,
+    _ => degrees,
+  };
+
+  (degrees.is_finite() && degrees.abs() <= max).then(|| degrees)
+}
+
+/// Entry of an image file directory.
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+  tag: u16,
+  kind: u16,
+  count: u32,
+  /// Position of the value (or of the offset of the value) in the TIFF structure.
+  position: usize,
+}
+
+/// TIFF structure containing EXIF.
+struct Tiff<'a> {
+  data: &'a [u8],
+  little_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+  fn new(data: &'a [u8]) -> Option<Self> {
+    let little_endian = match data.get(0..2)? {
+      b"II" => true,
+      b"MM" => false,
+      _ => return None,
+    };
+
+    let tiff = Self { data, little_endian };
+    if tiff.u16(2)? != 42 { return None }
+
+    Some(tiff)
+  }
+
+  fn u16(&self, offset: usize) -> Option<u16> {
+    let bytes = [*self.data.get(offset)?, *self.data.get(offset + 1)?];
+    Some(if self.little_endian { u16::from_le_bytes(bytes) } else { u16::from_be_bytes(bytes) })
+  }
+
+  fn u32(&self, offset: usize) -> Option<u32> {
+    let bytes = [*self.data.get(offset)?, *self.data.get(offset + 1)?, *self.data.get(offset + 2)?, *self.data.get(offset + 3)?];
+    Some(if self.little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) })
+  }
+
+  fn ifd(&self, offset: usize) -> Option<Vec<Entry>> {
+    let count = self.u16(offset)? as usize;
+
+    (0..count)
+      .map(|i| {
+        let position = offset + 2 + i * 12;
+        Some(Entry { tag: self.u16(position)?, kind: self.u16(position + 2)?, count: self.u32(position + 4)?, position: position + 8 })
+      })
+      .collect()
+  }
+
+  fn first_ifd(&self) -> Option<Vec<Entry>> {
+    self.ifd(self.u32(4)? as usize)
+  }
+
+  fn sub_ifd(&self, ifd: &[Entry], tag: u16) -> Option<Vec<Entry>> {
+    let entry = ifd.iter().find(|entry| entry.tag == tag)?;
+
+    self.ifd(self.u32(entry.position)? as usize)
+  }
+
+  /// Returns the position of the value; values longer than 4 bytes are stored elsewhere.
+  fn value_position(&self, entry: &Entry, size: usize) -> Option<usize> {
+    if size <= 4 { return Some(entry.position) }
+
+    let position = self.u32(entry.position)? as usize;
+    self.data.get(position..position.checked_add(size)?)?;
+
+    Some(position)
+  }
+
+  fn ascii(&self, ifd: &[Entry], tag: u16) -> Option<String> {
+    let entry = ifd.iter().find(|entry| entry.tag == tag && entry.kind == 2)?;
+    let size = entry.count as usize;
+    let position = self.value_position(entry, size)?;
+
+    let text = String::from_utf8_lossy(self.data.get(position..position + size)?);
+    let text = text.trim_end_matches('\0').trim();
+    if text.is_empty() { return None }
+
+    Some(text.chars().take(MAX_TEXT_LENGTH).collect())
+  }
+
+  fn short(&self, ifd: &[Entry], tag: u16) -> Option<u16> {
+    let entry = ifd.iter().find(|entry| entry.tag == tag && entry.kind == 3 && entry.count > 0)?;
+
+    self.u16(entry.position)
+  }
+
+  fn rationals(&self, ifd: &[Entry], tag: u16) -> Option<Vec<f64>> {
+    let entry = ifd.iter().find(|entry| entry.tag == tag && entry.kind == 5)?;
+    let position = self.value_position(entry, entry.count as usize * 8)?;
+
+    (0..entry.count as usize)
+      .map(|i| {
+        let numerator = self.u32(position + i * 8)?;
+        let denominator = self.u32(position + i * 8 + 4)?;
+        (denominator != 0).then(|| numerator as f64 / denominator as f64)
+      })
+      .collect()
+  }
+}
@@ -0,0 +1,255 @@
+//! Checks of pending database migrations.
+//!
+//! Migrations are applied automatically when the server starts. Migrations which can lose data
+//! (dropping tables or columns, rewriting rows, ...) are called destructive; they are applied only when
+//! `allow_destructive_migrations` is enabled (e.g. `ROCKET_ALLOW_DESTRUCTIVE_MIGRATIONS=true` in containers),
+//! so there's a chance to back up the database first. Fresh databases have no data to lose, so they are always migrated.
+//!
+//! `galera --migrate-check` reports pending migrations without applying them,
+//! the `galera db` commands manage them without `diesel_cli` (see `src/maintenance.rs`).
+
+use crate::config;
+use diesel::connection::SimpleConnection;
+use diesel::sql_types::Text;
+use diesel::{Connection, MysqlConnection, RunQueryDsl};
+use diesel_migrations::{MigrationConnection, RunMigrationsError};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::io::Write;
+
+include!(concat!(env!("OUT_DIR"), "/migrations.rs"));
+
+embed_migrations!();
+
+/// Keywords following `DROP` in `ALTER TABLE` which don't remove data.
+const SAFE_DROPS: [&str; 7] = ["FOREIGN", "INDEX", "KEY", "PRIMARY", "CONSTRAINT", "CHECK", "DEFAULT"];
+
+/// Migration which wasn't applied yet.
+#[derive(Serialize, JsonSchema, Debug, Clone)]
+pub struct PendingMigration {
+  pub version: String,
+  /// Statements which can lose data.
+  pub destructive_statements: Vec<String>,
+}
+
+impl PendingMigration {
+  pub fn is_destructive(&self) -> bool {
+    !self.destructive_statements.is_empty()
+  }
+}
+
+/// State of migrations of a database.
+#[derive(Serialize, JsonSchema, Debug, Clone)]
+pub struct MigrationReport {
+  /// The database has no applied migrations, so it has no data to lose.
+  pub fresh: bool,
+  pub applied: usize,
+  pub pending: Vec<PendingMigration>,
+}
+
+impl MigrationReport {
+  /// Creates the report of the database.\
+  /// The table of applied migrations is created when it's missing, otherwise nothing is changed.
+  pub fn new(c: &MysqlConnection) -> Result<Self, diesel::result::Error> {
+    diesel_migrations::setup_database(c)?;
+    let applied: HashSet<String> = c.previously_run_migration_versions()?;
+
+    let pending = MIGRATIONS.iter()
+      .filter(|(version, _, _)| !applied.contains(*version))
+      .map(|(version, up, _)| PendingMigration { version: version.to_string(), destructive_statements: destructive_statements(up) })
+      .collect();
+
+    Ok(Self { fresh: applied.is_empty(), applied: applied.len(), pending })
+  }
+
+  /// Checks whether applying the pending migrations can lose data.
+  pub fn is_destructive(&self) -> bool {
+    !self.fresh && self.pending.iter().any(PendingMigration::is_destructive)
+  }
+}
+
+/// Applies the pending migrations, the names of applied migrations are written to the output.
+pub fn run_pending(c: &MysqlConnection, out: &mut dyn Write) -> Result<(), RunMigrationsError> {
+  embedded_migrations::run_with_output(c, out)
+}
+
+/// Reverts the last applied migration and returns its version, or `None` when no migration is applied.\
+/// Destructive reverting SQL (which is usual, e.g. dropping created tables) is refused unless it's allowed.
+pub fn revert_latest(c: &MysqlConnection, allow_destructive: bool) -> Result<Option<String>, String> {
+  diesel_migrations::setup_database(c).map_err(|err| err.to_string())?;
+
+  let version = match c.latest_run_migration_version().map_err(|err| err.to_string())? {
+    Some(version) => version,
+    None => return Ok(None),
+  };
+
+  let down = match MIGRATIONS.iter().find(|(v, _, _)| *v == version) {
+    Some((_, _, down)) => down,
+    None => return Err(format!("migration {} isn't known to this version of galera", version)),
+  };
+
+  let destructive = destructive_statements(down);
+  if !destructive.is_empty() && !allow_destructive {
+    return Err(format!("reverting migration {} can lose data: {}", version, destructive.join("; ")));
+  }
+
+  // MySQL commits schema changes implicitly, so the transaction only keeps the version and plain statements together
+  c.transaction(|| {
+    c.batch_execute(down)?;
+
+    diesel::sql_query("DELETE FROM `__diesel_schema_migrations` WHERE `version` = ?")
+      .bind::<Text, _>(&version)
+      .execute(c)
+  }).map_err(|err: diesel::result::Error| err.to_string())?;
+
+  Ok(Some(version))
+}
+
+/// Returns named indexes created by the migrations as pairs of a table and an index name.\
+/// Indexes removed by later migrations are left out; unnamed indexes and indexes of foreign keys can't be checked.
+pub fn expected_indexes() -> Vec<(String, String)> {
+  let mut indexes: Vec<(String, String)> = Vec::new();
+
+  for (_, up, _) in MIGRATIONS {
+    for statement in statements(up) {
+      let raw: Vec<&str> = statement.split_whitespace().collect();
+      let names: Vec<&str> = raw.iter()
+        .map(|token| token.trim_matches(|c: char| c == '`' || c == ',' || c == '(' || c == ')'))
+        .collect();
+      let keywords: Vec<String> = names.iter().map(|name| name.to_uppercase()).collect();
+
+      let table = keywords.iter()
+        .position(|keyword| keyword == "TABLE")
+        .and_then(|i| (i + 1..names.len()).find(|&j| !["IF", "NOT", "EXISTS"].contains(&keywords[j].as_str())));
+
+      let table = match table {
+        Some(j) => names[j].to_string(),
+        None => continue,
+      };
+
+      if keywords[0] == "DROP" {
+        indexes.retain(|(t, _)| *t != table);
+        continue;
+      }
+
+      for i in 1..names.len() {
+        let name = match names.get(i + 1) {
+          // unnamed indexes are followed by their columns
+          Some(name) if !raw[i + 1].starts_with('(') => name.to_string(),
+          _ => continue,
+        };
+
+        let is_index = keywords[i] == "INDEX" || keywords[i] == "KEY";
+        let is_unique_constraint = keywords[i] == "CONSTRAINT" && keywords.get(i + 2).map(String::as_str) == Some("UNIQUE");
+
+        if is_index && keywords[i - 1] == "DROP" {
+          indexes.retain(|(t, index)| *t != table || *index != name);
+        } else if (is_index && keywords[i - 1] != "PRIMARY" && keywords[i - 1] != "FOREIGN") || is_unique_constraint {
+          indexes.push((table.clone(), name));
+        }
+      }
+    }
+  }
+
+  let mut unique = HashSet::new();
+  indexes.retain(|index| unique.insert(index.clone()));
+
+  indexes
+}
+
+/// Splits the SQL to statements without comments, each on one line.
+fn statements(sql: &str) -> Vec<String> {
+  let without_comments: String = sql.lines()
+    .filter(|line| !line.trim_start().starts_with("--"))
+    .collect::<Vec<_>>()
+    .join("\n");
+
+  without_comments.split(';')
+    .map(|statement| statement.split_whitespace().collect::<Vec<_>>().join(" "))
+    .filter(|statement| !statement.is_empty())
+    .collect()
+}
+
+/// Returns statements of the SQL which can lose data.
+fn destructive_statements(sql: &str) -> Vec<String> {
+  statements(sql).into_iter()
+    .filter(|statement| is_destructive(statement))
+    .collect()
+}
+
+fn is_destructive(statement: &str) -> bool {
+  let tokens: Vec<String> = statement.split_whitespace()
+    .map(|token| token.trim_matches(|c: char| c == '`' || c == ',' || c == '(').to_uppercase())
+    .collect();
+
+  match tokens.first().map(String::as_str) {
+    Some("DELETE" | "TRUNCATE" | "UPDATE") => true,
+    Some("DROP") => matches!(tokens.get(1).map(String::as_str), Some("TABLE" | "DATABASE" | "SCHEMA")),
+    // columns can be dropped without the COLUMN keyword, and changed columns can truncate values
+    Some("ALTER") => tokens.windows(2).any(|pair| {
+      (pair[0] == "DROP" && !SAFE_DROPS.contains(&pair[1].as_str())) || pair[0] == "MODIFY" || pair[0] == "CHANGE"
+    }),
+    _ => false,
+  }
+}
+
+/// Prints pending migrations of the configured database for `--migrate-check`.\
+/// Returns the exit code: 0 when the migrations can be applied safely, 2 when they are destructive, 1 on errors.
+pub fn migrate_check() -> i32 {
+  let c = match connect() {
+    Some(c) => c,
+    None => return 1,
+  };
+
+  let report = MigrationReport::new(&c).map_err(|err| err.to_string());
+
+  let report = match report {
+    Ok(report) => report,
+    Err(err) => {
+      eprintln!("Migrations couldn't be checked: {}", err);
+      return 1;
+    },
+  };
+
+  println!("{} migrations are applied, {} are pending.", report.applied, report.pending.len());
+
+  for migration in &report.pending {
+    if migration.is_destructive() {
+      println!("{} (destructive)", migration.version);
+      for statement in &migration.destructive_statements {
+        println!("  {}", statement);
+      }
+    } else {
+      println!("{}", migration.version);
+    }
+  }
+
+  if report.is_destructive() {
+    println!("Back up the database before applying destructive migrations; they are applied only with allow_destructive_migrations enabled.");
+    return 2;
+  }
+
+  0
+}
+
+/// Connects to the configured database for the command line tools, errors are printed.
+pub fn connect() -> Option<MysqlConnection> {
+  dotenv::dotenv().ok();
+
+  let url: String = match config::figment().extract_inner("databases.galera.url") {
+    Ok(url) => url,
+    Err(err) => {
+      eprintln!("Database URL isn't configured: {}", err);
+      return None;
+    },
+  };
+
+  match MysqlConnection::establish(&url) {
+    Ok(c) => Some(c),
+    Err(err) => {
+      eprintln!("Database isn't available: {}", err);
+      None
+    },
+  }
+}
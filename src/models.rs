@@ -1,12 +1,22 @@
-use super::schema::{album, album_media, album_invite, album_share_link, auth_access_token, auth_refresh_token, folder, media, favorite_media, user};
-use chrono::{Duration, NaiveDateTime, Utc};
-use email_address::EmailAddress;
-use lazy_regex::regex_is_match;
+use super::schema::{album, album_media, album_invite, album_share_link, album_share_link_access, album_share_link_download, album_visit, auth_access_token, auth_refresh_token, folder, folder_scan, job, library, media, favorite_media, media_detection, media_face, media_grant, media_integrity, media_tag, media_version, organization, organization_admin, password_reset, person, scan_alert, scan_issue, tag, tag_suggestion, user, user_feature, user_invite, user_scan_ignore, user_setting};
+use chrono::{DateTime, Duration, FixedOffset, NaiveDateTime, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+use crate::auth::password;
+use crate::banned_passwords::BannedPasswords;
+use crate::config::PasswordPolicy;
+use crate::edit::EditOperation;
+use crate::features::Feature;
+use crate::locale::DateFormat;
+use crate::metadata::MediaMetadata;
+use crate::routes::pagination::{natural_sort_key, MediaSort, SortOrder};
+use crate::scan::filesystem::FileStat;
+use crate::scan::{FileIssue, ScanSummary};
+use crate::validation::{self, ValidationErrors};
 use nanoid::nanoid;
 use rocket_okapi::JsonSchema;
-use rocket::form::FromForm;
+use rocket::form::{FromForm, FromFormField};
 use serde::{Serialize, Deserialize};
-use sha2::Digest;
+use std::str::FromStr;
 
 #[allow(non_camel_case_types)]
 #[derive(Identifiable, Queryable)]
@@ -16,6 +26,48 @@ pub struct User {
   pub username: String,
   pub email: String,
   pub password: String,
+  pub organization_id: i32,
+  pub role: String,
+  /// Disabled users can't sign in and their tokens aren't accepted.
+  pub disabled: bool,
+}
+
+impl User {
+  pub fn role(&self) -> UserRole {
+    self.role.parse().unwrap_or(UserRole::User)
+  }
+}
+
+/// Role of a user on the whole instance; organizations have their own administrators.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UserRole {
+  User,
+  /// Operates the instance: manages all users and organizations and sees server statistics.
+  Admin,
+}
+
+impl UserRole {
+  pub const ALL: [UserRole; 2] = [UserRole::User, UserRole::Admin];
+
+  /// Returns the name used in the database.
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      UserRole::User => "user",
+      UserRole::Admin => "admin",
+    }
+  }
+}
+
+impl FromStr for UserRole {
+  type Err = ();
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    UserRole::ALL.iter()
+      .find(|role| role.as_str() == s)
+      .copied()
+      .ok_or(())
+  }
 }
 
 /// Struct for inserting new users.
@@ -32,59 +84,30 @@ impl NewUser {
     NewUser { username, email, password }
   }
 
-  /// Encrypts the password.
-  // TODO: deduplicate later
-  pub fn hash_password(mut self) -> Self {
-    let mut hasher = sha2::Sha512::new();
-    hasher.update(self.password);
-    // {:x} means format as hexadecimal
-    self.password = format!("{:X}", hasher.finalize());
+  /// Hashes the password using `auth::password::hash_password()`.
+  pub async fn hash_password(mut self) -> Self {
+    self.password = password::hash_password(&self.password).await;
 
     self
   }
 
-  /// Checks the email.
-  pub fn is_email_valid(&self) -> bool {
-    EmailAddress::is_valid(&self.email)
-  }
+  /// Normalizes the username and the email using `validation::normalize_identifier()`.
+  pub fn normalize(mut self) -> Self {
+    self.username = validation::normalize_identifier(&self.username);
+    self.email = validation::normalize_identifier(&self.email);
 
-  /// Runs username, email and password checks.
-  pub fn check(&self) -> bool {
-    self.check_username() && self.is_email_valid() && self.check_password()
-  }
-
-  /// Checks the password.
-  ///
-  /// # Validity
-  ///
-  /// The **minimum length is 8 characters** and the **maximum is 128**.\
-  /// There are **no limits on what characters you can use**
-  /// because it could make cracking passwords easier.\
-  /// Maximum length limit is there to prevent long password denial of service
-  pub fn check_password(&self) -> bool {
-    let len = self.password.chars().count();
-
-    if len < 8 || len > 128 { return false; }
-
-    true
+    self
   }
 
-  /// Checks the username.
-  ///
-  /// # Validity
-  ///
-  /// The **minimum length is 5 characters** and the **maximum is 30**.\
-  /// The first character of a username must be a letter.\
-  /// Usernames are low-caps only and can contain these characters:
-  /// 1. latin letters (a-z)
-  /// 2. numbers (0-9)
-  /// 3. underscore (_)
-  pub fn check_username(&self) -> bool {
-    let len = self.password.chars().count();
+  /// Runs username, email and password checks.
+  pub fn validate(&self, policy: &PasswordPolicy, banned: &BannedPasswords) -> Result<(), ValidationErrors> {
+    let mut errors = ValidationErrors::new();
 
-    if len < 5 || len > 30 { return false; }
+    validation::validate_username(&self.username, &mut errors);
+    validation::validate_email(&self.email, &mut errors);
+    validation::validate_password("password", &self.password, policy, banned, &mut errors);
 
-    regex_is_match!(r"^[a-z_][a-z0-9_]{4,29}$", self.username.as_str())
+    errors.into_result()
   }
 }
 
@@ -98,6 +121,10 @@ pub struct Folder {
   pub owner_id: i32,
   pub parent: Option<i32>,
   pub name: String,
+  /// Set when the directory of a folder with media disappeared.
+  pub missing_since: Option<NaiveDateTime>,
+  /// `None` for folders created before folders had UUIDs, until they get one at the next start.
+  pub uuid: Option<String>,
 }
 
 /// Struct for inserting new folders.
@@ -107,14 +134,25 @@ pub struct NewFolder {
   pub owner_id: i32,
   pub parent: Option<i32>,
   pub name: String,
+  pub uuid: String,
 }
 
 impl NewFolder {
   pub fn new(owner_id: i32, name: String, parent: Option<i32>) -> NewFolder {
-    NewFolder { owner_id, name, parent }
+    NewFolder { owner_id, name, parent, uuid: nanoid!() }
   }
 }
 
+/// Modification time of a folder at its last complete scan.
+#[derive(Identifiable, Queryable, Associations, Insertable)]
+#[table_name = "folder_scan"]
+#[primary_key(folder_id)]
+#[belongs_to(Folder, foreign_key = "folder_id")]
+pub struct FolderScan {
+  pub folder_id: i32,
+  pub modified_at: NaiveDateTime,
+}
+
 #[allow(non_camel_case_types)]
 #[derive(Identifiable, Queryable, Associations, Serialize, JsonSchema)]
 #[table_name = "album"]
@@ -128,6 +166,31 @@ pub struct Album {
   pub thumbnail_link: Option<String>,
   pub link: String,
   pub password: Option<String>,
+  /// Locked albums can't be deleted and no share links can be created for them.
+  pub locked: bool,
+  /// Kind of the smart album; media of smart albums are selected automatically.
+  pub smart: Option<String>,
+  /// Default sorting of the album's media.
+  pub sort: Option<String>,
+  pub sort_order: Option<String>,
+  /// Local time when the earliest media of the album was taken; `None` for empty albums.
+  pub date_from: Option<NaiveDateTime>,
+  /// Local time when the latest media of the album was taken.
+  pub date_to: Option<NaiveDateTime>,
+}
+
+impl Album {
+  pub fn smart(&self) -> Option<SmartAlbum> {
+    self.smart.as_deref().and_then(|smart| smart.parse().ok())
+  }
+
+  pub fn sort(&self) -> Option<MediaSort> {
+    self.sort.as_deref().and_then(|sort| sort.parse().ok())
+  }
+
+  pub fn sort_order(&self) -> Option<SortOrder> {
+    self.sort_order.as_deref().and_then(|order| order.parse().ok())
+  }
 }
 
 /// Struct for inserting new albums.
@@ -140,6 +203,8 @@ pub struct NewAlbum {
   pub created_at: NaiveDateTime,
   pub link: String,
   pub password: Option<String>,
+  #[serde(skip)]
+  pub smart: Option<String>,
 }
 
 impl NewAlbum {
@@ -148,7 +213,48 @@ impl NewAlbum {
     let created_at = NaiveDateTime::from_timestamp(timestamp, 0);
     let link = nanoid!();
 
-    NewAlbum { owner_id, name, description, created_at, link, password }
+    NewAlbum { owner_id, name, description, created_at, link, password, smart: None }
+  }
+
+  /// Creates a smart album named after its kind.
+  pub fn new_smart(owner_id: i32, smart: SmartAlbum) -> NewAlbum {
+    NewAlbum { smart: Some(smart.as_str().to_string()), ..NewAlbum::new(owner_id, smart.name().to_string(), None, None) }
+  }
+}
+
+/// Kind of a smart album.\
+/// Smart albums can't be edited manually, shared or used for invites.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SmartAlbum {
+  /// Media liked by the owner of the album.
+  Favorites,
+}
+
+impl SmartAlbum {
+  /// Returns the name used in the database.
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      SmartAlbum::Favorites => "favorites",
+    }
+  }
+
+  /// Returns the default name of the album.
+  pub fn name(&self) -> &'static str {
+    match self {
+      SmartAlbum::Favorites => "Favorites",
+    }
+  }
+}
+
+impl FromStr for SmartAlbum {
+  type Err = ();
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    [SmartAlbum::Favorites].iter()
+      .find(|smart| smart.as_str() == s)
+      .copied()
+      .ok_or(())
   }
 }
 
@@ -165,6 +271,22 @@ pub struct Album_invite {
   pub write_access: bool,
 }
 
+/// struct for inviting users to an album.
+#[derive(Insertable)]
+#[table_name = "album_invite"]
+pub struct NewAlbumInvite {
+  pub album_id: i32,
+  pub invited_user_id: i32,
+  pub accepted: bool,
+  pub write_access: bool,
+}
+
+impl NewAlbumInvite {
+  pub fn new(album_id: i32, invited_user_id: i32, write_access: bool) -> NewAlbumInvite {
+    NewAlbumInvite { album_id, invited_user_id, accepted: false, write_access }
+  }
+}
+
 #[allow(non_camel_case_types)]
 #[derive(Identifiable, Queryable, Associations)]
 #[table_name = "album_share_link"]
@@ -174,7 +296,23 @@ pub struct AlbumShareLink {
   pub album_id: i32,
   pub uuid: String,
   pub password: Option<String>,
-  pub expiration: Option<NaiveDateTime>
+  pub expiration: Option<NaiveDateTime>,
+  pub title: Option<String>,
+  pub welcome_message: Option<String>,
+  pub accent_color: Option<String>,
+  pub allow_zip_download: bool,
+  /// Bandwidth shared by all visitors of the link in KiB/s.
+  pub bandwidth_limit: Option<i32>,
+}
+
+impl AlbumShareLink {
+  pub fn branding(&self) -> AlbumShareLinkBranding {
+    AlbumShareLinkBranding {
+      title: self.title.clone(),
+      welcome_message: self.welcome_message.clone(),
+      accent_color: self.accent_color.clone(),
+    }
+  }
 }
 
 #[allow(non_camel_case_types)]
@@ -184,14 +322,125 @@ pub struct NewAlbumShareLink {
   pub album_id: i32,
   pub uuid: String,
   pub password: Option<String>,
-  pub expiration: Option<NaiveDateTime>
+  pub expiration: Option<NaiveDateTime>,
+  pub title: Option<String>,
+  pub welcome_message: Option<String>,
+  pub accent_color: Option<String>,
+  pub allow_zip_download: bool,
+  pub bandwidth_limit: Option<i32>,
 }
 
 impl NewAlbumShareLink {
-  pub fn new(album_id: i32, password: Option<String>, expiration: Option<NaiveDateTime>) -> Self {
+  pub fn new(album_id: i32, password: Option<String>, expiration: Option<NaiveDateTime>, branding: AlbumShareLinkBranding, allow_zip_download: bool, bandwidth_limit: Option<i32>) -> Self {
     let uuid = nanoid!();
 
-    Self { album_id, uuid, password, expiration }
+    Self { album_id, uuid, password, expiration, title: branding.title, welcome_message: branding.welcome_message, accent_color: branding.accent_color, allow_zip_download, bandwidth_limit }
+  }
+}
+
+/// Access to a shared album through its share link; wrong passwords are recorded too.
+#[allow(non_camel_case_types)]
+#[derive(Identifiable, Queryable, Associations)]
+#[table_name = "album_share_link_access"]
+#[belongs_to(AlbumShareLink, foreign_key = "album_share_link_id")]
+pub struct AlbumShareLinkAccess {
+  pub id: i32,
+  pub album_share_link_id: i32,
+  pub accessed_at: NaiveDateTime,
+  pub ip_address: Option<String>,
+  pub user_agent: Option<String>,
+  pub succeeded: bool,
+}
+
+#[derive(Insertable)]
+#[table_name = "album_share_link_access"]
+pub struct NewAlbumShareLinkAccess {
+  pub album_share_link_id: i32,
+  pub accessed_at: NaiveDateTime,
+  pub ip_address: Option<String>,
+  pub user_agent: Option<String>,
+  pub succeeded: bool,
+}
+
+impl NewAlbumShareLinkAccess {
+  /// Creates an access happening now; user agents are truncated.
+  pub fn new(album_share_link_id: i32, ip_address: Option<String>, user_agent: Option<String>, succeeded: bool) -> NewAlbumShareLinkAccess {
+    NewAlbumShareLinkAccess {
+      album_share_link_id,
+      accessed_at: Utc::now().naive_utc(),
+      ip_address,
+      user_agent: user_agent.map(|user_agent| user_agent.chars().take(USER_AGENT_MAX_LENGTH).collect()),
+      succeeded,
+    }
+  }
+}
+
+/// Download of the whole shared album as a zip archive.
+#[allow(non_camel_case_types)]
+#[derive(Identifiable, Queryable, Associations)]
+#[table_name = "album_share_link_download"]
+#[belongs_to(AlbumShareLink, foreign_key = "album_share_link_id")]
+pub struct AlbumShareLinkDownload {
+  pub id: i32,
+  pub album_share_link_id: i32,
+  pub downloaded_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "album_share_link_download"]
+pub struct NewAlbumShareLinkDownload {
+  pub album_share_link_id: i32,
+  pub downloaded_at: NaiveDateTime,
+}
+
+impl NewAlbumShareLinkDownload {
+  /// Creates a download happening now.
+  pub fn new(album_share_link_id: i32) -> NewAlbumShareLinkDownload {
+    NewAlbumShareLinkDownload { album_share_link_id, downloaded_at: Utc::now().naive_utc() }
+  }
+}
+
+/// Branding of an album share link shown to its visitors.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default)]
+pub struct AlbumShareLinkBranding {
+  /// Title shown instead of the album name (at most 255 characters).
+  pub title: Option<String>,
+  /// Message shown to visitors above the media (at most 2000 characters).
+  pub welcome_message: Option<String>,
+  /// Accent color of the page in the `#rrggbb` format.
+  pub accent_color: Option<String>,
+}
+
+impl AlbumShareLinkBranding {
+  /// Trims the values and removes empty ones; colors are lowercased.
+  pub fn normalize(self) -> Self {
+    fn trimmed(value: Option<String>) -> Option<String> {
+      value.map(|value| value.trim().to_string()).filter(|value| !value.is_empty())
+    }
+
+    Self {
+      title: trimmed(self.title),
+      welcome_message: trimmed(self.welcome_message),
+      accent_color: trimmed(self.accent_color).map(|color| color.to_lowercase()),
+    }
+  }
+
+  pub fn validate(&self) -> Result<(), ValidationErrors> {
+    let mut errors = ValidationErrors::new();
+
+    if let Some(title) = &self.title {
+      validation::validate_max_length("title", title, 255, &mut errors);
+    }
+
+    if let Some(welcome_message) = &self.welcome_message {
+      validation::validate_max_length("welcome_message", welcome_message, 2000, &mut errors);
+    }
+
+    if let Some(accent_color) = &self.accent_color {
+      validation::validate_color("accent_color", accent_color, &mut errors);
+    }
+
+    errors.into_result()
   }
 }
 
@@ -203,14 +452,46 @@ impl NewAlbumShareLink {
 pub struct AlbumMedia {
   pub id: i32,
   pub album_id: i32,
-  pub media_id: i32
+  pub media_id: i32,
+  pub added_at: NaiveDateTime,
+  /// User who added the media; `None` when they were deleted or the media were added before it was recorded.
+  pub added_by: Option<i32>,
 }
 
 #[derive(Insertable, Deserialize, JsonSchema)]
 #[table_name = "album_media"]
 pub struct NewAlbumMedia {
   pub album_id: i32,
-  pub media_id: i32
+  pub media_id: i32,
+  pub added_at: NaiveDateTime,
+  pub added_by: Option<i32>,
+}
+
+impl NewAlbumMedia {
+  /// Creates media added to the album now.
+  pub fn new(album_id: i32, media_id: i32, added_by: Option<i32>) -> NewAlbumMedia {
+    NewAlbumMedia { album_id, media_id, added_at: Utc::now().naive_utc(), added_by }
+  }
+}
+
+/// Time of the last visit of an album by a user.
+#[allow(non_camel_case_types)]
+#[derive(Identifiable, Queryable, Associations, Insertable)]
+#[table_name = "album_visit"]
+#[primary_key(user_id, album_id)]
+#[belongs_to(Album, foreign_key = "album_id")]
+#[belongs_to(User, foreign_key = "user_id")]
+pub struct AlbumVisit {
+  pub user_id: i32,
+  pub album_id: i32,
+  pub visited_at: NaiveDateTime,
+}
+
+impl AlbumVisit {
+  /// Creates a visit happening now.
+  pub fn new(user_id: i32, album_id: i32) -> AlbumVisit {
+    AlbumVisit { user_id, album_id, visited_at: Utc::now().naive_utc() }
+  }
 }
 
 //#[table_name = "posts"]
@@ -228,8 +509,76 @@ pub struct Media {
   pub height: u32,
   pub description: Option<String>,
   pub date_taken: NaiveDateTime,
+  pub date_taken_offset: Option<i32>,
   pub uuid: String,
   pub sha2_512: String,
+  /// Current version; 0 is the original in the gallery, edited versions are stored separately.
+  pub version: i32,
+  pub camera_make: Option<String>,
+  pub camera_model: Option<String>,
+  pub latitude: Option<f64>,
+  pub longitude: Option<f64>,
+  pub iso: Option<u32>,
+  /// Focal length in millimeters.
+  pub focal_length: Option<f64>,
+  /// EXIF orientation (1-8).
+  pub orientation: Option<u16>,
+  /// Filename used for natural sorting, see `pagination::natural_sort_key()`.
+  pub filename_sort_key: Option<String>,
+  /// Size of the file at the last scan.
+  pub file_size: Option<u64>,
+  /// Modification time (UTC) of the file at the last scan.
+  pub file_modified_at: Option<NaiveDateTime>,
+  /// Set when a scan didn't find the file; such media aren't listed until the file appears again or is relinked.
+  pub missing_since: Option<NaiveDateTime>,
+}
+
+impl Media {
+  /// Returns the local time when the media was taken together with its UTC offset.\
+  /// The offset stored with the media (from EXIF) takes precedence,
+  /// otherwise the `fallback` time zone (usually the owner's default time zone) is used.
+  pub fn date_taken_with_offset(&self, fallback: Tz) -> DateTime<FixedOffset> {
+    if let Some(offset) = self.date_taken_offset.and_then(FixedOffset::east_opt) {
+      if let Some(date_taken) = offset.from_local_datetime(&self.date_taken).single() {
+        return date_taken;
+      }
+    }
+
+    // local time can be ambiguous or nonexistent because of DST
+    let date_taken = fallback.from_local_datetime(&self.date_taken)
+      .earliest()
+      .unwrap_or_else(|| fallback.from_utc_datetime(&self.date_taken));
+
+    date_taken.with_timezone(&date_taken.offset().fix())
+  }
+}
+
+/// File of media as recorded by the last scan.
+#[derive(Queryable, Debug, Clone)]
+pub struct ScannedFile {
+  pub media_id: i32,
+  pub filename: String,
+  pub version: i32,
+  pub file_size: Option<u64>,
+  pub file_modified_at: Option<NaiveDateTime>,
+  pub missing_since: Option<NaiveDateTime>,
+}
+
+impl ScannedFile {
+  /// Returns the recorded size and modification time; `None` for media scanned before they were recorded.
+  pub fn stat(&self) -> Option<FileStat> {
+    Some(FileStat { size: self.file_size?, modified: self.file_modified_at? })
+  }
+}
+
+/// Current size and modification time of a scanned file.\
+/// `sha2_512` is set when the content of the file changed.
+#[derive(Debug, Clone)]
+pub struct ScannedFileChange {
+  pub media_id: i32,
+  pub version: i32,
+  pub stat: FileStat,
+  pub sha2_512: Option<String>,
 }
 
 /// struct for inserting new media
@@ -243,13 +592,25 @@ pub struct NewMedia {
   pub height: u32,
   pub description: Option<String>,
   pub date_taken: NaiveDateTime,
+  pub date_taken_offset: Option<i32>,
   pub uuid: String,
   pub sha2_512: String,
+  pub camera_make: Option<String>,
+  pub camera_model: Option<String>,
+  pub latitude: Option<f64>,
+  pub longitude: Option<f64>,
+  pub iso: Option<u32>,
+  pub focal_length: Option<f64>,
+  pub orientation: Option<u16>,
+  pub filename_sort_key: Option<String>,
+  pub file_size: Option<u64>,
+  pub file_modified_at: Option<NaiveDateTime>,
 }
 
 impl NewMedia {
-  pub fn new(filename: String, folder_id: i32, owner_id: i32, width: u32, height: u32, description: Option<String>, date_taken: NaiveDateTime, uuid: String, sha2_512: String) -> NewMedia {
+  pub fn new(filename: String, folder_id: i32, owner_id: i32, width: u32, height: u32, description: Option<String>, date_taken: NaiveDateTime, date_taken_offset: Option<i32>, uuid: String, sha2_512: String) -> NewMedia {
     NewMedia {
+      filename_sort_key: Some(natural_sort_key(&filename)),
       filename,
       folder_id,
       owner_id,
@@ -257,10 +618,46 @@ impl NewMedia {
       height,
       description,
       date_taken,
+      date_taken_offset,
       uuid,
       sha2_512,
+      camera_make: None,
+      camera_model: None,
+      latitude: None,
+      longitude: None,
+      iso: None,
+      focal_length: None,
+      orientation: None,
+      file_size: None,
+      file_modified_at: None,
     }
   }
+
+  /// Sets the size and modification time of the file, so later scans can skip it while it's unchanged.
+  pub fn with_file_stat(mut self, stat: Option<FileStat>) -> NewMedia {
+    self.file_size = stat.map(|stat| stat.size);
+    self.file_modified_at = stat.map(|stat| stat.modified);
+
+    self
+  }
+
+  /// Sets metadata read from the media; the date taken is replaced only when it's known.
+  pub fn with_metadata(mut self, metadata: MediaMetadata) -> NewMedia {
+    if let Some(date_taken) = metadata.date_taken {
+      self.date_taken = date_taken;
+      self.date_taken_offset = metadata.date_taken_offset;
+    }
+
+    self.camera_make = metadata.camera_make;
+    self.camera_model = metadata.camera_model;
+    self.latitude = metadata.latitude;
+    self.longitude = metadata.longitude;
+    self.iso = metadata.iso;
+    self.focal_length = metadata.focal_length;
+    self.orientation = metadata.orientation;
+
+    self
+  }
 }
 
 #[allow(non_camel_case_types)]
@@ -298,8 +695,14 @@ impl NewFavoriteMedia {
 pub struct AuthRefreshToken {
   pub id: i32,
   pub user_id: i32,
+  /// Identifies the session; tokens issued before sessions were listed don't have it.
+  pub uuid: Option<String>,
+  /// Hash of the token, see `auth::token::hash_token`.
   pub refresh_token: String,
   pub expiration_time: NaiveDateTime,
+  pub created_at: Option<NaiveDateTime>,
+  pub user_agent: Option<String>,
+  pub ip_address: Option<String>,
 }
 
 /// struct for inserting refresh tokens.
@@ -307,18 +710,37 @@ pub struct AuthRefreshToken {
 #[table_name = "auth_refresh_token"]
 pub struct NewAuthRefreshToken {
   pub user_id: i32,
+  pub uuid: String,
   pub refresh_token: String,
   pub expiration_time: NaiveDateTime,
+  pub created_at: NaiveDateTime,
+  pub user_agent: Option<String>,
+  pub ip_address: Option<String>,
 }
 
+/// Maximum number of characters of a stored user agent.
+const USER_AGENT_MAX_LENGTH: usize = 255;
+
 impl NewAuthRefreshToken {
   pub fn new(user_id: i32, refresh_token: String) -> NewAuthRefreshToken {
     NewAuthRefreshToken {
       user_id,
+      uuid: nanoid!(),
       refresh_token,
-      expiration_time: Utc::now().naive_utc() + Duration::hours(1)
+      expiration_time: Utc::now().naive_utc() + Duration::hours(1),
+      created_at: Utc::now().naive_utc(),
+      user_agent: None,
+      ip_address: None,
     }
   }
+
+  /// Records the client which logged in; user agents are truncated.
+  pub fn with_client(mut self, user_agent: Option<String>, ip_address: Option<String>) -> Self {
+    self.user_agent = user_agent.map(|user_agent| user_agent.chars().take(USER_AGENT_MAX_LENGTH).collect());
+    self.ip_address = ip_address;
+
+    self
+  }
 }
 
 #[allow(non_camel_case_types)]
@@ -328,6 +750,7 @@ impl NewAuthRefreshToken {
 pub struct AuthAccessToken {
   pub id: i32,
   pub refresh_token_id: i32,
+  /// Hash of the token, see `auth::token::hash_token`.
   pub access_token: String,
   pub expiration_time: NaiveDateTime,
 }
@@ -350,3 +773,807 @@ impl NewAuthAccessToken {
     }
   }
 }
+
+#[allow(non_camel_case_types)]
+#[derive(Identifiable, Queryable, Associations, Insertable, Clone)]
+#[table_name = "user_setting"]
+#[primary_key(user_id)]
+#[belongs_to(User, foreign_key = "user_id")]
+pub struct UserSetting {
+  pub user_id: i32,
+  pub timezone: Option<String>,
+  /// BCP 47 language tag, see `locale::normalize_locale`.
+  pub locale: Option<String>,
+  /// See `DateFormat::as_str()`.
+  pub date_format: Option<String>,
+}
+
+impl UserSetting {
+  /// Returns default settings of a user.
+  pub fn new(user_id: i32) -> UserSetting {
+    UserSetting {
+      user_id,
+      timezone: None,
+      locale: None,
+      date_format: None,
+    }
+  }
+
+  /// Returns the user's date format; `None` when it isn't set or is invalid.
+  pub fn date_format(&self) -> Option<DateFormat> {
+    self.date_format.as_deref().and_then(|date_format| date_format.parse().ok())
+  }
+
+  /// Returns the user's default time zone; UTC when it isn't set or is invalid.
+  pub fn timezone(&self) -> Tz {
+    self.timezone.as_ref()
+      .and_then(|timezone| timezone.parse::<Tz>().ok())
+      .unwrap_or(Tz::UTC)
+  }
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Identifiable, Queryable, Associations)]
+#[table_name = "user_feature"]
+#[belongs_to(User, foreign_key = "user_id")]
+pub struct UserFeature {
+  pub id: i32,
+  pub user_id: i32,
+  pub feature: String,
+}
+
+/// struct for enabling features.
+#[derive(Insertable)]
+#[table_name = "user_feature"]
+pub struct NewUserFeature {
+  pub user_id: i32,
+  pub feature: String,
+}
+
+impl NewUserFeature {
+  pub fn new(user_id: i32, feature: Feature) -> NewUserFeature {
+    NewUserFeature {
+      user_id,
+      feature: feature.as_str().to_string(),
+    }
+  }
+}
+
+/// struct for inserting scan ignore patterns.
+#[derive(Insertable)]
+#[table_name = "user_scan_ignore"]
+pub struct NewUserScanIgnore {
+  pub user_id: i32,
+  pub pattern: String,
+}
+
+impl NewUserScanIgnore {
+  pub fn new(user_id: i32, pattern: String) -> NewUserScanIgnore {
+    NewUserScanIgnore { user_id, pattern }
+  }
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Identifiable, Queryable, Associations)]
+#[table_name = "media_grant"]
+#[belongs_to(Media, foreign_key = "media_id")]
+#[belongs_to(User, foreign_key = "user_id")]
+pub struct MediaGrant {
+  pub id: i32,
+  pub media_id: i32,
+  pub user_id: i32,
+  pub created_at: NaiveDateTime,
+}
+
+/// struct for sharing a media with another user.
+#[derive(Insertable)]
+#[table_name = "media_grant"]
+pub struct NewMediaGrant {
+  pub media_id: i32,
+  pub user_id: i32,
+}
+
+impl NewMediaGrant {
+  pub fn new(media_id: i32, user_id: i32) -> NewMediaGrant {
+    NewMediaGrant { media_id, user_id }
+  }
+}
+
+/// Tag of media; names are unique per user regardless of the letter case.
+#[derive(Identifiable, Queryable, Associations, Clone)]
+#[table_name = "tag"]
+#[belongs_to(User, foreign_key = "owner_id")]
+pub struct Tag {
+  pub id: i32,
+  pub owner_id: i32,
+  pub name: String,
+  pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "tag"]
+pub struct NewTag {
+  pub owner_id: i32,
+  pub name: String,
+}
+
+#[derive(Identifiable, Queryable, Associations)]
+#[table_name = "media_tag"]
+#[belongs_to(Media, foreign_key = "media_id")]
+#[belongs_to(Tag, foreign_key = "tag_id")]
+pub struct MediaTag {
+  pub id: i32,
+  pub media_id: i32,
+  pub tag_id: i32,
+}
+
+#[derive(Insertable)]
+#[table_name = "media_tag"]
+pub struct NewMediaTag {
+  pub media_id: i32,
+  pub tag_id: i32,
+}
+
+/// Media processed by the detection of faces and objects.
+#[derive(Identifiable, Queryable, Associations, Insertable)]
+#[table_name = "media_detection"]
+#[primary_key(media_id)]
+#[belongs_to(Media, foreign_key = "media_id")]
+pub struct MediaDetection {
+  pub media_id: i32,
+  pub detected_at: NaiveDateTime,
+}
+
+/// Tag suggested for a media by the detection of objects.
+#[derive(Identifiable, Queryable, Associations)]
+#[table_name = "tag_suggestion"]
+#[belongs_to(Media, foreign_key = "media_id")]
+pub struct TagSuggestion {
+  pub id: i32,
+  pub media_id: i32,
+  pub name: String,
+  /// Confidence of the detection between 0 and 1.
+  pub confidence: f64,
+}
+
+#[derive(Insertable)]
+#[table_name = "tag_suggestion"]
+pub struct NewTagSuggestion {
+  pub media_id: i32,
+  pub name: String,
+  pub confidence: f64,
+}
+
+/// Directory with media of a user outside of the gallery, see `libraries`.
+#[derive(Identifiable, Queryable, Associations, Clone, Debug)]
+#[table_name = "library"]
+#[belongs_to(User, foreign_key = "owner_id")]
+pub struct Library {
+  pub id: i32,
+  pub uuid: String,
+  pub owner_id: i32,
+  /// Name of the subfolder of the user's root folder; `None` when it replaces the user's directory in the gallery.
+  pub name: Option<String>,
+  pub path: String,
+  pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "library"]
+pub struct NewLibrary {
+  pub uuid: String,
+  pub owner_id: i32,
+  pub name: Option<String>,
+  pub path: String,
+}
+
+impl NewLibrary {
+  pub fn new(owner_id: i32, name: Option<String>, path: String) -> NewLibrary {
+    NewLibrary { uuid: nanoid!(), owner_id, name, path }
+  }
+}
+
+/// Person in media of a user.
+#[derive(Identifiable, Queryable, Associations, Clone)]
+#[table_name = "person"]
+#[belongs_to(User, foreign_key = "owner_id")]
+pub struct Person {
+  pub id: i32,
+  pub uuid: String,
+  pub owner_id: i32,
+  pub name: String,
+  pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "person"]
+pub struct NewPerson {
+  pub uuid: String,
+  pub owner_id: i32,
+  pub name: String,
+}
+
+impl NewPerson {
+  pub fn new(owner_id: i32, name: String) -> NewPerson {
+    NewPerson { uuid: nanoid!(), owner_id, name }
+  }
+}
+
+/// Face in a media; the bounding box is relative to the width and height of the original media,
+/// from its top left corner.
+#[derive(Identifiable, Queryable, Associations, Clone)]
+#[table_name = "media_face"]
+#[belongs_to(Media, foreign_key = "media_id")]
+#[belongs_to(Person, foreign_key = "person_id")]
+pub struct MediaFace {
+  pub id: i32,
+  pub uuid: String,
+  pub media_id: i32,
+  /// `None` when the face wasn't recognized yet or the person was deleted.
+  pub person_id: Option<i32>,
+  pub x: f64,
+  pub y: f64,
+  pub width: f64,
+  pub height: f64,
+  pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "media_face"]
+pub struct NewMediaFace {
+  pub uuid: String,
+  pub media_id: i32,
+  pub person_id: Option<i32>,
+  pub x: f64,
+  pub y: f64,
+  pub width: f64,
+  pub height: f64,
+}
+
+/// Result of the last integrity check of a media.
+#[allow(non_camel_case_types)]
+#[derive(Identifiable, Queryable, Associations, Insertable, Clone)]
+#[table_name = "media_integrity"]
+#[primary_key(media_id)]
+#[belongs_to(Media, foreign_key = "media_id")]
+pub struct MediaIntegrity {
+  pub media_id: i32,
+  pub verified_at: NaiveDateTime,
+  /// One of `ok`, `mismatch` and `missing`.
+  pub status: String,
+  /// Hash of the file when it doesn't match the stored one.
+  pub actual_sha2_512: Option<String>,
+}
+
+impl MediaIntegrity {
+  pub fn new(media_id: i32, status: IntegrityStatus, actual_sha2_512: Option<String>) -> MediaIntegrity {
+    MediaIntegrity {
+      media_id,
+      verified_at: Utc::now().naive_utc(),
+      status: status.as_str().to_string(),
+      actual_sha2_512,
+    }
+  }
+}
+
+/// Version of a media; version 0 is the original, the others were created by edits.
+#[derive(Identifiable, Queryable, Associations, Clone)]
+#[table_name = "media_version"]
+#[belongs_to(Media, foreign_key = "media_id")]
+pub struct MediaVersion {
+  pub id: i32,
+  pub media_id: i32,
+  pub version: i32,
+  /// Edit operations (JSON) applied to the previous current version; `None` for the original.
+  pub operations: Option<String>,
+  pub width: u32,
+  pub height: u32,
+  pub sha2_512: String,
+  pub orientation: Option<u16>,
+  pub created_at: NaiveDateTime,
+}
+
+impl MediaVersion {
+  /// Returns the edit operations which created the version.
+  pub fn operations(&self) -> Option<Vec<EditOperation>> {
+    serde_json::from_str(self.operations.as_ref()?).ok()
+  }
+}
+
+#[derive(Insertable)]
+#[table_name = "media_version"]
+pub struct NewMediaVersion {
+  pub media_id: i32,
+  pub version: i32,
+  pub operations: Option<String>,
+  pub width: u32,
+  pub height: u32,
+  pub sha2_512: String,
+  pub orientation: Option<u16>,
+  pub created_at: NaiveDateTime,
+}
+
+impl NewMediaVersion {
+  /// Creates the version 0 from the media before its first edit.
+  pub fn original(media: &Media) -> NewMediaVersion {
+    NewMediaVersion {
+      media_id: media.id,
+      version: 0,
+      operations: None,
+      width: media.width,
+      height: media.height,
+      sha2_512: media.sha2_512.clone(),
+      orientation: media.orientation,
+      created_at: Utc::now().naive_utc(),
+    }
+  }
+
+  /// Creates an edited version; edited versions have their orientation applied to the pixels.
+  pub fn edited(media_id: i32, version: i32, operations: &[EditOperation], width: u32, height: u32, sha2_512: String) -> NewMediaVersion {
+    NewMediaVersion {
+      media_id,
+      version,
+      operations: serde_json::to_string(operations).ok(),
+      width,
+      height,
+      sha2_512,
+      orientation: None,
+      created_at: Utc::now().naive_utc(),
+    }
+  }
+}
+
+/// Result of an integrity check.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegrityStatus {
+  /// The file matches the stored hash.
+  Ok,
+  /// The file was changed or corrupted.
+  Mismatch,
+  /// The file doesn't exist.
+  Missing,
+}
+
+impl IntegrityStatus {
+  /// Returns the name used in the database.
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      IntegrityStatus::Ok => "ok",
+      IntegrityStatus::Mismatch => "mismatch",
+      IntegrityStatus::Missing => "missing",
+    }
+  }
+}
+
+impl FromStr for IntegrityStatus {
+  type Err = ();
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    [IntegrityStatus::Ok, IntegrityStatus::Mismatch, IntegrityStatus::Missing].iter()
+      .find(|status| status.as_str() == s)
+      .copied()
+      .ok_or(())
+  }
+}
+
+/// Long-running job (scan, metadata write-back, ...) persisted so it survives restarts.
+#[derive(Identifiable, Queryable, Associations, Clone)]
+#[table_name = "job"]
+#[belongs_to(User, foreign_key = "user_id")]
+pub struct Job {
+  pub id: i32,
+  pub uuid: String,
+  pub user_id: i32,
+  /// One of `JobKind`.
+  pub kind: String,
+  /// One of `JobState`.
+  pub state: String,
+  /// Number of items to process; `None` when it isn't known.
+  pub total: Option<u32>,
+  pub processed: u32,
+  /// Reason of the failure.
+  pub error: Option<String>,
+  /// UUID of the interrupted job this job continues.
+  pub resumed_from: Option<String>,
+  pub created_at: NaiveDateTime,
+  pub finished_at: Option<NaiveDateTime>,
+}
+
+impl Job {
+  pub fn kind(&self) -> Option<JobKind> {
+    JobKind::from_str(&self.kind).ok()
+  }
+}
+
+#[derive(Insertable)]
+#[table_name = "job"]
+pub struct NewJob {
+  pub uuid: String,
+  pub user_id: i32,
+  pub kind: String,
+  pub state: String,
+  pub resumed_from: Option<String>,
+  pub created_at: NaiveDateTime,
+}
+
+impl NewJob {
+  /// Creates a running job.
+  pub fn new(user_id: i32, kind: JobKind) -> NewJob {
+    NewJob {
+      uuid: nanoid!(),
+      user_id,
+      kind: kind.as_str().to_string(),
+      state: JobState::Running.as_str().to_string(),
+      resumed_from: None,
+      created_at: Utc::now().naive_utc(),
+    }
+  }
+
+  /// Creates a running job continuing an interrupted one.
+  pub fn resumed(interrupted: &Job, kind: JobKind) -> NewJob {
+    NewJob {
+      resumed_from: Some(interrupted.uuid.clone()),
+      ..NewJob::new(interrupted.user_id, kind)
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+  Scan,
+  MetadataWriteBack,
+  Export,
+}
+
+impl JobKind {
+  /// Returns the name used in the database.
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      JobKind::Scan => "scan",
+      JobKind::MetadataWriteBack => "metadata_write_back",
+      JobKind::Export => "export",
+    }
+  }
+}
+
+impl FromStr for JobKind {
+  type Err = ();
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    [JobKind::Scan, JobKind::MetadataWriteBack, JobKind::Export].iter()
+      .find(|kind| kind.as_str() == s)
+      .copied()
+      .ok_or(())
+  }
+}
+
+/// State of a job; running jobs can only become finished or failed.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+  Running,
+  Finished,
+  /// The job failed or was interrupted by a restart.
+  Failed,
+}
+
+impl JobState {
+  /// Returns the name used in the database.
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      JobState::Running => "running",
+      JobState::Finished => "finished",
+      JobState::Failed => "failed",
+    }
+  }
+}
+
+impl FromStr for JobState {
+  type Err = ();
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    [JobState::Running, JobState::Finished, JobState::Failed].iter()
+      .find(|state| state.as_str() == s)
+      .copied()
+      .ok_or(())
+  }
+}
+
+/// Isolated space of users (e.g. a family); users share media and albums only within their organization.
+#[derive(Identifiable, Queryable, Clone)]
+#[table_name = "organization"]
+pub struct Organization {
+  pub id: i32,
+  pub uuid: String,
+  pub name: String,
+  pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "organization"]
+pub struct NewOrganization {
+  pub uuid: String,
+  pub name: String,
+  pub created_at: NaiveDateTime,
+}
+
+impl NewOrganization {
+  pub fn new(name: String) -> NewOrganization {
+    NewOrganization {
+      uuid: nanoid!(),
+      name,
+      created_at: Utc::now().naive_utc(),
+    }
+  }
+}
+
+/// Administrator of an organization; administrators manage its users.
+#[derive(Identifiable, Queryable, Associations, Insertable)]
+#[table_name = "organization_admin"]
+#[primary_key(user_id)]
+#[belongs_to(User, foreign_key = "user_id")]
+#[belongs_to(Organization, foreign_key = "organization_id")]
+pub struct OrganizationAdmin {
+  pub user_id: i32,
+  pub organization_id: i32,
+}
+
+/// One-time link for signing up into an organization.
+#[derive(Identifiable, Queryable, Associations, Debug, Clone)]
+#[table_name = "user_invite"]
+#[belongs_to(Organization, foreign_key = "organization_id")]
+pub struct UserInvite {
+  pub id: i32,
+  pub token: String,
+  pub organization_id: i32,
+  /// `None` when the administrator who created the invite was deleted.
+  pub created_by: Option<i32>,
+  pub created_at: NaiveDateTime,
+  pub expiration: NaiveDateTime,
+  /// The new user becomes an administrator of the organization.
+  pub admin: bool,
+  pub used_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[table_name = "user_invite"]
+pub struct NewUserInvite {
+  /// Hash of the token, see `auth::token::hash_token`.
+  pub token: String,
+  pub organization_id: i32,
+  pub created_by: Option<i32>,
+  pub created_at: NaiveDateTime,
+  pub expiration: NaiveDateTime,
+  pub admin: bool,
+}
+
+impl NewUserInvite {
+  pub fn new(token: String, organization_id: i32, created_by: i32, expiration: NaiveDateTime, admin: bool) -> NewUserInvite {
+    NewUserInvite {
+      token,
+      organization_id,
+      created_by: Some(created_by),
+      created_at: Utc::now().naive_utc(),
+      expiration,
+      admin,
+    }
+  }
+}
+
+/// One-time token for setting a new password without the current one.
+#[derive(Identifiable, Queryable, Associations, Debug, Clone)]
+#[table_name = "password_reset"]
+#[belongs_to(User, foreign_key = "user_id")]
+pub struct PasswordReset {
+  pub id: i32,
+  /// Hash of the token, see `auth::token::hash_token`.
+  pub token: String,
+  pub user_id: i32,
+  /// `None` when the administrator who issued the reset was deleted.
+  pub created_by: Option<i32>,
+  pub created_at: NaiveDateTime,
+  pub expiration: NaiveDateTime,
+  pub used_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[table_name = "password_reset"]
+pub struct NewPasswordReset {
+  /// Hash of the token, see `auth::token::hash_token`.
+  pub token: String,
+  pub user_id: i32,
+  pub created_by: Option<i32>,
+  pub created_at: NaiveDateTime,
+  pub expiration: NaiveDateTime,
+}
+
+impl NewPasswordReset {
+  pub fn new(token: String, user_id: i32, created_by: i32, expiration: NaiveDateTime) -> NewPasswordReset {
+    NewPasswordReset {
+      token,
+      user_id,
+      created_by: Some(created_by),
+      created_at: Utc::now().naive_utc(),
+      expiration,
+    }
+  }
+}
+
+/// Scan which found an unusual share of the user's media modified or missing.
+#[derive(Identifiable, Queryable, Associations, Debug, Clone)]
+#[table_name = "scan_alert"]
+#[belongs_to(User, foreign_key = "user_id")]
+pub struct ScanAlert {
+  pub id: i32,
+  pub user_id: i32,
+  /// `None` when the job of the scan was deleted.
+  pub job_id: Option<i32>,
+  pub created_at: NaiveDateTime,
+  /// Media of the user before the scan.
+  pub media: u32,
+  pub modified: u32,
+  pub missing: u32,
+  /// Changes of modified media weren't stored.
+  pub paused: bool,
+  pub confirmed_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[table_name = "scan_alert"]
+pub struct NewScanAlert {
+  pub user_id: i32,
+  pub job_id: Option<i32>,
+  pub created_at: NaiveDateTime,
+  pub media: u32,
+  pub modified: u32,
+  pub missing: u32,
+  pub paused: bool,
+}
+
+impl NewScanAlert {
+  pub fn new(job: &Job, summary: &ScanSummary) -> NewScanAlert {
+    NewScanAlert {
+      user_id: job.user_id,
+      job_id: Some(job.id),
+      created_at: Utc::now().naive_utc(),
+      media: summary.media as u32,
+      modified: summary.modified as u32,
+      missing: summary.missing as u32,
+      paused: summary.paused,
+    }
+  }
+}
+
+/// Kind of a problem with a file found by a scan.
+#[derive(FromFormField, Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanIssueKind {
+  /// The file isn't a supported image, video or audio.
+  #[field(value = "unsupported")]
+  Unsupported,
+  /// The file couldn't be read.
+  #[field(value = "unreadable")]
+  Unreadable,
+  /// Dimensions of the image couldn't be read, e.g. because it's damaged.
+  #[field(value = "unknown_dimensions")]
+  UnknownDimensions,
+  /// The media couldn't be saved to the database.
+  #[field(value = "not_saved")]
+  NotSaved,
+}
+
+impl ScanIssueKind {
+  /// Returns the name used in the database.
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      ScanIssueKind::Unsupported => "unsupported",
+      ScanIssueKind::Unreadable => "unreadable",
+      ScanIssueKind::UnknownDimensions => "unknown_dimensions",
+      ScanIssueKind::NotSaved => "not_saved",
+    }
+  }
+
+  /// Unsupported files are only warnings, as galleries often contain other files (e.g. sidecars).
+  pub fn severity(&self) -> ScanIssueSeverity {
+    match self {
+      ScanIssueKind::Unsupported => ScanIssueSeverity::Warning,
+      _ => ScanIssueSeverity::Error,
+    }
+  }
+}
+
+impl FromStr for ScanIssueKind {
+  type Err = ();
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    [ScanIssueKind::Unsupported, ScanIssueKind::Unreadable, ScanIssueKind::UnknownDimensions, ScanIssueKind::NotSaved].iter()
+      .find(|kind| kind.as_str() == s)
+      .copied()
+      .ok_or(())
+  }
+}
+
+#[derive(FromFormField, Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanIssueSeverity {
+  /// The file was skipped, as it isn't media.
+  #[field(value = "warning")]
+  Warning,
+  /// The media wasn't added to the gallery.
+  #[field(value = "error")]
+  Error,
+}
+
+impl ScanIssueSeverity {
+  /// Returns the name used in the database.
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      ScanIssueSeverity::Warning => "warning",
+      ScanIssueSeverity::Error => "error",
+    }
+  }
+}
+
+impl FromStr for ScanIssueSeverity {
+  type Err = ();
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    [ScanIssueSeverity::Warning, ScanIssueSeverity::Error].iter()
+      .find(|severity| severity.as_str() == s)
+      .copied()
+      .ok_or(())
+  }
+}
+
+/// Problem with a file found by the scan of a job.
+#[derive(Identifiable, Queryable, Associations, Debug, Clone)]
+#[table_name = "scan_issue"]
+#[belongs_to(Job, foreign_key = "job_id")]
+pub struct ScanIssue {
+  pub id: i32,
+  pub job_id: i32,
+  /// Path relative to the gallery directory, e.g. `john/Holiday/IMG_0001.jpg`.
+  pub path: String,
+  /// One of `ScanIssueKind`.
+  pub kind: String,
+  /// One of `ScanIssueSeverity`.
+  pub severity: String,
+  pub message: Option<String>,
+  pub created_at: NaiveDateTime,
+}
+
+impl ScanIssue {
+  pub fn kind(&self) -> Option<ScanIssueKind> {
+    ScanIssueKind::from_str(&self.kind).ok()
+  }
+
+  pub fn severity(&self) -> Option<ScanIssueSeverity> {
+    ScanIssueSeverity::from_str(&self.severity).ok()
+  }
+}
+
+#[derive(Insertable)]
+#[table_name = "scan_issue"]
+pub struct NewScanIssue {
+  pub job_id: i32,
+  pub path: String,
+  pub kind: String,
+  pub severity: String,
+  pub message: Option<String>,
+  pub created_at: NaiveDateTime,
+}
+
+impl NewScanIssue {
+  pub fn new(job: &Job, issue: &FileIssue) -> NewScanIssue {
+    NewScanIssue {
+      job_id: job.id,
+      path: issue.path.to_string_lossy().into_owned(),
+      kind: issue.kind.as_str().to_string(),
+      severity: issue.kind.severity().as_str().to_string(),
+      message: issue.message.clone(),
+      created_at: Utc::now().naive_utc(),
+    }
+  }
+}
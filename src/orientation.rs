@@ -0,0 +1,29 @@
+//! EXIF orientation of images.
+//!
+//! Some clients (TVs, digital photo frames, ...) ignore the EXIF orientation tag,
+//! so they need media re-encoded with the orientation already applied to the pixels.
+
+use crate::metadata::MediaMetadata;
+use image::DynamicImage;
+use std::io;
+use std::path::Path;
+
+/// Reads the EXIF orientation (1-8) of an image.\
+/// Returns `None` for images without the orientation tag.
+pub fn read_orientation(path: &Path) -> io::Result<Option<u16>> {
+  Ok(MediaMetadata::read(path)?.orientation)
+}
+
+/// Applies the EXIF orientation to the pixels.
+pub fn apply_orientation(image: DynamicImage, orientation: u16) -> DynamicImage {
+  match orientation {
+    2 => image.fliph(),
+    3 => image.rotate180(),
+    4 => image.flipv(),
+    5 => image.rotate90().fliph(),
+    6 => image.rotate90(),
+    7 => image.rotate270().fliph(),
+    8 => image.rotate270(),
+    _ => image,
+  }
+}
@@ -0,0 +1,45 @@
+//! Recovery from panics in request handlers.
+//!
+//! Rocket catches panics of handlers and responds with 500 instead of closing the connection; the panic hook installed
+//! here logs the panic with its location and counts it for `/metrics`. The 500 catcher (see `catchers`) responds
+//! with a JSON error and logs the failed request.
+//!
+//! The panic isn't passed to the catcher: the request may be finished on another thread than the one which panicked,
+//! so anything recorded per thread could be reported for an unrelated request.
+
+use std::panic::{self, PanicHookInfo};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Panics since the server started.
+static PANICS: AtomicU64 = AtomicU64::new(0);
+
+/// Installs the panic hook; panics are logged instead of printed to the standard error.
+pub fn install_hook() {
+  panic::set_hook(Box::new(|info| {
+    PANICS.fetch_add(1, Ordering::Relaxed);
+
+    let message = describe(info);
+    let thread = std::thread::current();
+
+    error!("Thread {} panicked: {}", thread.name().unwrap_or("<unnamed>"), message);
+  }));
+}
+
+/// Returns the number of panics since the server started.
+pub fn count() -> u64 {
+  PANICS.load(Ordering::Relaxed)
+}
+
+/// Returns the message of the panic with its location.
+fn describe(info: &PanicHookInfo) -> String {
+  let payload = info.payload();
+
+  let message = payload.downcast_ref::<&str>().copied()
+    .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+    .unwrap_or("Box<dyn Any>");
+
+  match info.location() {
+    Some(location) => format!("{} at {}:{}", message, location.file(), location.line()),
+    None => message.to_owned(),
+  }
+}
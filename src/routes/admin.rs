@@ -0,0 +1,441 @@
+//! Management of the instance, allowed only to its administrators.
+//!
+//! Users are addressed by their usernames.
+
+use crate::auth::token::Admin;
+use crate::config::{Config, ScanAlertPolicy};
+use crate::db;
+use crate::derivatives;
+use crate::errors;
+use crate::jobs;
+use crate::libraries;
+use crate::models::{IntegrityStatus, JobKind, Library, NewJob, NewLibrary, UserRole};
+use crate::timeouts::TimedJson;
+use crate::validation;
+use crate::DbConn;
+use crate::features::Feature;
+use super::{FeatureStatus, JobResponse};
+use rocket::{http::Status, State};
+use rocket::serde::json::Json;
+use schemars::JsonSchema;
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use nanoid::nanoid;
+
+/// Checks whether the user is the only administrator who isn't disabled, so the instance would be left without one.
+async fn is_last_admin(conn: &DbConn, user_id: i32) -> Result<bool, Status> {
+  let is_admin = db::users::is_user_admin(conn, user_id).await;
+  let admins = db::users::count_admins(conn).await;
+  if is_admin.is_err() || admins.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(is_admin.unwrap() && admins.unwrap() <= 1)
+}
+
+/// User as seen by administrators of the instance.
+#[derive(Serialize, JsonSchema)]
+pub struct AdminUser {
+  pub username: String,
+  pub email: String,
+  /// Name of the user's organization.
+  pub organization: String,
+  pub role: UserRole,
+  pub disabled: bool,
+}
+
+/// Returns all users of the instance; allowed only to its administrators.
+#[openapi]
+#[get("/admin/users")]
+pub async fn admin_get_users(_admin: Admin, conn: DbConn) -> Result<Json<Vec<AdminUser>>, Status> {
+  let users = db::users::select_users(&conn).await;
+  if users.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(Json(users.unwrap().into_iter().map(|(user, organization)| AdminUser { role: user.role(), username: user.username, email: user.email, organization, disabled: user.disabled }).collect()))
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct UserRoleUpdate {
+  pub role: UserRole,
+}
+
+/// Changes the role of the user; allowed only to administrators of the instance.
+///
+/// Responds with 409 when the last administrator would be demoted.
+#[openapi]
+#[put("/admin/users/<username>/role", data = "<role_update>", format = "json")]
+pub async fn admin_update_user_role(_admin: Admin, conn: DbConn, username: String, role_update: TimedJson<UserRoleUpdate>) -> Result<Status, Status> {
+  let user_id = db::users::get_user_id(&conn, validation::normalize_identifier(&username)).await.map_err(errors::internal)?;
+  if user_id.is_none() { return Err(Status::NotFound) }
+
+  let user_id = user_id.unwrap();
+
+  if role_update.role != UserRole::Admin && is_last_admin(&conn, user_id).await? { return Err(Status::Conflict) }
+
+  let changed_rows = db::users::update_user_role(&conn, user_id, role_update.role).await;
+  if changed_rows.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(Status::Ok)
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct UserDisabledUpdate {
+  pub disabled: bool,
+}
+
+/// Disables or enables the user; allowed only to administrators of the instance.
+///
+/// Disabled users can't sign in and are signed out of all devices.\
+/// Responds with 409 when the last administrator would be disabled.
+#[openapi]
+#[put("/admin/users/<username>/disable", data = "<disabled_update>", format = "json")]
+pub async fn admin_disable_user(_admin: Admin, conn: DbConn, username: String, disabled_update: TimedJson<UserDisabledUpdate>) -> Result<Status, Status> {
+  let user_id = db::users::get_user_id(&conn, validation::normalize_identifier(&username)).await.map_err(errors::internal)?;
+  if user_id.is_none() { return Err(Status::NotFound) }
+
+  let user_id = user_id.unwrap();
+
+  if disabled_update.disabled && is_last_admin(&conn, user_id).await? { return Err(Status::Conflict) }
+
+  let changed_rows = db::users::update_user_disabled(&conn, user_id, disabled_update.disabled).await;
+  if changed_rows.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(Status::Ok)
+}
+
+/// Deletes the user with their albums, folders, media and tokens; allowed only to administrators of the instance.
+///
+/// Files in the gallery aren't deleted.\
+/// Responds with 409 when the last administrator would be deleted.
+#[openapi]
+#[delete("/admin/users/<username>")]
+pub async fn admin_delete_user(_admin: Admin, conn: DbConn, username: String) -> Result<Status, Status> {
+  let user_id = db::users::get_user_id(&conn, validation::normalize_identifier(&username)).await.map_err(errors::internal)?;
+  if user_id.is_none() { return Err(Status::NotFound) }
+
+  let user_id = user_id.unwrap();
+
+  if is_last_admin(&conn, user_id).await? { return Err(Status::Conflict) }
+
+  let deleted = db::users::delete_user(&conn, user_id).await;
+  if deleted.is_err() { return Err(Status::InternalServerError) }
+
+  derivatives::remove_derivatives_of_deleted(&conn, deleted.unwrap()).await;
+
+  Ok(Status::Ok)
+}
+
+/// Number of hours a password reset can be used.
+const PASSWORD_RESET_HOURS: i64 = 24;
+
+#[derive(Serialize, JsonSchema)]
+pub struct PasswordResetResponse {
+  /// Token passed to `POST /user/password/reset`.
+  pub token: String,
+  pub expiration: NaiveDateTime,
+}
+
+/// Issues a one-time token for setting a new password of the user, e.g. when they forgot it;
+/// allowed only to administrators of the instance.
+///
+/// The token is passed to the user by the administrator.
+#[openapi]
+#[post("/admin/users/<username>/password-reset")]
+pub async fn admin_create_password_reset(admin: Admin, conn: DbConn, username: String) -> Result<(Status, Json<PasswordResetResponse>), Status> {
+  let user_id = db::users::get_user_id(&conn, validation::normalize_identifier(&username)).await.map_err(errors::internal)?;
+  if user_id.is_none() { return Err(Status::NotFound) }
+
+  let expiration = Utc::now().naive_utc() + Duration::hours(PASSWORD_RESET_HOURS);
+
+  let token = nanoid!();
+
+  let reset = db::users::insert_password_reset(&conn, &token, user_id.unwrap(), admin.claims.user_id, expiration).await;
+  if reset.is_err() { return Err(Status::InternalServerError) }
+
+  let reset = reset.unwrap();
+
+  Ok((Status::Created, Json(PasswordResetResponse { token, expiration: reset.expiration })))
+}
+
+/// Scans the gallery of any user; allowed only to administrators of the instance.
+///
+/// The scan is recorded as a job of the scanned user and the finished job is returned.
+#[openapi]
+#[post("/admin/users/<username>/scan")]
+pub async fn admin_scan_user(_admin: Admin, conn: DbConn, config: &State<Config>, username: String) -> Result<Json<JobResponse>, Status> {
+  let user_id = db::users::get_user_id(&conn, validation::normalize_identifier(&username)).await.map_err(errors::internal)?;
+  if user_id.is_none() { return Err(Status::NotFound) }
+
+  scan_user(&conn, config, user_id.unwrap(), config.scan_alerts).await.map(Json)
+}
+
+/// Scans the gallery of the user as a new job and returns the finished job.
+async fn scan_user(conn: &DbConn, config: &Config, user_id: i32, alerts: ScanAlertPolicy) -> Result<JobResponse, Status> {
+  let job = db::jobs::insert_job(conn, NewJob::new(user_id, JobKind::Scan)).await;
+  if job.is_err() { return Err(Status::InternalServerError) }
+
+  let mut job = job.unwrap();
+
+  let state = jobs::run_scan(conn, &job, config.scan_symlinks, config.scan_duplicates, alerts, config.gallery_check).await;
+  job.state = state.as_str().to_string();
+
+  Ok(JobResponse::new(job))
+}
+
+/// Scan which found an unusual share of the user's media modified or missing.
+#[derive(Serialize, JsonSchema)]
+pub struct ScanAlertResponse {
+  pub id: i32,
+  pub username: String,
+  pub created_at: NaiveDateTime,
+  /// Media of the user before the scan.
+  pub media: u32,
+  pub modified: u32,
+  pub missing: u32,
+  /// Changes of modified media weren't stored; they are stored by a scan started when the alert is confirmed.
+  pub paused: bool,
+}
+
+/// Returns alerts which weren't confirmed yet, from the newest; allowed only to administrators of the instance.
+#[openapi]
+#[get("/admin/scan-alerts")]
+pub async fn admin_get_scan_alerts(_admin: Admin, conn: DbConn) -> Result<Json<Vec<ScanAlertResponse>>, Status> {
+  let alerts = db::scan::select_unconfirmed_scan_alerts(&conn).await;
+  if alerts.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(Json(alerts.unwrap().into_iter().map(|(alert, username)| ScanAlertResponse {
+    id: alert.id,
+    username,
+    created_at: alert.created_at,
+    media: alert.media,
+    modified: alert.modified,
+    missing: alert.missing,
+    paused: alert.paused,
+  }).collect()))
+}
+
+/// Confirms the alert and all other alerts of its user; allowed only to administrators of the instance.
+///
+/// When the changes were paused, the gallery of the user is scanned again without alerts, so they are stored,
+/// and the finished job is returned.
+#[openapi]
+#[post("/admin/scan-alerts/<scan_alert_id>/confirm")]
+pub async fn admin_confirm_scan_alert(_admin: Admin, conn: DbConn, config: &State<Config>, scan_alert_id: i32) -> Result<Json<Option<JobResponse>>, Status> {
+  let alert = db::scan::select_scan_alert(&conn, scan_alert_id).await;
+  if alert.is_err() { return Err(Status::InternalServerError) }
+
+  let alert = alert.unwrap();
+  if alert.is_none() { return Err(Status::NotFound) }
+
+  let alert = alert.unwrap();
+  if alert.confirmed_at.is_some() { return Err(Status::Conflict) }
+
+  if db::scan::confirm_scan_alerts(&conn, alert.user_id).await.is_err() { return Err(Status::InternalServerError) }
+
+  if !alert.paused { return Ok(Json(None)) }
+
+  scan_user(&conn, config, alert.user_id, ScanAlertPolicy::confirmed()).await.map(|job| Json(Some(job)))
+}
+
+/// Statistics of the whole instance.
+#[derive(Serialize, JsonSchema)]
+pub struct AdminStats {
+  pub server_version: String,
+  pub organizations: i64,
+  pub users: i64,
+  pub media: i64,
+  /// Albums of all users, including smart albums.
+  pub albums: i64,
+  /// Scan alerts which weren't confirmed yet.
+  pub scan_alerts: i64,
+  /// Media which failed their last integrity check, see `GET /admin/integrity`.
+  pub integrity_failures: i64,
+}
+
+/// Returns statistics of the instance; allowed only to its administrators.
+#[openapi]
+#[get("/admin/stats")]
+pub async fn admin_get_stats(_admin: Admin, conn: DbConn) -> Result<Json<AdminStats>, Status> {
+  let organizations = db::organizations::count_organizations(&conn).await;
+  let users = db::users::count_users(&conn).await;
+  let media = db::media::count_media(&conn).await;
+  let albums = db::albums::count_albums(&conn).await;
+  let scan_alerts = db::scan::count_unconfirmed_scan_alerts(&conn).await;
+  let integrity_failures = db::integrity::count_integrity_failures(&conn).await;
+
+  if organizations.is_err() || users.is_err() || media.is_err() || albums.is_err() || scan_alerts.is_err() || integrity_failures.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(Json(AdminStats {
+    server_version: env!("CARGO_PKG_VERSION").to_string(),
+    organizations: organizations.unwrap(),
+    users: users.unwrap(),
+    media: media.unwrap(),
+    albums: albums.unwrap(),
+    scan_alerts: scan_alerts.unwrap(),
+    integrity_failures: integrity_failures.unwrap(),
+  }))
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct IntegrityFailureResponse {
+  pub username: String,
+  pub media_uuid: String,
+  pub status: IntegrityStatus,
+  pub verified_at: DateTime<Utc>,
+  /// Hash of the file when it doesn't match the stored one.
+  pub actual_sha2_512: Option<String>,
+}
+
+/// Returns media of all users which failed the last integrity check, from the newest check;
+/// allowed only to administrators of the instance.
+///
+/// Media are checked periodically when `integrity_check_files` is configured.
+#[openapi]
+#[get("/admin/integrity")]
+pub async fn admin_get_integrity_failures(_admin: Admin, conn: DbConn) -> Result<Json<Vec<IntegrityFailureResponse>>, Status> {
+  let failures = db::integrity::select_all_integrity_failures(&conn).await;
+  if failures.is_err() { return Err(Status::InternalServerError) }
+
+  let result = failures.unwrap().into_iter()
+    .filter_map(|(integrity, media_uuid, username)| Some(IntegrityFailureResponse {
+      username,
+      media_uuid,
+      status: integrity.status.parse().ok()?,
+      verified_at: DateTime::from_utc(integrity.verified_at, Utc),
+      actual_sha2_512: integrity.actual_sha2_512,
+    }))
+    .collect::<Vec<IntegrityFailureResponse>>();
+
+  Ok(Json(result))
+}
+
+/// Library of a user, see `libraries`.
+#[derive(Serialize, JsonSchema)]
+pub struct LibraryResponse {
+  pub uuid: String,
+  /// Name of the subfolder of the user's root folder; `None` when the library replaces the user's directory in the gallery.
+  pub name: Option<String>,
+  pub path: String,
+  /// The directory exists, e.g. the drive is mounted.
+  pub available: bool,
+  pub created_at: NaiveDateTime,
+}
+
+impl LibraryResponse {
+  pub fn new(library: Library) -> Self {
+    LibraryResponse {
+      available: Path::new(&library.path).is_dir(),
+      uuid: library.uuid,
+      name: library.name,
+      path: library.path,
+      created_at: library.created_at,
+    }
+  }
+}
+
+/// Returns libraries of the user; allowed only to administrators of the instance.
+#[openapi]
+#[get("/admin/users/<username>/libraries")]
+pub async fn admin_get_libraries(_admin: Admin, conn: DbConn, username: String) -> Result<Json<Vec<LibraryResponse>>, Status> {
+  let user_id = db::users::get_user_id(&conn, validation::normalize_identifier(&username)).await.map_err(errors::internal)?;
+  if user_id.is_none() { return Err(Status::NotFound) }
+
+  let libraries = db::libraries::select_libraries(&conn, user_id.unwrap()).await;
+  if libraries.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(Json(libraries.unwrap().into_iter().map(LibraryResponse::new).collect()))
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct LibraryInsert {
+  /// Name of the subfolder of the user's root folder; without it, the library replaces the user's directory in the gallery.
+  pub name: Option<String>,
+  /// Absolute path of an existing directory.
+  pub path: String,
+}
+
+/// Adds a library to the user; allowed only to administrators of the instance.
+///
+/// Media of the library are added by the next scan of the user.\
+/// Responds with 422 when the name isn't a valid folder name or the path isn't an existing directory,
+/// and with 409 when the user already has a library with the name.
+#[openapi]
+#[post("/admin/users/<username>/libraries", data = "<library_insert>", format = "json")]
+pub async fn admin_create_library(_admin: Admin, conn: DbConn, username: String, library_insert: TimedJson<LibraryInsert>) -> Result<(Status, Json<LibraryResponse>), Status> {
+  let user_id = db::users::get_user_id(&conn, validation::normalize_identifier(&username)).await.map_err(errors::internal)?;
+  if user_id.is_none() { return Err(Status::NotFound) }
+
+  let user_id = user_id.unwrap();
+  let library_insert = library_insert.into_inner();
+
+  let name = library_insert.name.map(|name| name.trim().to_string());
+  if name.as_deref().map_or(false, |name| !libraries::is_name_valid(name)) { return Err(Status::UnprocessableEntity) }
+  if !libraries::is_path_valid(Path::new(&library_insert.path)) { return Err(Status::UnprocessableEntity) }
+
+  let existing = db::libraries::select_library_by_name(&conn, user_id, name.clone()).await;
+  if existing.is_err() { return Err(Status::InternalServerError) }
+  if existing.unwrap().is_some() { return Err(Status::Conflict) }
+
+  let inserted = db::libraries::insert_library(&conn, NewLibrary::new(user_id, name.clone(), library_insert.path)).await;
+  if inserted.is_err() { return Err(Status::InternalServerError) }
+
+  let library = db::libraries::select_library_by_name(&conn, user_id, name).await;
+  if library.is_err() { return Err(Status::InternalServerError) }
+
+  match library.unwrap() {
+    Some(library) => Ok((Status::Created, Json(LibraryResponse::new(library)))),
+    None => Err(Status::InternalServerError),
+  }
+}
+
+/// Removes a library of the user; allowed only to administrators of the instance.
+///
+/// Files of the library aren't deleted. The next scan of the user reconciles its folders like other folders
+/// whose directories disappeared.
+#[openapi]
+#[delete("/admin/users/<username>/libraries/<library_uuid>")]
+pub async fn admin_delete_library(_admin: Admin, conn: DbConn, username: String, library_uuid: String) -> Result<Status, Status> {
+  let user_id = db::users::get_user_id(&conn, validation::normalize_identifier(&username)).await.map_err(errors::internal)?;
+  if user_id.is_none() { return Err(Status::NotFound) }
+
+  let deleted = db::libraries::delete_library(&conn, user_id.unwrap(), library_uuid).await;
+  if deleted.is_err() { return Err(Status::InternalServerError) }
+  if deleted.unwrap() == 0 { return Err(Status::NotFound) }
+
+  Ok(Status::Ok)
+}
+
+/// Returns experimental features and whether they are enabled for the user; allowed only to administrators of the instance.
+#[openapi]
+#[get("/admin/users/<username>/features")]
+pub async fn admin_get_user_features(_admin: Admin, conn: DbConn, username: String) -> Result<Json<Vec<FeatureStatus>>, Status> {
+  let user_id = db::users::get_user_id(&conn, validation::normalize_identifier(&username)).await.map_err(errors::internal)?;
+  if user_id.is_none() { return Err(Status::NotFound) }
+
+  let enabled = db::users::select_user_features(&conn, user_id.unwrap()).await.map_err(errors::internal)?;
+
+  Ok(Json(FeatureStatus::all(&enabled)))
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct FeatureUpdate {
+  pub enabled: bool,
+}
+
+/// Enables or disables an experimental feature for the user, e.g. `watcher`; allowed only to administrators of the instance.
+///
+/// Features are enabled only by administrators, as some of them load the server, e.g. the watcher.\
+/// Responds with 404 when the user or the feature doesn't exist.
+#[openapi]
+#[put("/admin/users/<username>/features/<feature>", data = "<feature_update>", format = "json")]
+pub async fn admin_update_user_feature(_admin: Admin, conn: DbConn, username: String, feature: String, feature_update: TimedJson<FeatureUpdate>) -> Result<Status, Status> {
+  let feature = feature.parse::<Feature>();
+  if feature.is_err() { return Err(Status::NotFound) }
+
+  let user_id = db::users::get_user_id(&conn, validation::normalize_identifier(&username)).await.map_err(errors::internal)?;
+  if user_id.is_none() { return Err(Status::NotFound) }
+
+  let result = db::users::set_user_feature(&conn, user_id.unwrap(), feature.unwrap(), feature_update.into_inner().enabled).await;
+  if result.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(Status::Ok)
+}
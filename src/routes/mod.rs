@@ -1,784 +1,4174 @@
-use crate::auth::login::{UserLogin, UserInfo, LoginResponse};
-use crate::auth::shared_album_link::{SharedAlbumLinkSecurity, hash_password};
-use crate::auth::token::{Claims, ClaimsEncoded};
+use crate::api_version;
+use crate::auth::login::{LoginClient, UserLogin, UserInfo, LoginResponse};
+use crate::bandwidth::{Bandwidth, BandwidthLimiter, BandwidthStats};
+use crate::banned_passwords::BannedPasswords;
+use crate::auth::permissions::{self, AlbumAction, AlbumRole, MediaAction};
+use crate::auth::password::{self, hash_password};
+use crate::auth::shared_album_link::SharedAlbumLinkSecurity;
+use crate::auth::secret::Secret;
+use crate::auth::signed_url::SignedUrl;
+use crate::auth::token::{Admin, Claims, ClaimsEncoded};
+use crate::config::{AccessDeniedPolicy, Config, HttpSettings};
+use crate::coview::{CoViewEvents, CoViewPosition, CoViewSessions};
 use crate::db::{self, users::get_user_by_id};
+use crate::derivatives;
 use crate::directories::Directories;
-use crate::models::{Album, AlbumShareLink, Folder, Media, NewAlbum, NewAlbumMedia, NewAlbumShareLink, NewUser};
-use crate::scan;
-use crate::schema::media;
+use crate::download::ZipDownload;
+use crate::edit::{self, EditOperation};
+use crate::errors::{self, ApiError};
+use crate::export::{self, ExportDownload, ExportFormat};
+use crate::import::{self, ForeignLibrary, ImportReport, ImportSource};
+use crate::features::Feature;
+use crate::geo::{self, GeoBounds, GeoCluster};
+use crate::jobs;
+use crate::libraries;
+use crate::locale::{self, AcceptLanguage, DateFormat};
+use crate::login_limit::{LoginLimiter, LoginStats, TooManyLogins};
+use crate::management::{Metrics, MetricsAccess};
+use crate::migrations::MigrationReport;
+use crate::panics;
+use crate::models::{Album, Folder, AlbumShareLink, AlbumShareLinkBranding, IntegrityStatus, Job, JobKind, JobState, Media, NewAlbum, NewAlbumInvite, NewAlbumMedia, NewAlbumShareLink, NewJob, NewMediaVersion, NewMediaFace, NewOrganization, NewPerson, NewUser, Organization, OrganizationAdmin, ScanIssue, ScanIssueKind, ScanIssueSeverity, SmartAlbum, UserInvite, UserSetting};
+use crate::scan::{self, FolderReconciliation};
+use crate::scan::filesystem::{Filesystem, LocalFilesystem};
+use crate::stream_limit::{self, MediaStream, StreamLimiter, StreamOwner, TooManyStreams};
+use crate::telemetry::TelemetryReport;
+use crate::timeouts::{self, BodyTimeout, TimedJson};
+use crate::transcode::{self, Playback, TranscodeStatus, Transcoder};
+use crate::upload::{self, UploadRejection};
+use crate::validation::{self, ValidationErrors};
+use crate::write_back::{WriteBackJobs, WriteBackProgress};
 use crate::DbConn;
-use chrono::{NaiveDateTime, Utc};
-use diesel::ExpressionMethods;
-use diesel::OptionalExtension;
-use diesel::QueryDsl;
+use self::pagination::{MediaListing, MediaPage, MediaPagination, MediaSort, SortOrder};
+use chrono::{DateTime, Duration, FixedOffset, NaiveDateTime, Utc};
+use checksums::{hash_file, Algorithm::SHA2512};
+use chrono_tz::Tz;
 use diesel::RunQueryDsl;
-use diesel::Table;
-use rocket::{fs::NamedFile, http::Status};
+use nanoid::nanoid;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use rocket::{data::{Data, Limits, ToByteUnit}, http::{ContentType, Status}, Shutdown, State};
 use serde::{Deserialize, Serialize};
 use schemars::JsonSchema;
 use rocket::serde::json::Json;
 
+pub mod admin;
+pub mod pagination;
+
 #[openapi]
 #[get("/")]
 pub async fn index() -> &'static str {
   "Hello, world!"
 }
 
-/// Creates a new user
-#[openapi]
-#[post("/user", data = "<user>", format = "json")]
-pub async fn create_user(conn: DbConn, user: Json<NewUser>) -> Result<Status, Status> {
-  if !user.check() { return Err(Status::UnprocessableEntity) }
+/// Validates the user and inserts it into the organization; returns the ID of the new user.\
+/// The user becomes an administrator of the organization when it has none, and of the instance when it has none.
+async fn insert_organization_user(conn: &DbConn, config: &Config, banned_passwords: &BannedPasswords, user: NewUser, organization_id: i32) -> Result<i32, ApiError> {
+  let user = user.normalize();
+  user.validate(&config.password_policy, banned_passwords)?;
+
+  if !db::users::is_user_unique(conn, user.clone()).await? { return Err(Status::Conflict.into()); };
 
-  if !db::users::is_user_unique(&conn, user.0.clone()).await { return Err(Status::Conflict); };
+  let new_user = user.hash_password().await;
+  let result = db::users::insert_user(conn, new_user.clone(), organization_id).await?;
+  if result == 0 { return Err(Status::InternalServerError.into()) }
 
-  let new_user = user.into_inner().hash_password();
-  let result = db::users::insert_user(&conn, new_user.clone()).await;
-  if result == 0 { return Err(Status::InternalServerError) }
+  let user_id = db::users::get_user_id(conn, new_user.username.clone()).await?.ok_or(Status::InternalServerError)?;
+
+  if db::organizations::count_organization_admins(conn, organization_id).await? == 0 {
+    db::organizations::insert_organization_admin(conn, OrganizationAdmin { user_id, organization_id }).await?;
+  }
+
+  // the first user of the instance administers it
+  db::users::promote_first_admin(conn).await?;
 
   info!("A new user was created with name {}", new_user.username);
-  Ok(Status::Ok)
+  Ok(user_id)
 }
 
-/// You must provide either a username or an email together with a password.
+/// Creates a new user
+///
+/// Usernames and emails are trimmed, lowercased and NFC normalized.\
+/// Passwords must satisfy the configured `password_policy`.\
+/// Responds with 422 and a list of invalid fields when the data are invalid.\
+/// The user joins the default organization; the first user becomes its administrator.\
+/// With an `invite` token (see `/organization/invites`), the user joins the organization of the invite instead.
+/// When `disable_local_signups` is enabled, the invite is required once the default organization has an administrator.
+/// Responds with 403 when the invite is unknown, expired or used, or when it's required and missing.\
+/// The preferred language of the user is taken from the `Accept-Language` header, see `/user/settings`.
 #[openapi]
-#[post("/login", data = "<user_login>", format = "json")]
-pub async fn login(conn: DbConn, user_login: Json<UserLogin>) -> Result<Json<LoginResponse>, Status> {
-  let token_option = user_login.into_inner().hash_password().login(&conn).await;
-  if token_option.is_none() { return Err(Status::Conflict); }
+#[post("/user?<invite>", data = "<user>", format = "json")]
+pub async fn create_user(conn: DbConn, config: &State<Config>, banned_passwords: &State<BannedPasswords>, accept_language: AcceptLanguage, invite: Option<String>, user: TimedJson<NewUser>) -> Result<Status, ApiError> {
+  if let Some(token) = invite {
+    let user_id = create_invited_user(&conn, config, banned_passwords, token, user.into_inner()).await?;
+    set_signup_locale(&conn, user_id, accept_language).await;
 
-  let token = token_option.unwrap();
+    return Ok(Status::Ok);
+  }
 
-  let user_info = get_user_by_id(&conn, token.user_id).await;
-  if user_info.is_none() { return Err(Status::InternalServerError) }
+  let organization_id = db::organizations::select_default_organization_id(&conn).await?.ok_or(Status::InternalServerError)?;
 
-  let encoded = token.encode();
-  if encoded.is_err() { return Err(Status::InternalServerError) }
+  // the first user can still sign up, so the instance can be set up
+  if config.disable_local_signups && db::organizations::count_organization_admins(&conn, organization_id).await? > 0 {
+    return Err(Status::Forbidden.into());
+  }
 
-  Ok(
-    Json(
-      LoginResponse::new(
-        encoded.unwrap(),
-        UserInfo::from(user_info.unwrap())
-      )
-    )
-  )
+  let user_id = insert_organization_user(&conn, config, banned_passwords, user.into_inner(), organization_id).await?;
+  set_signup_locale(&conn, user_id, accept_language).await;
+
+  Ok(Status::Ok)
 }
 
-/// Refreshes sent token
-// TODO: send token in header instead of body
-// https://stackoverflow.com/a/53881397
-#[openapi]
-#[post("/login/refresh", data = "<encoded_bearer_token>", format = "json")]
-pub async fn refresh_token(conn: DbConn, encoded_bearer_token: Json<ClaimsEncoded>) -> Result<Json<ClaimsEncoded>, Status> {
-  let bearer_token_result = encoded_bearer_token.into_inner();
-  let decoded = bearer_token_result.clone().decode();
-  let bearer_token: Claims;
-
-  // access token is expired - most of the time (token needs to be refreshed because it is expired)
-  if decoded.is_err() {
-    let expired = match decoded.unwrap_err().kind() {
-      jsonwebtoken::errors::ErrorKind::ExpiredSignature => true,
-      _ => false
-    };
+/// Stores the language of the signup request as the preferred language of the new user; failures are only logged.
+async fn set_signup_locale(conn: &DbConn, user_id: i32, accept_language: AcceptLanguage) {
+  let locale = match accept_language.0 {
+    Some(locale) => locale,
+    None => return,
+  };
 
-    // the error is not expired token
-    if !expired { return Err(Status::Unauthorized) }
+  let setting = UserSetting { locale: Some(locale), ..UserSetting::new(user_id) };
+  if let Err(err) = db::users::upsert_user_setting(conn, setting).await {
+    error!("Locale of user {} couldn't be saved: {}", user_id, err);
+  }
+}
 
-    let temp = bearer_token_result.decode_without_validation();
+/// Creates a user using the invite and returns its ID; the invite can't be used again unless creating the user fails.
+async fn create_invited_user(conn: &DbConn, config: &Config, banned_passwords: &BannedPasswords, token: String, user: NewUser) -> Result<i32, ApiError> {
+  let invite = db::organizations::claim_user_invite(conn, token).await?.ok_or(Status::Forbidden)?;
 
-    // couldn't be decoded
-    if temp.is_err() { return Err(Status::Unauthorized) }
+  let user_id = match insert_organization_user(conn, config, banned_passwords, user, invite.organization_id).await {
+    Ok(user_id) => user_id,
+    Err(err) => {
+      if db::organizations::release_user_invite(conn, invite.id).await.is_err() {
+        error!("Invite {} couldn't be released.", invite.id);
+      }
 
+      return Err(err);
+    },
+  };
 
-    bearer_token = temp.unwrap().claims
-  } else {
-    // access token is not yet expired
-    bearer_token = decoded.unwrap().claims;
+  if invite.admin {
+    db::organizations::insert_organization_admin(conn, OrganizationAdmin { user_id, organization_id: invite.organization_id }).await?;
   }
 
-  // refresh token is expired
-  if bearer_token.is_refresh_token_expired(&conn).await { return Err(Status::Unauthorized); }
+  Ok(user_id)
+}
 
-  let new_token = Claims::from_existing(&bearer_token);
+#[derive(Deserialize, JsonSchema)]
+pub struct PasswordChange {
+  pub current_password: String,
+  pub new_password: String,
+}
 
-  let refresh_token_id = db::tokens::select_refresh_token_id(&conn, bearer_token.refresh_token()).await;
-  if refresh_token_id.is_none() { return Err(Status::InternalServerError); }
+/// Changes the password of the authenticated user and signs them out of all other devices.
+///
+/// The new password must satisfy the configured `password_policy`, otherwise the response is 422
+/// with a list of invalid fields. Responds with 403 when the current password is wrong.
+#[openapi]
+#[put("/user/password", data = "<password_change>", format = "json")]
+pub async fn change_password(claims: Claims, conn: DbConn, config: &State<Config>, banned_passwords: &State<BannedPasswords>, password_change: TimedJson<PasswordChange>) -> Result<Status, ApiError> {
+  let password_change = password_change.into_inner();
 
-  Claims::delete_obsolete_access_tokens(&conn, refresh_token_id.unwrap()).await;
+  let user = get_user_by_id(&conn, claims.user_id).await?.ok_or(Status::InternalServerError)?;
+  if !password::verify_password(&password_change.current_password, &user.password).await.is_valid() { return Err(Status::Forbidden.into()) }
 
-  if new_token.add_access_token_to_db(&conn, refresh_token_id.unwrap()).await.is_none() { return Err(Status::InternalServerError); }
+  let mut errors = ValidationErrors::new();
+  validation::validate_password("new_password", &password_change.new_password, &config.password_policy, banned_passwords, &mut errors);
+  errors.into_result()?;
 
-  let new_encoded_token = new_token.encode();
-  if new_encoded_token.is_err() { return Err(Status::InternalServerError); }
+  db::users::update_user_password(&conn, claims.user_id, hash_password(&password_change.new_password).await, Some(claims.refresh_token())).await?;
 
-  Ok(Json(new_encoded_token.unwrap()))
+  Ok(Status::Ok)
 }
 
-#[derive(Serialize, Deserialize, JsonSchema, Queryable)]
-pub struct MediaResponse {
-  pub filename: String,
-  pub owner_id: i32,
-  pub width: u32,
-  pub height: u32,
-  pub description: Option<String>,
-  pub date_taken: NaiveDateTime,
-  pub uuid: String,
+#[derive(Deserialize, JsonSchema)]
+pub struct PasswordResetUse {
+  /// Token issued by an administrator using `POST /admin/users/<username>/password-reset`.
+  pub token: String,
+  pub new_password: String,
 }
 
-impl From<Media> for MediaResponse {
-  fn from(media: Media) -> Self {
-    MediaResponse { filename: media.filename, owner_id: media.owner_id, width: media.width, height: media.height, description: media.description, date_taken: media.date_taken, uuid: media.uuid }
-  }
+/// Sets a new password using a one-time reset token and signs the user out of all devices.
+///
+/// Responds with 403 when the token doesn't exist, expired or was already used,
+/// and with 422 and a list of invalid fields when the new password doesn't satisfy the `password_policy`.
+#[openapi]
+#[post("/user/password/reset", data = "<password_reset>", format = "json")]
+pub async fn reset_password(conn: DbConn, config: &State<Config>, banned_passwords: &State<BannedPasswords>, password_reset: TimedJson<PasswordResetUse>) -> Result<Status, ApiError> {
+  let password_reset = password_reset.into_inner();
+
+  let mut errors = ValidationErrors::new();
+  validation::validate_password("new_password", &password_reset.new_password, &config.password_policy, banned_passwords, &mut errors);
+  errors.into_result()?;
+
+  let user_id = db::users::reset_user_password(&conn, password_reset.token, hash_password(&password_reset.new_password).await).await?;
+  if user_id.is_none() { return Err(Status::Forbidden.into()) }
+
+  Ok(Status::Ok)
 }
 
-impl From<&Media> for MediaResponse {
-  fn from(media: &Media) -> Self {
-    MediaResponse { filename: media.filename.clone(), owner_id: media.owner_id, width: media.width, height: media.height, description: media.description.clone(), date_taken: media.date_taken, uuid: media.uuid.clone() }
-  }
+#[derive(Serialize, JsonSchema)]
+pub struct SessionResponse {
+  /// `None` for sessions started before sessions were recorded; they can't be revoked one by one.
+  uuid: Option<String>,
+  created_at: Option<NaiveDateTime>,
+  /// The session ends unless it's refreshed by then.
+  expiration_time: NaiveDateTime,
+  user_agent: Option<String>,
+  ip_address: Option<String>,
+  /// The session of the request.
+  current: bool,
 }
 
-/// Gets a list of all media
-// FIXME: skips new media in /gallery/username/<medianame>; /gallery/username/<some_folder>/<medianame> works
+/// Lists devices where the authenticated user is logged in, the newest first.
 #[openapi]
-#[get("/media")]
-pub async fn media_structure(claims: Claims, conn: DbConn) -> Json<Vec<MediaResponse>> {
-  error!("user_id: {}", claims.user_id);
+#[get("/user/sessions")]
+pub async fn get_user_sessions(claims: Claims, conn: DbConn) -> Result<Json<Vec<SessionResponse>>, Status> {
+  let sessions = db::tokens::select_user_sessions(&conn, claims.user_id).await;
+  if sessions.is_err() { return Err(Status::InternalServerError) }
+
+  let current = claims.refresh_token();
+
+  Ok(Json(sessions.unwrap().into_iter().map(|session| SessionResponse {
+    current: session.refresh_token == current,
+    uuid: session.uuid,
+    created_at: session.created_at,
+    expiration_time: session.expiration_time,
+    user_agent: session.user_agent,
+    ip_address: session.ip_address,
+  }).collect()))
+}
 
-  let structure = db::media::get_media_structure(&conn, claims.user_id).await;
+/// Signs the authenticated user out of the session; its tokens stop working immediately.
+///
+/// The current session can be revoked too, which logs the user out.
+#[openapi]
+#[delete("/user/sessions/<session_uuid>")]
+pub async fn delete_user_session(claims: Claims, conn: DbConn, session_uuid: String) -> Result<Status, Status> {
+  let deleted = db::tokens::delete_user_session(&conn, claims.user_id, session_uuid).await;
+  if deleted.is_err() { return Err(Status::InternalServerError) }
 
-  Json(structure)
-}
+  if deleted.unwrap() == 0 { return Err(Status::NotFound) }
 
-#[derive(Serialize, Deserialize, JsonSchema, Queryable)]
-pub struct AlbumInsertData {
-  pub name: String,
-  pub description: Option<String>,
+  Ok(Status::Ok)
 }
 
-#[derive(Serialize, Deserialize, JsonSchema, Queryable)]
-pub struct AlbumResponse {
-  pub owner_id: i32,
-  pub name: String,
-  pub description: Option<String>,
-  pub created_at: NaiveDateTime,
-  pub thumbnail_link: Option<String>,
-  pub link: String
+#[derive(Serialize, JsonSchema)]
+pub struct OrganizationResponse {
+  uuid: String,
+  name: String,
+  created_at: NaiveDateTime,
+  /// The authenticated user administers the organization.
+  admin: bool,
 }
 
-impl From<Album> for AlbumResponse {
-  fn from(album: Album) -> Self {
-    AlbumResponse { owner_id: album.owner_id, name: album.name, description: album.description, created_at: album.created_at, thumbnail_link: album.thumbnail_link, link: album.link }
+impl OrganizationResponse {
+  fn new(organization: Organization, admin: bool) -> Self {
+    Self {
+      uuid: organization.uuid,
+      name: organization.name,
+      created_at: organization.created_at,
+      admin,
+    }
   }
 }
 
-impl From<&Album> for AlbumResponse {
-  fn from(album: &Album) -> Self {
-    AlbumResponse { owner_id: album.owner_id, name: album.name.clone(), description: album.description.clone(), created_at: album.created_at, thumbnail_link: album.thumbnail_link.clone(), link: album.link.clone() }
-  }
+#[derive(Deserialize, JsonSchema)]
+pub struct OrganizationUpdate {
+  pub name: String,
 }
 
-impl From<NewAlbum> for AlbumResponse {
-  fn from(album: NewAlbum) -> Self {
-    AlbumResponse { owner_id: album.owner_id, name: album.name, description: album.description, created_at: album.created_at, thumbnail_link: None, link: album.link }
+/// Trims the name of an organization and checks its length.
+fn validate_organization_name(name: &str) -> Result<String, ValidationErrors> {
+  let name = name.trim().to_string();
+  let mut errors = ValidationErrors::new();
+
+  if name.is_empty() {
+    errors.add("name", validation::InvalidReason::TooShort { min: 1 });
+  } else if name.chars().count() > ORGANIZATION_NAME_MAX_LENGTH {
+    errors.add("name", validation::InvalidReason::TooLong { max: ORGANIZATION_NAME_MAX_LENGTH });
   }
+
+  errors.into_result()?;
+  Ok(name)
 }
 
-/// Creates a new album
+/// Maximum length of organization names.
+const ORGANIZATION_NAME_MAX_LENGTH: usize = 255;
+
+/// Returns the organization of the authenticated user.
+///
+/// Users, their media and albums belong to a single organization; they can't be shared with users of other organizations.
 #[openapi]
-#[post("/album", data = "<album_insert_data>", format = "json")]
-pub async fn create_album(claims: Claims, conn: DbConn, album_insert_data: Json<AlbumInsertData>) -> Json<Option<AlbumResponse>> {
-  db::albums::insert_album(&conn, claims.user_id, album_insert_data.into_inner()).await;
+#[get("/organization")]
+pub async fn get_organization(claims: Claims, conn: DbConn) -> Result<Json<OrganizationResponse>, Status> {
+  let organization = db::organizations::select_user_organization(&conn, claims.user_id).await;
+  if organization.is_err() { return Err(Status::InternalServerError) }
 
-  let last_insert_id = db::general::get_last_insert_id(&conn).await;
+  let admin = db::organizations::is_organization_admin(&conn, claims.user_id).await;
+  if admin.is_err() { return Err(Status::InternalServerError) }
 
-  if last_insert_id.is_none() {
-    error!("Last insert id was not returned. This may happen if restarting MySQL during scanning.");
-    return Json(None);
-  }
+  Ok(Json(OrganizationResponse::new(organization.unwrap(), admin.unwrap())))
+}
 
-  let accessible = db::albums::user_has_album_access(&conn, claims.user_id, last_insert_id.unwrap()).await;
-  if accessible.is_err() || !accessible.unwrap() { return Json(None); }
+/// Renames the organization; allowed only to its administrators.
+#[openapi]
+#[put("/organization", data = "<organization_update>", format = "json")]
+pub async fn update_organization(claims: Claims, conn: DbConn, organization_update: TimedJson<OrganizationUpdate>) -> Result<Status, ApiError> {
+  let organization_id = permissions::authorize_organization_admin(&conn, claims.user_id).await?;
 
-  // TODO: impl from u jiné struktury bez ID a hesla
-  let album = db::albums::select_album(&conn, last_insert_id.unwrap()).await;
-  if album.is_none() { return Json(None); }
+  let name = validate_organization_name(&organization_update.into_inner().name)?;
 
-  Json(Some(AlbumResponse::from(album.unwrap())))
+  db::organizations::update_organization_name(&conn, organization_id, name).await?;
+
+  Ok(Status::Ok)
 }
 
 #[derive(Deserialize, JsonSchema)]
-pub struct AlbumAddMedia {
-  album_uuid: String,
-  media_uuid: String,
+pub struct OrganizationInsert {
+  pub name: String,
+  /// The first administrator of the organization.
+  pub admin: NewUser,
 }
 
-/// Adds media to an album
+/// Creates a new organization with its first administrator.
+///
+/// Allowed only to administrators of the instance.\
+/// Responds with 422 and a list of invalid fields when the data are invalid.
 #[openapi]
-#[post("/album/media", data = "<list_of_media>", format = "json")]
-pub async fn album_add_media(claims: Claims, conn: DbConn, list_of_media: Json<Vec<AlbumAddMedia>>) -> Result<(), Status> {
-  let mut transformed = vec![];
+#[post("/organization", data = "<organization_insert>", format = "json")]
+pub async fn create_organization(_admin: Admin, conn: DbConn, config: &State<Config>, banned_passwords: &State<BannedPasswords>, organization_insert: TimedJson<OrganizationInsert>) -> Result<Json<OrganizationResponse>, ApiError> {
+  let organization_insert = organization_insert.into_inner();
+  let name = validate_organization_name(&organization_insert.name)?;
 
-  // TODO: optimise this so it doesn't check the same data multiple times
-  for new in list_of_media.into_inner() {
-    let album_id = db::albums::select_album_id(&conn, new.album_uuid).await;
-    if album_id.is_none() { continue; }
+  let admin = organization_insert.admin.normalize();
+  admin.validate(&config.password_policy, banned_passwords)?;
 
-    let album_access = db::albums::user_has_album_access(&conn, claims.user_id, album_id.unwrap()).await;
-    if album_access.is_err() { return Err(Status::InternalServerError) };
-    if !album_access.unwrap() { return Err(Status::Forbidden) }
+  if !db::users::is_user_unique(&conn, admin.clone()).await? { return Err(Status::Conflict.into()); };
 
-    let media_access = db::media::media_user_has_access(&conn, new.media_uuid.clone(), claims.user_id).await;
-    if media_access.is_err() { return Err(Status::InternalServerError) };
-    if !media_access.unwrap() { return Err(Status::Forbidden) }
+  let organization = db::organizations::insert_organization(&conn, NewOrganization::new(name)).await?;
 
-    let media_id = db::media::select_media_id(&conn, new.media_uuid).await;
-    if media_id.is_none() { continue; }
+  insert_organization_user(&conn, config, banned_passwords, admin, organization.id).await?;
 
-    // skip media that is already present in the album
-    let has_media = db::albums::album_already_has_media(&conn, album_id.unwrap(), media_id.unwrap()).await;
-    if has_media.is_err() { return Err(Status::InternalServerError) };
+  info!("A new organization was created with name {}", organization.name);
+  Ok(Json(OrganizationResponse::new(organization, false)))
+}
 
-    if has_media.unwrap() { continue; }
+#[derive(Serialize, JsonSchema)]
+pub struct OrganizationUser {
+  username: String,
+  email: String,
+  admin: bool,
+}
 
-    transformed.push(NewAlbumMedia {
-      album_id: album_id.unwrap(),
-      media_id: media_id.unwrap()
-    })
-  }
+/// Lists users of the organization; allowed only to its administrators.
+#[openapi]
+#[get("/organization/users")]
+pub async fn get_organization_users(claims: Claims, conn: DbConn) -> Result<Json<Vec<OrganizationUser>>, Status> {
+  let organization_id = permissions::authorize_organization_admin(&conn, claims.user_id).await?;
 
-  let r = db::albums::album_add_media(&conn, transformed).await;
-  if r.is_none() {
-    return Err(Status::InternalServerError);
-  }
+  let users = db::organizations::select_organization_users(&conn, organization_id).await;
+  if users.is_err() { return Err(Status::InternalServerError) }
 
-  Ok(())
+  Ok(Json(users.unwrap().into_iter().map(|(user, admin)| OrganizationUser { username: user.username, email: user.email, admin }).collect()))
 }
 
-/// Retrieves a list of albums of an authenticated user
+/// Creates a new user in the organization; allowed only to its administrators.
+///
+/// The data are validated the same way as in `POST /user`.
 #[openapi]
-#[get("/album")]
-pub async fn get_album_list(claims: Claims, conn: DbConn) -> Json<Vec<AlbumResponse>> {
-  let albums = db::albums::get_album_list(&conn, claims.user_id).await;
+#[post("/organization/users", data = "<user>", format = "json")]
+pub async fn create_organization_user(claims: Claims, conn: DbConn, config: &State<Config>, banned_passwords: &State<BannedPasswords>, user: TimedJson<NewUser>) -> Result<Status, ApiError> {
+  let organization_id = permissions::authorize_organization_admin(&conn, claims.user_id).await?;
 
-  let result = albums.iter()
-    .map(AlbumResponse::from)
-    .collect::<Vec<AlbumResponse>>();
+  insert_organization_user(&conn, config, banned_passwords, user.into_inner(), organization_id).await?;
 
-  Json(result)
+  Ok(Status::Created)
 }
 
-#[derive(Serialize, Deserialize, JsonSchema, Queryable)]
-pub struct AlbumUpdateData {
-  pub name: Option<String>,
-  pub description: Option<String>,
+/// Maximum number of users imported at once.
+const MAX_IMPORTED_USERS: usize = 1000;
+
+/// Format of imported users.
+#[derive(FromFormField, JsonSchema, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UserImportFormat {
+  /// Array of objects with the fields of `UserImportRow`.
+  #[field(value = "json")]
+  Json,
+  /// Comma separated values with a header row naming the columns (`username,email,password`).
+  #[field(value = "csv")]
+  Csv,
 }
 
-// TODO: rewrite later and use forwarding (ranks)
-// problem seems to be in okapi as it overwrites the route when there are multiple ranks
-// while the Request guards are wrapped in Option, there are no error codes from that Request guards
-/// Gets a list of media in an album
-#[openapi]
-#[get("/album/<album_uuid>/media")]
-pub async fn get_album_structure(shared_album_link_security: Option<SharedAlbumLinkSecurity>, claims_option: Option<Claims>, conn: DbConn, album_uuid: String) -> Result<Json<Vec<MediaResponse>>, Status> {
-  let album_id_option = db::albums::select_album_id(&conn, album_uuid).await;
-  if album_id_option.is_none() {
-    return Err(Status::NotFound);
-  }
+/// User to be imported.
+#[derive(Deserialize, JsonSchema)]
+pub struct UserImportRow {
+  pub username: String,
+  pub email: String,
+  /// Random password satisfying the password policy is generated when it's missing.
+  #[serde(default)]
+  pub password: Option<String>,
+}
 
-  let album_option = db::albums::select_album(&conn, album_id_option.unwrap()).await;
-  if album_option.is_none() {
-    return Err(Status::NotFound);
+/// Result of one imported row.
+#[derive(Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UserImportStatus {
+  Created,
+  /// The row is valid, but nothing was created as other rows are not.
+  Valid,
+  /// See `errors`.
+  Invalid,
+  /// The username or the email is already used, or it's repeated in the import.
+  Conflict,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct UserImportResult {
+  /// Index of the row starting with 1; header rows aren't counted.
+  pub row: usize,
+  /// Normalized username.
+  pub username: String,
+  pub status: UserImportStatus,
+  pub errors: Vec<validation::FieldError>,
+  /// Password generated for rows without one; it's shown only once.
+  pub generated_password: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct UserImportReport {
+  /// Number of created users; either all users are created or none.
+  pub created: usize,
+  pub rows: Vec<UserImportResult>,
+}
+
+/// Parses imported users; returns `None` when the data are malformed.
+fn parse_user_import(data: &str, format: UserImportFormat) -> Option<Vec<UserImportRow>> {
+  match format {
+    UserImportFormat::Json => serde_json::from_str(data).ok(),
+    UserImportFormat::Csv => csv::ReaderBuilder::new()
+      .trim(csv::Trim::All)
+      .from_reader(data.as_bytes())
+      .deserialize()
+      .collect::<Result<Vec<UserImportRow>, csv::Error>>()
+      .ok(),
   }
+}
 
-  let album = album_option.unwrap();
+/// Imports users into the organization; allowed only to its administrators.
+///
+/// The body is a JSON array (`format=json`, the default) or CSV with a header row (`format=csv`) of at most 1000 users.
+/// Rows are validated the same way as in `POST /user`; rows without a password get a generated one,
+/// which is returned in the report, so the administrator can hand it over.\
+/// Users are created in one transaction: when any row is invalid or conflicts, no user is created
+/// and the response is 422 with the report of every row. Responds with 400 when the body is malformed
+/// and with 408 when it isn't received in time.
+#[openapi]
+#[post("/organization/users/import?<format>", data = "<data>")]
+pub async fn import_organization_users(claims: Claims, conn: DbConn, config: &State<Config>, banned_passwords: &State<BannedPasswords>, body_timeout: BodyTimeout, format: Option<UserImportFormat>, data: Data<'_>) -> Result<(Status, Json<UserImportReport>), ApiError> {
+  let organization_id = permissions::authorize_organization_admin(&conn, claims.user_id).await?;
+
+  let data = body_timeout.read(data.open(1.mebibytes()).into_string()).await?.map_err(|_| Status::BadRequest)?;
+  if !data.is_complete() { return Err(Status::PayloadTooLarge.into()) }
+
+  let rows = parse_user_import(&data, format.unwrap_or(UserImportFormat::Json)).ok_or(Status::BadRequest)?;
+  if rows.len() > MAX_IMPORTED_USERS { return Err(Status::PayloadTooLarge.into()) }
+
+  let mut results = vec![];
+  let mut new_users = vec![];
+  let mut identifiers = HashSet::new();
+
+  for (index, row) in rows.into_iter().enumerate() {
+    let generated_password = if row.password.as_deref().map_or(true, str::is_empty) { Some(config.password_policy.generate_password()) } else { None };
+    let password = generated_password.clone().or(row.password).unwrap_or_default();
+
+    let user = NewUser::new(row.username, row.email, password).normalize();
+    let mut result = UserImportResult { row: index + 1, username: user.username.clone(), status: UserImportStatus::Valid, errors: vec![], generated_password };
+
+    if let Err(errors) = user.validate(&config.password_policy, banned_passwords) {
+      result.status = UserImportStatus::Invalid;
+      result.errors = errors.errors;
+    } else if !identifiers.insert(user.username.clone()) || !identifiers.insert(user.email.clone()) || !db::users::is_user_unique(&conn, user.clone()).await? {
+      result.status = UserImportStatus::Conflict;
+    } else {
+      new_users.push(user);
+    }
+
+    results.push(result);
+  }
 
-  if claims_option.is_some() {
-    if album.owner_id != claims_option.unwrap().user_id {
-      return Err(Status::Unauthorized);
+  if results.iter().any(|result| result.status != UserImportStatus::Valid) {
+    for result in &mut results {
+      result.generated_password = None;
     }
 
-    // let accessible = db::albums::user_has_album_access(&conn, claims.user_id, album_id).await;
-    // if accessible.is_err() { return Err(Status::InternalServerError) }
+    return Ok((Status::UnprocessableEntity, Json(UserImportReport { created: 0, rows: results })));
+  }
 
-    // if !accessible.unwrap() {
-    //   return Err(Status::Forbidden);
-    // }
+  // hashing takes tens of milliseconds per password, so it's done only when every row is valid
+  let mut hashed_users = Vec::with_capacity(new_users.len());
+  for user in new_users {
+    hashed_users.push(user.hash_password().await);
+  }
 
-    // TODO: check if non-owner user has permission to access the album (preparation for shared albums)
+  let new_users = hashed_users;
+  let created = new_users.len();
+  db::organizations::insert_organization_users(&conn, new_users, organization_id).await?;
 
-  } else if shared_album_link_security.is_some() {
-    // TODO: maybe check more things
-  } else {
-    return Err(Status::Unauthorized);
+  for result in &mut results {
+    result.status = UserImportStatus::Created;
   }
 
-  let structure = db::albums::get_album_media(&conn, album.id).await;
+  info!("{} users were imported into organization {}", created, organization_id);
+  Ok((Status::Created, Json(UserImportReport { created, rows: results })))
+}
 
-  if structure.is_err() { return Err(Status::InternalServerError) }
+#[derive(Deserialize, JsonSchema)]
+pub struct UserInviteInsert {
+  /// Time after which the invite can't be used; 7 days by default.
+  pub expires_in: Option<ExpiresIn>,
+  /// The new user becomes an administrator of the organization.
+  #[serde(default)]
+  pub admin: bool,
+}
 
-  let result = structure.unwrap().iter()
-    .map(MediaResponse::from)
-    .collect::<Vec<MediaResponse>>();
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct UserInviteResponse {
+  /// ID used for revoking the invite.
+  pub id: i32,
+  /// Token passed to `POST /user?invite=<token>`; only hashes of tokens are stored, so it's returned only
+  /// when the invite is created.
+  pub token: Option<String>,
+  pub created_at: NaiveDateTime,
+  pub expiration: NaiveDateTime,
+  pub admin: bool,
+}
 
-  Ok(Json(result))
+impl From<UserInvite> for UserInviteResponse {
+  fn from(invite: UserInvite) -> Self {
+    UserInviteResponse { id: invite.id, token: None, created_at: invite.created_at, expiration: invite.expiration, admin: invite.admin }
+  }
 }
 
-/// Updates already existing album
+/// Creates a one-time invite into the organization; allowed only to its administrators.
+///
+/// The token of the invite is returned only in this response.
+/// Responds with 422 when `expires_in` is malformed.
 #[openapi]
-#[put("/album/<album_uuid>", data = "<album_update_data>", format = "json")]
-pub async fn update_album(claims: Claims, conn: DbConn, album_uuid: String, album_update_data: Json<AlbumUpdateData>) -> Result<Status, Status> {
-  if album_update_data.name.is_none() && album_update_data.description.is_none() {
-    return Err(Status::UnprocessableEntity);
-  }
+#[post("/organization/invites", data = "<invite_insert>", format = "json")]
+pub async fn create_user_invite(claims: Claims, conn: DbConn, invite_insert: TimedJson<UserInviteInsert>) -> Result<(Status, Json<UserInviteResponse>), Status> {
+  let organization_id = permissions::authorize_organization_admin(&conn, claims.user_id).await?;
 
-  let album_id_option = db::albums::select_album_id(&conn, album_uuid).await;
-  if album_id_option.is_none() {
-    return Err(Status::NotFound);
-  }
+  let expires_in = match &invite_insert.expires_in {
+    Some(expires_in) => expires_in.duration(),
+    None => Some(Duration::days(7)),
+  };
 
-  let album_id = album_id_option.unwrap();
+  let expiration = expires_in.and_then(|expires_in| Utc::now().naive_utc().checked_add_signed(expires_in));
+  if expiration.is_none() { return Err(Status::UnprocessableEntity) }
 
-  let accessible = db::albums::user_has_album_access(&conn, claims.user_id, album_id).await;
-  if accessible.is_err() { return Err(Status::InternalServerError) }
+  let token = nanoid!();
 
-  if !accessible.unwrap() {
-    return Err(Status::Forbidden);
-  }
+  let invite = db::organizations::insert_user_invite(&conn, &token, organization_id, claims.user_id, expiration.unwrap(), invite_insert.admin).await;
+  if invite.is_err() { return Err(Status::InternalServerError) }
 
-  let changed_rows = db::albums::update_album(&conn, album_id, album_update_data.into_inner()).await;
-  error!("changed: {:?}", changed_rows);
-  if changed_rows.is_none() { return Err(Status::InternalServerError) }
+  Ok((Status::Created, Json(UserInviteResponse { token: Some(token), ..UserInviteResponse::from(invite.unwrap()) })))
+}
 
-  if changed_rows.unwrap() == 0 {
-    return Ok(Status::NoContent);
-  }
+/// Returns invites of the organization which can still be used; allowed only to its administrators.
+#[openapi]
+#[get("/organization/invites")]
+pub async fn get_user_invites(claims: Claims, conn: DbConn) -> Result<Json<Vec<UserInviteResponse>>, Status> {
+  let organization_id = permissions::authorize_organization_admin(&conn, claims.user_id).await?;
+
+  let invites = db::organizations::select_organization_invites(&conn, organization_id).await;
+  if invites.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(Json(invites.unwrap().into_iter().map(UserInviteResponse::from).collect()))
+}
+
+/// Revokes the invite; allowed only to administrators of its organization.
+#[openapi]
+#[delete("/organization/invites/<invite_id>")]
+pub async fn delete_user_invite(claims: Claims, conn: DbConn, invite_id: i32) -> Result<Status, Status> {
+  let organization_id = permissions::authorize_organization_admin(&conn, claims.user_id).await?;
+
+  let deleted = db::organizations::delete_user_invite(&conn, organization_id, invite_id).await;
+  if deleted.is_err() { return Err(Status::InternalServerError) }
+
+  if deleted.unwrap() == 0 { return Err(Status::NotFound) }
 
   Ok(Status::Ok)
 }
 
-/// Deletes an album
+/// Returns the ID of the user if they belong to the organization.
+async fn select_organization_user_id(conn: &DbConn, organization_id: i32, username: String) -> Result<i32, Status> {
+  let user_id = db::users::get_user_id(conn, username).await.map_err(errors::internal)?;
+  if user_id.is_none() { return Err(Status::NotFound) }
+
+  let user = get_user_by_id(conn, user_id.unwrap()).await.map_err(errors::internal)?;
+  if user.is_none() { return Err(Status::NotFound) }
+
+  let user = user.unwrap();
+
+  // users of other organizations are hidden
+  if user.organization_id != organization_id { return Err(Status::NotFound) }
+
+  Ok(user.id)
+}
+
+/// Makes the user an administrator of the organization; allowed only to its administrators.
 #[openapi]
-#[delete("/album/<album_uuid>")]
-pub async fn delete_album(claims: Claims, conn: DbConn, album_uuid: String) -> Result<Status, Status> {
-  let album_id_option = db::albums::select_album_id(&conn, album_uuid).await;
-  if album_id_option.is_none() {
-    return Err(Status::NotFound);
-  }
+#[put("/organization/users/<username>/admin")]
+pub async fn add_organization_admin(claims: Claims, conn: DbConn, username: String) -> Result<Status, Status> {
+  let organization_id = permissions::authorize_organization_admin(&conn, claims.user_id).await?;
+  let user_id = select_organization_user_id(&conn, organization_id, username).await?;
 
-  let album_id = album_id_option.unwrap();
+  let changed_rows = db::organizations::insert_organization_admin(&conn, OrganizationAdmin { user_id, organization_id }).await;
+  if changed_rows.is_err() { return Err(Status::InternalServerError) }
 
-  let album = db::albums::select_album(&conn, album_id).await;
+  Ok(Status::Ok)
+}
 
-  if album.is_none() { return Err(Status::NotFound); }
+/// Revokes administration of the organization from the user; allowed only to its administrators.
+///
+/// Responds with 409 when the user is its last administrator.
+#[openapi]
+#[delete("/organization/users/<username>/admin")]
+pub async fn delete_organization_admin(claims: Claims, conn: DbConn, username: String) -> Result<Status, Status> {
+  let organization_id = permissions::authorize_organization_admin(&conn, claims.user_id).await?;
+  let user_id = select_organization_user_id(&conn, organization_id, username).await?;
 
-  let accessible = db::albums::user_has_album_access(&conn, claims.user_id, album_id).await;
-  if accessible.is_err() { return Err(Status::InternalServerError) }
+  let is_admin = db::organizations::is_organization_admin(&conn, user_id).await;
+  if is_admin.is_err() { return Err(Status::InternalServerError) }
 
-  if !accessible.unwrap() {
-    return Err(Status::Forbidden);
-  }
+  if !is_admin.unwrap() { return Ok(Status::Ok) }
 
-  let deleted = db::albums::delete_album(&conn, album_id).await;
-  if deleted.is_err() { return Err(Status::ImATeapot) }
+  let admins = db::organizations::count_organization_admins(&conn, organization_id).await;
+  if admins.is_err() { return Err(Status::InternalServerError) }
+
+  if admins.unwrap() <= 1 { return Err(Status::Conflict) }
+
+  let changed_rows = db::organizations::delete_organization_admin(&conn, user_id).await;
+  if changed_rows.is_err() { return Err(Status::InternalServerError) }
 
   Ok(Status::Ok)
 }
 
-#[derive(Serialize, Deserialize, JsonSchema)]
-pub struct AlbumShareLinkInsert {
-  pub expiration: Option<NaiveDateTime>,
-  pub password: Option<String>,
+/// Next step suggested to a new user.
+#[derive(Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OnboardingHint {
+  /// The user has no media; they can be uploaded or copied to the gallery folder and scanned.
+  UploadMedia,
+  /// The user hasn't liked any media, so the Favorites album is empty.
+  LikeMedia,
+  /// The user has no albums except smart ones.
+  CreateAlbum,
 }
 
-impl AlbumShareLinkInsert {
-  // Normalizes passwords and hashes them if they are not None
-  pub fn normalize_and_hash_password(self) -> Self {
-    if self.password.is_none() { return self }
+#[derive(Serialize, JsonSchema)]
+pub struct OnboardingResponse {
+  /// UUID of the Favorites smart album.
+  pub favorites_album_uuid: String,
+  /// UUID of the sample media when it was copied by this request.
+  pub sample_media_uuid: Option<String>,
+  /// Suggested next steps, in the order they should be done.
+  pub hints: Vec<OnboardingHint>,
+}
 
-    let password = self.password.unwrap();
+/// Prepares the account of the authenticated user; meant to be called right after the first login.
+///
+/// Creates the gallery folder and the Favorites smart album. When `sample` is true and `sample_media` is configured,
+/// the sample image is copied to the gallery folder too.\
+/// Calling it again is safe, nothing is created twice, so it can also be used to get current hints.
+#[openapi]
+#[post("/user/onboarding?<sample>")]
+pub async fn onboard_user(claims: Claims, conn: DbConn, config: &State<Config>, sample: Option<bool>) -> Result<Json<OnboardingResponse>, Status> {
+  let username = db::users::get_user_username(&conn, claims.user_id).await.map_err(errors::internal)?;
+  if username.is_none() { return Err(Status::InternalServerError) }
 
-    let hashed_password = match password.len() {
-      0 => None,
-      _ => Some(hash_password(password))
-    };
+  let username = username.unwrap();
 
-    Self {
-      expiration: self.expiration,
-      password: hashed_password,
+  let gallery = Directories::new().and_then(|directories| directories.gallery());
+  if gallery.is_none() { return Err(Status::InternalServerError) }
+
+  let user_directory = libraries::user_directory(&conn, &gallery.unwrap(), claims.user_id, &username).await;
+  if user_directory.is_none() { return Err(Status::InternalServerError) }
+
+  let user_directory = user_directory.unwrap();
+
+  let root_folder = select_or_insert_root_folder(&conn, claims.user_id, &username).await;
+  if root_folder.is_none() { return Err(Status::InternalServerError) }
+
+  let root_folder = root_folder.unwrap();
+
+  let mut sample_media_uuid = None;
+  if let (Some(true), Some(sample_media)) = (sample, &config.sample_media) {
+    let filename = sample_media.file_name().and_then(|filename| filename.to_str());
+    if filename.is_none() { return Err(Status::InternalServerError) }
+
+    let filename = filename.unwrap().to_string();
+    let path = user_directory.join(&filename);
+
+    if !path.exists() {
+      if let Err(err) = rocket::tokio::fs::copy(sample_media, &path).await {
+        error!("Sample media {:?} couldn't be copied: {}", sample_media, err);
+        return Err(Status::InternalServerError);
+      }
+
+      let image_dimensions = image::image_dimensions(&path);
+      if image_dimensions.is_err() { return Err(Status::InternalServerError) }
+
+      sample_media_uuid = Some(db::media::insert_media(&conn, filename, root_folder, claims.user_id, image_dimensions.unwrap(), None, path).await.map_err(errors::internal)?);
     }
   }
+
+  let mut favorites = db::albums::select_smart_album(&conn, claims.user_id, SmartAlbum::Favorites).await;
+  if let Ok(None) = favorites {
+    if db::albums::insert_smart_album(&conn, claims.user_id, SmartAlbum::Favorites).await.is_err() { return Err(Status::InternalServerError) }
+
+    favorites = db::albums::select_smart_album(&conn, claims.user_id, SmartAlbum::Favorites).await;
+  }
+
+  let favorites = favorites.ok().flatten();
+  if favorites.is_none() { return Err(Status::InternalServerError) }
+
+  let first_page = MediaPagination { limit: Some(1), ..Default::default() };
+
+  let media = db::media::get_media_structure(&conn, claims.user_id, None, first_page.clone()).await;
+  let liked = db::media::get_liked_media(&conn, claims.user_id, first_page).await;
+  if media.is_err() || liked.is_err() { return Err(Status::InternalServerError) }
+
+  let albums = db::albums::get_album_list(&conn, claims.user_id).await.map_err(errors::internal)?;
+
+  let mut hints = vec![];
+  if media.unwrap().is_empty() { hints.push(OnboardingHint::UploadMedia) }
+  if liked.unwrap().is_empty() { hints.push(OnboardingHint::LikeMedia) }
+  if albums.iter().all(|album| album.smart.is_some()) { hints.push(OnboardingHint::CreateAlbum) }
+
+  Ok(Json(OnboardingResponse { favorites_album_uuid: favorites.unwrap().link, sample_media_uuid, hints }))
+}
+
+/// Settings of a user.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct UserSettings {
+  /// Default IANA time zone (e.g. `Europe/Prague`) of media without a known UTC offset.
+  pub timezone: Option<String>,
+  /// Preferred language as a BCP 47 tag (e.g. `cs-CZ`); taken from the `Accept-Language` header at signup.
+  pub locale: Option<String>,
+  pub date_format: Option<DateFormat>,
 }
 
-#[derive(Serialize, Deserialize, JsonSchema)]
-pub struct SharedAlbumLinkResponse {
-  uuid: String,
-  expiration: Option<NaiveDateTime>,
-}
+impl From<UserSetting> for UserSettings {
+  fn from(setting: UserSetting) -> Self {
+    let date_format = setting.date_format();
+    UserSettings { timezone: setting.timezone, locale: setting.locale, date_format }
+  }
+}
+
+/// Returns settings of the authenticated user.
+#[openapi]
+#[get("/user/settings")]
+pub async fn get_user_settings(claims: Claims, conn: DbConn) -> Result<Json<UserSettings>, Status> {
+  let setting = db::users::select_user_setting(&conn, claims.user_id).await;
+  if setting.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(Json(UserSettings::from(setting.unwrap())))
+}
+
+/// Updates settings of the authenticated user.
+///
+/// Responds with 422 when the time zone or the locale is invalid.
+#[openapi]
+#[put("/user/settings", data = "<settings>", format = "json")]
+pub async fn update_user_settings(claims: Claims, conn: DbConn, settings: TimedJson<UserSettings>) -> Result<Status, Status> {
+  let settings = settings.into_inner();
+
+  if let Some(timezone) = &settings.timezone {
+    if timezone.parse::<Tz>().is_err() { return Err(Status::UnprocessableEntity) }
+  }
+
+  let locale = match &settings.locale {
+    Some(locale) => Some(locale::normalize_locale(locale).ok_or(Status::UnprocessableEntity)?),
+    None => None,
+  };
+
+  let setting_result = db::users::select_user_setting(&conn, claims.user_id).await;
+  if setting_result.is_err() { return Err(Status::InternalServerError) }
+
+  let mut setting = setting_result.unwrap();
+  setting.timezone = settings.timezone;
+  setting.locale = locale;
+  setting.date_format = settings.date_format.map(|date_format| date_format.as_str().to_string());
+
+  let changed_rows = db::users::upsert_user_setting(&conn, setting).await;
+  if changed_rows.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(Status::Ok)
+}
+
+/// You must provide either a username or an email together with a password.
+///
+/// Responds with 429 and the `Retry-After` header after too many failed logins from the client address
+/// or into the account (see `login_limits` in the configuration).
+#[openapi]
+#[post("/login", data = "<user_login>", format = "json")]
+pub async fn login(conn: DbConn, secret: &State<Secret>, login_limiter: &State<LoginLimiter>, address: Option<IpAddr>, client: LoginClient, user_login: TimedJson<UserLogin>) -> Result<Result<Json<LoginResponse>, TooManyLogins>, Status> {
+  let user_login = user_login.into_inner().normalize();
+
+  if let Some(retry_after) = login_limiter.locked(address, user_login.username_or_email()) {
+    return Ok(Err(TooManyLogins(retry_after)));
+  }
+
+  let token_option = user_login.login(&conn, client).await;
+  if token_option.is_none() {
+    login_limiter.failed(address, user_login.username_or_email());
+    return Err(Status::Conflict);
+  }
+
+  login_limiter.succeeded(user_login.username_or_email());
+
+  let token = token_option.unwrap();
+
+  let user_info = get_user_by_id(&conn, token.user_id).await.map_err(errors::internal)?;
+  if user_info.is_none() { return Err(Status::InternalServerError) }
+
+  let encoded = token.encode(secret);
+  if encoded.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(Ok(
+    Json(
+      LoginResponse::new(
+        encoded.unwrap(),
+        UserInfo::from(user_info.unwrap())
+      )
+    )
+  ))
+}
+
+/// Refreshes sent token
+// TODO: send token in header instead of body
+// https://stackoverflow.com/a/53881397
+#[openapi]
+#[post("/login/refresh", data = "<encoded_bearer_token>", format = "json")]
+pub async fn refresh_token(conn: DbConn, secret: &State<Secret>, encoded_bearer_token: TimedJson<ClaimsEncoded>) -> Result<Json<ClaimsEncoded>, ApiError> {
+  let bearer_token_result = encoded_bearer_token.into_inner();
+
+  let bearer_token: Claims = match bearer_token_result.clone().decode(secret) {
+    // access token is not yet expired
+    Ok(decoded) => decoded.claims,
+    // access token is expired - most of the time (token needs to be refreshed because it is expired)
+    Err(err) if matches!(err.kind(), jsonwebtoken::errors::ErrorKind::ExpiredSignature) => {
+      bearer_token_result.decode_without_validation().map_err(|_| Status::Unauthorized)?.claims
+    },
+    // the error is not expired token
+    Err(_) => return Err(Status::Unauthorized.into()),
+  };
+
+  // refresh token is expired
+  if bearer_token.is_refresh_token_expired(&conn).await { return Err(Status::Unauthorized.into()); }
+
+  let is_admin = db::users::is_user_admin(&conn, bearer_token.user_id).await?;
+  let new_token = Claims::from_existing(&bearer_token, is_admin);
+
+  let refresh_token_id = db::tokens::select_refresh_token_id(&conn, bearer_token.refresh_token()).await?.ok_or(Status::InternalServerError)?;
+
+  Claims::delete_obsolete_access_tokens(&conn, refresh_token_id).await;
+
+  if new_token.add_access_token_to_db(&conn, refresh_token_id).await.is_none() { return Err(Status::InternalServerError.into()); }
+
+  let new_encoded_token = new_token.encode(secret).map_err(|_| Status::InternalServerError)?;
+
+  Ok(Json(new_encoded_token))
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct MediaResponse {
+  pub filename: String,
+  pub owner_id: i32,
+  pub width: u32,
+  pub height: u32,
+  pub description: Option<String>,
+  /// RFC 3339 timestamp with the UTC offset of the place where the media was taken.
+  pub date_taken: DateTime<FixedOffset>,
+  pub uuid: String,
+  pub camera_make: Option<String>,
+  pub camera_model: Option<String>,
+  pub latitude: Option<f64>,
+  pub longitude: Option<f64>,
+  pub iso: Option<u32>,
+  /// Focal length in millimeters.
+  pub focal_length: Option<f64>,
+  /// EXIF orientation (1-8); clients should rotate the media accordingly.
+  pub orientation: Option<u16>,
+  /// The requesting user likes the media; always `false` for visitors of share links.
+  #[serde(default)]
+  pub is_liked: bool,
+  /// Number of users who like the media; only in albums and media shared with the user, which more users can see.
+  pub like_count: Option<i64>,
+  /// Username of the user who added the media to the album; only in albums listed by their owners and invited users.
+  pub added_by: Option<String>,
+  /// Time when the media was added to the album; only together with `added_by`.
+  pub added_at: Option<NaiveDateTime>,
+}
+
+/// Likes of media in a response.
+#[derive(Debug, Default)]
+pub struct MediaLikes {
+  /// IDs of media liked by the requesting user.
+  liked: HashSet<i32>,
+  /// Numbers of likes by media IDs; `None` when they aren't shown.
+  counts: Option<HashMap<i32, i64>>,
+}
+
+impl MediaLikes {
+  /// Selects likes of the media; `user_id` is the requesting user (`None` for visitors of share links)
+  /// and `with_counts` adds numbers of likes of each media.
+  pub async fn select(conn: &DbConn, media: &[Media], user_id: Option<i32>, with_counts: bool) -> Result<Self, Status> {
+    let likes = db::media::select_media_likes(conn, media.iter().map(|media| media.id).collect()).await;
+    if likes.is_err() { return Err(Status::InternalServerError) }
+
+    let mut media_likes = MediaLikes { liked: HashSet::new(), counts: with_counts.then(HashMap::new) };
+    for (media_id, liked_by) in likes.unwrap() {
+      if Some(liked_by) == user_id { media_likes.liked.insert(media_id); }
+
+      if let Some(counts) = media_likes.counts.as_mut() {
+        *counts.entry(media_id).or_insert(0) += 1;
+      }
+    }
+
+    Ok(media_likes)
+  }
+}
+
+impl MediaResponse {
+  /// Creates a response from media.\
+  /// `timezone` is used for media without a known UTC offset, see `Media::date_taken_with_offset()`.
+  pub fn new(media: &Media, timezone: Tz, likes: &MediaLikes) -> Self {
+    MediaResponse {
+      filename: media.filename.clone(),
+      owner_id: media.owner_id,
+      width: media.width,
+      height: media.height,
+      description: media.description.clone(),
+      date_taken: media.date_taken_with_offset(timezone),
+      uuid: media.uuid.clone(),
+      camera_make: media.camera_make.clone(),
+      camera_model: media.camera_model.clone(),
+      latitude: media.latitude,
+      longitude: media.longitude,
+      iso: media.iso,
+      focal_length: media.focal_length,
+      orientation: media.orientation,
+      is_liked: likes.liked.contains(&media.id),
+      like_count: likes.counts.as_ref().map(|counts| counts.get(&media.id).copied().unwrap_or(0)),
+      added_by: None,
+      added_at: None,
+    }
+  }
+}
+
+/// Gets a page of all media.
+///
+/// Media are ordered by `sort` (`date_taken` by default) and ID, newest first unless `order` says otherwise.
+/// Use `cursor` for stable pagination while new media are being added, `offset` is only a fallback.
+/// Without `limit`, `offset` and `cursor`, all media are returned as a plain array like before the pagination was added.\
+/// `folder` limits the media to one folder, given as a path relative to the user's gallery folder (e.g. `Holiday/Beach`);
+/// responds with 404 when the folder doesn't exist.\
+/// Responds with 422 when the cursor or the dates are invalid.
+// FIXME: skips new media in /gallery/username/<medianame>; /gallery/username/<some_folder>/<medianame> works
+#[openapi]
+#[get("/media?<folder>&<pagination..>")]
+pub async fn media_structure(claims: Claims, conn: DbConn, pagination: MediaPagination, folder: Option<String>) -> Result<Json<MediaListing>, ApiError> {
+  pagination.validate()?;
+
+  let folder_id = match folder {
+    Some(folder) => Some(select_folder_id_by_path(&conn, claims.user_id, &folder).await?),
+    None => None,
+  };
+
+  let structure = db::media::get_media_structure(&conn, claims.user_id, folder_id, pagination.clone()).await?;
+  let likes = MediaLikes::select(&conn, &structure, Some(claims.user_id), false).await?;
+
+  let timezone = db::users::get_user_timezone(&conn, claims.user_id).await;
+
+  Ok(Json(MediaListing::new(MediaPage::new(structure, &pagination, timezone, &likes), &pagination)))
+}
+
+/// Finds a folder of the user by its path relative to the user's gallery folder; an empty path is the root folder.
+async fn select_folder_id_by_path(conn: &DbConn, user_id: i32, path: &str) -> Result<i32, Status> {
+  let root_folder = db::folders::select_root_folder(conn, user_id).await;
+  if root_folder.is_err() { return Err(Status::InternalServerError) }
+
+  let mut folder_id = match root_folder.unwrap() {
+    Some(root_folder) => root_folder.id,
+    None => return Err(Status::NotFound),
+  };
+
+  for name in path.split('/').filter(|name| !name.is_empty()) {
+    folder_id = match db::folders::select_child_folder_id(conn, name.to_string(), Some(folder_id), user_id).await.map_err(errors::internal)? {
+      Some(child_id) => child_id,
+      None => return Err(Status::NotFound),
+    };
+  }
+
+  Ok(folder_id)
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct FolderResponse {
+  /// `None` for a moment after upgrading, until folders get their UUIDs.
+  pub uuid: Option<String>,
+  pub name: String,
+  /// Number of media in the folder itself.
+  pub media_count: i64,
+  /// Number of media in the folder and all its subfolders.
+  pub total_media_count: i64,
+  /// Time since which the directory of the folder is missing, see `/user/scan/folders`.
+  pub missing_since: Option<DateTime<Utc>>,
+  /// Subfolders ordered by name.
+  pub children: Vec<FolderResponse>,
+}
+
+/// Groups folders by their parents.
+fn folder_children(folders: &[Folder]) -> HashMap<Option<i32>, Vec<&Folder>> {
+  let mut children: HashMap<Option<i32>, Vec<&Folder>> = HashMap::new();
+  for folder in folders {
+    children.entry(folder.parent).or_default().push(folder);
+  }
+
+  children
+}
+
+fn folder_tree(children: &HashMap<Option<i32>, Vec<&Folder>>, media_counts: &HashMap<i32, i64>, parent: Option<i32>) -> Vec<FolderResponse> {
+  let mut tree: Vec<FolderResponse> = children.get(&parent).into_iter().flatten()
+    .map(|folder| {
+      let subfolders = folder_tree(children, media_counts, Some(folder.id));
+      let media_count = media_counts.get(&folder.id).copied().unwrap_or(0);
+
+      FolderResponse {
+        uuid: folder.uuid.clone(),
+        name: folder.name.clone(),
+        media_count,
+        total_media_count: media_count + subfolders.iter().map(|subfolder| subfolder.total_media_count).sum::<i64>(),
+        missing_since: folder.missing_since.map(|missing_since| DateTime::from_utc(missing_since, Utc)),
+        children: subfolders,
+      }
+    })
+    .collect();
+
+  tree.sort_by(|a, b| a.name.cmp(&b.name));
+
+  tree
+}
+
+/// Returns IDs of the folder and all its subfolders.
+fn folder_descendants(children: &HashMap<Option<i32>, Vec<&Folder>>, folder_id: i32) -> Vec<i32> {
+  let mut folder_ids = vec![folder_id];
+  let mut index = 0;
+
+  while index < folder_ids.len() {
+    let subfolders = children.get(&Some(folder_ids[index])).into_iter().flatten();
+    folder_ids.extend(subfolders.map(|subfolder| subfolder.id));
+    index += 1;
+  }
+
+  folder_ids
+}
+
+/// Returns the folder tree of the authenticated user with numbers of media.
+///
+/// The tree starts with the root folder of the user (the gallery folder named after the username);
+/// folders are created by scans, see `/scan_media`.
+#[openapi]
+#[get("/folders")]
+pub async fn get_folders(claims: Claims, conn: DbConn) -> Result<Json<Vec<FolderResponse>>, Status> {
+  let folders = db::folders::select_user_folders(&conn, claims.user_id).await;
+  if folders.is_err() { return Err(Status::InternalServerError) }
+
+  let media_counts = db::media::count_folder_media(&conn, claims.user_id).await;
+  if media_counts.is_err() { return Err(Status::InternalServerError) }
+
+  let folders = folders.unwrap();
+
+  Ok(Json(folder_tree(&folder_children(&folders), &media_counts.unwrap(), None)))
+}
+
+/// Gets a page of media in a folder of the authenticated user, without media of its subfolders.
+///
+/// Pagination works the same way as in `/media`.\
+/// Responds with 404 when the folder doesn't exist and with 422 when the cursor or the dates are invalid.
+#[openapi]
+#[get("/folder/<folder_uuid>/media?<pagination..>")]
+pub async fn get_folder_media(claims: Claims, conn: DbConn, folder_uuid: String, pagination: MediaPagination) -> Result<Json<MediaPage>, ApiError> {
+  pagination.validate()?;
+
+  let folder = db::folders::select_folder_by_uuid(&conn, folder_uuid, claims.user_id).await?.ok_or(Status::NotFound)?;
+
+  let structure = db::media::get_media_structure(&conn, claims.user_id, Some(folder.id), pagination.clone()).await?;
+  let likes = MediaLikes::select(&conn, &structure, Some(claims.user_id), false).await?;
+
+  let timezone = db::users::get_user_timezone(&conn, claims.user_id).await;
+
+  Ok(Json(MediaPage::new(structure, &pagination, timezone, &likes)))
+}
+
+/// Creates an album with media of a folder of the authenticated user.
+///
+/// The album is named after the folder unless the name is sent; with `recursive`, media of subfolders are added too.\
+/// The album doesn't follow the folder, media scanned into the folder later aren't added.\
+/// Responds with 404 when the folder doesn't exist.
+#[openapi]
+#[post("/folder/<folder_uuid>/album?<recursive>", data = "<album_insert_data>", format = "json")]
+pub async fn create_album_from_folder(claims: Claims, conn: DbConn, folder_uuid: String, recursive: Option<bool>, album_insert_data: Result<TimedJson<AlbumInsertData>, Status>) -> Result<(Status, Json<AlbumResponse>), Status> {
+  let album_insert_data = TimedJson::optional(album_insert_data)?;
+
+  let folder = db::folders::select_folder_by_uuid(&conn, folder_uuid, claims.user_id).await;
+  if folder.is_err() { return Err(Status::InternalServerError) }
+
+  let folder = folder.unwrap();
+  if folder.is_none() { return Err(Status::NotFound) }
+
+  let folder = folder.unwrap();
+
+  let folder_ids = if recursive.unwrap_or(false) {
+    let folders = db::folders::select_user_folders(&conn, claims.user_id).await;
+    if folders.is_err() { return Err(Status::InternalServerError) }
+
+    let folders = folders.unwrap();
+    folder_descendants(&folder_children(&folders), folder.id)
+  } else {
+    vec![folder.id]
+  };
+
+  let media_ids = db::media::select_folder_media_ids(&conn, claims.user_id, folder_ids).await;
+  if media_ids.is_err() { return Err(Status::InternalServerError) }
+
+  let (name, description) = match album_insert_data {
+    Some(album_insert_data) => (album_insert_data.name.clone(), album_insert_data.description.clone()),
+    None => (folder.name, None),
+  };
+
+  let album_id = db::albums::insert_album_with_media(&conn, NewAlbum::new(claims.user_id, name, description, None), media_ids.unwrap()).await;
+  if album_id.is_err() { return Err(Status::InternalServerError) }
+
+  let album_id = album_id.unwrap();
+  update_album_date_range(&conn, album_id).await;
+  update_album_thumbnail_fallback(&conn, album_id).await;
+
+  let album = db::albums::select_album(&conn, album_id).await.map_err(errors::internal)?;
+  if album.is_none() { return Err(Status::InternalServerError) }
+
+  Ok((Status::Created, Json(AlbumResponse::from(album.unwrap()))))
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Queryable)]
+pub struct AlbumInsertData {
+  pub name: String,
+  pub description: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Queryable)]
+pub struct AlbumResponse {
+  pub owner_id: i32,
+  pub name: String,
+  pub description: Option<String>,
+  pub created_at: NaiveDateTime,
+  /// UUID of the media shown as the thumbnail, see `/album/<album_uuid>/thumbnail`.
+  pub thumbnail_link: Option<String>,
+  pub link: String,
+  pub locked: bool,
+  /// Number of media added since the last visit of the album.
+  pub new_media_count: i64,
+  /// Kind of the smart album; `None` for regular albums.
+  pub smart: Option<SmartAlbum>,
+  /// Local time when the earliest media of the album was taken; `None` for empty albums.
+  pub date_from: Option<NaiveDateTime>,
+  /// Local time when the latest media of the album was taken.
+  pub date_to: Option<NaiveDateTime>,
+}
+
+impl From<Album> for AlbumResponse {
+  fn from(album: Album) -> Self {
+    let smart = album.smart();
+    AlbumResponse { owner_id: album.owner_id, name: album.name, description: album.description, created_at: album.created_at, thumbnail_link: album.thumbnail_link, link: album.link, locked: album.locked, new_media_count: 0, smart, date_from: album.date_from, date_to: album.date_to }
+  }
+}
+
+impl From<&Album> for AlbumResponse {
+  fn from(album: &Album) -> Self {
+    AlbumResponse { owner_id: album.owner_id, name: album.name.clone(), description: album.description.clone(), created_at: album.created_at, thumbnail_link: album.thumbnail_link.clone(), link: album.link.clone(), locked: album.locked, new_media_count: 0, smart: album.smart(), date_from: album.date_from, date_to: album.date_to }
+  }
+}
+
+impl From<NewAlbum> for AlbumResponse {
+  fn from(album: NewAlbum) -> Self {
+    let smart = album.smart.as_deref().and_then(|smart| smart.parse().ok());
+    AlbumResponse { owner_id: album.owner_id, name: album.name, description: album.description, created_at: album.created_at, thumbnail_link: None, link: album.link, locked: false, new_media_count: 0, smart, date_from: None, date_to: None }
+  }
+}
+
+/// Creates a new album
+#[openapi]
+#[post("/album", data = "<album_insert_data>", format = "json")]
+pub async fn create_album(claims: Claims, conn: DbConn, album_insert_data: TimedJson<AlbumInsertData>) -> Result<Json<AlbumResponse>, ApiError> {
+  let album = db::albums::insert_album(&conn, claims.user_id, album_insert_data.into_inner()).await?;
+
+  // TODO: impl from u jiné struktury bez ID a hesla
+  Ok(Json(AlbumResponse::from(album)))
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct AlbumAddMedia {
+  album_uuid: String,
+  media_uuid: String,
+}
+
+/// Adds media to an album
+#[openapi]
+#[post("/album/media", data = "<list_of_media>", format = "json")]
+pub async fn album_add_media(claims: Claims, conn: DbConn, config: &State<Config>, list_of_media: TimedJson<Vec<AlbumAddMedia>>) -> Result<(), Status> {
+  let mut transformed = vec![];
+
+  // TODO: optimise this so it doesn't check the same data multiple times
+  for new in list_of_media.into_inner() {
+    let album_id = db::albums::select_album_id(&conn, new.album_uuid).await.map_err(errors::internal)?;
+    if album_id.is_none() { continue; }
+
+    permissions::authorize_album(&conn, config.access_denied, claims.user_id, album_id.unwrap(), AlbumAction::AddMedia).await?;
+
+    let album = db::albums::select_album(&conn, album_id.unwrap()).await.map_err(errors::internal)?;
+    if album.is_none() { continue; }
+
+    // media of smart albums are selected automatically
+    if album.unwrap().smart.is_some() { return Err(Status::UnprocessableEntity) }
+
+    // media shared with the user can't be added, as it could be exposed further using album share links
+    permissions::authorize_media(&conn, config.access_denied, claims.user_id, new.media_uuid.clone(), MediaAction::Manage).await?;
+
+    let media_id = db::media::select_media_id(&conn, new.media_uuid).await.map_err(errors::internal)?;
+    if media_id.is_none() { continue; }
+
+    // media already present in the album are skipped when inserting
+    transformed.push(NewAlbumMedia::new(album_id.unwrap(), media_id.unwrap(), Some(claims.user_id)))
+  }
+
+  let mut album_ids: Vec<i32> = transformed.iter().map(|new| new.album_id).collect();
+  album_ids.sort_unstable();
+  album_ids.dedup();
+
+  db::albums::album_add_media(&conn, transformed).await.map_err(errors::internal)?;
+
+  for album_id in album_ids {
+    update_album_date_range(&conn, album_id).await;
+    update_album_thumbnail_fallback(&conn, album_id).await;
+  }
+
+  Ok(())
+}
+
+/// Updates the cached date range of the album; failures are only logged, as the media were already changed.
+async fn update_album_date_range(conn: &DbConn, album_id: i32) {
+  if let Err(err) = db::albums::update_album_date_range(conn, album_id).await {
+    error!("Date range of album {} couldn't be updated: {}", album_id, err);
+  }
+}
+
+/// Chooses a thumbnail of the album when it has none or its media was removed; failures are only logged.
+async fn update_album_thumbnail_fallback(conn: &DbConn, album_id: i32) {
+  if let Err(err) = db::albums::update_album_thumbnail_fallback(conn, album_id).await {
+    error!("Thumbnail of album {} couldn't be updated: {}", album_id, err);
+  }
+}
+
+/// Updates the cached date range of the user's favorites album after a like changed.
+async fn update_favorites_date_range(conn: &DbConn, user_id: i32) {
+  if let Ok(Some(favorites)) = db::albums::select_smart_album(conn, user_id, SmartAlbum::Favorites).await {
+    update_album_date_range(conn, favorites.id).await;
+  }
+}
+
+/// Retrieves a list of albums of an authenticated user
+///
+/// `new_media_count` counts media added since the user last fetched media of the album.
+#[openapi]
+#[get("/album")]
+pub async fn get_album_list(claims: Claims, conn: DbConn) -> Result<Json<Vec<AlbumResponse>>, ApiError> {
+  let albums = db::albums::get_album_list(&conn, claims.user_id).await?;
+
+  let album_ids = albums.iter().map(|album| album.id).collect();
+  let new_media_counts: HashMap<i32, i64> = db::albums::count_new_album_media(&conn, claims.user_id, album_ids).await
+    .map(|counts| counts.into_iter().collect())
+    .unwrap_or_default();
+
+  let mut result = vec![];
+  for album in albums {
+    let new_media_count = new_media_counts.get(&album.id).copied().unwrap_or(0);
+
+    let mut album_response = AlbumResponse::from(album);
+    album_response.new_media_count = new_media_count;
+
+    result.push(album_response);
+  }
+
+  Ok(Json(result))
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Queryable)]
+pub struct AlbumUpdateData {
+  pub name: Option<String>,
+  pub description: Option<String>,
+  /// Default sorting of the album's media.
+  pub sort: Option<MediaSort>,
+  /// Default order; used only together with `sort`.
+  pub sort_order: Option<SortOrder>,
+}
+
+// TODO: rewrite later and use forwarding (ranks)
+// problem seems to be in okapi as it overwrites the route when there are multiple ranks
+// while the Request guards are wrapped in Option, there are no error codes from that Request guards
+/// Gets a page of media in an album.
+///
+/// Media are ordered the same way as in `/media`; the album's default sorting is used when `sort` isn't set,
+/// so other sortings can be previewed without changing it.
+/// Without `limit`, `offset` and `cursor`, all media are returned as a plain array.\
+/// The owner and invited users also see who added each media and when.
+#[openapi]
+#[get("/album/<album_uuid>/media?<pagination..>")]
+pub async fn get_album_structure(shared_album_link_security: Option<SharedAlbumLinkSecurity>, claims_option: Option<Claims>, conn: DbConn, config: &State<Config>, album_uuid: String, pagination: MediaPagination) -> Result<Json<MediaListing>, ApiError> {
+  let album_id = db::albums::select_album_id(&conn, album_uuid).await?.ok_or(Status::NotFound)?;
+  let album = db::albums::select_album(&conn, album_id).await?.ok_or(Status::NotFound)?;
+
+  let pagination = pagination.with_default_sort(album.sort(), album.sort_order());
+  pagination.validate()?;
+
+  let user_id = claims_option.map(|claims| claims.user_id);
+
+  if let Some(user_id) = user_id {
+    permissions::authorize_album(&conn, config.access_denied, user_id, album.id, AlbumAction::View).await?;
+
+    if db::albums::upsert_album_visit(&conn, user_id, album.id).await.is_err() {
+      error!("Visit of album {} couldn't be recorded.", album.id);
+    }
+  } else if let Some(shared_album_link_security) = shared_album_link_security {
+    permissions::authorize_share_link_album(&shared_album_link_security, album.id)?;
+  } else {
+    return Err(Status::Unauthorized.into());
+  }
+
+  let structure = match album.smart() {
+    Some(SmartAlbum::Favorites) => db::media::get_liked_media(&conn, album.owner_id, pagination.clone()).await,
+    None => db::albums::get_album_media(&conn, album.id, pagination.clone()).await,
+  }?;
+
+  let likes = MediaLikes::select(&conn, &structure, user_id, true).await?;
+
+  // visitors of share links don't see who added the media; media of smart albums aren't added by anyone
+  let contributions = match (user_id, album.smart()) {
+    (Some(_), None) => {
+      let media_ids = structure.iter().map(|media| media.id).collect();
+      db::albums::select_album_contributions(&conn, album.id, Some(media_ids)).await?
+    },
+    _ => vec![],
+  };
+
+  let timezone = db::users::get_user_timezone(&conn, album.owner_id).await;
+
+  let mut page = MediaPage::new(structure, &pagination, timezone, &likes);
+
+  let contributions: HashMap<String, (String, NaiveDateTime)> = contributions.into_iter()
+    .map(|(username, added_at, media_uuid)| (media_uuid, (username, added_at)))
+    .collect();
+
+  for media in page.media.iter_mut() {
+    if let Some((username, added_at)) = contributions.get(&media.uuid) {
+      media.added_by = Some(username.clone());
+      media.added_at = Some(*added_at);
+    }
+  }
+
+  Ok(Json(MediaListing::new(page, &pagination)))
+}
+
+/// Updates already existing album
+///
+/// `sort` and `sort_order` set the default sorting of the album's media.\
+/// Invited users with write access can change only the description.
+#[openapi]
+#[put("/album/<album_uuid>", data = "<album_update_data>", format = "json")]
+pub async fn update_album(claims: Claims, conn: DbConn, config: &State<Config>, album_uuid: String, album_update_data: TimedJson<AlbumUpdateData>) -> Result<Status, Status> {
+  if album_update_data.name.is_none() && album_update_data.description.is_none() && album_update_data.sort.is_none() {
+    return Err(Status::UnprocessableEntity);
+  }
+
+  let album_id_option = db::albums::select_album_id(&conn, album_uuid).await.map_err(errors::internal)?;
+  if album_id_option.is_none() {
+    return Err(Status::NotFound);
+  }
+
+  let album_id = album_id_option.unwrap();
+
+  let action = if album_update_data.name.is_some() || album_update_data.sort.is_some() {
+    AlbumAction::Update
+  } else {
+    AlbumAction::UpdateDescription
+  };
+
+  permissions::authorize_album(&conn, config.access_denied, claims.user_id, album_id, action).await?;
+
+  let changed_rows = db::albums::update_album(&conn, album_id, album_update_data.into_inner()).await.map_err(errors::internal)?;
+  if changed_rows == 0 {
+    return Ok(Status::NoContent);
+  }
+
+  Ok(Status::Ok)
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct AlbumThumbnail {
+  /// Media of the album shown as its thumbnail; `None` shows the first added media again.
+  pub media_uuid: Option<String>,
+}
+
+/// Sets the thumbnail of an album
+///
+/// Invited users with write access can set it too; the media must be in the album.\
+/// Until a thumbnail is chosen, or when its media is removed from the album, the first added media is shown.
+#[openapi]
+#[put("/album/<album_uuid>/thumbnail", data = "<album_thumbnail>", format = "json")]
+pub async fn update_album_thumbnail(claims: Claims, conn: DbConn, config: &State<Config>, album_uuid: String, album_thumbnail: TimedJson<AlbumThumbnail>) -> Result<Status, Status> {
+  let album_id_option = db::albums::select_album_id(&conn, album_uuid).await.map_err(errors::internal)?;
+  if album_id_option.is_none() { return Err(Status::NotFound) }
+
+  let album_id = album_id_option.unwrap();
+
+  permissions::authorize_album(&conn, config.access_denied, claims.user_id, album_id, AlbumAction::SetThumbnail).await?;
+
+  if let Some(media_uuid) = album_thumbnail.media_uuid.clone() {
+    let media = db::media::select_media_by_uuid(&conn, media_uuid).await;
+    if media.is_err() { return Err(Status::InternalServerError) }
+
+    let media_option = media.unwrap();
+    if media_option.is_none() { return Err(Status::NotFound) }
+
+    let in_album = db::albums::album_already_has_media(&conn, album_id, media_option.unwrap().id).await;
+    if in_album.is_err() { return Err(Status::InternalServerError) }
+
+    if !in_album.unwrap() { return Err(Status::UnprocessableEntity) }
+  }
+
+  let changed_rows = db::albums::update_album_thumbnail(&conn, album_id, album_thumbnail.into_inner().media_uuid).await;
+  if changed_rows.is_err() { return Err(Status::InternalServerError) }
+
+  update_album_thumbnail_fallback(&conn, album_id).await;
+
+  if changed_rows.unwrap() == 0 {
+    return Ok(Status::NoContent);
+  }
+
+  Ok(Status::Ok)
+}
+
+/// Deletes an album
+///
+/// Responds with 423 when the album is locked.
+#[openapi]
+#[delete("/album/<album_uuid>")]
+pub async fn delete_album(claims: Claims, conn: DbConn, config: &State<Config>, album_uuid: String) -> Result<Status, Status> {
+  let album_id_option = db::albums::select_album_id(&conn, album_uuid).await.map_err(errors::internal)?;
+  if album_id_option.is_none() {
+    return Err(Status::NotFound);
+  }
+
+  let album_id = album_id_option.unwrap();
+
+  let album = db::albums::select_album(&conn, album_id).await.map_err(errors::internal)?;
+
+  if album.is_none() { return Err(Status::NotFound); }
+
+  permissions::authorize_album(&conn, config.access_denied, claims.user_id, album_id, AlbumAction::Delete).await?;
+
+  if album.unwrap().locked { return Err(Status::Locked) }
+
+  let deleted = db::albums::delete_album(&conn, album_id).await;
+  if deleted.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(Status::Ok)
+}
+
+/// Locks an album, so it can't be deleted and no share links can be created for it.
+#[openapi]
+#[put("/album/<album_uuid>/lock")]
+pub async fn lock_album(claims: Claims, conn: DbConn, config: &State<Config>, album_uuid: String) -> Result<Status, Status> {
+  set_album_locked(claims, conn, config.access_denied, album_uuid, true).await
+}
+
+/// Unlocks a locked album.
+#[openapi]
+#[delete("/album/<album_uuid>/lock")]
+pub async fn unlock_album(claims: Claims, conn: DbConn, config: &State<Config>, album_uuid: String) -> Result<Status, Status> {
+  set_album_locked(claims, conn, config.access_denied, album_uuid, false).await
+}
+
+async fn set_album_locked(claims: Claims, conn: DbConn, policy: AccessDeniedPolicy, album_uuid: String, locked: bool) -> Result<Status, Status> {
+  let album_id_option = db::albums::select_album_id(&conn, album_uuid).await.map_err(errors::internal)?;
+  if album_id_option.is_none() { return Err(Status::NotFound) }
+
+  let album_id = album_id_option.unwrap();
+
+  permissions::authorize_album(&conn, policy, claims.user_id, album_id, AlbumAction::Lock).await?;
+
+  let changed_rows = db::albums::set_album_locked(&conn, album_id, locked).await;
+  if changed_rows.is_err() { return Err(Status::InternalServerError) }
+
+  if changed_rows.unwrap() == 0 {
+    return Ok(Status::NoContent);
+  }
+
+  Ok(Status::Ok)
+}
+
+/// Removes media from an album.
+///
+/// Editors can remove only their own media.
+#[openapi]
+#[delete("/album/<album_uuid>/media/<media_uuid>")]
+pub async fn album_remove_media(claims: Claims, conn: DbConn, config: &State<Config>, album_uuid: String, media_uuid: String) -> Result<Status, Status> {
+  let album_id_option = db::albums::select_album_id(&conn, album_uuid).await.map_err(errors::internal)?;
+  if album_id_option.is_none() { return Err(Status::NotFound) }
+
+  let album_id = album_id_option.unwrap();
+
+  let media = db::media::select_media_by_uuid(&conn, media_uuid).await;
+  if media.is_err() { return Err(Status::InternalServerError) }
+
+  let media_option = media.unwrap();
+  if media_option.is_none() { return Err(Status::NotFound) }
+
+  let media = media_option.unwrap();
+
+  let role = permissions::authorize_album(&conn, config.access_denied, claims.user_id, album_id, AlbumAction::RemoveMedia).await?;
+
+  if role == AlbumRole::Editor && media.owner_id != claims.user_id { return Err(Status::Forbidden) }
+
+  let album = db::albums::select_album(&conn, album_id).await.map_err(errors::internal)?;
+  if album.is_none() { return Err(Status::NotFound) }
+
+  if album.unwrap().smart.is_some() { return Err(Status::UnprocessableEntity) }
+
+  let deleted = db::albums::album_remove_media(&conn, album_id, media.id).await;
+  if deleted.is_err() { return Err(Status::InternalServerError) }
+
+  if deleted.unwrap() == 0 {
+    return Ok(Status::NoContent);
+  }
+
+  update_album_date_range(&conn, album_id).await;
+  update_album_thumbnail_fallback(&conn, album_id).await;
+
+  Ok(Status::Ok)
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct AlbumInviteInsert {
+  username: String,
+  /// Allows the user to add and remove own media.
+  #[serde(default)]
+  write_access: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct AlbumInviteResponse {
+  username: String,
+  accepted: bool,
+  write_access: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct AlbumInvitePending {
+  album: AlbumResponse,
+  write_access: bool,
+}
+
+/// Invites a user to an album.
+#[openapi]
+#[post("/album/<album_uuid>/invite", data = "<album_invite_insert>", format = "json")]
+pub async fn create_album_invite(claims: Claims, conn: DbConn, config: &State<Config>, album_uuid: String, album_invite_insert: TimedJson<AlbumInviteInsert>) -> Result<Status, Status> {
+  let album_id_option = db::albums::select_album_id(&conn, album_uuid).await.map_err(errors::internal)?;
+  if album_id_option.is_none() { return Err(Status::NotFound) }
+
+  let album_id = album_id_option.unwrap();
+
+  permissions::authorize_album(&conn, config.access_denied, claims.user_id, album_id, AlbumAction::ManageInvites).await?;
+
+  let album = db::albums::select_album(&conn, album_id).await.map_err(errors::internal)?;
+  if album.is_none() { return Err(Status::NotFound) }
+
+  // smart albums can contain media shared with the owner, which mustn't be exposed further
+  if album.unwrap().smart.is_some() { return Err(Status::UnprocessableEntity) }
+
+  let album_invite_insert = album_invite_insert.into_inner();
+
+  let user_id_option = db::users::get_user_id(&conn, album_invite_insert.username).await.map_err(errors::internal)?;
+  if user_id_option.is_none() { return Err(Status::NotFound) }
+
+  let user_id = user_id_option.unwrap();
+
+  // owners can't invite themselves
+  if user_id == claims.user_id { return Err(Status::UnprocessableEntity) }
+
+  // users of other organizations are hidden
+  let same_organization = db::organizations::users_share_organization(&conn, claims.user_id, user_id).await;
+  if same_organization.is_err() { return Err(Status::InternalServerError) }
+
+  if !same_organization.unwrap() { return Err(Status::NotFound) }
+
+  let changed_rows = db::albums::insert_album_invite(&conn, NewAlbumInvite::new(album_id, user_id, album_invite_insert.write_access)).await;
+  if changed_rows.is_ok() {
+    return Ok(Status::Created);
+  }
+
+  error!("Inserting album invite failed: {}", changed_rows.unwrap_err());
+  Err(Status::Conflict)
+}
+
+/// Gets a list of users invited to an album.
+#[openapi]
+#[get("/album/<album_uuid>/invite")]
+pub async fn get_album_invites(claims: Claims, conn: DbConn, config: &State<Config>, album_uuid: String) -> Result<Json<Vec<AlbumInviteResponse>>, Status> {
+  let album_id_option = db::albums::select_album_id(&conn, album_uuid).await.map_err(errors::internal)?;
+  if album_id_option.is_none() { return Err(Status::NotFound) }
+
+  let album_id = album_id_option.unwrap();
+
+  permissions::authorize_album(&conn, config.access_denied, claims.user_id, album_id, AlbumAction::ManageInvites).await?;
+
+  let invites = db::albums::select_album_invites(&conn, album_id).await;
+  if invites.is_err() { return Err(Status::InternalServerError) }
+
+  let result = invites.unwrap().into_iter()
+    .map(|(invite, username)| AlbumInviteResponse { username, accepted: invite.accepted, write_access: invite.write_access })
+    .collect::<Vec<AlbumInviteResponse>>();
+
+  Ok(Json(result))
+}
+
+/// Number of the latest media listed for each contributor of an album.
+const CONTRIBUTOR_LATEST_MEDIA: usize = 5;
+
+#[derive(Serialize, JsonSchema)]
+pub struct AlbumContributor {
+  username: String,
+  media_count: i64,
+  last_added_at: NaiveDateTime,
+  /// UUIDs of the media the user added last, the newest first.
+  latest_media_uuids: Vec<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct AlbumStats {
+  media_count: i64,
+  /// Users who added media to the album, the ones who added the most first.
+  contributors: Vec<AlbumContributor>,
+}
+
+/// Gets statistics of users who added media to an album, so its owner can see who added what.
+///
+/// Media added before contributors were recorded are counted for their owners.
+#[openapi]
+#[get("/album/<album_uuid>/stats")]
+pub async fn get_album_stats(claims: Claims, conn: DbConn, config: &State<Config>, album_uuid: String) -> Result<Json<AlbumStats>, Status> {
+  let album_id_option = db::albums::select_album_id(&conn, album_uuid).await.map_err(errors::internal)?;
+  if album_id_option.is_none() { return Err(Status::NotFound) }
+
+  let album_id = album_id_option.unwrap();
+
+  permissions::authorize_album(&conn, config.access_denied, claims.user_id, album_id, AlbumAction::ManageInvites).await?;
+
+  let contributions = db::albums::select_album_contributions(&conn, album_id, None).await;
+  if contributions.is_err() { return Err(Status::InternalServerError) }
+
+  let contributions = contributions.unwrap();
+  let media_count = contributions.len() as i64;
+
+  // contributions are the newest first, so the first one of each user is their last
+  let mut contributors: Vec<AlbumContributor> = vec![];
+  for (username, added_at, media_uuid) in contributions {
+    match contributors.iter_mut().find(|contributor| contributor.username == username) {
+      Some(contributor) => {
+        contributor.media_count += 1;
+        if contributor.latest_media_uuids.len() < CONTRIBUTOR_LATEST_MEDIA { contributor.latest_media_uuids.push(media_uuid); }
+      },
+      None => contributors.push(AlbumContributor { username, media_count: 1, last_added_at: added_at, latest_media_uuids: vec![media_uuid] }),
+    }
+  }
+
+  contributors.sort_by(|a, b| b.media_count.cmp(&a.media_count).then_with(|| b.last_added_at.cmp(&a.last_added_at)));
+
+  Ok(Json(AlbumStats { media_count, contributors }))
+}
+
+/// Gets a list of album invites of the authenticated user which weren't accepted yet.
+#[openapi]
+#[get("/album/invite")]
+pub async fn get_pending_album_invites(claims: Claims, conn: DbConn) -> Result<Json<Vec<AlbumInvitePending>>, Status> {
+  let invites = db::albums::select_pending_album_invites(&conn, claims.user_id).await;
+  if invites.is_err() { return Err(Status::InternalServerError) }
+
+  let result = invites.unwrap().into_iter()
+    .map(|(invite, album)| AlbumInvitePending { album: AlbumResponse::from(album), write_access: invite.write_access })
+    .collect::<Vec<AlbumInvitePending>>();
+
+  Ok(Json(result))
+}
+
+/// Accepts an invite to an album.
+#[openapi]
+#[post("/album/<album_uuid>/invite/accept")]
+pub async fn accept_album_invite(claims: Claims, conn: DbConn, album_uuid: String) -> Result<Status, Status> {
+  let album_id_option = db::albums::select_album_id(&conn, album_uuid).await.map_err(errors::internal)?;
+  if album_id_option.is_none() { return Err(Status::NotFound) }
+
+  let changed_rows = db::albums::accept_album_invite(&conn, album_id_option.unwrap(), claims.user_id).await;
+  if changed_rows.is_err() { return Err(Status::InternalServerError) }
+
+  if changed_rows.unwrap() == 0 { return Err(Status::NotFound) }
+
+  Ok(Status::Ok)
+}
+
+/// Removes an invite to an album.
+///
+/// Owners can remove any invite, invited users can remove their own invite to decline it or to leave the album.
+#[openapi]
+#[delete("/album/<album_uuid>/invite/<username>")]
+pub async fn delete_album_invite(claims: Claims, conn: DbConn, config: &State<Config>, album_uuid: String, username: String) -> Result<Status, Status> {
+  let album_id_option = db::albums::select_album_id(&conn, album_uuid).await.map_err(errors::internal)?;
+  if album_id_option.is_none() { return Err(Status::NotFound) }
+
+  let album_id = album_id_option.unwrap();
+
+  let user_id_option = db::users::get_user_id(&conn, username).await.map_err(errors::internal)?;
+  if user_id_option.is_none() { return Err(Status::NotFound) }
+
+  let user_id = user_id_option.unwrap();
+
+  if user_id != claims.user_id {
+    permissions::authorize_album(&conn, config.access_denied, claims.user_id, album_id, AlbumAction::ManageInvites).await?;
+  }
+
+  let deleted = db::albums::delete_album_invite(&conn, album_id, user_id).await;
+  if deleted.is_err() { return Err(Status::InternalServerError) }
+
+  if deleted.unwrap() == 0 {
+    return Ok(Status::NoContent);
+  }
+
+  Ok(Status::Ok)
+}
+
+/// Relative expiration time.
+/// # Example
+/// Both `604800` and `"7d"` mean that the link expires in 7 days.\
+/// Supported units are `s`, `m`, `h`, `d` and `w`; a number without a unit means seconds.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+#[serde(untagged)]
+pub enum ExpiresIn {
+  Seconds(i64),
+  Text(String),
+}
+
+impl ExpiresIn {
+  /// Returns the duration; `None` when it's malformed or not positive.
+  pub fn duration(&self) -> Option<Duration> {
+    let seconds = match self {
+      ExpiresIn::Seconds(seconds) => *seconds,
+      ExpiresIn::Text(text) => {
+        let text = text.trim();
+        let (number, multiplier) = match text.char_indices().last()? {
+          (i, 's') => (&text[..i], 1),
+          (i, 'm') => (&text[..i], 60),
+          (i, 'h') => (&text[..i], 60 * 60),
+          (i, 'd') => (&text[..i], 60 * 60 * 24),
+          (i, 'w') => (&text[..i], 60 * 60 * 24 * 7),
+          _ => (text, 1),
+        };
+
+        number.trim().parse::<i64>().ok()?.checked_mul(multiplier)?
+      }
+    };
+
+    if seconds <= 0 { return None }
+
+    Some(Duration::seconds(seconds))
+  }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct AlbumShareLinkInsert {
+  /// Absolute expiration time in UTC.
+  pub expiration: Option<NaiveDateTime>,
+  /// Relative expiration time; can't be combined with `expiration`.
+  pub expires_in: Option<ExpiresIn>,
+  pub password: Option<String>,
+  #[serde(flatten)]
+  pub branding: AlbumShareLinkBranding,
+  /// Allows visitors to download the whole album as a zip archive.
+  #[serde(default)]
+  pub allow_zip_download: bool,
+  /// Bandwidth shared by all visitors of the link in KiB/s; zero or null disables the limit.
+  #[serde(default)]
+  pub bandwidth_limit: Option<i32>,
+}
+
+impl AlbumShareLinkInsert {
+  // Normalizes passwords and hashes them if they are not None
+  pub async fn normalize_and_hash_password(self) -> Self {
+    if self.password.is_none() { return self }
+
+    let password = self.password.unwrap();
+
+    let hashed_password = match password.len() {
+      0 => None,
+      _ => Some(hash_password(&password).await)
+    };
+
+    Self {
+      expiration: self.expiration,
+      expires_in: self.expires_in,
+      password: hashed_password,
+      branding: self.branding,
+      allow_zip_download: self.allow_zip_download,
+      bandwidth_limit: self.bandwidth_limit,
+    }
+  }
+
+  /// Converts `expires_in` to an absolute UTC `expiration`.\
+  /// Returns `Err(())` when both are set or when `expires_in` is malformed.
+  pub fn normalize_expiration(self) -> Result<Self, ()> {
+    let expires_in = match self.expires_in {
+      Some(expires_in) => expires_in,
+      None => return Ok(self),
+    };
+
+    if self.expiration.is_some() { return Err(()) }
+
+    let expiration = Utc::now().naive_utc().checked_add_signed(expires_in.duration().ok_or(())?).ok_or(())?;
+
+    Ok(Self {
+      expiration: Some(expiration),
+      expires_in: None,
+      password: self.password,
+      branding: self.branding,
+      allow_zip_download: self.allow_zip_download,
+      bandwidth_limit: self.bandwidth_limit,
+    })
+  }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct SharedAlbumLinkResponse {
+  uuid: String,
+  /// Absolute expiration time.
+  expiration: Option<DateTime<Utc>>,
+  /// Number of seconds until the link expires; zero when it's already expired.
+  expires_in: Option<i64>,
+  #[serde(flatten)]
+  branding: AlbumShareLinkBranding,
+  allow_zip_download: bool,
+  /// Number of times the whole album was downloaded as a zip archive.
+  zip_downloads: usize,
+  /// Time of the last zip download.
+  last_zip_download: Option<DateTime<Utc>>,
+  /// Bandwidth shared by all visitors of the link in KiB/s.
+  bandwidth_limit: Option<i32>,
+  /// Traffic of the link since the server started.
+  bandwidth: BandwidthStats,
+}
+
+impl SharedAlbumLinkResponse {
+  pub fn new(uuid: String, expiration: Option<NaiveDateTime>, branding: AlbumShareLinkBranding, allow_zip_download: bool, bandwidth_limit: Option<i32>) -> Self {
+    Self {
+      uuid,
+      expiration: expiration.map(|expiration| DateTime::from_utc(expiration, Utc)),
+      expires_in: remaining_seconds(expiration),
+      branding,
+      allow_zip_download,
+      zip_downloads: 0,
+      last_zip_download: None,
+      bandwidth_limit,
+      bandwidth: BandwidthStats::default(),
+    }
+  }
+
+  /// Adds traffic statistics of the link.
+  pub fn with_bandwidth(self, bandwidth: BandwidthStats) -> Self {
+    Self { bandwidth, ..self }
+  }
+
+  /// Adds zip downloads of the link, ordered from the newest.
+  pub fn with_downloads(self, downloads: &[NaiveDateTime]) -> Self {
+    Self {
+      zip_downloads: downloads.len(),
+      last_zip_download: downloads.first().map(|downloaded_at| DateTime::from_utc(*downloaded_at, Utc)),
+      ..self
+    }
+  }
+}
+
+/// Returns the number of seconds remaining until the given UTC time; zero when it's in the past.
+fn remaining_seconds(expiration: Option<NaiveDateTime>) -> Option<i64> {
+  expiration.map(|expiration| (expiration - Utc::now().naive_utc()).num_seconds().max(0))
+}
+
+/// Creates a new album share link.
+///
+/// The expiration can be set either as an absolute `expiration` or as a relative `expires_in`.\
+/// `title`, `welcome_message` and `accent_color` brand the shared album page.\
+/// Responds with 423 when the album is locked.
+#[openapi]
+#[post("/album/<album_uuid>/share/link", data = "<album_share_link_insert>", format = "json")]
+pub async fn create_album_share_link(claims: Claims, conn: DbConn, config: &State<Config>, album_uuid: String, album_share_link_insert: Result<TimedJson<AlbumShareLinkInsert>, Status>) -> Result<Json<SharedAlbumLinkResponse>, ApiError> {
+  let album_share_link_insert = TimedJson::optional(album_share_link_insert)?;
+
+  let album_id_option = db::albums::select_album_id(&conn, album_uuid).await?;
+  if album_id_option.is_none() { return Err(Status::NotFound.into()) }
+
+  let album_id = album_id_option.unwrap();
+
+  let album = db::albums::select_album(&conn, album_id).await?;
+  if album.is_none() { return Err(Status::NotFound.into()) }
+
+  permissions::authorize_album(&conn, config.access_denied, claims.user_id, album_id, AlbumAction::ManageShareLinks).await?;
+
+  let album = album.unwrap();
+  if album.locked { return Err(Status::Locked.into()) }
+
+  // smart albums can contain media shared with the owner, which mustn't be exposed further
+  if album.smart.is_some() { return Err(Status::UnprocessableEntity.into()) }
+
+  let album_share_link_insert_inner = match album_share_link_insert {
+    Some(album_share_link) => album_share_link,
+    None => AlbumShareLinkInsert {
+      expiration: None,
+      expires_in: None,
+      password: None,
+      branding: AlbumShareLinkBranding::default(),
+      allow_zip_download: false,
+      bandwidth_limit: None,
+    }
+  };
+
+  let normalized = album_share_link_insert_inner.normalize_expiration().map_err(|_| Status::UnprocessableEntity)?;
+  let mut album_share_link_insert_inner = normalized.normalize_and_hash_password().await;
+
+  album_share_link_insert_inner.branding = album_share_link_insert_inner.branding.normalize();
+  album_share_link_insert_inner.branding.validate()?;
+  album_share_link_insert_inner.bandwidth_limit = album_share_link_insert_inner.bandwidth_limit.filter(|limit| *limit > 0);
+
+  let album_share_link = NewAlbumShareLink::new(album_id, album_share_link_insert_inner.password, album_share_link_insert_inner.expiration, album_share_link_insert_inner.branding.clone(), album_share_link_insert_inner.allow_zip_download, album_share_link_insert_inner.bandwidth_limit);
+
+  // It would be better to return result and have different responses for each error kind.
+  // But it looks like that Diesel uses one error kind for multiple different errors and changes only the message.
+  let changed_rows = db::albums::insert_album_share_link(&conn, album_share_link.clone()).await?;
+  if changed_rows == 0 { return Err(Status::InternalServerError.into()) }
+
+  Ok(
+    Json(
+      SharedAlbumLinkResponse::new(album_share_link.uuid, album_share_link.expiration, album_share_link_insert_inner.branding, album_share_link.allow_zip_download, album_share_link.bandwidth_limit)
+    )
+  )
+}
+
+impl From<&AlbumShareLink> for SharedAlbumLinkResponse {
+  fn from(album_share_link: &AlbumShareLink) -> Self {
+    Self::new(album_share_link.uuid.clone(), album_share_link.expiration, album_share_link.branding(), album_share_link.allow_zip_download, album_share_link.bandwidth_limit)
+  }
+}
+
+/// Gets a list of album share links together with the number of their zip downloads and their traffic.
+#[openapi]
+#[get("/album/<album_uuid>/share/link")]
+pub async fn get_album_share_links(claims: Claims, conn: DbConn, config: &State<Config>, bandwidth_limiter: &State<BandwidthLimiter>, album_uuid: String) -> Result<Json<Vec<SharedAlbumLinkResponse>>, Status> {
+  let album_id_option = db::albums::select_album_id(&conn, album_uuid).await.map_err(errors::internal)?;
+  if album_id_option.is_none() {
+    return Err(Status::NotFound);
+  }
+
+  let album_id = album_id_option.unwrap();
+
+  let album = db::albums::select_album(&conn, album_id).await.map_err(errors::internal)?;
+  if album.is_none() { return Err(Status::NotFound) }
+
+  permissions::authorize_album(&conn, config.access_denied, claims.user_id, album_id, AlbumAction::ManageShareLinks).await?;
+
+  let links = db::albums::select_album_share_links(&conn, album_id).await;
+  if links.is_err() { return Err(Status::InternalServerError) }
+
+  let downloads = db::albums::select_album_share_link_downloads(&conn, album_id).await;
+  if downloads.is_err() { return Err(Status::InternalServerError) }
+
+  let downloads = downloads.unwrap();
+
+  let result = links.unwrap().iter()
+    .map(|link| {
+      let link_downloads: Vec<NaiveDateTime> = downloads.iter()
+        .filter(|(album_share_link_id, _)| *album_share_link_id == link.id)
+        .map(|(_, downloaded_at)| *downloaded_at)
+        .collect();
+
+      SharedAlbumLinkResponse::from(link)
+        .with_downloads(&link_downloads)
+        .with_bandwidth(bandwidth_limiter.link_stats(&link.uuid))
+    })
+    .collect::<Vec<SharedAlbumLinkResponse>>();
+
+  Ok(Json(result))
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct AlbumShareLinkBasic {
+  pub album_uuid: String,
+  pub is_password_protected: bool,
+  pub is_expired: bool,
+  /// Absolute expiration time.
+  pub expiration: Option<DateTime<Utc>>,
+  /// Number of seconds until the link expires; zero when it's already expired.
+  pub expires_in: Option<i64>,
+  #[serde(flatten)]
+  pub branding: AlbumShareLinkBranding,
+  /// Whether the whole album can be downloaded as a zip archive.
+  pub allow_zip_download: bool,
+}
+
+impl AlbumShareLinkBasic {
+  pub fn new(album_share_link: AlbumShareLink, album_uuid: String) -> Self {
+    let current_time = NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0);
+
+    Self {
+      album_uuid,
+      is_expired: album_share_link.expiration.is_some() && album_share_link.expiration.unwrap() < current_time,
+      is_password_protected: album_share_link.password.is_some(),
+      expiration: album_share_link.expiration.map(|expiration| DateTime::from_utc(expiration, Utc)),
+      expires_in: remaining_seconds(album_share_link.expiration),
+      branding: album_share_link.branding(),
+      allow_zip_download: album_share_link.allow_zip_download,
+     }
+  }
+}
+
+/// Gets basic information about album share link, including its branding.
+#[openapi]
+#[get("/album/share/link/<album_share_link_uuid>")]
+pub async fn get_album_share_link(conn: DbConn, album_share_link_uuid: String) -> Result<Json<AlbumShareLinkBasic>, Status> {
+  let album_share_link_result = db::albums::select_album_share_link_by_uuid(&conn, album_share_link_uuid).await;
+  if album_share_link_result.is_err() { return Err(Status::InternalServerError) }
+
+  let album_share_link_option = album_share_link_result.unwrap();
+  if album_share_link_option.is_none() { return Err(Status::NotFound) }
+
+  let album_share_link = album_share_link_option.unwrap();
+
+  let album = db::albums::select_album(&conn, album_share_link.album_id).await.map_err(errors::internal)?;
+  if album.is_none() { return Err(Status::InternalServerError)  }
+
+  Ok(
+    Json(
+      AlbumShareLinkBasic::new(album_share_link, album.unwrap().link)
+    )
+  )
+}
+
+/// Downloads the whole shared album as a zip archive.
+///
+/// Responds with 403 when the share link doesn't allow zip downloads.\
+/// Every download is recorded and shown to the album owner in the list of share links.
+#[openapi]
+#[get("/album/share/link/<album_share_link_uuid>/download")]
+pub async fn download_shared_album(shared_album_link_security: SharedAlbumLinkSecurity, conn: DbConn, bandwidth_limiter: &State<BandwidthLimiter>, album_share_link_uuid: String) -> Result<ZipDownload, Status> {
+  // the link in the authorization header must be the downloaded one
+  if shared_album_link_security.share_link_uuid() != album_share_link_uuid { return Err(Status::Unauthorized) }
+
+  let album_share_link_result = db::albums::select_album_share_link_by_uuid(&conn, album_share_link_uuid).await;
+  if album_share_link_result.is_err() { return Err(Status::InternalServerError) }
+
+  let album_share_link_option = album_share_link_result.unwrap();
+  if album_share_link_option.is_none() { return Err(Status::NotFound) }
+
+  let album_share_link = album_share_link_option.unwrap();
+  if remaining_seconds(album_share_link.expiration) == Some(0) { return Err(Status::Unauthorized) }
+
+  if !album_share_link.allow_zip_download { return Err(Status::Forbidden) }
+
+  let album = db::albums::select_album(&conn, album_share_link.album_id).await.map_err(errors::internal)?;
+  if album.is_none() { return Err(Status::NotFound) }
+
+  let album = album.unwrap();
+
+  let media = db::albums::get_album_media(&conn, album.id, MediaPagination::default()).await;
+  if media.is_err() { return Err(Status::InternalServerError) }
+
+  let mut files = vec![];
+  for media in media.unwrap() {
+    let path = scan::get_media_path(&conn, &media).await;
+    if path.is_none() { return Err(Status::InternalServerError) }
+
+    files.push((media.filename, path.unwrap()));
+  }
+
+  if files.is_empty() { return Err(Status::UnprocessableEntity) }
+
+  if db::albums::insert_album_share_link_download(&conn, album_share_link.id).await.is_err() {
+    error!("Download of album share link {} couldn't be recorded.", album_share_link.id);
+  }
+
+  let bandwidth = bandwidth_limiter.share_link(&album_share_link.uuid, album_share_link.bandwidth_limit);
+  let name = album_share_link.title.unwrap_or(album.name);
+
+  Ok(ZipDownload::throttled(files, zip_filename(&name), bandwidth))
+}
+
+/// How long signed URLs of a slideshow are valid.
+const SLIDESHOW_URL_HOURS: i64 = 6;
+
+/// Default number of seconds an image is shown in a slideshow.
+const SLIDESHOW_DEFAULT_INTERVAL: u32 = 10;
+
+#[derive(Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SlideshowMediaType {
+  Image,
+  Video,
+  Audio,
+}
+
+impl SlideshowMediaType {
+  /// Guesses the type from the file extension, so files don't have to be read.
+  fn from_filename(filename: &str) -> Self {
+    let extension = filename.rsplit('.').next().unwrap_or_default().to_lowercase();
+
+    match extension.as_str() {
+      "mp4" | "m4v" | "mkv" | "webm" | "mov" | "avi" | "wmv" | "mpg" | "mpeg" | "flv" => SlideshowMediaType::Video,
+      "mid" | "midi" | "mp3" | "m4a" | "ogg" | "flac" | "wav" | "amr" | "aac" => SlideshowMediaType::Audio,
+      _ => SlideshowMediaType::Image,
+    }
+  }
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct SlideshowItem {
+  media_uuid: String,
+  /// Signed URL of the media, usable without the authorization header.
+  url: String,
+  media_type: SlideshowMediaType,
+  /// Seconds the media is shown; `None` for videos and audio, which are played until their end.
+  duration: Option<u32>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct SlideshowResponse {
+  title: Option<String>,
+  items: Vec<SlideshowItem>,
+  /// Signed URLs stop working at this time.
+  expires_at: NaiveDateTime,
+  /// Signed URL of a fresh playlist with the same options; it should be fetched before `expires_at`.
+  refresh_url: String,
+}
+
+/// Returns the shared album as a playlist for TV and kiosk clients.
+///
+/// Instead of the authorization header, the playlist can also be requested with a `token` taken from `refresh_url`;
+/// tokens stop working when the password of the share link is changed.\
+/// All URLs in the playlist are signed and valid until `expires_at`.\
+/// `interval` sets how many seconds each image is shown, `shuffle` randomizes the order
+/// and `oriented` requests images with their EXIF orientation applied.\
+/// Media are ordered by the date taken otherwise.
+#[openapi]
+#[get("/album/share/link/<album_share_link_uuid>/slideshow?<token>&<shuffle>&<interval>&<oriented>")]
+pub async fn get_shared_album_slideshow(shared_album_link_security: Option<SharedAlbumLinkSecurity>, conn: DbConn, secret: &State<Secret>, album_share_link_uuid: String, token: Option<String>, shuffle: Option<bool>, interval: Option<u32>, oriented: Option<bool>) -> Result<Json<SlideshowResponse>, Status> {
+  let path = format!("/album/share/link/{}/slideshow", album_share_link_uuid);
+
+  let signed_url = match (&shared_album_link_security, token) {
+    (None, Some(token)) => SignedUrl::verify(&token, &path, secret),
+    _ => None,
+  };
+
+  let authorized_link = match (&shared_album_link_security, &signed_url) {
+    (Some(shared_album_link_security), _) => Some(shared_album_link_security.share_link_uuid().to_owned()),
+    (None, Some(signed_url)) => Some(signed_url.share_link_uuid().to_owned()),
+    (None, None) => None,
+  };
+
+  // the link in the authorization header or in the token must be the requested one
+  if authorized_link.as_deref() != Some(album_share_link_uuid.as_str()) { return Err(Status::Unauthorized) }
+
+  let album_share_link_result = db::albums::select_album_share_link_by_uuid(&conn, album_share_link_uuid.clone()).await;
+  if album_share_link_result.is_err() { return Err(Status::InternalServerError) }
+
+  let album_share_link_option = album_share_link_result.unwrap();
+  if album_share_link_option.is_none() { return Err(Status::NotFound) }
+
+  let album_share_link = album_share_link_option.unwrap();
+  if remaining_seconds(album_share_link.expiration) == Some(0) { return Err(Status::Unauthorized) }
+
+  let password_hash = album_share_link.password.as_deref();
+  if let Some(signed_url) = signed_url {
+    if !signed_url.matches_password(password_hash) { return Err(Status::Unauthorized) }
+  }
+
+  let media = db::albums::get_album_media(&conn, album_share_link.album_id, MediaPagination::default()).await;
+  if media.is_err() { return Err(Status::InternalServerError) }
+
+  let mut media = media.unwrap();
+  if shuffle == Some(true) {
+    use rand::seq::SliceRandom;
+    media.shuffle(&mut rand::thread_rng());
+  }
+
+  let interval = interval.unwrap_or(SLIDESHOW_DEFAULT_INTERVAL).clamp(1, 3600);
+  // the signed URLs must not outlive the share link
+  let expires_in = match remaining_seconds(album_share_link.expiration) {
+    Some(remaining) => Duration::seconds(remaining.min(SLIDESHOW_URL_HOURS * 3600)),
+    None => Duration::hours(SLIDESHOW_URL_HOURS),
+  };
+  let expires_at = Utc::now().naive_utc() + expires_in;
+
+  let mut items = vec![];
+  for media in media {
+    let media_path = format!("/media/{}", media.uuid);
+    let media_token = SignedUrl::new(album_share_link_uuid.clone(), password_hash, media_path.clone(), expires_in).encode(secret);
+    if media_token.is_err() { return Err(Status::InternalServerError) }
+
+    let media_type = SlideshowMediaType::from_filename(&media.filename);
+    // signed paths don't include the version, so tokens are accepted by the legacy routes too
+    let mut url = format!("{}{}?token={}", api_version::prefix(), media_path, media_token.unwrap());
+    if oriented == Some(true) && media_type == SlideshowMediaType::Image {
+      url.push_str("&oriented=true");
+    }
+
+    items.push(SlideshowItem {
+      media_uuid: media.uuid,
+      url,
+      media_type,
+      duration: (media_type == SlideshowMediaType::Image).then(|| interval),
+    });
+  }
+
+  let refresh_token = SignedUrl::new(album_share_link_uuid, password_hash, path.clone(), expires_in).encode(secret);
+  if refresh_token.is_err() { return Err(Status::InternalServerError) }
+
+  let refresh_url = format!(
+    "{}{}?token={}&shuffle={}&interval={}&oriented={}",
+    api_version::prefix(), path, refresh_token.unwrap(), shuffle == Some(true), interval, oriented == Some(true)
+  );
+
+  Ok(Json(SlideshowResponse { title: album_share_link.title, items, expires_at, refresh_url }))
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct CoViewSessionResponse {
+  /// Token visitors join the session with.
+  token: String,
+  expires_at: NaiveDateTime,
+}
+
+/// Starts a co-view session of the album, a slideshow watched together by visitors of its share links.
+///
+/// Visitors join it with the returned token at `/album/share/link/<album_share_link_uuid>/co-view/<session_token>`.
+/// Allowed only to the album owner; sessions are kept in memory until `expires_at` and end when the server restarts.
+#[openapi]
+#[post("/album/<album_uuid>/co-view")]
+pub async fn create_co_view_session(claims: Claims, conn: DbConn, config: &State<Config>, co_view_sessions: &State<CoViewSessions>, album_uuid: String) -> Result<Json<CoViewSessionResponse>, Status> {
+  let album_id_option = db::albums::select_album_id(&conn, album_uuid).await.map_err(errors::internal)?;
+  if album_id_option.is_none() { return Err(Status::NotFound) }
+
+  let album_id = album_id_option.unwrap();
+
+  permissions::authorize_album(&conn, config.access_denied, claims.user_id, album_id, AlbumAction::ManageShareLinks).await?;
+
+  let (token, expires_at) = co_view_sessions.create(album_id, claims.user_id);
+
+  Ok(Json(CoViewSessionResponse { token, expires_at }))
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct CoViewUpdateResponse {
+  /// Visitors which received the position.
+  viewers: usize,
+}
+
+/// Sends the position of the slideshow to all visitors of the co-view session.
+///
+/// Allowed only to the user who started the session.
+#[openapi]
+#[put("/album/co-view/<session_token>", data = "<position>", format = "json")]
+pub async fn update_co_view_session(claims: Claims, co_view_sessions: &State<CoViewSessions>, session_token: String, position: TimedJson<CoViewPosition>) -> Result<Json<CoViewUpdateResponse>, Status> {
+  let viewers = co_view_sessions.update(&session_token, claims.user_id, position.into_inner())?;
+
+  Ok(Json(CoViewUpdateResponse { viewers }))
+}
+
+/// Ends the co-view session; the event streams of its visitors are closed.
+#[openapi]
+#[delete("/album/co-view/<session_token>")]
+pub async fn delete_co_view_session(claims: Claims, co_view_sessions: &State<CoViewSessions>, session_token: String) -> Result<Status, Status> {
+  co_view_sessions.end(&session_token, claims.user_id)?;
+
+  Ok(Status::Ok)
+}
+
+/// Joins a co-view session of the shared album.
+///
+/// Returns server-sent events with the position of the slideshow: the current one right away
+/// and then every change made by the owner; the stream ends with the session.
+#[openapi]
+#[get("/album/share/link/<album_share_link_uuid>/co-view/<session_token>")]
+pub async fn join_co_view_session(shared_album_link_security: SharedAlbumLinkSecurity, conn: DbConn, co_view_sessions: &State<CoViewSessions>, shutdown: Shutdown, album_share_link_uuid: String, session_token: String) -> Result<CoViewEvents, Status> {
+  // the link in the authorization header must be the requested one
+  if shared_album_link_security.share_link_uuid() != album_share_link_uuid { return Err(Status::Unauthorized) }
+
+  let album_share_link_result = db::albums::select_album_share_link_by_uuid(&conn, album_share_link_uuid).await;
+  if album_share_link_result.is_err() { return Err(Status::InternalServerError) }
+
+  let album_share_link_option = album_share_link_result.unwrap();
+  if album_share_link_option.is_none() { return Err(Status::NotFound) }
+
+  let album_share_link = album_share_link_option.unwrap();
+  if remaining_seconds(album_share_link.expiration) == Some(0) { return Err(Status::Unauthorized) }
+
+  co_view_sessions.join(&session_token, album_share_link.album_id, shutdown).ok_or(Status::NotFound)
+}
+
+/// Creates a file name of a zip archive which is safe to use in the `Content-Disposition` header.
+fn zip_filename(name: &str) -> String {
+  let safe: String = name.chars()
+    .filter(|c| c.is_ascii_alphanumeric() || *c == ' ' || *c == '-' || *c == '_')
+    .collect();
+
+  match safe.trim() {
+    "" => String::from("album.zip"),
+    safe => format!("{}.zip", safe),
+  }
+}
+
+/// Updates already existing album share link.
+///
+/// The expiration can be set either as an absolute `expiration` or as a relative `expires_in`.\
+/// The branding is replaced too, so fields which are not sent are removed.
+#[openapi]
+#[put("/album/share/link/<album_share_link_uuid>", data = "<album_share_link_insert>", format = "json")]
+pub async fn update_album_share_link(claims: Claims, conn: DbConn, config: &State<Config>, album_share_link_uuid: String, album_share_link_insert: TimedJson<AlbumShareLinkInsert>) -> Result<Status, ApiError> {
+  let album_share_link = db::albums::select_album_share_link_by_uuid(&conn, album_share_link_uuid).await?.ok_or(Status::NotFound)?;
+
+  let album = db::albums::select_album(&conn, album_share_link.album_id).await?;
+  if album.is_none() { return Err(Status::NotFound.into()) }
+
+  permissions::authorize_album(&conn, config.access_denied, claims.user_id, album_share_link.album_id, AlbumAction::ManageShareLinks).await?;
+
+  let normalized = album_share_link_insert.into_inner().normalize_expiration().map_err(|_| Status::UnprocessableEntity)?;
+  let mut album_share_link_insert = normalized.normalize_and_hash_password().await;
+
+  album_share_link_insert.branding = album_share_link_insert.branding.normalize();
+  album_share_link_insert.branding.validate()?;
+  album_share_link_insert.bandwidth_limit = album_share_link_insert.bandwidth_limit.filter(|limit| *limit > 0);
+
+  let changed_rows = db::albums::update_album_share_link(&conn, album_share_link.id, album_share_link_insert).await?;
+  if changed_rows == 0 {
+    return Ok(Status::NoContent);
+  }
+
+  Ok(Status::Ok)
+}
+
+/// Deletes an album share link.
+#[openapi]
+#[delete("/album/share/link/<album_share_link_uuid>")]
+pub async fn delete_album_share_link(claims: Claims, conn: DbConn, config: &State<Config>, album_share_link_uuid: String) -> Result<Status, Status> {
+  let album_share_link_result = db::albums::select_album_share_link_by_uuid(&conn, album_share_link_uuid.clone()).await;
+  if album_share_link_result.is_err() { return Err(Status::InternalServerError) }
+
+  let album_share_link = album_share_link_result.unwrap();
+  if album_share_link.is_none() { return Err(Status::NotFound) }
+
+  let album_id = album_share_link.unwrap().album_id;
+
+  let album = db::albums::select_album(&conn, album_id).await.map_err(errors::internal)?;
+  if album.is_none() { return Err(Status::NotFound) }
+
+  permissions::authorize_album(&conn, config.access_denied, claims.user_id, album_id, AlbumAction::ManageShareLinks).await?;
+
+  let deleted = db::albums::delete_album_share_link(&conn, album_share_link_uuid).await;
+  if deleted.is_err() { return Err(Status::InternalServerError) }
+
+  if deleted.unwrap() == 0 {
+    return Ok(Status::NoContent);
+  }
+
+  Ok(Status::Ok)
+}
+
+/// Maximum number of accesses returned by the activity of a share link.
+const SHARE_LINK_ACTIVITY_LIMIT: i64 = 500;
+
+#[derive(Serialize, JsonSchema)]
+pub struct ShareLinkAccessResponse {
+  accessed_at: DateTime<Utc>,
+  ip_address: Option<String>,
+  user_agent: Option<String>,
+  /// Whether the visitor knew the password.
+  succeeded: bool,
+}
+
+/// Gets the latest accesses through an album share link, newest first.
+///
+/// Wrong passwords are listed every time, successful accesses of a visitor at most once an hour.
+#[openapi]
+#[get("/album/<album_uuid>/share/link/<album_share_link_uuid>/activity")]
+pub async fn get_album_share_link_activity(claims: Claims, conn: DbConn, config: &State<Config>, album_uuid: String, album_share_link_uuid: String) -> Result<Json<Vec<ShareLinkAccessResponse>>, Status> {
+  let album_id_option = db::albums::select_album_id(&conn, album_uuid).await.map_err(errors::internal)?;
+  if album_id_option.is_none() { return Err(Status::NotFound) }
+
+  let album_id = album_id_option.unwrap();
+
+  permissions::authorize_album(&conn, config.access_denied, claims.user_id, album_id, AlbumAction::ManageShareLinks).await?;
+
+  let album_share_link_result = db::albums::select_album_share_link_by_uuid(&conn, album_share_link_uuid).await;
+  if album_share_link_result.is_err() { return Err(Status::InternalServerError) }
+
+  let album_share_link = match album_share_link_result.unwrap() {
+    Some(album_share_link) if album_share_link.album_id == album_id => album_share_link,
+    _ => return Err(Status::NotFound),
+  };
+
+  let accesses = db::albums::select_album_share_link_accesses(&conn, album_share_link.id, SHARE_LINK_ACTIVITY_LIMIT).await;
+  if accesses.is_err() { return Err(Status::InternalServerError) }
+
+  let result = accesses.unwrap().into_iter()
+    .map(|access| ShareLinkAccessResponse {
+      accessed_at: DateTime::from_utc(access.accessed_at, Utc),
+      ip_address: access.ip_address,
+      user_agent: access.user_agent,
+      succeeded: access.succeeded,
+    })
+    .collect();
+
+  Ok(Json(result))
+}
+
+/// Returns folders of the authenticated user whose directories disappeared (a dry run).
+///
+/// Every scan removes such folders when they have no media, not even in their subfolders,
+/// and marks the other ones as missing until their directories appear again.
+#[openapi]
+#[get("/user/scan/folders")]
+pub async fn get_orphaned_folders(claims: Claims, conn: DbConn) -> Result<Json<FolderReconciliation>, Status> {
+  let gallery = Directories::new().and_then(|directories| directories.gallery());
+  if gallery.is_none() { return Err(Status::InternalServerError) }
+
+  let report = scan::find_orphaned_folders(&conn, gallery.unwrap(), claims.user_id).await;
+  if report.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(Json(report.unwrap()))
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct MissingMedia {
+  media_uuid: String,
+  filename: String,
+  /// Path of the folder relative to the gallery directory, e.g. `john/Holiday`.
+  folder: String,
+  /// Since when the file is missing (UTC).
+  missing_since: NaiveDateTime,
+}
+
+/// Returns the path of the folder relative to the gallery directory.
+fn folder_path(folders: &HashMap<i32, Folder>, folder_id: i32) -> String {
+  let mut names = vec![];
+  let mut current = folders.get(&folder_id);
+
+  while let Some(folder) = current {
+    names.push(folder.name.as_str());
+    current = folder.parent.and_then(|parent| folders.get(&parent));
+  }
+
+  names.into_iter().rev().collect::<PathBuf>().to_string_lossy().into_owned()
+}
+
+/// Returns media of the authenticated user whose files weren't found by a scan.
+///
+/// Such media are left out of listings until their files appear again;
+/// they can be purged with `DELETE /user/scan/media` or relinked with `/user/scan/media/<media_uuid>/relink`.
+#[openapi]
+#[get("/user/scan/media")]
+pub async fn get_missing_media(claims: Claims, conn: DbConn) -> Result<Json<Vec<MissingMedia>>, Status> {
+  let media = db::media::select_missing_media(&conn, claims.user_id).await;
+  if media.is_err() { return Err(Status::InternalServerError) }
+
+  let folders = db::folders::select_user_folders(&conn, claims.user_id).await;
+  if folders.is_err() { return Err(Status::InternalServerError) }
+
+  let folders: HashMap<i32, Folder> = folders.unwrap().into_iter().map(|folder| (folder.id, folder)).collect();
+
+  let missing = media.unwrap().into_iter()
+    .filter_map(|media| Some(MissingMedia {
+      folder: folder_path(&folders, media.folder_id),
+      missing_since: media.missing_since?,
+      media_uuid: media.uuid,
+      filename: media.filename,
+    }))
+    .collect();
+
+  Ok(Json(missing))
+}
+
+/// Deletes media of the authenticated user whose files are missing, or only the one with `media_uuid`.
+///
+/// Their albums, likes, tags and edits are deleted too. Responds with 404 when `media_uuid` isn't missing media of the user.
+#[openapi]
+#[delete("/user/scan/media?<media_uuid>")]
+pub async fn purge_missing_media(claims: Claims, conn: DbConn, media_uuid: Option<String>) -> Result<Status, Status> {
+  let single = media_uuid.is_some();
+
+  let deleted = db::media::delete_missing_media(&conn, claims.user_id, media_uuid).await;
+  if deleted.is_err() { return Err(Status::InternalServerError) }
+
+  let hashes = deleted.unwrap();
+  if single && hashes.is_empty() { return Err(Status::NotFound) }
+
+  derivatives::remove_derivatives_of_deleted(&conn, hashes).await;
+
+  Ok(Status::Ok)
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct MediaRelink {
+  /// Path of the file relative to the gallery directory, e.g. `john/Holiday/IMG_0001.jpg`.
+  path: String,
+}
+
+/// Points media whose file is missing to another file of the authenticated user, e.g. after it was moved.
+///
+/// The file's hash becomes the hash of the original.\
+/// Responds with 409 when the media isn't missing or another media already has the file,
+/// and with 422 when the path isn't a supported file in the user's folders.
+#[openapi]
+#[post("/user/scan/media/<media_uuid>/relink", data = "<relink>", format = "json")]
+pub async fn relink_missing_media(claims: Claims, conn: DbConn, config: &State<Config>, media_uuid: String, relink: TimedJson<MediaRelink>) -> Result<Status, Status> {
+  let media = db::media::select_media_by_uuid(&conn, media_uuid.clone()).await;
+  if media.is_err() { return Err(Status::InternalServerError) }
+
+  let media = media.unwrap().ok_or(Status::NotFound)?;
+
+  permissions::authorize_media(&conn, config.access_denied, claims.user_id, media_uuid, MediaAction::Manage).await?;
+
+  if media.missing_since.is_none() { return Err(Status::Conflict) }
+
+  let gallery = Directories::new().and_then(|directories| directories.gallery());
+  if gallery.is_none() { return Err(Status::InternalServerError) }
+
+  let username = db::users::get_user_username(&conn, claims.user_id).await.map_err(errors::internal)?;
+  if username.is_none() { return Err(Status::InternalServerError) }
+
+  let username = username.unwrap();
+
+  // the path must be a file in one of the user's folders, without `..` leading out of them
+  let relative = PathBuf::from(relink.into_inner().path);
+  let valid = relative.components().all(|component| matches!(component, std::path::Component::Normal(_)))
+    && relative.iter().next().map_or(false, |root| root == username.as_str())
+    && relative.iter().count() > 1;
+
+  if !valid { return Err(Status::UnprocessableEntity) }
+
+  let libraries = libraries::UserLibraries::load(&conn, &gallery.unwrap(), claims.user_id, &username).await;
+  if libraries.is_err() { return Err(Status::InternalServerError) }
+
+  let path = libraries.unwrap().resolve(&relative);
+  if !path.is_file() || !scan::is_media_supported(&path) { return Err(Status::UnprocessableEntity) }
+
+  let filename = relative.file_name().and_then(|filename| filename.to_str()).map(str::to_owned);
+  if filename.is_none() { return Err(Status::UnprocessableEntity) }
+
+  let filename = filename.unwrap();
+
+  let folder_id = scan::select_or_insert_folder_id(&conn, relative.parent().unwrap_or(&relative).to_path_buf(), claims.user_id).await;
+  if folder_id.is_none() { return Err(Status::InternalServerError) }
+
+  let folder_id = folder_id.unwrap();
+
+  let exists = db::media::media_file_exists(&conn, folder_id, filename.clone()).await;
+  if exists.is_err() { return Err(Status::InternalServerError) }
+  if exists.unwrap() { return Err(Status::Conflict) }
+
+  let stat = LocalFilesystem.stat(&path);
+  if stat.is_none() { return Err(Status::UnprocessableEntity) }
+
+  let sha2_512 = rocket::tokio::task::spawn_blocking(move || hash_file(&path, SHA2512)).await;
+  if sha2_512.is_err() { return Err(Status::InternalServerError) }
+
+  let result = db::media::relink_media(&conn, media, folder_id, filename, stat.unwrap(), sha2_512.unwrap()).await;
+  if result.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(Status::Ok)
+}
+
+/// Maximum number of scan ignore patterns of one user.
+const MAX_SCAN_IGNORE_PATTERNS: usize = 100;
+
+/// Returns scan ignore patterns of the authenticated user.
+#[openapi]
+#[get("/user/scan/ignore")]
+pub async fn get_scan_ignore_patterns(claims: Claims, conn: DbConn) -> Result<Json<Vec<String>>, Status> {
+  let patterns = db::scan::select_scan_ignore_patterns(&conn, claims.user_id).await;
+  if patterns.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(Json(patterns.unwrap()))
+}
+
+/// Replaces scan ignore patterns of the authenticated user.
+///
+/// Patterns are globs; patterns without a slash match names of files and folders anywhere in the gallery
+/// (e.g. `RAW` or `*.tmp`), other patterns match paths relative to the user's gallery folder (e.g. `Photos/RAW`).
+/// Changes are applied on the next scan.
+#[openapi]
+#[put("/user/scan/ignore", data = "<patterns>", format = "json")]
+pub async fn update_scan_ignore_patterns(claims: Claims, conn: DbConn, patterns: TimedJson<Vec<String>>) -> Result<Status, Status> {
+  let mut patterns = patterns.into_inner();
+  patterns.sort();
+  patterns.dedup();
+
+  if patterns.len() > MAX_SCAN_IGNORE_PATTERNS { return Err(Status::UnprocessableEntity) }
+
+  if patterns.iter().any(|pattern| pattern.chars().count() > 255 || !scan::IgnorePatterns::is_valid(pattern)) {
+    return Err(Status::UnprocessableEntity);
+  }
+
+  let result = db::scan::replace_scan_ignore_patterns(&conn, claims.user_id, patterns).await;
+  if result.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(Status::Ok)
+}
+
+/// Searches for new media
+///
+/// The scan is recorded as a job (see `/jobs`); scans interrupted by a restart are resumed automatically.
+// https://api.rocket.rs/master/rocket/struct.State.html
+#[openapi]
+#[get("/scan_media")]
+pub async fn scan_media(claims: Claims, conn: DbConn, config: &State<Config>) -> &'static str {
+  let job = db::jobs::insert_job(&conn, NewJob::new(claims.user_id, JobKind::Scan)).await;
+  if job.is_err() { return "false"; }
+
+  match jobs::run_scan(&conn, &job.unwrap(), config.scan_symlinks, config.scan_duplicates, config.scan_alerts, config.gallery_check).await {
+    JobState::Finished => "true",
+    _ => "false",
+  }
+}
+
+// TODO: rewrite later and use forwarding (ranks)
+// problem seems to be in okapi as it overwrites the route when there are multiple ranks
+// while the Request guards are wrapped in Option, there are no error codes from that Request guards
+/// Returns a media
+///
+/// With `oriented=true`, images are returned with their EXIF orientation applied to the pixels,
+/// for clients which can't rotate them on their own (some TVs and photo frames).\
+/// `token` is a signed URL token from a shared album slideshow, used instead of the authorization header.\
+/// Responds with 429 when the media is a video or larger than 32 MiB and the user, or the visitor of the share link,
+/// already streams `max_streams_per_user` such media.
+#[openapi]
+#[get("/media/<media_uuid>?<oriented>&<token>")]
+pub async fn get_media_by_uuid(shared_album_link_security: Option<SharedAlbumLinkSecurity>, claims_option: Option<Claims>, conn: DbConn, secret: &State<Secret>, config: &State<Config>, stream_limiter: &State<StreamLimiter>, bandwidth_limiter: &State<BandwidthLimiter>, address: Option<IpAddr>, media_uuid: String, oriented: Option<bool>, token: Option<String>) -> Option<Result<MediaStream, TooManyStreams>> {
+  let media: Media = db::media::select_media_by_uuid(&conn, media_uuid).await.ok()??;
+
+  // the file of missing media isn't there
+  if media.missing_since.is_some() { return None }
+
+  let stream_owner = if let Some(claims) = claims_option {
+    let access = db::media::media_user_has_access(&conn, media.uuid.clone(), claims.user_id).await;
+    if !access.ok()? {
+      return None;
+    }
+
+    // TODO: check if non-owner user has permission to access the album (preparation for shared albums)
+
+    StreamOwner::User(claims.user_id)
+  } else if let Some(shared_album_link_security) = shared_album_link_security {
+    permissions::authorize_share_link_media(&conn, shared_album_link_security.album_id(), media.id).await.ok()?;
+
+    StreamOwner::ShareLink(shared_album_link_security.share_link_uuid().to_owned(), address)
+  } else if let Some(token) = token {
+    // signed URLs are issued only for media of the share link's album
+    let signed_url = SignedUrl::verify(&token, &format!("/media/{}", media.uuid), secret)?;
+    let album_share_link = db::albums::select_album_share_link_by_uuid(&conn, signed_url.share_link_uuid().to_owned()).await.ok()??;
+    if remaining_seconds(album_share_link.expiration) == Some(0) { return None }
+    if !signed_url.matches_password(album_share_link.password.as_deref()) { return None }
+
+    permissions::authorize_share_link_media(&conn, album_share_link.album_id, media.id).await.ok()?;
+
+    StreamOwner::ShareLink(album_share_link.uuid, address)
+  } else {
+    return None;
+  };
+
+  // visitors of share links are limited by the bandwidth of the link
+  let bandwidth = match &stream_owner {
+    StreamOwner::ShareLink(album_share_link_uuid, _) => {
+      let album_share_link = db::albums::select_album_share_link_by_uuid(&conn, album_share_link_uuid.clone()).await.ok()??;
+      bandwidth_limiter.share_link(album_share_link_uuid, album_share_link.bandwidth_limit)
+    },
+    StreamOwner::User(_) => Bandwidth::unlimited(),
+  };
+
+  let mut path = scan::get_media_path(&conn, &media).await?;
+
+  let permit = match stream_limit::is_limited(&path, transcode::is_video(&media.filename)).await {
+    true => match stream_limiter.acquire(stream_owner, config.max_streams_per_user) {
+      Some(permit) => Some(permit),
+      None => return Some(Err(TooManyStreams)),
+    },
+    false => None,
+  };
+
+  if oriented == Some(true) {
+    path = match derivatives::oriented_media(&media.sha2_512, path.clone()).await {
+      Ok(oriented_path) => oriented_path,
+      Err(err) => {
+        warn!("Media {} couldn't be oriented, serving the original: {}", media.uuid, err);
+        path
+      },
+    };
+  }
+
+  MediaStream::throttled(&path, permit, bandwidth).await.ok().map(Ok)
+}
+
+/// Returns a video in a format browsers can play.
+///
+/// Videos browsers can't play (Matroska, AVI, WMV, ...) are transcoded into MP4 on the first request,
+/// which responds with 202 and the `Retry-After` header until the video is ready.
+/// Other videos are returned as they are.\
+/// Responds with 415 when the media isn't a video and with 500 when transcoding failed.\
+/// Responds with 429 when the user already streams `max_streams_per_user` media.
+#[openapi]
+#[get("/media/<media_uuid>/playback")]
+pub async fn get_media_playback(claims: Claims, conn: DbConn, config: &State<Config>, stream_limiter: &State<StreamLimiter>, transcoder: &State<Transcoder>, media_uuid: String) -> Result<Result<Playback, TooManyStreams>, Status> {
+  let media = db::media::select_media_by_uuid(&conn, media_uuid.clone()).await;
+  if media.is_err() { return Err(Status::InternalServerError) }
+
+  let media_option = media.unwrap();
+  if media_option.is_none() { return Err(Status::NotFound) }
+
+  permissions::authorize_media(&conn, config.access_denied, claims.user_id, media_uuid, MediaAction::View).await?;
+
+  let media = media_option.unwrap();
+  if !transcode::is_video(&media.filename) { return Err(Status::UnsupportedMediaType) }
+
+  let original = scan::get_media_path(&conn, &media).await;
+  if original.is_none() { return Err(Status::NotFound) }
+
+  let mut path = original.unwrap();
+  if transcode::needs_transcoding(&media.filename) {
+    path = match transcoder.playback(&media.sha2_512, path).await {
+      Ok(TranscodeStatus::Ready(path)) => path,
+      Ok(TranscodeStatus::Pending) => return Ok(Ok(Playback::Pending)),
+      Ok(TranscodeStatus::Failed) => return Err(Status::InternalServerError),
+      Err(err) => {
+        error!("Transcoding of media {} couldn't be started: {}", media.uuid, err);
+        return Err(Status::InternalServerError);
+      },
+    };
+  }
+
+  let permit = match stream_limiter.acquire(StreamOwner::User(claims.user_id), config.max_streams_per_user) {
+    Some(permit) => permit,
+    None => return Ok(Err(TooManyStreams)),
+  };
+
+  match MediaStream::open(&path, permit).await {
+    Ok(stream) => Ok(Ok(Playback::Stream(Box::new(stream)))),
+    Err(_) => Err(Status::NotFound),
+  }
+}
+
+/// Returns the preview strip of a video, a grid of 10x10 frames taken at regular intervals used for scrubbing its timeline.
+///
+/// The strip is created on the first request, which responds with 202 and the `Retry-After` header until it's ready.
+/// The positions of the frames are described by `/media/<media_uuid>/preview-strip.vtt`.\
+/// Responds with 415 when the media isn't a video and with 500 when the strip couldn't be created.\
+/// Responds with 429 when the user already streams `max_streams_per_user` media.
+#[openapi]
+#[get("/media/<media_uuid>/preview-strip")]
+pub async fn get_media_preview_strip(claims: Claims, conn: DbConn, config: &State<Config>, stream_limiter: &State<StreamLimiter>, transcoder: &State<Transcoder>, media_uuid: String) -> Result<Result<Playback, TooManyStreams>, Status> {
+  preview_strip(claims, conn, config, stream_limiter, transcoder, media_uuid, false).await
+}
+
+/// Returns WebVTT thumbnails of a video: cues mapping time ranges to frames of its preview strip (`preview-strip#xywh=x,y,w,h`).
+///
+/// Responds like `/media/<media_uuid>/preview-strip`.
+#[openapi]
+#[get("/media/<media_uuid>/preview-strip.vtt")]
+pub async fn get_media_preview_strip_vtt(claims: Claims, conn: DbConn, config: &State<Config>, stream_limiter: &State<StreamLimiter>, transcoder: &State<Transcoder>, media_uuid: String) -> Result<Result<Playback, TooManyStreams>, Status> {
+  preview_strip(claims, conn, config, stream_limiter, transcoder, media_uuid, true).await
+}
+
+async fn preview_strip(claims: Claims, conn: DbConn, config: &State<Config>, stream_limiter: &State<StreamLimiter>, transcoder: &State<Transcoder>, media_uuid: String, vtt: bool) -> Result<Result<Playback, TooManyStreams>, Status> {
+  let media = db::media::select_media_by_uuid(&conn, media_uuid.clone()).await;
+  if media.is_err() { return Err(Status::InternalServerError) }
+
+  let media_option = media.unwrap();
+  if media_option.is_none() { return Err(Status::NotFound) }
+
+  permissions::authorize_media(&conn, config.access_denied, claims.user_id, media_uuid, MediaAction::View).await?;
+
+  let media = media_option.unwrap();
+  if !transcode::is_video(&media.filename) { return Err(Status::UnsupportedMediaType) }
+
+  let original = scan::get_media_path(&conn, &media).await;
+  if original.is_none() { return Err(Status::NotFound) }
+
+  let path = match transcoder.preview_strip(&media.sha2_512, original.unwrap()).await {
+    Ok(TranscodeStatus::Ready(path)) => path,
+    Ok(TranscodeStatus::Pending) => return Ok(Ok(Playback::Pending)),
+    Ok(TranscodeStatus::Failed) => return Err(Status::InternalServerError),
+    Err(err) => {
+      error!("Preview strip of media {} couldn't be started: {}", media.uuid, err);
+      return Err(Status::InternalServerError);
+    },
+  };
+
+  let path = if vtt { transcode::preview_strip_vtt(&path) } else { path };
+
+  let permit = match stream_limiter.acquire(StreamOwner::User(claims.user_id), config.max_streams_per_user) {
+    Some(permit) => permit,
+    None => return Ok(Err(TooManyStreams)),
+  };
+
+  match MediaStream::open(&path, permit).await {
+    Ok(stream) => Ok(Ok(Playback::Stream(Box::new(stream)))),
+    Err(_) => Err(Status::NotFound),
+  }
+}
+
+/// Maximum number of media downloaded as one archive.
+const MAX_DOWNLOADED_MEDIA: usize = 1000;
+
+/// Downloads the selected media (at most 1000) as a zip archive.
+///
+/// The archive is streamed while it's being created.
+/// Responds with 404 when any of the media doesn't exist or when the user has no access to it
+/// (or with 403, depending on the `access_denied` configuration), with 413 when too many media are selected
+/// and with 500 when any of the files can't be read; nothing is streamed in these cases.
+#[openapi]
+#[post("/media/download", data = "<media_uuids>", format = "json")]
+pub async fn download_media(claims: Claims, conn: DbConn, config: &State<Config>, media_uuids: TimedJson<Vec<String>>) -> Result<ZipDownload, Status> {
+  let media_uuids = media_uuids.into_inner();
+  if media_uuids.len() > MAX_DOWNLOADED_MEDIA { return Err(Status::PayloadTooLarge) }
+
+  let mut files = vec![];
+
+  for media_uuid in media_uuids {
+    let media = db::media::select_media_by_uuid(&conn, media_uuid.clone()).await;
+    if media.is_err() { return Err(Status::InternalServerError) }
+
+    let media_option = media.unwrap();
+    if media_option.is_none() { return Err(Status::NotFound) }
+
+    permissions::authorize_media(&conn, config.access_denied, claims.user_id, media_uuid, MediaAction::View).await?;
+
+    let media = media_option.unwrap();
+
+    let path = scan::get_media_path(&conn, &media).await;
+    if path.is_none() { return Err(Status::InternalServerError) }
+
+    files.push((media.filename, path.unwrap()));
+  }
+
+  if files.is_empty() { return Err(Status::UnprocessableEntity) }
+
+  if let Err(path) = ZipDownload::check_readable(&files).await {
+    error!("Media file {} can't be read for a download.", path.display());
+    return Err(Status::InternalServerError);
+  }
+
+  Ok(ZipDownload::new(files, String::from("galera.zip")))
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct MediaUploadResponse {
+  /// UUID of the uploaded media; `None` when the upload was rejected.
+  pub media_uuid: Option<String>,
+  /// SHA-512 of the received file computed by the server.
+  pub sha2_512: String,
+  /// Reason why the upload was rejected.
+  pub rejection: Option<UploadRejection>,
+}
+
+/// Checks whether the name can be used as a name of an uploaded file.
+fn is_upload_filename_valid(filename: &str) -> bool {
+  !filename.is_empty()
+    && filename.chars().count() <= 255
+    && filename != "."
+    && filename != ".."
+    && !filename.contains(|c| c == '/' || c == '\\' || c == '\0')
+}
+
+/// Selects the root folder of the user; it's added to the database when the user's folder wasn't scanned yet.
+async fn select_or_insert_root_folder(conn: &DbConn, user_id: i32, username: &str) -> Option<Folder> {
+  let mut root_folder = db::folders::select_root_folder(conn, user_id).await;
+  if let Ok(None) = root_folder {
+    scan::add_folders_to_db(conn, vec![PathBuf::from(username)], user_id).await;
+    root_folder = db::folders::select_root_folder(conn, user_id).await;
+  }
+
+  root_folder.ok().flatten()
+}
+
+/// Uploads a media to the root folder of the authenticated user.
+///
+/// The body contains the raw file, its maximum size is set by the `upload` limit (1 GiB by default).\
+/// When `sha2_512` is set and doesn't match the SHA-512 of the received file, the file is discarded
+/// and the response is 422 with the hash computed by the server, so clients can detect corruption in transit.\
+/// Images are decoded before they are stored. Corrupt images and images whose extension doesn't match their
+/// content are quarantined and the response is 422 with the reason; unsupported files are rejected with 415.\
+/// The request is validated before the body is read, so clients using `Expect: 100-continue`
+/// don't have to send the body of uploads that would be rejected.\
+/// Responds with 408 when the body isn't received within the timeout of the route (see `timeouts`).
+#[openapi]
+#[post("/media/upload?<filename>&<sha2_512>", data = "<data>")]
+pub async fn upload_media(claims: Claims, conn: DbConn, limits: &Limits, body_timeout: BodyTimeout, filename: String, sha2_512: Option<String>, data: Data<'_>) -> Result<(Status, Json<MediaUploadResponse>), Status> {
+  if !is_upload_filename_valid(&filename) { return Err(Status::UnprocessableEntity) }
+
+  let username = db::users::get_user_username(&conn, claims.user_id).await.map_err(errors::internal)?;
+  if username.is_none() { return Err(Status::InternalServerError) }
+
+  let username = username.unwrap();
+
+  let directories = Directories::new();
+  if directories.is_none() { return Err(Status::InternalServerError) }
+
+  let directories = directories.unwrap();
+
+  let gallery = directories.gallery();
+  let uploads = directories.uploads();
+  if gallery.is_none() || uploads.is_none() { return Err(Status::InternalServerError) }
+
+  let user_directory = libraries::user_directory(&conn, &gallery.unwrap(), claims.user_id, &username).await;
+  if user_directory.is_none() { return Err(Status::InternalServerError) }
+
+  let user_directory = user_directory.unwrap();
+
+  let root_folder = select_or_insert_root_folder(&conn, claims.user_id, &username).await;
+  if root_folder.is_none() { return Err(Status::InternalServerError) }
+
+  let root_folder = root_folder.unwrap();
+
+  // rejects the upload before its body is read; a file created meanwhile is detected when the upload is stored
+  let path = user_directory.join(&filename);
+  if path.exists() { return Err(Status::Conflict) }
+
+  // everything was validated, so the body can be read now
+  let temporary_path = uploads.unwrap().join(nanoid!());
+
+  let written = body_timeout.read(data.open(limits.get("upload").unwrap_or_else(|| 1.gibibytes())).into_file(&temporary_path)).await;
+  if let Err(status) = written {
+    rocket::tokio::fs::remove_file(&temporary_path).await.ok();
+    return Err(status);
+  }
+
+  let written = written.unwrap();
+  if written.is_err() {
+    rocket::tokio::fs::remove_file(&temporary_path).await.ok();
+    return Err(Status::InternalServerError);
+  }
+
+  if !written.unwrap().is_complete() {
+    rocket::tokio::fs::remove_file(&temporary_path).await.ok();
+    return Err(Status::PayloadTooLarge);
+  }
+
+  let hashed_path = temporary_path.clone();
+  let hash = rocket::tokio::task::spawn_blocking(move || hash_file(&hashed_path, SHA2512)).await;
+  if hash.is_err() {
+    rocket::tokio::fs::remove_file(&temporary_path).await.ok();
+    return Err(Status::InternalServerError);
+  }
+
+  let hash = hash.unwrap();
+
+  if let Some(expected) = sha2_512 {
+    if !expected.eq_ignore_ascii_case(&hash) {
+      rocket::tokio::fs::remove_file(&temporary_path).await.ok();
+      return Ok((Status::UnprocessableEntity, Json(MediaUploadResponse { media_uuid: None, sha2_512: hash, rejection: Some(UploadRejection::HashMismatch) })));
+    }
+  }
+
+  let checked_path = temporary_path.clone();
+  let checked_filename = filename.clone();
+  let image_dimensions = rocket::tokio::task::spawn_blocking(move || upload::check_upload(&checked_path, &checked_filename)).await;
+  if image_dimensions.is_err() {
+    rocket::tokio::fs::remove_file(&temporary_path).await.ok();
+    return Err(Status::InternalServerError);
+  }
+
+  let image_dimensions = image_dimensions.unwrap();
+  if let Err(rejection) = image_dimensions {
+    let status = match rejection {
+      UploadRejection::Unsupported { .. } => Status::UnsupportedMediaType,
+      _ => Status::UnprocessableEntity,
+    };
+
+    if rejection.is_suspicious() {
+      upload::quarantine(&temporary_path, &filename, &rejection).await;
+    } else {
+      rocket::tokio::fs::remove_file(&temporary_path).await.ok();
+    }
+
+    return Ok((status, Json(MediaUploadResponse { media_uuid: None, sha2_512: hash, rejection: Some(rejection) })));
+  }
+
+  if let Err(err) = upload::store(&temporary_path, &path).await {
+    rocket::tokio::fs::remove_file(&temporary_path).await.ok();
+
+    return match err.kind() {
+      std::io::ErrorKind::AlreadyExists => Err(Status::Conflict),
+      _ => Err(Status::InternalServerError),
+    };
+  }
+
+  let media_uuid = db::media::insert_media(&conn, filename, root_folder, claims.user_id, image_dimensions.unwrap(), None, path).await.map_err(errors::internal)?;
+
+  Ok((Status::Created, Json(MediaUploadResponse { media_uuid: Some(media_uuid), sha2_512: hash, rejection: None })))
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct MediaIntegrityResponse {
+  pub media_uuid: String,
+  pub status: IntegrityStatus,
+  pub verified_at: DateTime<Utc>,
+  /// Hash of the file when it doesn't match the stored one.
+  pub actual_sha2_512: Option<String>,
+}
+
+/// Returns media of the authenticated user which failed the last integrity check.
+///
+/// Media are checked periodically when `integrity_check_files` is configured.
+#[openapi]
+#[get("/media/integrity")]
+pub async fn get_media_integrity_failures(claims: Claims, conn: DbConn) -> Result<Json<Vec<MediaIntegrityResponse>>, Status> {
+  let failures = db::integrity::select_integrity_failures(&conn, claims.user_id).await;
+  if failures.is_err() { return Err(Status::InternalServerError) }
+
+  let result = failures.unwrap().into_iter()
+    .filter_map(|(integrity, media_uuid)| Some(MediaIntegrityResponse {
+      media_uuid,
+      status: integrity.status.parse().ok()?,
+      verified_at: DateTime::from_utc(integrity.verified_at, Utc),
+      actual_sha2_512: integrity.actual_sha2_512,
+    }))
+    .collect::<Vec<MediaIntegrityResponse>>();
+
+  Ok(Json(result))
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct MediaDescription {
+  description: Option<String>
+}
+
+/// Updates description of a media
+#[openapi]
+#[put("/media/<media_uuid>/description", data = "<description>", format = "json")]
+pub async fn media_update_description(claims: Claims, conn: DbConn, config: &State<Config>, media_uuid: String, description: TimedJson<MediaDescription>) -> Result<Status, Status> {
+  let media_id_option = db::media::select_media_id(&conn, media_uuid.clone()).await.map_err(errors::internal)?;
+  if media_id_option.is_none() {
+    return Err(Status::NotFound);
+  }
+
+  permissions::authorize_media(&conn, config.access_denied, claims.user_id, media_uuid, MediaAction::Manage).await?;
+
+  let media_id = media_id_option.unwrap();
+
+  let mut description_option = description.into_inner().description;
+
+  // check for empty string
+  if let Some(string) = description_option {
+    description_option = if string.chars().count() > 0 { Some(string) } else { None };
+  }
+
+  let result = db::media::update_description(&conn, media_id, description_option).await;
+
+  if result.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(Status::Ok)
+}
+
+/// Deletes description of a media
+#[openapi]
+#[delete("/media/<media_uuid>/description")]
+pub async fn media_delete_description(claims: Claims, conn: DbConn, config: &State<Config>, media_uuid: String) -> Result<Status, Status> {
+  let media_id_option = db::media::select_media_id(&conn, media_uuid.clone()).await.map_err(errors::internal)?;
+  if media_id_option.is_none() {
+    return Err(Status::NotFound);
+  }
+
+  permissions::authorize_media(&conn, config.access_denied, claims.user_id, media_uuid, MediaAction::Manage).await?;
+
+  let media_id = media_id_option.unwrap();
+
+  let result = db::media::update_description(&conn, media_id, None).await;
+
+  if result.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(Status::Ok)
+}
+
+/// Maximum number of media changed by one batch update.
+const MAX_BATCH_MEDIA: usize = 1000;
+
+/// Change applied to every media of a batch; missing fields are left unchanged.
+#[derive(Deserialize, JsonSchema)]
+pub struct MediaPatch {
+  /// New description; an empty string removes it.
+  description: Option<String>,
+  /// RFC 3339 timestamp replacing the time the media was taken; its UTC offset is stored with the media.
+  date_taken: Option<DateTime<FixedOffset>>,
+  /// UUIDs of albums the media are added to.
+  #[serde(default)]
+  add_to_albums: Vec<String>,
+  /// UUIDs of albums the media are removed from.
+  #[serde(default)]
+  remove_from_albums: Vec<String>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct MediaBatchUpdate {
+  media_uuids: Vec<String>,
+  patch: MediaPatch,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct MediaBatchResult {
+  media_uuid: String,
+  /// HTTP status of the media: 200 when it was changed, 404 when it doesn't exist
+  /// and 403 (or the status of the `access_denied` policy) when the user can't manage it.
+  status: u16,
+}
+
+/// Resolves UUIDs of albums changed by a batch update, checking the user can perform the action in each of them.
+async fn select_batch_albums(conn: &DbConn, policy: AccessDeniedPolicy, user_id: i32, album_uuids: Vec<String>, action: AlbumAction) -> Result<Vec<i32>, Status> {
+  let mut album_ids = vec![];
+
+  for album_uuid in album_uuids {
+    let album_id = db::albums::select_album_id(conn, album_uuid).await.map_err(errors::internal)?;
+    if album_id.is_none() { return Err(Status::NotFound) }
+
+    let album_id = album_id.unwrap();
+    permissions::authorize_album(conn, policy, user_id, album_id, action).await?;
+
+    let album = db::albums::select_album(conn, album_id).await.map_err(errors::internal)?;
+    if album.is_none() { return Err(Status::NotFound) }
+
+    // media of smart albums are selected automatically
+    if album.unwrap().smart.is_some() { return Err(Status::UnprocessableEntity) }
+
+    album_ids.push(album_id);
+  }
+
+  Ok(album_ids)
+}
+
+/// Changes the description, the date taken or albums of several media at once.
+///
+/// The patch is applied to all media the user can manage in one transaction; the response contains the result of each media.\
+/// Albums are checked before any media is changed: responds with 404 when an album doesn't exist, with 403 when the user can't add
+/// or remove media there and with 422 for smart albums and for more than 1000 media.
+#[openapi]
+#[put("/media/batch", data = "<batch>", format = "json")]
+pub async fn update_media_batch(claims: Claims, conn: DbConn, config: &State<Config>, batch: TimedJson<MediaBatchUpdate>) -> Result<Json<Vec<MediaBatchResult>>, Status> {
+  let MediaBatchUpdate { media_uuids, patch } = batch.into_inner();
+  if media_uuids.len() > MAX_BATCH_MEDIA { return Err(Status::UnprocessableEntity) }
+
+  let add_album_ids = select_batch_albums(&conn, config.access_denied, claims.user_id, patch.add_to_albums, AlbumAction::AddMedia).await?;
+  let remove_album_ids = select_batch_albums(&conn, config.access_denied, claims.user_id, patch.remove_from_albums, AlbumAction::RemoveMedia).await?;
+
+  let mut results = vec![];
+  let mut media_ids = vec![];
+
+  for media_uuid in media_uuids {
+    let media_id = db::media::select_media_id(&conn, media_uuid.clone()).await.map_err(errors::internal)?;
+
+    let status = match media_id {
+      None => Status::NotFound,
+      Some(media_id) => match permissions::authorize_media(&conn, config.access_denied, claims.user_id, media_uuid.clone(), MediaAction::Manage).await {
+        Ok(()) => {
+          media_ids.push(media_id);
+          Status::Ok
+        },
+        Err(status) => status,
+      },
+    };
+
+    if status == Status::InternalServerError { return Err(status) }
+
+    results.push(MediaBatchResult { media_uuid, status: status.code });
+  }
+
+  media_ids.sort_unstable();
+  media_ids.dedup();
+  if media_ids.is_empty() { return Ok(Json(results)) }
+
+  let description = patch.description.map(|description| Some(description).filter(|description| !description.is_empty()));
+  let date_taken_changed = patch.date_taken.is_some();
+
+  // date ranges of albums which already contain the media change too
+  let mut changed_album_ids = if date_taken_changed {
+    let album_ids = db::albums::select_media_album_ids(&conn, media_ids.clone()).await;
+    if album_ids.is_err() { return Err(Status::InternalServerError) }
+
+    album_ids.unwrap()
+  } else {
+    vec![]
+  };
+
+  changed_album_ids.extend(add_album_ids.iter().chain(remove_album_ids.iter()));
+  changed_album_ids.sort_unstable();
+  changed_album_ids.dedup();
+
+  let updated = db::media::update_media_batch(&conn, claims.user_id, media_ids, description, patch.date_taken, add_album_ids, remove_album_ids).await;
+  if let Err(err) = updated {
+    error!("Batch update of media failed: {}", err);
+    return Err(Status::InternalServerError);
+  }
+
+  for album_id in changed_album_ids {
+    update_album_date_range(&conn, album_id).await;
+    update_album_thumbnail_fallback(&conn, album_id).await;
+  }
+
+  if date_taken_changed { update_favorites_date_range(&conn, claims.user_id).await; }
+
+  Ok(Json(results))
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct MediaEdit {
+  operations: Vec<EditOperation>,
+}
+
+/// Edits an image and returns the edited media.
+///
+/// Every edit creates a new version, which is served instead of the original; the original in the gallery is never changed.\
+/// Edits are applied on top of the current version; the media keeps its UUID, so it stays in all albums.\
+/// Responds with 422 for media which are not images and for operations which don't fit the image.
+#[openapi]
+#[post("/media/<media_uuid>/edit", data = "<media_edit>", format = "json")]
+pub async fn edit_media(claims: Claims, conn: DbConn, config: &State<Config>, media_uuid: String, media_edit: TimedJson<MediaEdit>) -> Result<Json<MediaResponse>, ApiError> {
+  let media = db::media::select_media_by_uuid(&conn, media_uuid.clone()).await?.ok_or(Status::NotFound)?;
+
+  permissions::authorize_media(&conn, config.access_denied, claims.user_id, media_uuid, MediaAction::Manage).await?;
+
+  let source = scan::get_media_path(&conn, &media).await.ok_or(Status::InternalServerError)?;
+
+  let version = db::media::select_next_media_version(&conn, media.id).await?;
+  let target = edit::media_version_path(&media.uuid, &media.filename, version).ok_or(Status::InternalServerError)?;
+  let operations = media_edit.into_inner().operations;
+
+  let mut errors = ValidationErrors::new();
+  if image::ImageFormat::from_path(&source).is_err() {
+    errors.add("media", validation::InvalidReason::InvalidFormat);
+    return Err(errors.into());
+  }
+
+  let dimensions_source = source.clone();
+  let dimensions = rocket::tokio::task::spawn_blocking(move || edit::displayed_dimensions(&dimensions_source, (media.width, media.height))).await;
+  if !matches!(dimensions, Ok(Ok(_))) { return Err(Status::InternalServerError.into()) }
+
+  edit::validate_operations(&operations, dimensions.unwrap().unwrap(), &mut errors);
+  errors.into_result()?;
+
+  let edit_operations = operations.clone();
+  let edited = rocket::tokio::task::spawn_blocking(move || -> std::io::Result<(u32, u32, String)> {
+    std::fs::create_dir_all(target.parent().unwrap())?;
+
+    if let Err(err) = edit::edit_image(&source, &target, &edit_operations) {
+      std::fs::remove_file(&target).ok();
+      return Err(err);
+    }
+
+    let (width, height) = image::image_dimensions(&target).map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+
+    Ok((width, height, hash_file(&target, SHA2512)))
+  }).await;
+
+  let (width, height, hash) = match edited {
+    Ok(Ok(edited)) => edited,
+    Ok(Err(err)) => {
+      error!("Media {} couldn't be edited: {}", media.uuid, err);
+      return Err(Status::InternalServerError.into());
+    },
+    Err(_) => return Err(Status::InternalServerError.into()),
+  };
+
+  let edited_version = NewMediaVersion::edited(media.id, version, &operations, width, height, hash);
+  db::media::insert_media_version(&conn, NewMediaVersion::original(&media), edited_version).await?;
+
+  media_version_changed(&conn, &claims, media.uuid, media.sha2_512).await.map_err(ApiError::from)
+}
+
+/// Removes derivatives of the previous version and returns the media with the new version.
+async fn media_version_changed(conn: &DbConn, claims: &Claims, media_uuid: String, previous_sha2_512: String) -> Result<Json<MediaResponse>, Status> {
+  if let Err(err) = derivatives::remove_unused_derivatives(conn, &previous_sha2_512).await {
+    warn!("Derivatives of the previous version of media {} couldn't be removed: {}", media_uuid, err);
+  }
+
+  let media = db::media::select_media_by_uuid(conn, media_uuid).await;
+  if !matches!(media, Ok(Some(_))) { return Err(Status::InternalServerError) }
+
+  let media = media.unwrap().unwrap();
+  let likes = MediaLikes::select(conn, std::slice::from_ref(&media), Some(claims.user_id), false).await?;
+
+  let timezone = db::users::get_user_timezone(conn, claims.user_id).await;
+
+  Ok(Json(MediaResponse::new(&media, timezone, &likes)))
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct MediaVersionResponse {
+  /// 0 is the original.
+  version: i32,
+  /// Edit operations applied to the version which was current at that time; `None` for the original.
+  operations: Option<Vec<EditOperation>>,
+  width: u32,
+  height: u32,
+  created_at: NaiveDateTime,
+  current: bool,
+}
+
+/// Returns the edit history of a media.
+///
+/// Media which were never edited have no versions.
+#[openapi]
+#[get("/media/<media_uuid>/versions")]
+pub async fn get_media_versions(claims: Claims, conn: DbConn, config: &State<Config>, media_uuid: String) -> Result<Json<Vec<MediaVersionResponse>>, Status> {
+  let media = db::media::select_media_by_uuid(&conn, media_uuid.clone()).await;
+  if media.is_err() { return Err(Status::InternalServerError) }
+
+  let media_option = media.unwrap();
+  if media_option.is_none() { return Err(Status::NotFound) }
+
+  permissions::authorize_media(&conn, config.access_denied, claims.user_id, media_uuid, MediaAction::Manage).await?;
+
+  let media = media_option.unwrap();
+
+  let versions = db::media::select_media_versions(&conn, media.id).await;
+  if versions.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(Json(
+    versions.unwrap()
+      .into_iter()
+      .map(|version| MediaVersionResponse {
+        version: version.version,
+        operations: version.operations(),
+        width: version.width,
+        height: version.height,
+        created_at: version.created_at,
+        current: version.version == media.version,
+      })
+      .collect()
+  ))
+}
+
+/// Makes an older version (0 for the original) the current version of the media.
+///
+/// Newer versions are kept, so it's possible to revert back to them.
+#[openapi]
+#[post("/media/<media_uuid>/versions/<version>/revert")]
+pub async fn revert_media_version(claims: Claims, conn: DbConn, config: &State<Config>, media_uuid: String, version: i32) -> Result<Json<MediaResponse>, Status> {
+  let media = db::media::select_media_by_uuid(&conn, media_uuid.clone()).await;
+  if media.is_err() { return Err(Status::InternalServerError) }
+
+  let media_option = media.unwrap();
+  if media_option.is_none() { return Err(Status::NotFound) }
+
+  permissions::authorize_media(&conn, config.access_denied, claims.user_id, media_uuid.clone(), MediaAction::Manage).await?;
+
+  let media = media_option.unwrap();
+
+  let media_version = db::media::select_media_version(&conn, media.id, version).await;
+  if media_version.is_err() { return Err(Status::InternalServerError) }
+
+  let media_version_option = media_version.unwrap();
+  if media_version_option.is_none() { return Err(Status::NotFound) }
+
+  if db::media::revert_media_version(&conn, media_version_option.unwrap()).await.is_err() { return Err(Status::InternalServerError) }
+
+  media_version_changed(&conn, &claims, media_uuid, media.sha2_512).await
+}
+
+/// Starts a job writing descriptions of the user's media back into the files (as XMP).
+///
+/// Only JPEGs are written; media which can't be written are reported as skipped in the progress.\
+/// With `dry_run`, files are only checked and nothing is written.\
+/// Responds with 409 when a job of the user is already running.
+#[openapi]
+#[post("/media/metadata/write_back?<dry_run>")]
+pub async fn start_metadata_write_back(claims: Claims, conn: DbConn, write_back_jobs: &State<WriteBackJobs>, dry_run: Option<bool>) -> Result<Status, Status> {
+  let started = write_back_jobs.start(conn, claims.user_id, dry_run.unwrap_or(false)).await;
+  if started.is_err() { return Err(Status::InternalServerError) }
+
+  if !started.unwrap() { return Err(Status::Conflict) }
+
+  Ok(Status::Accepted)
+}
+
+/// Returns the progress of the user's last metadata write-back job.
+///
+/// Progress is kept in memory; after a restart, the job is only listed in `/jobs`.
+#[openapi]
+#[get("/media/metadata/write_back")]
+pub async fn get_metadata_write_back(claims: Claims, write_back_jobs: &State<WriteBackJobs>) -> Result<Json<WriteBackProgress>, Status> {
+  match write_back_jobs.progress(claims.user_id) {
+    Some(progress) => Ok(Json(progress)),
+    None => Err(Status::NotFound),
+  }
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct JobResponse {
+  uuid: String,
+  kind: Option<JobKind>,
+  state: Option<JobState>,
+  /// Number of items to process; `None` when it isn't known (e.g. for scans).
+  total: Option<u32>,
+  processed: u32,
+  error: Option<String>,
+  /// UUID of the interrupted job this job continues.
+  resumed_from: Option<String>,
+  created_at: NaiveDateTime,
+  finished_at: Option<NaiveDateTime>,
+}
+
+impl JobResponse {
+  fn new(job: Job) -> Self {
+    JobResponse {
+      kind: job.kind(),
+      state: JobState::from_str(&job.state).ok(),
+      uuid: job.uuid,
+      total: job.total,
+      processed: job.processed,
+      error: job.error,
+      resumed_from: job.resumed_from,
+      created_at: job.created_at,
+      finished_at: job.finished_at,
+    }
+  }
+}
+
+/// Number of jobs returned by `/jobs`.
+const JOBS_LIMIT: i64 = 50;
+
+/// Returns the latest jobs (scans, metadata write-backs, ...) of the user, newest first.
+///
+/// Jobs interrupted by a restart are failed; interrupted scans are continued by a new job
+/// which refers to the interrupted one in `resumed_from`.
+#[openapi]
+#[get("/jobs")]
+pub async fn get_jobs(claims: Claims, conn: DbConn) -> Result<Json<Vec<JobResponse>>, Status> {
+  let jobs = db::jobs::select_user_jobs(&conn, claims.user_id, JOBS_LIMIT).await;
+  if jobs.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(Json(jobs.unwrap().into_iter().map(JobResponse::new).collect()))
+}
+
+/// Starts an export of the user's favorites, albums and descriptions in the `format` other software understands.
+///
+/// The export is a job (see `/jobs`); its archive is downloaded from `/export/<job_uuid>` once the job finished.
+/// Starting a new export removes the archive of the previous one.\
+/// Responds with 409 when an export of the user is already running.
+#[openapi]
+#[post("/export?<format>")]
+pub async fn start_export(claims: Claims, conn: DbConn, format: ExportFormat) -> Result<(Status, Json<JobResponse>), ApiError> {
+  match export::start(conn, claims.user_id, format).await? {
+    Some(job) => Ok((Status::Accepted, Json(JobResponse::new(job)))),
+    None => Err(Status::Conflict.into()),
+  }
+}
+
+/// Downloads the archive of a finished export.
+///
+/// Responds with 409 while the export is running and with 404 when it failed
+/// or its archive was replaced by a newer export.
+#[openapi]
+#[get("/export/<job_uuid>")]
+pub async fn download_export(claims: Claims, conn: DbConn, job_uuid: String) -> Result<ExportDownload, ApiError> {
+  let job = match db::jobs::select_job_by_uuid(&conn, job_uuid).await? {
+    Some(job) if job.user_id == claims.user_id && job.kind() == Some(JobKind::Export) => job,
+    _ => return Err(Status::NotFound.into()),
+  };
+
+  match JobState::from_str(&job.state) {
+    Ok(JobState::Running) => return Err(Status::Conflict.into()),
+    Ok(JobState::Finished) => {},
+    _ => return Err(Status::NotFound.into()),
+  }
+
+  ExportDownload::open(&job).await.ok_or_else(|| Status::NotFound.into())
+}
+
+/// Maximum size of imported metadata.
+const MAX_IMPORT_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Imports favorites, albums and descriptions exported from PhotoPrism or Immich (`source`) onto the user's media.
+///
+/// The body is a JSON object with the lists returned by the API of the other software, see the `import` module;
+/// media are matched by their paths, or by their hashes when their paths don't match.
+/// Nothing is removed: descriptions are only set for media without one and media are added to albums
+/// with the same name, which are created when they don't exist.\
+/// With `dry_run=true`, the report shows what would be imported without changing anything.\
+/// Responds with 400 when the body isn't an export of the source and with 408 when it isn't received in time.
+#[openapi]
+#[post("/import?<source>&<dry_run>", data = "<data>")]
+pub async fn import_metadata(claims: Claims, conn: DbConn, body_timeout: BodyTimeout, source: ImportSource, dry_run: Option<bool>, data: Data<'_>) -> Result<Json<ImportReport>, ApiError> {
+  let data = body_timeout.read(data.open(MAX_IMPORT_SIZE.bytes()).into_string()).await?.map_err(|_| Status::BadRequest)?;
+  if !data.is_complete() { return Err(Status::PayloadTooLarge.into()) }
+
+  let library = ForeignLibrary::parse(&data, source);
+  if library.is_none() { return Err(Status::BadRequest.into()) }
+
+  let report = import::import(&conn, claims.user_id, library.unwrap(), dry_run.unwrap_or(false)).await?;
+  if report.is_none() { return Err(Status::InternalServerError.into()) }
+
+  let report = report.unwrap();
+
+  for album_id in &report.album_ids {
+    update_album_date_range(&conn, *album_id).await;
+    update_album_thumbnail_fallback(&conn, *album_id).await;
+  }
+
+  if report.liked > 0 && !report.dry_run { update_favorites_date_range(&conn, claims.user_id).await; }
+
+  Ok(Json(report))
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct ScanIssueResponse {
+  /// Path relative to the gallery directory, e.g. `john/Holiday/IMG_0001.jpg`.
+  path: String,
+  kind: Option<ScanIssueKind>,
+  severity: Option<ScanIssueSeverity>,
+  message: Option<String>,
+  created_at: NaiveDateTime,
+}
+
+impl ScanIssueResponse {
+  fn new(issue: ScanIssue) -> Self {
+    ScanIssueResponse {
+      kind: issue.kind(),
+      severity: issue.severity(),
+      path: issue.path,
+      message: issue.message,
+      created_at: issue.created_at,
+    }
+  }
+}
+
+/// Returns files which the scan job couldn't add, ordered by their paths.
+///
+/// Issues can be filtered by their `kind`, `severity` (unsupported files are warnings, other issues are errors)
+/// and `path`, a prefix of paths relative to the gallery directory (e.g. `john/Holiday/`).
+/// Only folders changed since the previous scan are scanned, so issues of unchanged folders are listed by earlier scans.
+#[openapi]
+#[get("/scan/<job_uuid>/issues?<kind>&<severity>&<path>")]
+pub async fn get_scan_issues(claims: Claims, conn: DbConn, job_uuid: String, kind: Option<ScanIssueKind>, severity: Option<ScanIssueSeverity>, path: Option<String>) -> Result<Json<Vec<ScanIssueResponse>>, Status> {
+  let job = db::jobs::select_job_by_uuid(&conn, job_uuid).await;
+  if job.is_err() { return Err(Status::InternalServerError) }
+
+  let job = match job.unwrap() {
+    Some(job) if job.user_id == claims.user_id && job.kind() == Some(JobKind::Scan) => job,
+    _ => return Err(Status::NotFound),
+  };
+
+  let issues = db::scan::select_scan_issues(&conn, job.id, kind, severity, path).await;
+  if issues.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(Json(issues.unwrap().into_iter().map(ScanIssueResponse::new).collect()))
+}
+
+/// Returns clusters of the authenticated user's media with GPS coordinates for a map.
+///
+/// The bounding box is in degrees; `west` is greater than `east` when the map crosses the antimeridian.
+/// `zoom` is the zoom level of the map (0 - 22), clusters get smaller as it grows, see `geo` for details.\
+/// Clusters are ordered from the biggest one.\
+/// Responds with 422 when the bounding box or the zoom level is invalid.
+#[openapi]
+#[get("/media/geo?<north>&<south>&<east>&<west>&<zoom>")]
+pub async fn get_media_geo(claims: Claims, conn: DbConn, north: f64, south: f64, east: f64, west: f64, zoom: u8) -> Result<Json<Vec<GeoCluster>>, Status> {
+  let bounds = GeoBounds { north, south, east, west };
+  if !bounds.is_valid() || zoom > geo::MAX_ZOOM { return Err(Status::UnprocessableEntity) }
+
+  let media = db::media::select_geo_media(&conn, claims.user_id, bounds).await;
+  if media.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(Json(geo::cluster(media.unwrap(), zoom)))
+}
+
+/// Returns a page of liked media.
+///
+/// Media are ordered the same way as in `/media`; without `limit`, `offset` and `cursor`,
+/// all liked media are returned as a plain array.
+#[openapi]
+#[get("/media/liked?<pagination..>")]
+pub async fn get_media_liked_list(claims: Claims, conn: DbConn, pagination: MediaPagination) -> Result<Json<MediaListing>, ApiError> {
+  pagination.validate()?;
+
+  let liked = db::media::get_liked_media(&conn, claims.user_id, pagination.clone()).await?;
+  let likes = MediaLikes::select(&conn, &liked, Some(claims.user_id), false).await?;
+
+  let timezone = db::users::get_user_timezone(&conn, claims.user_id).await;
+
+  Ok(Json(MediaListing::new(MediaPage::new(liked, &pagination, timezone, &likes), &pagination)))
+}
+
+/// Media sharing the same content.
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct DuplicateGroup {
+  /// SHA2-512 of the media.
+  pub sha2_512: String,
+  /// Media with the hash, oldest first.
+  pub media: Vec<MediaResponse>,
+}
+
+/// Returns groups of the user's media with identical content, so the library can be cleaned up.
+///
+/// Media are compared by the hash of their current version.
+#[openapi]
+#[get("/media/duplicates")]
+pub async fn get_duplicate_media(claims: Claims, conn: DbConn) -> Result<Json<Vec<DuplicateGroup>>, Status> {
+  let duplicates = db::media::select_duplicate_media(&conn, claims.user_id).await;
+  if duplicates.is_err() { return Err(Status::InternalServerError) }
+
+  let duplicates = duplicates.unwrap();
+  let likes = MediaLikes::select(&conn, &duplicates, Some(claims.user_id), false).await?;
+
+  let timezone = db::users::get_user_timezone(&conn, claims.user_id).await;
+
+  let mut groups: Vec<DuplicateGroup> = vec![];
+  for media in duplicates {
+    let response = MediaResponse::new(&media, timezone, &likes);
+
+    match groups.last_mut() {
+      Some(group) if group.sha2_512 == media.sha2_512 => group.media.push(response),
+      _ => groups.push(DuplicateGroup { sha2_512: media.sha2_512, media: vec![response] }),
+    }
+  }
+
+  Ok(Json(groups))
+}
+
+/// Likes the media; liking media which is already liked does nothing.
+#[openapi]
+#[post("/media/<media_uuid>/like")]
+pub async fn media_like(claims: Claims, conn: DbConn, config: &State<Config>, media_uuid: String) -> Result<Status, Status> {
+  let media_id_option = db::media::select_media_id(&conn, media_uuid.clone()).await.map_err(errors::internal)?;
+  if media_id_option.is_none() {
+    return Err(Status::NotFound);
+  }
+
+  permissions::authorize_media(&conn, config.access_denied, claims.user_id, media_uuid, MediaAction::View).await?;
+
+  let media_id = media_id_option.unwrap();
+
+  let changed_rows = db::media::media_like(&conn, media_id, claims.user_id).await;
+  if let Err(err) = changed_rows {
+    error!("Inserting like failed: {}", err);
+    return Err(Status::InternalServerError);
+  }
+
+  if changed_rows.unwrap() > 0 { update_favorites_date_range(&conn, claims.user_id).await; }
+
+  Ok(Status::Ok)
+}
+
+/// Unlikes the media.
+#[openapi]
+#[delete("/media/<media_uuid>/like")]
+pub async fn media_unlike(claims: Claims, conn: DbConn, media_uuid: String) -> Result<Status, Status> {
+  let media_id_option = db::media::select_media_id(&conn, media_uuid).await.map_err(errors::internal)?;
+  if media_id_option.is_none() {
+    return Err(Status::NotFound);
+  }
+
+  let media_id = media_id_option.unwrap();
+
+  let r = db::media::media_unlike(&conn, media_id, claims.user_id).await;
+
+  if r.is_err() { return Ok(Status::InternalServerError) }
 
-/// Creates a new album share link.
-#[openapi]
-#[post("/album/<album_uuid>/share/link", data = "<album_share_link_insert>", format = "json")]
-pub async fn create_album_share_link(claims: Claims, conn: DbConn, album_uuid: String, album_share_link_insert: Option<Json<AlbumShareLinkInsert>>) -> Result<Json<SharedAlbumLinkResponse>, Status> {
-  let album_id_option = db::albums::select_album_id(&conn, album_uuid).await;
-  if album_id_option.is_none() { return Err(Status::NotFound) }
+  let changed_rows = r.unwrap();
 
-  let album_id = album_id_option.unwrap();
+  if changed_rows == 0 {
+    return Ok(Status::NoContent);
+  }
 
-  let album = db::albums::select_album(&conn, album_id).await;
-  if album.is_none() { return Err(Status::NotFound) }
+  update_favorites_date_range(&conn, claims.user_id).await;
 
-  if album.unwrap().owner_id != claims.user_id { return Err(Status::Forbidden) }
+  Ok(Status::Ok)
+}
 
-  let mut album_share_link_insert_inner = match album_share_link_insert {
-    Some(album_share_link) => album_share_link.into_inner(),
-    None => AlbumShareLinkInsert {
-      expiration: None,
-      password: None
-    }
-  };
+/// Maximum length of a tag name.
+const MAX_TAG_LENGTH: usize = 64;
 
-  album_share_link_insert_inner = album_share_link_insert_inner.normalize_and_hash_password();
+/// Maximum number of tags added in one request.
+const MAX_ADDED_TAGS: usize = 100;
 
-  let album_share_link = NewAlbumShareLink::new(album_id, album_share_link_insert_inner.password, album_share_link_insert_inner.expiration);
+/// Trims the tag name; `None` when it's empty or too long.
+fn normalize_tag(name: &str) -> Option<String> {
+  let name = name.trim();
+  if name.is_empty() || name.chars().count() > MAX_TAG_LENGTH { return None }
 
-  // It would be better to return result and have different responses for each error kind.
-  // But it looks like that Diesel uses one error kind for multiple different errors and changes only the message.
-  let changed_rows = db::albums::insert_album_share_link(&conn, album_share_link.clone()).await;
-  if changed_rows.is_err() { return Err(Status::InternalServerError) }
-  if changed_rows.unwrap() == 0 { return Err(Status::InternalServerError) }
+  Some(name.to_owned())
+}
 
-  Ok(
-    Json(
-      SharedAlbumLinkResponse {
-        uuid: album_share_link.uuid,
-        expiration: album_share_link.expiration
-      }
-    )
-  )
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct TagResponse {
+  name: String,
+  /// Number of the user's media with the tag.
+  media_count: i64,
 }
 
-impl From<&AlbumShareLink> for SharedAlbumLinkResponse {
-  fn from(album_share_link: &AlbumShareLink) -> Self {
-    Self { uuid: album_share_link.uuid.clone(), expiration: album_share_link.expiration }
-  }
+/// Returns tags of the authenticated user with the numbers of media having them.
+///
+/// Media with a tag are listed using the `tag` filter of media listings.
+#[openapi]
+#[get("/tags")]
+pub async fn get_tags(claims: Claims, conn: DbConn) -> Result<Json<Vec<TagResponse>>, Status> {
+  let tags = db::tags::select_tag_counts(&conn, claims.user_id).await;
+  if tags.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(Json(
+    tags.unwrap()
+      .into_iter()
+      .map(|(name, media_count)| TagResponse { name, media_count })
+      .collect()
+  ))
 }
 
-/// Gets a list of album share links.
+/// Returns names of the media's tags.
 #[openapi]
-#[get("/album/<album_uuid>/share/link")]
-pub async fn get_album_share_links(claims: Claims, conn: DbConn, album_uuid: String) -> Result<Json<Vec<SharedAlbumLinkResponse>>, Status> {
-  let album_id_option = db::albums::select_album_id(&conn, album_uuid).await;
-  if album_id_option.is_none() {
+#[get("/media/<media_uuid>/tags")]
+pub async fn get_media_tags(claims: Claims, conn: DbConn, config: &State<Config>, media_uuid: String) -> Result<Json<Vec<String>>, Status> {
+  let media_id_option = db::media::select_media_id(&conn, media_uuid.clone()).await.map_err(errors::internal)?;
+  if media_id_option.is_none() {
     return Err(Status::NotFound);
   }
 
-  let album_id = album_id_option.unwrap();
-
-  let album = db::albums::select_album(&conn, album_id).await;
-  if album.is_none() { return Err(Status::NotFound) }
-
-  if album.unwrap().owner_id != claims.user_id { return Err(Status::Forbidden) }
-
-  let links = db::albums::select_album_share_links(&conn, album_id).await;
-  if links.is_err() { return Err(Status::InternalServerError) }
+  permissions::authorize_media(&conn, config.access_denied, claims.user_id, media_uuid, MediaAction::View).await?;
 
-  let result = links.unwrap().iter()
-    .map(SharedAlbumLinkResponse::from)
-    .collect::<Vec<SharedAlbumLinkResponse>>();
+  let tags = db::tags::select_media_tags(&conn, media_id_option.unwrap()).await;
+  if tags.is_err() { return Err(Status::InternalServerError) }
 
-  Ok(Json(result))
+  Ok(Json(tags.unwrap()))
 }
 
 #[derive(Serialize, JsonSchema)]
-pub struct AlbumShareLinkBasic {
-  pub album_uuid: String,
-  pub is_password_protected: bool,
-  pub is_expired: bool
+pub struct TagSuggestionResponse {
+  name: String,
+  /// Confidence of the detection between 0 and 1.
+  confidence: f64,
 }
 
-impl AlbumShareLinkBasic {
-  pub fn new(album_share_link: AlbumShareLink, album_uuid: String) -> Self {
-    let current_time = NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0);
-
-    Self {
-      album_uuid,
-      is_expired: album_share_link.expiration.is_some() && album_share_link.expiration.unwrap() < current_time,
-      is_password_protected: album_share_link.password.is_some()
-     }
+/// Returns tags suggested for the media by the detection of objects, the most confident first.
+///
+/// Tags the media already has aren't suggested; the list is empty when the detection isn't enabled.
+#[openapi]
+#[get("/media/<media_uuid>/tags/suggested")]
+pub async fn get_media_tag_suggestions(claims: Claims, conn: DbConn, config: &State<Config>, media_uuid: String) -> Result<Json<Vec<TagSuggestionResponse>>, Status> {
+  let media_id_option = db::media::select_media_id(&conn, media_uuid.clone()).await.map_err(errors::internal)?;
+  if media_id_option.is_none() {
+    return Err(Status::NotFound);
   }
-}
 
-/// Gets basic information about album share link.
-#[openapi]
-#[get("/album/share/link/<album_share_link_uuid>")]
-pub async fn get_album_share_link(conn: DbConn, album_share_link_uuid: String) -> Result<Json<AlbumShareLinkBasic>, Status> {
-  let album_share_link_result = db::albums::select_album_share_link_by_uuid(&conn, album_share_link_uuid).await;
-  if album_share_link_result.is_err() { return Err(Status::InternalServerError) }
+  permissions::authorize_media(&conn, config.access_denied, claims.user_id, media_uuid, MediaAction::Manage).await?;
 
-  let album_share_link_option = album_share_link_result.unwrap();
-  if album_share_link_option.is_none() { return Err(Status::NotFound) }
+  let media_id = media_id_option.unwrap();
 
-  let album_share_link = album_share_link_option.unwrap();
+  let suggestions = db::detection::select_tag_suggestions(&conn, media_id).await;
+  if suggestions.is_err() { return Err(Status::InternalServerError) }
 
-  let album = db::albums::select_album(&conn, album_share_link.album_id).await;
-  if album.is_none() { return Err(Status::InternalServerError)  }
+  let tags = db::tags::select_media_tags(&conn, media_id).await;
+  if tags.is_err() { return Err(Status::InternalServerError) }
 
-  Ok(
-    Json(
-      AlbumShareLinkBasic::new(album_share_link, album.unwrap().link)
-    )
-  )
+  // names of tags are compared regardless of the letter case, like in the database
+  let tags: HashSet<String> = tags.unwrap().iter().map(|tag| tag.to_lowercase()).collect();
+
+  Ok(Json(
+    suggestions.unwrap()
+      .into_iter()
+      .filter(|suggestion| !tags.contains(&suggestion.name.to_lowercase()))
+      .map(|suggestion| TagSuggestionResponse { name: suggestion.name, confidence: suggestion.confidence })
+      .collect()
+  ))
 }
 
-/// Updates already existing album share link.
+/// Tags the media and returns names of all its tags.
+///
+/// Tags which don't exist yet are created; tags the media already has are skipped.\
+/// Responds with 422 when a name is empty or longer than 64 characters, or when more than 100 tags are added.
 #[openapi]
-#[put("/album/share/link/<album_share_link_uuid>", data = "<album_share_link_insert>", format = "json")]
-pub async fn update_album_share_link(claims: Claims, conn: DbConn, album_share_link_uuid: String, album_share_link_insert: Json<AlbumShareLinkInsert>) -> Result<Status, Status> {
-  let album_share_link_result = db::albums::select_album_share_link_by_uuid(&conn, album_share_link_uuid).await;
-  if album_share_link_result.is_err() { return Err(Status::InternalServerError) }
-
-  let album_share_link_option = album_share_link_result.unwrap();
-  if album_share_link_option.is_none() { return Err(Status::NotFound) }
+#[post("/media/<media_uuid>/tags", data = "<names>", format = "json")]
+pub async fn add_media_tags(claims: Claims, conn: DbConn, config: &State<Config>, media_uuid: String, names: TimedJson<Vec<String>>) -> Result<Json<Vec<String>>, Status> {
+  let media_id_option = db::media::select_media_id(&conn, media_uuid.clone()).await.map_err(errors::internal)?;
+  if media_id_option.is_none() {
+    return Err(Status::NotFound);
+  }
 
-  let album_share_link = album_share_link_option.unwrap();
+  permissions::authorize_media(&conn, config.access_denied, claims.user_id, media_uuid, MediaAction::Manage).await?;
 
-  let album = db::albums::select_album(&conn, album_share_link.album_id).await;
-  if album.is_none() { return Err(Status::NotFound) }
+  if names.len() > MAX_ADDED_TAGS { return Err(Status::UnprocessableEntity) }
 
-  if album.unwrap().owner_id != claims.user_id { return Err(Status::Forbidden) }
+  let names: Option<Vec<String>> = names.iter().map(|name| normalize_tag(name)).collect();
+  if names.is_none() { return Err(Status::UnprocessableEntity) }
 
-  let changed_rows = db::albums::update_album_share_link(&conn, album_share_link.id, album_share_link_insert.into_inner().normalize_and_hash_password()).await;
-  if changed_rows.is_err() { return Err(Status::InternalServerError) }
+  let media_id = media_id_option.unwrap();
 
-  if changed_rows.unwrap() == 0 {
-    return Ok(Status::NoContent);
+  let inserted = db::tags::insert_media_tags(&conn, claims.user_id, media_id, names.unwrap()).await;
+  if let Err(err) = inserted {
+    error!("Tags of media {} couldn't be added: {}", media_id, err);
+    return Err(Status::InternalServerError);
   }
 
-  Ok(Status::Ok)
+  let tags = db::tags::select_media_tags(&conn, media_id).await;
+  if tags.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(Json(tags.unwrap()))
 }
 
-/// Deletes an album share link.
+/// Removes the tag from the media; the tag is deleted when no other media have it.
+///
+/// Responds with 404 when the media doesn't have the tag.
 #[openapi]
-#[delete("/album/share/link/<album_share_link_uuid>")]
-pub async fn delete_album_share_link(claims: Claims, conn: DbConn, album_share_link_uuid: String) -> Result<Status, Status> {
-  let album_share_link_result = db::albums::select_album_share_link_by_uuid(&conn, album_share_link_uuid.clone()).await;
-  if album_share_link_result.is_err() { return Err(Status::InternalServerError) }
+#[delete("/media/<media_uuid>/tags/<name>")]
+pub async fn remove_media_tag(claims: Claims, conn: DbConn, config: &State<Config>, media_uuid: String, name: String) -> Result<Status, Status> {
+  let media_id_option = db::media::select_media_id(&conn, media_uuid.clone()).await.map_err(errors::internal)?;
+  if media_id_option.is_none() {
+    return Err(Status::NotFound);
+  }
 
-  let album_share_link = album_share_link_result.unwrap();
-  if album_share_link.is_none() { return Err(Status::NotFound) }
+  permissions::authorize_media(&conn, config.access_denied, claims.user_id, media_uuid, MediaAction::Manage).await?;
 
-  let album = db::albums::select_album(&conn, album_share_link.unwrap().album_id).await;
-  if album.is_none() { return Err(Status::NotFound) }
+  let removed = db::tags::delete_media_tag(&conn, claims.user_id, media_id_option.unwrap(), name).await;
+  if removed.is_err() { return Err(Status::InternalServerError) }
 
-  if album.unwrap().owner_id != claims.user_id { return Err(Status::Forbidden) }
+  if removed.unwrap() == 0 { return Err(Status::NotFound) }
 
-  let deleted = db::albums::delete_album_share_link(&conn, album_share_link_uuid).await;
-  if deleted.is_err() { return Err(Status::InternalServerError) }
+  Ok(Status::Ok)
+}
 
-  if deleted.unwrap() == 0 {
-    return Ok(Status::NoContent);
-  }
+#[derive(Deserialize, JsonSchema)]
+pub struct PersonInsert {
+  name: String,
+}
 
-  Ok(Status::Ok)
+#[derive(Serialize, JsonSchema)]
+pub struct PersonResponse {
+  uuid: String,
+  name: String,
+  /// Number of media with faces of the person.
+  media_count: i64,
 }
 
-/// Searches for new media
-// https://api.rocket.rs/master/rocket/struct.State.html
+/// Creates a person, so their faces can be assigned in media.
+///
+/// Responds with 422 when the name is empty or longer than 255 characters.
 #[openapi]
-#[get("/scan_media")]
-pub async fn scan_media(claims: Claims, conn: DbConn) -> &'static str {
-  let directories = Directories::new();
-  if directories.is_none() { return "false"; }
-
-  let xdg_data = directories.unwrap().gallery().to_owned();
-  if xdg_data.is_none() { return "false"; }
+#[post("/person", data = "<person_insert>", format = "json")]
+pub async fn create_person(claims: Claims, conn: DbConn, person_insert: TimedJson<PersonInsert>) -> Result<(Status, Json<PersonResponse>), Status> {
+  let name = person_insert.name.trim();
+  if name.is_empty() || name.chars().count() > 255 { return Err(Status::UnprocessableEntity) }
 
-  // let now_future = Delay::new(Duration::from_secs(10));
+  let new_person = NewPerson::new(claims.user_id, name.to_owned());
+  let uuid = new_person.uuid.clone();
 
-  // this thread will run until scanning is complete
-  // thread::spawn(|conn, xdg_data, user_id| async {
-  scan::scan_root(&conn, xdg_data.unwrap(), claims.user_id).await;
-  // });
+  if db::people::insert_person(&conn, new_person).await.is_err() { return Err(Status::InternalServerError) }
 
-  "true"
+  Ok((Status::Created, Json(PersonResponse { uuid, name: name.to_owned(), media_count: 0 })))
 }
 
-// TODO: rewrite later and use forwarding (ranks)
-// problem seems to be in okapi as it overwrites the route when there are multiple ranks
-// while the Request guards are wrapped in Option, there are no error codes from that Request guards
-/// Returns a media
+/// Returns people of the authenticated user ordered by their names.
 #[openapi]
-#[get("/media/<media_uuid>")]
-pub async fn get_media_by_uuid(shared_album_link_security: Option<SharedAlbumLinkSecurity>, claims_option: Option<Claims>, conn: DbConn, media_uuid: String) -> Option<NamedFile> {
-  let media: Media = conn.run(|c| {
-    media::table
-      .select(media::table::all_columns())
-      .filter(media::dsl::uuid.eq(media_uuid))
-      .first::<Media>(c)
-      .optional()
-      .unwrap()
-  }).await?;
-
-  if claims_option.is_some() {
-    if media.owner_id != claims_option.unwrap().user_id {
-      return None;
-    }
-
-    // TODO: check if non-owner user has permission to access the album (preparation for shared albums)
-
-  } else if shared_album_link_security.is_some() {
-    // TODO: maybe check more things
-  } else {
-    return None;
-  }
+#[get("/person")]
+pub async fn get_people(claims: Claims, conn: DbConn) -> Result<Json<Vec<PersonResponse>>, Status> {
+  let people = db::people::select_people(&conn, claims.user_id).await;
+  if people.is_err() { return Err(Status::InternalServerError) }
+
+  let counts = db::people::count_people_media(&conn, claims.user_id).await;
+  if counts.is_err() { return Err(Status::InternalServerError) }
+
+  let counts = counts.unwrap();
+
+  Ok(Json(
+    people.unwrap()
+      .into_iter()
+      .map(|person| PersonResponse { media_count: counts.get(&person.id).copied().unwrap_or(0), uuid: person.uuid, name: person.name })
+      .collect()
+  ))
+}
 
-  let directories = Directories::new();
-  if directories.is_none() { return None; }
+/// Gets a page of media with faces of the person.
+///
+/// Media are ordered the same way as in `/media`.\
+/// Responds with 422 when the cursor or the dates are invalid.
+#[openapi]
+#[get("/person/<person_uuid>/media?<pagination..>")]
+pub async fn get_person_media(claims: Claims, conn: DbConn, person_uuid: String, pagination: MediaPagination) -> Result<Json<MediaPage>, ApiError> {
+  pagination.validate()?;
 
-  let xdg_data = directories.unwrap().gallery().to_owned();
-  if xdg_data.is_none() { return None; }
+  let person = db::people::select_person_by_uuid(&conn, person_uuid, claims.user_id).await?.ok_or(Status::NotFound)?;
 
-  let mut folders: Vec<Folder> = vec!();
+  let media = db::people::get_person_media(&conn, person.id, pagination.clone()).await?;
+  let likes = MediaLikes::select(&conn, &media, Some(claims.user_id), false).await?;
 
-  let current_folder = db::folders::select_folder(&conn, media.folder_id).await?;
-  folders.push(current_folder.clone());
+  let timezone = db::users::get_user_timezone(&conn, claims.user_id).await;
 
-  scan::select_parent_folder_recursive(&conn, current_folder, media.owner_id, &mut folders);
+  Ok(Json(MediaPage::new(media, &pagination, timezone, &likes)))
+}
 
-  let mut path = xdg_data.unwrap();
+/// Bounding box of a face relative to the width and height of the original media, from its top left corner.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy)]
+pub struct FaceBox {
+  pub x: f64,
+  pub y: f64,
+  pub width: f64,
+  pub height: f64,
+}
 
-  if !folders.is_empty() {
-    for folder in folders.iter().rev() {
-      path = path.join(folder.name.as_str());
-    }
+impl FaceBox {
+  /// Checks whether the box isn't empty and lies within the media.
+  pub fn is_valid(&self) -> bool {
+    [self.x, self.y, self.width, self.height].iter().all(|value| value.is_finite())
+      && self.x >= 0.0 && self.y >= 0.0 && self.width > 0.0 && self.height > 0.0
+      && self.x + self.width <= 1.0 && self.y + self.height <= 1.0
   }
-  path = path.join(&media.filename);
+}
 
-  NamedFile::open(path).await.ok()
+#[derive(Deserialize, JsonSchema)]
+pub struct MediaFaceInsert {
+  #[serde(flatten)]
+  bounding_box: FaceBox,
+  /// Person whose face it is.
+  person_uuid: String,
 }
 
-#[derive(Serialize, Deserialize, JsonSchema)]
-pub struct MediaDescription {
-  description: Option<String>
+#[derive(Serialize, JsonSchema)]
+pub struct MediaFaceResponse {
+  uuid: String,
+  #[serde(flatten)]
+  bounding_box: FaceBox,
+  /// `None` when the face wasn't assigned to anyone or the person was deleted.
+  person_uuid: Option<String>,
+  person_name: Option<String>,
 }
 
-/// Updates description of a media
+/// Returns faces in the media.
 #[openapi]
-#[put("/media/<media_uuid>/description", data = "<description>", format = "json")]
-pub async fn media_update_description(claims: Claims, conn: DbConn, media_uuid: String, description: Json<MediaDescription>) -> Result<Status, Status> {
-  let media_id_option = db::media::select_media_id(&conn, media_uuid.clone()).await;
+#[get("/media/<media_uuid>/face")]
+pub async fn get_media_faces(claims: Claims, conn: DbConn, config: &State<Config>, media_uuid: String) -> Result<Json<Vec<MediaFaceResponse>>, Status> {
+  let media_id_option = db::media::select_media_id(&conn, media_uuid.clone()).await.map_err(errors::internal)?;
   if media_id_option.is_none() {
     return Err(Status::NotFound);
   }
 
-  let access = db::media::media_user_has_access(&conn, media_uuid, claims.user_id).await;
-  if access.is_err() { return Err(Status::InternalServerError) }
+  permissions::authorize_media(&conn, config.access_denied, claims.user_id, media_uuid, MediaAction::Manage).await?;
+
+  let faces = db::people::select_media_faces(&conn, media_id_option.unwrap()).await;
+  if faces.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(Json(
+    faces.unwrap()
+      .into_iter()
+      .map(|(face, person)| {
+        let (person_uuid, person_name) = person.unzip();
+
+        MediaFaceResponse {
+          uuid: face.uuid,
+          bounding_box: FaceBox { x: face.x, y: face.y, width: face.width, height: face.height },
+          person_uuid,
+          person_name,
+        }
+      })
+      .collect()
+  ))
+}
 
-  if !access.unwrap() { return Err(Status::Forbidden) }
+/// Marks a face of the person in the media.
+///
+/// Responds with 404 when the person doesn't exist and with 422 when the bounding box is empty or exceeds the media.
+#[openapi]
+#[post("/media/<media_uuid>/face", data = "<face_insert>", format = "json")]
+pub async fn create_media_face(claims: Claims, conn: DbConn, config: &State<Config>, media_uuid: String, face_insert: TimedJson<MediaFaceInsert>) -> Result<Status, Status> {
+  let media_id_option = db::media::select_media_id(&conn, media_uuid.clone()).await.map_err(errors::internal)?;
+  if media_id_option.is_none() {
+    return Err(Status::NotFound);
+  }
 
-  let media_id = media_id_option.unwrap();
+  permissions::authorize_media(&conn, config.access_denied, claims.user_id, media_uuid, MediaAction::Manage).await?;
 
-  let mut description_option = description.into_inner().description;
+  let face_insert = face_insert.into_inner();
+  if !face_insert.bounding_box.is_valid() { return Err(Status::UnprocessableEntity) }
 
-  // check for empty string
-  if let Some(string) = description_option {
-    description_option = if string.chars().count() > 0 { Some(string) } else { None };
-  }
+  let person = db::people::select_person_by_uuid(&conn, face_insert.person_uuid, claims.user_id).await;
+  if person.is_err() { return Err(Status::InternalServerError) }
 
-  let result = db::media::update_description(&conn, media_id, description_option).await;
+  let person = person.unwrap();
+  if person.is_none() { return Err(Status::NotFound) }
 
-  if result.is_err() { return Err(Status::InternalServerError) }
+  let bounding_box = face_insert.bounding_box;
+  let new_face = NewMediaFace {
+    uuid: nanoid!(),
+    media_id: media_id_option.unwrap(),
+    person_id: Some(person.unwrap().id),
+    x: bounding_box.x,
+    y: bounding_box.y,
+    width: bounding_box.width,
+    height: bounding_box.height,
+  };
 
-  Ok(Status::Ok)
+  if db::people::insert_media_face(&conn, new_face).await.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(Status::Created)
 }
 
-/// Deletes description of a media
+/// Removes the face from the media.
 #[openapi]
-#[delete("/media/<media_uuid>/description")]
-pub async fn media_delete_description(claims: Claims, conn: DbConn, media_uuid: String) -> Result<Status, Status> {
-  let media_id_option = db::media::select_media_id(&conn, media_uuid.clone()).await;
+#[delete("/media/<media_uuid>/face/<face_uuid>")]
+pub async fn delete_media_face(claims: Claims, conn: DbConn, config: &State<Config>, media_uuid: String, face_uuid: String) -> Result<Status, Status> {
+  let media_id_option = db::media::select_media_id(&conn, media_uuid.clone()).await.map_err(errors::internal)?;
   if media_id_option.is_none() {
     return Err(Status::NotFound);
   }
 
-  let access = db::media::media_user_has_access(&conn, media_uuid, claims.user_id).await;
-  if access.is_err() { return Err(Status::InternalServerError) }
+  permissions::authorize_media(&conn, config.access_denied, claims.user_id, media_uuid, MediaAction::Manage).await?;
 
-  if !access.unwrap() { return Err(Status::Forbidden) }
+  let deleted = db::people::delete_media_face(&conn, media_id_option.unwrap(), face_uuid).await;
+  if deleted.is_err() { return Err(Status::InternalServerError) }
 
-  let media_id = media_id_option.unwrap();
+  if deleted.unwrap() == 0 { return Err(Status::NotFound) }
 
-  let result = db::media::update_description(&conn, media_id, None).await;
+  Ok(Status::Ok)
+}
 
-  if result.is_err() { return Err(Status::InternalServerError) }
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct MediaGrantInsert {
+  username: String,
+}
 
-  Ok(Status::Ok)
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct MediaGrantResponse {
+  username: String,
+  created_at: NaiveDateTime,
 }
 
-/// Returns a list of liked media.
+/// Returns a list of users the media is shared with.
 #[openapi]
-#[get("/media/liked")]
-pub async fn get_media_liked_list(claims: Claims, conn: DbConn) -> Result<Json<Vec<MediaResponse>>, Status> {
-  let liked = db::media::get_liked_media(&conn, claims.user_id).await;
-
-  if liked.is_err() {
-    return Err(Status::InternalServerError)
+#[get("/media/<media_uuid>/grant")]
+pub async fn get_media_grants(claims: Claims, conn: DbConn, config: &State<Config>, media_uuid: String) -> Result<Json<Vec<MediaGrantResponse>>, Status> {
+  let media_id_option = db::media::select_media_id(&conn, media_uuid.clone()).await.map_err(errors::internal)?;
+  if media_id_option.is_none() {
+    return Err(Status::NotFound);
   }
 
-  let result = liked.unwrap().iter()
-    .map(MediaResponse::from)
-    .collect::<Vec<MediaResponse>>();
+  permissions::authorize_media(&conn, config.access_denied, claims.user_id, media_uuid, MediaAction::Manage).await?;
 
-  Ok(Json(result))
+  let grants = db::media::select_media_grants(&conn, media_id_option.unwrap()).await;
+  if grants.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(Json(
+    grants.unwrap()
+      .into_iter()
+      .map(|(username, created_at)| MediaGrantResponse { username, created_at })
+      .collect()
+  ))
 }
 
-/// Likes the media.
+/// Shares the media with another user.
 #[openapi]
-#[post("/media/<media_uuid>/like")]
-pub async fn media_like(claims: Claims, conn: DbConn, media_uuid: String) -> Result<Status, Status> {
-  let media_id_option = db::media::select_media_id(&conn, media_uuid).await;
+#[post("/media/<media_uuid>/grant", data = "<media_grant_insert>", format = "json")]
+pub async fn create_media_grant(claims: Claims, conn: DbConn, config: &State<Config>, media_uuid: String, media_grant_insert: TimedJson<MediaGrantInsert>) -> Result<Status, Status> {
+  let media_id_option = db::media::select_media_id(&conn, media_uuid.clone()).await.map_err(errors::internal)?;
   if media_id_option.is_none() {
     return Err(Status::NotFound);
   }
 
-  let media_id = media_id_option.unwrap();
+  permissions::authorize_media(&conn, config.access_denied, claims.user_id, media_uuid, MediaAction::Manage).await?;
 
-  // It would be better to return result and have different responses for each error kind.
-  // But it looks like that Diesel uses one error kind for multiple different errors and changes only the message.
-  let changed_rows = db::media::media_like(&conn, media_id, claims.user_id).await;
+  let user_id_option = db::users::get_user_id(&conn, media_grant_insert.into_inner().username).await.map_err(errors::internal)?;
+  if user_id_option.is_none() {
+    return Err(Status::NotFound);
+  }
+
+  let user_id = user_id_option.unwrap();
+
+  // owners always have access to their media
+  if user_id == claims.user_id { return Err(Status::UnprocessableEntity) }
+
+  // users of other organizations are hidden
+  let same_organization = db::organizations::users_share_organization(&conn, claims.user_id, user_id).await;
+  if same_organization.is_err() { return Err(Status::InternalServerError) }
+
+  if !same_organization.unwrap() { return Err(Status::NotFound) }
+
+  let changed_rows = db::media::insert_media_grant(&conn, media_id_option.unwrap(), user_id).await;
   if changed_rows.is_ok() {
-    return Ok(Status::Ok);
+    return Ok(Status::Created);
   }
 
-  error!("Inserting like failed: {}", changed_rows.unwrap_err());
+  error!("Inserting media grant failed: {}", changed_rows.unwrap_err());
   Err(Status::Conflict)
 }
 
-/// Unlikes the media.
+/// Stops sharing the media with another user.
 #[openapi]
-#[delete("/media/<media_uuid>/like")]
-pub async fn media_unlike(claims: Claims, conn: DbConn, media_uuid: String) -> Result<Status, Status> {
-  let media_id_option = db::media::select_media_id(&conn, media_uuid).await;
+#[delete("/media/<media_uuid>/grant/<username>")]
+pub async fn delete_media_grant(claims: Claims, conn: DbConn, config: &State<Config>, media_uuid: String, username: String) -> Result<Status, Status> {
+  let media_id_option = db::media::select_media_id(&conn, media_uuid.clone()).await.map_err(errors::internal)?;
   if media_id_option.is_none() {
     return Err(Status::NotFound);
   }
 
-  let media_id = media_id_option.unwrap();
-
-  let r = db::media::media_unlike(&conn, media_id, claims.user_id).await;
+  permissions::authorize_media(&conn, config.access_denied, claims.user_id, media_uuid, MediaAction::Manage).await?;
 
-  if r.is_err() { return Ok(Status::InternalServerError) }
+  let user_id_option = db::users::get_user_id(&conn, username).await.map_err(errors::internal)?;
+  if user_id_option.is_none() {
+    return Err(Status::NotFound);
+  }
 
-  let changed_rows = r.unwrap();
+  let r = db::media::delete_media_grant(&conn, media_id_option.unwrap(), user_id_option.unwrap()).await;
+  if r.is_err() { return Err(Status::InternalServerError) }
 
-  if changed_rows == 0 {
+  if r.unwrap() == 0 {
     return Ok(Status::NoContent);
   }
 
   Ok(Status::Ok)
 }
 
+/// Returns a page of media shared with the user by other users.
+///
+/// Media are ordered the same way as in `/media`.
+#[openapi]
+#[get("/media/shared?<pagination..>")]
+pub async fn get_media_shared_list(claims: Claims, conn: DbConn, pagination: MediaPagination) -> Result<Json<MediaPage>, ApiError> {
+  pagination.validate()?;
+
+  let shared = db::media::get_shared_media(&conn, claims.user_id, pagination.clone()).await?;
+  let likes = MediaLikes::select(&conn, &shared, Some(claims.user_id), true).await?;
+
+  let timezone = db::users::get_user_timezone(&conn, claims.user_id).await;
+
+  Ok(Json(MediaPage::new(shared, &pagination, timezone, &likes)))
+}
+
 #[derive(Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct SystemInfoPublic {
@@ -805,3 +4195,162 @@ impl SystemInfoPublic {
 pub async fn system_info_public() -> Json<SystemInfoPublic> {
   Json(SystemInfoPublic::new())
 }
+
+/// Bandwidth limit and traffic of all share links.
+#[derive(Serialize, JsonSchema)]
+pub struct SystemBandwidth {
+  /// Bandwidth of all share links together in KiB/s; zero means no limit.
+  share_link_bandwidth_limit: u64,
+  /// Traffic of share links since the server started.
+  share_links: BandwidthStats,
+}
+
+/// Returns the bandwidth limit of share links and the number of bytes sent and throttled.
+///
+/// Allowed only to administrators of the instance.
+#[openapi]
+#[get("/system/bandwidth")]
+pub async fn system_bandwidth(_admin: Admin, config: &State<Config>, bandwidth_limiter: &State<BandwidthLimiter>) -> Json<SystemBandwidth> {
+  Json(SystemBandwidth {
+    share_link_bandwidth_limit: config.share_link_bandwidth_limit,
+    share_links: bandwidth_limiter.stats(),
+  })
+}
+
+/// Returns the number of failed and rejected logins since the server started and the locked out addresses and accounts.
+///
+/// Allowed only to administrators of the instance.
+#[openapi]
+#[get("/system/logins")]
+pub async fn system_logins(_admin: Admin, login_limiter: &State<LoginLimiter>) -> Json<LoginStats> {
+  Json(login_limiter.stats())
+}
+
+/// Returns the state of database migrations.
+///
+/// The server applies migrations when it starts, so pending migrations are listed only
+/// when they were added while the server was running.\
+/// Allowed only to administrators of the instance.
+#[openapi]
+#[get("/system/migrations")]
+pub async fn system_migrations(_admin: Admin, conn: DbConn) -> Result<Json<MigrationReport>, Status> {
+  let report = conn.run(|c| MigrationReport::new(c)).await;
+  if report.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(Json(report.unwrap()))
+}
+
+/// State of anonymous usage statistics.
+#[derive(Serialize, JsonSchema)]
+pub struct TelemetryStatus {
+  /// Statistics are sent only when `telemetry` is enabled and `telemetry_endpoint` is set.
+  enabled: bool,
+  endpoint: Option<String>,
+  interval_hours: u64,
+  /// Exact data which would be sent now.
+  report: TelemetryReport,
+}
+
+/// Returns the anonymous usage statistics and whether they are sent.
+///
+/// The report is returned even when telemetry is disabled, so it can be reviewed before enabling it.\
+/// Allowed only to administrators of the instance.
+#[openapi]
+#[get("/system/telemetry")]
+pub async fn system_telemetry(_admin: Admin, conn: DbConn, config: &State<Config>) -> Result<Json<TelemetryStatus>, Status> {
+  let report = TelemetryReport::new(&conn).await;
+  if report.is_err() { return Err(Status::InternalServerError) }
+
+  Ok(Json(TelemetryStatus {
+    enabled: config.telemetry && config.telemetry_endpoint.is_some(),
+    endpoint: config.telemetry_endpoint.clone(),
+    interval_hours: config.telemetry_interval_hours,
+    report: report.unwrap(),
+  }))
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct FeatureStatus {
+  pub feature: Feature,
+  pub enabled: bool,
+}
+
+impl FeatureStatus {
+  /// Returns all existing features and whether they are enabled.
+  pub fn all(enabled: &[Feature]) -> Vec<Self> {
+    Feature::ALL.iter()
+      .map(|feature| FeatureStatus { feature: *feature, enabled: enabled.contains(feature) })
+      .collect()
+  }
+}
+
+/// Features available to the authenticated user.
+#[derive(Serialize, JsonSchema)]
+pub struct SystemFeatures {
+  /// Experimental features and whether they are enabled for the user.
+  user_features: Vec<FeatureStatus>,
+  /// HTTP server settings, so clients can tune the number of parallel requests.
+  http: HttpSettings,
+}
+
+/// Returns the features available to the authenticated user.
+#[openapi]
+#[get("/system/features")]
+pub async fn system_features(claims: Claims, conn: DbConn, http_settings: &State<HttpSettings>) -> Result<Json<SystemFeatures>, Status> {
+  let enabled = db::users::select_user_features(&conn, claims.user_id).await;
+  if enabled.is_err() { return Err(Status::InternalServerError) }
+
+  let user_features = FeatureStatus::all(&enabled.unwrap());
+
+  Ok(Json(SystemFeatures { user_features, http: http_settings.inner().clone() }))
+}
+
+/// Checks whether the server can serve requests; responds with 503 when the database isn't available.
+///
+/// Meant for load balancers and container health checks, so no token is required.
+#[openapi]
+#[get("/health")]
+pub async fn health(conn: DbConn) -> Status {
+  let result = conn.run(|c| diesel::sql_query("SELECT 1").execute(c)).await;
+  if result.is_err() { return Status::ServiceUnavailable }
+
+  Status::Ok
+}
+
+/// Returns metrics of the instance in the Prometheus text format.
+///
+/// No token is required on the management listener; elsewhere, it's allowed only to administrators of the instance
+/// (see `management_address`).
+#[openapi]
+#[get("/metrics")]
+pub async fn metrics(_access: MetricsAccess, conn: DbConn, bandwidth_limiter: &State<BandwidthLimiter>, login_limiter: &State<LoginLimiter>) -> Result<(ContentType, String), Status> {
+  let organizations = db::organizations::count_organizations(&conn).await;
+  let users = db::users::count_users(&conn).await;
+  let media = db::media::count_media(&conn).await;
+  let albums = db::albums::count_albums(&conn).await;
+  let scan_alerts = db::scan::count_unconfirmed_scan_alerts(&conn).await;
+
+  if organizations.is_err() || users.is_err() || media.is_err() || albums.is_err() || scan_alerts.is_err() { return Err(Status::InternalServerError) }
+
+  let share_links = bandwidth_limiter.stats();
+  let logins = login_limiter.stats();
+  let (request_timeouts, body_timeouts) = timeouts::stats();
+
+  let mut metrics = Metrics::default();
+  metrics.gauge("organizations", "Number of organizations.", organizations.unwrap());
+  metrics.gauge("users", "Number of users.", users.unwrap());
+  metrics.gauge("media", "Number of media of all users.", media.unwrap());
+  metrics.gauge("albums", "Number of albums of all users, including smart albums.", albums.unwrap());
+  metrics.gauge("scan_alerts", "Number of scan alerts which weren't confirmed yet.", scan_alerts.unwrap());
+  metrics.counter("share_link_sent_bytes_total", "Bytes sent through share links.", share_links.sent_bytes);
+  metrics.counter("share_link_throttled_bytes_total", "Bytes of share links delayed by a bandwidth limit.", share_links.throttled_bytes);
+  metrics.counter("failed_logins_total", "Logins with wrong credentials.", logins.failed_logins);
+  metrics.counter("rejected_logins_total", "Logins rejected because the address or the account was locked out.", logins.rejected_logins);
+  metrics.gauge("locked_addresses", "Addresses locked out now.", logins.locked_addresses);
+  metrics.gauge("locked_accounts", "Accounts locked out now.", logins.locked_accounts);
+  metrics.counter("panics_total", "Panics of request handlers and background tasks; they're logged.", panics::count());
+  metrics.counter("request_timeouts_total", "Requests whose handlers didn't respond in time; they failed with 503.", request_timeouts);
+  metrics.counter("body_timeouts_total", "Requests whose bodies weren't received in time; they failed with 408.", body_timeouts);
+
+  Ok((ContentType::Plain, metrics.into_text()))
+}
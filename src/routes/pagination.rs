@@ -0,0 +1,376 @@
+//! Pagination of media listings.
+//!
+//! # Ordering
+//!
+//! Media are ordered by `sort` (`date_taken` by default) and then by their ID in the same direction.\
+//! The ID is unique, so the ordering is total and two requests for the same page return the same items
+//! as long as nothing was inserted or deleted in between.\
+//! Media are ordered from the newest by default, filenames are ordered alphabetically.\
+//! `filename_natural` orders numbers in filenames by their value, so `IMG_2` comes before `IMG_10`.\
+//! Albums can have their own default sorting, which is used when `sort` isn't set.
+//!
+//! # Modes
+//!
+//! 1. **Keyset (cursor) mode** - used when the `cursor` query parameter is present.\
+//!    Every page contains a `next_cursor` which points right after its last item.
+//!    Media inserted while browsing never shift the following pages, so no item is skipped or returned twice.
+//!    A cursor can only be used with the same `sort` and `order` as the page it comes from.
+//! 2. **Offset mode** - used as a fallback when only `offset` is present.\
+//!    Media inserted while browsing shift the following pages.
+//!
+//! `/media`, `/media/liked` and `/album/<album_uuid>/media` listed all media as a plain array before they were paginated,
+//! so they still respond with the array when none of `limit`, `offset` and `cursor` is set (see `MediaListing`).
+//!
+//! # Filters
+//!
+//! `date_from` and `date_to` (inclusive, `YYYY-MM-DD`) limit the local date when the media was taken.\
+//! `tag` limits the media to the ones with the tag of that name (regardless of the letter case).
+
+use crate::models::Media;
+use crate::routes::{MediaLikes, MediaResponse};
+use crate::validation::{InvalidReason, ValidationErrors};
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use chrono_tz::Tz;
+use rocket::form::{FromForm, FromFormField};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::str::{self, FromStr};
+
+/// Maximum number of items on one page.
+pub const MAX_PAGE_LIMIT: i64 = 1000;
+
+/// Attribute media are sorted by.
+#[derive(FromFormField, Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaSort {
+  #[default]
+  #[field(value = "date_taken")]
+  DateTaken,
+  #[field(value = "filename")]
+  Filename,
+  /// Filenames with numbers ordered by their value.
+  #[field(value = "filename_natural")]
+  NaturalFilename,
+  /// Order in which media were added to the gallery.
+  #[field(value = "created")]
+  Created,
+}
+
+impl MediaSort {
+  pub const ALL: [MediaSort; 4] = [MediaSort::DateTaken, MediaSort::Filename, MediaSort::NaturalFilename, MediaSort::Created];
+
+  /// Returns the name used in the database.
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      MediaSort::DateTaken => "date_taken",
+      MediaSort::Filename => "filename",
+      MediaSort::NaturalFilename => "filename_natural",
+      MediaSort::Created => "created",
+    }
+  }
+}
+
+impl FromStr for MediaSort {
+  type Err = ();
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    MediaSort::ALL.iter()
+      .find(|sort| sort.as_str() == s)
+      .copied()
+      .ok_or(())
+  }
+}
+
+#[derive(FromFormField, Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+  #[field(value = "asc")]
+  Asc,
+  #[field(value = "desc")]
+  Desc,
+}
+
+impl SortOrder {
+  /// Returns the name used in the database.
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      SortOrder::Asc => "asc",
+      SortOrder::Desc => "desc",
+    }
+  }
+}
+
+impl FromStr for SortOrder {
+  type Err = ();
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "asc" => Ok(SortOrder::Asc),
+      "desc" => Ok(SortOrder::Desc),
+      _ => Err(()),
+    }
+  }
+}
+
+/// Query parameters used for paginating media listings.
+#[derive(FromForm, Deserialize, JsonSchema, Clone, Debug, Default)]
+pub struct MediaPagination {
+  /// Maximum number of returned media (at most 1000). All media are returned when not set.
+  pub limit: Option<i64>,
+  /// Number of skipped media. Ignored when `cursor` is set.
+  pub offset: Option<i64>,
+  /// Opaque cursor returned as `next_cursor` of the previous page.
+  pub cursor: Option<String>,
+  pub sort: Option<MediaSort>,
+  /// Descending by default, except for filenames.
+  pub order: Option<SortOrder>,
+  /// First day (`YYYY-MM-DD`) when the media were taken.
+  pub date_from: Option<String>,
+  /// Last day (`YYYY-MM-DD`) when the media were taken.
+  pub date_to: Option<String>,
+  /// Name of a tag the media have.
+  pub tag: Option<String>,
+}
+
+impl MediaPagination {
+  /// Returns the limit clamped to the allowed range.
+  pub fn limit(&self) -> Option<i64> {
+    self.limit.map(|limit| limit.clamp(1, MAX_PAGE_LIMIT))
+  }
+
+  /// Returns the offset if the offset mode is used.
+  pub fn offset(&self) -> Option<i64> {
+    if self.cursor.is_some() { return None }
+
+    self.offset.map(|offset| offset.max(0))
+  }
+
+  pub fn sort(&self) -> MediaSort {
+    self.sort.unwrap_or_default()
+  }
+
+  pub fn order(&self) -> SortOrder {
+    match (self.order, self.sort()) {
+      (Some(order), _) => order,
+      (None, MediaSort::Filename | MediaSort::NaturalFilename) => SortOrder::Asc,
+      (None, _) => SortOrder::Desc,
+    }
+  }
+
+  /// Decodes the cursor.\
+  /// Fails when the cursor is present but malformed or created for another `sort`.
+  pub fn decoded_cursor(&self) -> Result<Option<MediaCursor>, ValidationErrors> {
+    let mut errors = ValidationErrors::new();
+
+    let cursor = match &self.cursor {
+      Some(cursor) => MediaCursor::decode(cursor).filter(|cursor| cursor.sort() == self.sort()),
+      None => return Ok(None),
+    };
+
+    if cursor.is_none() { errors.add("cursor", InvalidReason::InvalidFormat) }
+
+    errors.into_result().map(|_| cursor)
+  }
+
+  /// Returns the range of `date_taken` as `[from, to)`.\
+  /// Fails with every malformed date.
+  pub fn date_range(&self) -> Result<(Option<NaiveDateTime>, Option<NaiveDateTime>), ValidationErrors> {
+    let mut errors = ValidationErrors::new();
+
+    let mut parse = |field: &str, date: &Option<String>| match date {
+      Some(date) => {
+        let parsed = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok();
+        if parsed.is_none() { errors.add(field, InvalidReason::InvalidFormat) }
+
+        parsed
+      },
+      None => None,
+    };
+
+    let from = parse("date_from", &self.date_from).map(|date| date.and_hms(0, 0, 0));
+    let to = parse("date_to", &self.date_to).map(|date| date.and_hms(0, 0, 0) + Duration::days(1));
+
+    errors.into_result().map(|_| (from, to))
+  }
+
+  /// Uses the sorting when the request doesn't set its own, e.g. the default sorting of an album.\
+  /// The order is used only together with the sort.
+  pub fn with_default_sort(mut self, sort: Option<MediaSort>, order: Option<SortOrder>) -> Self {
+    if self.sort.is_none() && sort.is_some() {
+      self.sort = sort;
+      self.order = self.order.or(order);
+    }
+
+    self
+  }
+
+  /// Checks whether any of `limit`, `offset` and `cursor` is set.
+  pub fn is_paginated(&self) -> bool {
+    self.limit.is_some() || self.offset.is_some() || self.cursor.is_some()
+  }
+
+  /// Checks the cursor and the dates; routes respond with 422 and the invalid parameters when they are invalid.
+  pub fn validate(&self) -> Result<(), ValidationErrors> {
+    let mut errors = ValidationErrors::new();
+
+    if let Err(invalid) = self.decoded_cursor() { errors.errors.extend(invalid.errors) }
+    if let Err(invalid) = self.date_range() { errors.errors.extend(invalid.errors) }
+
+    errors.into_result()
+  }
+}
+
+/// Value of the sorted attribute of the last media on a page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CursorKey {
+  DateTaken(NaiveDateTime),
+  Filename(String),
+  /// Key created by `natural_sort_key()`.
+  NaturalFilename(String),
+  /// Media are added in the order of their IDs, so no other value is needed.
+  Created,
+}
+
+/// Position in a media listing; points right after the media with the given key and `id`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaCursor {
+  pub key: CursorKey,
+  pub id: i32,
+}
+
+impl MediaCursor {
+  /// Creates a cursor pointing right after the media.
+  pub fn after(media: &Media, sort: MediaSort) -> Self {
+    let key = match sort {
+      MediaSort::DateTaken => CursorKey::DateTaken(media.date_taken),
+      MediaSort::Filename => CursorKey::Filename(media.filename.clone()),
+      MediaSort::NaturalFilename => CursorKey::NaturalFilename(media.filename_sort_key.clone().unwrap_or_default()),
+      MediaSort::Created => CursorKey::Created,
+    };
+
+    Self { key, id: media.id }
+  }
+
+  pub fn sort(&self) -> MediaSort {
+    match self.key {
+      CursorKey::DateTaken(_) => MediaSort::DateTaken,
+      CursorKey::Filename(_) => MediaSort::Filename,
+      CursorKey::NaturalFilename(_) => MediaSort::NaturalFilename,
+      CursorKey::Created => MediaSort::Created,
+    }
+  }
+
+  /// Encodes the cursor as an URL safe base64 string.
+  pub fn encode(&self) -> String {
+    // filenames can contain colons, so they are last
+    let raw = match &self.key {
+      CursorKey::DateTaken(date_taken) => format!("d:{}:{}", self.id, date_taken.timestamp()),
+      CursorKey::Filename(filename) => format!("f:{}:{}", self.id, filename),
+      CursorKey::NaturalFilename(key) => format!("n:{}:{}", self.id, key),
+      CursorKey::Created => format!("c:{}:", self.id),
+    };
+
+    base64::encode_config(raw, base64::URL_SAFE_NO_PAD)
+  }
+
+  /// Decodes a cursor created by `MediaCursor::encode()`.
+  pub fn decode(encoded: &str) -> Option<Self> {
+    let decoded = base64::decode_config(encoded, base64::URL_SAFE_NO_PAD).ok()?;
+    let mut parts = str::from_utf8(&decoded).ok()?.splitn(3, ':');
+    let (sort, id, value) = (parts.next()?, parts.next()?.parse().ok()?, parts.next()?);
+
+    let key = match sort {
+      "d" => CursorKey::DateTaken(NaiveDateTime::from_timestamp_opt(value.parse().ok()?, 0)?),
+      "f" => CursorKey::Filename(value.to_owned()),
+      "n" => CursorKey::NaturalFilename(value.to_owned()),
+      "c" => CursorKey::Created,
+      _ => return None,
+    };
+
+    Some(Self { key, id })
+  }
+}
+
+/// Number of digits numbers in natural sort keys are padded to.
+const NATURAL_SORT_DIGITS: usize = 20;
+
+/// Maximum length of natural sort keys, the length of their database column.
+pub const NATURAL_SORT_KEY_LENGTH: usize = 512;
+
+/// Creates a key of the filename which sorts naturally as a string.\
+/// Numbers are padded with zeros, so they are compared by their value.
+/// # Example
+/// ```
+/// assert!(natural_sort_key("IMG_2.jpg") < natural_sort_key("IMG_10.jpg"));
+/// ```
+pub fn natural_sort_key(filename: &str) -> String {
+  let mut key = String::with_capacity(filename.len());
+  let mut digits = String::new();
+
+  for c in filename.chars() {
+    if c.is_ascii_digit() {
+      digits.push(c);
+    } else {
+      push_padded_number(&mut key, &mut digits);
+      key.push(c);
+    }
+  }
+
+  push_padded_number(&mut key, &mut digits);
+
+  key.chars().take(NATURAL_SORT_KEY_LENGTH).collect()
+}
+
+/// Moves the digits to the key as a padded number.
+fn push_padded_number(key: &mut String, digits: &mut String) {
+  if digits.is_empty() { return }
+
+  let number = digits.trim_start_matches('0');
+  // longer numbers can't be padded, they are kept as they are
+  key.push_str(&"0".repeat(NATURAL_SORT_DIGITS.saturating_sub(number.len())));
+  key.push_str(number);
+  digits.clear();
+}
+
+/// One page of media.
+#[derive(Serialize, JsonSchema)]
+pub struct MediaPage {
+  pub media: Vec<MediaResponse>,
+  /// Cursor of the next page; `None` when this is the last page.
+  pub next_cursor: Option<String>,
+}
+
+impl MediaPage {
+  /// Creates a page from media ordered according to the module documentation.\
+  /// `timezone` is used for media without a known UTC offset.
+  pub fn new(media: Vec<Media>, pagination: &MediaPagination, timezone: Tz, likes: &MediaLikes) -> Self {
+    let next_cursor = match (pagination.limit(), media.last()) {
+      (Some(limit), Some(last)) if media.len() as i64 == limit => Some(MediaCursor::after(last, pagination.sort()).encode()),
+      _ => None,
+    };
+
+    Self {
+      media: media.iter().map(|media| MediaResponse::new(media, timezone, likes)).collect(),
+      next_cursor,
+    }
+  }
+}
+
+/// Media of a listing which responded with a plain array before it was paginated.
+#[derive(Serialize, JsonSchema)]
+#[serde(untagged)]
+pub enum MediaListing {
+  /// All media, when the request has no pagination parameters.
+  All(Vec<MediaResponse>),
+  Page(MediaPage),
+}
+
+impl MediaListing {
+  /// Keeps the page only when the request is paginated, see `MediaPagination::is_paginated()`.
+  pub fn new(page: MediaPage, pagination: &MediaPagination) -> Self {
+    match pagination.is_paginated() {
+      true => MediaListing::Page(page),
+      false => MediaListing::All(page.media),
+    }
+  }
+}
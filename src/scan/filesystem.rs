@@ -0,0 +1,135 @@
+//! Access to the files being scanned.
+
+use crate::config::SymlinkPolicy;
+use crate::metadata::MediaMetadata;
+use crate::models::ScanIssueKind;
+use super::{check_media_supported, is_symlink_allowed, FileIssue, ScanOptions};
+use checksums::{hash_file, Algorithm::SHA2512};
+use chrono::NaiveDateTime;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Source of folders and media for the `Scanner`.
+pub trait Filesystem {
+  /// Returns folders in `root` (including the `root` itself) which directly contain at least one file.
+  fn folders(&self, root: &Path, options: &ScanOptions) -> Vec<PathBuf>;
+
+  /// Returns supported media directly in the folder; other files are returned as issues.
+  fn media(&self, folder: &Path, options: &ScanOptions) -> (Vec<PathBuf>, Vec<FileIssue>);
+
+  /// Returns the width and height of the media; `None` when they can't be read.
+  fn dimensions(&self, media: &Path) -> Option<(u32, u32)>;
+
+  /// Returns the SHA-512 of the media.
+  fn hash(&self, media: &Path) -> String;
+
+  /// Returns metadata of the media, see `MediaMetadata::read_or_modified()`.
+  fn metadata(&self, media: &Path) -> MediaMetadata;
+
+  /// Returns the size and modification time of a file or folder; `None` when they can't be read.
+  fn stat(&self, path: &Path) -> Option<FileStat>;
+}
+
+/// Size and modification time of a file, used to find out whether it changed since the last scan.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileStat {
+  pub size: u64,
+  /// UTC, truncated to microseconds as the database doesn't store more.
+  pub modified: NaiveDateTime,
+}
+
+/// Files on the local disk.
+#[derive(Clone, Copy)]
+pub struct LocalFilesystem;
+
+impl Filesystem for LocalFilesystem {
+  // TODO: find out which is better: strip -> sort vs sort -> strip
+  fn folders(&self, root: &Path, options: &ScanOptions) -> Vec<PathBuf> {
+    let mut dirs = vec![];
+
+    // walkdir detects symlink loops when following links and returns them as errors
+    let walker = walkdir::WalkDir::new(root)
+      .follow_links(options.symlinks == SymlinkPolicy::Follow)
+      .into_iter()
+      // ignored folders are not entered at all
+      .filter_entry(|entry| !options.ignore.is_ignored(entry.path()));
+
+    for entry in walker {
+      let entry = match entry {
+        Ok(entry) => entry,
+        Err(err) => {
+          if err.loop_ancestor().is_some() {
+            warn!("Skipping symlink loop: {}", err);
+          }
+          continue;
+        }
+      };
+
+      if entry.path_is_symlink() && !is_symlink_allowed(entry.path(), options.symlinks) { continue }
+
+      let path = entry.into_path();
+      if path.is_file() {
+        if let Some(parent) = path.parent() {
+          dirs.push(parent.to_path_buf());
+        }
+      }
+    }
+
+    dirs.sort();
+    dirs.dedup();
+
+    dirs
+  }
+
+  fn media(&self, folder: &Path, options: &ScanOptions) -> (Vec<PathBuf>, Vec<FileIssue>) {
+    if options.ignore.is_ignored(folder) { return (vec![], vec![]) }
+
+    let entries = match fs::read_dir(folder) {
+      Ok(entries) => entries,
+      Err(_) => return (vec![], vec![]),
+    };
+
+    let files = entries
+      .filter_map(|entry| entry.ok())
+      .map(|entry| entry.path())
+      .filter(|path| !path.is_symlink() || is_symlink_allowed(path, options.symlinks))
+      .filter(|path| !options.ignore.is_ignored(path))
+      .filter(|path| path.is_file());
+
+    let mut media = vec![];
+    let mut issues = vec![];
+
+    for path in files {
+      match check_media_supported(&path) {
+        Ok(true) => media.push(path),
+        Ok(false) => issues.push(FileIssue::new(path, ScanIssueKind::Unsupported, None)),
+        Err(err) => issues.push(FileIssue::new(path, ScanIssueKind::Unreadable, Some(err.to_string()))),
+      }
+    }
+
+    (media, issues)
+  }
+
+  fn dimensions(&self, media: &Path) -> Option<(u32, u32)> {
+    image::image_dimensions(media).ok()
+  }
+
+  fn hash(&self, media: &Path) -> String {
+    hash_file(media, SHA2512)
+  }
+
+  fn metadata(&self, media: &Path) -> MediaMetadata {
+    MediaMetadata::read_or_modified(media)
+  }
+
+  fn stat(&self, path: &Path) -> Option<FileStat> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+
+    Some(FileStat {
+      size: metadata.len(),
+      modified: NaiveDateTime::from_timestamp_opt(modified.as_secs() as i64, modified.subsec_micros() * 1000)?,
+    })
+  }
+}
@@ -0,0 +1,46 @@
+//! In-memory index of folders used while scanning.
+
+use crate::models::Folder;
+use std::collections::HashMap;
+
+/// Folders of one user indexed by their parent and name.\
+/// It's built once per scan and updated after every insert,
+/// so folders don't have to be selected for each segment of each scanned path.
+#[derive(Debug, Default)]
+pub struct FolderTree {
+  ids: HashMap<(Option<i32>, String), i32>,
+  children: HashMap<Option<i32>, Vec<Folder>>,
+}
+
+impl FolderTree {
+  pub fn new(folders: Vec<Folder>) -> Self {
+    let mut tree = Self::default();
+
+    for folder in folders {
+      tree.insert(folder);
+    }
+
+    tree
+  }
+
+  /// Returns the ID of a folder with the given parent and name; `None` parent means a root folder.
+  pub fn get(&self, parent: Option<i32>, name: &str) -> Option<i32> {
+    self.ids.get(&(parent, name.to_owned())).copied()
+  }
+
+  /// Adds a folder, e.g. right after it was inserted into the repository.
+  pub fn insert(&mut self, folder: Folder) {
+    self.ids.insert((folder.parent, folder.name.clone()), folder.id);
+    self.children.entry(folder.parent).or_default().push(folder);
+  }
+
+  /// Returns the root folder.
+  pub fn root(&self) -> Option<&Folder> {
+    self.children.get(&None)?.first()
+  }
+
+  /// Returns direct subfolders of the folder.
+  pub fn children(&self, folder_id: i32) -> &[Folder] {
+    self.children.get(&Some(folder_id)).map_or(&[], Vec::as_slice)
+  }
+}
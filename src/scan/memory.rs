@@ -0,0 +1,248 @@
+//! In-memory filesystem and repository, so the `Scanner` can be tested without a gallery or a database.
+
+use crate::metadata::MediaMetadata;
+use crate::models::{Folder, FolderScan, NewFolder, NewMedia, ScannedFile, ScannedFileChange};
+use super::filesystem::{FileStat, Filesystem};
+use super::repository::Repository;
+use super::{FileIssue, ScanOptions};
+use chrono::NaiveDateTime;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Content of the `MemoryFilesystem`.
+#[derive(Debug, Default)]
+pub struct MemoryFiles {
+  /// Files by their absolute paths with their stats and hashes.
+  pub files: BTreeMap<PathBuf, (FileStat, String)>,
+  /// Modification times of directories.
+  pub directories: HashMap<PathBuf, NaiveDateTime>,
+}
+
+/// Filesystem keeping files in memory; all files are supported media of 1x1 pixels without metadata.\
+/// Clones share the files, so they can be changed while a scanner owns another clone.
+#[derive(Clone, Default)]
+pub struct MemoryFilesystem {
+  pub files: Arc<Mutex<MemoryFiles>>,
+}
+
+impl MemoryFilesystem {
+  pub fn files(&self) -> MutexGuard<'_, MemoryFiles> {
+    self.files.lock().unwrap()
+  }
+
+  /// Adds or replaces a file; like a file changed in place, it doesn't change the modification time of its directory.\
+  /// Missing directories of the file are created with the modification time `modified`.
+  pub fn write(&self, path: &str, size: u64, modified: NaiveDateTime, sha2_512: &str) {
+    let path = PathBuf::from(path);
+    let mut files = self.files();
+
+    for directory in path.ancestors().skip(1) {
+      files.directories.entry(directory.to_path_buf()).or_insert(modified);
+    }
+
+    files.files.insert(path, (FileStat { size, modified }, sha2_512.to_owned()));
+  }
+
+  /// Removes a file or a directory with everything in it.
+  pub fn remove(&self, path: &str) {
+    let path = Path::new(path);
+    let mut files = self.files();
+
+    files.files.retain(|file, _| !file.starts_with(path));
+    files.directories.retain(|directory, _| !directory.starts_with(path));
+  }
+
+  /// Sets the modification time of a directory, e.g. after a file was added or removed.
+  pub fn touch(&self, directory: &str, modified: NaiveDateTime) {
+    self.files().directories.insert(PathBuf::from(directory), modified);
+  }
+}
+
+impl Filesystem for MemoryFilesystem {
+  fn folders(&self, root: &Path, _options: &ScanOptions) -> Vec<PathBuf> {
+    let mut folders: Vec<PathBuf> = self.files().files.keys()
+      .filter(|file| file.starts_with(root))
+      .filter_map(|file| Some(file.parent()?.to_path_buf()))
+      .collect();
+
+    folders.sort();
+    folders.dedup();
+
+    folders
+  }
+
+  fn media(&self, folder: &Path, _options: &ScanOptions) -> (Vec<PathBuf>, Vec<FileIssue>) {
+    let media = self.files().files.keys()
+      .filter(|file| file.parent() == Some(folder))
+      .cloned()
+      .collect();
+
+    (media, vec![])
+  }
+
+  fn dimensions(&self, _media: &Path) -> Option<(u32, u32)> {
+    Some((1, 1))
+  }
+
+  fn hash(&self, media: &Path) -> String {
+    self.files().files.get(media).map(|(_, sha2_512)| sha2_512.clone()).unwrap_or_default()
+  }
+
+  fn metadata(&self, _media: &Path) -> MediaMetadata {
+    MediaMetadata::default()
+  }
+
+  fn stat(&self, path: &Path) -> Option<FileStat> {
+    let files = self.files();
+
+    if let Some((stat, _)) = files.files.get(path) { return Some(*stat) }
+
+    files.directories.get(path).map(|modified| FileStat { size: 0, modified: *modified })
+  }
+}
+
+/// Media stored by the `MemoryRepository`.
+#[derive(Debug, Clone)]
+pub struct MemoryMedia {
+  pub folder_id: i32,
+  pub owner_id: i32,
+  pub sha2_512: String,
+  pub file: ScannedFile,
+}
+
+/// Content of the `MemoryRepository`.
+#[derive(Debug, Default)]
+pub struct MemoryState {
+  pub folders: Vec<Folder>,
+  /// Modification times of folders at their last complete scan by folder IDs.
+  pub folder_scans: HashMap<i32, NaiveDateTime>,
+  pub media: Vec<MemoryMedia>,
+}
+
+/// Repository keeping everything in memory.\
+/// Clones share the state, so it can be inspected and changed while a scanner owns another clone.
+#[derive(Clone, Default)]
+pub struct MemoryRepository {
+  pub state: Arc<Mutex<MemoryState>>,
+}
+
+impl MemoryRepository {
+  pub fn state(&self) -> MutexGuard<'_, MemoryState> {
+    self.state.lock().unwrap()
+  }
+
+  /// Returns the stored media with the filename.
+  pub fn media(&self, filename: &str) -> Option<MemoryMedia> {
+    self.state().media.iter().find(|media| media.file.filename == filename).cloned()
+  }
+}
+
+#[rocket::async_trait]
+impl Repository for MemoryRepository {
+  async fn select_folders(&self, user_id: i32) -> Option<Vec<Folder>> {
+    Some(self.state().folders.iter().filter(|folder| folder.owner_id == user_id).cloned().collect())
+  }
+
+  async fn insert_folder(&self, new_folder: NewFolder) -> Option<i32> {
+    let mut state = self.state();
+    let id = state.folders.iter().map(|folder| folder.id).max().unwrap_or_default() + 1;
+
+    state.folders.push(Folder {
+      id,
+      owner_id: new_folder.owner_id,
+      parent: new_folder.parent,
+      name: new_folder.name,
+      missing_since: None,
+      uuid: Some(new_folder.uuid),
+    });
+
+    Some(id)
+  }
+
+  async fn delete_folders(&self, folder_ids: Vec<i32>) -> bool {
+    let mut state = self.state();
+    state.folders.retain(|folder| !folder_ids.contains(&folder.id));
+    state.folder_scans.retain(|folder_id, _| !folder_ids.contains(folder_id));
+
+    true
+  }
+
+  async fn update_folders_missing_since(&self, folder_ids: Vec<i32>, missing_since: Option<NaiveDateTime>) -> bool {
+    for folder in self.state().folders.iter_mut().filter(|folder| folder_ids.contains(&folder.id)) {
+      folder.missing_since = missing_since;
+    }
+
+    true
+  }
+
+  async fn select_folder_scans(&self, user_id: i32) -> Option<Vec<FolderScan>> {
+    let state = self.state();
+
+    Some(state.folder_scans.iter()
+      .filter(|(folder_id, _)| state.folders.iter().any(|folder| folder.id == **folder_id && folder.owner_id == user_id))
+      .map(|(folder_id, modified_at)| FolderScan { folder_id: *folder_id, modified_at: *modified_at })
+      .collect())
+  }
+
+  async fn update_folder_scan(&self, folder_scan: FolderScan) -> bool {
+    self.state().folder_scans.insert(folder_scan.folder_id, folder_scan.modified_at);
+
+    true
+  }
+
+  async fn select_scanned_files(&self, folder_id: i32) -> Option<Vec<ScannedFile>> {
+    Some(self.state().media.iter().filter(|media| media.folder_id == folder_id).map(|media| media.file.clone()).collect())
+  }
+
+  async fn update_scanned_files(&self, changes: Vec<ScannedFileChange>) -> bool {
+    let mut state = self.state();
+
+    for change in changes {
+      if let Some(media) = state.media.iter_mut().find(|media| media.file.media_id == change.media_id) {
+        media.file.file_size = Some(change.stat.size);
+        media.file.file_modified_at = Some(change.stat.modified);
+        if let Some(sha2_512) = change.sha2_512 { media.sha2_512 = sha2_512 }
+      }
+    }
+
+    true
+  }
+
+  async fn update_media_missing_since(&self, media_ids: Vec<i32>, missing_since: Option<NaiveDateTime>) -> bool {
+    for media in self.state().media.iter_mut().filter(|media| media_ids.contains(&media.file.media_id)) {
+      media.file.missing_since = missing_since;
+    }
+
+    true
+  }
+
+  async fn count_media(&self, user_id: i32) -> Option<usize> {
+    Some(self.state().media.iter().filter(|media| media.owner_id == user_id).count())
+  }
+
+  async fn media_hash_exists(&self, sha2_512: String, user_id: i32) -> bool {
+    self.state().media.iter().any(|media| media.owner_id == user_id && media.sha2_512 == sha2_512)
+  }
+
+  async fn insert_media(&self, new_media: NewMedia) -> bool {
+    let mut state = self.state();
+    let media_id = state.media.iter().map(|media| media.file.media_id).max().unwrap_or_default() + 1;
+
+    state.media.push(MemoryMedia {
+      folder_id: new_media.folder_id,
+      owner_id: new_media.owner_id,
+      sha2_512: new_media.sha2_512,
+      file: ScannedFile {
+        media_id,
+        filename: new_media.filename,
+        version: 0,
+        file_size: new_media.file_size,
+        file_modified_at: new_media.file_modified_at,
+        missing_since: None,
+      },
+    });
+
+    true
+  }
+}
@@ -1,15 +1,34 @@
+use crate::config::{DuplicatePolicy, GalleryCheckPolicy, ScanAlertPolicy, SymlinkPolicy};
 use crate::db;
-use crate::models::{Folder, NewFolder};
+use crate::directories::Directories;
+use crate::edit;
+use crate::libraries::UserLibraries;
+use crate::models::{Folder, Media};
 use crate::DbConn;
 use futures::executor;
-use std::fs;
 use std::fs::create_dir_all;
 use std::path::{Path, PathBuf};
+use self::filesystem::LocalFilesystem;
+use self::folder_tree::FolderTree;
+use self::repository::{DbRepository, Repository};
+pub use self::scanner::{FileIssue, FolderReconciliation, MissingFolder, ScanSummary, Scanner};
+
+pub mod filesystem;
+pub mod folder_tree;
+#[cfg(test)]
+pub mod memory;
+pub mod repository;
+pub mod scanner;
 
 /// checks if the file type is supported.
 /// returns **true** for example for **image/jpeg**
-/// and **false** for **text/json**
+/// and **false** for **text/json** or files which can't be read
 pub fn is_media_supported(pathbuf: &Path) -> bool {
+  check_media_supported(pathbuf).unwrap_or(false)
+}
+
+/// Checks if the file type is supported, like `is_media_supported()`; fails when the file can't be read.
+pub fn check_media_supported(pathbuf: &Path) -> std::io::Result<bool> {
   let valid_mime_types = [
     "image/jpeg",
     "image/png",
@@ -39,188 +58,224 @@ pub fn is_media_supported(pathbuf: &Path) -> bool {
     "audio/aac",
   ];
 
-  let kind = infer::get_from_path(pathbuf).unwrap();
+  let kind = infer::get_from_path(pathbuf)?;
 
-  if kind.is_none() { return false; }
+  if kind.is_none() { return Ok(false); }
 
   if valid_mime_types.contains(&kind.unwrap().mime_type()) {
     trace!("Found: {:?} with type: {:?}", pathbuf, kind.unwrap().mime_type());
 
-    return true;
+    return Ok(true);
   }
 
-  false
+  Ok(false)
 }
 
-pub struct Scan {
-  user_id: i32,
-  username: String,
-  directory: PathBuf
+/// Glob patterns of files and folders excluded from scanning.
+/// # Example
+/// Pattern `RAW` ignores all files and folders named RAW, `*.tmp` ignores all files with tmp extension
+/// and `Photos/RAW` ignores only the RAW folder inside of the Photos folder in the user's root folder.
+/// Libraries are subfolders of the root folder, so `external/RAW` ignores the RAW folder of the library `external`.
+#[derive(Clone, Debug, Default)]
+pub struct IgnorePatterns {
+  libraries: UserLibraries,
+  patterns: Vec<glob::Pattern>,
 }
 
-impl Scan {
-  pub async fn new(conn: &DbConn, user_id: i32, directory: PathBuf) -> Option<Self> {
-    let username = db::users::get_user_username(conn, user_id).await?;
+impl IgnorePatterns {
+  /// Creates ignore patterns relative to the user's root folder; invalid patterns are skipped.
+  pub fn new(libraries: UserLibraries, patterns: &[String]) -> Self {
+    let patterns = patterns.iter()
+      .filter_map(|pattern| glob::Pattern::new(pattern).ok())
+      .collect();
 
-    Some(Self {
-      user_id,
-      username,
-      directory
-    })
+    Self { libraries, patterns }
   }
 
-  /// Outputs a list of populated folders.
-  // TODO: find out which is better: strip -> sort vs sort -> strip
-  pub fn get_folders(&self) -> Vec<PathBuf> {
-    let mut dirs = vec![];
-
-    for entry in walkdir::WalkDir::new(PathBuf::from(&self.directory).join(&self.username)) {
-      if entry.is_ok() {
-        let path = entry.unwrap().into_path();
-        if path.is_file() {
-          if let Some(parent) = path.parent() {
-            let strip = PathBuf::from(parent.strip_prefix(&self.directory).unwrap());
-            dirs.push(strip);
-          }
-        }
-      }
-    }
+  /// Checks whether the pattern is valid.
+  pub fn is_valid(pattern: &str) -> bool {
+    !pattern.trim().is_empty() && glob::Pattern::new(pattern).is_ok()
+  }
 
-    dirs.sort();
-    dirs.dedup();
+  /// Checks whether the path is ignored.\
+  /// Patterns without a slash are matched against every component of the path,
+  /// other patterns are matched against the whole path relative to the root folder.
+  pub fn is_ignored(&self, path: &Path) -> bool {
+    if self.patterns.is_empty() { return false }
+
+    // without the root folder, which is the first component
+    let relative: PathBuf = match self.libraries.relative(path) {
+      Some(relative) => relative.iter().skip(1).collect(),
+      None => return false,
+    };
+
+    self.patterns.iter().any(|pattern| {
+      if pattern.as_str().contains('/') {
+        return pattern.matches_path(&relative);
+      }
 
-    dirs
+      relative.iter().any(|component| component.to_str().map_or(false, |name| pattern.matches(name)))
+    })
   }
 }
 
-/// scans folder of a given user
-pub async fn scan_root(conn: &DbConn, xdg_data: PathBuf, user_id: i32) {
-  // root directory
-  let username_option = db::users::get_user_username(conn, user_id).await;
-  if username_option.is_none() { return; }
-
-  let username = username_option.unwrap();
-
-  let current_dir = xdg_data.join(username.clone());
+/// Options affecting which files are scanned.
+#[derive(Clone, Debug, Default)]
+pub struct ScanOptions {
+  pub symlinks: SymlinkPolicy,
+  pub ignore: IgnorePatterns,
+  pub duplicates: DuplicatePolicy,
+  pub alerts: ScanAlertPolicy,
+  /// Folders whose directories disappeared are removed or marked, see `Scanner::reconcile_folders()`.
+  pub reconcile: bool,
+}
 
-  info!("Scanning files and folders for user {} started.", username);
+/// Name of the file marking the gallery directory as available, see `GalleryCheckPolicy`.
+pub const GALLERY_MARKER: &str = ".galera";
 
-  if !Path::new(&current_dir).exists() {
-    let result = create_dir_all(Path::new(&current_dir));
+/// Checks whether the gallery directory looks available according to the policy; returns the reason when it doesn't.
+pub fn check_gallery(gallery: &Path, policy: GalleryCheckPolicy) -> Result<(), String> {
+  let entries = match std::fs::read_dir(gallery) {
+    Ok(entries) => entries,
+    Err(err) => return Err(format!("Gallery directory {:?} can't be read: {}", gallery, err)),
+  };
 
-    if result.is_err() {
-      error!("Failed to create user folder.");
-      return;
-    }
+  if policy.require_marker && !gallery.join(GALLERY_MARKER).is_file() {
+    return Err(format!("Gallery directory {:?} doesn't contain the file {}.", gallery, GALLERY_MARKER));
   }
 
-  let scan = Scan::new(&conn, user_id, xdg_data.clone()).await;
-  if scan.is_none() { return }
-
-  let found_folders = scan.unwrap().get_folders();
-
-  add_folders_to_db(conn, found_folders, user_id).await;
-
-  scan_folders_for_media(conn, xdg_data, user_id).await;
+  if entries.take(policy.min_entries).count() < policy.min_entries {
+    return Err(format!("Gallery directory {:?} has fewer than {} entries.", gallery, policy.min_entries));
+  }
 
-  info!("Scanning is done.");
+  Ok(())
 }
 
-// folders when using NTFS can be max. 260 characters (we currently support max. 255 - Linux maximum and max. VARCHAR size) TODO: warn user when scanning folder that is longer and skip it
-pub async fn add_folders_to_db(conn: &DbConn, relative_paths: Vec<PathBuf>, user_id: i32) {
-  for path in relative_paths {
-    debug!("scanning path: {:?}", path);
-
-    let string_split = path.to_str().unwrap().split('/').into_iter().map(|f| f.to_owned());
-
-    let mut parent: Option<i32> = None;
-    for (i, s) in string_split.enumerate() {
-      let folder_id: Option<i32>;
-      if i == 0 {
-        parent = None;
+/// Checks whether a symlink should be scanned according to the policy.
+fn is_symlink_allowed(path: &Path, policy: SymlinkPolicy) -> bool {
+  match policy {
+    SymlinkPolicy::Follow => true,
+    SymlinkPolicy::Ignore => {
+      debug!("Ignoring symlink {:?}.", path);
+      false
+    },
+    SymlinkPolicy::Record => {
+      if path.is_dir() {
+        info!("Symlinked directory {:?} was not scanned.", path);
+        return false;
       }
 
-      folder_id = db::folders::select_child_folder_id(conn, s.clone(), parent, user_id).await;
+      true
+    },
+  }
+}
 
-      if folder_id.is_none() {
-        let new_folder = NewFolder::new(user_id, s.clone(), parent);
+/// Scans the folder of a given user, including their libraries.\
+/// When the gallery or a library doesn't pass the checks of the policy, folders aren't reconciled,
+/// or the scan fails in the strict mode.
+pub async fn scan_root(conn: &DbConn, xdg_data: PathBuf, user_id: i32, symlinks: SymlinkPolicy, duplicates: DuplicatePolicy, alerts: ScanAlertPolicy, gallery_check: GalleryCheckPolicy) -> Result<ScanSummary, &'static str> {
+  // root directory
+  let username_option = db::users::get_user_username(conn, user_id).await;
+  if username_option.is_err() { return Err("User couldn't be loaded.") }
 
-        db::folders::insert_folder(conn, new_folder, s, path.clone()).await;
+  let username_option = username_option.unwrap();
+  if username_option.is_none() { return Err("User doesn't exist.") }
 
-        let last_insert_id = db::general::get_last_insert_id(conn).await;
+  let username = username_option.unwrap();
 
-        if last_insert_id.is_none() {
-          error!("Last insert id was not returned. This may happen if restarting MySQL during scanning.");
-          return;
-        }
+  let libraries = UserLibraries::load(conn, &xdg_data, user_id, &username).await;
+  if libraries.is_err() {
+    error!("Libraries of user {} couldn't be loaded.", username);
+    return Err("Libraries couldn't be loaded.");
+  }
 
-        parent = Some(last_insert_id.unwrap());
-      } else {
-        parent = folder_id;
-      }
+  let libraries = libraries.unwrap();
+
+  // checked before the user's folder is created, which would be created in an empty mount point
+  let mut checked = vec![xdg_data.as_path()];
+  checked.extend(libraries.directories().into_iter().map(|(_, directory)| directory).filter(|directory| !directory.starts_with(&xdg_data)));
+
+  let mut reconcile = true;
+  for directory in checked {
+    match check_gallery(directory, gallery_check) {
+      Ok(()) => {},
+      Err(reason) if gallery_check.strict => {
+        error!("{} The scan was refused.", reason);
+        return Err("Gallery directory looks unavailable.");
+      },
+      Err(reason) => {
+        warn!("{} Folders won't be reconciled.", reason);
+        reconcile = false;
+      },
     }
   }
-}
 
-pub async fn scan_folders_for_media(conn: &DbConn, xdg_data: PathBuf, user_id: i32) {
-  let username_option = db::users::get_user_username(conn, user_id).await;
-  if username_option.is_none() { return; }
+  let current_dir = libraries.root().to_path_buf();
 
-  let username = username_option.unwrap();
-
-  let root_folder_result = db::folders::select_root_folder(conn, user_id).await;
-  if root_folder_result.is_err() { return }
+  info!("Scanning files and folders for user {} started.", username);
 
-  let root_folder_option = root_folder_result.unwrap();
-  if root_folder_option.is_none() { return }
+  // directories outside of the gallery aren't created, they are checked above
+  if !Path::new(&current_dir).exists() && current_dir.starts_with(&xdg_data) {
+    let result = create_dir_all(Path::new(&current_dir));
 
-  scan_select(conn, root_folder_option.unwrap(), None, xdg_data, user_id, username.clone());
-}
+    if result.is_err() {
+      error!("Failed to create user folder.");
+      return Err("User folder couldn't be created.");
+    }
+  }
 
-pub fn scan_select(conn: &DbConn, parent_folder: Folder, mut path: Option<PathBuf>, xdg_data: PathBuf, user_id: i32, username: String) {
-  if path.is_none() {
-    path = Some(xdg_data.join(parent_folder.name.clone()));
+  let ignore_patterns = db::scan::select_scan_ignore_patterns(conn, user_id).await;
+  if ignore_patterns.is_err() {
+    error!("Scan ignore patterns couldn't be loaded.");
+    return Err("Scan ignore patterns couldn't be loaded.");
   }
-  let folders: Vec<Folder> = executor::block_on(db::folders::select_subfolders(conn, parent_folder.clone(), user_id));
 
-  let path_clean = path.unwrap();
+  let options = ScanOptions {
+    symlinks,
+    ignore: IgnorePatterns::new(libraries.clone(), &ignore_patterns.unwrap()),
+    duplicates,
+    alerts,
+    reconcile,
+  };
 
-  scan_folder_media(conn, parent_folder, path_clean.clone(), user_id);
+  let summary = Scanner::new(LocalFilesystem, DbRepository::new(conn), user_id, libraries, options).run().await;
 
-  for folder in folders {
-    scan_select(conn, folder.clone(), Some(path_clean.clone().join(folder.name)), xdg_data.clone(), user_id, username.clone());
-  }
+  info!("Scanning is done.");
+
+  Ok(summary)
 }
 
-/// Scans user's folder for media
-pub fn scan_folder_media(conn: &DbConn, parent_folder: Folder, path: PathBuf, user_id: i32) {
-  // get files in a folder
-  let media_scanned_option = folder_get_media(path);
-  if media_scanned_option.is_none() { return; }
+/// Finds folders of the user whose directories disappeared without changing them; the next scan reconciles them.
+pub async fn find_orphaned_folders(conn: &DbConn, xdg_data: PathBuf, user_id: i32) -> Result<FolderReconciliation, &'static str> {
+  let username_option = db::users::get_user_username(conn, user_id).await;
+  if username_option.is_err() { return Err("User couldn't be loaded.") }
 
-  let media_scanned_vec = media_scanned_option.unwrap();
+  let username_option = username_option.unwrap();
+  if username_option.is_none() { return Err("User doesn't exist.") }
 
-  if media_scanned_vec.is_empty() { return; }
+  let libraries = UserLibraries::load(conn, &xdg_data, user_id, &username_option.unwrap()).await;
+  if libraries.is_err() { return Err("Libraries couldn't be loaded.") }
 
-  for media_scanned in media_scanned_vec {
-    let name = media_scanned.file_name().unwrap().to_str().unwrap().to_owned();
+  let scanner = Scanner::new(LocalFilesystem, DbRepository::new(conn), user_id, libraries.unwrap(), ScanOptions::default());
 
-    let media: Option<i32> = executor::block_on(db::media::check_if_media_present(conn, name.clone(), parent_folder.clone(), user_id));
+  scanner.reconcile_folders(true).await.ok_or("Folders couldn't be selected.")
+}
 
-    if media.is_none() {
-      debug!("{:?} doesnt exist in database", media_scanned);
+/// Adds folders to the database, including their parents.\
+/// Paths are relative to the gallery directory, e.g. `john/Holiday`.
+pub async fn add_folders_to_db(conn: &DbConn, relative_paths: Vec<PathBuf>, user_id: i32) {
+  scanner::sync_folders(&DbRepository::new(conn), &relative_paths, user_id).await;
+}
 
-      let image_dimensions = image::image_dimensions(media_scanned.clone())
-        .ok();
+/// Returns the ID of a folder given by its path relative to the gallery directory, e.g. `john/Holiday`;
+/// the folder is added with its parents when it isn't in the database yet.
+pub async fn select_or_insert_folder_id(conn: &DbConn, relative_path: PathBuf, user_id: i32) -> Option<i32> {
+  let repository = DbRepository::new(conn);
+  scanner::sync_folders(&repository, std::slice::from_ref(&relative_path), user_id).await?;
 
-      if image_dimensions.is_none() {
-        warn!("Image {:?} was skipped as its dimensions are unknown.", media_scanned);
-        continue;
-      }
+  let tree = FolderTree::new(repository.select_folders(user_id).await?);
 
-      executor::block_on(db::media::insert_media(conn, name, parent_folder.clone(), user_id,  image_dimensions.unwrap(), None, media_scanned));
-    }
-  }
+  relative_path.iter().try_fold(None, |parent, name| Some(Some(tree.get(parent, name.to_str()?)?)))?
 }
 
 /// Recursively selects parent folder.\
@@ -239,25 +294,66 @@ pub fn scan_folder_media(conn: &DbConn, parent_folder: Folder, path: PathBuf, us
 /// ```
 // TODO: Write faster recursive function with diesel's sql_query()
 pub fn select_parent_folder_recursive(conn: &DbConn, current_folder: Folder, user_id: i32, vec: &mut Vec<Folder>) -> bool {
-  let parent = executor::block_on(db::folders::select_parent_folder(conn, current_folder, user_id));
-  if parent.is_none() { return false; }
+  let parent = match executor::block_on(db::folders::select_parent_folder(conn, current_folder, user_id)) {
+    Ok(Some(parent)) => parent,
+    Ok(None) => return false,
+    Err(err) => {
+      error!("Parent folder couldn't be selected: {}", err);
+      return false;
+    },
+  };
+
+  vec.push(parent.clone());
+
+  select_parent_folder_recursive(conn, parent, user_id, vec)
+}
 
-  vec.push(parent.clone().unwrap());
+/// Returns the absolute path of the current version of a media file.\
+/// Edited media are stored outside of the gallery, see `get_original_media_path()` for the original.
+/// # Example
+/// ```
+/// let path: Option<PathBuf> = get_media_path(&conn, &media).await;
+/// ```
+pub async fn get_media_path(conn: &DbConn, media: &Media) -> Option<PathBuf> {
+  if media.version != 0 { return edit::media_version_path(&media.uuid, &media.filename, media.version) }
 
-  select_parent_folder_recursive(conn, parent.unwrap(), user_id, vec)
+  get_original_media_path(conn, media).await
 }
 
+/// Returns folders of the media, from its folder up to the root folder of its owner.
+async fn select_media_folders(conn: &DbConn, media: &Media) -> Option<Vec<Folder>> {
+  let mut folders: Vec<Folder> = vec!();
+
+  let current_folder = db::folders::select_folder(conn, media.folder_id).await.ok()??;
+  folders.push(current_folder.clone());
+
+  select_parent_folder_recursive(conn, current_folder, media.owner_id, &mut folders);
+
+  Some(folders)
+}
+
+/// Returns the path of the original media file relative to the folder of its owner, e.g. `Holiday/IMG_0001.jpg`.\
+/// Media in libraries start with the name of the library.
+pub async fn get_relative_media_path(conn: &DbConn, media: &Media) -> Option<PathBuf> {
+  let folders = select_media_folders(conn, media).await?;
+
+  // the root folder is named after the owner
+  let relative: PathBuf = folders.iter().rev().skip(1).map(|folder| folder.name.as_str()).collect();
+
+  Some(relative.join(&media.filename))
+}
+
+/// Returns the absolute path of the original media file in the gallery or in a library of its owner.
+pub async fn get_original_media_path(conn: &DbConn, media: &Media) -> Option<PathBuf> {
+  let xdg_data = Directories::new()?.gallery()?;
+
+  let folders = select_media_folders(conn, media).await?;
 
-pub fn folder_get_media(dir: PathBuf) -> Option<Vec<PathBuf>> {
-  if !dir.exists() { return None; }
+  // the root folder is named after the owner
+  let username = folders.last()?.name.clone();
+  let libraries = UserLibraries::load(conn, &xdg_data, media.owner_id, &username).await.ok()?;
 
-  let data: Vec<PathBuf> = fs::read_dir(&dir).unwrap()
-    .into_iter()
-    .filter(|r| r.is_ok()) // Get rid of Err variants for Result<DirEntry>
-    .map(|r| r.unwrap().path()) // This is safe, since we only have the Ok variants
-    .filter(|r| r.is_file()) // Get rid of Err variants for Result<DirEntry>
-    .filter(|r| is_media_supported(r)) // Filter out non-folders
-    .collect();
+  let relative: PathBuf = folders.iter().rev().map(|folder| folder.name.as_str()).collect();
 
-  Some(data)
+  Some(libraries.resolve(&relative).join(&media.filename))
 }
@@ -0,0 +1,186 @@
+//! Storage of scanned folders and media.
+
+use crate::db;
+use crate::models::{Folder, FolderScan, NewFolder, NewMedia, ScannedFile, ScannedFileChange};
+use crate::DbConn;
+use chrono::NaiveDateTime;
+
+/// Storage used by the `Scanner`.
+#[rocket::async_trait]
+pub trait Repository {
+  /// Returns all folders of the user; `None` when they can't be selected.
+  async fn select_folders(&self, user_id: i32) -> Option<Vec<Folder>>;
+
+  /// Inserts a folder and returns its ID.
+  async fn insert_folder(&self, new_folder: NewFolder) -> Option<i32>;
+
+  /// Deletes folders in the given order together with their scans; returns `false` when it fails.
+  async fn delete_folders(&self, folder_ids: Vec<i32>) -> bool;
+
+  /// Sets or clears the time since which directories of the folders are missing; returns `false` when it fails.
+  async fn update_folders_missing_since(&self, folder_ids: Vec<i32>, missing_since: Option<NaiveDateTime>) -> bool;
+
+  /// Returns modification times of the user's folders at their last complete scan.
+  async fn select_folder_scans(&self, user_id: i32) -> Option<Vec<FolderScan>>;
+
+  /// Stores the modification time of a completely scanned folder.
+  async fn update_folder_scan(&self, folder_scan: FolderScan) -> bool;
+
+  /// Returns files of media in the folder as recorded by the last scan.
+  async fn select_scanned_files(&self, folder_id: i32) -> Option<Vec<ScannedFile>>;
+
+  /// Stores changes of scanned files; returns `false` when it fails.
+  async fn update_scanned_files(&self, changes: Vec<ScannedFileChange>) -> bool;
+
+  /// Sets or clears the time since which files of the media are missing; returns `false` when it fails.
+  async fn update_media_missing_since(&self, media_ids: Vec<i32>, missing_since: Option<NaiveDateTime>) -> bool;
+
+  /// Counts media of the user; `None` when they can't be counted.
+  async fn count_media(&self, user_id: i32) -> Option<usize>;
+
+  /// Checks whether the user already has media with the given hash.
+  async fn media_hash_exists(&self, sha2_512: String, user_id: i32) -> bool;
+
+  /// Inserts media; returns `false` when it fails.
+  async fn insert_media(&self, new_media: NewMedia) -> bool;
+}
+
+/// Repository backed by the database.
+pub struct DbRepository<'a> {
+  conn: &'a DbConn,
+}
+
+impl<'a> DbRepository<'a> {
+  pub fn new(conn: &'a DbConn) -> Self {
+    Self { conn }
+  }
+}
+
+#[rocket::async_trait]
+impl Repository for DbRepository<'_> {
+  async fn select_folders(&self, user_id: i32) -> Option<Vec<Folder>> {
+    match db::folders::select_user_folders(self.conn, user_id).await {
+      Ok(folders) => Some(folders),
+      Err(err) => {
+        error!("Folders of user {} couldn't be selected: {}", user_id, err);
+        None
+      },
+    }
+  }
+
+  async fn insert_folder(&self, new_folder: NewFolder) -> Option<i32> {
+    let name = new_folder.name.clone();
+
+    match db::folders::insert_folder(self.conn, new_folder).await {
+      Ok(folder) => Some(folder.id),
+      Err(err) => {
+        error!("Folder {} couldn't be inserted: {}", name, err);
+        None
+      },
+    }
+  }
+
+  async fn delete_folders(&self, folder_ids: Vec<i32>) -> bool {
+    match db::folders::delete_folders(self.conn, folder_ids).await {
+      Ok(()) => true,
+      Err(err) => {
+        error!("Folders couldn't be deleted: {}", err);
+        false
+      },
+    }
+  }
+
+  async fn update_folders_missing_since(&self, folder_ids: Vec<i32>, missing_since: Option<NaiveDateTime>) -> bool {
+    match db::folders::update_folders_missing_since(self.conn, folder_ids, missing_since).await {
+      Ok(_) => true,
+      Err(err) => {
+        error!("Missing folders couldn't be updated: {}", err);
+        false
+      },
+    }
+  }
+
+  async fn select_folder_scans(&self, user_id: i32) -> Option<Vec<FolderScan>> {
+    match db::scan::select_folder_scans(self.conn, user_id).await {
+      Ok(folder_scans) => Some(folder_scans),
+      Err(err) => {
+        error!("Folder scans of user {} couldn't be selected: {}", user_id, err);
+        None
+      },
+    }
+  }
+
+  async fn update_folder_scan(&self, folder_scan: FolderScan) -> bool {
+    let folder_id = folder_scan.folder_id;
+
+    match db::scan::replace_folder_scan(self.conn, folder_scan).await {
+      Ok(_) => true,
+      Err(err) => {
+        error!("Scan of folder {} couldn't be saved: {}", folder_id, err);
+        false
+      },
+    }
+  }
+
+  async fn select_scanned_files(&self, folder_id: i32) -> Option<Vec<ScannedFile>> {
+    match db::media::select_scanned_files(self.conn, folder_id).await {
+      Ok(files) => Some(files),
+      Err(err) => {
+        error!("Media of folder {} couldn't be selected: {}", folder_id, err);
+        None
+      },
+    }
+  }
+
+  async fn update_scanned_files(&self, changes: Vec<ScannedFileChange>) -> bool {
+    match db::media::update_scanned_files(self.conn, changes).await {
+      Ok(()) => true,
+      Err(err) => {
+        error!("Scanned files couldn't be updated: {}", err);
+        false
+      },
+    }
+  }
+
+  async fn update_media_missing_since(&self, media_ids: Vec<i32>, missing_since: Option<NaiveDateTime>) -> bool {
+    match db::media::update_media_missing_since(self.conn, media_ids, missing_since).await {
+      Ok(_) => true,
+      Err(err) => {
+        error!("Missing media couldn't be updated: {}", err);
+        false
+      },
+    }
+  }
+
+  async fn count_media(&self, user_id: i32) -> Option<usize> {
+    match db::media::count_user_media(self.conn, user_id).await {
+      Ok(media) => Some(media as usize),
+      Err(err) => {
+        error!("Media of user {} couldn't be counted: {}", user_id, err);
+        None
+      },
+    }
+  }
+
+  async fn media_hash_exists(&self, sha2_512: String, user_id: i32) -> bool {
+    match db::media::media_hash_exists(self.conn, user_id, sha2_512).await {
+      Ok(exists) => exists,
+      Err(err) => {
+        error!("Duplicates of user {} couldn't be checked: {}", user_id, err);
+        false
+      },
+    }
+  }
+
+  async fn insert_media(&self, new_media: NewMedia) -> bool {
+    let filename = new_media.filename.clone();
+
+    match db::media::insert_media_batch(self.conn, vec![new_media]).await {
+      Ok(_) => true,
+      Err(err) => {
+        error!("Media {} couldn't be inserted: {}", filename, err);
+        false
+      },
+    }
+  }
+}
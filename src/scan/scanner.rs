@@ -0,0 +1,602 @@
+//! Scanning of a user's gallery folder in separate stages.
+//!
+//! 1. `Scanner::discover_folders()` finds folders containing files.
+//! 2. `Scanner::sync_folders()` adds missing folders to the repository.
+//! 3. `Scanner::scan_media()` adds new media of every folder in the repository.
+//! 4. `Scanner::reconcile_folders()` removes or marks folders whose directories disappeared.
+//!
+//! Media whose files disappeared are marked as missing by `Scanner::scan_media()` and left out of listings;
+//! the mark is cleared when the file appears again. Users can purge or relink them (see `/user/scan/media`).
+//!
+//! Scans are incremental: folders whose modification time didn't change since their last complete scan
+//! aren't listed (only their known files are checked), and files whose size and modification time didn't change
+//! aren't read.
+//!
+//! Changes of modified files and scans of folders are stored after all folders are scanned, so they can be held back
+//! when an unusual share of media is modified or missing (see `ScanAlertPolicy`).
+//!
+//! Folders are identified by their paths relative to the gallery directory; their directories may be in libraries
+//! outside of the gallery (see `libraries`).
+//!
+//! The filesystem and the repository are injected, so each stage can run against
+//! a temporary directory or in memory (see `memory`).
+
+use crate::config::DuplicatePolicy;
+use crate::libraries::UserLibraries;
+use crate::models::{Folder, FolderScan, NewFolder, NewMedia, ScanIssueKind, ScannedFile, ScannedFileChange};
+use super::filesystem::Filesystem;
+use super::folder_tree::FolderTree;
+use super::repository::Repository;
+use super::ScanOptions;
+use chrono::{Duration, NaiveDateTime, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Folders modified less than this number of seconds ago are scanned again next time.
+const RACY_SECONDS: i64 = 2;
+
+/// Problem with a file which the scan couldn't add.
+#[derive(Debug, Clone)]
+pub struct FileIssue {
+  /// Absolute path while scanning; relative to the gallery directory in the `ScanSummary`.
+  pub path: PathBuf,
+  pub kind: ScanIssueKind,
+  pub message: Option<String>,
+}
+
+impl FileIssue {
+  pub fn new(path: PathBuf, kind: ScanIssueKind, message: Option<String>) -> Self {
+    Self { path, kind, message }
+  }
+}
+
+/// Result of scanning the media of a user.
+#[derive(Debug, Default, Clone)]
+pub struct ScanSummary {
+  /// Media of the user before the scan.
+  pub media: usize,
+  pub added: usize,
+  /// Media whose files changed since the last scan.
+  pub modified: usize,
+  /// Media whose files weren't found in their folders, not counting media already marked as missing.
+  pub missing: usize,
+  /// Media marked as missing whose files appeared again.
+  pub restored: usize,
+  /// An unusual share of the media is modified or missing.
+  pub alert: bool,
+  /// Changes of modified media weren't stored because of the alert.
+  pub paused: bool,
+  /// Folders removed because their directories disappeared.
+  pub removed_folders: usize,
+  /// Folders with media whose directories disappeared.
+  pub missing_folders: usize,
+  /// Files of changed folders which weren't added.
+  pub issues: Vec<FileIssue>,
+}
+
+/// Folders whose directories disappeared, see `Scanner::reconcile_folders()`.
+#[derive(Serialize, JsonSchema, Debug, Default)]
+pub struct FolderReconciliation {
+  /// Folders without media, not even in their subfolders; they're removed.
+  pub removed: Vec<String>,
+  /// Folders with media; they're marked as missing.
+  pub missing: Vec<MissingFolder>,
+  /// Folders marked as missing whose directories appeared again; the mark is cleared.
+  pub restored: Vec<String>,
+}
+
+#[derive(Serialize, JsonSchema, Debug)]
+pub struct MissingFolder {
+  /// Path relative to the gallery directory, e.g. `john/Holiday`.
+  pub path: String,
+  /// Media directly in the folder.
+  pub media: usize,
+}
+
+/// Result of scanning one folder, stored once all folders are scanned.
+#[derive(Default)]
+struct FolderResult {
+  added: usize,
+  /// Media whose files disappeared since the last scan.
+  missing: Vec<i32>,
+  /// Media marked as missing whose files appeared again.
+  restored: Vec<i32>,
+  changes: Vec<ScannedFileChange>,
+  issues: Vec<FileIssue>,
+  /// Set when the folder was scanned completely.
+  folder_scan: Option<FolderScan>,
+}
+
+pub struct Scanner<F: Filesystem, R: Repository> {
+  filesystem: F,
+  repository: R,
+  user_id: i32,
+  /// Directories of the user's folders.
+  libraries: UserLibraries,
+  options: ScanOptions,
+}
+
+impl<F: Filesystem + Clone + Send + Sync + 'static, R: Repository + Sync> Scanner<F, R> {
+  pub fn new(filesystem: F, repository: R, user_id: i32, libraries: UserLibraries, options: ScanOptions) -> Self {
+    Self { filesystem, repository, user_id, libraries, options }
+  }
+
+  /// Runs all stages; folders aren't reconciled when the options say so or when the changes were held back.
+  pub async fn run(&self) -> ScanSummary {
+    let folders = self.discover_folders();
+
+    if self.sync_folders(&folders).await.is_none() { return ScanSummary::default() }
+
+    let mut summary = self.scan_media().await;
+    if !self.options.reconcile || summary.paused { return summary }
+
+    if let Some(reconciliation) = self.reconcile_folders(false).await {
+      summary.removed_folders = reconciliation.removed.len();
+      summary.missing_folders = reconciliation.missing.len();
+    }
+
+    summary
+  }
+
+  /// Returns folders containing files, relative to the gallery directory (e.g. `john/Holiday`).\
+  /// Directories hidden by libraries are left out.
+  pub fn discover_folders(&self) -> Vec<PathBuf> {
+    self.libraries.directories()
+      .into_iter()
+      .flat_map(|(relative, directory)| {
+        self.filesystem.folders(directory, &self.options)
+          .into_iter()
+          .filter_map(move |folder| Some((relative.join(folder.strip_prefix(directory).ok()?), folder)))
+      })
+      .filter(|(relative, folder)| self.libraries.resolve(relative) == *folder)
+      .map(|(relative, _)| relative)
+      .collect()
+  }
+
+  /// Adds folders which are not in the repository yet, including their parents.
+  pub async fn sync_folders(&self, relative_paths: &[PathBuf]) -> Option<()> {
+    sync_folders(&self.repository, relative_paths, self.user_id).await
+  }
+
+  /// Adds new media of all folders in the repository and updates changed files.\
+  /// Changes are held back when an unusual share of media is modified or missing and the policy says so.
+  pub async fn scan_media(&self) -> ScanSummary {
+    let mut summary = ScanSummary::default();
+
+    let tree = match self.repository.select_folders(self.user_id).await {
+      Some(folders) => FolderTree::new(folders),
+      None => return summary,
+    };
+
+    let folder_scans: HashMap<i32, NaiveDateTime> = match self.repository.select_folder_scans(self.user_id).await {
+      Some(folder_scans) => folder_scans.into_iter().map(|scan| (scan.folder_id, scan.modified_at)).collect(),
+      None => return summary,
+    };
+
+    summary.media = match self.repository.count_media(self.user_id).await {
+      Some(media) => media,
+      None => return summary,
+    };
+
+    let root_folder = match tree.root() {
+      Some(root_folder) => root_folder,
+      None => return summary,
+    };
+
+    let mut changes = vec![];
+    let (mut missing, mut restored) = (vec![], vec![]);
+    let mut completed_folders = vec![];
+    let mut folders = vec![(PathBuf::from(&root_folder.name), root_folder)];
+
+    while let Some((path, folder)) = folders.pop() {
+      let result = self.scan_folder_media(&self.libraries.resolve(&path), folder, folder_scans.get(&folder.id).copied()).await;
+
+      summary.added += result.added;
+      summary.missing += result.missing.len();
+      summary.restored += result.restored.len();
+      missing.extend(result.missing);
+      restored.extend(result.restored);
+      summary.modified += result.changes.iter().filter(|change| change.sha2_512.is_some()).count();
+      changes.extend(result.changes);
+      summary.issues.extend(result.issues.into_iter().map(|issue| self.relative_issue(issue)));
+      completed_folders.extend(result.folder_scan);
+
+      for subfolder in tree.children(folder.id) {
+        folders.push((path.join(&subfolder.name), subfolder));
+      }
+    }
+
+    summary.alert = self.options.alerts.is_unusual(summary.media, summary.modified + summary.missing);
+    if summary.alert {
+      warn!("Scan of user {} found {} of {} media modified and {} missing.", self.libraries.username(), summary.modified, summary.media, summary.missing);
+    }
+
+    // folders aren't marked as scanned, so the held back changes are found again by the next scan
+    summary.paused = summary.alert && self.options.alerts.pause;
+    if summary.paused { return summary }
+
+    if !changes.is_empty() && !self.repository.update_scanned_files(changes).await { return summary }
+
+    if !missing.is_empty() && !self.repository.update_media_missing_since(missing, Some(Utc::now().naive_utc())).await { return summary }
+    if !restored.is_empty() && !self.repository.update_media_missing_since(restored, None).await { return summary }
+
+    for folder_scan in completed_folders {
+      self.repository.update_folder_scan(folder_scan).await;
+    }
+
+    summary
+  }
+
+  /// Removes folders whose directories disappeared and which have no media, not even in their subfolders,
+  /// and marks the other ones as missing; the mark is cleared when the directory appears again.\
+  /// With `dry_run`, nothing is changed and only the report is returned.\
+  /// Returns `None` when the folders can't be read or changed.
+  pub async fn reconcile_folders(&self, dry_run: bool) -> Option<FolderReconciliation> {
+    let mut report = FolderReconciliation::default();
+
+    let tree = FolderTree::new(self.repository.select_folders(self.user_id).await?);
+    let root_folder = match tree.root() {
+      Some(root_folder) => root_folder,
+      None => return Some(report),
+    };
+
+    // parents are listed before their subfolders
+    let mut ordered = vec![];
+    let mut folders = vec![(PathBuf::from(&root_folder.name), root_folder)];
+
+    while let Some((path, folder)) = folders.pop() {
+      for subfolder in tree.children(folder.id) {
+        folders.push((path.join(&subfolder.name), subfolder));
+      }
+
+      ordered.push((path, folder));
+    }
+
+    let (mut removed, mut missing, mut restored) = (vec![], vec![], vec![]);
+    // parents of kept missing folders can't be removed
+    let mut kept = HashSet::new();
+
+    for (path, folder) in ordered.into_iter().rev() {
+      let path_string = path.to_string_lossy().into_owned();
+
+      if self.filesystem.stat(&self.libraries.resolve(&path)).is_some() {
+        if folder.missing_since.is_some() {
+          restored.push(folder.id);
+          report.restored.push(path_string);
+        }
+
+        continue;
+      }
+
+      let media = self.repository.select_scanned_files(folder.id).await?.len();
+
+      // the root folder is never removed
+      if media == 0 && folder.parent.is_some() && !kept.contains(&folder.id) {
+        removed.push(folder.id);
+        report.removed.push(path_string);
+        continue;
+      }
+
+      kept.extend(folder.parent);
+      if folder.missing_since.is_none() { missing.push(folder.id) }
+      report.missing.push(MissingFolder { path: path_string, media });
+    }
+
+    if dry_run { return Some(report) }
+
+    if !report.removed.is_empty() || !report.missing.is_empty() {
+      info!("Folders of user {}: {} removed and {} missing.", self.libraries.username(), report.removed.len(), report.missing.len());
+    }
+
+    // subfolders are ordered before their parents, which they reference
+    if !removed.is_empty() && !self.repository.delete_folders(removed).await { return None }
+    if !missing.is_empty() && !self.repository.update_folders_missing_since(missing, Some(Utc::now().naive_utc())).await { return None }
+    if !restored.is_empty() && !self.repository.update_folders_missing_since(restored, None).await { return None }
+
+    Some(report)
+  }
+
+  fn relative_issue(&self, issue: FileIssue) -> FileIssue {
+    let path = self.libraries.relative(&issue.path).unwrap_or(issue.path);
+
+    FileIssue { path, ..issue }
+  }
+
+  /// Adds new media of one folder and finds changed and missing files.\
+  /// `last_scan` is the modification time of the folder at its last complete scan; while it's unchanged, the folder
+  /// isn't listed and only its known files are checked, as files changed in place don't change their folder.\
+  /// Files already in the user's library under another name are skipped when the policy says so.
+  async fn scan_folder_media(&self, path: &Path, folder: &Folder, last_scan: Option<NaiveDateTime>) -> FolderResult {
+    let mut result = FolderResult::default();
+
+    // read before listing the folder, so files added during the scan change it
+    let modified = self.filesystem.stat(path).map(|stat| stat.modified);
+
+    let mut scanned: HashMap<String, _> = match self.repository.select_scanned_files(folder.id).await {
+      Some(files) => files.into_iter().map(|file| (file.filename.clone(), file)).collect(),
+      None => return result,
+    };
+
+    if modified.is_some() && modified == last_scan {
+      trace!("Folder {:?} is unchanged since the last scan.", path);
+
+      // files can't be added or removed without changing the folder, so missing media stay missing
+      for file in scanned.into_values().filter(|file| file.missing_since.is_none()) {
+        result.changes.extend(self.file_change(&path.join(&file.filename), &file).await);
+      }
+
+      return result;
+    }
+
+    let mut complete = true;
+
+    let (files, issues) = self.filesystem.media(path, &self.options);
+    result.issues = issues;
+
+    for media in files {
+      let name = match media.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name.to_owned(),
+        None => continue,
+      };
+
+      // files left in `scanned` after listing the folder are missing
+      if let Some(file) = scanned.remove(&name) {
+        if file.missing_since.is_some() {
+          info!("Missing media {:?} appeared again.", media);
+          result.restored.push(file.media_id);
+        }
+
+        result.changes.extend(self.file_change(&media, &file).await);
+        continue;
+      }
+
+      debug!("{:?} doesn't exist in the repository", media);
+
+      let dimensions = self.filesystem.dimensions(&media);
+      if dimensions.is_none() {
+        warn!("Image {:?} was skipped as its dimensions are unknown.", media);
+        result.issues.push(FileIssue::new(media, ScanIssueKind::UnknownDimensions, None));
+        continue;
+      }
+
+      let hash = match self.hash(&media).await {
+        Some(hash) => hash,
+        None => {
+          complete = false;
+          result.issues.push(FileIssue::new(media, ScanIssueKind::Unreadable, None));
+          continue;
+        },
+      };
+
+      if self.options.duplicates == DuplicatePolicy::Skip && self.repository.media_hash_exists(hash.clone(), self.user_id).await {
+        info!("Media {:?} was skipped as it's a duplicate of existing media.", media);
+        continue;
+      }
+
+      let stat = self.filesystem.stat(&media);
+      let (width, height) = dimensions.unwrap();
+      let new_media = NewMedia::new(name, folder.id, self.user_id, width, height, None, NaiveDateTime::from_timestamp(10, 10), None, Uuid::new_v4().to_string(), hash)
+        .with_metadata(self.filesystem.metadata(&media))
+        .with_file_stat(stat);
+
+      if self.repository.insert_media(new_media).await {
+        result.added += 1;
+      } else {
+        complete = false;
+        result.issues.push(FileIssue::new(media, ScanIssueKind::NotSaved, None));
+      }
+    }
+
+    result.missing = scanned.into_values()
+      .filter(|file| file.missing_since.is_none())
+      .map(|file| file.media_id)
+      .collect();
+
+    // like git, a folder modified within the last moment isn't trusted, as it could change again within the same timestamp
+    let racy = |modified: NaiveDateTime| modified > Utc::now().naive_utc() - Duration::seconds(RACY_SECONDS);
+
+    result.folder_scan = modified
+      .filter(|modified| complete && !racy(*modified))
+      .map(|modified| FolderScan { folder_id: folder.id, modified_at: modified });
+
+    result
+  }
+
+  /// Hashes the media on a blocking thread, so reading large files doesn't stall other tasks of the runtime.\
+  /// Returns `None` when the hashing thread panicked.
+  async fn hash(&self, media: &Path) -> Option<String> {
+    let filesystem = self.filesystem.clone();
+    let media = media.to_path_buf();
+
+    rocket::tokio::task::spawn_blocking(move || filesystem.hash(&media)).await.ok()
+  }
+
+  /// Returns the current size and modification time of the scanned file when they changed since the last scan,
+  /// with the new hash when the content could have changed. The change is left for the next scan when hashing fails.
+  async fn file_change(&self, path: &Path, file: &ScannedFile) -> Option<ScannedFileChange> {
+    let stat = self.filesystem.stat(path).filter(|stat| file.stat() != Some(*stat))?;
+
+    // media scanned before file stats were recorded are assumed to be unchanged
+    let sha2_512 = match file.stat() {
+      Some(_) => Some(self.hash(path).await?),
+      None => None,
+    };
+    if sha2_512.is_some() { info!("Media {:?} changed since the last scan.", path) }
+
+    Some(ScannedFileChange { media_id: file.media_id, version: file.version, stat, sha2_512 })
+  }
+}
+
+/// Adds folders which are not in the repository yet, including their parents.\
+/// Paths are relative to the gallery directory, so their first component is the user's root folder.\
+/// Returns `None` when inserting a folder fails.
+// folders when using NTFS can be max. 260 characters (we currently support max. 255 - Linux maximum and max. VARCHAR size) TODO: warn user when scanning folder that is longer and skip it
+pub async fn sync_folders<R: Repository + Sync>(repository: &R, relative_paths: &[PathBuf], user_id: i32) -> Option<()> {
+  // all folders are selected at once instead of selecting each segment of each path
+  let mut tree = FolderTree::new(repository.select_folders(user_id).await?);
+
+  for path in relative_paths {
+    debug!("scanning path: {:?}", path);
+
+    let mut parent: Option<i32> = None;
+    for name in path.iter().filter_map(|name| name.to_str()) {
+      parent = match tree.get(parent, name) {
+        Some(folder_id) => Some(folder_id),
+        None => {
+          let new_folder = NewFolder::new(user_id, name.to_owned(), parent);
+          let uuid = new_folder.uuid.clone();
+
+          let folder_id = repository.insert_folder(new_folder).await?;
+          tree.insert(Folder { id: folder_id, owner_id: user_id, parent, name: name.to_owned(), missing_since: None, uuid: Some(uuid) });
+
+          Some(folder_id)
+        },
+      };
+    }
+  }
+
+  Some(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Scanner;
+  use crate::libraries::UserLibraries;
+  use crate::scan::memory::{MemoryFilesystem, MemoryRepository};
+  use crate::scan::ScanOptions;
+  use chrono::{NaiveDate, NaiveDateTime};
+  use std::path::Path;
+
+  const USER_ID: i32 = 1;
+
+  fn time(day: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd(2022, 1, day).and_hms(12, 0, 0)
+  }
+
+  fn scanner(filesystem: &MemoryFilesystem, repository: &MemoryRepository) -> Scanner<MemoryFilesystem, MemoryRepository> {
+    let libraries = UserLibraries::new(Path::new("/gallery"), "john", &[]);
+    let options = ScanOptions { reconcile: true, ..ScanOptions::default() };
+
+    Scanner::new(filesystem.clone(), repository.clone(), USER_ID, libraries, options)
+  }
+
+  /// Gallery of john with two media in his root folder and one in `Holiday`, scanned once.
+  async fn scanned_gallery() -> (MemoryFilesystem, MemoryRepository) {
+    let filesystem = MemoryFilesystem::default();
+    filesystem.write("/gallery/john/a.jpg", 100, time(1), "a");
+    filesystem.write("/gallery/john/b.jpg", 100, time(1), "b");
+    filesystem.write("/gallery/john/Holiday/c.jpg", 100, time(1), "c");
+
+    let repository = MemoryRepository::default();
+    scanner(&filesystem, &repository).run().await;
+
+    (filesystem, repository)
+  }
+
+  #[rocket::async_test]
+  async fn adds_folders_and_media() {
+    let filesystem = MemoryFilesystem::default();
+    filesystem.write("/gallery/john/a.jpg", 100, time(1), "a");
+    filesystem.write("/gallery/john/Holiday/c.jpg", 100, time(1), "c");
+
+    let repository = MemoryRepository::default();
+    let summary = scanner(&filesystem, &repository).run().await;
+
+    assert_eq!(summary.added, 2);
+
+    let state = repository.state();
+    let mut folders: Vec<&str> = state.folders.iter().map(|folder| folder.name.as_str()).collect();
+    folders.sort_unstable();
+    assert_eq!(folders, ["Holiday", "john"]);
+
+    // both folders were scanned completely, so they aren't listed again while they're unchanged
+    assert_eq!(state.folder_scans.len(), 2);
+  }
+
+  #[rocket::async_test]
+  async fn skips_known_media() {
+    let (filesystem, repository) = scanned_gallery().await;
+
+    let summary = scanner(&filesystem, &repository).run().await;
+
+    assert_eq!((summary.media, summary.added, summary.modified, summary.missing), (3, 0, 0, 0));
+  }
+
+  #[rocket::async_test]
+  async fn finds_files_changed_in_place() {
+    let (filesystem, repository) = scanned_gallery().await;
+
+    // the modification time of the directory doesn't change
+    filesystem.write("/gallery/john/Holiday/c.jpg", 200, time(2), "c2");
+
+    let summary = scanner(&filesystem, &repository).run().await;
+
+    assert_eq!(summary.modified, 1);
+
+    let media = repository.media("c.jpg").unwrap();
+    assert_eq!(media.sha2_512, "c2");
+    assert_eq!(media.file.file_size, Some(200));
+  }
+
+  #[rocket::async_test]
+  async fn marks_missing_media_and_restores_them() {
+    let (filesystem, repository) = scanned_gallery().await;
+
+    filesystem.remove("/gallery/john/b.jpg");
+    filesystem.touch("/gallery/john", time(2));
+
+    let summary = scanner(&filesystem, &repository).run().await;
+
+    assert_eq!(summary.missing, 1);
+    assert!(repository.media("b.jpg").unwrap().file.missing_since.is_some());
+
+    filesystem.write("/gallery/john/b.jpg", 100, time(1), "b");
+    filesystem.touch("/gallery/john", time(3));
+
+    let summary = scanner(&filesystem, &repository).run().await;
+
+    assert_eq!((summary.restored, summary.missing), (1, 0));
+    assert!(repository.media("b.jpg").unwrap().file.missing_since.is_none());
+  }
+
+  #[rocket::async_test]
+  async fn adds_new_media_of_changed_folders() {
+    let (filesystem, repository) = scanned_gallery().await;
+
+    filesystem.write("/gallery/john/Holiday/d.jpg", 100, time(2), "d");
+    filesystem.touch("/gallery/john/Holiday", time(2));
+
+    let summary = scanner(&filesystem, &repository).run().await;
+
+    assert_eq!(summary.added, 1);
+    assert!(repository.media("d.jpg").is_some());
+  }
+
+  #[rocket::async_test]
+  async fn marks_folders_whose_directories_disappeared() {
+    let (filesystem, repository) = scanned_gallery().await;
+
+    filesystem.write("/gallery/john/Empty/e.jpg", 100, time(1), "e");
+    scanner(&filesystem, &repository).run().await;
+
+    // the folder of a media which was purged
+    repository.state().media.retain(|media| media.file.filename != "e.jpg");
+
+    filesystem.remove("/gallery/john/Holiday");
+    filesystem.remove("/gallery/john/Empty");
+    filesystem.touch("/gallery/john", time(2));
+
+    let reconciliation = scanner(&filesystem, &repository).reconcile_folders(false).await.unwrap();
+
+    assert_eq!(reconciliation.removed, ["john/Empty"]);
+    assert_eq!(reconciliation.missing.len(), 1);
+    assert_eq!((reconciliation.missing[0].path.as_str(), reconciliation.missing[0].media), ("john/Holiday", 1));
+
+    let state = repository.state();
+    assert!(state.folders.iter().all(|folder| folder.name != "Empty"));
+    assert!(state.folders.iter().any(|folder| folder.name == "Holiday" && folder.missing_since.is_some()));
+  }
+}
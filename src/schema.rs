@@ -8,6 +8,12 @@ table! {
     thumbnail_link -> Nullable<Varchar>,
     link -> Varchar,
     password -> Nullable<Varchar>,
+    locked -> Bool,
+    smart -> Nullable<Varchar>,
+    sort -> Nullable<Varchar>,
+    sort_order -> Nullable<Varchar>,
+    date_from -> Nullable<Timestamp>,
+    date_to -> Nullable<Timestamp>,
   }
 }
 
@@ -26,6 +32,8 @@ table! {
     id -> Integer,
     album_id -> Integer,
     media_id -> Integer,
+    added_at -> Datetime,
+    added_by -> Nullable<Integer>,
   }
 }
 
@@ -36,6 +44,38 @@ table! {
     uuid -> Varchar,
     password -> Nullable<Varchar>,
     expiration -> Nullable<Datetime>,
+    title -> Nullable<Varchar>,
+    welcome_message -> Nullable<Text>,
+    accent_color -> Nullable<Char>,
+    allow_zip_download -> Bool,
+    bandwidth_limit -> Nullable<Integer>,
+  }
+}
+
+table! {
+  album_share_link_access (id) {
+    id -> Integer,
+    album_share_link_id -> Integer,
+    accessed_at -> Datetime,
+    ip_address -> Nullable<Varchar>,
+    user_agent -> Nullable<Varchar>,
+    succeeded -> Bool,
+  }
+}
+
+table! {
+  album_share_link_download (id) {
+    id -> Integer,
+    album_share_link_id -> Integer,
+    downloaded_at -> Datetime,
+  }
+}
+
+table! {
+  album_visit (user_id, album_id) {
+    user_id -> Integer,
+    album_id -> Integer,
+    visited_at -> Datetime,
   }
 }
 
@@ -52,8 +92,12 @@ table! {
   auth_refresh_token (id) {
     id -> Integer,
     user_id -> Integer,
+    uuid -> Nullable<Varchar>,
     refresh_token -> Varchar,
     expiration_time -> Timestamp,
+    created_at -> Nullable<Datetime>,
+    user_agent -> Nullable<Varchar>,
+    ip_address -> Nullable<Varchar>,
   }
 }
 
@@ -65,12 +109,48 @@ table! {
   }
 }
 
+table! {
+  folder_scan (folder_id) {
+    folder_id -> Integer,
+    modified_at -> Timestamp,
+  }
+}
+
 table! {
   folder (id) {
     id -> Integer,
     owner_id -> Integer,
     parent -> Nullable<Integer>,
     name -> Varchar,
+    missing_since -> Nullable<Datetime>,
+    uuid -> Nullable<Varchar>,
+  }
+}
+
+table! {
+  job (id) {
+    id -> Integer,
+    uuid -> Varchar,
+    user_id -> Integer,
+    kind -> Varchar,
+    state -> Varchar,
+    total -> Nullable<Unsigned<Integer>>,
+    processed -> Unsigned<Integer>,
+    error -> Nullable<Text>,
+    resumed_from -> Nullable<Varchar>,
+    created_at -> Datetime,
+    finished_at -> Nullable<Datetime>,
+  }
+}
+
+table! {
+  library (id) {
+    id -> Integer,
+    uuid -> Varchar,
+    owner_id -> Integer,
+    name -> Nullable<Varchar>,
+    path -> Varchar,
+    created_at -> Datetime,
   }
 }
 
@@ -84,8 +164,164 @@ table! {
     height -> Unsigned<Integer>,
     description -> Nullable<Varchar>,
     date_taken -> Timestamp,
+    date_taken_offset -> Nullable<Integer>,
+    uuid -> Varchar,
+    sha2_512 -> Varchar,
+    version -> Integer,
+    camera_make -> Nullable<Varchar>,
+    camera_model -> Nullable<Varchar>,
+    latitude -> Nullable<Double>,
+    longitude -> Nullable<Double>,
+    iso -> Nullable<Unsigned<Integer>>,
+    focal_length -> Nullable<Double>,
+    orientation -> Nullable<Unsigned<SmallInt>>,
+    filename_sort_key -> Nullable<Varchar>,
+    file_size -> Nullable<Unsigned<BigInt>>,
+    file_modified_at -> Nullable<Timestamp>,
+    missing_since -> Nullable<Datetime>,
+  }
+}
+
+table! {
+  media_detection (media_id) {
+    media_id -> Integer,
+    detected_at -> Datetime,
+  }
+}
+
+table! {
+  media_face (id) {
+    id -> Integer,
     uuid -> Varchar,
+    media_id -> Integer,
+    person_id -> Nullable<Integer>,
+    x -> Double,
+    y -> Double,
+    width -> Double,
+    height -> Double,
+    created_at -> Datetime,
+  }
+}
+
+table! {
+  media_grant (id) {
+    id -> Integer,
+    media_id -> Integer,
+    user_id -> Integer,
+    created_at -> Timestamp,
+  }
+}
+
+table! {
+  media_integrity (media_id) {
+    media_id -> Integer,
+    verified_at -> Datetime,
+    status -> Varchar,
+    actual_sha2_512 -> Nullable<Varchar>,
+  }
+}
+
+table! {
+  media_tag (id) {
+    id -> Integer,
+    media_id -> Integer,
+    tag_id -> Integer,
+  }
+}
+
+table! {
+  media_version (id) {
+    id -> Integer,
+    media_id -> Integer,
+    version -> Integer,
+    operations -> Nullable<Text>,
+    width -> Unsigned<Integer>,
+    height -> Unsigned<Integer>,
     sha2_512 -> Varchar,
+    orientation -> Nullable<Unsigned<SmallInt>>,
+    created_at -> Datetime,
+  }
+}
+
+table! {
+  organization (id) {
+    id -> Integer,
+    uuid -> Varchar,
+    name -> Varchar,
+    created_at -> Datetime,
+  }
+}
+
+table! {
+  organization_admin (user_id) {
+    user_id -> Integer,
+    organization_id -> Integer,
+  }
+}
+
+table! {
+  password_reset (id) {
+    id -> Integer,
+    token -> Varchar,
+    user_id -> Integer,
+    created_by -> Nullable<Integer>,
+    created_at -> Timestamp,
+    expiration -> Timestamp,
+    used_at -> Nullable<Timestamp>,
+  }
+}
+
+table! {
+  person (id) {
+    id -> Integer,
+    uuid -> Varchar,
+    owner_id -> Integer,
+    name -> Varchar,
+    created_at -> Datetime,
+  }
+}
+
+table! {
+  scan_alert (id) {
+    id -> Integer,
+    user_id -> Integer,
+    job_id -> Nullable<Integer>,
+    created_at -> Timestamp,
+    media -> Unsigned<Integer>,
+    modified -> Unsigned<Integer>,
+    missing -> Unsigned<Integer>,
+    paused -> Bool,
+    confirmed_at -> Nullable<Timestamp>,
+  }
+}
+
+table! {
+  scan_issue (id) {
+    id -> Integer,
+    job_id -> Integer,
+    path -> Text,
+    kind -> Varchar,
+    severity -> Varchar,
+    message -> Nullable<Text>,
+    created_at -> Datetime,
+  }
+}
+
+table! {
+  tag (id) {
+    id -> Integer,
+    owner_id -> Integer,
+    name -> Varchar,
+    created_at -> Datetime,
+  }
+}
+
+table! {
+  tag_suggestion (id) {
+    id -> Integer,
+    media_id -> Integer,
+    name -> Varchar,
+    confidence -> Double,
   }
 }
 
@@ -95,6 +331,47 @@ table! {
     username -> Varchar,
     email -> Varchar,
     password -> Varchar,
+    organization_id -> Integer,
+    role -> Varchar,
+    disabled -> Bool,
+  }
+}
+
+table! {
+  user_feature (id) {
+    id -> Integer,
+    user_id -> Integer,
+    feature -> Varchar,
+  }
+}
+
+table! {
+  user_invite (id) {
+    id -> Integer,
+    token -> Varchar,
+    organization_id -> Integer,
+    created_by -> Nullable<Integer>,
+    created_at -> Timestamp,
+    expiration -> Timestamp,
+    admin -> Bool,
+    used_at -> Nullable<Timestamp>,
+  }
+}
+
+table! {
+  user_scan_ignore (id) {
+    id -> Integer,
+    user_id -> Integer,
+    pattern -> Varchar,
+  }
+}
+
+table! {
+  user_setting (user_id) {
+    user_id -> Integer,
+    timezone -> Nullable<Varchar>,
+    locale -> Nullable<Varchar>,
+    date_format -> Nullable<Varchar>,
   }
 }
 
@@ -103,24 +380,80 @@ joinable!(album_invite -> album (album_id));
 joinable!(album_invite -> user (invited_user_id));
 joinable!(album_media -> album (album_id));
 joinable!(album_media -> media (media_id));
+joinable!(album_media -> user (added_by));
 joinable!(album_share_link -> album (album_id));
+joinable!(album_share_link_access -> album_share_link (album_share_link_id));
+joinable!(album_share_link_download -> album_share_link (album_share_link_id));
+joinable!(album_visit -> album (album_id));
+joinable!(album_visit -> user (user_id));
 joinable!(auth_access_token -> auth_refresh_token (refresh_token_id));
 joinable!(auth_refresh_token -> user (user_id));
 joinable!(favorite_media -> media (media_id));
 joinable!(favorite_media -> user (user_id));
 joinable!(folder -> user (owner_id));
+joinable!(folder_scan -> folder (folder_id));
+joinable!(job -> user (user_id));
+joinable!(library -> user (owner_id));
 joinable!(media -> folder (folder_id));
+joinable!(media_detection -> media (media_id));
+joinable!(media_face -> media (media_id));
+joinable!(media_face -> person (person_id));
+joinable!(media_grant -> media (media_id));
+joinable!(media_grant -> user (user_id));
+joinable!(media_integrity -> media (media_id));
+joinable!(media_tag -> media (media_id));
+joinable!(media_tag -> tag (tag_id));
+joinable!(media_version -> media (media_id));
 joinable!(media -> user (owner_id));
+joinable!(organization_admin -> organization (organization_id));
+joinable!(organization_admin -> user (user_id));
+joinable!(password_reset -> user (user_id));
+joinable!(person -> user (owner_id));
+joinable!(scan_alert -> job (job_id));
+joinable!(scan_alert -> user (user_id));
+joinable!(scan_issue -> job (job_id));
+joinable!(tag -> user (owner_id));
+joinable!(tag_suggestion -> media (media_id));
+joinable!(user -> organization (organization_id));
+joinable!(user_feature -> user (user_id));
+joinable!(user_invite -> organization (organization_id));
+joinable!(user_invite -> user (created_by));
+joinable!(user_scan_ignore -> user (user_id));
+joinable!(user_setting -> user (user_id));
 
 allow_tables_to_appear_in_same_query!(
   album,
   album_invite,
   album_media,
   album_share_link,
+  album_share_link_access,
+  album_share_link_download,
+  album_visit,
   auth_access_token,
   auth_refresh_token,
   favorite_media,
   folder,
+  folder_scan,
+  job,
+  library,
   media,
+  media_detection,
+  media_face,
+  media_grant,
+  media_integrity,
+  media_tag,
+  media_version,
+  organization,
+  organization_admin,
+  password_reset,
+  person,
+  scan_alert,
+  scan_issue,
+  tag,
+  tag_suggestion,
   user,
+  user_feature,
+  user_invite,
+  user_scan_ignore,
+  user_setting,
 );
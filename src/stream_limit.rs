@@ -0,0 +1,175 @@
+//! Limit of media streamed concurrently by one user.
+//!
+//! A user opening dozens of large videos at once could saturate the disk for everyone else,
+//! so each stream of a video or of a file larger than `LIMITED_SIZE` holds a permit which is returned
+//! once the response body is dropped. Smaller images aren't limited, so a grid of thumbnails loaded
+//! at once (e.g. over HTTP/2) isn't rejected.
+
+use crate::bandwidth::{Bandwidth, Throttled};
+use okapi::openapi3::Responses;
+use rocket::http::{ContentType, Header, Status};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use rocket::tokio::fs::File;
+use rocket::tokio::io::{self, AsyncRead, AsyncSeek, ReadBuf, SeekFrom};
+use rocket_okapi::{gen::OpenApiGenerator, response::OpenApiResponderInner, util::ensure_status_code_exists};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+/// Seconds clients should wait before retrying a rejected stream.
+const RETRY_AFTER: u32 = 5;
+
+/// Size in bytes above which streams of media other than videos are limited.
+pub const LIMITED_SIZE: u64 = 32 * 1024 * 1024;
+
+/// Checks whether the stream of the media needs a permit: videos and files larger than `LIMITED_SIZE` do.
+pub async fn is_limited(path: &Path, is_video: bool) -> bool {
+  if is_video { return true }
+
+  match File::open(path).await {
+    Ok(file) => file.metadata().await.map_or(true, |metadata| metadata.len() > LIMITED_SIZE),
+    Err(_) => true,
+  }
+}
+
+/// Who the streams are counted for.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum StreamOwner {
+  User(i32),
+  /// Anonymous visitors of a shared album are counted per share link and client address,
+  /// so one visitor can't use up the streams of everyone else.
+  ShareLink(String, Option<IpAddr>),
+}
+
+/// Counts active streams of every owner; managed by Rocket.
+#[derive(Debug, Default)]
+pub struct StreamLimiter {
+  active: Arc<Mutex<HashMap<StreamOwner, usize>>>,
+}
+
+impl StreamLimiter {
+  /// Returns a permit for a new stream, or `None` when the owner already has `max_streams` streams.\
+  /// Zero `max_streams` means no limit.
+  pub fn acquire(&self, owner: StreamOwner, max_streams: usize) -> Option<StreamPermit> {
+    let mut active = self.active.lock().unwrap();
+    let count = active.entry(owner.clone()).or_insert(0);
+
+    if max_streams != 0 && *count >= max_streams { return None }
+
+    *count += 1;
+
+    Some(StreamPermit { owner, active: self.active.clone() })
+  }
+}
+
+/// Active stream; it's released when dropped.
+#[derive(Debug)]
+pub struct StreamPermit {
+  owner: StreamOwner,
+  active: Arc<Mutex<HashMap<StreamOwner, usize>>>,
+}
+
+impl Drop for StreamPermit {
+  fn drop(&mut self) {
+    let mut active = self.active.lock().unwrap();
+
+    if let Some(count) = active.get_mut(&self.owner) {
+      *count -= 1;
+      if *count == 0 { active.remove(&self.owner); }
+    }
+  }
+}
+
+/// Media file holding its stream permit until the whole response is sent (or the client disconnects).
+pub struct MediaStream {
+  file: PermitFile,
+  content_type: Option<ContentType>,
+  size: Option<usize>,
+}
+
+impl MediaStream {
+  pub async fn open(path: &Path, permit: StreamPermit) -> io::Result<Self> {
+    MediaStream::throttled(path, Some(permit), Bandwidth::unlimited()).await
+  }
+
+  /// Opens the media, which is then sent only as fast as the bandwidth allows; unlimited streams have no permit.
+  pub async fn throttled(path: &Path, permit: Option<StreamPermit>, bandwidth: Bandwidth) -> io::Result<Self> {
+    let file = File::open(path).await?;
+    let size = file.metadata().await.ok().map(|metadata| metadata.len() as usize);
+
+    // Rocket doesn't know WebVTT, which describes preview strips
+    let content_type = path.extension()
+      .and_then(|extension| extension.to_str())
+      .and_then(|extension| match extension {
+        "vtt" => Some(ContentType::new("text", "vtt")),
+        _ => ContentType::from_extension(extension),
+      });
+
+    Ok(Self { file: PermitFile { file: Throttled::new(file, bandwidth), _permit: permit }, content_type, size })
+  }
+}
+
+impl<'r> Responder<'r, 'static> for MediaStream {
+  fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+    let mut response = Response::build();
+    if let Some(content_type) = self.content_type {
+      response.header(content_type);
+    }
+
+    response.sized_body(self.size, self.file).ok()
+  }
+}
+
+impl OpenApiResponderInner for MediaStream {
+  fn responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+    <Vec<u8>>::responses(gen)
+  }
+}
+
+/// File whose stream permit is released together with the file.
+struct PermitFile {
+  file: Throttled<File>,
+  _permit: Option<StreamPermit>,
+}
+
+impl AsyncRead for PermitFile {
+  fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+    Pin::new(&mut self.file).poll_read(cx, buf)
+  }
+}
+
+impl AsyncSeek for PermitFile {
+  fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+    Pin::new(&mut self.file).start_seek(position)
+  }
+
+  fn poll_complete(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+    Pin::new(&mut self.file).poll_complete(cx)
+  }
+}
+
+/// Responds with 429 and the `Retry-After` header when the user has too many active streams.
+#[derive(Debug)]
+pub struct TooManyStreams;
+
+impl<'r> Responder<'r, 'static> for TooManyStreams {
+  fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+    Response::build()
+      .status(Status::TooManyRequests)
+      .header(Header::new("Retry-After", RETRY_AFTER.to_string()))
+      .ok()
+  }
+}
+
+impl OpenApiResponderInner for TooManyStreams {
+  fn responses(_: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+    let mut responses = Responses::default();
+    ensure_status_code_exists(&mut responses, 429);
+
+    Ok(responses)
+  }
+}
@@ -0,0 +1,96 @@
+//! Anonymous usage statistics, sent only when `telemetry` is enabled.
+//!
+//! Every `telemetry_interval_hours`, a report is sent as JSON to `telemetry_endpoint`.
+//! It contains only aggregate values: the version, the number of media rounded to a power of ten
+//! and features enabled for at least one user. No identifiers, names, paths or addresses are sent.\
+//! `GET /system/telemetry` returns the exact report which would be sent.
+
+use crate::background::Background;
+use crate::db;
+use crate::features::Feature;
+use crate::DbConn;
+use rocket::tokio::{task, time};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::time::Duration;
+
+/// Seconds after the start before the first report is sent, so it doesn't slow down the startup.
+const FIRST_REPORT_DELAY: u64 = 600;
+
+/// Seconds to wait for the endpoint.
+const REQUEST_TIMEOUT: u64 = 30;
+
+/// Anonymous usage statistics of the instance.
+#[derive(Serialize, JsonSchema, Debug, Clone)]
+pub struct TelemetryReport {
+  pub version: String,
+  /// Range of the number of media, e.g. `100-999`.
+  pub media_count: String,
+  /// Features enabled for at least one user.
+  pub features: Vec<Feature>,
+}
+
+impl TelemetryReport {
+  /// Collects the statistics of the instance.
+  pub async fn new(conn: &DbConn) -> Result<Self, diesel::result::Error> {
+    let media_count = db::media::count_media(conn).await?;
+    let features = db::users::select_enabled_features(conn).await?;
+
+    Ok(Self {
+      version: env!("CARGO_PKG_VERSION").to_string(),
+      media_count: count_bucket(media_count),
+      features,
+    })
+  }
+}
+
+/// Rounds the count to a range between powers of ten, so the exact number isn't revealed.
+/// # Example
+/// ```
+/// assert_eq!(count_bucket(0), "0");
+/// assert_eq!(count_bucket(1234), "1000-9999");
+/// assert_eq!(count_bucket(2_000_000), "1000000+");
+/// ```
+pub fn count_bucket(count: i64) -> String {
+  if count <= 0 { return "0".to_string() }
+
+  let mut lower = 1;
+  while lower < 1_000_000 && count >= lower * 10 {
+    lower *= 10;
+  }
+
+  if count >= 1_000_000 { return format!("{}+", lower) }
+
+  format!("{}-{}", lower, lower * 10 - 1)
+}
+
+/// Sends the report; the HTTP request is blocking, so it runs outside of the async runtime.
+async fn send(endpoint: String, report: TelemetryReport) -> Result<(), String> {
+  task::spawn_blocking(move || {
+    ureq::post(&endpoint)
+      .timeout(Duration::from_secs(REQUEST_TIMEOUT))
+      .send_json(report)
+      .map(|_| ())
+      .map_err(|err| err.to_string())
+  }).await.map_err(|err| err.to_string())?
+}
+
+/// Sends a report every `interval_hours`.
+pub async fn run(background: Background, endpoint: String, interval_hours: u64) {
+  time::sleep(Duration::from_secs(FIRST_REPORT_DELAY)).await;
+
+  loop {
+    match background.conn().await {
+      Some(conn) => match TelemetryReport::new(&conn).await {
+        Ok(report) => match send(endpoint.clone(), report).await {
+          Ok(()) => info!("Anonymous usage statistics were sent to {}.", endpoint),
+          Err(err) => warn!("Anonymous usage statistics couldn't be sent: {}", err),
+        },
+        Err(err) => error!("Anonymous usage statistics couldn't be collected: {}", err),
+      },
+      None => error!("Anonymous usage statistics were skipped as no database connection is available."),
+    }
+
+    time::sleep(Duration::from_secs(interval_hours.max(1) * 3600)).await;
+  }
+}
@@ -0,0 +1,210 @@
+//! Timeouts of requests, so slow clients or a hung database can't occupy handlers forever.
+//!
+//! Handlers of all routes are wrapped by `wrap()`: a handler which doesn't respond within the timeout of its route
+//! is dropped and the request fails with 503. Bodies are read using `BodyTimeout`, which fails with 408 when
+//! the client sends them too slowly: JSON bodies by the `TimedJson` data guard, other bodies (uploads, imports)
+//! by their handlers. Handlers of routes with a body get the body timeout on top of their own, so a slow body
+//! fails with 408 rather than 503.
+//! Responses streamed after the handler returned (media, ZIP archives, events) aren't limited.
+//!
+//! Timeouts are set by `timeouts` in the configuration, see `TimeoutPolicy`; timed out requests are counted
+//! in `/metrics`.
+
+use crate::config::{self, TimeoutPolicy};
+use okapi::openapi3::RequestBody;
+use rocket::data::{self, Data, FromData, Limits};
+use rocket::http::{Method, Status};
+use rocket::request::{self, FromRequest, Request};
+use rocket::route::{Handler, Outcome, Route};
+use rocket::serde::json::Json;
+use rocket::tokio::time;
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::request::{OpenApiFromData, OpenApiFromRequest, RequestHeaderInput};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Handlers which didn't respond in time since the server started.
+static REQUEST_TIMEOUTS: AtomicU64 = AtomicU64::new(0);
+/// Request bodies which weren't received in time since the server started.
+static BODY_TIMEOUTS: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the configured timeouts.\
+/// Invalid timeouts are reported when the configuration is extracted, so the defaults are used here.
+pub fn policy() -> TimeoutPolicy {
+  config::figment().extract_inner("timeouts").unwrap_or_default()
+}
+
+/// Returns the numbers of timed out handlers and request bodies since the server started.
+pub fn stats() -> (u64, u64) {
+  (REQUEST_TIMEOUTS.load(Ordering::Relaxed), BODY_TIMEOUTS.load(Ordering::Relaxed))
+}
+
+/// Converts seconds to a timeout; zero means no timeout.
+fn timeout(seconds: u64) -> Option<Duration> {
+  (seconds > 0).then_some(Duration::from_secs(seconds))
+}
+
+/// Wraps handlers of the routes, so they fail when they don't respond within the timeouts of the policy.
+pub fn wrap(routes: Vec<Route>, policy: &TimeoutPolicy) -> Vec<Route> {
+  routes.into_iter()
+    .map(|mut route| {
+      let (mut request_seconds, body_seconds) = policy.route_seconds(route.name.as_deref().unwrap_or_default());
+
+      // the handler reads the body first, which is limited by its own timeout
+      if request_seconds > 0 && matches!(route.method, Method::Post | Method::Put | Method::Patch) {
+        request_seconds = if body_seconds > 0 { request_seconds + body_seconds } else { 0 };
+      }
+
+      route.handler = Box::new(TimeoutHandler {
+        handler: route.handler,
+        request: timeout(request_seconds),
+        body: BodyTimeout(timeout(body_seconds)),
+      });
+
+      route
+    })
+    .collect()
+}
+
+#[derive(Clone)]
+struct TimeoutHandler {
+  handler: Box<dyn Handler>,
+  request: Option<Duration>,
+  body: BodyTimeout,
+}
+
+#[rocket::async_trait]
+impl Handler for TimeoutHandler {
+  async fn handle<'r>(&self, request: &'r Request<'_>, data: Data<'r>) -> Outcome<'r> {
+    // read by the `BodyTimeout` guard of the handler
+    request.local_cache(|| self.body);
+
+    let duration = match self.request {
+      Some(duration) => duration,
+      None => return self.handler.handle(request, data).await,
+    };
+
+    match time::timeout(duration, self.handler.handle(request, data)).await {
+      Ok(outcome) => outcome,
+      Err(_) => {
+        REQUEST_TIMEOUTS.fetch_add(1, Ordering::Relaxed);
+        warn!("Request {} {} wasn't handled within {} seconds.", request.method(), request.uri(), duration.as_secs());
+
+        Outcome::Failure(Status::ServiceUnavailable)
+      },
+    }
+  }
+}
+
+/// Request guard limiting how long reading the request body may take.
+/// # Example
+/// ```
+/// let data = body_timeout.read(data.open(1.mebibytes()).into_string()).await?;
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BodyTimeout(Option<Duration>);
+
+impl BodyTimeout {
+  /// Runs the future reading the body; fails with 408 when it doesn't finish in time.
+  pub async fn read<T>(&self, read: impl Future<Output = T>) -> Result<T, Status> {
+    let duration = match self.0 {
+      Some(duration) => duration,
+      None => return Ok(read.await),
+    };
+
+    time::timeout(duration, read).await.map_err(|_| {
+      BODY_TIMEOUTS.fetch_add(1, Ordering::Relaxed);
+      warn!("Request body wasn't received within {} seconds.", duration.as_secs());
+
+      Status::RequestTimeout
+    })
+  }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for BodyTimeout {
+  type Error = ();
+
+  async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+    // routes which weren't wrapped don't limit their bodies
+    request::Outcome::Success(*request.local_cache(BodyTimeout::default))
+  }
+}
+
+impl<'a> OpenApiFromRequest<'a> for BodyTimeout {
+  fn from_request_input(_gen: &mut OpenApiGenerator, _name: String, _required: bool) -> rocket_okapi::Result<RequestHeaderInput> {
+    Ok(RequestHeaderInput::None)
+  }
+}
+
+/// JSON request body read within the body timeout of the route; used instead of `Json` as a data guard.\
+/// Fails with 408 when the body isn't received in time, with 413 when it's over the `json` limit
+/// and with 422 when it can't be parsed.
+#[derive(Debug)]
+pub struct TimedJson<T>(pub T);
+
+impl<T> TimedJson<T> {
+  pub fn into_inner(self) -> T {
+    self.0
+  }
+
+  /// Returns the body of a route where it's optional (its guard is `Result<TimedJson<T>, Status>`):
+  /// a missing or invalid body is `None`, but a body which wasn't received in time still fails with 408.
+  pub fn optional(body: Result<Self, Status>) -> Result<Option<T>, Status> {
+    match body {
+      Ok(body) => Ok(Some(body.0)),
+      Err(status) if status == Status::RequestTimeout => Err(status),
+      Err(_) => Ok(None),
+    }
+  }
+}
+
+impl<T> Deref for TimedJson<T> {
+  type Target = T;
+
+  fn deref(&self) -> &T {
+    &self.0
+  }
+}
+
+impl<T> DerefMut for TimedJson<T> {
+  fn deref_mut(&mut self) -> &mut T {
+    &mut self.0
+  }
+}
+
+#[rocket::async_trait]
+impl<'r, T: DeserializeOwned> FromData<'r> for TimedJson<T> {
+  /// Status of the failure.
+  type Error = Status;
+
+  async fn from_data(request: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+    let body_timeout = *request.local_cache(BodyTimeout::default);
+    let limit = request.limits().get("json").unwrap_or(Limits::JSON);
+
+    let body = match body_timeout.read(data.open(limit).into_string()).await {
+      Ok(Ok(body)) if body.is_complete() => body.into_inner(),
+      Ok(Ok(_)) => return data::Outcome::Failure((Status::PayloadTooLarge, Status::PayloadTooLarge)),
+      Ok(Err(_)) => return data::Outcome::Failure((Status::BadRequest, Status::BadRequest)),
+      Err(status) => return data::Outcome::Failure((status, status)),
+    };
+
+    match serde_json::from_str(&body) {
+      Ok(value) => data::Outcome::Success(TimedJson(value)),
+      Err(err) => {
+        debug!("Request body of {} {} isn't valid: {}", request.method(), request.uri(), err);
+        data::Outcome::Failure((Status::UnprocessableEntity, Status::UnprocessableEntity))
+      },
+    }
+  }
+}
+
+impl<'r, T: JsonSchema + DeserializeOwned> OpenApiFromData<'r> for TimedJson<T> {
+  fn request_body(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<RequestBody> {
+    Json::<T>::request_body(gen)
+  }
+}
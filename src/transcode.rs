@@ -0,0 +1,313 @@
+//! Transcoding of videos browsers can't play (Matroska, AVI, WMV, ...) into MP4 using `ffmpeg`.
+//!
+//! Transcodes are derivatives (`playback.mp4` in the derivatives directory of the media).
+//! They are created in the background on the first request and served from the cache afterwards,
+//! because transcoding a long video takes much longer than clients wait for a response.
+//!
+//! Preview strips used for scrubbing the timeline of videos are created the same way: `preview-strip.jpg`
+//! is a grid of frames taken at regular intervals and `preview-strip.vtt` maps time ranges to the frames
+//! (`WebVTT` thumbnails understood by common web players).
+
+use crate::derivatives;
+use crate::stream_limit::MediaStream;
+use okapi::openapi3::Responses;
+use rocket::http::{Header, Status};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use rocket::tokio::{fs, sync::Semaphore, task};
+use rocket_okapi::{gen::OpenApiGenerator, response::OpenApiResponderInner, util::ensure_status_code_exists};
+use std::collections::HashSet;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+
+/// Name of the transcoded video in the derivatives directory.
+const PLAYBACK_FILENAME: &str = "playback.mp4";
+
+/// Name of the grid of preview frames in the derivatives directory.
+const PREVIEW_STRIP_FILENAME: &str = "preview-strip.jpg";
+
+/// Name of the `WebVTT` file describing the preview frames; it's written before the grid.
+const PREVIEW_STRIP_VTT_FILENAME: &str = "preview-strip.vtt";
+
+/// Number of preview frames and columns of their grid.
+const PREVIEW_FRAMES: u32 = 100;
+const PREVIEW_COLUMNS: u32 = 10;
+
+/// Width of one preview frame in pixels.
+const PREVIEW_FRAME_WIDTH: u32 = 160;
+
+/// Seconds clients should wait before asking for a video which is being transcoded.
+const RETRY_AFTER: u32 = 10;
+
+/// Extensions of videos; the scanner accepts the same formats.
+const VIDEO_EXTENSIONS: [&str; 10] = ["mp4", "m4v", "mkv", "webm", "mov", "avi", "wmv", "mpg", "mpeg", "flv"];
+
+/// Extensions of videos all major browsers play without transcoding.
+const WEB_PLAYABLE_EXTENSIONS: [&str; 3] = ["mp4", "m4v", "webm"];
+
+fn extension(filename: &str) -> String {
+  Path::new(filename).extension().and_then(|extension| extension.to_str()).unwrap_or_default().to_lowercase()
+}
+
+pub fn is_video(filename: &str) -> bool {
+  VIDEO_EXTENSIONS.contains(&extension(filename).as_str())
+}
+
+/// Checks whether the video must be transcoded before browsers can play it.
+pub fn needs_transcoding(filename: &str) -> bool {
+  is_video(filename) && !WEB_PLAYABLE_EXTENSIONS.contains(&extension(filename).as_str())
+}
+
+/// State of the transcoded video of a media.
+#[derive(Debug)]
+pub enum TranscodeStatus {
+  Ready(PathBuf),
+  /// The video is being transcoded (or waits for a free slot).
+  Pending,
+  /// Transcoding failed; it's retried after a restart.
+  Failed,
+}
+
+/// Creates a derivative of a video: `fn(ffmpeg, original, target)`.
+type Derive = fn(&Path, &Path, &Path) -> io::Result<()>;
+
+/// Transcodes videos and creates their preview strips in the background; managed by Rocket.
+#[derive(Clone)]
+pub struct Transcoder {
+  ffmpeg: PathBuf,
+  /// Limits the number of `ffmpeg` processes, each of them can use all CPU cores.
+  slots: Arc<Semaphore>,
+  /// Derivatives being created, as `<sha2_512>/<filename>`.
+  running: Arc<Mutex<HashSet<String>>>,
+  failed: Arc<Mutex<HashSet<String>>>,
+}
+
+impl Transcoder {
+  /// Creates a transcoder running at most `max_transcodes` (at least one) `ffmpeg` processes at once.
+  pub fn new(ffmpeg: PathBuf, max_transcodes: usize) -> Self {
+    Self {
+      ffmpeg,
+      slots: Arc::new(Semaphore::new(max_transcodes.max(1))),
+      running: Arc::new(Mutex::new(HashSet::new())),
+      failed: Arc::new(Mutex::new(HashSet::new())),
+    }
+  }
+
+  /// Returns the transcoded video of the media with the hash (its `sha2_512`); transcoding is started when it doesn't exist yet.
+  /// # Example
+  /// ```
+  /// match transcoder.playback(&media.sha2_512, original).await? {
+  ///   TranscodeStatus::Ready(path) => ...,
+  ///   TranscodeStatus::Pending => ...,
+  ///   TranscodeStatus::Failed => ...,
+  /// }
+  /// ```
+  pub async fn playback(&self, sha2_512: &str, original: PathBuf) -> io::Result<TranscodeStatus> {
+    self.derivative(sha2_512, original, PLAYBACK_FILENAME, run_ffmpeg).await
+  }
+
+  /// Returns the grid of preview frames of the video with the hash (its `sha2_512`); it's created when it doesn't exist yet.\
+  /// Its `WebVTT` description is next to it, see `preview_strip_vtt()`.
+  pub async fn preview_strip(&self, sha2_512: &str, original: PathBuf) -> io::Result<TranscodeStatus> {
+    self.derivative(sha2_512, original, PREVIEW_STRIP_FILENAME, run_preview_strip).await
+  }
+
+  async fn derivative(&self, sha2_512: &str, original: PathBuf, filename: &str, derive: Derive) -> io::Result<TranscodeStatus> {
+    let directory = derivatives::media_derivatives_dir(sha2_512)
+      .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "Derivatives directory is unknown."))?;
+    let path = directory.join(filename);
+
+    if fs::metadata(&path).await.is_ok() { return Ok(TranscodeStatus::Ready(path)) }
+
+    let key = format!("{}/{}", sha2_512, filename);
+    if self.failed.lock().unwrap().contains(&key) { return Ok(TranscodeStatus::Failed) }
+
+    // only the first request starts transcoding
+    if self.running.lock().unwrap().insert(key.clone()) {
+      fs::create_dir_all(&directory).await?;
+
+      rocket::tokio::spawn(self.clone().transcode(key, original, path, derive));
+    }
+
+    Ok(TranscodeStatus::Pending)
+  }
+
+  async fn transcode(self, key: String, original: PathBuf, target: PathBuf, derive: Derive) {
+    let slot = self.slots.acquire().await;
+
+    let ffmpeg = self.ffmpeg.clone();
+    let result = task::spawn_blocking(move || derive(&ffmpeg, &original, &target)).await
+      .map_err(|err| io::Error::new(ErrorKind::Other, err))
+      .and_then(|result| result);
+
+    drop(slot);
+
+    if let Err(err) = result {
+      error!("Derivative {} couldn't be created: {}", key, err);
+      self.failed.lock().unwrap().insert(key.clone());
+    }
+
+    self.running.lock().unwrap().remove(&key);
+  }
+}
+
+/// Returns the `WebVTT` description of the preview strip at `preview_strip`.
+pub fn preview_strip_vtt(preview_strip: &Path) -> PathBuf {
+  preview_strip.with_file_name(PREVIEW_STRIP_VTT_FILENAME)
+}
+
+/// Transcodes the video into H.264/AAC MP4.\
+/// It's written to a temporary file first, so a partial video is never served.
+fn run_ffmpeg(ffmpeg: &Path, original: &Path, target: &Path) -> io::Result<()> {
+  let temporary = target.with_extension(format!("{}.tmp", std::process::id()));
+
+  let output = Command::new(ffmpeg)
+    .args(["-nostdin", "-y", "-loglevel", "error", "-i"])
+    .arg(original)
+    // the first video and audio stream; subtitles and other streams can't be stored in MP4
+    .args(["-map", "0:v:0", "-map", "0:a:0?"])
+    // yuv420p and even dimensions are required by most hardware decoders
+    .args(["-c:v", "libx264", "-preset", "veryfast", "-crf", "23", "-pix_fmt", "yuv420p", "-vf", "scale=trunc(iw/2)*2:trunc(ih/2)*2"])
+    .args(["-c:a", "aac", "-b:a", "160k"])
+    // the index at the beginning of the file allows playback before the whole video is downloaded
+    .args(["-movflags", "+faststart", "-f", "mp4"])
+    .arg(&temporary)
+    .output();
+
+  let output = match output {
+    Ok(output) => output,
+    Err(err) => {
+      std::fs::remove_file(&temporary).ok();
+      return Err(err);
+    },
+  };
+
+  if !output.status.success() {
+    std::fs::remove_file(&temporary).ok();
+    return Err(io::Error::new(ErrorKind::Other, String::from_utf8_lossy(&output.stderr).trim().to_owned()));
+  }
+
+  std::fs::rename(temporary, target)
+}
+
+/// Reads the duration of the video in seconds from the output of `ffmpeg -i`.
+fn probe_duration(ffmpeg: &Path, original: &Path) -> io::Result<f64> {
+  // without an output file ffmpeg only prints information about the input and fails
+  let output = Command::new(ffmpeg)
+    .args(["-nostdin", "-hide_banner", "-i"])
+    .arg(original)
+    .output()?;
+
+  let stderr = String::from_utf8_lossy(&output.stderr);
+
+  stderr.lines()
+    .filter_map(|line| line.trim().strip_prefix("Duration: "))
+    .filter_map(|line| line.split(',').next())
+    .find_map(|duration| {
+      let mut seconds = 0.0;
+      for part in duration.split(':') {
+        seconds = seconds * 60.0 + part.parse::<f64>().ok()?;
+      }
+
+      Some(seconds)
+    })
+    .filter(|seconds| *seconds > 0.0)
+    .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "Duration of the video is unknown."))
+}
+
+/// Formats seconds as a `WebVTT` timestamp, e.g. `00:01:02.500`.
+fn vtt_timestamp(seconds: f64) -> String {
+  let milliseconds = (seconds * 1000.0).round() as u64;
+
+  format!("{:02}:{:02}:{:02}.{:03}", milliseconds / 3_600_000, milliseconds / 60_000 % 60, milliseconds / 1000 % 60, milliseconds % 1000)
+}
+
+/// Creates the grid of preview frames and its `WebVTT` description.\
+/// The grid is written to a temporary file first and renamed last, so an incomplete strip is never served.
+fn run_preview_strip(ffmpeg: &Path, original: &Path, target: &Path) -> io::Result<()> {
+  let duration = probe_duration(ffmpeg, original)?;
+  let interval = duration / PREVIEW_FRAMES as f64;
+  let rows = (PREVIEW_FRAMES + PREVIEW_COLUMNS - 1) / PREVIEW_COLUMNS;
+
+  let temporary = target.with_extension(format!("{}.tmp", std::process::id()));
+
+  let output = Command::new(ffmpeg)
+    .args(["-nostdin", "-y", "-loglevel", "error", "-i"])
+    .arg(original)
+    .args(["-map", "0:v:0"])
+    .arg("-vf")
+    .arg(format!("fps=1/{},scale={}:-2,tile={}x{}", interval, PREVIEW_FRAME_WIDTH, PREVIEW_COLUMNS, rows))
+    .args(["-frames:v", "1", "-q:v", "5", "-f", "image2", "-c:v", "mjpeg"])
+    .arg(&temporary)
+    .output();
+
+  let output = match output {
+    Ok(output) => output,
+    Err(err) => {
+      std::fs::remove_file(&temporary).ok();
+      return Err(err);
+    },
+  };
+
+  if !output.status.success() {
+    std::fs::remove_file(&temporary).ok();
+    return Err(io::Error::new(ErrorKind::Other, String::from_utf8_lossy(&output.stderr).trim().to_owned()));
+  }
+
+  let (width, height) = match image::image_dimensions(&temporary) {
+    Ok(dimensions) => dimensions,
+    Err(err) => {
+      std::fs::remove_file(&temporary).ok();
+      return Err(io::Error::new(ErrorKind::InvalidData, err));
+    },
+  };
+
+  let (frame_width, frame_height) = (width / PREVIEW_COLUMNS, height / rows);
+
+  // frames are referenced relative to the URL of the description, which is next to the strip
+  let mut vtt = String::from("WEBVTT\n");
+  for frame in 0..PREVIEW_FRAMES {
+    let (x, y) = (frame % PREVIEW_COLUMNS * frame_width, frame / PREVIEW_COLUMNS * frame_height);
+
+    vtt.push_str(&format!(
+      "\n{} --> {}\npreview-strip#xywh={},{},{},{}\n",
+      vtt_timestamp(frame as f64 * interval), vtt_timestamp((frame + 1) as f64 * interval), x, y, frame_width, frame_height
+    ));
+  }
+
+  if let Err(err) = std::fs::write(preview_strip_vtt(target), vtt) {
+    std::fs::remove_file(&temporary).ok();
+    return Err(err);
+  }
+
+  std::fs::rename(temporary, target)
+}
+
+/// Playable video (or its preview strip), or 202 with the `Retry-After` header while it's being created.
+pub enum Playback {
+  Stream(Box<MediaStream>),
+  Pending,
+}
+
+impl<'r> Responder<'r, 'static> for Playback {
+  fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+    match self {
+      Playback::Stream(stream) => (*stream).respond_to(request),
+      Playback::Pending => Response::build()
+        .status(Status::Accepted)
+        .header(Header::new("Retry-After", RETRY_AFTER.to_string()))
+        .ok(),
+    }
+  }
+}
+
+impl OpenApiResponderInner for Playback {
+  fn responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+    let mut responses = MediaStream::responses(gen)?;
+    ensure_status_code_exists(&mut responses, 202);
+
+    Ok(responses)
+  }
+}
@@ -0,0 +1,126 @@
+//! Sanity checks of uploaded files.
+//!
+//! Magic bytes only tell what a file claims to be, so uploaded images are decoded completely before
+//! they are moved to the gallery. Corrupt files would otherwise fail every time the thumbnailer tried
+//! to process them.
+//!
+//! Files which are corrupt or whose content doesn't match their extension are moved to the quarantine
+//! directory instead of being deleted, so administrators can inspect them.
+
+use crate::directories::Directories;
+use crate::scan;
+use image::io::Reader;
+use image::{ImageError, ImageFormat};
+use nanoid::nanoid;
+use rocket::tokio::fs;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::io;
+use std::path::Path;
+
+/// Error code of renames across filesystems (the same on Linux and macOS).
+const EXDEV: i32 = 18;
+
+/// Reason why an upload was rejected.
+#[derive(Serialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum UploadRejection {
+  /// The SHA-512 of the received file doesn't match the one sent by the client.
+  HashMismatch,
+  /// The type of the file isn't supported; `detected_type` is its MIME type when it's known.
+  Unsupported { detected_type: Option<String> },
+  /// The content of the file is in another format than its extension says.
+  ExtensionMismatch { extension: String, detected_type: String },
+  /// The file can't be decoded.
+  Corrupt { error: String },
+}
+
+impl UploadRejection {
+  /// Suspicious files are quarantined instead of being deleted.
+  pub fn is_suspicious(&self) -> bool {
+    matches!(self, UploadRejection::ExtensionMismatch { .. } | UploadRejection::Corrupt { .. })
+  }
+}
+
+/// Checks the uploaded file by decoding it and returns its dimensions.\
+/// `filename` is the name under which the file is uploaded; it's used to check its extension.
+pub fn check_upload(path: &Path, filename: &str) -> Result<(u32, u32), UploadRejection> {
+  let detected_type = infer::get_from_path(path).ok().flatten().map(|kind| kind.mime_type().to_owned());
+  if detected_type.is_none() || !scan::is_media_supported(path) {
+    return Err(UploadRejection::Unsupported { detected_type });
+  }
+
+  let detected_type = detected_type.unwrap();
+
+  let reader = Reader::open(path).and_then(|reader| reader.with_guessed_format());
+  if let Err(err) = reader { return Err(UploadRejection::Corrupt { error: err.to_string() }) }
+
+  let reader = reader.unwrap();
+
+  // only images can be uploaded, the thumbnailer of other media isn't able to check them
+  let format = match reader.format() {
+    Some(format) => format,
+    None => return Err(UploadRejection::Unsupported { detected_type: Some(detected_type) }),
+  };
+
+  if let Ok(expected) = ImageFormat::from_path(filename) {
+    if expected != format {
+      let extension = Path::new(filename).extension().and_then(|extension| extension.to_str()).unwrap_or_default().to_owned();
+      return Err(UploadRejection::ExtensionMismatch { extension, detected_type });
+    }
+  }
+
+  let image = match reader.decode() {
+    Ok(image) => image,
+    Err(ImageError::Unsupported(_)) => return Err(UploadRejection::Unsupported { detected_type: Some(detected_type) }),
+    Err(err) => return Err(UploadRejection::Corrupt { error: err.to_string() }),
+  };
+
+  if image.width() == 0 || image.height() == 0 {
+    return Err(UploadRejection::Corrupt { error: String::from("The image is empty.") });
+  }
+
+  Ok((image.width(), image.height()))
+}
+
+/// Moves a rejected upload to the quarantine directory; it's deleted when it can't be moved.
+pub async fn quarantine(path: &Path, filename: &str, rejection: &UploadRejection) {
+  let quarantine = Directories::new().and_then(|directories| directories.quarantine());
+
+  if let Some(quarantine) = quarantine {
+    let quarantined_path = quarantine.join(format!("{}_{}", nanoid!(), filename));
+
+    if move_file(path, &quarantined_path).await.is_ok() {
+      warn!("Upload {} was quarantined as {:?}: {:?}.", filename, quarantined_path, rejection);
+      return;
+    }
+  }
+
+  error!("Upload {} couldn't be quarantined, so it was deleted.", filename);
+  fs::remove_file(path).await.ok();
+}
+
+/// Moves an accepted upload to `target`, which must not exist; the error is `AlreadyExists` when it does.\
+/// The target is created before the file is moved, so concurrent uploads with the same filename
+/// can't overwrite each other.
+pub async fn store(temporary: &Path, target: &Path) -> io::Result<()> {
+  fs::OpenOptions::new().write(true).create_new(true).open(target).await?;
+
+  let moved = move_file(temporary, target).await;
+  if moved.is_err() {
+    fs::remove_file(target).await.ok();
+  }
+
+  moved
+}
+
+/// Renames the file; when the directories are on different filesystems, it's copied and the original is removed.
+async fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+  match fs::rename(from, to).await {
+    Err(err) if err.raw_os_error() == Some(EXDEV) => {
+      fs::copy(from, to).await?;
+      fs::remove_file(from).await
+    },
+    result => result,
+  }
+}
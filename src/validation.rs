@@ -0,0 +1,173 @@
+//! Validation of data sent by clients.
+//!
+//! Invalid requests are rejected with 422 and a list of invalid fields in the details of the error
+//! (see `errors::ApiError::Invalid`), so clients can show the reason next to each field.
+
+use crate::banned_passwords::BannedPasswords;
+use crate::config::PasswordPolicy;
+use email_address::EmailAddress;
+use lazy_regex::regex_is_match;
+use schemars::JsonSchema;
+use serde::Serialize;
+use unicode_normalization::UnicodeNormalization;
+
+/// Reason why a field is invalid.
+#[derive(Serialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum InvalidReason {
+  TooShort { min: usize },
+  TooLong { max: usize },
+  InvalidFirstCharacter,
+  InvalidCharacters,
+  InvalidFormat,
+  /// The value doesn't contain a character of the required class.
+  MissingCharacter { class: CharacterClass },
+  /// The password is commonly used or was leaked.
+  Banned,
+}
+
+/// Class of characters required by the password policy.
+#[derive(Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CharacterClass {
+  Lowercase,
+  Uppercase,
+  Digit,
+  /// Any character which is neither a letter nor a digit.
+  Symbol,
+}
+
+impl CharacterClass {
+  pub fn matches(&self, c: char) -> bool {
+    match self {
+      CharacterClass::Lowercase => c.is_lowercase(),
+      CharacterClass::Uppercase => c.is_uppercase(),
+      CharacterClass::Digit => c.is_numeric(),
+      CharacterClass::Symbol => !c.is_alphanumeric(),
+    }
+  }
+}
+
+/// Invalid field of a request.
+#[derive(Serialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+  pub field: String,
+  #[serde(flatten)]
+  pub reason: InvalidReason,
+}
+
+/// List of invalid fields.
+#[derive(Serialize, JsonSchema, Debug, Default)]
+pub struct ValidationErrors {
+  pub errors: Vec<FieldError>,
+}
+
+impl ValidationErrors {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Adds an invalid field.
+  pub fn add(&mut self, field: &str, reason: InvalidReason) {
+    self.errors.push(FieldError { field: field.to_string(), reason });
+  }
+
+  /// Returns `Ok(())` when no field is invalid.
+  pub fn into_result(self) -> Result<(), Self> {
+    if self.errors.is_empty() { return Ok(()) }
+
+    Err(self)
+  }
+}
+
+/// Normalizes usernames and emails, so they can be compared regardless of case and unicode representation.\
+/// Leading and trailing whitespace is removed, the rest is lowercased and NFC normalized.
+/// # Example
+/// ```
+/// assert_eq!(normalize_identifier(" Anna@Example.com "), "anna@example.com");
+/// ```
+pub fn normalize_identifier(value: &str) -> String {
+  value.trim().to_lowercase().nfc().collect()
+}
+
+/// Checks the username.
+///
+/// # Validity
+///
+/// The **minimum length is 5 characters** and the **maximum is 30**.\
+/// The first character of a username must be a letter or an underscore.\
+/// Usernames are low-caps only and can contain these characters:
+/// 1. latin letters (a-z)
+/// 2. numbers (0-9)
+/// 3. underscore (_)
+pub fn validate_username(username: &str, errors: &mut ValidationErrors) {
+  let len = username.chars().count();
+
+  if len < 5 {
+    errors.add("username", InvalidReason::TooShort { min: 5 });
+  } else if len > 30 {
+    errors.add("username", InvalidReason::TooLong { max: 30 });
+  } else if !regex_is_match!(r"^[a-z_]", username) {
+    errors.add("username", InvalidReason::InvalidFirstCharacter);
+  } else if !regex_is_match!(r"^[a-z0-9_]+$", username) {
+    errors.add("username", InvalidReason::InvalidCharacters);
+  }
+}
+
+/// Checks the email.
+pub fn validate_email(email: &str, errors: &mut ValidationErrors) {
+  if !EmailAddress::is_valid(email) {
+    errors.add("email", InvalidReason::InvalidFormat);
+  }
+}
+
+/// Checks that the value has at most `max` characters.
+pub fn validate_max_length(field: &str, value: &str, max: usize, errors: &mut ValidationErrors) {
+  if value.chars().count() > max {
+    errors.add(field, InvalidReason::TooLong { max });
+  }
+}
+
+/// Checks that the value is a color in the `#rrggbb` format.
+pub fn validate_color(field: &str, value: &str, errors: &mut ValidationErrors) {
+  if !regex_is_match!(r"^#[0-9a-fA-F]{6}$", value) {
+    errors.add(field, InvalidReason::InvalidFormat);
+  }
+}
+
+/// Checks the password against the password policy.
+///
+/// # Validity
+///
+/// The **minimum length is 8 characters** (or more, see `PasswordPolicy::min_length`) and the **maximum is 128**.\
+/// No characters are forbidden because it could make cracking passwords easier,
+/// but the policy can require some character classes.\
+/// Maximum length limit is there to prevent long password denial of service.\
+/// Banned passwords are rejected regardless of their length.
+pub fn validate_password(field: &str, password: &str, policy: &PasswordPolicy, banned: &BannedPasswords, errors: &mut ValidationErrors) {
+  let len = password.chars().count();
+  let min = policy.min_length.clamp(8, 128);
+
+  if len < min {
+    errors.add(field, InvalidReason::TooShort { min });
+  } else if len > 128 {
+    errors.add(field, InvalidReason::TooLong { max: 128 });
+  }
+
+  let classes = [
+    (policy.require_lowercase, CharacterClass::Lowercase),
+    (policy.require_uppercase, CharacterClass::Uppercase),
+    (policy.require_digit, CharacterClass::Digit),
+    (policy.require_symbol, CharacterClass::Symbol),
+  ];
+
+  for (required, class) in classes {
+    if required && !password.chars().any(|c| class.matches(c)) {
+      errors.add(field, InvalidReason::MissingCharacter { class });
+    }
+  }
+
+  if banned.contains(password) {
+    errors.add(field, InvalidReason::Banned);
+  }
+}
@@ -0,0 +1,162 @@
+//! Automatic scanning of changed files for users with the `watcher` feature.
+//!
+//! Every `watch.interval_seconds`, the watcher compares modification times of all directories of each user
+//! (their root folder and libraries) with the previous check. Polling is used instead of `inotify` or `FSEvents`,
+//! so the watcher also works on network mounts (where they don't report changes made by other machines) and doesn't
+//! run out of watches on large galleries. Only directories are read, not files, and checks are rate-limited:
+//! the time between them is at least `MIN_INTERVAL_SECONDS` and grows with the time the previous check spent walking,
+//! so large galleries are checked less often. Symbolic links are followed only with the `follow` `scan_symlinks` policy.
+//! A directory's modification time changes when files are added, removed or renamed in it; files changed in place
+//! don't change it, so they're found by the next scan of the user (manual or started by another change),
+//! which checks known files of unchanged folders too.
+//!
+//! Changes are debounced: a user's directories are scanned once they stayed unchanged for `watch.debounce_seconds`,
+//! so copying many files results in one scan. Scans are incremental jobs like `/scan_media`, which add new media
+//! and remove missing ones; users with a scan already running are skipped until the next check.
+
+use crate::background::Background;
+use crate::config::{Config, SymlinkPolicy};
+use crate::db;
+use crate::directories::Directories;
+use crate::features::Feature;
+use crate::jobs;
+use crate::libraries::UserLibraries;
+use crate::models::{JobKind, NewJob};
+use crate::DbConn;
+use rocket::tokio::{task, time};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use walkdir::WalkDir;
+
+/// State of the directories of one user.
+struct WatchedUser {
+  fingerprint: u64,
+  /// When a change was last noticed; `None` when the directories were scanned since.
+  changed_at: Option<Instant>,
+}
+
+/// Minimum number of seconds between two checks, regardless of `watch.interval_seconds`.
+const MIN_INTERVAL_SECONDS: u64 = 10;
+
+/// The time between two checks is at least this multiple of the time the previous check spent walking directories.
+const CHECK_DURATION_FACTOR: u32 = 10;
+
+/// Hashes paths and modification times of all directories under the given ones.\
+/// Symlinked directories are entered only when the policy follows them; walkdir skips symlink loops.
+fn fingerprint(directories: Vec<PathBuf>, symlinks: SymlinkPolicy) -> u64 {
+  let mut hasher = DefaultHasher::new();
+
+  for directory in directories {
+    let walker = WalkDir::new(directory).follow_links(symlinks == SymlinkPolicy::Follow);
+
+    for entry in walker.into_iter().filter_map(Result::ok) {
+      if !entry.file_type().is_dir() { continue }
+
+      entry.path().hash(&mut hasher);
+
+      if let Some(modified) = entry.metadata().ok().and_then(|metadata| metadata.modified().ok()) {
+        modified.hash(&mut hasher);
+      }
+    }
+  }
+
+  hasher.finish()
+}
+
+/// Returns the fingerprint of the user's directories.
+async fn user_fingerprint(conn: &DbConn, gallery: &Path, user_id: i32, symlinks: SymlinkPolicy) -> Option<u64> {
+  let username = db::users::get_user_username(conn, user_id).await.ok()??;
+  let libraries = UserLibraries::load(conn, gallery, user_id, &username).await.ok()?;
+
+  let directories = libraries.directories()
+    .into_iter()
+    .map(|(_, directory)| directory.to_path_buf())
+    .collect();
+
+  task::spawn_blocking(move || fingerprint(directories, symlinks)).await.ok()
+}
+
+/// Starts a scan of the user's directories unless one is already running.\
+/// Returns whether the directories were scanned.
+async fn scan(conn: &DbConn, config: &Config, user_id: i32) -> bool {
+  match db::jobs::is_user_job_running(conn, user_id, JobKind::Scan).await {
+    Ok(false) => (),
+    _ => return false,
+  }
+
+  let job = db::jobs::insert_job(conn, NewJob::new(user_id, JobKind::Scan)).await;
+  if job.is_err() {
+    error!("Watcher couldn't start a scan of user {}.", user_id);
+    return false;
+  }
+
+  jobs::run_scan(conn, &job.unwrap(), config.scan_symlinks, config.scan_duplicates, config.scan_alerts, config.gallery_check).await;
+
+  true
+}
+
+/// Checks directories of users with the `watcher` feature periodically and scans them when they change.
+pub async fn run(background: Background, config: Config) {
+  let interval = Duration::from_secs(config.watch.interval_seconds.max(MIN_INTERVAL_SECONDS));
+  let debounce = Duration::from_secs(config.watch.debounce_seconds);
+  let mut users: HashMap<i32, WatchedUser> = HashMap::new();
+  let mut walk_duration = Duration::ZERO;
+
+  loop {
+    time::sleep(interval.max(walk_duration * CHECK_DURATION_FACTOR)).await;
+    walk_duration = Duration::ZERO;
+
+    let gallery = match Directories::new().and_then(|directories| directories.gallery()) {
+      Some(gallery) => gallery,
+      None => continue,
+    };
+
+    let conn = background.conn().await;
+    if conn.is_none() {
+      error!("Watcher check was skipped as no database connection is available.");
+      continue;
+    }
+
+    let conn = conn.unwrap();
+
+    let user_ids = db::users::select_feature_user_ids(&conn, Feature::Watcher).await;
+    if user_ids.is_err() {
+      error!("Watcher couldn't load users with the watcher feature.");
+      continue;
+    }
+
+    let user_ids = user_ids.unwrap();
+
+    // users who turned the feature off start over with a new baseline when they turn it on again
+    users.retain(|user_id, _| user_ids.contains(user_id));
+
+    for user_id in user_ids {
+      let started = Instant::now();
+      let fingerprint = user_fingerprint(&conn, &gallery, user_id, config.scan_symlinks).await;
+      walk_duration += started.elapsed();
+
+      let fingerprint = match fingerprint {
+        Some(fingerprint) => fingerprint,
+        None => continue,
+      };
+
+      let watched = users.entry(user_id).or_insert(WatchedUser { fingerprint, changed_at: None });
+
+      if watched.fingerprint != fingerprint {
+        watched.fingerprint = fingerprint;
+        watched.changed_at = Some(Instant::now());
+        continue;
+      }
+
+      let settled = watched.changed_at.map_or(false, |changed_at| changed_at.elapsed() >= debounce);
+
+      // changes made during the scan are noticed by the next check, as the fingerprint is from before it
+      if settled && scan(&conn, &config, user_id).await {
+        watched.changed_at = None;
+      }
+    }
+  }
+}
@@ -0,0 +1,329 @@
+//! Writing of metadata from the database back into media files.
+//!
+//! Descriptions are written as an XMP packet (`dc:description`) embedded into JPEG originals,
+//! so they stay with the files when the library is moved to other software.\
+//! Other formats are skipped, as are files which can't be written and files with XMP written by other software
+//! (it would be lost otherwise).
+//!
+//! Files are written to a temporary file first and then renamed, so an interrupted job never leaves a broken file.
+
+use crate::db;
+use crate::models::{Job, JobKind, JobState, Media, NewJob};
+use crate::scan;
+use crate::DbConn;
+use checksums::{hash_file, Algorithm::SHA2512};
+use chrono::{NaiveDateTime, Utc};
+use rocket::tokio::task;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Namespace identifying XMP segments in JPEGs.
+const XMP_NAMESPACE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// Marks XMP packets written by Galera, so they can be replaced on the next write-back.
+const XMP_CREATOR_TOOL: &str = "Galera";
+
+#[derive(Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WriteBackState {
+  Running,
+  Finished,
+  Failed,
+}
+
+/// Reason why a media was not written.
+#[derive(Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WriteBackSkipReason {
+  /// Only JPEGs are supported.
+  UnsupportedFormat,
+  /// The file or its folder is read-only.
+  ReadOnly,
+  /// The file contains XMP written by other software.
+  ForeignMetadata,
+  /// The file already contains the same metadata.
+  Unchanged,
+  Missing,
+  Failed,
+}
+
+#[derive(Serialize, JsonSchema, Debug, Clone)]
+pub struct WriteBackSkip {
+  pub media_uuid: String,
+  pub reason: WriteBackSkipReason,
+}
+
+/// Progress of a write-back job.
+#[derive(Serialize, JsonSchema, Debug, Clone)]
+pub struct WriteBackProgress {
+  /// Files are only checked, nothing is written.
+  pub dry_run: bool,
+  pub state: WriteBackState,
+  /// Number of media with metadata to write.
+  pub total: usize,
+  pub processed: usize,
+  /// Number of written files (or files which would be written in the dry-run mode).
+  pub written: usize,
+  pub skipped: Vec<WriteBackSkip>,
+  pub started_at: NaiveDateTime,
+  pub finished_at: Option<NaiveDateTime>,
+}
+
+impl WriteBackProgress {
+  fn new(dry_run: bool) -> Self {
+    Self {
+      dry_run,
+      state: WriteBackState::Running,
+      total: 0,
+      processed: 0,
+      written: 0,
+      skipped: vec![],
+      started_at: Utc::now().naive_utc(),
+      finished_at: None,
+    }
+  }
+}
+
+/// Write-back jobs of all users (the last one of each user); managed by Rocket.
+#[derive(Default, Clone)]
+pub struct WriteBackJobs {
+  jobs: Arc<Mutex<HashMap<i32, WriteBackProgress>>>,
+}
+
+impl WriteBackJobs {
+  /// Returns the progress of the user's last job.
+  pub fn progress(&self, user_id: i32) -> Option<WriteBackProgress> {
+    self.jobs.lock().unwrap().get(&user_id).cloned()
+  }
+
+  /// Starts a job writing metadata of all media of the user.\
+  /// Returns `false` when a job of the user is already running.\
+  /// The job is also stored in the `job` table, so it's reported as failed when a restart interrupts it.
+  pub async fn start(&self, conn: DbConn, user_id: i32, dry_run: bool) -> Result<bool, diesel::result::Error> {
+    {
+      let mut jobs = self.jobs.lock().unwrap();
+      if matches!(jobs.get(&user_id), Some(progress) if progress.state == WriteBackState::Running) { return Ok(false) }
+
+      jobs.insert(user_id, WriteBackProgress::new(dry_run));
+    }
+
+    let job = match db::jobs::insert_job(&conn, NewJob::new(user_id, JobKind::MetadataWriteBack)).await {
+      Ok(job) => job,
+      Err(err) => {
+        self.jobs.lock().unwrap().remove(&user_id);
+        return Err(err);
+      },
+    };
+
+    let jobs = self.clone();
+    rocket::tokio::spawn(async move {
+      let (state, error) = match jobs.run(&conn, &job, dry_run).await {
+        Ok(()) => (WriteBackState::Finished, None),
+        Err(err) => {
+          error!("Metadata write-back of user {} failed: {}", user_id, err);
+          (WriteBackState::Failed, Some(err.to_string()))
+        },
+      };
+
+      let job_state = if state == WriteBackState::Finished { JobState::Finished } else { JobState::Failed };
+      if db::jobs::finish_job(&conn, job.id, job_state, error).await.is_err() {
+        error!("State of job {} couldn't be saved.", job.uuid);
+      }
+
+      jobs.update(user_id, |progress| {
+        progress.state = state;
+        progress.finished_at = Some(Utc::now().naive_utc());
+      });
+    });
+
+    Ok(true)
+  }
+
+  fn update(&self, user_id: i32, update: impl FnOnce(&mut WriteBackProgress)) {
+    if let Some(progress) = self.jobs.lock().unwrap().get_mut(&user_id) {
+      update(progress);
+    }
+  }
+
+  async fn run(&self, conn: &DbConn, job: &Job, dry_run: bool) -> Result<(), diesel::result::Error> {
+    let media = db::media::select_media_with_description(conn, job.user_id).await?;
+    let total = media.len() as u32;
+    self.update(job.user_id, |progress| progress.total = media.len());
+
+    for (processed, media) in media.into_iter().enumerate() {
+      let result = write_media(conn, &media, dry_run).await;
+
+      self.update(job.user_id, |progress| {
+        progress.processed += 1;
+        match result {
+          Ok(()) => progress.written += 1,
+          Err(reason) => progress.skipped.push(WriteBackSkip { media_uuid: media.uuid.clone(), reason }),
+        }
+      });
+
+      db::jobs::update_job_progress(conn, job.id, processed as u32 + 1, Some(total)).await?;
+    }
+
+    Ok(())
+  }
+}
+
+/// Writes metadata of one media into its original file.
+async fn write_media(conn: &DbConn, media: &Media, dry_run: bool) -> Result<(), WriteBackSkipReason> {
+  let path = scan::get_original_media_path(conn, media).await.ok_or(WriteBackSkipReason::Missing)?;
+  let description = media.description.clone().unwrap_or_default();
+
+  let written_path = path.clone();
+  let hash = task::spawn_blocking(move || write_file(&written_path, &description, dry_run)).await
+    .map_err(|_| WriteBackSkipReason::Failed)??;
+
+  if let Some(hash) = hash {
+    // the file changed, so the stored hash must follow, otherwise the integrity check would report it
+    if db::media::update_original_hash(conn, media.id, media.version, hash).await.is_err() {
+      error!("Hash of media {} couldn't be updated after the write-back.", media.uuid);
+      return Err(WriteBackSkipReason::Failed);
+    }
+  }
+
+  Ok(())
+}
+
+/// Writes the description into the file; returns the new hash of the file (`None` in the dry-run mode).
+fn write_file(path: &Path, description: &str, dry_run: bool) -> Result<Option<String>, WriteBackSkipReason> {
+  let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default().to_lowercase();
+  if !["jpg", "jpeg"].contains(&extension.as_str()) { return Err(WriteBackSkipReason::UnsupportedFormat) }
+
+  let data = match fs::read(path) {
+    Ok(data) => data,
+    Err(err) if err.kind() == ErrorKind::NotFound => return Err(WriteBackSkipReason::Missing),
+    Err(_) => return Err(WriteBackSkipReason::Failed),
+  };
+
+  let written = with_xmp_description(&data, description)?;
+  if written == data { return Err(WriteBackSkipReason::Unchanged) }
+
+  if !is_writable(path) { return Err(WriteBackSkipReason::ReadOnly) }
+
+  if dry_run { return Ok(None) }
+
+  replace_file(path, &written).map_err(|err| {
+    error!("Metadata couldn't be written into {:?}: {}", path, err);
+    WriteBackSkipReason::Failed
+  })?;
+
+  Ok(Some(hash_file(path, SHA2512)))
+}
+
+/// Checks whether the file can be replaced, which needs the file and its folder to be writable.
+fn is_writable(path: &Path) -> bool {
+  let file_writable = fs::metadata(path).map(|metadata| !metadata.permissions().readonly()).unwrap_or(false);
+  if !file_writable { return false }
+
+  // read-only mounts can't be detected from permissions
+  let probe = temporary_path(path);
+  let writable = OpenOptions::new().write(true).create_new(true).open(&probe).is_ok();
+  fs::remove_file(&probe).ok();
+
+  writable
+}
+
+fn temporary_path(path: &Path) -> PathBuf {
+  let filename = path.file_name().and_then(|filename| filename.to_str()).unwrap_or_default();
+
+  path.with_file_name(format!(".{}.galera-write-back", filename))
+}
+
+fn replace_file(path: &Path, data: &[u8]) -> io::Result<()> {
+  let temporary = temporary_path(path);
+
+  let result = (|| {
+    let mut file = OpenOptions::new().write(true).create_new(true).open(&temporary)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+
+    fs::rename(&temporary, path)
+  })();
+
+  if result.is_err() { fs::remove_file(&temporary).ok(); }
+
+  result
+}
+
+/// Returns the JPEG with the XMP packet containing the description.\
+/// An XMP segment written by Galera is replaced, the packet is inserted after the other `APPn` segments otherwise.
+fn with_xmp_description(data: &[u8], description: &str) -> Result<Vec<u8>, WriteBackSkipReason> {
+  if !data.starts_with(&[0xFF, 0xD8]) { return Err(WriteBackSkipReason::UnsupportedFormat) }
+
+  let mut position = 2;
+  let mut existing = None;
+
+  // APPn segments are at the beginning of the file
+  while position + 4 <= data.len() && data[position] == 0xFF && (0xE0..=0xEF).contains(&data[position + 1]) {
+    let length = u16::from_be_bytes([data[position + 2], data[position + 3]]) as usize;
+    let segment = data.get(position + 4..position + 2 + length).ok_or(WriteBackSkipReason::Failed)?;
+
+    if data[position + 1] == 0xE1 && segment.starts_with(XMP_NAMESPACE) {
+      let packet = String::from_utf8_lossy(&segment[XMP_NAMESPACE.len()..]);
+      if !packet.contains(&format!("xmp:CreatorTool=\"{}\"", XMP_CREATOR_TOOL)) { return Err(WriteBackSkipReason::ForeignMetadata) }
+
+      existing = Some(position..position + 2 + length);
+    }
+
+    position += 2 + length;
+  }
+
+  let packet = xmp_packet(description);
+  let mut segment = vec![0xFF, 0xE1];
+  segment.extend(((2 + XMP_NAMESPACE.len() + packet.len()) as u16).to_be_bytes());
+  segment.extend(XMP_NAMESPACE);
+  segment.extend(packet.as_bytes());
+
+  let mut written = Vec::with_capacity(data.len() + segment.len());
+  match existing {
+    Some(range) => {
+      written.extend(&data[..range.start]);
+      written.extend(&segment);
+      written.extend(&data[range.end..]);
+    },
+    None => {
+      written.extend(&data[..position]);
+      written.extend(&segment);
+      written.extend(&data[position..]);
+    },
+  }
+
+  Ok(written)
+}
+
+/// Escapes text for XML content and attribute values.
+pub fn escape_xml(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}
+
+fn xmp_packet(description: &str) -> String {
+  let description = escape_xml(description);
+
+  format!(
+    concat!(
+      "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>",
+      "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">",
+      "<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">",
+      "<rdf:Description rdf:about=\"\" xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\" xmp:CreatorTool=\"{}\">",
+      "<dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:description>",
+      "</rdf:Description>",
+      "</rdf:RDF>",
+      "</x:xmpmeta>",
+      "<?xpacket end=\"w\"?>",
+    ),
+    XMP_CREATOR_TOOL, description
+  )
+}